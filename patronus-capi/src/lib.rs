@@ -0,0 +1,272 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+//! # C API
+//!
+//! Exposes the patronus interpreter to C, C++ and Python (e.g. through `ctypes` or cocotb)
+//! testbenches. All functions operate on an opaque `PatronusSim` handle which is created by
+//! `patronus_sim_load_btor2` and needs to be released with `patronus_sim_free`.
+//! Signals are referred to by their name in the transition system.
+
+use baa::{BitVecOps, BitVecValue, Value};
+use patronus::btor2;
+use patronus::expr::{Context, ExprRef, TypeCheck};
+use patronus::sim::{InitKind, Interpreter, Simulator};
+use patronus::system::TransitionSystem;
+use rustc_hash::FxHashMap;
+use std::ffi::{c_char, c_int, CStr};
+use std::mem::ManuallyDrop;
+
+pub const PATRONUS_OK: c_int = 0;
+pub const PATRONUS_ERR_NULL: c_int = -1;
+pub const PATRONUS_ERR_UNKNOWN_SIGNAL: c_int = -2;
+pub const PATRONUS_ERR_VALUE: c_int = -3;
+
+/// Owns the design as well as the interpreter that simulates it.
+pub struct PatronusSim {
+    /// borrows from `design`, thus it needs to be dropped first
+    sim: ManuallyDrop<Interpreter<'static>>,
+    design: *mut (Context, TransitionSystem),
+    names: FxHashMap<String, ExprRef>,
+}
+
+impl PatronusSim {
+    fn new(ctx: Context, sys: TransitionSystem) -> Self {
+        let names = sys.get_name_map(&ctx);
+        let design = Box::into_raw(Box::new((ctx, sys)));
+        // SAFETY: `design` is only freed in `drop`, after the interpreter is gone
+        let (ctx, sys) = unsafe { &*design };
+        let sim = ManuallyDrop::new(Interpreter::new(ctx, sys));
+        Self { sim, design, names }
+    }
+
+    fn ctx(&self) -> &Context {
+        // SAFETY: `design` stays valid for the lifetime of `self`
+        unsafe { &(*self.design).0 }
+    }
+}
+
+impl Drop for PatronusSim {
+    fn drop(&mut self) {
+        unsafe {
+            ManuallyDrop::drop(&mut self.sim);
+            drop(Box::from_raw(self.design));
+        }
+    }
+}
+
+unsafe fn to_str<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        None
+    } else {
+        CStr::from_ptr(s).to_str().ok()
+    }
+}
+
+/// Loads a btor2 file and creates an interpreter for it.
+/// Returns `NULL` if the file could not be loaded.
+///
+/// # Safety
+/// `filename` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn patronus_sim_load_btor2(filename: *const c_char) -> *mut PatronusSim {
+    let Some(filename) = to_str(filename) else {
+        return std::ptr::null_mut();
+    };
+    match btor2::parse_file(filename) {
+        Some((ctx, sys)) => Box::into_raw(Box::new(PatronusSim::new(ctx, sys))),
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// Releases a simulator created by `patronus_sim_load_btor2`.
+///
+/// # Safety
+/// `sim` must either be `NULL` or a handle that has not been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn patronus_sim_free(sim: *mut PatronusSim) {
+    if !sim.is_null() {
+        drop(Box::from_raw(sim));
+    }
+}
+
+/// Initializes all states and inputs. A `seed` of zero initializes everything to zero,
+/// otherwise values are randomized with the given seed.
+///
+/// # Safety
+/// `sim` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn patronus_sim_init(sim: *mut PatronusSim, seed: u64) -> c_int {
+    let Some(sim) = sim.as_mut() else {
+        return PATRONUS_ERR_NULL;
+    };
+    let kind = if seed == 0 {
+        InitKind::Zero
+    } else {
+        InitKind::Random(seed)
+    };
+    sim.sim.init(kind);
+    PATRONUS_OK
+}
+
+/// Advances the simulation by one step.
+///
+/// # Safety
+/// `sim` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn patronus_sim_step(sim: *mut PatronusSim) -> c_int {
+    let Some(sim) = sim.as_mut() else {
+        return PATRONUS_ERR_NULL;
+    };
+    sim.sim.step();
+    PATRONUS_OK
+}
+
+/// Returns the number of steps executed so far.
+///
+/// # Safety
+/// `sim` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn patronus_sim_step_count(sim: *const PatronusSim) -> u64 {
+    sim.as_ref().map(|s| s.sim.step_count()).unwrap_or(0)
+}
+
+/// Assigns `value` to the bit-vector signal called `name`.
+/// The value is truncated to the width of the signal.
+/// Fails with `PATRONUS_ERR_VALUE` if the signal is wider than 64 bits.
+///
+/// # Safety
+/// `sim` must be a valid handle and `name` a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn patronus_sim_set(
+    sim: *mut PatronusSim,
+    name: *const c_char,
+    value: u64,
+) -> c_int {
+    let (Some(sim), Some(name)) = (sim.as_mut(), to_str(name)) else {
+        return PATRONUS_ERR_NULL;
+    };
+    let Some(&expr) = sim.names.get(name) else {
+        return PATRONUS_ERR_UNKNOWN_SIGNAL;
+    };
+    let Some(width) = expr.get_bv_type(sim.ctx()) else {
+        return PATRONUS_ERR_VALUE;
+    };
+    if width > u64::BITS {
+        return PATRONUS_ERR_VALUE;
+    }
+    let value = if width < u64::BITS {
+        value & ((1u64 << width) - 1)
+    } else {
+        value
+    };
+    match sim.sim.set(expr, &BitVecValue::from_u64(value, width)) {
+        Ok(()) => PATRONUS_OK,
+        Err(_) => PATRONUS_ERR_UNKNOWN_SIGNAL,
+    }
+}
+
+/// Reads the value of the bit-vector signal called `name` into `value`.
+/// Fails with `PATRONUS_ERR_VALUE` if the signal is wider than 64 bits.
+///
+/// # Safety
+/// `sim` must be a valid handle, `name` a valid NUL-terminated string and
+/// `value` must point to writable memory.
+#[no_mangle]
+pub unsafe extern "C" fn patronus_sim_get(
+    sim: *const PatronusSim,
+    name: *const c_char,
+    value: *mut u64,
+) -> c_int {
+    let (Some(sim), Some(name), false) = (sim.as_ref(), to_str(name), value.is_null()) else {
+        return PATRONUS_ERR_NULL;
+    };
+    let Some(&expr) = sim.names.get(name) else {
+        return PATRONUS_ERR_UNKNOWN_SIGNAL;
+    };
+    match sim.sim.get(expr) {
+        Value::BitVec(v) => match v.to_u64() {
+            Some(v) => {
+                *value = v;
+                PATRONUS_OK
+            }
+            None => PATRONUS_ERR_VALUE,
+        },
+        Value::Array(_) => PATRONUS_ERR_VALUE,
+    }
+}
+
+/// Saves the current state and writes an id that can be used to restore it to `id`.
+///
+/// # Safety
+/// `sim` must be a valid handle and `id` must point to writable memory.
+#[no_mangle]
+pub unsafe extern "C" fn patronus_sim_take_snapshot(sim: *mut PatronusSim, id: *mut u32) -> c_int {
+    let (Some(sim), false) = (sim.as_mut(), id.is_null()) else {
+        return PATRONUS_ERR_NULL;
+    };
+    *id = sim.sim.take_snapshot();
+    PATRONUS_OK
+}
+
+/// Restores a state previously saved with `patronus_sim_take_snapshot`.
+///
+/// # Safety
+/// `sim` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn patronus_sim_restore_snapshot(sim: *mut PatronusSim, id: u32) -> c_int {
+    let Some(sim) = sim.as_mut() else {
+        return PATRONUS_ERR_NULL;
+    };
+    match sim.sim.restore_snapshot(id) {
+        Ok(()) => PATRONUS_OK,
+        Err(_) => PATRONUS_ERR_VALUE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+
+    #[test]
+    fn delay_through_c_api() {
+        let filename = CString::new("../inputs/unittest/delay.btor").unwrap();
+        let reg0 = CString::new("reg0").unwrap();
+        let reg1 = CString::new("reg1").unwrap();
+        let mut value = 0u64;
+        unsafe {
+            let sim = patronus_sim_load_btor2(filename.as_ptr());
+            assert!(!sim.is_null());
+            assert_eq!(patronus_sim_init(sim, 0), PATRONUS_OK);
+            let mut snapshot = 0u32;
+            assert_eq!(patronus_sim_take_snapshot(sim, &mut snapshot), PATRONUS_OK);
+            assert_eq!(
+                patronus_sim_take_snapshot(std::ptr::null_mut(), &mut snapshot),
+                PATRONUS_ERR_NULL
+            );
+            patronus_sim_step(sim);
+            patronus_sim_step(sim);
+            assert_eq!(
+                patronus_sim_get(sim, reg1.as_ptr(), &mut value),
+                PATRONUS_OK
+            );
+            assert_eq!(value, 1);
+            patronus_sim_restore_snapshot(sim, snapshot);
+            assert_eq!(
+                patronus_sim_get(sim, reg0.as_ptr(), &mut value),
+                PATRONUS_OK
+            );
+            assert_eq!(value, 0);
+            assert_eq!(patronus_sim_set(sim, reg0.as_ptr(), 7), PATRONUS_OK);
+            patronus_sim_step(sim);
+            assert_eq!(
+                patronus_sim_get(sim, reg1.as_ptr(), &mut value),
+                PATRONUS_OK
+            );
+            assert_eq!(value, 7);
+            assert_eq!(patronus_sim_step_count(sim), 3);
+            patronus_sim_free(sim);
+        }
+    }
+}
@@ -0,0 +1,24 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use patronus::bench::suite;
+use patronus::config::EGraphConfig;
+use patronus_egraphs::run_egraph;
+
+fn saturation(c: &mut Criterion) {
+    let config = EGraphConfig {
+        iter_limit: Some(5),
+        ..Default::default()
+    };
+    let mut group = c.benchmark_group("egraph");
+    group.sample_size(10);
+    for bench in suite() {
+        group.bench_function(&bench.name, |b| b.iter(|| run_egraph(&bench, &config)));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, saturation);
+criterion_main!(benches);
@@ -0,0 +1,201 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+/*!
+# Applying Proven Equalities
+
+Turns the result of an e-graph run back into expressions of the core IR. Expressions are
+added to a single e-graph and saturated. Afterwards, a specific representative can be
+selected for any e-class, either as one of its e-nodes or as a complete expression that the
+e-graph proved to be equal. All remaining classes use the best representative according to
+the cost model of the configuration.
+[`EqualityRewriter::rewrite`] then builds the chosen representative in the `Context` and
+[`EqualityRewriter::apply_to_system`] replaces every root of a transition system that was
+added to the e-graph.
+
+!*/
+
+use crate::cse::{best_nodes, build, dag_size};
+use crate::{
+    configure_runner, to_arith, try_from_arith, Arith, ArithRewrite, EGraph, EGraphError, Rewrite,
+};
+use egg::{Id, Language, RecExpr};
+use patronus::config::EGraphConfig;
+use patronus::expr::{Context, ExprRef};
+use patronus::system::TransitionSystem;
+use rustc_hash::FxHashMap;
+
+pub struct EqualityRewriter {
+    egraph: EGraph,
+    /// e-class of every expression that was added
+    classes: FxHashMap<ExprRef, Id>,
+    choice: FxHashMap<Id, Arith>,
+}
+
+impl EqualityRewriter {
+    /// Adds all `exprs` to an e-graph and saturates it within the limits of `config`.
+    pub fn new(
+        ctx: &Context,
+        exprs: &[ExprRef],
+        rules: &[ArithRewrite],
+        config: &EGraphConfig,
+    ) -> Result<Self, EGraphError> {
+        let egg_rules: Vec<Rewrite> = rules.iter().flat_map(|r| r.to_egg()).collect();
+        let mut runner = configure_runner(egg::Runner::default(), config);
+        for &e in exprs.iter() {
+            runner = runner.with_expr(&to_arith(ctx, e)?);
+        }
+        let runner = runner.run(&egg_rules);
+        let egraph = runner.egraph;
+        let classes = exprs
+            .iter()
+            .zip(runner.roots.iter())
+            .map(|(&e, &id)| (e, egraph.find(id)))
+            .collect();
+        let choice = best_nodes(&egraph, config.cost_model);
+        Ok(Self {
+            egraph,
+            classes,
+            choice,
+        })
+    }
+
+    pub fn egraph(&self) -> &EGraph {
+        &self.egraph
+    }
+
+    /// The e-class of an expression that was passed to [`EqualityRewriter::new`].
+    pub fn class_of(&self, e: ExprRef) -> Option<Id> {
+        self.classes.get(&e).map(|&id| self.egraph.find(id))
+    }
+
+    /// Uses `node` whenever the representative of `class` is needed.
+    pub fn select_node(&mut self, class: Id, node: Arith) -> Result<(), EGraphError> {
+        let class = self.egraph.find(class);
+        let canonical = node.clone().map_children(|c| self.egraph.find(c));
+        let found = self.egraph[class]
+            .nodes
+            .iter()
+            .any(|n| n.clone().map_children(|c| self.egraph.find(c)) == canonical);
+        if !found {
+            return Err(EGraphError::InvalidExpr(format!(
+                "`{node}` is not part of e-class {class}"
+            )));
+        }
+        self.choice.insert(class, node);
+        Ok(())
+    }
+
+    /// Selects `repr` as the representative of `e`. Every node of `repr` needs to be in the
+    /// e-graph and the e-graph needs to have proven that `repr` is equal to `e`.
+    pub fn select_expr(&mut self, e: ExprRef, repr: &RecExpr<Arith>) -> Result<(), EGraphError> {
+        let class = self.class_of(e).ok_or_else(|| {
+            EGraphError::InvalidExpr(format!("{e:?} was not added to the e-graph"))
+        })?;
+        let mut ids: Vec<Id> = Vec::with_capacity(repr.as_ref().len());
+        let mut selected = vec![];
+        for node in repr.as_ref().iter() {
+            let node = node.clone().map_children(|c| ids[usize::from(c)]);
+            let Some(id) = self.egraph.lookup(node.clone()) else {
+                return Err(EGraphError::InvalidExpr(format!(
+                    "`{repr}` was not derived by the e-graph"
+                )));
+            };
+            selected.push((id, node));
+            ids.push(id);
+        }
+        if ids.last().map(|&id| self.egraph.find(id)) != Some(class) {
+            return Err(EGraphError::InvalidExpr(format!(
+                "`{repr}` is not proven to be equal to the original expression"
+            )));
+        }
+        for (id, node) in selected {
+            self.choice.insert(self.egraph.find(id), node);
+        }
+        Ok(())
+    }
+
+    /// Extracts the selected representative of `class`.
+    pub fn extract(&self, class: Id) -> Result<RecExpr<Arith>, EGraphError> {
+        let class = self.egraph.find(class);
+        if dag_size(&self.egraph, &self.choice, &[class]).is_none() {
+            return Err(EGraphError::InvalidExpr(
+                "the selected representatives form a cycle".to_string(),
+            ));
+        }
+        let mut expr = RecExpr::default();
+        build(
+            &self.egraph,
+            &self.choice,
+            class,
+            &mut expr,
+            &mut FxHashMap::default(),
+        );
+        Ok(expr)
+    }
+
+    /// Builds the selected representative of `e` in `ctx`. Expressions that were not added
+    /// to the e-graph are returned unchanged.
+    pub fn rewrite(&self, ctx: &mut Context, e: ExprRef) -> Result<ExprRef, EGraphError> {
+        match self.class_of(e) {
+            Some(class) => try_from_arith(ctx, &self.extract(class)?),
+            None => Ok(e),
+        }
+    }
+
+    /// Replaces every expression of `sys` that was added to the e-graph with its selected
+    /// representative. Returns the number of distinct expressions that were replaced.
+    pub fn apply_to_system(
+        &self,
+        ctx: &mut Context,
+        sys: &mut TransitionSystem,
+    ) -> Result<usize, EGraphError> {
+        let mut replacements = FxHashMap::default();
+        for &e in self.classes.keys() {
+            let new = self.rewrite(ctx, e)?;
+            if new != e {
+                replacements.insert(e, new);
+            }
+        }
+        sys.update_expressions(|e| replacements.get(&e).copied());
+        Ok(replacements.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_rewrites;
+
+    #[test]
+    fn test_select_and_apply() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("sum".into());
+        let a = ctx.bv_symbol("a", 8);
+        let b = ctx.bv_symbol("b", 8);
+        sys.add_input(&ctx, a);
+        sys.add_input(&ctx, b);
+        let sum = ctx.add(a, b);
+        sys.add_output(&mut ctx, "sum".into(), sum);
+
+        let rules: Vec<ArithRewrite> = create_rewrites()
+            .into_iter()
+            .filter(|r| r.name() == "commute-add")
+            .collect();
+        let mut rewriter =
+            EqualityRewriter::new(&ctx, &[sum], &rules, &EGraphConfig::default()).unwrap();
+        // `b + a` is only part of the e-graph because of the commutativity rule
+        let swapped = ctx.add(b, a);
+        let proven = to_arith(&ctx, swapped).unwrap();
+        rewriter.select_expr(sum, &proven).unwrap();
+        assert_eq!(rewriter.rewrite(&mut ctx, sum).unwrap(), swapped);
+
+        // expressions that were not proven equal cannot be selected
+        let other = ctx.sub(a, b);
+        let other = to_arith(&ctx, other).unwrap();
+        assert!(rewriter.select_expr(sum, &other).is_err());
+
+        assert_eq!(rewriter.apply_to_system(&mut ctx, &mut sys).unwrap(), 1);
+        assert_eq!(sys.lookup_output(&ctx, "sum"), Some(swapped));
+    }
+}
@@ -5,6 +5,7 @@
 use baa::BitVecOps;
 use egg::{define_language, Analysis, DidMerge, Id, Language, RecExpr};
 use patronus::expr::*;
+use rustc_hash::FxHashMap;
 use std::cmp::{max, Ordering};
 use std::fmt::{Display, Formatter};
 use std::str::FromStr;
@@ -13,6 +14,13 @@ define_language! {
     /// Intermediate expression language for bit vector arithmetic rewrites.
     /// Inspired by: "ROVER: RTL Optimization via Verified E-Graph Rewriting" (TCAD'24)
     /// arguments for binop: w, w_a, s_a, a, w_b, s_b, b
+    /// arguments for negation: w, w_a, s_a, a
+    /// arguments for concat: w, w_a, a, w_b, b
+    /// arguments for repeat: w, n, w_a, a
+    /// arguments for rotations: w, a, w_b, b
+    /// arguments for rotations by a constant: w, n, a
+    /// arguments for saturating ops: w, s, w_a, s_a, a, w_b, s_b, b
+    /// arguments for comparisons: w, s, w_a, s_a, a, w_b, s_b, b
     pub enum Arith {
         // operations on actual bit-vec values
         "+" = Add([Id; 7]),
@@ -21,9 +29,28 @@ define_language! {
         "<<" = LeftShift([Id; 7]),
         ">>" = RightShift([Id; 7]),
         ">>>" = ArithmeticRightShift([Id; 7]),
+        // `a` is extended to `w` bits before it is negated
+        "neg" = Negate([Id; 4]),
+        // `a` is placed in the most significant bits
+        "concat" = Concat([Id; 5]),
+        // `n` copies of `a`, the count is represented as a width
+        "repeat" = Repeat([Id; 4]),
+        // `a` has the same width as the result, the amount `b` is unsigned
+        "rol" = RotateLeft([Id; 4]),
+        "ror" = RotateRight([Id; 4]),
+        // the amount `n` is represented as a width
+        "rol-const" = RotateLeftConst([Id; 3]),
+        // `s` is the sign of the operation, operands are extended to `w` bits
+        "sat+" = SaturatingAdd([Id; 8]),
+        "sat-" = SaturatingSub([Id; 8]),
+        // `s` is the sign of the comparison, operands are extended to `w` bits, the result is 1-bit
+        ">" = Greater([Id; 8]),
+        ">=" = GreaterEqual([Id; 8]),
         // operations on widths
         "max+1" = WidthMaxPlus1([Id; 2]),
         "wlsh" = WidthLeftShift([Id; 2]),
+        "w+" = WidthAdd([Id; 2]),
+        "w*" = WidthMul([Id; 2]),
         Width(WidthValue),
         Sign(Sign),
         // not a width, but a value constant
@@ -37,7 +64,7 @@ define_language! {
 pub struct WidthValue(WidthInt);
 
 pub(crate) fn eval_width_max_plus_1(wa: WidthInt, wb: WidthInt) -> WidthInt {
-    max(wa, wb) + 1
+    max(wa, wb).saturating_add(1)
 }
 
 pub(crate) fn eval_width_left_shift(wa: WidthInt, wb: WidthInt) -> WidthInt {
@@ -46,7 +73,7 @@ pub(crate) fn eval_width_left_shift(wa: WidthInt, wb: WidthInt) -> WidthInt {
         WidthInt::MAX
     } else {
         let max_shift: WidthInt = (1 << wb) - 1;
-        wa + max_shift
+        wa.saturating_add(max_shift)
     }
 }
 
@@ -158,6 +185,8 @@ impl Analysis<Arith> for WidthConstantFold {
             &Arith::Width(w) => Some(w.0),
             Arith::WidthMaxPlus1([a, b]) => Some(eval_width_max_plus_1(x(a)?, x(b)?)),
             Arith::WidthLeftShift([a, b]) => Some(eval_width_left_shift(x(a)?, x(b)?)),
+            Arith::WidthAdd([a, b]) => Some(x(a)?.saturating_add(x(b)?)),
+            Arith::WidthMul([a, b]) => Some(x(a)?.saturating_mul(x(b)?)),
             _ => None,
         }
     }
@@ -174,84 +203,354 @@ impl Analysis<Arith> for WidthConstantFold {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum EGraphError {
+    #[error("`{0}` cannot be represented in the arithmetic IR")]
+    UnsupportedExpr(String),
+    #[error("unknown rewrite rule `{0}`")]
+    UnknownRule(String),
+    #[error("`{0}` does not evaluate to a constant width")]
+    SymbolicWidth(String),
+    #[error("invalid arithmetic expression: {0}")]
+    InvalidExpr(String),
+    #[error(transparent)]
+    Limit(#[from] LimitError),
+}
+
 /// Convert from our internal IR to the arithmetic expression IR suitable for rewrites.
-pub fn to_arith(ctx: &Context, e: ExprRef) -> egg::RecExpr<Arith> {
+/// Uses the default [`WidthLimits`].
+pub fn to_arith(ctx: &Context, e: ExprRef) -> Result<egg::RecExpr<Arith>, EGraphError> {
+    to_arith_with_limits(ctx, e, &WidthLimits::default())
+}
+
+/// Convert from our internal IR to the arithmetic expression IR suitable for rewrites.
+/// Fails if `e` contains an expression that exceeds `limits`, since widths derived by the
+/// rewrite rules grow with the widths of their operands.
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+pub fn to_arith_with_limits(
+    ctx: &Context,
+    e: ExprRef,
+    limits: &WidthLimits,
+) -> Result<egg::RecExpr<Arith>, EGraphError> {
+    limits.check(ctx, [e], "egraph")?;
+    if let Some(unsupported) = find_unsupported(ctx, e) {
+        return Err(EGraphError::UnsupportedExpr(
+            unsupported.serialize_to_str(ctx),
+        ));
+    }
     let mut out = egg::RecExpr::default();
-    traversal::bottom_up_multi_pat(
-        ctx,
-        e,
-        |ctx, expr, children| {
-            // ignore any sing or zero extension when calculating the children
-            expr.for_each_child(|c| {
-                children.push(remove_ext(ctx, *c).0);
-            });
-        },
-        |_ctx, expr, children| match ctx[expr].clone() {
-            Expr::BVSymbol { name, .. } => out.add(Arith::Symbol(ctx[name].to_string())),
-            Expr::BVAdd(a, b, width) => convert_bin_op(
-                ctx,
-                &mut out,
-                Arith::Add,
-                a,
-                b,
-                width,
-                children[0],
-                children[1],
-            ),
-            Expr::BVSub(a, b, width) => convert_bin_op(
-                ctx,
-                &mut out,
-                Arith::Sub,
-                a,
-                b,
-                width,
-                children[0],
-                children[1],
-            ),
-            Expr::BVMul(a, b, width) => convert_bin_op(
-                ctx,
-                &mut out,
-                Arith::Mul,
-                a,
-                b,
-                width,
-                children[0],
-                children[1],
-            ),
-            Expr::BVShiftLeft(a, b, width) => convert_bin_op(
-                ctx,
-                &mut out,
-                Arith::LeftShift,
-                a,
-                b,
-                width,
-                children[0],
-                children[1],
-            ),
-            Expr::BVShiftRight(a, b, width) => convert_bin_op(
-                ctx,
-                &mut out,
-                Arith::RightShift,
-                a,
-                b,
-                width,
-                children[0],
+    traversal::bottom_up_multi_pat(ctx, e, arith_children, |ctx, expr, children| {
+        convert_node(ctx, &mut out, expr, children)
+    });
+    Ok(out)
+}
+
+/// Converts many expressions into a single shared [`RecExpr`]. Every expression is converted
+/// at most once, thus sub-expressions that are shared between roots are only added once and
+/// failed conversions are not repeated.
+pub(crate) struct ArithConverter<'a> {
+    ctx: &'a Context,
+    limits: WidthLimits,
+    out: RecExpr<Arith>,
+    /// `None` if the expression cannot be converted
+    converted: FxHashMap<ExprRef, Option<Id>>,
+}
+
+impl<'a> ArithConverter<'a> {
+    pub(crate) fn new(ctx: &'a Context, limits: WidthLimits) -> Self {
+        Self {
+            ctx,
+            limits,
+            out: RecExpr::default(),
+            converted: FxHashMap::default(),
+        }
+    }
+
+    /// Returns the root of `e` in the shared expression or `None` if `e` cannot be converted.
+    /// Only the widths of the converted expressions and their direct children are checked
+    /// against the limits.
+    pub(crate) fn convert(&mut self, e: ExprRef) -> Option<Id> {
+        let ctx = self.ctx;
+        let mut todo = vec![(e, false)];
+        let mut children = Vec::with_capacity(4);
+        while let Some((e, bottom_up)) = todo.pop() {
+            if self.converted.contains_key(&e) {
+                continue;
+            }
+            if !bottom_up && (check_supported(ctx, e).is_err() || !self.within_limits(e)) {
+                self.converted.insert(e, None);
+                continue;
+            }
+            children.clear();
+            arith_children(ctx, &ctx[e], &mut children);
+            if !bottom_up {
+                todo.push((e, true));
+                todo.extend(children.iter().map(|&c| (c, false)));
+                continue;
+            }
+            // children are in reverse order, just like in `bottom_up_multi_pat`
+            let converted: Option<Vec<Id>> =
+                children.iter().rev().map(|c| self.converted[c]).collect();
+            let id = converted.map(|children| convert_node(ctx, &mut self.out, e, &children));
+            self.converted.insert(e, id);
+        }
+        self.converted[&e]
+    }
+
+    fn within_limits(&self, e: ExprRef) -> bool {
+        let fits = |e: ExprRef| {
+            e.get_bv_type(self.ctx)
+                .is_some_and(|w| w <= self.limits.max_width)
+        };
+        let mut children_fit = true;
+        self.ctx[e].for_each_child(|&c| children_fit &= fits(c));
+        fits(e) && children_fit
+    }
+
+    /// The shared expression which contains all converted expressions.
+    pub(crate) fn into_expr(self) -> RecExpr<Arith> {
+        self.out
+    }
+}
+
+/// Pushes the expressions that are converted into the children of the arithmetic node of `expr`.
+fn arith_children(ctx: &Context, expr: &Expr, children: &mut Vec<ExprRef>) {
+    if let Some(sat) = ctx.get_saturating(expr) {
+        // the expansion is an ite with three children
+        let b = remove_ext(ctx, sat.b).0;
+        children.extend([remove_ext(ctx, sat.a).0, b, b]);
+        return;
+    }
+    match ctx.get_rotation(expr) {
+        // a rotation has the same number of children as its expansion, i.e., an ite
+        Some((a, Rotation::Left(b) | Rotation::Right(b))) => children.extend([a, b, b]),
+        Some((a, Rotation::LeftBy(_))) => children.extend([a, a]),
+        // ignore any sing or zero extension when calculating the children
+        None => expr.for_each_child(|c| {
+            children.push(remove_ext(ctx, *c).0);
+        }),
+    }
+}
+
+/// Adds the arithmetic node of `expr` to `out`. The converted `children` are in reverse order.
+fn convert_node(ctx: &Context, out: &mut RecExpr<Arith>, expr: ExprRef, children: &[Id]) -> Id {
+    if let Some(sat) = ctx.get_saturating(&ctx[expr]) {
+        return convert_saturating(ctx, out, sat, children);
+    }
+    if let Some((a, rotation)) = ctx.get_rotation(&ctx[expr]) {
+        return convert_rotation(ctx, out, a, rotation, children);
+    }
+    match ctx[expr].clone() {
+        Expr::BVSymbol { name, .. } => out.add(Arith::Symbol(ctx[name].to_string())),
+        Expr::BVLiteral(value) => out.add(Arith::Const(value.get(ctx).to_u64().unwrap())),
+        Expr::BVConcat(a, b, width) => {
+            let width_out = out.add(width.into());
+            let width_a = out.add(a.get_bv_type(ctx).unwrap().into());
+            let width_b = out.add(b.get_bv_type(ctx).unwrap().into());
+            // children are in reverse order
+            out.add(Arith::Concat([
+                width_out,
+                width_a,
                 children[1],
-            ),
-            Expr::BVArithmeticShiftRight(a, b, width) => convert_bin_op(
-                ctx,
-                &mut out,
-                Arith::ArithmeticRightShift,
-                a,
-                b,
-                width,
+                width_b,
                 children[0],
-                children[1],
-            ),
-            _ => todo!("{}", expr.serialize_to_str(ctx)),
+            ]))
+        }
+        Expr::BVAdd(a, b, width) => {
+            convert_bin_op(ctx, out, Arith::Add, a, b, width, children[0], children[1])
+        }
+        Expr::BVNegate(a, width) => {
+            let (base_a, sign_a) = remove_ext(ctx, a);
+            let width_out = out.add(width.into());
+            let width_a = out.add(base_a.get_bv_type(ctx).unwrap().into());
+            let sign_a = out.add(sign_a.into());
+            out.add(Arith::Negate([width_out, width_a, sign_a, children[0]]))
+        }
+        Expr::BVSub(a, b, width) => {
+            convert_bin_op(ctx, out, Arith::Sub, a, b, width, children[0], children[1])
+        }
+        Expr::BVMul(a, b, width) => {
+            convert_bin_op(ctx, out, Arith::Mul, a, b, width, children[0], children[1])
+        }
+        Expr::BVShiftLeft(a, b, width) => convert_bin_op(
+            ctx,
+            out,
+            Arith::LeftShift,
+            a,
+            b,
+            width,
+            children[0],
+            children[1],
+        ),
+        Expr::BVShiftRight(a, b, width) => convert_bin_op(
+            ctx,
+            out,
+            Arith::RightShift,
+            a,
+            b,
+            width,
+            children[0],
+            children[1],
+        ),
+        Expr::BVArithmeticShiftRight(a, b, width) => convert_bin_op(
+            ctx,
+            out,
+            Arith::ArithmeticRightShift,
+            a,
+            b,
+            width,
+            children[0],
+            children[1],
+        ),
+        // children are in reverse order
+        Expr::BVGreater(a, b) => convert_comparison(
+            ctx,
+            out,
+            Arith::Greater,
+            Sign::Unsigned,
+            (a, children[1]),
+            (b, children[0]),
+        ),
+        Expr::BVGreaterSigned(a, b, _) => convert_comparison(
+            ctx,
+            out,
+            Arith::Greater,
+            Sign::Signed,
+            (a, children[1]),
+            (b, children[0]),
+        ),
+        Expr::BVGreaterEqual(a, b) => convert_comparison(
+            ctx,
+            out,
+            Arith::GreaterEqual,
+            Sign::Unsigned,
+            (a, children[1]),
+            (b, children[0]),
+        ),
+        Expr::BVGreaterEqualSigned(a, b, _) => convert_comparison(
+            ctx,
+            out,
+            Arith::GreaterEqual,
+            Sign::Signed,
+            (a, children[1]),
+            (b, children[0]),
+        ),
+        _ => unreachable!("{}", expr.serialize_to_str(ctx)),
+    }
+}
+
+/// Canonicalizes chains of commutative and associative operators before converting `e`.
+/// Different operand orders thus result in the same initial e-graph nodes.
+pub fn to_canonical_arith(
+    ctx: &mut Context,
+    e: ExprRef,
+) -> Result<egg::RecExpr<Arith>, EGraphError> {
+    let e = canonicalize_single_expression(ctx, e);
+    to_arith(ctx, e)
+}
+
+/// Returns the first expression that `to_arith` is unable to convert.
+fn find_unsupported(ctx: &Context, e: ExprRef) -> Option<ExprRef> {
+    let mut todo = vec![e];
+    while let Some(e) = todo.pop() {
+        if let Err(unsupported) = check_supported(ctx, e) {
+            return Some(unsupported);
+        }
+        arith_children(ctx, &ctx[e], &mut todo);
+    }
+    None
+}
+
+/// Checks whether `e` itself, ignoring its children, can be converted.
+/// Returns the offending expression otherwise.
+fn check_supported(ctx: &Context, e: ExprRef) -> Result<(), ExprRef> {
+    if ctx.get_saturating(&ctx[e]).is_some() || ctx.get_rotation(&ctx[e]).is_some() {
+        return Ok(());
+    }
+    match ctx[e] {
+        Expr::BVSymbol { .. }
+        | Expr::BVAdd(..)
+        | Expr::BVSub(..)
+        | Expr::BVNegate(..)
+        | Expr::BVMul(..)
+        | Expr::BVShiftLeft(..)
+        | Expr::BVShiftRight(..)
+        | Expr::BVArithmeticShiftRight(..)
+        | Expr::BVGreater(..)
+        | Expr::BVGreaterSigned(..)
+        | Expr::BVGreaterEqual(..)
+        | Expr::BVGreaterEqualSigned(..) => Ok(()),
+        // an extension can only be represented as part of a binary op
+        Expr::BVConcat(a, b, _) => match [a, b].into_iter().find(|&c| remove_ext(ctx, c).0 != c) {
+            Some(ext) => Err(ext),
+            None => Ok(()),
         },
-    );
-    out
+        Expr::BVLiteral(value) if value.get(ctx).to_u64().is_some() => Ok(()),
+        _ => Err(e),
+    }
+}
+
+fn convert_rotation(
+    ctx: &Context,
+    out: &mut RecExpr<Arith>,
+    a: ExprRef,
+    rotation: Rotation,
+    children: &[Id],
+) -> Id {
+    // children are in reverse order
+    let width = out.add(a.get_bv_type(ctx).unwrap().into());
+    match rotation {
+        Rotation::Left(b) | Rotation::Right(b) => {
+            let width_b = out.add(b.get_bv_type(ctx).unwrap().into());
+            // the expansion is an ite with the children a, b, b
+            let args = [width, children[2], width_b, children[0]];
+            if matches!(rotation, Rotation::Left(_)) {
+                out.add(Arith::RotateLeft(args))
+            } else {
+                out.add(Arith::RotateRight(args))
+            }
+        }
+        Rotation::LeftBy(n) => {
+            let n = out.add(n.into());
+            out.add(Arith::RotateLeftConst([width, n, children[1]]))
+        }
+    }
+}
+
+fn convert_saturating(
+    ctx: &Context,
+    out: &mut RecExpr<Arith>,
+    sat: Saturating,
+    children: &[Id],
+) -> Id {
+    let width_out = out.add(sat.a.get_bv_type(ctx).unwrap().into());
+    let sign = if sat.signed {
+        Sign::Signed
+    } else {
+        Sign::Unsigned
+    };
+    let sign = out.add(sign.into());
+    let (base_a, sign_a) = remove_ext(ctx, sat.a);
+    let (base_b, sign_b) = remove_ext(ctx, sat.b);
+    let width_a = out.add(base_a.get_bv_type(ctx).unwrap().into());
+    let width_b = out.add(base_b.get_bv_type(ctx).unwrap().into());
+    let sign_a = out.add(sign_a.into());
+    let sign_b = out.add(sign_b.into());
+    // children are in reverse order: b, b, a
+    let args = [
+        width_out,
+        sign,
+        width_a,
+        sign_a,
+        children[2],
+        width_b,
+        sign_b,
+        children[0],
+    ];
+    match sat.op {
+        SaturatingOp::Add => out.add(Arith::SaturatingAdd(args)),
+        SaturatingOp::Sub => out.add(Arith::SaturatingSub(args)),
+    }
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -289,6 +588,36 @@ fn convert_bin_op(
     ]))
 }
 
+/// Comparisons are performed with the width of the original operands, which are extended
+/// from their base width.
+fn convert_comparison(
+    ctx: &Context,
+    out: &mut RecExpr<Arith>,
+    op: fn([Id; 8]) -> Arith,
+    sign: Sign,
+    (a, converted_a): (ExprRef, Id),
+    (b, converted_b): (ExprRef, Id),
+) -> Id {
+    let width = out.add(a.get_bv_type(ctx).unwrap().into());
+    let sign = out.add(sign.into());
+    let (base_a, sign_a) = remove_ext(ctx, a);
+    let (base_b, sign_b) = remove_ext(ctx, b);
+    let width_a = out.add(base_a.get_bv_type(ctx).unwrap().into());
+    let width_b = out.add(base_b.get_bv_type(ctx).unwrap().into());
+    let sign_a = out.add(sign_a.into());
+    let sign_b = out.add(sign_b.into());
+    out.add(op([
+        width,
+        sign,
+        width_a,
+        sign_a,
+        converted_a,
+        width_b,
+        sign_b,
+        converted_b,
+    ]))
+}
+
 /// Removes any sign or zero extend expressions and returns whether the removed extension was signed.
 fn remove_ext(ctx: &Context, e: ExprRef) -> (ExprRef, Sign) {
     match ctx[e] {
@@ -299,6 +628,10 @@ fn remove_ext(ctx: &Context, e: ExprRef) -> (ExprRef, Sign) {
 }
 
 /// Convert from the arithmetic expression IR back to our internal SMTLib based IR.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(nodes = expr.as_ref().len()))
+)]
 pub fn from_arith(ctx: &mut Context, expr: &RecExpr<Arith>) -> ExprRef {
     let expressions = expr.as_ref();
     let mut todo = vec![(expressions.len() - 1, false, 0)];
@@ -337,6 +670,97 @@ pub fn from_arith(ctx: &mut Context, expr: &RecExpr<Arith>) -> ExprRef {
             Arith::ArithmeticRightShift(_) => patronus_bin_op(ctx, &mut stack, |ctx, a, b| {
                 ctx.arithmetic_shift_right(a, b)
             }),
+            Arith::Negate(_) => {
+                // w, w_a, s_a, a
+                let wo = get_u64(ctx, stack.pop().unwrap()) as WidthInt;
+                let wa = get_u64(ctx, stack.pop().unwrap()) as WidthInt;
+                let sa = get_u64(ctx, stack.pop().unwrap()) != 0;
+                let a = stack.pop().unwrap();
+                let a = resize(ctx, a, wo, wa, sa);
+                ctx.negate(a)
+            }
+            Arith::Concat(_) => {
+                // w, w_a, a, w_b, b
+                let w = get_u64(ctx, stack.pop().unwrap()) as WidthInt;
+                let wa = get_u64(ctx, stack.pop().unwrap()) as WidthInt;
+                let a = stack.pop().unwrap();
+                let wb = get_u64(ctx, stack.pop().unwrap()) as WidthInt;
+                let b = stack.pop().unwrap();
+                debug_assert_eq!(a.get_bv_type(ctx), Some(wa));
+                debug_assert_eq!(b.get_bv_type(ctx), Some(wb));
+                let res = ctx.concat(a, b);
+                debug_assert_eq!(res.get_bv_type(ctx), Some(w), "{expr}");
+                res
+            }
+            Arith::Repeat(_) => {
+                // w, n, w_a, a
+                let w = get_u64(ctx, stack.pop().unwrap()) as WidthInt;
+                let n = get_u64(ctx, stack.pop().unwrap());
+                let wa = get_u64(ctx, stack.pop().unwrap()) as WidthInt;
+                let a = stack.pop().unwrap();
+                // btor2 does not allow zero-width values
+                assert!(n > 0, "cannot repeat an expression zero times");
+                debug_assert_eq!(a.get_bv_type(ctx), Some(wa));
+                let res = (1..n).fold(a, |res, _| ctx.concat(res, a));
+                debug_assert_eq!(res.get_bv_type(ctx), Some(w), "{expr}");
+                res
+            }
+            Arith::RotateLeft(_) | Arith::RotateRight(_) => {
+                // w, a, w_b, b
+                let _w = stack.pop().unwrap();
+                let a = stack.pop().unwrap();
+                let _wb = stack.pop().unwrap();
+                let b = stack.pop().unwrap();
+                if matches!(expr, Arith::RotateLeft(_)) {
+                    ctx.rotate_left(a, b)
+                } else {
+                    ctx.rotate_right(a, b)
+                }
+            }
+            Arith::RotateLeftConst(_) => {
+                // w, n, a
+                let _w = stack.pop().unwrap();
+                let n = get_u64(ctx, stack.pop().unwrap());
+                let a = stack.pop().unwrap();
+                ctx.rotate_left_by(a, n)
+            }
+            Arith::SaturatingAdd(_) | Arith::SaturatingSub(_) => {
+                // w, s, w_a, s_a, a, w_b, s_b, b
+                let wo = get_u64(ctx, stack.pop().unwrap()) as WidthInt;
+                let signed = get_u64(ctx, stack.pop().unwrap()) != 0;
+                let wa = get_u64(ctx, stack.pop().unwrap()) as WidthInt;
+                let sa = get_u64(ctx, stack.pop().unwrap()) != 0;
+                let a = stack.pop().unwrap();
+                let wb = get_u64(ctx, stack.pop().unwrap()) as WidthInt;
+                let sb = get_u64(ctx, stack.pop().unwrap()) != 0;
+                let b = stack.pop().unwrap();
+                let a = resize(ctx, a, wo, wa, sa);
+                let b = resize(ctx, b, wo, wb, sb);
+                if matches!(expr, Arith::SaturatingAdd(_)) {
+                    ctx.saturating_add(a, b, signed)
+                } else {
+                    ctx.saturating_sub(a, b, signed)
+                }
+            }
+            Arith::Greater(_) | Arith::GreaterEqual(_) => {
+                // w, s, w_a, s_a, a, w_b, s_b, b
+                let w = get_u64(ctx, stack.pop().unwrap()) as WidthInt;
+                let signed = get_u64(ctx, stack.pop().unwrap()) != 0;
+                let wa = get_u64(ctx, stack.pop().unwrap()) as WidthInt;
+                let sa = get_u64(ctx, stack.pop().unwrap()) != 0;
+                let a = stack.pop().unwrap();
+                let wb = get_u64(ctx, stack.pop().unwrap()) as WidthInt;
+                let sb = get_u64(ctx, stack.pop().unwrap()) != 0;
+                let b = stack.pop().unwrap();
+                let a = resize(ctx, a, w, wa, sa);
+                let b = resize(ctx, b, w, wb, sb);
+                match (expr, signed) {
+                    (Arith::Greater(_), false) => ctx.greater(a, b),
+                    (Arith::Greater(_), true) => ctx.greater_signed(a, b),
+                    (_, false) => ctx.greater_or_equal(a, b),
+                    (_, true) => ctx.greater_or_equal_signed(a, b),
+                }
+            }
             Arith::WidthMaxPlus1(_) => {
                 let a = get_u64(ctx, stack.pop().unwrap()) as WidthInt;
                 let b = get_u64(ctx, stack.pop().unwrap()) as WidthInt;
@@ -347,6 +771,13 @@ pub fn from_arith(ctx: &mut Context, expr: &RecExpr<Arith>) -> ExprRef {
                 let b = get_u64(ctx, stack.pop().unwrap()) as WidthInt;
                 ctx.bit_vec_val(eval_width_left_shift(a, b), 32)
             }
+            Arith::WidthAdd(_) | Arith::WidthMul(_) => {
+                let a = get_u64(ctx, stack.pop().unwrap()) as WidthInt;
+                let b = get_u64(ctx, stack.pop().unwrap()) as WidthInt;
+                let width = eval_width_arith(expr, a, b)
+                    .unwrap_or_else(|| panic!("({expr} {a} {b}) does not fit into a width"));
+                ctx.bit_vec_val(width, 32)
+            }
             Arith::Width(width) => ctx.bit_vec_val(*width, 32),
             Arith::Sign(sign) => ctx.bit_vec_val(*sign, 1),
             Arith::Const(value) => {
@@ -368,6 +799,74 @@ pub fn from_arith(ctx: &mut Context, expr: &RecExpr<Arith>) -> ExprRef {
     stack.pop().unwrap()
 }
 
+/// Like [`from_arith`], but returns an error instead of panicking if a width computation,
+/// e.g. `max+1` or `wlsh`, depends on a width that is not a constant or if an expression is
+/// repeated zero times.
+pub fn try_from_arith(ctx: &mut Context, expr: &RecExpr<Arith>) -> Result<ExprRef, EGraphError> {
+    Ok(from_arith(ctx, &eval_widths(expr)?))
+}
+
+/// Replaces all width computations with the constant width they evaluate to. This is the
+/// same constant folding that [`WidthConstantFold`] performs inside the e-graph.
+pub fn eval_widths(expr: &RecExpr<Arith>) -> Result<RecExpr<Arith>, EGraphError> {
+    let nodes = expr.as_ref();
+    let mut widths: Vec<Option<WidthInt>> = Vec::with_capacity(nodes.len());
+    let mut out = RecExpr::default();
+    for node in nodes.iter() {
+        let width = match node {
+            Arith::Width(w) => Some(w.0),
+            Arith::WidthMaxPlus1([a, b])
+            | Arith::WidthLeftShift([a, b])
+            | Arith::WidthAdd([a, b])
+            | Arith::WidthMul([a, b]) => {
+                let arg = |id: &Id| {
+                    widths[usize::from(*id)].ok_or_else(|| {
+                        EGraphError::SymbolicWidth(format!(
+                            "({node} {} {})",
+                            nodes[usize::from(*a)],
+                            nodes[usize::from(*b)]
+                        ))
+                    })
+                };
+                let (a, b) = (arg(a)?, arg(b)?);
+                Some(match node {
+                    Arith::WidthMaxPlus1(_) => eval_width_max_plus_1(a, b),
+                    Arith::WidthLeftShift(_) => eval_width_left_shift(a, b),
+                    _ => eval_width_arith(node, a, b).ok_or_else(|| {
+                        EGraphError::InvalidExpr(format!(
+                            "({node} {a} {b}) does not fit into a width"
+                        ))
+                    })?,
+                })
+            }
+            _ => None,
+        };
+        if let Arith::Repeat([_, n, _, _]) = node {
+            if widths[usize::from(*n)] == Some(0) {
+                return Err(EGraphError::InvalidExpr(format!(
+                    "`{node}` repeats an expression zero times"
+                )));
+            }
+        }
+        widths.push(width);
+        // children keep their index since we add exactly one node per node
+        match width {
+            Some(w) => out.add(w.into()),
+            None => out.add(node.clone()),
+        };
+    }
+    Ok(out)
+}
+
+/// Computes `w+` and `w*`, `None` if the result does not fit into a [`WidthInt`].
+fn eval_width_arith(op: &Arith, a: WidthInt, b: WidthInt) -> Option<WidthInt> {
+    match op {
+        Arith::WidthAdd(_) => a.checked_add(b),
+        Arith::WidthMul(_) => a.checked_mul(b),
+        other => unreachable!("`{other}` is not a width sum or product"),
+    }
+}
+
 /// extracts the expected widths of all proper child expressions
 fn get_child_widths(root: usize, expressions: &[Arith], out: &mut Vec<WidthInt>) {
     debug_assert!(out.is_empty());
@@ -380,8 +879,41 @@ fn get_child_widths(root: usize, expressions: &[Arith], out: &mut Vec<WidthInt>)
         out.extend_from_slice(&[0, 0, 0, a_width, 0, 0, b_width]);
     } else {
         match expr {
+            Arith::Negate([_, w_a, _, _]) => {
+                let a_width = get_width(usize::from(*w_a), expressions);
+                out.extend_from_slice(&[0, 0, 0, a_width]);
+            }
+            Arith::Concat([_, w_a, _, w_b, _]) => {
+                let a_width = get_width(usize::from(*w_a), expressions);
+                let b_width = get_width(usize::from(*w_b), expressions);
+                out.extend_from_slice(&[0, 0, a_width, 0, b_width]);
+            }
+            Arith::Repeat([_, _, w_a, _]) => {
+                let a_width = get_width(usize::from(*w_a), expressions);
+                out.extend_from_slice(&[0, 0, 0, a_width]);
+            }
+            Arith::RotateLeft([w, _, w_b, _]) | Arith::RotateRight([w, _, w_b, _]) => {
+                let a_width = get_width(usize::from(*w), expressions);
+                let b_width = get_width(usize::from(*w_b), expressions);
+                out.extend_from_slice(&[0, a_width, 0, b_width]);
+            }
+            Arith::RotateLeftConst([w, _, _]) => {
+                let a_width = get_width(usize::from(*w), expressions);
+                out.extend_from_slice(&[0, 0, a_width]);
+            }
+            Arith::SaturatingAdd([_, _, w_a, _, _, w_b, _, _])
+            | Arith::SaturatingSub([_, _, w_a, _, _, w_b, _, _])
+            | Arith::Greater([_, _, w_a, _, _, w_b, _, _])
+            | Arith::GreaterEqual([_, _, w_a, _, _, w_b, _, _]) => {
+                let a_width = get_width(usize::from(*w_a), expressions);
+                let b_width = get_width(usize::from(*w_b), expressions);
+                out.extend_from_slice(&[0, 0, 0, 0, a_width, 0, 0, b_width]);
+            }
             // calculated width
-            Arith::WidthMaxPlus1(_) | Arith::WidthLeftShift(_) => {
+            Arith::WidthMaxPlus1(_)
+            | Arith::WidthLeftShift(_)
+            | Arith::WidthAdd(_)
+            | Arith::WidthMul(_) => {
                 // widths are always propagated as 32-bit values
                 out.extend_from_slice(&[32, 32]);
             }
@@ -406,7 +938,13 @@ fn get_width(root: usize, expressions: &[Arith]) -> WidthInt {
             let b = get_width(usize::from(*b), expressions);
             eval_width_left_shift(a, b)
         }
-        other => todo!("calculate width for {other:?}"),
+        op @ (Arith::WidthAdd([a, b]) | Arith::WidthMul([a, b])) => {
+            let a = get_width(usize::from(*a), expressions);
+            let b = get_width(usize::from(*b), expressions);
+            eval_width_arith(op, a, b)
+                .unwrap_or_else(|| panic!("({op} {a} {b}) does not fit into a width"))
+        }
+        other => unreachable!("`{other}` is not a constant width, use `try_from_arith`"),
     }
 }
 
@@ -481,6 +1019,21 @@ fn extend(
     }
 }
 
+/// Extends an operand to `w_out` bits, wider operands are truncated.
+fn resize(
+    ctx: &mut Context,
+    expr: ExprRef,
+    w_out: WidthInt,
+    w_in: WidthInt,
+    signed: bool,
+) -> ExprRef {
+    if w_in > w_out {
+        ctx.slice(expr, w_out - 1, 0)
+    } else {
+        extend(ctx, expr, w_out, w_in, signed)
+    }
+}
+
 pub type EGraph = egg::EGraph<Arith, WidthConstantFold>;
 
 /// Finds a width or sign constant in the e-class referred to by the substitution
@@ -497,6 +1050,18 @@ pub fn get_const_width_or_sign(egraph: &EGraph, id: Id) -> Option<WidthInt> {
         .next()
 }
 
+/// Returns the value of a constant in the e-class, if there is one.
+pub fn get_const(egraph: &EGraph, id: Id) -> Option<u64> {
+    egraph[id]
+        .nodes
+        .iter()
+        .flat_map(|n| match n {
+            Arith::Const(value) => Some(*value),
+            _ => None,
+        })
+        .next()
+}
+
 #[cfg(test)]
 pub(crate) fn verification_fig_1(ctx: &mut Context) -> (ExprRef, ExprRef) {
     let a = ctx.bv_symbol("A", 16);
@@ -520,6 +1085,28 @@ pub(crate) fn verification_fig_1(ctx: &mut Context) -> (ExprRef, ExprRef) {
     (spec, implementation)
 }
 
+#[cfg(test)]
+pub(crate) fn verification_distributivity(ctx: &mut Context) -> (ExprRef, ExprRef) {
+    let a = ctx.bv_symbol("A", 8);
+    let b = ctx.bv_symbol("B", 8);
+    let c = ctx.bv_symbol("C", 8);
+    // A * (B + C)
+    let spec = ctx.build(|x| {
+        x.mul(
+            x.zero_extend(a, 9),
+            x.zero_extend(x.add(x.zero_extend(b, 1), x.zero_extend(c, 1)), 8),
+        )
+    });
+    // A * B + A * C
+    let implementation = ctx.build(|x| {
+        x.add(
+            x.zero_extend(x.mul(x.zero_extend(a, 8), x.zero_extend(b, 8)), 1),
+            x.zero_extend(x.mul(x.zero_extend(a, 8), x.zero_extend(c, 8)), 1),
+        )
+    });
+    (spec, implementation)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -529,8 +1116,8 @@ mod tests {
         let mut ctx = Context::default();
         let (spec, implementation) = verification_fig_1(&mut ctx);
 
-        let spec_e = to_arith(&ctx, spec);
-        let impl_e = to_arith(&ctx, implementation);
+        let spec_e = to_arith(&ctx, spec).unwrap();
+        let impl_e = to_arith(&ctx, implementation).unwrap();
 
         // convert back into out IR
         let spec_back = from_arith(&mut ctx, &spec_e);
@@ -540,4 +1127,149 @@ mod tests {
         assert_eq!(spec_back, spec);
         assert_eq!(impl_back, implementation);
     }
+
+    #[test]
+    fn test_concat_and_repeat_conversion() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("A", 8);
+        let b = ctx.bv_symbol("B", 4);
+        let e = ctx.build(|c| c.concat(c.add(a, a), c.concat(b, c.zero(4))));
+        let e_arith = to_arith(&ctx, e).unwrap();
+        assert_eq!(from_arith(&mut ctx, &e_arith), e);
+
+        // repeat is expanded into a chain of concatenations
+        let repeat: RecExpr<Arith> = "(repeat W<12> W<3> W<4> B)".parse().unwrap();
+        let expected = ctx.build(|c| c.concat(c.concat(b, b), b));
+        assert_eq!(from_arith(&mut ctx, &repeat), expected);
+        let empty: RecExpr<Arith> = "(repeat W<0> W<0> W<4> B)".parse().unwrap();
+        assert!(matches!(
+            try_from_arith(&mut ctx, &empty),
+            Err(EGraphError::InvalidExpr(_))
+        ));
+
+        // extensions cannot be represented inside a concatenation
+        let ext = ctx.build(|c| c.concat(c.zero_extend(b, 4), a));
+        assert!(to_arith(&ctx, ext).is_err());
+    }
+
+    #[test]
+    fn test_shared_conversion() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("a", 8);
+        let b = ctx.bv_symbol("b", 8);
+        // a tree traversal would visit 2^64 nodes
+        let mut doubled = a;
+        for _ in 0..64 {
+            doubled = ctx.add(doubled, doubled);
+        }
+        let product = ctx.mul(doubled, b);
+        let unsupported = ctx.build(|c| c.add(doubled, c.and(a, b)));
+
+        let mut converter = ArithConverter::new(&ctx, WidthLimits::default());
+        assert!(converter.convert(unsupported).is_none());
+        let root = converter.convert(product).unwrap();
+        // the sum was converted as part of the failed conversion and is reused
+        assert!(converter.converted[&doubled].is_some());
+        let expr = converter.into_expr();
+        assert!(expr.as_ref().len() < 64 * 8);
+        assert!(matches!(expr[root], Arith::Mul(_)));
+    }
+
+    #[test]
+    fn test_comparison_conversion() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("a", 4);
+        let b = ctx.bv_symbol("b", 6);
+        let e = ctx.build(|c| c.greater_signed(c.zero_extend(a, 4), c.sign_extend(b, 2)));
+        let e_arith = to_arith(&ctx, e).unwrap();
+        assert_eq!(
+            e_arith.to_string(),
+            "(> W<8> sign W<4> unsign a W<6> sign b)"
+        );
+        assert_eq!(from_arith(&mut ctx, &e_arith), e);
+
+        let ge = ctx.build(|c| c.greater_or_equal(c.add(a, a), a));
+        let ge_arith = to_arith(&ctx, ge).unwrap();
+        assert_eq!(from_arith(&mut ctx, &ge_arith), ge);
+    }
+
+    #[test]
+    fn test_eval_widths() {
+        let mut ctx = Context::default();
+        let e: RecExpr<Arith> = "(<< W<8> W<4> unsign a (max+1 W<2> (wlsh W<1> W<2>)) unsign b)"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            eval_widths(&e).unwrap().to_string(),
+            "(<< W<8> W<4> unsign a W<5> unsign b)"
+        );
+        let a = ctx.bv_symbol("a", 4);
+        let b = ctx.bv_symbol("b", 5);
+        let expected = ctx.build(|c| c.shift_left(c.zero_extend(a, 4), c.zero_extend(b, 3)));
+        assert_eq!(try_from_arith(&mut ctx, &e).unwrap(), expected);
+
+        let symbolic: RecExpr<Arith> = "(+ W<8> (max+1 W<2> x) unsign a W<2> unsign b)"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            try_from_arith(&mut ctx, &symbolic),
+            Err(EGraphError::SymbolicWidth("(max+1 W<2> x)".to_string()))
+        );
+
+        let huge: RecExpr<Arith> = "(w* W<65536> W<65536>)".parse().unwrap();
+        assert!(matches!(
+            eval_widths(&huge),
+            Err(EGraphError::InvalidExpr(_))
+        ));
+    }
+
+    #[test]
+    fn test_to_arith_unsupported() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("A", 16);
+        let b = ctx.bv_symbol("B", 16);
+        let e = ctx.build(|c| c.add(c.and(a, b), b));
+        assert!(matches!(
+            to_arith(&ctx, e),
+            Err(EGraphError::UnsupportedExpr(s)) if s == "and(A, B)"
+        ));
+    }
+
+    #[test]
+    fn test_to_canonical_arith() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("A", 16);
+        let b = ctx.bv_symbol("B", 16);
+        let c = ctx.bv_symbol("C", 16);
+        let left = ctx.build(|x| x.mul(x.add(a, b), c));
+        let right = ctx.build(|x| x.mul(c, x.add(b, a)));
+        assert_ne!(
+            to_arith(&ctx, left).unwrap(),
+            to_arith(&ctx, right).unwrap()
+        );
+        assert_eq!(
+            to_canonical_arith(&mut ctx, left).unwrap(),
+            to_canonical_arith(&mut ctx, right).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_to_arith_width_limits() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("A", 64);
+        let b = ctx.bv_symbol("B", 64);
+        let e = ctx.build(|c| c.add(a, b));
+        let limits = WidthLimits {
+            max_width: 32,
+            ..WidthLimits::default()
+        };
+        assert!(to_arith(&ctx, e).is_ok());
+        assert!(matches!(
+            to_arith_with_limits(&ctx, e, &limits),
+            Err(EGraphError::Limit(LimitError::TooWide { width: 64, .. }))
+        ));
+        // derived widths saturate instead of overflowing
+        assert_eq!(eval_width_max_plus_1(WidthInt::MAX, 3), WidthInt::MAX);
+        assert_eq!(eval_width_left_shift(WidthInt::MAX - 1, 4), WidthInt::MAX);
+    }
 }
@@ -0,0 +1,153 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+/*!
+# Batch Equivalence Checking
+
+Regression suites often contain thousands of independent `(spec, implementation)` pairs.
+[`check_equivalence_batch`] distributes them over a pool of threads. Every check uses its own
+e-graph, while the rewrite rules are converted once and shared by all threads. Results are
+returned in the order of the pairs, no matter which thread finished first.
+
+!*/
+
+use crate::limits::check_equivalence_with_egg_rules;
+use crate::{Arith, ArithRewrite, EGraphEquivResult, Rewrite};
+use egg::RecExpr;
+use patronus::config::EGraphConfig;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+pub struct EquivPair {
+    pub name: String,
+    pub spec: RecExpr<Arith>,
+    pub implementation: RecExpr<Arith>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PairResult {
+    pub name: String,
+    pub result: EGraphEquivResult,
+    pub time: Duration,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BatchReport {
+    /// in the same order as the pairs
+    pub results: Vec<PairResult>,
+    /// wall clock time of the whole batch
+    pub time: Duration,
+}
+
+impl BatchReport {
+    pub fn num_equivalent(&self) -> usize {
+        self.results
+            .iter()
+            .filter(|r| r.result.is_equivalent())
+            .count()
+    }
+
+    /// Pairs for which equivalence could not be established.
+    pub fn failed(&self) -> impl Iterator<Item = &PairResult> + '_ {
+        self.results.iter().filter(|r| !r.result.is_equivalent())
+    }
+}
+
+/// Checks all `pairs` with up to `threads` threads. Uses one thread per available core if
+/// `threads` is zero.
+pub fn check_equivalence_batch(
+    pairs: &[EquivPair],
+    rules: &[ArithRewrite],
+    config: &EGraphConfig,
+    threads: usize,
+) -> BatchReport {
+    let start = Instant::now();
+    let egg_rules: Vec<Rewrite> = rules.iter().flat_map(|r| r.to_egg()).collect();
+    let threads = if threads == 0 {
+        std::thread::available_parallelism().map_or(1, |n| n.get())
+    } else {
+        threads
+    };
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<PairResult>>> = Mutex::new(vec![None; pairs.len()]);
+    std::thread::scope(|s| {
+        for _ in 0..threads.min(pairs.len()) {
+            s.spawn(|| loop {
+                let ii = next.fetch_add(1, Ordering::Relaxed);
+                let Some(pair) = pairs.get(ii) else {
+                    break;
+                };
+                let pair_start = Instant::now();
+                let result = check_equivalence_with_egg_rules(
+                    &pair.spec,
+                    &pair.implementation,
+                    &egg_rules,
+                    config,
+                );
+                results.lock().unwrap()[ii] = Some(PairResult {
+                    name: pair.name.clone(),
+                    result,
+                    time: pair_start.elapsed(),
+                });
+            });
+        }
+    });
+    let results = results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|r| r.expect("every pair was checked"))
+        .collect();
+    BatchReport {
+        results,
+        time: start.elapsed(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arithmetic::verification_fig_1;
+    use crate::{create_rewrites, to_arith};
+    use patronus::expr::Context;
+
+    #[test]
+    fn test_batch_keeps_order() {
+        let mut ctx = Context::default();
+        let (spec, implementation) = verification_fig_1(&mut ctx);
+        let (spec, implementation) = (
+            to_arith(&ctx, spec).unwrap(),
+            to_arith(&ctx, implementation).unwrap(),
+        );
+        let a = ctx.bv_symbol("a", 8);
+        let b = ctx.bv_symbol("b", 8);
+        let different = EquivPair {
+            name: "different".to_string(),
+            spec: to_arith(&ctx, a).unwrap(),
+            implementation: to_arith(&ctx, b).unwrap(),
+        };
+        let mut pairs: Vec<EquivPair> = (0..3)
+            .map(|ii| EquivPair {
+                name: format!("fig1_{ii}"),
+                spec: spec.clone(),
+                implementation: implementation.clone(),
+            })
+            .collect();
+        pairs.insert(1, different);
+
+        let report =
+            check_equivalence_batch(&pairs, &create_rewrites(), &EGraphConfig::default(), 2);
+        let names: Vec<&str> = report.results.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["fig1_0", "different", "fig1_1", "fig1_2"]);
+        assert_eq!(report.num_equivalent(), 3);
+        let failed: Vec<&str> = report.failed().map(|r| r.name.as_str()).collect();
+        assert_eq!(failed, ["different"]);
+        assert!(
+            check_equivalence_batch(&[], &[], &EGraphConfig::default(), 0)
+                .results
+                .is_empty()
+        );
+    }
+}
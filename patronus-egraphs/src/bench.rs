@@ -0,0 +1,57 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+/*!
+# Benchmarks
+
+Times equality saturation on the next state functions of the systems in
+[`patronus::bench::suite`]. Only available with the `bench` feature.
+
+!*/
+
+use crate::{configure_runner, create_egg_rewrites, to_arith};
+use patronus::bench::{suite, BenchSystem};
+use patronus::config::EGraphConfig;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EGraphBenchResult {
+    pub name: String,
+    /// number of next state functions that could be converted and were saturated
+    pub exprs: usize,
+    pub iterations: usize,
+    pub nodes: usize,
+    pub classes: usize,
+    pub elapsed: Duration,
+}
+
+/// Adds all next state functions that can be converted into a single e-graph and runs the
+/// rewrites configured in `config`.
+pub fn run_egraph(bench: &BenchSystem, config: &EGraphConfig) -> EGraphBenchResult {
+    let exprs: Vec<_> = bench
+        .sys
+        .get_next_exprs()
+        .into_iter()
+        .flat_map(|e| to_arith(&bench.ctx, e).ok())
+        .collect();
+    let start = Instant::now();
+    let runner = exprs
+        .iter()
+        .fold(configure_runner(egg::Runner::default(), config), |r, e| {
+            r.with_expr(e)
+        })
+        .run(&create_egg_rewrites());
+    EGraphBenchResult {
+        name: bench.name.clone(),
+        exprs: exprs.len(),
+        iterations: runner.iterations.len(),
+        nodes: runner.egraph.total_number_of_nodes(),
+        classes: runner.egraph.number_of_classes(),
+        elapsed: start.elapsed(),
+    }
+}
+
+/// Runs [`run_egraph`] on every system in the suite.
+pub fn run_egraph_suite(config: &EGraphConfig) -> Vec<EGraphBenchResult> {
+    suite().iter().map(|b| run_egraph(b, config)).collect()
+}
@@ -0,0 +1,16 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! Prints a markdown catalog of all built-in arithmetic rewrite rules.
+//! Usage: `rule-catalog [OUTPUT.md]`
+
+use patronus_egraphs::{create_rewrites, rule_catalog};
+
+fn main() {
+    let catalog = rule_catalog(&create_rewrites());
+    match std::env::args().nth(1) {
+        Some(filename) => std::fs::write(&filename, catalog).expect("failed to write catalog"),
+        None => print!("{catalog}"),
+    }
+}
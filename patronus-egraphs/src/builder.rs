@@ -0,0 +1,367 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+/*!
+# Building Arithmetic Expressions
+
+[`ArithBuilder`] creates a [`RecExpr<Arith>`] directly, without a patronus [`Context`](patronus::expr::Context).
+Widths and signs are added as e-nodes automatically. Every operation checks the same
+properties that [`to_arith`](crate::to_arith) guarantees for converted expressions: operands
+of binary operations are never wider than the result, constants fit their width and a symbol
+always has the same width.
+
+!*/
+
+use crate::{Arith, EGraphError, Sign};
+use egg::{Id, Language, RecExpr};
+use patronus::expr::WidthInt;
+use rustc_hash::FxHashMap;
+
+/// A node of an [`ArithBuilder`] together with its width.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArithValue {
+    id: Id,
+    width: WidthInt,
+}
+
+impl ArithValue {
+    pub fn id(&self) -> Id {
+        self.id
+    }
+
+    pub fn width(&self) -> WidthInt {
+        self.width
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ArithBuilder {
+    expr: RecExpr<Arith>,
+    symbols: FxHashMap<String, WidthInt>,
+}
+
+impl ArithBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn symbol(&mut self, name: &str, width: WidthInt) -> Result<ArithValue, EGraphError> {
+        match self.symbols.get(name) {
+            Some(&w) if w != width => {
+                return Err(invalid(format!(
+                    "symbol `{name}` was declared with {w} bits, not {width}"
+                )))
+            }
+            _ => {}
+        }
+        check_width(width)?;
+        self.symbols.insert(name.to_string(), width);
+        Ok(self.value(Arith::Symbol(name.to_string()), width))
+    }
+
+    pub fn constant(&mut self, value: u64, width: WidthInt) -> Result<ArithValue, EGraphError> {
+        check_width(width)?;
+        if width < u64::BITS && value >> width != 0 {
+            return Err(invalid(format!("{value} does not fit into {width} bits")));
+        }
+        Ok(self.value(Arith::Const(value), width))
+    }
+
+    pub fn add(
+        &mut self,
+        width: WidthInt,
+        a: (ArithValue, Sign),
+        b: (ArithValue, Sign),
+    ) -> Result<ArithValue, EGraphError> {
+        self.bin_op(Arith::Add, width, a, b)
+    }
+
+    pub fn sub(
+        &mut self,
+        width: WidthInt,
+        a: (ArithValue, Sign),
+        b: (ArithValue, Sign),
+    ) -> Result<ArithValue, EGraphError> {
+        self.bin_op(Arith::Sub, width, a, b)
+    }
+
+    pub fn mul(
+        &mut self,
+        width: WidthInt,
+        a: (ArithValue, Sign),
+        b: (ArithValue, Sign),
+    ) -> Result<ArithValue, EGraphError> {
+        self.bin_op(Arith::Mul, width, a, b)
+    }
+
+    pub fn shift_left(
+        &mut self,
+        width: WidthInt,
+        a: (ArithValue, Sign),
+        b: (ArithValue, Sign),
+    ) -> Result<ArithValue, EGraphError> {
+        self.bin_op(Arith::LeftShift, width, a, b)
+    }
+
+    pub fn shift_right(
+        &mut self,
+        width: WidthInt,
+        a: (ArithValue, Sign),
+        b: (ArithValue, Sign),
+    ) -> Result<ArithValue, EGraphError> {
+        self.bin_op(Arith::RightShift, width, a, b)
+    }
+
+    pub fn arithmetic_shift_right(
+        &mut self,
+        width: WidthInt,
+        a: (ArithValue, Sign),
+        b: (ArithValue, Sign),
+    ) -> Result<ArithValue, EGraphError> {
+        self.bin_op(Arith::ArithmeticRightShift, width, a, b)
+    }
+
+    /// Two's complement negation of `a` extended to `width` bits.
+    pub fn negate(
+        &mut self,
+        width: WidthInt,
+        (a, sign_a): (ArithValue, Sign),
+    ) -> Result<ArithValue, EGraphError> {
+        check_width(width)?;
+        check_extension(width, &[a])?;
+        let (w, wa, sa) = (self.width(width), self.width(a.width), self.sign(sign_a));
+        Ok(self.value(Arith::Negate([w, wa, sa, a.id]), width))
+    }
+
+    /// `a` is placed in the most significant bits.
+    pub fn concat(&mut self, a: ArithValue, b: ArithValue) -> Result<ArithValue, EGraphError> {
+        let width = a
+            .width
+            .checked_add(b.width)
+            .ok_or_else(|| invalid("concatenation is too wide".to_string()))?;
+        let (w, wa, wb) = (self.width(width), self.width(a.width), self.width(b.width));
+        Ok(self.value(Arith::Concat([w, wa, a.id, wb, b.id]), width))
+    }
+
+    pub fn repeat(&mut self, n: WidthInt, a: ArithValue) -> Result<ArithValue, EGraphError> {
+        if n == 0 {
+            return Err(invalid("cannot repeat a value zero times".to_string()));
+        }
+        let width = n
+            .checked_mul(a.width)
+            .ok_or_else(|| invalid("repetition is too wide".to_string()))?;
+        let (w, n, wa) = (self.width(width), self.width(n), self.width(a.width));
+        Ok(self.value(Arith::Repeat([w, n, wa, a.id]), width))
+    }
+
+    /// Rotates `a` left by the unsigned amount `b`.
+    pub fn rotate_left(&mut self, a: ArithValue, b: ArithValue) -> ArithValue {
+        let (w, wb) = (self.width(a.width), self.width(b.width));
+        self.value(Arith::RotateLeft([w, a.id, wb, b.id]), a.width)
+    }
+
+    /// Rotates `a` right by the unsigned amount `b`.
+    pub fn rotate_right(&mut self, a: ArithValue, b: ArithValue) -> ArithValue {
+        let (w, wb) = (self.width(a.width), self.width(b.width));
+        self.value(Arith::RotateRight([w, a.id, wb, b.id]), a.width)
+    }
+
+    pub fn rotate_left_by(
+        &mut self,
+        a: ArithValue,
+        n: WidthInt,
+    ) -> Result<ArithValue, EGraphError> {
+        if n >= a.width {
+            return Err(invalid(format!(
+                "rotation by {n} is not smaller than the width {}",
+                a.width
+            )));
+        }
+        let (w, n) = (self.width(a.width), self.width(n));
+        Ok(self.value(Arith::RotateLeftConst([w, n, a.id]), a.width))
+    }
+
+    pub fn saturating_add(
+        &mut self,
+        width: WidthInt,
+        sign: Sign,
+        a: (ArithValue, Sign),
+        b: (ArithValue, Sign),
+    ) -> Result<ArithValue, EGraphError> {
+        self.saturating(Arith::SaturatingAdd, width, sign, a, b)
+    }
+
+    pub fn saturating_sub(
+        &mut self,
+        width: WidthInt,
+        sign: Sign,
+        a: (ArithValue, Sign),
+        b: (ArithValue, Sign),
+    ) -> Result<ArithValue, EGraphError> {
+        self.saturating(Arith::SaturatingSub, width, sign, a, b)
+    }
+
+    /// Compares both operands after extending them to `width` bits. The result has 1 bit.
+    pub fn greater(
+        &mut self,
+        width: WidthInt,
+        sign: Sign,
+        a: (ArithValue, Sign),
+        b: (ArithValue, Sign),
+    ) -> Result<ArithValue, EGraphError> {
+        let res = self.saturating(Arith::Greater, width, sign, a, b)?;
+        Ok(ArithValue { width: 1, ..res })
+    }
+
+    pub fn greater_equal(
+        &mut self,
+        width: WidthInt,
+        sign: Sign,
+        a: (ArithValue, Sign),
+        b: (ArithValue, Sign),
+    ) -> Result<ArithValue, EGraphError> {
+        let res = self.saturating(Arith::GreaterEqual, width, sign, a, b)?;
+        Ok(ArithValue { width: 1, ..res })
+    }
+
+    /// Returns the expression rooted at `root`. Nodes that `root` does not depend on are removed.
+    pub fn finish(self, root: ArithValue) -> RecExpr<Arith> {
+        let nodes = self.expr.as_ref();
+        let root = usize::from(root.id);
+        let mut used = vec![false; root + 1];
+        used[root] = true;
+        // children always come before their parents
+        for ii in (0..=root).rev() {
+            if used[ii] {
+                for c in nodes[ii].children() {
+                    used[usize::from(*c)] = true;
+                }
+            }
+        }
+        let mut new_ids: Vec<Id> = Vec::with_capacity(root + 1);
+        let mut out = RecExpr::default();
+        for (ii, node) in nodes[..=root].iter().enumerate() {
+            let id = if used[ii] {
+                out.add(node.clone().map_children(|c| new_ids[usize::from(c)]))
+            } else {
+                Id::from(0)
+            };
+            new_ids.push(id);
+        }
+        out
+    }
+
+    fn bin_op(
+        &mut self,
+        op: fn([Id; 7]) -> Arith,
+        width: WidthInt,
+        (a, sign_a): (ArithValue, Sign),
+        (b, sign_b): (ArithValue, Sign),
+    ) -> Result<ArithValue, EGraphError> {
+        check_width(width)?;
+        check_extension(width, &[a, b])?;
+        let (w, wa, wb) = (self.width(width), self.width(a.width), self.width(b.width));
+        let (sa, sb) = (self.sign(sign_a), self.sign(sign_b));
+        Ok(self.value(op([w, wa, sa, a.id, wb, sb, b.id]), width))
+    }
+
+    /// Saturating operations and comparisons share the same arguments.
+    fn saturating(
+        &mut self,
+        op: fn([Id; 8]) -> Arith,
+        width: WidthInt,
+        sign: Sign,
+        (a, sign_a): (ArithValue, Sign),
+        (b, sign_b): (ArithValue, Sign),
+    ) -> Result<ArithValue, EGraphError> {
+        check_width(width)?;
+        check_extension(width, &[a, b])?;
+        let (w, s) = (self.width(width), self.sign(sign));
+        let (wa, wb) = (self.width(a.width), self.width(b.width));
+        let (sa, sb) = (self.sign(sign_a), self.sign(sign_b));
+        Ok(self.value(op([w, s, wa, sa, a.id, wb, sb, b.id]), width))
+    }
+
+    fn width(&mut self, width: WidthInt) -> Id {
+        self.expr.add(width.into())
+    }
+
+    fn sign(&mut self, sign: Sign) -> Id {
+        self.expr.add(sign.into())
+    }
+
+    fn value(&mut self, node: Arith, width: WidthInt) -> ArithValue {
+        let id = self.expr.add(node);
+        ArithValue { id, width }
+    }
+}
+
+fn invalid(msg: String) -> EGraphError {
+    EGraphError::InvalidExpr(msg)
+}
+
+fn check_width(width: WidthInt) -> Result<(), EGraphError> {
+    if width == 0 {
+        Err(invalid("bit-vectors need at least one bit".to_string()))
+    } else {
+        Ok(())
+    }
+}
+
+/// Operands are extended to the width of the result, they can thus never be wider.
+fn check_extension(width: WidthInt, operands: &[ArithValue]) -> Result<(), EGraphError> {
+    match operands.iter().find(|o| o.width > width) {
+        Some(o) => Err(invalid(format!(
+            "operand with {} bits is wider than the result with {width} bits",
+            o.width
+        ))),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::to_arith;
+    use patronus::expr::Context;
+
+    #[test]
+    fn test_builder_matches_conversion() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("A", 8);
+        let b = ctx.bv_symbol("B", 4);
+        let e = ctx.build(|c| c.mul(c.add(a, c.zero_extend(b, 4)), c.bit_vec_val(3, 8)));
+        let expected = to_arith(&ctx, e).unwrap();
+
+        let mut builder = ArithBuilder::new();
+        let (a, b) = (
+            builder.symbol("A", 8).unwrap(),
+            builder.symbol("B", 4).unwrap(),
+        );
+        let unused = builder.symbol("C", 8).unwrap();
+        let sum = builder
+            .add(8, (a, Sign::Unsigned), (b, Sign::Unsigned))
+            .unwrap();
+        let three = builder.constant(3, 8).unwrap();
+        let product = builder
+            .mul(8, (sum, Sign::Unsigned), (three, Sign::Unsigned))
+            .unwrap();
+        // an unused node after the root
+        builder.concat(unused, a).unwrap();
+        let expr = builder.finish(product);
+        assert_eq!(expr.to_string(), expected.to_string());
+
+        // the same checks as the conversion
+        let mut builder = ArithBuilder::new();
+        let a = builder.symbol("A", 8).unwrap();
+        assert!(builder.symbol("A", 4).is_err());
+        assert!(builder.constant(16, 4).is_err());
+        assert!(builder
+            .add(4, (a, Sign::Unsigned), (a, Sign::Unsigned))
+            .is_err());
+        assert!(builder.rotate_left_by(a, 8).is_err());
+        let wide = builder.symbol("W", 1 << 31).unwrap();
+        assert!(builder.concat(wide, wide).is_err());
+        assert!(builder.repeat(3, wide).is_err());
+    }
+}
@@ -0,0 +1,357 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+/*!
+# Proof Cache
+
+Datapaths are often instantiated many times with different parameters. [`ProofCache`]
+remembers the outcome of equivalence checks, so that a pair of expressions with the same
+shape does not trigger another saturation run. Symbols are always renamed in the order in
+which they appear, which is sound since equivalence does not depend on the names.
+
+In width-generic mode, a proof found for one instantiation is reused as a hint for all
+instantiations whose widths compare the same way against each other, against the constants
+`1` and `2` and against the sum, `max+1` and `wlsh` of any two widths. Since rules may match
+on widths that are derived during saturation or turn widths into constants, a hint is never
+returned as a result. Instead, the check is repeated at the concrete widths with only the
+rules that the original proof applied, which is typically a lot faster than saturating with
+all rules. If this does not establish equivalence, the full check runs.
+
+Only results that do not depend on the resource limits are cached, i.e., equivalent and
+saturated. Saturated results are only reused for the exact same widths. The cache is tied to
+the names of the rules it was created with and is cleared when it is used with a different
+rule set.
+
+!*/
+
+use crate::arithmetic::{eval_width_left_shift, eval_width_max_plus_1};
+use crate::limits::{check_equivalence_with_egg_rules, check_equivalence_with_runner};
+use crate::{configure_runner, Arith, ArithRewrite, EGraphEquivResult, Rewrite};
+use egg::{Id, Language, RecExpr};
+use patronus::config::EGraphConfig;
+use patronus::expr::WidthInt;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+
+/// Above this number of distinct widths, the width profile gets too large and no hint is
+/// recorded.
+const MAX_GENERIC_WIDTHS: usize = 16;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ProofCacheError {
+    #[error("failed to read or write proof cache")]
+    Io(#[from] std::io::Error),
+    #[error("invalid proof cache: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum CachedResult {
+    Equivalent,
+    Saturated,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofCache {
+    generic: bool,
+    rules: Vec<String>,
+    /// results for the exact widths
+    entries: BTreeMap<String, CachedResult>,
+    /// rules applied by a proof, keyed by the width-generic form of the proven expressions
+    #[serde(default)]
+    hints: BTreeMap<String, Vec<String>>,
+    #[serde(skip)]
+    hits: usize,
+    #[serde(skip)]
+    rechecks: usize,
+    #[serde(skip)]
+    misses: usize,
+}
+
+impl ProofCache {
+    /// Creates an empty cache that reuses results across widths iff `generic` is set.
+    pub fn new(generic: bool) -> Self {
+        Self {
+            generic,
+            ..Default::default()
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ProofCacheError> {
+        let file = std::io::BufReader::new(std::fs::File::open(path)?);
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ProofCacheError> {
+        let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        serde_json::to_writer(file, self)?;
+        Ok(())
+    }
+
+    /// Same as [`crate::check_equivalence`], but returns a cached result if there is one.
+    pub fn check_equivalence(
+        &mut self,
+        lhs: &RecExpr<Arith>,
+        rhs: &RecExpr<Arith>,
+        rules: &[ArithRewrite],
+        config: &EGraphConfig,
+    ) -> EGraphEquivResult {
+        let names: Vec<String> = rules.iter().map(|r| r.name().to_string()).collect();
+        if names != self.rules {
+            self.entries.clear();
+            self.hints.clear();
+            self.rules = names;
+        }
+        let key = canonical(lhs, rhs, true).0;
+        if let Some(cached) = self.entries.get(&key) {
+            self.hits += 1;
+            return match cached {
+                CachedResult::Equivalent => EGraphEquivResult::Equivalent,
+                CachedResult::Saturated => EGraphEquivResult::Saturated,
+            };
+        }
+        let generic_key = self.generic_key(lhs, rhs);
+        if let Some(applied) = generic_key.as_ref().and_then(|k| self.hints.get(k)) {
+            let egg_rules: Vec<Rewrite> = rules
+                .iter()
+                .filter(|r| applied.iter().any(|a| a == r.name()))
+                .flat_map(|r| r.to_egg())
+                .collect();
+            if check_equivalence_with_egg_rules(lhs, rhs, &egg_rules, config).is_equivalent() {
+                self.rechecks += 1;
+                self.entries.insert(key, CachedResult::Equivalent);
+                return EGraphEquivResult::Equivalent;
+            }
+        }
+        self.misses += 1;
+        let egg_rules: Vec<Rewrite> = rules.iter().flat_map(|r| r.to_egg()).collect();
+        let runner = configure_runner(egg::Runner::default(), config);
+        let run = check_equivalence_with_runner(runner, lhs, rhs, &egg_rules);
+        match run.result {
+            EGraphEquivResult::Equivalent => {
+                self.entries.insert(key, CachedResult::Equivalent);
+                if let Some(generic_key) = generic_key {
+                    self.hints.insert(generic_key, run.applied);
+                }
+            }
+            EGraphEquivResult::Saturated => {
+                self.entries.insert(key, CachedResult::Saturated);
+            }
+            _ => {}
+        }
+        run.result
+    }
+
+    /// The canonical form of both expressions with renamed widths followed by the width
+    /// profile. `None` if the cache is not width-generic or there are too many widths.
+    fn generic_key(&self, lhs: &RecExpr<Arith>, rhs: &RecExpr<Arith>) -> Option<String> {
+        if !self.generic {
+            return None;
+        }
+        let (mut out, widths) = canonical(lhs, rhs, false);
+        if widths.len() > MAX_GENERIC_WIDTHS {
+            return None;
+        }
+        out.push_str(&width_profile(&widths));
+        Some(out)
+    }
+
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// Number of checks that were proven at their concrete widths with the rules of an
+    /// earlier proof, see the width-generic mode.
+    pub fn rechecks(&self) -> usize {
+        self.rechecks
+    }
+
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[derive(Default)]
+struct Names {
+    concrete_widths: bool,
+    symbols: Vec<String>,
+    widths: Vec<WidthInt>,
+}
+
+impl Names {
+    fn index<T: PartialEq>(values: &mut Vec<T>, value: T) -> usize {
+        match values.iter().position(|v| *v == value) {
+            Some(ii) => ii,
+            None => {
+                values.push(value);
+                values.len() - 1
+            }
+        }
+    }
+}
+
+/// Prints both expressions with renamed symbols and, unless `concrete_widths` is set,
+/// renamed widths. Returns the widths in the order of their variables.
+fn canonical(
+    lhs: &RecExpr<Arith>,
+    rhs: &RecExpr<Arith>,
+    concrete_widths: bool,
+) -> (String, Vec<WidthInt>) {
+    let mut names = Names {
+        concrete_widths,
+        ..Default::default()
+    };
+    let mut out = String::new();
+    for expr in [lhs, rhs] {
+        let root = Id::from(expr.as_ref().len() - 1);
+        print(expr.as_ref(), root, &mut names, &mut out);
+        out.push('\n');
+    }
+    (out, names.widths)
+}
+
+fn print(nodes: &[Arith], id: Id, names: &mut Names, out: &mut String) {
+    let node = &nodes[usize::from(id)];
+    match node {
+        Arith::Symbol(name) => {
+            let ii = Names::index(&mut names.symbols, name.clone());
+            write!(out, "s{ii}").unwrap();
+        }
+        Arith::Width(w) if !names.concrete_widths => {
+            let ii = Names::index(&mut names.widths, WidthInt::from(*w));
+            write!(out, "w{ii}").unwrap();
+        }
+        _ if node.is_leaf() => write!(out, "{node}").unwrap(),
+        _ => {
+            write!(out, "({node}").unwrap();
+            for &child in node.children() {
+                out.push(' ');
+                print(nodes, child, names, out);
+            }
+            out.push(')');
+        }
+    }
+}
+
+/// Compares all widths with the terms that appear in rule conditions.
+fn width_profile(widths: &[WidthInt]) -> String {
+    let cmp = |a: WidthInt, b: WidthInt| match a.cmp(&b) {
+        Ordering::Less => '<',
+        Ordering::Equal => '=',
+        Ordering::Greater => '>',
+    };
+    let mut terms: Vec<WidthInt> = vec![1, 2];
+    terms.extend(widths.iter().copied());
+    for &a in widths.iter() {
+        for &b in widths.iter() {
+            terms.push(a.saturating_add(b));
+            terms.push(eval_width_max_plus_1(a, b));
+            terms.push(eval_width_left_shift(a, b));
+        }
+    }
+    let mut out = String::with_capacity(terms.len() * widths.len());
+    for &w in widths.iter() {
+        out.extend(terms.iter().map(|&t| cmp(w, t)));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_rewrites, to_arith};
+    use patronus::expr::{Context, ExprRef};
+
+    /// `a + b` and `b + a` with both operands extended by one bit
+    fn commuted_add(ctx: &mut Context, prefix: &str, width: WidthInt) -> (ExprRef, ExprRef) {
+        let a = ctx.bv_symbol(&format!("{prefix}_a"), width);
+        let b = ctx.bv_symbol(&format!("{prefix}_b"), width);
+        let spec = ctx.build(|c| c.add(c.zero_extend(a, 1), c.zero_extend(b, 1)));
+        let implementation = ctx.build(|c| c.add(c.zero_extend(b, 1), c.zero_extend(a, 1)));
+        (spec, implementation)
+    }
+
+    fn check(
+        cache: &mut ProofCache,
+        ctx: &mut Context,
+        prefix: &str,
+        width: WidthInt,
+    ) -> EGraphEquivResult {
+        let (spec, implementation) = commuted_add(ctx, prefix, width);
+        let (spec, implementation) = (
+            to_arith(ctx, spec).unwrap(),
+            to_arith(ctx, implementation).unwrap(),
+        );
+        cache.check_equivalence(
+            &spec,
+            &implementation,
+            &create_rewrites(),
+            &EGraphConfig::default(),
+        )
+    }
+
+    #[test]
+    fn test_exact_cache() {
+        let mut ctx = Context::default();
+        let mut cache = ProofCache::new(false);
+        assert!(check(&mut cache, &mut ctx, "x", 8).is_equivalent());
+        assert!(check(&mut cache, &mut ctx, "y", 8).is_equivalent());
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+        // different widths need a new proof
+        assert!(check(&mut cache, &mut ctx, "x", 12).is_equivalent());
+        assert_eq!((cache.hits(), cache.misses()), (1, 2));
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_width_generic_cache() {
+        let mut ctx = Context::default();
+        let mut cache = ProofCache::new(true);
+        assert!(check(&mut cache, &mut ctx, "x", 8).is_equivalent());
+        // proven again at 12 bits, with the rules of the 8-bit proof
+        assert!(check(&mut cache, &mut ctx, "y", 12).is_equivalent());
+        assert_eq!((cache.hits(), cache.rechecks(), cache.misses()), (0, 1, 1));
+        assert!(check(&mut cache, &mut ctx, "y", 12).is_equivalent());
+        assert_eq!(cache.hits(), 1);
+        // with a single bit, the widths relate differently to the constants 1 and 2
+        assert!(check(&mut cache, &mut ctx, "z", 1).is_equivalent());
+        assert_eq!(cache.misses(), 2);
+
+        let path = std::env::temp_dir().join("patronus_test_proof_cache.json");
+        cache.save(&path).unwrap();
+        let mut loaded = ProofCache::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.len(), 3);
+        assert!(check(&mut loaded, &mut ctx, "w", 16).is_equivalent());
+        assert_eq!((loaded.rechecks(), loaded.misses()), (1, 0));
+    }
+
+    #[test]
+    fn test_width_generic_hint_is_not_a_result() {
+        let mut ctx = Context::default();
+        let mut cache = ProofCache::new(true);
+        let a = ctx.bv_symbol("a", 8);
+        let b = ctx.bv_symbol("b", 8);
+        let (add, sub) = (ctx.add(a, b), ctx.sub(a, b));
+        let (add, sub) = (to_arith(&ctx, add).unwrap(), to_arith(&ctx, sub).unwrap());
+        // pretend that an earlier proof showed a + b == a - b with all rules
+        let rules = create_rewrites();
+        cache.rules = rules.iter().map(|r| r.name().to_string()).collect();
+        let hint = cache.generic_key(&add, &sub).unwrap();
+        cache.hints.insert(hint, cache.rules.clone());
+        let res = cache.check_equivalence(&add, &sub, &rules, &EGraphConfig::default());
+        assert!(!res.is_equivalent());
+        assert_eq!((cache.rechecks(), cache.misses()), (0, 1));
+    }
+}
@@ -0,0 +1,116 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+/*!
+# Rule Catalog
+
+Renders a list of rewrite rules as a markdown document, such that the rule base can be
+reviewed without reading the Rust source. Every rule is shown as an infix summary that
+leaves out widths and signs, followed by both patterns in the IR syntax, the condition under
+which it applies and all derived right-hand side values.
+
+The `rule-catalog` binary writes the catalog of [`crate::create_rewrites`].
+
+!*/
+
+use crate::{is_bin_op, Arith, ArithRewrite, ConstFn};
+use egg::{ENodeOrVar, Id, Language, PatternAst};
+use patronus::expr::WidthInt;
+use std::fmt::Write;
+
+/// Generates a markdown catalog of `rules`, in order.
+pub fn rule_catalog(rules: &[ArithRewrite]) -> String {
+    let mut out = String::new();
+    writeln!(out, "# Arithmetic Rewrite Rules\n").unwrap();
+    writeln!(out, "{} rules\n", rules.len()).unwrap();
+    for rule in rules.iter() {
+        write_rule(&mut out, rule).expect("writing to a String cannot fail");
+    }
+    out
+}
+
+fn write_rule(out: &mut String, rule: &ArithRewrite) -> std::fmt::Result {
+    let (lhs, rhs) = rule.patterns();
+    writeln!(out, "## {}\n", rule.name())?;
+    writeln!(out, "`{}` => `{}`\n", infix(lhs), infix(rhs))?;
+    writeln!(out, "```")?;
+    writeln!(out, "{lhs}")?;
+    writeln!(out, "=> {rhs}")?;
+    writeln!(out, "```\n")?;
+    match rule.condition() {
+        Some(cond) => writeln!(out, "- applies if: `{cond}`")?,
+        None => writeln!(out, "- applies unconditionally")?,
+    }
+    for (value, width) in rule.width_values() {
+        writeln!(out, "- `{value}` is the value of the width `{width}`")?;
+    }
+    for (value, f, operand) in rule.const_values() {
+        let f = match f {
+            ConstFn::Log2 => "log2",
+            ConstFn::Pow2 => "2^",
+        };
+        writeln!(
+            out,
+            "- `{value}` is `{f}({operand})`, `{operand}` needs to be a constant"
+        )?;
+    }
+    writeln!(out)
+}
+
+/// Summarizes a pattern in infix notation, leaving out all widths and signs of operands.
+pub fn infix(pattern: &PatternAst<Arith>) -> String {
+    let root = Id::from(pattern.as_ref().len() - 1);
+    infix_node(pattern, root)
+}
+
+fn infix_node(pattern: &PatternAst<Arith>, id: Id) -> String {
+    let node = match &pattern[id] {
+        ENodeOrVar::Var(v) => return v.to_string(),
+        ENodeOrVar::ENode(node) => node,
+    };
+    let c = |ii: usize| infix_node(pattern, node.children()[ii]);
+    match node {
+        n if is_bin_op(n) => format!("({} {n} {})", c(3), c(6)),
+        Arith::Negate(_) => format!("-{}", c(3)),
+        Arith::Concat(_) => format!("concat({}, {})", c(2), c(4)),
+        Arith::Repeat(_) => format!("repeat({}, {})", c(1), c(3)),
+        Arith::RotateLeft(_) | Arith::RotateRight(_) => format!("{node}({}, {})", c(1), c(3)),
+        Arith::RotateLeftConst(_) => format!("rol({}, {})", c(2), c(1)),
+        Arith::SaturatingAdd(_)
+        | Arith::SaturatingSub(_)
+        | Arith::Greater(_)
+        | Arith::GreaterEqual(_) => {
+            format!("({} {node} {})", c(4), c(7))
+        }
+        Arith::WidthMaxPlus1(_) | Arith::WidthLeftShift(_) => {
+            format!("{node}({}, {})", c(0), c(1))
+        }
+        Arith::WidthAdd(_) => format!("({} + {})", c(0), c(1)),
+        Arith::WidthMul(_) => format!("({} * {})", c(0), c(1)),
+        Arith::Width(w) => WidthInt::from(*w).to_string(),
+        Arith::Sign(_) | Arith::Const(_) | Arith::Symbol(_) => node.to_string(),
+        other => unreachable!("`{other}` is handled as a binary operation"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_rewrites;
+
+    #[test]
+    fn test_rule_catalog() {
+        let rules = create_rewrites();
+        let catalog = rule_catalog(&rules);
+        assert_eq!(catalog.matches("\n## ").count(), rules.len());
+        assert!(catalog.contains("## commute-add\n\n`(?a + ?b)` => `(?b + ?a)`\n"));
+        assert!(catalog.contains(
+            "- applies if: `?wbc >= ?wo || (?wbc >= max+1(?wb, ?wc) && ?sb == ?sbc && ?sc == ?sbc)`"
+        ));
+
+        let assoc = rules.iter().find(|r| r.name() == "assoc-add-left").unwrap();
+        let (lhs, rhs) = assoc.patterns();
+        assert_eq!(infix(lhs), "((?a + ?b) + ?c)");
+        assert_eq!(infix(rhs), "(?a + (?b + ?c))");
+    }
+}
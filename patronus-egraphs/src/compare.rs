@@ -0,0 +1,99 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+/*!
+# Comparing Rule Sets
+
+In order to evaluate candidate rules, [`compare_rule_sets`] saturates the same expressions
+once with each of two rule sets and compares the resulting partitions into e-classes.
+Every pair of expressions that is merged by one set, but not by the other, is an equality
+that only one of the sets can prove.
+
+!*/
+
+use crate::{Arith, ArithRewrite, Equivalence, PartitionedClasses};
+use egg::RecExpr;
+use patronus::config::EGraphConfig;
+
+pub struct RuleSetComparison {
+    pub a: PartitionedClasses,
+    pub b: PartitionedClasses,
+    /// pairs of expression indices `(i, j)` with `i < j` that only rule set `a` proves equal
+    pub only_a: Vec<(usize, usize)>,
+    /// pairs of expression indices `(i, j)` with `i < j` that only rule set `b` proves equal
+    pub only_b: Vec<(usize, usize)>,
+}
+
+impl RuleSetComparison {
+    /// Both rule sets prove exactly the same equalities.
+    pub fn is_same(&self) -> bool {
+        self.only_a.is_empty() && self.only_b.is_empty()
+    }
+}
+
+/// Saturates `exprs` with rule set `a` and with rule set `b` and reports which equalities
+/// only one of them establishes.
+pub fn compare_rule_sets(
+    exprs: &[RecExpr<Arith>],
+    a: &[ArithRewrite],
+    b: &[ArithRewrite],
+    config: &EGraphConfig,
+) -> RuleSetComparison {
+    let a = Equivalence::new(a, config).check(exprs);
+    let b = Equivalence::new(b, config).check(exprs);
+    let only_a = only_in(&a, &b);
+    let only_b = only_in(&b, &a);
+    RuleSetComparison {
+        a,
+        b,
+        only_a,
+        only_b,
+    }
+}
+
+fn only_in(proving: &PartitionedClasses, other: &PartitionedClasses) -> Vec<(usize, usize)> {
+    let mut out = vec![];
+    for class in proving.classes() {
+        for (ii, &i) in class.iter().enumerate() {
+            for &j in class[ii + 1..].iter() {
+                if !other.same_class(i, j) {
+                    out.push((i, j));
+                }
+            }
+        }
+    }
+    out.sort_unstable();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_rewrites, to_arith};
+    use patronus::expr::Context;
+
+    #[test]
+    fn test_compare_commutativity() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("a", 8);
+        let b = ctx.bv_symbol("b", 8);
+        let exprs: Vec<_> = [ctx.add(a, b), ctx.add(b, a), ctx.mul(a, b), ctx.mul(b, a)]
+            .into_iter()
+            .map(|e| to_arith(&ctx, e).unwrap())
+            .collect();
+        let rules = create_rewrites();
+        let only = |name: &str| -> Vec<ArithRewrite> {
+            rules.iter().filter(|r| r.name() == name).cloned().collect()
+        };
+        let config = EGraphConfig::default();
+
+        let res = compare_rule_sets(&exprs, &only("commute-add"), &only("commute-mul"), &config);
+        assert_eq!(res.only_a, [(0, 1)]);
+        assert_eq!(res.only_b, [(2, 3)]);
+        assert!(!res.is_same());
+
+        let res = compare_rule_sets(&exprs, &only("commute-add"), &only("commute-add"), &config);
+        assert!(res.is_same());
+        assert_eq!(res.a.classes(), res.b.classes());
+    }
+}
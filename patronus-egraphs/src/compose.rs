@@ -0,0 +1,182 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+/*!
+# Compositional Equivalence Proofs
+
+Saturating a complete datapath in a single e-graph often does not terminate, even though every
+building block can be proven equivalent to its counterpart in isolation.
+[`check_equivalence_compositional`] first proves a list of `(spec, implementation)` blocks,
+in order. Every block that is proven equivalent is replaced by a fresh symbol in all later
+blocks and in the top-level expressions, such that the final e-graph only has to reason about
+how the blocks are connected.
+
+Replacing both sides of a proven equivalence by the same unconstrained symbol is sound: if the
+abstracted expressions are equivalent for every value of the symbol, they are in particular
+equivalent for the value computed by the block. Blocks that cannot be proven are left in place.
+
+!*/
+
+use crate::{check_equivalence, to_arith, ArithRewrite, EGraphEquivResult, EGraphError};
+use patronus::config::EGraphConfig;
+use patronus::expr::{simple_transform_expr, Context, ExprRef, TypeCheck};
+use rustc_hash::FxHashMap;
+
+/// Outcome of proving a single sub-block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockResult {
+    pub spec: ExprRef,
+    pub implementation: ExprRef,
+    pub result: EGraphEquivResult,
+    /// fresh symbol that replaces the block, if it was proven equivalent
+    pub symbol: Option<ExprRef>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompositionalResult {
+    /// one entry per block, in order
+    pub blocks: Vec<BlockResult>,
+    /// specification with all proven blocks replaced
+    pub spec: ExprRef,
+    /// implementation with all proven blocks replaced
+    pub implementation: ExprRef,
+    /// result of the final check of the abstracted expressions
+    pub result: EGraphEquivResult,
+}
+
+impl CompositionalResult {
+    pub fn num_proven_blocks(&self) -> usize {
+        self.blocks.iter().filter(|b| b.symbol.is_some()).count()
+    }
+}
+
+/// Proves that `spec` and `implementation` are equivalent by proving each of the `blocks`
+/// first. Later blocks may contain earlier ones.
+pub fn check_equivalence_compositional(
+    ctx: &mut Context,
+    spec: ExprRef,
+    implementation: ExprRef,
+    blocks: &[(ExprRef, ExprRef)],
+    rules: &[ArithRewrite],
+    config: &EGraphConfig,
+) -> Result<CompositionalResult, EGraphError> {
+    let mut proven: FxHashMap<ExprRef, ExprRef> = FxHashMap::default();
+    let mut results = Vec::with_capacity(blocks.len());
+    for (ii, &(block_spec, block_impl)) in blocks.iter().enumerate() {
+        let (lhs, rhs) = (
+            substitute(ctx, &proven, block_spec),
+            substitute(ctx, &proven, block_impl),
+        );
+        let result = check_equivalence(&to_arith(ctx, lhs)?, &to_arith(ctx, rhs)?, rules, config);
+        let symbol = if result.is_equivalent() {
+            let width = block_spec
+                .get_bv_type(ctx)
+                .expect("blocks need to be bit-vector expressions");
+            // the design may already contain a signal with the same name
+            let name = ctx.fresh_name(&format!("__block{ii}"));
+            let symbol = ctx.bv_symbol(&name, width);
+            proven.insert(block_spec, symbol);
+            proven.insert(block_impl, symbol);
+            Some(symbol)
+        } else {
+            None
+        };
+        results.push(BlockResult {
+            spec: block_spec,
+            implementation: block_impl,
+            result,
+            symbol,
+        });
+    }
+
+    let spec = substitute(ctx, &proven, spec);
+    let implementation = substitute(ctx, &proven, implementation);
+    let result = if spec == implementation {
+        EGraphEquivResult::Equivalent
+    } else {
+        check_equivalence(
+            &to_arith(ctx, spec)?,
+            &to_arith(ctx, implementation)?,
+            rules,
+            config,
+        )
+    };
+    Ok(CompositionalResult {
+        blocks: results,
+        spec,
+        implementation,
+        result,
+    })
+}
+
+fn substitute(ctx: &mut Context, proven: &FxHashMap<ExprRef, ExprRef>, e: ExprRef) -> ExprRef {
+    if proven.is_empty() {
+        e
+    } else {
+        simple_transform_expr(ctx, e, |_, e, _| proven.get(&e).copied())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arithmetic::verification_fig_1;
+    use crate::create_rewrites;
+
+    #[test]
+    fn test_compositional_proof() {
+        let mut ctx = Context::default();
+        let (block_spec, block_impl) = verification_fig_1(&mut ctx);
+        let c = ctx.bv_symbol("C", 63);
+        let spec = ctx.add(block_spec, c);
+        let implementation = ctx.add(c, block_impl);
+        let a = ctx.bv_symbol("A", 16);
+        let b = ctx.bv_symbol("B", 16);
+        let rules = create_rewrites();
+        let config = EGraphConfig::default();
+
+        let res = check_equivalence_compositional(
+            &mut ctx,
+            spec,
+            implementation,
+            &[(a, b), (block_spec, block_impl)],
+            &rules,
+            &config,
+        )
+        .unwrap();
+        assert_eq!(res.blocks.len(), 2);
+        assert_eq!(res.blocks[0].symbol, None);
+        assert!(!res.blocks[0].result.is_equivalent());
+        assert_eq!(res.num_proven_blocks(), 1);
+        let symbol = res.blocks[1].symbol.unwrap();
+        assert_eq!(ctx.get_symbol_name(symbol), Some("__block1"));
+        // only the connection between the blocks remains
+        assert_eq!(res.spec, ctx.add(symbol, c));
+        assert_eq!(res.implementation, ctx.add(c, symbol));
+        assert!(res.result.is_equivalent());
+    }
+
+    #[test]
+    fn test_block_symbol_does_not_alias_design() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("a", 8);
+        let b = ctx.bv_symbol("b", 8);
+        let existing = ctx.bv_symbol("__block0", 8);
+        let (block_spec, block_impl) = (ctx.add(a, b), ctx.add(b, a));
+        // a + b is not the same as the unrelated signal `__block0`
+        let res = check_equivalence_compositional(
+            &mut ctx,
+            block_spec,
+            existing,
+            &[(block_spec, block_impl)],
+            &create_rewrites(),
+            &EGraphConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(res.num_proven_blocks(), 1);
+        let symbol = res.blocks[0].symbol.unwrap();
+        assert_ne!(symbol, existing);
+        assert_eq!(ctx.get_symbol_name(symbol), Some("__block0_0"));
+        assert!(!res.result.is_equivalent());
+    }
+}
@@ -0,0 +1,569 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+/*!
+# Rewrite Conditions
+
+Conditions are constraints over the width and sign variables of a rewrite rule.
+In contrast to an opaque closure, they can be printed, evaluated, exported to SMT
+in order to verify a rule, and composed when rules are chained.
+
+Conditions are written in a small language:
+`(?sb == 0 && ?wb > 1) || ?wo >= max+1(?wa, ?wb) + 1`.
+Width expressions support constants, variables, `+`, `max+1(a, b)` and `wlsh(a, b)`,
+which correspond to the width operations of the arithmetic IR.
+Signs are treated as widths with `unsign = 0` and `sign = 1`.
+!*/
+
+use crate::arithmetic::{eval_width_left_shift, eval_width_max_plus_1};
+use egg::Var;
+use patronus::expr::{Context, ExprRef, WidthInt};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// An expression over width variables.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WidthExpr {
+    Var(Var),
+    Const(WidthInt),
+    Add(Box<WidthExpr>, Box<WidthExpr>),
+    /// `max(a, b) + 1`, the width needed for an addition without overflow
+    MaxPlus1(Box<WidthExpr>, Box<WidthExpr>),
+    /// `a + 2^b - 1`, the width needed for a left shift without overflow
+    LeftShift(Box<WidthExpr>, Box<WidthExpr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// A boolean constraint over width variables.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WidthConstraint {
+    Cmp(WidthExpr, CmpOp, WidthExpr),
+    And(Vec<WidthConstraint>),
+    Or(Vec<WidthConstraint>),
+}
+
+impl WidthExpr {
+    fn eval(&self, lookup: &impl Fn(Var) -> Option<WidthInt>) -> Option<WidthInt> {
+        let value = match self {
+            WidthExpr::Var(v) => lookup(*v)?,
+            WidthExpr::Const(c) => *c,
+            WidthExpr::Add(a, b) => a.eval(lookup)?.saturating_add(b.eval(lookup)?),
+            WidthExpr::MaxPlus1(a, b) => eval_width_max_plus_1(a.eval(lookup)?, b.eval(lookup)?),
+            WidthExpr::LeftShift(a, b) => eval_width_left_shift(a.eval(lookup)?, b.eval(lookup)?),
+        };
+        Some(value)
+    }
+
+    fn collect_vars(&self, out: &mut Vec<Var>) {
+        match self {
+            WidthExpr::Var(v) => {
+                if !out.contains(v) {
+                    out.push(*v);
+                }
+            }
+            WidthExpr::Const(_) => {}
+            WidthExpr::Add(a, b) | WidthExpr::MaxPlus1(a, b) | WidthExpr::LeftShift(a, b) => {
+                a.collect_vars(out);
+                b.collect_vars(out);
+            }
+        }
+    }
+
+    fn substitute(&self, f: &impl Fn(Var) -> WidthExpr) -> Self {
+        match self {
+            WidthExpr::Var(v) => f(*v),
+            WidthExpr::Const(c) => WidthExpr::Const(*c),
+            WidthExpr::Add(a, b) => WidthExpr::Add(a.substitute(f).into(), b.substitute(f).into()),
+            WidthExpr::MaxPlus1(a, b) => {
+                WidthExpr::MaxPlus1(a.substitute(f).into(), b.substitute(f).into())
+            }
+            WidthExpr::LeftShift(a, b) => {
+                WidthExpr::LeftShift(a.substitute(f).into(), b.substitute(f).into())
+            }
+        }
+    }
+
+    fn to_smt(
+        &self,
+        ctx: &mut Context,
+        width: WidthInt,
+        symbol: &mut impl FnMut(&mut Context, Var) -> ExprRef,
+    ) -> ExprRef {
+        match self {
+            WidthExpr::Var(v) => symbol(ctx, *v),
+            WidthExpr::Const(c) => ctx.bit_vec_val(*c, width),
+            WidthExpr::Add(a, b) => {
+                let (a, b) = (a.to_smt(ctx, width, symbol), b.to_smt(ctx, width, symbol));
+                ctx.add(a, b)
+            }
+            WidthExpr::MaxPlus1(a, b) => {
+                let (a, b) = (a.to_smt(ctx, width, symbol), b.to_smt(ctx, width, symbol));
+                ctx.build(|c| c.add(c.ite(c.greater_or_equal(a, b), a, b), c.one(width)))
+            }
+            WidthExpr::LeftShift(a, b) => {
+                let (a, b) = (a.to_smt(ctx, width, symbol), b.to_smt(ctx, width, symbol));
+                ctx.build(|c| {
+                    let max_shift = c.sub(c.shift_left(c.one(width), b), c.one(width));
+                    c.add(a, max_shift)
+                })
+            }
+        }
+    }
+}
+
+impl WidthConstraint {
+    /// Variables in the order of their first appearance.
+    pub fn vars(&self) -> Vec<Var> {
+        let mut out = vec![];
+        self.collect_vars(&mut out);
+        out
+    }
+
+    fn collect_vars(&self, out: &mut Vec<Var>) {
+        match self {
+            WidthConstraint::Cmp(a, _, b) => {
+                a.collect_vars(out);
+                b.collect_vars(out);
+            }
+            WidthConstraint::And(cs) | WidthConstraint::Or(cs) => {
+                cs.iter().for_each(|c| c.collect_vars(out));
+            }
+        }
+    }
+
+    /// Evaluates the constraint. Returns `None` if `lookup` does not know a variable.
+    pub fn eval(&self, lookup: impl Fn(Var) -> Option<WidthInt>) -> Option<bool> {
+        self.eval_internal(&lookup)
+    }
+
+    fn eval_internal(&self, lookup: &impl Fn(Var) -> Option<WidthInt>) -> Option<bool> {
+        match self {
+            WidthConstraint::Cmp(a, op, b) => {
+                let (a, b) = (a.eval(lookup)?, b.eval(lookup)?);
+                Some(match op {
+                    CmpOp::Eq => a == b,
+                    CmpOp::Ne => a != b,
+                    CmpOp::Lt => a < b,
+                    CmpOp::Le => a <= b,
+                    CmpOp::Gt => a > b,
+                    CmpOp::Ge => a >= b,
+                })
+            }
+            WidthConstraint::And(cs) => {
+                for c in cs.iter() {
+                    if !c.eval_internal(lookup)? {
+                        return Some(false);
+                    }
+                }
+                Some(true)
+            }
+            WidthConstraint::Or(cs) => {
+                for c in cs.iter() {
+                    if c.eval_internal(lookup)? {
+                        return Some(true);
+                    }
+                }
+                Some(false)
+            }
+        }
+    }
+
+    /// Conjunction of two constraints, e.g., in order to chain two rules.
+    pub fn and(self, other: WidthConstraint) -> Self {
+        let mut cs = match self {
+            WidthConstraint::And(cs) => cs,
+            other => vec![other],
+        };
+        match other {
+            WidthConstraint::And(mut others) => cs.append(&mut others),
+            other => cs.push(other),
+        }
+        WidthConstraint::And(cs)
+    }
+
+    /// Replaces every variable. When chaining rules, this maps the variables of the second
+    /// rule to the width expressions that the first rule produces.
+    pub fn substitute(&self, f: impl Fn(Var) -> WidthExpr) -> Self {
+        self.substitute_internal(&f)
+    }
+
+    fn substitute_internal(&self, f: &impl Fn(Var) -> WidthExpr) -> Self {
+        match self {
+            WidthConstraint::Cmp(a, op, b) => {
+                WidthConstraint::Cmp(a.substitute(f), *op, b.substitute(f))
+            }
+            WidthConstraint::And(cs) => {
+                WidthConstraint::And(cs.iter().map(|c| c.substitute_internal(f)).collect())
+            }
+            WidthConstraint::Or(cs) => {
+                WidthConstraint::Or(cs.iter().map(|c| c.substitute_internal(f)).collect())
+            }
+        }
+    }
+
+    /// Encodes the constraint as a boolean expression over `width`-bit values.
+    /// `symbol` provides the expression for each variable. The encoding uses modular
+    /// arithmetic, thus `width` needs to be large enough to avoid overflows.
+    pub fn to_smt(
+        &self,
+        ctx: &mut Context,
+        width: WidthInt,
+        mut symbol: impl FnMut(&mut Context, Var) -> ExprRef,
+    ) -> ExprRef {
+        self.to_smt_internal(ctx, width, &mut symbol)
+    }
+
+    fn to_smt_internal(
+        &self,
+        ctx: &mut Context,
+        width: WidthInt,
+        symbol: &mut impl FnMut(&mut Context, Var) -> ExprRef,
+    ) -> ExprRef {
+        match self {
+            WidthConstraint::Cmp(a, op, b) => {
+                let (a, b) = (a.to_smt(ctx, width, symbol), b.to_smt(ctx, width, symbol));
+                match op {
+                    CmpOp::Eq => ctx.equal(a, b),
+                    CmpOp::Ne => ctx.build(|c| c.not(c.equal(a, b))),
+                    CmpOp::Lt => ctx.greater(b, a),
+                    CmpOp::Le => ctx.greater_or_equal(b, a),
+                    CmpOp::Gt => ctx.greater(a, b),
+                    CmpOp::Ge => ctx.greater_or_equal(a, b),
+                }
+            }
+            WidthConstraint::And(cs) => {
+                let cs: Vec<_> = cs
+                    .iter()
+                    .map(|c| c.to_smt_internal(ctx, width, symbol))
+                    .collect();
+                cs.into_iter()
+                    .reduce(|a, b| ctx.and(a, b))
+                    .unwrap_or_else(|| ctx.get_true())
+            }
+            WidthConstraint::Or(cs) => {
+                let cs: Vec<_> = cs
+                    .iter()
+                    .map(|c| c.to_smt_internal(ctx, width, symbol))
+                    .collect();
+                cs.into_iter()
+                    .reduce(|a, b| ctx.or(a, b))
+                    .unwrap_or_else(|| ctx.get_false())
+            }
+        }
+    }
+}
+
+impl Display for WidthExpr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WidthExpr::Var(v) => write!(f, "{v}"),
+            WidthExpr::Const(c) => write!(f, "{c}"),
+            WidthExpr::Add(a, b) => write!(f, "{a} + {b}"),
+            WidthExpr::MaxPlus1(a, b) => write!(f, "max+1({a}, {b})"),
+            WidthExpr::LeftShift(a, b) => write!(f, "wlsh({a}, {b})"),
+        }
+    }
+}
+
+impl Display for CmpOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            CmpOp::Eq => "==",
+            CmpOp::Ne => "!=",
+            CmpOp::Lt => "<",
+            CmpOp::Le => "<=",
+            CmpOp::Gt => ">",
+            CmpOp::Ge => ">=",
+        };
+        write!(f, "{s}")
+    }
+}
+
+impl Display for WidthConstraint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WidthConstraint::Cmp(a, op, b) => write!(f, "{a} {op} {b}"),
+            WidthConstraint::And(cs) => write_joined(f, cs, " && "),
+            WidthConstraint::Or(cs) => write_joined(f, cs, " || "),
+        }
+    }
+}
+
+fn write_joined(f: &mut Formatter<'_>, cs: &[WidthConstraint], sep: &str) -> std::fmt::Result {
+    for (ii, c) in cs.iter().enumerate() {
+        if ii > 0 {
+            write!(f, "{sep}")?;
+        }
+        if matches!(c, WidthConstraint::Cmp(..)) {
+            write!(f, "{c}")?;
+        } else {
+            write!(f, "({c})")?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("failed to parse condition at position {pos}: {msg}")]
+pub struct ConditionParseError {
+    pub pos: usize,
+    pub msg: String,
+}
+
+impl FromStr for WidthConstraint {
+    type Err = ConditionParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = ConditionParser { input: s, pos: 0 };
+        let c = parser.parse_or()?;
+        parser.skip_whitespace();
+        if parser.pos < s.len() {
+            return Err(parser.error("unexpected trailing input"));
+        }
+        Ok(c)
+    }
+}
+
+/// Recursive descent parser for conditions.
+struct ConditionParser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> ConditionParser<'a> {
+    fn error(&self, msg: &str) -> ConditionParseError {
+        ConditionParseError {
+            pos: self.pos,
+            msg: msg.to_string(),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.input[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Consumes `token` if it comes next.
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        if self.input[self.pos..].starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), ConditionParseError> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{token}`")))
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<WidthConstraint, ConditionParseError> {
+        let mut cs = vec![self.parse_and()?];
+        while self.eat("||") {
+            cs.push(self.parse_and()?);
+        }
+        Ok(if cs.len() == 1 {
+            cs.pop().unwrap()
+        } else {
+            WidthConstraint::Or(cs)
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<WidthConstraint, ConditionParseError> {
+        let mut cs = vec![self.parse_atom()?];
+        while self.eat("&&") {
+            cs.push(self.parse_atom()?);
+        }
+        Ok(if cs.len() == 1 {
+            cs.pop().unwrap()
+        } else {
+            WidthConstraint::And(cs)
+        })
+    }
+
+    fn parse_atom(&mut self) -> Result<WidthConstraint, ConditionParseError> {
+        if self.eat("(") {
+            let c = self.parse_or()?;
+            self.expect(")")?;
+            return Ok(c);
+        }
+        let a = self.parse_sum()?;
+        // longer operators need to be tried first
+        let op = [
+            ("==", CmpOp::Eq),
+            ("!=", CmpOp::Ne),
+            ("<=", CmpOp::Le),
+            (">=", CmpOp::Ge),
+            ("<", CmpOp::Lt),
+            (">", CmpOp::Gt),
+        ]
+        .into_iter()
+        .find(|(token, _)| self.eat(token))
+        .map(|(_, op)| op)
+        .ok_or_else(|| self.error("expected a comparison"))?;
+        let b = self.parse_sum()?;
+        Ok(WidthConstraint::Cmp(a, op, b))
+    }
+
+    fn parse_sum(&mut self) -> Result<WidthExpr, ConditionParseError> {
+        let mut e = self.parse_term()?;
+        while self.eat("+") {
+            e = WidthExpr::Add(e.into(), self.parse_term()?.into());
+        }
+        Ok(e)
+    }
+
+    fn parse_term(&mut self) -> Result<WidthExpr, ConditionParseError> {
+        if self.eat("max+1(") {
+            let (a, b) = self.parse_args()?;
+            return Ok(WidthExpr::MaxPlus1(a.into(), b.into()));
+        }
+        if self.eat("wlsh(") {
+            let (a, b) = self.parse_args()?;
+            return Ok(WidthExpr::LeftShift(a.into(), b.into()));
+        }
+        self.skip_whitespace();
+        let rest = &self.input[self.pos..];
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '?'))
+            .unwrap_or(rest.len());
+        let token = &rest[..len];
+        let e = if token.starts_with('?') {
+            WidthExpr::Var(token.parse().map_err(|_| self.error("invalid variable"))?)
+        } else {
+            WidthExpr::Const(
+                token
+                    .parse()
+                    .map_err(|_| self.error("expected a variable or a constant"))?,
+            )
+        };
+        self.pos += len;
+        Ok(e)
+    }
+
+    fn parse_args(&mut self) -> Result<(WidthExpr, WidthExpr), ConditionParseError> {
+        let a = self.parse_sum()?;
+        self.expect(",")?;
+        let b = self.parse_sum()?;
+        self.expect(")")?;
+        Ok((a, b))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use baa::BitVecOps;
+    use patronus::expr::{eval_bv_expr, SerializableIrNode};
+
+    fn lookup(assignment: &[(&str, WidthInt)]) -> impl Fn(Var) -> Option<WidthInt> + '_ {
+        move |v| {
+            assignment
+                .iter()
+                .find(|(n, _)| n.parse::<Var>().unwrap() == v)
+                .map(|(_, w)| *w)
+        }
+    }
+
+    #[test]
+    fn test_parse_and_print() {
+        let src = "(?sb == 0 && ?wb > 1) || (?sb == 1 && ?wb > 2) || ?wo <= ?wb";
+        let c: WidthConstraint = src.parse().unwrap();
+        assert_eq!(c.to_string(), src);
+        assert_eq!(c.to_string().parse::<WidthConstraint>().unwrap(), c);
+        let vars: Vec<String> = c.vars().iter().map(|v| v.to_string()).collect();
+        assert_eq!(vars, ["?sb", "?wb", "?wo"]);
+
+        let c: WidthConstraint = "?wo >= wlsh(?wa + 1, max+1(?wb, 3))".parse().unwrap();
+        assert_eq!(c.to_string(), "?wo >= wlsh(?wa + 1, max+1(?wb, 3))");
+
+        assert!("?wo >=".parse::<WidthConstraint>().is_err());
+        assert!("?wo > 1 &&".parse::<WidthConstraint>().is_err());
+        assert!("(?wo > 1".parse::<WidthConstraint>().is_err());
+    }
+
+    #[test]
+    fn test_eval() {
+        let c: WidthConstraint = "?wbc >= max+1(?wb, ?wc)".parse().unwrap();
+        assert_eq!(
+            c.eval(lookup(&[("?wbc", 5), ("?wb", 4), ("?wc", 2)])),
+            Some(true)
+        );
+        assert_eq!(
+            c.eval(lookup(&[("?wbc", 4), ("?wb", 4), ("?wc", 2)])),
+            Some(false)
+        );
+        assert_eq!(c.eval(lookup(&[("?wbc", 4)])), None);
+        let c: WidthConstraint = "?wo >= wlsh(?wa, ?wb)".parse().unwrap();
+        assert_eq!(
+            c.eval(lookup(&[("?wo", 11), ("?wa", 4), ("?wb", 3)])),
+            Some(true)
+        );
+        assert_eq!(
+            c.eval(lookup(&[("?wo", 10), ("?wa", 4), ("?wb", 3)])),
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn test_compose() {
+        let first: WidthConstraint = "?wo >= ?wa + ?wb".parse().unwrap();
+        let second: WidthConstraint = "?wo > 2".parse().unwrap();
+        // the output of the second rule is the `?wa` of the first one
+        let second = second.substitute(|v| {
+            if v.to_string() == "?wo" {
+                WidthExpr::Var("?wa".parse().unwrap())
+            } else {
+                WidthExpr::Var(v)
+            }
+        });
+        let both = first.and(second);
+        assert_eq!(both.to_string(), "?wo >= ?wa + ?wb && ?wa > 2");
+        assert_eq!(
+            both.eval(lookup(&[("?wo", 8), ("?wa", 3), ("?wb", 5)])),
+            Some(true)
+        );
+        assert_eq!(
+            both.eval(lookup(&[("?wo", 8), ("?wa", 2), ("?wb", 5)])),
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn test_to_smt() {
+        let mut ctx = Context::default();
+        let c: WidthConstraint = "?wo >= max+1(?wa, ?wb)".parse().unwrap();
+        let e = c.to_smt(&mut ctx, 8, |ctx, v| {
+            ctx.bv_symbol(v.to_string().trim_start_matches('?'), 8)
+        });
+        let wo = ctx.bv_symbol("wo", 8);
+        let wa = ctx.bv_symbol("wa", 8);
+        let wb = ctx.bv_symbol("wb", 8);
+        let mut values = vec![
+            (wo, baa::BitVecValue::from_u64(5, 8)),
+            (wa, baa::BitVecValue::from_u64(4, 8)),
+            (wb, baa::BitVecValue::from_u64(2, 8)),
+        ];
+        assert!(
+            eval_bv_expr(&ctx, values.as_slice(), e).is_true(),
+            "{}",
+            e.serialize_to_str(&ctx)
+        );
+        values[0].1 = baa::BitVecValue::from_u64(4, 8);
+        assert!(eval_bv_expr(&ctx, values.as_slice(), e).is_false());
+    }
+}
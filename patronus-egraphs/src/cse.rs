@@ -0,0 +1,431 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+/*!
+# System Level Optimization
+
+Adds the arithmetic parts of all expressions in a transition system to a single e-graph.
+After saturation, one representation is chosen for every e-class across all roots,
+which lets different next state functions share common sub-expressions.
+The extraction is aware of sharing: a node that is slightly larger on its own is preferred,
+if its children are already needed elsewhere.
+
+!*/
+
+use crate::arithmetic::ArithConverter;
+use crate::{configure_runner, Arith, ArithRewrite, EGraph, Rewrite};
+use egg::{AstDepth, AstSize, CostFunction, Extractor, Id, Language, RecExpr};
+use patronus::config::{CostModel, EGraphConfig};
+use patronus::expr::*;
+use patronus::system::TransitionSystem;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+/// Maximum number of improvement passes of the sharing-aware extraction.
+const MAX_EXTRACTION_PASSES: usize = 8;
+
+/// Result of [`optimize_system`].
+pub struct SystemOptimization {
+    pub sys: TransitionSystem,
+    /// number of arithmetic sub-expressions that were added to the e-graph
+    pub regions: usize,
+    /// number of e-classes needed by all extracted expressions
+    pub extracted_classes: usize,
+}
+
+/// Optimizes all arithmetic sub-expressions of `sys` in a shared e-graph. Only maximal
+/// sub-expressions that [`crate::to_arith`] can convert completely are considered. Everything
+/// else remains untouched.
+pub fn optimize_system(
+    ctx: &mut Context,
+    sys: &TransitionSystem,
+    rules: &[ArithRewrite],
+    config: &EGraphConfig,
+) -> SystemOptimization {
+    let roots = sys.get_all_exprs();
+    let mut converter = ArithConverter::new(ctx, WidthLimits::default());
+    let regions = find_regions(ctx, &roots, &mut converter);
+    let mut out = sys.clone();
+    if regions.is_empty() {
+        return SystemOptimization {
+            sys: out,
+            regions: 0,
+            extracted_classes: 0,
+        };
+    }
+
+    // all regions share one expression, common sub-expressions are thus only converted once
+    let expr = converter.into_expr();
+    let mut egraph = EGraph::default();
+    let mut ids = Vec::with_capacity(expr.as_ref().len());
+    for node in expr.as_ref() {
+        let node = node.clone().map_children(|c| ids[usize::from(c)]);
+        ids.push(egraph.add(node));
+    }
+    let region_ids: Vec<Id> = regions
+        .iter()
+        .map(|&(_, id)| ids[usize::from(id)])
+        .collect();
+
+    let egg_rules: Vec<Rewrite> = rules.iter().flat_map(|r| r.to_egg()).collect();
+    let runner = configure_runner(egg::Runner::default().with_egraph(egraph), config);
+    let runner = runner.run(&egg_rules);
+    let (extracted, extracted_classes) =
+        extract_shared(&runner.egraph, &region_ids, config.cost_model);
+
+    let mut replacements = FxHashMap::default();
+    for (&(region, _), expr) in regions.iter().zip(extracted.iter()) {
+        replacements.insert(region, crate::from_arith(ctx, expr));
+    }
+    let mut new_roots = FxHashMap::default();
+    for &root in roots.iter() {
+        let new = simple_transform_expr(ctx, root, |_, e, _| replacements.get(&e).copied());
+        new_roots.insert(root, new);
+    }
+    out.update_expressions(|e| new_roots.get(&e).copied());
+
+    SystemOptimization {
+        sys: out,
+        regions: regions.len(),
+        extracted_classes,
+    }
+}
+
+/// Finds the largest sub-expressions that can be converted into the arithmetic IR and returns
+/// them together with their root in the shared expression of `converter`.
+/// Symbols and literals on their own are not worth optimizing.
+fn find_regions(
+    ctx: &Context,
+    roots: &[ExprRef],
+    converter: &mut ArithConverter,
+) -> Vec<(ExprRef, Id)> {
+    let mut visited = FxHashSet::default();
+    let mut regions = vec![];
+    let mut todo: Vec<ExprRef> = roots.iter().rev().copied().collect();
+    while let Some(e) = todo.pop() {
+        if !visited.insert(e) || e.get_bv_type(ctx).is_none() {
+            continue;
+        }
+        let expr = &ctx[e];
+        if matches!(expr, Expr::BVSymbol { .. } | Expr::BVLiteral(_)) {
+            continue;
+        }
+        if let Some(id) = converter.convert(e) {
+            regions.push((e, id));
+        } else {
+            expr.for_each_child(|&c| todo.push(c));
+        }
+    }
+    regions
+}
+
+/// Chooses one node per e-class, starting from the best tree for every class according to
+/// `cost_model` and then greedily switching nodes whenever that reduces the total number of
+/// e-classes needed. Returns one expression per root and the number of e-classes used.
+fn extract_shared(
+    egraph: &EGraph,
+    roots: &[Id],
+    cost_model: CostModel,
+) -> (Vec<RecExpr<Arith>>, usize) {
+    let roots: Vec<Id> = roots.iter().map(|&r| egraph.find(r)).collect();
+    let mut shared = SharedChoice::new(egraph, best_nodes(egraph, cost_model), &roots);
+
+    for _ in 0..MAX_EXTRACTION_PASSES {
+        let mut improved = false;
+        for class in used_classes(egraph, &shared.choice, &roots) {
+            for node in egraph[class].nodes.iter() {
+                // earlier switches might have made the class unnecessary
+                if shared.refs.contains_key(&class) && *node != shared.choice[&class] {
+                    improved |= shared.try_switch(class, node);
+                }
+            }
+        }
+        if !improved {
+            break;
+        }
+    }
+
+    let exprs = roots
+        .iter()
+        .map(|&root| {
+            let mut expr = RecExpr::default();
+            let mut added = FxHashMap::default();
+            build(egraph, &shared.choice, root, &mut expr, &mut added);
+            expr
+        })
+        .collect();
+    (exprs, shared.refs.len())
+}
+
+/// The chosen node of every e-class together with the number of references to every e-class
+/// that is needed by the roots. Switching a node only updates the classes that become used or
+/// unused, instead of recounting all classes that are reachable from the roots.
+struct SharedChoice<'a> {
+    egraph: &'a EGraph,
+    choice: FxHashMap<Id, Arith>,
+    /// number of roots and chosen nodes that refer to a class, unused classes are missing
+    refs: FxHashMap<Id, usize>,
+    /// the rank of a class is larger than the rank of all children of its chosen node,
+    /// which proves that the choice is acyclic
+    rank: FxHashMap<Id, usize>,
+}
+
+impl<'a> SharedChoice<'a> {
+    fn new(egraph: &'a EGraph, choice: FxHashMap<Id, Arith>, roots: &[Id]) -> Self {
+        let rank = heights(egraph, &choice);
+        let mut shared = Self {
+            egraph,
+            choice,
+            refs: FxHashMap::default(),
+            rank,
+        };
+        for &root in roots {
+            shared.acquire(root);
+        }
+        shared
+    }
+
+    /// Chooses `node` for `class` if that does not introduce a cycle and reduces the number
+    /// of e-classes needed.
+    fn try_switch(&mut self, class: Id, node: &Arith) -> bool {
+        let children: Vec<Id> = node
+            .children()
+            .iter()
+            .map(|&c| self.egraph.find(c))
+            .collect();
+        if children.iter().any(|&c| self.reaches(c, class)) {
+            return false;
+        }
+        let cost = self.refs.len();
+        let previous = self.switch(class, node.clone());
+        if self.refs.len() < cost {
+            if children.iter().any(|c| self.rank[c] >= self.rank[&class]) {
+                self.rank = heights(self.egraph, &self.choice);
+            }
+            true
+        } else {
+            self.switch(class, previous);
+            false
+        }
+    }
+
+    fn switch(&mut self, class: Id, node: Arith) -> Arith {
+        for &child in node.children() {
+            self.acquire(child);
+        }
+        let previous = self.choice.insert(class, node).unwrap();
+        for &child in previous.children() {
+            self.release(child);
+        }
+        previous
+    }
+
+    fn acquire(&mut self, class: Id) {
+        let mut todo = vec![class];
+        while let Some(id) = todo.pop() {
+            let id = self.egraph.find(id);
+            let count = self.refs.entry(id).or_insert(0);
+            *count += 1;
+            if *count == 1 {
+                todo.extend(self.choice[&id].children().iter().copied());
+            }
+        }
+    }
+
+    fn release(&mut self, class: Id) {
+        let mut todo = vec![class];
+        while let Some(id) = todo.pop() {
+            let id = self.egraph.find(id);
+            let count = self.refs.get_mut(&id).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                self.refs.remove(&id);
+                todo.extend(self.choice[&id].children().iter().copied());
+            }
+        }
+    }
+
+    /// Whether `target` can be reached from `from` through the chosen nodes.
+    /// Only classes with a larger rank than `target` need to be explored.
+    fn reaches(&self, from: Id, target: Id) -> bool {
+        let min_rank = self.rank[&target];
+        let mut visited = FxHashSet::default();
+        let mut todo = vec![from];
+        while let Some(id) = todo.pop() {
+            let id = self.egraph.find(id);
+            if id == target {
+                return true;
+            }
+            if self.rank[&id] > min_rank && visited.insert(id) {
+                todo.extend(self.choice[&id].children().iter().copied());
+            }
+        }
+        false
+    }
+}
+
+/// Height of every e-class when following the chosen nodes, which need to be acyclic.
+fn heights(egraph: &EGraph, choice: &FxHashMap<Id, Arith>) -> FxHashMap<Id, usize> {
+    let mut height = FxHashMap::default();
+    for class in egraph.classes() {
+        let mut todo = vec![(class.id, false)];
+        while let Some((id, done)) = todo.pop() {
+            if height.contains_key(&id) {
+                continue;
+            }
+            let children = choice[&id].children().iter().map(|&c| egraph.find(c));
+            if done {
+                let h = children.map(|c| height[&c] + 1).max().unwrap_or(0);
+                height.insert(id, h);
+            } else {
+                todo.push((id, true));
+                todo.extend(children.map(|c| (c, false)));
+            }
+        }
+    }
+    height
+}
+
+fn used_classes(egraph: &EGraph, choice: &FxHashMap<Id, Arith>, roots: &[Id]) -> Vec<Id> {
+    let mut used = FxHashSet::default();
+    let mut todo = roots.to_vec();
+    let mut order = vec![];
+    while let Some(id) = todo.pop() {
+        let id = egraph.find(id);
+        if used.insert(id) {
+            order.push(id);
+            todo.extend(choice[&id].children().iter().copied());
+        }
+    }
+    order
+}
+
+/// Number of distinct e-classes reachable from the roots, or `None` if the choice is cyclic.
+pub(crate) fn dag_size(
+    egraph: &EGraph,
+    choice: &FxHashMap<Id, Arith>,
+    roots: &[Id],
+) -> Option<usize> {
+    // 1: on the current path, 2: done
+    let mut state: FxHashMap<Id, u8> = FxHashMap::default();
+    let mut todo: Vec<(Id, bool)> = roots.iter().map(|&r| (r, false)).collect();
+    while let Some((id, done)) = todo.pop() {
+        let id = egraph.find(id);
+        if done {
+            state.insert(id, 2);
+            continue;
+        }
+        match state.get(&id) {
+            Some(2) => continue,
+            Some(_) => return None,
+            None => {}
+        }
+        state.insert(id, 1);
+        todo.push((id, true));
+        for &child in choice[&id].children() {
+            let child = egraph.find(child);
+            match state.get(&child) {
+                Some(1) => return None,
+                Some(_) => {}
+                None => todo.push((child, false)),
+            }
+        }
+    }
+    Some(state.len())
+}
+
+/// The cheapest node of every e-class according to `cost_model`.
+pub(crate) fn best_nodes(egraph: &EGraph, cost_model: CostModel) -> FxHashMap<Id, Arith> {
+    fn best(egraph: &EGraph, cost: impl CostFunction<Arith>) -> FxHashMap<Id, Arith> {
+        let extractor = Extractor::new(egraph, cost);
+        egraph
+            .classes()
+            .map(|c| (c.id, extractor.find_best_node(c.id).clone()))
+            .collect()
+    }
+    match cost_model {
+        CostModel::AstSize => best(egraph, AstSize),
+        CostModel::AstDepth => best(egraph, AstDepth),
+    }
+}
+
+pub(crate) fn build(
+    egraph: &EGraph,
+    choice: &FxHashMap<Id, Arith>,
+    class: Id,
+    expr: &mut RecExpr<Arith>,
+    added: &mut FxHashMap<Id, Id>,
+) -> Id {
+    let class = egraph.find(class);
+    if let Some(&id) = added.get(&class) {
+        return id;
+    }
+    let node = choice[&class]
+        .clone()
+        .map_children(|c| build(egraph, choice, c, expr, added));
+    let id = expr.add(node);
+    added.insert(class, id);
+    id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_rewrites, to_arith};
+    use patronus::system::State;
+
+    #[test]
+    fn test_shared_product() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("shared".into());
+        let a = ctx.bv_symbol("a", 8);
+        let b = ctx.bv_symbol("b", 8);
+        sys.add_input(&ctx, a);
+        sys.add_input(&ctx, b);
+        let s = ctx.bv_symbol("s", 8);
+        let next = ctx.build(|c| c.ite(c.equal(a, b), c.mul(a, b), s));
+        sys.add_state(
+            &ctx,
+            State {
+                symbol: s,
+                init: None,
+                next: Some(next),
+            },
+        );
+        let product = ctx.mul(b, a);
+        sys.add_output(&mut ctx, "product".into(), product);
+
+        let res = optimize_system(&mut ctx, &sys, &create_rewrites(), &EGraphConfig::default());
+        assert_eq!(res.regions, 2);
+        let new_next = res.sys.states[0].next.unwrap();
+        let Expr::BVIte { tru, .. } = ctx[new_next] else {
+            panic!("the ite is not part of the e-graph");
+        };
+        // both products are represented by the same expression
+        assert_eq!(tru, res.sys.outputs[0].expr);
+    }
+
+    #[test]
+    fn test_cost_model() {
+        let mut ctx = Context::default();
+        let [a, b, c, d] = ["a", "b", "c", "d"].map(|name| ctx.bv_symbol(name, 8));
+        let chain = ctx.build(|x| x.add(x.add(x.add(a, b), c), d));
+        let tree = ctx.build(|x| x.add(x.add(a, b), x.add(c, d)));
+        let mut egraph = EGraph::default();
+        let chain_class = egraph.add_expr(&to_arith(&ctx, chain).unwrap());
+        let tree_class = egraph.add_expr(&to_arith(&ctx, tree).unwrap());
+        egraph.union(chain_class, tree_class);
+        egraph.rebuild();
+
+        // both have the same size, but the tree is not as deep
+        let choice = best_nodes(&egraph, CostModel::AstDepth);
+        let mut expr = RecExpr::default();
+        build(
+            &egraph,
+            &choice,
+            chain_class,
+            &mut expr,
+            &mut FxHashMap::default(),
+        );
+        assert_eq!(crate::from_arith(&mut ctx, &expr), tree);
+    }
+}
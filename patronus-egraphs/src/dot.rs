@@ -3,20 +3,52 @@
 // author: Kevin Laeufer <laeufer@cornell.edu>
 // some of the code is based on `egg` source code which is licenced under MIT
 
-use crate::{get_const_width_or_sign, is_bin_op, EGraph};
-use egg::Language;
-use rustc_hash::FxHashMap;
+use crate::{get_const_width_or_sign, is_bin_op, ArithMatch, EGraph};
+use egg::{Id, Language};
+use rustc_hash::{FxHashMap, FxHashSet};
 use std::io::{BufWriter, Write};
 
 pub fn to_pdf(filename: &str, egraph: &EGraph) -> std::io::Result<()> {
+    render("pdf", filename, egraph, &[])
+}
+
+pub fn to_dot(filename: &str, egraph: &EGraph) -> std::io::Result<()> {
+    let mut out = BufWriter::new(std::fs::File::create(filename)?);
+    write_to_dot(&mut out, egraph, &[])?;
+    Ok(())
+}
+
+/// Renders the e-graph as a dot graph. E-classes that contain one of the `highlight` matches
+/// are filled in, which makes it easy to see where a rule applies (or does not apply).
+pub fn egraph_to_dot(egraph: &EGraph, highlight: &[ArithMatch]) -> String {
+    let mut out = Vec::new();
+    write_to_dot(&mut out, egraph, highlight).expect("writing to a Vec cannot fail");
+    String::from_utf8(out).unwrap()
+}
+
+/// Like [`egraph_to_dot`], but renders the graph to an SVG file. Requires graphviz.
+pub fn egraph_to_svg(
+    filename: &str,
+    egraph: &EGraph,
+    highlight: &[ArithMatch],
+) -> std::io::Result<()> {
+    render("svg", filename, egraph, highlight)
+}
+
+fn render(
+    format: &str,
+    filename: &str,
+    egraph: &EGraph,
+    highlight: &[ArithMatch],
+) -> std::io::Result<()> {
     use std::process::{Command, Stdio};
     let mut child = Command::new("dot")
-        .args(["-Tpdf", "-o", filename])
+        .args([&format!("-T{format}"), "-o", filename])
         .stdin(Stdio::piped())
         .stdout(Stdio::null())
         .spawn()?;
     let stdin = child.stdin.as_mut().expect("Failed to open stdin");
-    write_to_dot(stdin, egraph)?;
+    write_to_dot(stdin, egraph, highlight)?;
     match child.wait()?.code() {
         Some(0) => Ok(()),
         Some(e) => panic!("dot program returned error code {}", e),
@@ -24,16 +56,16 @@ pub fn to_pdf(filename: &str, egraph: &EGraph) -> std::io::Result<()> {
     }
 }
 
-pub fn to_dot(filename: &str, egraph: &EGraph) -> std::io::Result<()> {
-    let mut out = BufWriter::new(std::fs::File::create(filename)?);
-    write_to_dot(&mut out, egraph)?;
-    Ok(())
-}
-
 /// Reimplements egg's `to_dot` functionality.
 /// This is necessary because we do not want to show the Width nodes in the graph, because
 /// otherwise it becomes very confusing.
-fn write_to_dot(out: &mut impl Write, egraph: &EGraph) -> std::io::Result<()> {
+fn write_to_dot(
+    out: &mut impl Write,
+    egraph: &EGraph,
+    highlight: &[ArithMatch],
+) -> std::io::Result<()> {
+    let highlight: FxHashSet<Id> = highlight.iter().map(|m| egraph.find(m.eclass)).collect();
+
     writeln!(out, "digraph egraph {{")?;
 
     // set compound=true to enable edges to clusters
@@ -53,7 +85,12 @@ fn write_to_dot(out: &mut impl Write, egraph: &EGraph) -> std::io::Result<()> {
     for class in egraph.classes() {
         if !widths.contains_key(&class.id) {
             writeln!(out, "  subgraph cluster_{} {{", class.id)?;
-            writeln!(out, "    style=dotted")?;
+            if highlight.contains(&class.id) {
+                writeln!(out, "    style=\"filled,dotted\"")?;
+                writeln!(out, "    fillcolor=lightyellow")?;
+            } else {
+                writeln!(out, "    style=dotted")?;
+            }
             writeln!(out, "    label=\"{}\"", class.id)?;
             for (i, node) in class.iter().enumerate() {
                 let label = if is_bin_op(node) {
@@ -114,3 +151,35 @@ fn write_to_dot(out: &mut impl Write, egraph: &EGraph) -> std::io::Result<()> {
 
     write!(out, "}}")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arithmetic::verification_fig_1;
+    use crate::{create_rewrites, to_arith};
+    use patronus::expr::Context;
+
+    #[test]
+    fn test_egraph_to_dot_highlights_matches() {
+        let mut ctx = Context::default();
+        let (spec, _) = verification_fig_1(&mut ctx);
+        let mut egraph = EGraph::default();
+        egraph.add_expr(&to_arith(&ctx, spec).unwrap());
+        egraph.rebuild();
+
+        let plain = egraph_to_dot(&egraph, &[]);
+        assert!(plain.starts_with("digraph egraph {"));
+        assert!(!plain.contains("fillcolor"));
+
+        let commute_mul = create_rewrites()
+            .into_iter()
+            .find(|r| r.name() == "commute-mul")
+            .unwrap();
+        let matches = commute_mul.find_lhs_matches(&egraph);
+        assert_eq!(matches.len(), 1);
+        let dot = egraph_to_dot(&egraph, &matches);
+        assert_eq!(dot.matches("fillcolor").count(), 1);
+        // the multiplication is labelled with its output width
+        assert!(dot.contains("* ("), "{dot}");
+    }
+}
@@ -0,0 +1,156 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+/*!
+# Clustering Equivalent Expressions
+
+Pairwise checks need one e-graph per pair, which is wasteful when many candidate
+implementations are compared against each other and against a single spec.
+[`Equivalence::check`] adds all expressions to the same e-graph as roots of one runner and,
+after saturation, partitions them by the e-class that they ended up in.
+
+!*/
+
+use crate::limits::stop_result;
+use crate::{configure_runner, Arith, ArithRewrite, EGraph, EGraphEquivResult, Rewrite};
+use egg::{Id, RecExpr};
+use patronus::config::EGraphConfig;
+use rustc_hash::FxHashMap;
+
+/// Saturates many expressions in a single e-graph.
+pub struct Equivalence {
+    rules: Vec<Rewrite>,
+    config: EGraphConfig,
+}
+
+impl Equivalence {
+    pub fn new(rules: &[ArithRewrite], config: &EGraphConfig) -> Self {
+        Self {
+            rules: rules.iter().flat_map(|r| r.to_egg()).collect(),
+            config: config.clone(),
+        }
+    }
+
+    /// Groups `exprs` by e-class. Stops early once all expressions are in the same class.
+    pub fn check(&self, exprs: &[RecExpr<Arith>]) -> PartitionedClasses {
+        let mut runner = configure_runner(egg::Runner::default(), &self.config);
+        for expr in exprs.iter() {
+            runner = runner.with_expr(expr);
+        }
+        let runner = runner
+            .with_hook(|r| {
+                if all_merged(&r.egraph, &r.roots) {
+                    Err("equivalent".to_string())
+                } else {
+                    Ok(())
+                }
+            })
+            .run(&self.rules);
+        let result = if all_merged(&runner.egraph, &runner.roots) {
+            EGraphEquivResult::Equivalent
+        } else {
+            stop_result(&runner)
+        };
+        let roots: Vec<Id> = runner
+            .roots
+            .iter()
+            .map(|&r| runner.egraph.find(r))
+            .collect();
+        let mut index_of_class: FxHashMap<Id, usize> = FxHashMap::default();
+        let mut classes: Vec<Vec<usize>> = vec![];
+        let class_of = roots
+            .iter()
+            .enumerate()
+            .map(|(ii, root)| {
+                let class = *index_of_class.entry(*root).or_insert_with(|| {
+                    classes.push(vec![]);
+                    classes.len() - 1
+                });
+                classes[class].push(ii);
+                class
+            })
+            .collect();
+        PartitionedClasses {
+            roots,
+            class_of,
+            classes,
+            result,
+            egraph: runner.egraph,
+        }
+    }
+}
+
+fn all_merged(egraph: &EGraph, roots: &[Id]) -> bool {
+    roots
+        .iter()
+        .all(|&r| egraph.find(r) == egraph.find(roots[0]))
+}
+
+/// Partition of the checked expressions into e-classes.
+pub struct PartitionedClasses {
+    /// canonical e-class of every expression
+    pub roots: Vec<Id>,
+    /// index into `classes` for every expression
+    class_of: Vec<usize>,
+    /// indices of the expressions in each class, ordered by their first member
+    classes: Vec<Vec<usize>>,
+    /// `Equivalent` if all expressions ended up in the same class, otherwise the reason
+    /// for which saturation stopped
+    pub result: EGraphEquivResult,
+    pub egraph: EGraph,
+}
+
+impl PartitionedClasses {
+    pub fn classes(&self) -> &[Vec<usize>] {
+        &self.classes
+    }
+
+    pub fn num_classes(&self) -> usize {
+        self.classes.len()
+    }
+
+    /// Index of the class that the expression at `index` belongs to.
+    pub fn class_of(&self, index: usize) -> usize {
+        self.class_of[index]
+    }
+
+    pub fn same_class(&self, a: usize, b: usize) -> bool {
+        self.class_of[a] == self.class_of[b]
+    }
+
+    /// All expressions that were proven equivalent to the one at `index`, including itself.
+    pub fn equivalent_to(&self, index: usize) -> &[usize] {
+        &self.classes[self.class_of[index]]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arithmetic::verification_fig_1;
+    use crate::{create_rewrites, to_arith};
+    use patronus::expr::Context;
+
+    #[test]
+    fn test_partition_candidates() {
+        let mut ctx = Context::default();
+        let (spec, implementation) = verification_fig_1(&mut ctx);
+        let a = ctx.bv_symbol("a", 8);
+        let b = ctx.bv_symbol("b", 8);
+        let exprs: Vec<_> = [spec, a, implementation, b, a]
+            .into_iter()
+            .map(|e| to_arith(&ctx, e).unwrap())
+            .collect();
+        let equivalence = Equivalence::new(&create_rewrites(), &EGraphConfig::default());
+        let partition = equivalence.check(&exprs);
+        assert_eq!(partition.classes(), [vec![0, 2], vec![1, 4], vec![3]]);
+        assert!(partition.same_class(0, 2));
+        assert!(!partition.same_class(1, 3));
+        assert_eq!(partition.class_of(4), 1);
+        assert_eq!(partition.equivalent_to(2), [0, 2]);
+        assert!(!partition.result.is_equivalent());
+
+        let partition = equivalence.check(&[exprs[0].clone(), exprs[2].clone()]);
+        assert!(partition.result.is_equivalent());
+    }
+}
@@ -0,0 +1,290 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+/*!
+# Macro-Op Fusion
+
+Hardware libraries often provide fused operations, e.g., a multiply-accumulate unit, which are
+cheaper than the individual operators they replace. A [`FusionLibrary`] describes every fused
+operation as a pattern over the arithmetic IR together with its cost. [`extract_fused`] then
+chooses the cheapest representation of an e-class, where a fused operation can cover all
+e-nodes of its pattern at once. Covered nodes are replaced by a single composite node, whose
+arguments are the e-classes bound to the pattern variables.
+
+Plain e-nodes cost one for every operator on bit-vector values and nothing for widths, signs,
+constants and symbols.
+
+!*/
+
+use crate::{Arith, EGraph, RuleError};
+use egg::{Id, Language, Pattern, Searcher, Var};
+use rustc_hash::FxHashMap;
+use std::fmt::{Display, Formatter};
+
+/// A fused operation that replaces all e-nodes matched by its pattern.
+#[derive(Debug, Clone)]
+pub struct FusedOp {
+    name: String,
+    pattern: Pattern<Arith>,
+    cost: usize,
+}
+
+impl FusedOp {
+    pub fn new(name: &str, pattern: &str, cost: usize) -> Result<Self, RuleError> {
+        let pattern = pattern
+            .parse::<Pattern<Arith>>()
+            .map_err(|e| RuleError::Parse {
+                rule: name.to_string(),
+                src: pattern.to_string(),
+                msg: e.to_string(),
+            })?;
+        Ok(Self {
+            name: name.to_string(),
+            pattern,
+            cost,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn cost(&self) -> usize {
+        self.cost
+    }
+
+    /// Pattern variables in the order in which they become arguments of the composite node.
+    pub fn args(&self) -> Vec<Var> {
+        self.pattern.vars()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FusionLibrary {
+    ops: Vec<FusedOp>,
+}
+
+impl FusionLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, name: &str, pattern: &str, cost: usize) -> Result<(), RuleError> {
+        self.ops.push(FusedOp::new(name, pattern, cost)?);
+        Ok(())
+    }
+
+    pub fn ops(&self) -> &[FusedOp] {
+        &self.ops
+    }
+}
+
+/// A node of an extracted expression. Children refer to earlier nodes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FusedNode {
+    Arith(Arith),
+    Fused { name: String, args: Vec<Id> },
+}
+
+/// An expression that may contain composite nodes. The last node is the root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FusedExpr {
+    nodes: Vec<FusedNode>,
+}
+
+impl FusedExpr {
+    pub fn nodes(&self) -> &[FusedNode] {
+        &self.nodes
+    }
+
+    pub fn root(&self) -> Id {
+        Id::from(self.nodes.len() - 1)
+    }
+
+    /// Number of composite nodes.
+    pub fn num_fused(&self) -> usize {
+        self.nodes
+            .iter()
+            .filter(|n| matches!(n, FusedNode::Fused { .. }))
+            .count()
+    }
+
+    fn fmt_node(&self, f: &mut Formatter<'_>, id: Id) -> std::fmt::Result {
+        let (op, children): (String, &[Id]) = match &self.nodes[usize::from(id)] {
+            FusedNode::Arith(node) => (node.to_string(), node.children()),
+            FusedNode::Fused { name, args } => (name.clone(), args.as_slice()),
+        };
+        if children.is_empty() {
+            return write!(f, "{op}");
+        }
+        write!(f, "({op}")?;
+        for &child in children.iter() {
+            write!(f, " ")?;
+            self.fmt_node(f, child)?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl Display for FusedExpr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.fmt_node(f, self.root())
+    }
+}
+
+fn node_cost(node: &Arith) -> usize {
+    match node {
+        Arith::WidthMaxPlus1(_)
+        | Arith::WidthLeftShift(_)
+        | Arith::WidthAdd(_)
+        | Arith::WidthMul(_)
+        | Arith::Width(_)
+        | Arith::Sign(_)
+        | Arith::Const(_)
+        | Arith::Symbol(_) => 0,
+        _ => 1,
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Choice {
+    Node(Arith),
+    Fused(usize, Vec<Id>),
+}
+
+/// Extracts the cheapest representation of `root`, using fused operations from `library`
+/// wherever they reduce the cost. Returns the total cost and the expression.
+pub fn extract_fused(egraph: &EGraph, root: Id, library: &FusionLibrary) -> (usize, FusedExpr) {
+    // all places where a fused operation could be used, with its arguments
+    let mut fusions: Vec<(Id, usize, Vec<Id>)> = vec![];
+    for (ii, op) in library.ops.iter().enumerate() {
+        let args = op.args();
+        for m in op.pattern.search(egraph) {
+            for subst in m.substs.iter() {
+                let bound = args.iter().map(|&v| egraph.find(subst[v])).collect();
+                fusions.push((egraph.find(m.eclass), ii, bound));
+            }
+        }
+    }
+
+    // iterate until the costs of all e-classes reach a fixed point
+    let mut best: FxHashMap<Id, (usize, Choice)> = FxHashMap::default();
+    let cost_of = |best: &FxHashMap<Id, (usize, Choice)>, ids: &[Id]| {
+        ids.iter()
+            .map(|&c| best.get(&egraph.find(c)).map(|(cost, _)| *cost))
+            .sum::<Option<usize>>()
+    };
+    let improve = |best: &mut FxHashMap<Id, (usize, Choice)>, class: Id, cost, choice| {
+        if best.get(&class).map_or(true, |(old, _)| cost < *old) {
+            best.insert(class, (cost, choice));
+            true
+        } else {
+            false
+        }
+    };
+    loop {
+        let mut changed = false;
+        for class in egraph.classes() {
+            for node in class.nodes.iter() {
+                if let Some(children) = cost_of(&best, node.children()) {
+                    let cost = node_cost(node) + children;
+                    changed |= improve(&mut best, class.id, cost, Choice::Node(node.clone()));
+                }
+            }
+        }
+        for (class, op, args) in fusions.iter() {
+            if let Some(children) = cost_of(&best, args) {
+                let cost = library.ops[*op].cost + children;
+                changed |= improve(&mut best, *class, cost, Choice::Fused(*op, args.clone()));
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    let root = egraph.find(root);
+    let cost = best[&root].0;
+    let mut nodes = vec![];
+    let mut built = FxHashMap::default();
+    build(egraph, library, &best, root, &mut nodes, &mut built);
+    (cost, FusedExpr { nodes })
+}
+
+fn build(
+    egraph: &EGraph,
+    library: &FusionLibrary,
+    best: &FxHashMap<Id, (usize, Choice)>,
+    class: Id,
+    nodes: &mut Vec<FusedNode>,
+    built: &mut FxHashMap<Id, Id>,
+) -> Id {
+    let class = egraph.find(class);
+    if let Some(&id) = built.get(&class) {
+        return id;
+    }
+    let node = match &best[&class].1 {
+        Choice::Node(node) => {
+            let node = node
+                .clone()
+                .map_children(|c| build(egraph, library, best, c, nodes, built));
+            FusedNode::Arith(node)
+        }
+        Choice::Fused(op, args) => {
+            let args = args
+                .iter()
+                .map(|&c| build(egraph, library, best, c, nodes, built))
+                .collect();
+            FusedNode::Fused {
+                name: library.ops[*op].name.clone(),
+                args,
+            }
+        }
+    };
+    nodes.push(node);
+    let id = Id::from(nodes.len() - 1);
+    built.insert(class, id);
+    id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egg::RecExpr;
+
+    const MAC: &str = "(+ ?wo ?wm ?sm (* ?wm ?wa ?sa ?a ?wb ?sb ?b) ?wc ?sc ?c)";
+
+    #[test]
+    fn test_extract_multiply_accumulate() {
+        let expr: RecExpr<Arith> =
+            "(+ W<16> W<16> unsign (* W<16> W<8> unsign a W<8> unsign b) W<16> unsign c)"
+                .parse()
+                .unwrap();
+        let mut egraph = EGraph::default();
+        let root = egraph.add_expr(&expr);
+        egraph.rebuild();
+
+        // without a library, we get the original expression
+        let (cost, plain) = extract_fused(&egraph, root, &FusionLibrary::new());
+        assert_eq!(cost, 2);
+        assert_eq!(plain.num_fused(), 0);
+        assert_eq!(plain.to_string(), expr.to_string());
+
+        let mut library = FusionLibrary::new();
+        library.add("mac", MAC, 1).unwrap();
+        let (cost, fused) = extract_fused(&egraph, root, &library);
+        assert_eq!(cost, 1);
+        assert_eq!(fused.num_fused(), 1);
+        assert_eq!(
+            fused.to_string(),
+            "(mac W<16> W<16> unsign W<8> unsign a W<8> unsign b W<16> unsign c)"
+        );
+
+        // a fused operation that is more expensive is not used
+        let mut expensive = FusionLibrary::new();
+        expensive.add("mac", MAC, 3).unwrap();
+        let (cost, fused) = extract_fused(&egraph, root, &expensive);
+        assert_eq!(cost, 2);
+        assert_eq!(fused.num_fused(), 0);
+    }
+}
@@ -0,0 +1,578 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+/*!
+# Differential Rule Testing
+
+Checks rewrite rules by instantiating them with random widths and signs that satisfy the rule
+condition and evaluating both sides on random operand values with the patronus interpreter.
+This is much faster than proving a rule with an SMT solver and thus a cheap first line of
+defense when writing new rules.
+
+Output widths of `concat` and `repeat` are derived from their operands, all other widths are
+sampled between one and [`FuzzOptions::max_width`].
+
+!*/
+
+use crate::{is_bin_op, Arith, ArithRewrite, ConstFn, Sign};
+use baa::{BitVecOps, BitVecValue, Value};
+use egg::{ENodeOrVar, Id, Language, PatternAst, RecExpr, Var};
+use patronus::expr::traversal::{top_down, TraversalCmd};
+use patronus::expr::{eval_expr, Context, Expr, ExprRef, TypeCheck, WidthInt};
+use patronus::random::{default_seed, new_rng};
+use rand::rngs::SmallRng;
+use rustc_hash::FxHashMap;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuzzOptions {
+    /// widths are sampled from `1..=max_width`, a maximum of zero is treated as one
+    pub max_width: WidthInt,
+    /// number of width and sign assignments to try per rule
+    pub assignments: usize,
+    /// number of random operand values per assignment
+    pub values: usize,
+    pub seed: u64,
+}
+
+impl Default for FuzzOptions {
+    fn default() -> Self {
+        Self {
+            max_width: 8,
+            assignments: 256,
+            values: 8,
+            seed: default_seed(),
+        }
+    }
+}
+
+/// A rule instance for which both sides evaluate to different values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleMismatch {
+    pub rule: String,
+    /// widths and signs (0 for unsigned, 1 for signed)
+    pub assignment: Vec<(Var, WidthInt)>,
+    /// values of operands that the rule requires to be constant
+    pub constants: Vec<(Var, u64)>,
+    pub inputs: Vec<(String, BitVecValue)>,
+    pub lhs: BitVecValue,
+    pub rhs: BitVecValue,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FuzzReport {
+    /// number of assignments that satisfied the condition of each rule
+    pub checked: Vec<(String, usize)>,
+    /// at most one mismatch per rule
+    pub mismatches: Vec<RuleMismatch>,
+}
+
+impl FuzzReport {
+    pub fn checked(&self, rule: &str) -> usize {
+        self.checked
+            .iter()
+            .find(|(r, _)| r == rule)
+            .map_or(0, |(_, n)| *n)
+    }
+}
+
+pub fn fuzz_rewrites(rules: &[ArithRewrite], opts: &FuzzOptions) -> FuzzReport {
+    let mut rng = new_rng(opts.seed);
+    let mut report = FuzzReport::default();
+    for rule in rules.iter() {
+        let (checked, mismatch) = fuzz_rule(rule, opts, &mut rng);
+        report.checked.push((rule.name().to_string(), checked));
+        report.mismatches.extend(mismatch);
+    }
+    report
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VarKind {
+    Width,
+    Sign,
+    Operand,
+}
+
+fn fuzz_rule(
+    rule: &ArithRewrite,
+    opts: &FuzzOptions,
+    rng: &mut SmallRng,
+) -> (usize, Option<RuleMismatch>) {
+    let lhs = rule.patterns().0;
+    let vars = classify_vars(lhs);
+    let mut checked = 0;
+    for _ in 0..opts.assignments {
+        let mut assignment: FxHashMap<Var, WidthInt> = vars
+            .iter()
+            .flat_map(|&(v, kind)| match kind {
+                VarKind::Width => Some((v, 1 + sample(rng, opts.max_width as u64) as WidthInt)),
+                VarKind::Sign => Some((v, sample(rng, 2) as WidthInt)),
+                VarKind::Operand => None,
+            })
+            .collect();
+        derive_output_widths(lhs, &mut assignment);
+        let assignment: Vec<(Var, WidthInt)> = assignment.into_iter().collect();
+        if !rule.eval_condition(&assignment) {
+            continue;
+        }
+        let constants = sample_constants(rule, &assignment, rng);
+        let Some(instance) = instantiate_rule(rule, &vars, &assignment, constants) else {
+            // an operand is used with different widths
+            continue;
+        };
+        checked += 1;
+        for _ in 0..opts.values {
+            let inputs: Vec<(ExprRef, BitVecValue)> = instance
+                .symbols
+                .iter()
+                .map(|&(s, w)| (s, BitVecValue::random(rng, w)))
+                .collect();
+            if let Err(mismatch) = instance.check(rule, &assignment, inputs) {
+                return (checked, Some(mismatch));
+            }
+        }
+    }
+    (checked, None)
+}
+
+/// A rule with concrete widths and signs, converted into patronus expressions.
+struct Instance {
+    ctx: Context,
+    lhs: ExprRef,
+    rhs: ExprRef,
+    symbols: Vec<(ExprRef, WidthInt)>,
+    constants: Vec<(Var, u64)>,
+}
+
+impl Instance {
+    fn check(
+        &self,
+        rule: &ArithRewrite,
+        assignment: &[(Var, WidthInt)],
+        inputs: Vec<(ExprRef, BitVecValue)>,
+    ) -> Result<(), RuleMismatch> {
+        let lhs = eval_bv(&self.ctx, &inputs, self.lhs);
+        let rhs = eval_bv(&self.ctx, &inputs, self.rhs);
+        if lhs.width() == rhs.width() && lhs.is_equal(&rhs) {
+            return Ok(());
+        }
+        let mut assignment = assignment.to_vec();
+        assignment.sort_by_key(|(v, _)| v.to_string());
+        Err(RuleMismatch {
+            rule: rule.name().to_string(),
+            assignment,
+            constants: self.constants.clone(),
+            inputs: inputs
+                .into_iter()
+                .map(|(s, v)| (self.ctx.get_symbol_name(s).unwrap().to_string(), v))
+                .collect(),
+            lhs,
+            rhs,
+        })
+    }
+}
+
+/// Returns `None` if an operand would be used with different widths.
+fn instantiate_rule(
+    rule: &ArithRewrite,
+    vars: &[(Var, VarKind)],
+    assignment: &[(Var, WidthInt)],
+    constants: Vec<(Var, u64)>,
+) -> Option<Instance> {
+    let (lhs, rhs) = rule.patterns();
+    let subst = substitution(rule, vars, assignment, &constants);
+    let mut ctx = Context::default();
+    let lhs_expr = crate::from_arith(&mut ctx, &instantiate(lhs, &subst));
+    let rhs_arith = instantiate(rhs, &subst);
+    let rhs_expr = match rhs_arith.as_ref() {
+        // a single operand does not know its width
+        [Arith::Symbol(name)] => ctx.bv_symbol(name, lhs_expr.get_bv_type(&ctx).unwrap()),
+        _ => crate::from_arith(&mut ctx, &rhs_arith),
+    };
+    let symbols = collect_symbols(&ctx, [lhs_expr, rhs_expr])?;
+    Some(Instance {
+        ctx,
+        lhs: lhs_expr,
+        rhs: rhs_expr,
+        symbols,
+        constants,
+    })
+}
+
+/// Samples a value for every operand that the rule requires to be constant.
+fn sample_constants(
+    rule: &ArithRewrite,
+    assignment: &[(Var, WidthInt)],
+    rng: &mut SmallRng,
+) -> Vec<(Var, u64)> {
+    let lhs = rule.patterns().0;
+    rule.const_values()
+        .iter()
+        .map(|&(_, f, operand)| {
+            let width = operand_width(lhs, operand, assignment).min(u64::BITS);
+            let value = match f {
+                // the power of two has to fit into the operand
+                ConstFn::Log2 => 1u64 << sample(rng, width as u64),
+                ConstFn::Pow2 => sample(rng, 1u64 << width.min(6)),
+            };
+            (operand, value)
+        })
+        .collect()
+}
+
+/// Width of an operand of a binary operation.
+fn operand_width(
+    pattern: &PatternAst<Arith>,
+    operand: Var,
+    assignment: &[(Var, WidthInt)],
+) -> WidthInt {
+    let width = |id: Id| match &pattern[id] {
+        ENodeOrVar::Var(v) => assignment.iter().find(|(k, _)| k == v).map(|(_, w)| *w),
+        ENodeOrVar::ENode(Arith::Width(w)) => Some((*w).into()),
+        _ => None,
+    };
+    pattern
+        .as_ref()
+        .iter()
+        .flat_map(|node| match node {
+            ENodeOrVar::ENode(n) if is_bin_op(n) => {
+                let c = n.children();
+                [(c[1], c[3]), (c[4], c[6])]
+                    .into_iter()
+                    .find(|(_, op)| matches!(&pattern[*op], ENodeOrVar::Var(v) if *v == operand))
+                    .and_then(|(w, _)| width(w))
+            }
+            _ => None,
+        })
+        .next()
+        .expect("constant operands need to be part of a binary operation")
+}
+
+/// Re-evaluates a single rule instance, e.g., one that was reported as a [`RuleMismatch`].
+/// Widths, signs and constant operands are given by variable name, e.g., `("?wo", 8)`,
+/// and inputs as binary strings.
+/// Panics if the assignment is incomplete or does not satisfy the rule condition.
+pub fn check_rule_instance(
+    rule: &ArithRewrite,
+    assignment: &[(&str, WidthInt)],
+    constants: &[(&str, u64)],
+    inputs: &[(&str, &str)],
+) -> Result<(), RuleMismatch> {
+    let var = |v: &str| v.parse::<Var>().expect("invalid variable name");
+    let assignment: Vec<(Var, WidthInt)> = assignment.iter().map(|(v, w)| (var(v), *w)).collect();
+    let constants: Vec<(Var, u64)> = constants.iter().map(|(v, c)| (var(v), *c)).collect();
+    assert!(
+        rule.eval_condition(&assignment),
+        "assignment does not satisfy the condition of `{}`",
+        rule.name()
+    );
+    let vars = classify_vars(rule.patterns().0);
+    let instance = instantiate_rule(rule, &vars, &assignment, constants)
+        .expect("operands with inconsistent widths");
+    let inputs = instance
+        .symbols
+        .iter()
+        .map(|&(s, _)| {
+            let name = instance.ctx.get_symbol_name(s).unwrap();
+            let (_, value) = inputs
+                .iter()
+                .find(|(n, _)| *n == name)
+                .unwrap_or_else(|| panic!("missing value for `{name}`"));
+            (
+                s,
+                BitVecValue::from_bit_str(value).expect("invalid binary string"),
+            )
+        })
+        .collect();
+    instance.check(rule, &assignment, inputs)
+}
+
+impl RuleMismatch {
+    /// Generates a unit test that reproduces the mismatch with [`check_rule_instance`].
+    pub fn to_rust_test(&self, test_name: &str) -> String {
+        let assignment: Vec<String> = self
+            .assignment
+            .iter()
+            .map(|(v, w)| format!("(\"{v}\", {w})"))
+            .collect();
+        let constants: Vec<String> = self
+            .constants
+            .iter()
+            .map(|(v, c)| format!("(\"{v}\", {c})"))
+            .collect();
+        let inputs: Vec<String> = self
+            .inputs
+            .iter()
+            .map(|(n, v)| format!("(\"{n}\", \"{}\")", v.to_bit_str()))
+            .collect();
+        format!(
+            r#"/// lhs evaluates to {lhs}, rhs to {rhs}
+#[test]
+fn {test_name}() {{
+    let rules = patronus_egraphs::create_rewrites();
+    let rule = rules.iter().find(|r| r.name() == "{rule}").unwrap();
+    let assignment = [{assignment}];
+    let constants = [{constants}];
+    let inputs = [{inputs}];
+    patronus_egraphs::check_rule_instance(rule, &assignment, &constants, &inputs).unwrap();
+}}
+"#,
+            lhs = self.lhs.to_bit_str(),
+            rhs = self.rhs.to_bit_str(),
+            rule = self.rule,
+            assignment = assignment.join(", "),
+            constants = constants.join(", "),
+            inputs = inputs.join(", "),
+        )
+    }
+}
+
+/// Returns a value in `0..n`, or `0` if the range is empty.
+fn sample(rng: &mut SmallRng, n: u64) -> u64 {
+    if n == 0 {
+        return 0;
+    }
+    BitVecValue::random(rng, 32).to_u64().unwrap() % n
+}
+
+/// Determines for every variable in `pattern` whether it is a width, sign or operand.
+fn classify_vars(pattern: &PatternAst<Arith>) -> Vec<(Var, VarKind)> {
+    let mut out: Vec<(Var, VarKind)> = vec![];
+    for node in pattern.as_ref().iter() {
+        let ENodeOrVar::ENode(node) = node else {
+            continue;
+        };
+        for (ii, child) in node.children().iter().enumerate() {
+            let ENodeOrVar::Var(var) = &pattern[*child] else {
+                continue;
+            };
+            let kind = match node {
+                n if is_bin_op(n) => match ii {
+                    2 | 5 => VarKind::Sign,
+                    3 | 6 => VarKind::Operand,
+                    _ => VarKind::Width,
+                },
+                Arith::Negate(_) if ii == 2 => VarKind::Sign,
+                Arith::Negate(_) if ii == 3 => VarKind::Operand,
+                Arith::Concat(_) if ii == 2 || ii == 4 => VarKind::Operand,
+                Arith::Repeat(_) if ii == 3 => VarKind::Operand,
+                Arith::RotateLeft(_) | Arith::RotateRight(_) if ii == 1 || ii == 3 => {
+                    VarKind::Operand
+                }
+                Arith::RotateLeftConst(_) if ii == 2 => VarKind::Operand,
+                Arith::SaturatingAdd(_)
+                | Arith::SaturatingSub(_)
+                | Arith::Greater(_)
+                | Arith::GreaterEqual(_) => match ii {
+                    1 | 3 | 6 => VarKind::Sign,
+                    4 | 7 => VarKind::Operand,
+                    _ => VarKind::Width,
+                },
+                _ => VarKind::Width,
+            };
+            if !out.iter().any(|(v, _)| v == var) {
+                out.push((*var, kind));
+            }
+        }
+    }
+    out
+}
+
+/// Sets the output width of every `concat` and `repeat` to the width of its result.
+fn derive_output_widths(pattern: &PatternAst<Arith>, assignment: &mut FxHashMap<Var, WidthInt>) {
+    // children always come before their parents
+    for node in pattern.as_ref().iter() {
+        let width = |id: &Id, assignment: &FxHashMap<Var, WidthInt>| match &pattern[*id] {
+            ENodeOrVar::Var(v) => assignment.get(v).copied(),
+            ENodeOrVar::ENode(Arith::Width(w)) => Some((*w).into()),
+            _ => None,
+        };
+        let (out, derived) = match node {
+            ENodeOrVar::ENode(Arith::Concat([w, wa, _, wb, _])) => (
+                w,
+                width(wa, assignment)
+                    .zip(width(wb, assignment))
+                    .map(|(a, b)| a + b),
+            ),
+            ENodeOrVar::ENode(Arith::Repeat([w, n, wa, _])) => (
+                w,
+                width(n, assignment)
+                    .zip(width(wa, assignment))
+                    .map(|(n, a)| n * a),
+            ),
+            _ => continue,
+        };
+        if let (ENodeOrVar::Var(v), Some(derived)) = (&pattern[*out], derived) {
+            assignment.insert(*v, derived);
+        }
+    }
+}
+
+fn substitution(
+    rule: &ArithRewrite,
+    vars: &[(Var, VarKind)],
+    assignment: &[(Var, WidthInt)],
+    constants: &[(Var, u64)],
+) -> FxHashMap<Var, Arith> {
+    let value = |v: &Var| assignment.iter().find(|(k, _)| k == v).unwrap().1;
+    let constant = |v: &Var| constants.iter().find(|(k, _)| k == v).map(|(_, c)| *c);
+    let mut out: FxHashMap<Var, Arith> = vars
+        .iter()
+        .map(|(v, kind)| {
+            let node = match kind {
+                VarKind::Width => value(v).into(),
+                VarKind::Sign if value(v) == 0 => Sign::Unsigned.into(),
+                VarKind::Sign => Sign::Signed.into(),
+                VarKind::Operand => match constant(v) {
+                    Some(c) => Arith::Const(c),
+                    None => Arith::Symbol(v.to_string().trim_start_matches('?').into()),
+                },
+            };
+            (*v, node)
+        })
+        .collect();
+    for (v, width) in rule.width_values() {
+        out.insert(*v, Arith::Const(value(width) as u64));
+    }
+    for (v, f, operand) in rule.const_values() {
+        let c = constant(operand).expect("missing constant operand");
+        let result = f
+            .apply(c)
+            .expect("constant operand does not satisfy the rule");
+        out.insert(*v, Arith::Const(result));
+    }
+    out
+}
+
+fn instantiate(pattern: &PatternAst<Arith>, subst: &FxHashMap<Var, Arith>) -> RecExpr<Arith> {
+    let mut out = RecExpr::default();
+    for node in pattern.as_ref().iter() {
+        match node {
+            ENodeOrVar::ENode(n) => out.add(n.clone()),
+            ENodeOrVar::Var(v) => out.add(subst[v].clone()),
+        };
+    }
+    out
+}
+
+/// Returns all symbols with their widths or `None` if two symbols share a name.
+fn collect_symbols(ctx: &Context, roots: [ExprRef; 2]) -> Option<Vec<(ExprRef, WidthInt)>> {
+    let mut symbols: Vec<(ExprRef, WidthInt)> = vec![];
+    for root in roots {
+        top_down(ctx, root, |ctx, e| {
+            if let Expr::BVSymbol { width, .. } = ctx[e] {
+                if !symbols.iter().any(|(s, _)| *s == e) {
+                    symbols.push((e, width));
+                }
+            }
+            TraversalCmd::Continue
+        });
+    }
+    let mut names: Vec<&str> = symbols
+        .iter()
+        .map(|(s, _)| ctx.get_symbol_name(*s).unwrap())
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+    (names.len() == symbols.len()).then_some(symbols)
+}
+
+fn eval_bv(ctx: &Context, inputs: &[(ExprRef, BitVecValue)], e: ExprRef) -> BitVecValue {
+    match eval_expr(ctx, inputs, e) {
+        Value::BitVec(v) => v,
+        Value::Array(_) => unreachable!("arithmetic expressions are bit-vectors"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_rewrites;
+
+    #[test]
+    fn test_fuzz_builtin_rules() {
+        let rules = create_rewrites();
+        let report = fuzz_rewrites(&rules, &FuzzOptions::default());
+        assert!(report.mismatches.is_empty(), "{:?}", report.mismatches);
+        for name in [
+            "commute-add",
+            "commute-mul",
+            "assoc-add-left",
+            "assoc-add-right",
+            "assoc-mul-left",
+            "assoc-mul-right",
+            "distribute-mul-add",
+            "factor-mul-add",
+            "sub-to-add-neg",
+            "add-neg-to-sub",
+            "sub-neg-to-add",
+            "neg-neg",
+            "sub-zero-to-neg",
+            "sign-extend-neg-add",
+            "mul-pow2-to-shift",
+            "shift-to-mul-pow2",
+            "concat-to-shift-add",
+            "concat-repeat",
+            "repeat-repeat",
+            "rotate-left-compose",
+            "rotate-left-right-cancel",
+            "rotate-const-compose",
+            "rotate-by-width",
+            "commute-sat-add",
+            "sat-add-no-overflow",
+            "sat-sub-signed-no-overflow",
+            "greater-unsigned-to-signed",
+            "greater-equal-signed-to-unsigned",
+            "greater-narrow",
+        ] {
+            assert!(report.checked(name) > 0, "{name} was never checked");
+        }
+    }
+
+    #[test]
+    fn test_fuzz_zero_max_width() {
+        let opts = FuzzOptions {
+            max_width: 0,
+            assignments: 16,
+            ..Default::default()
+        };
+        let report = fuzz_rewrites(&create_rewrites(), &opts);
+        assert!(report.mismatches.is_empty(), "{:?}", report.mismatches);
+    }
+
+    #[test]
+    fn test_fuzz_finds_wrong_rule() {
+        let wrong = ArithRewrite::new(
+            "commute-sub",
+            "(- ?wo ?wa ?sa ?a ?wb ?sb ?b)",
+            "(- ?wo ?wb ?sb ?b ?wa ?sa ?a)",
+            None,
+        );
+        let report = fuzz_rewrites(&[wrong.clone()], &FuzzOptions::default());
+        assert_eq!(report.mismatches.len(), 1);
+        let mismatch = &report.mismatches[0];
+        assert_eq!(mismatch.rule, "commute-sub");
+        let test = mismatch.to_rust_test("fuzz_commute_sub");
+        assert!(test.contains("fn fuzz_commute_sub()"), "{test}");
+        assert!(test.contains("\"?wo\""), "{test}");
+        // the reported instance can be replayed
+        let assignment: Vec<(String, WidthInt)> = mismatch
+            .assignment
+            .iter()
+            .map(|(v, w)| (v.to_string(), *w))
+            .collect();
+        let assignment: Vec<(&str, WidthInt)> =
+            assignment.iter().map(|(v, w)| (v.as_str(), *w)).collect();
+        let inputs: Vec<(&str, String)> = mismatch
+            .inputs
+            .iter()
+            .map(|(n, v)| (n.as_str(), v.to_bit_str()))
+            .collect();
+        let inputs: Vec<(&str, &str)> = inputs.iter().map(|(n, v)| (*n, v.as_str())).collect();
+        assert_eq!(
+            check_rule_instance(&wrong, &assignment, &[], &inputs),
+            Err(mismatch.clone())
+        );
+        assert!(!mismatch.lhs.is_equal(&mismatch.rhs));
+    }
+}
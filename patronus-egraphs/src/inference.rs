@@ -0,0 +1,272 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+/*!
+# Rule Inference
+
+Enumerates small arithmetic terms in which all operands have the same width `?w`.
+Terms are grouped by their values on random inputs. Every term that falls into the
+class of a smaller term is a rewrite candidate, which is proven with an SMT solver
+before it is turned into an [`ArithRewrite`]. Similar to [Ruler](https://doi.org/10.1145/3485496),
+only terms built from class representatives are enumerated, since all others can
+already be simplified by one of the rules found earlier.
+
+Rules are proven for every width in [`InferenceOptions::verify_widths`], not for all widths.
+
+!*/
+
+use crate::{from_arith, Arith, ArithRewrite};
+use baa::{BitVecOps, BitVecValue, Value};
+use egg::RecExpr;
+use patronus::equiv::{prove_equiv, EquivBackend, EquivError, EquivOptions, EquivResult};
+use patronus::expr::{eval_expr, Context, ExprRef, WidthInt};
+use patronus::random::{default_seed, new_rng};
+use patronus::smt::{SmtLibSolver, BITWUZLA};
+use rustc_hash::FxHashMap;
+
+/// Operators that may appear in inferred rules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InferOp {
+    Add,
+    Sub,
+    Mul,
+    LeftShift,
+}
+
+impl InferOp {
+    fn symbol(&self) -> &'static str {
+        match self {
+            InferOp::Add => "+",
+            InferOp::Sub => "-",
+            InferOp::Mul => "*",
+            InferOp::LeftShift => "<<",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InferenceOptions {
+    pub ops: Vec<InferOp>,
+    /// number of distinct variables, called `?a`, `?b`, ...
+    pub vars: usize,
+    pub constants: Vec<u64>,
+    /// maximum number of operators and leaves in the left-hand side
+    pub max_size: usize,
+    /// width at which candidates are tested on random inputs
+    pub test_width: WidthInt,
+    pub tests: usize,
+    pub verify_widths: Vec<WidthInt>,
+    pub solver: SmtLibSolver,
+    pub seed: u64,
+}
+
+impl Default for InferenceOptions {
+    fn default() -> Self {
+        Self {
+            ops: vec![InferOp::Add, InferOp::Sub, InferOp::Mul, InferOp::LeftShift],
+            vars: 2,
+            constants: vec![0, 1],
+            max_size: 3,
+            test_width: 8,
+            tests: 32,
+            verify_widths: (1..=8).collect(),
+            solver: BITWUZLA,
+            seed: default_seed(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Term {
+    Var(usize),
+    Const(u64),
+    Op(InferOp, Box<Term>, Box<Term>),
+}
+
+impl Term {
+    /// Bit set of all variables used in the term.
+    fn vars(&self) -> u64 {
+        match self {
+            Term::Var(v) => 1 << v,
+            Term::Const(_) => 0,
+            Term::Op(_, a, b) => a.vars() | b.vars(),
+        }
+    }
+
+    /// Serializes the term as a pattern if `width` is `None` or as a concrete expression.
+    fn to_arith_str(&self, width: Option<WidthInt>) -> String {
+        match self {
+            Term::Var(v) => {
+                let name = (b'a' + *v as u8) as char;
+                if width.is_some() {
+                    name.to_string()
+                } else {
+                    format!("?{name}")
+                }
+            }
+            Term::Const(c) => c.to_string(),
+            Term::Op(op, a, b) => {
+                let w = width.map_or("?w".to_string(), |w| format!("W<{w}>"));
+                format!(
+                    "({} {w} {w} unsign {} {w} unsign {})",
+                    op.symbol(),
+                    a.to_arith_str(width),
+                    b.to_arith_str(width)
+                )
+            }
+        }
+    }
+
+    fn to_expr(&self, ctx: &mut Context, width: WidthInt) -> ExprRef {
+        match self {
+            Term::Var(_) => {
+                let name = self.to_arith_str(Some(width));
+                ctx.bv_symbol(&name, width)
+            }
+            Term::Const(c) => {
+                let mask = if width < u64::BITS {
+                    (1u64 << width) - 1
+                } else {
+                    u64::MAX
+                };
+                ctx.bit_vec_val(c & mask, width)
+            }
+            Term::Op(..) => {
+                // going through the arithmetic IR ensures that we verify the semantics of the rule
+                let expr: RecExpr<Arith> = self.to_arith_str(Some(width)).parse().unwrap();
+                from_arith(ctx, &expr)
+            }
+        }
+    }
+}
+
+/// Enumerates, tests and proves rewrites over the operators in `opts`.
+pub fn infer_rewrites(opts: &InferenceOptions) -> Result<Vec<ArithRewrite>, EquivError> {
+    let mut ctx = Context::default();
+    let mut rng = new_rng(opts.seed);
+    let tests: Vec<Vec<(ExprRef, BitVecValue)>> = (0..opts.tests)
+        .map(|_| {
+            (0..opts.vars)
+                .map(|v| {
+                    let var = Term::Var(v).to_expr(&mut ctx, opts.test_width);
+                    (var, BitVecValue::random(&mut rng, opts.test_width))
+                })
+                .collect()
+        })
+        .collect();
+    let equiv_opts = EquivOptions {
+        backend: EquivBackend::Smt(opts.solver.clone()),
+        ..Default::default()
+    };
+
+    // class representatives, grouped by size
+    let mut reps: Vec<Vec<Term>> = vec![vec![]; opts.max_size + 1];
+    let mut classes: FxHashMap<Vec<u64>, Term> = FxHashMap::default();
+    let mut rules = vec![];
+    for size in 1..=opts.max_size {
+        let candidates: Vec<Term> = if size == 1 {
+            (0..opts.vars)
+                .map(Term::Var)
+                .chain(opts.constants.iter().map(|&c| Term::Const(c)))
+                .collect()
+        } else {
+            let mut out = vec![];
+            for &op in opts.ops.iter() {
+                for size_a in 1..size - 1 {
+                    for a in reps[size_a].iter() {
+                        for b in reps[size - 1 - size_a].iter() {
+                            out.push(Term::Op(op, Box::new(a.clone()), Box::new(b.clone())));
+                        }
+                    }
+                }
+            }
+            out
+        };
+
+        for term in candidates {
+            let expr = term.to_expr(&mut ctx, opts.test_width);
+            let fingerprint: Vec<u64> = tests
+                .iter()
+                .map(|t| match eval_expr(&ctx, t.as_slice(), expr) {
+                    Value::BitVec(v) => v.to_u64().unwrap(),
+                    Value::Array(_) => unreachable!("terms are bit-vectors"),
+                })
+                .collect();
+            let Some(rep) = classes.get(&fingerprint) else {
+                classes.insert(fingerprint, term.clone());
+                reps[size].push(term);
+                continue;
+            };
+            // the rhs may not introduce new variables
+            if rep.vars() & !term.vars() != 0 {
+                continue;
+            }
+            if proven(&mut ctx, &term, rep, opts, &equiv_opts)? {
+                let name = format!("inferred-{}", rules.len());
+                rules.push(ArithRewrite::new(
+                    &name,
+                    &term.to_arith_str(None),
+                    &rep.to_arith_str(None),
+                    None,
+                ));
+            } else {
+                // random testing was wrong, the term is new
+                reps[size].push(term);
+            }
+        }
+    }
+    Ok(rules)
+}
+
+fn proven(
+    ctx: &mut Context,
+    lhs: &Term,
+    rhs: &Term,
+    opts: &InferenceOptions,
+    equiv_opts: &EquivOptions,
+) -> Result<bool, EquivError> {
+    for &width in opts.verify_widths.iter() {
+        let (a, b) = (lhs.to_expr(ctx, width), rhs.to_expr(ctx, width));
+        if let EquivResult::NotEquivalent(_) = prove_equiv(ctx, a, b, equiv_opts)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_infer_add_sub() {
+        let opts = InferenceOptions {
+            ops: vec![InferOp::Add, InferOp::Sub],
+            constants: vec![0],
+            verify_widths: vec![1, 4, 8],
+            ..Default::default()
+        };
+        let rules = infer_rewrites(&opts).unwrap();
+        let patterns: Vec<(String, String)> = rules
+            .iter()
+            .map(|r| {
+                let (lhs, rhs) = r.patterns();
+                (lhs.to_string(), rhs.to_string())
+            })
+            .collect();
+        let has = |lhs: &str, rhs: &str| patterns.iter().any(|(l, r)| l == lhs && r == rhs);
+        assert!(has("(+ ?w ?w unsign ?a ?w unsign 0)", "?a"));
+        assert!(has("(- ?w ?w unsign ?a ?w unsign ?a)", "0"));
+        assert!(has(
+            "(+ ?w ?w unsign ?b ?w unsign ?a)",
+            "(+ ?w ?w unsign ?a ?w unsign ?b)"
+        ));
+        // subtraction does not commute
+        assert!(!patterns
+            .iter()
+            .any(|(l, _)| l == "(- ?w ?w unsign ?b ?w unsign ?a)"));
+        for rule in rules.iter() {
+            assert_eq!(rule.to_egg().len(), 1);
+        }
+    }
+}
@@ -1,10 +1,58 @@
 // Copyright 2024 Cornell University
 // released under BSD 3-Clause License
 // author: Kevin Laeufer <laeufer@cornell.edu>
+mod apply;
 mod arithmetic;
+mod batch;
+#[cfg(feature = "bench")]
+mod bench;
+mod builder;
+mod cache;
+mod catalog;
+mod compare;
+mod compose;
+mod conditions;
+mod cse;
 mod dot;
+mod equivalence;
+mod fusion;
+mod fuzz;
+mod inference;
+mod limits;
+mod lint;
+mod pipeline;
 mod rewrites;
+mod schedule;
+mod serialize;
+mod session;
+mod tiered;
+mod trace;
+mod widths;
 
+pub use apply::*;
 pub use arithmetic::*;
+pub use batch::*;
+#[cfg(feature = "bench")]
+pub use bench::*;
+pub use builder::*;
+pub use cache::*;
+pub use catalog::*;
+pub use compare::*;
+pub use compose::*;
+pub use conditions::*;
+pub use cse::*;
 pub use dot::*;
+pub use equivalence::*;
+pub use fusion::*;
+pub use fuzz::*;
+pub use inference::*;
+pub use limits::*;
+pub use lint::*;
+pub use pipeline::*;
 pub use rewrites::*;
+pub use schedule::*;
+pub use serialize::*;
+pub use session::*;
+pub use tiered::*;
+pub use trace::*;
+pub use widths::*;
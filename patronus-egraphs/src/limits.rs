@@ -0,0 +1,281 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+/*!
+# Resource Limits
+
+Large datapaths can make an e-graph grow until the process is killed. We approximate the
+memory used by the e-graph from its number of nodes and classes and stop saturating once
+[`EGraphConfig::memory_limit`] would be exceeded. An equivalence check that runs into the
+limit reports that equivalence could not be established, instead of failing.
+Independent of any limit, a pairwise check stops right after the iteration that merges both
+expressions.
+
+!*/
+
+use crate::{configure_runner, with_egraph_hook, Arith, ArithRewrite, EGraph, HookAction, Rewrite};
+use egg::{Id, RecExpr, StopReason};
+use patronus::config::EGraphConfig;
+use patronus::mc::{CheckKind, CheckStatus, ResultRecord};
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+/// Approximate number of bytes needed to store an e-node. Every node is kept in its
+/// class, in the hash-cons table and in the parent list of each child.
+pub fn bytes_per_node() -> usize {
+    3 * std::mem::size_of::<Arith>() + 4 * std::mem::size_of::<Id>()
+}
+
+/// Approximate number of bytes needed for the bookkeeping of an e-class.
+pub fn bytes_per_class() -> usize {
+    std::mem::size_of::<egg::EClass<Arith, Option<patronus::expr::WidthInt>>>()
+        + 2 * std::mem::size_of::<Id>()
+}
+
+/// Approximate memory used by `egraph` in bytes.
+pub fn estimate_memory(egraph: &EGraph) -> usize {
+    egraph.total_number_of_nodes() * bytes_per_node()
+        + egraph.number_of_classes() * bytes_per_class()
+}
+
+/// Turns the memory limit into a node limit. egg checks the node limit after every rule
+/// application, which lets us stop before the memory is exhausted.
+pub fn with_memory_limit(
+    runner: egg::Runner<Arith, crate::WidthConstantFold>,
+    limit_bytes: usize,
+) -> egg::Runner<Arith, crate::WidthConstantFold> {
+    // every node may create a class
+    runner.with_node_limit(limit_bytes / (bytes_per_node() + bytes_per_class()))
+}
+
+/// Outcome of an equivalence check with the e-graph.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EGraphEquivResult {
+    Equivalent,
+    /// All rules were applied without merging both expressions.
+    Saturated,
+    /// Saturation stopped because the e-graph reached the memory limit.
+    ResourceLimit {
+        estimated_bytes: usize,
+    },
+    IterationLimit(usize),
+    /// Saturation stopped for another reason, e.g., a time limit or cancellation.
+    Stopped(String),
+}
+
+impl EGraphEquivResult {
+    pub fn is_equivalent(&self) -> bool {
+        matches!(self, EGraphEquivResult::Equivalent)
+    }
+}
+
+impl Display for EGraphEquivResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EGraphEquivResult::Equivalent => write!(f, "equivalent"),
+            EGraphEquivResult::Saturated => write!(f, "equivalence not established, saturated"),
+            EGraphEquivResult::ResourceLimit { estimated_bytes } => write!(
+                f,
+                "equivalence not established, resource limit (~{} KiB)",
+                estimated_bytes / 1024
+            ),
+            EGraphEquivResult::IterationLimit(n) => {
+                write!(f, "equivalence not established, iteration limit ({n})")
+            }
+            EGraphEquivResult::Stopped(reason) => {
+                write!(f, "equivalence not established, {reason}")
+            }
+        }
+    }
+}
+
+/// Result of a pairwise equivalence check with statistics about the run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EquivalenceRun {
+    pub result: EGraphEquivResult,
+    /// number of iterations that egg ran
+    pub iterations: usize,
+    /// iteration after which both expressions were in the same e-class, `0` if they were
+    /// equal from the start
+    pub merged_at: Option<usize>,
+    /// names of all rules that were applied at least once
+    pub applied: Vec<String>,
+}
+
+impl EquivalenceRun {
+    /// Normalized record of the run. Saturation cannot show that two expressions differ, thus
+    /// a run that does not merge them has an unknown status.
+    pub fn to_record(&self, design: &str, time: Duration, config: &EGraphConfig) -> ResultRecord {
+        let status = if self.result.is_equivalent() {
+            CheckStatus::Pass
+        } else {
+            CheckStatus::Unknown
+        };
+        ResultRecord::new(CheckKind::EGraphEquiv, "egraph", design, status)
+            .with_detail(format!(
+                "{} after {} iterations",
+                self.result, self.iterations
+            ))
+            .with_time("saturation", time)
+            .with_config(config)
+    }
+}
+
+/// Saturates an e-graph containing `lhs` and `rhs` within the limits of `config`.
+/// Saturation stops as soon as both expressions are merged.
+pub fn check_equivalence(
+    lhs: &RecExpr<Arith>,
+    rhs: &RecExpr<Arith>,
+    rules: &[ArithRewrite],
+    config: &EGraphConfig,
+) -> EGraphEquivResult {
+    check_equivalence_run(lhs, rhs, rules, config).result
+}
+
+/// Same as [`check_equivalence`], but also reports when both expressions were merged.
+pub fn check_equivalence_run(
+    lhs: &RecExpr<Arith>,
+    rhs: &RecExpr<Arith>,
+    rules: &[ArithRewrite],
+    config: &EGraphConfig,
+) -> EquivalenceRun {
+    let egg_rules: Vec<Rewrite> = rules.iter().flat_map(|r| r.to_egg()).collect();
+    let runner = configure_runner(egg::Runner::default(), config);
+    check_equivalence_with_runner(runner, lhs, rhs, &egg_rules)
+}
+
+/// Same as [`check_equivalence`], but with rules that were already converted for egg, which
+/// allows them to be shared between many checks.
+pub(crate) fn check_equivalence_with_egg_rules(
+    lhs: &RecExpr<Arith>,
+    rhs: &RecExpr<Arith>,
+    egg_rules: &[Rewrite],
+    config: &EGraphConfig,
+) -> EGraphEquivResult {
+    let runner = configure_runner(egg::Runner::default(), config);
+    check_equivalence_with_runner(runner, lhs, rhs, egg_rules).result
+}
+
+/// Saturates with a runner that was already configured, e.g., with an additional time limit.
+pub(crate) fn check_equivalence_with_runner(
+    runner: egg::Runner<Arith, crate::WidthConstantFold>,
+    lhs: &RecExpr<Arith>,
+    rhs: &RecExpr<Arith>,
+    egg_rules: &[Rewrite],
+) -> EquivalenceRun {
+    let runner = runner.with_expr(lhs).with_expr(rhs);
+    let merged = |egraph: &EGraph, roots: &[Id]| egraph.find(roots[0]) == egraph.find(roots[1]);
+    if merged(&runner.egraph, &runner.roots) {
+        return EquivalenceRun {
+            result: EGraphEquivResult::Equivalent,
+            iterations: 0,
+            merged_at: Some(0),
+            applied: vec![],
+        };
+    }
+    // stop as soon as both expressions are in the same class
+    let runner = with_egraph_hook(runner, move |_, egraph, roots| {
+        if merged(egraph, roots) {
+            HookAction::Stop("equivalent".to_string())
+        } else {
+            HookAction::Continue
+        }
+    })
+    .run(egg_rules);
+    let iterations = runner.iterations.len();
+    let mut applied: Vec<String> = vec![];
+    for (rule, _) in runner.iterations.iter().flat_map(|i| i.applied.iter()) {
+        if !applied.iter().any(|a| a == rule.as_str()) {
+            applied.push(rule.to_string());
+        }
+    }
+    if merged(&runner.egraph, &runner.roots) {
+        // the merge happened in the last iteration, either the hook stopped the runner right
+        // after it, or the iteration also ran into a limit
+        EquivalenceRun {
+            result: EGraphEquivResult::Equivalent,
+            iterations,
+            merged_at: Some(iterations),
+            applied,
+        }
+    } else {
+        EquivalenceRun {
+            result: stop_result(&runner),
+            iterations,
+            merged_at: None,
+            applied,
+        }
+    }
+}
+
+/// Explains why a finished runner stopped before establishing equivalence.
+pub(crate) fn stop_result(
+    runner: &egg::Runner<Arith, crate::WidthConstantFold>,
+) -> EGraphEquivResult {
+    match runner.stop_reason.clone().expect("runner has finished") {
+        StopReason::Saturated => EGraphEquivResult::Saturated,
+        StopReason::NodeLimit(_) => EGraphEquivResult::ResourceLimit {
+            estimated_bytes: estimate_memory(&runner.egraph),
+        },
+        StopReason::IterationLimit(n) => EGraphEquivResult::IterationLimit(n),
+        StopReason::TimeLimit(secs) => EGraphEquivResult::Stopped(format!("time limit ({secs}s)")),
+        StopReason::Other(reason) => EGraphEquivResult::Stopped(reason),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arithmetic::verification_fig_1;
+    use crate::{create_rewrites, to_arith};
+    use patronus::expr::Context;
+
+    #[test]
+    fn test_memory_limit() {
+        let mut ctx = Context::default();
+        let (spec, implementation) = verification_fig_1(&mut ctx);
+        let (spec, implementation) = (
+            to_arith(&ctx, spec).unwrap(),
+            to_arith(&ctx, implementation).unwrap(),
+        );
+        let rules = create_rewrites();
+        let res = check_equivalence(&spec, &implementation, &rules, &EGraphConfig::default());
+        assert_eq!(res, EGraphEquivResult::Equivalent);
+
+        let tiny = EGraphConfig {
+            memory_limit: Some(4 * 1024),
+            ..Default::default()
+        };
+        let res = check_equivalence(&spec, &implementation, &rules, &tiny);
+        assert!(
+            matches!(res, EGraphEquivResult::ResourceLimit { .. }),
+            "{res}"
+        );
+        assert!(res.to_string().contains("resource limit"));
+    }
+
+    #[test]
+    fn test_stop_when_merged() {
+        let mut ctx = Context::default();
+        let (spec, implementation) = verification_fig_1(&mut ctx);
+        let (spec, implementation) = (
+            to_arith(&ctx, spec).unwrap(),
+            to_arith(&ctx, implementation).unwrap(),
+        );
+        let rules = create_rewrites();
+        let config = EGraphConfig::default();
+        let run = check_equivalence_run(&spec, &implementation, &rules, &config);
+        assert_eq!(run.result, EGraphEquivResult::Equivalent);
+        let merged_at = run.merged_at.unwrap();
+        assert!(merged_at > 0);
+        // no iterations are wasted after the merge
+        assert_eq!(merged_at, run.iterations);
+        let record = run.to_record("fig1", Duration::ZERO, &config);
+        assert_eq!(record.status, CheckStatus::Pass);
+        assert_eq!(record.kind, CheckKind::EGraphEquiv);
+
+        let same = check_equivalence_run(&spec, &spec, &rules, &config);
+        assert_eq!(same.merged_at, Some(0));
+        assert_eq!(same.iterations, 0);
+    }
+}
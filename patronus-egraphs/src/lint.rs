@@ -0,0 +1,256 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+/*!
+# Linting Arithmetic Expressions
+
+Rules are validated when they are created, but expressions that are constructed by hand
+or deserialized can violate the conventions of the [`Arith`] language in ways that only
+surface deep inside the e-graph analysis or [`crate::from_arith`]. [`lint_arith`] checks
+that every width and sign argument is a constant of the right kind, that annotated operand
+widths agree with the width of the operand and that every symbol is used with a single width.
+Errors refer to nodes by their index in the [`RecExpr`].
+
+!*/
+
+use crate::arithmetic::{eval_width_left_shift, eval_width_max_plus_1};
+use crate::rewrites::{child_kinds, operand_width_ids};
+use crate::{Arith, VarKind};
+use egg::{Language, RecExpr};
+use patronus::expr::WidthInt;
+use rustc_hash::FxHashMap;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ArithLintError {
+    #[error("the expression is empty")]
+    Empty,
+    #[error("node {node} `{expr}`: child {child} does not refer to an earlier node")]
+    ForwardReference {
+        node: usize,
+        expr: String,
+        child: usize,
+    },
+    #[error("node {node} `{expr}`: argument {position} (node {child} `{found}`) needs to be a {expected}")]
+    KindMismatch {
+        node: usize,
+        expr: String,
+        position: usize,
+        child: usize,
+        found: String,
+        expected: VarKind,
+    },
+    #[error("node {node} `{expr}`: operand (node {operand}) is annotated with width {annotated}, but has width {actual}")]
+    InconsistentWidth {
+        node: usize,
+        expr: String,
+        operand: usize,
+        annotated: WidthInt,
+        actual: WidthInt,
+    },
+    #[error("node {node} `{expr}`: result width {width} should be {expected}")]
+    OutputWidth {
+        node: usize,
+        expr: String,
+        width: WidthInt,
+        expected: WidthInt,
+    },
+    #[error("node {node}: symbol `{name}` is used with width {width} and with width {other}")]
+    SymbolWidth {
+        node: usize,
+        name: String,
+        width: WidthInt,
+        other: WidthInt,
+    },
+    #[error("the root (node {node} `{expr}`) is a {found}, not a value")]
+    Root {
+        node: usize,
+        expr: String,
+        found: VarKind,
+    },
+}
+
+/// Checks that width and sign annotations of `expr` are well-formed and consistent.
+pub fn lint_arith(expr: &RecExpr<Arith>) -> Result<(), ArithLintError> {
+    let nodes = expr.as_ref();
+    // kind and, if known, the width constant or result width of every node
+    let mut info: Vec<(VarKind, Option<WidthInt>)> = Vec::with_capacity(nodes.len());
+    let mut symbols: FxHashMap<&str, WidthInt> = FxHashMap::default();
+    for (node, e) in nodes.iter().enumerate() {
+        for (position, (&child, expected)) in e.children().iter().zip(child_kinds(e)).enumerate() {
+            let child = usize::from(child);
+            if child >= node {
+                return Err(ArithLintError::ForwardReference {
+                    node,
+                    expr: e.to_string(),
+                    child,
+                });
+            }
+            if info[child].0 != expected {
+                return Err(ArithLintError::KindMismatch {
+                    node,
+                    expr: e.to_string(),
+                    position,
+                    child,
+                    found: nodes[child].to_string(),
+                    expected,
+                });
+            }
+        }
+        // all width arguments are known to be width constants at this point
+        let width = |ii: usize| info[usize::from(e.children()[ii])].1.unwrap();
+
+        for (width_id, operand) in operand_width_ids(e) {
+            let annotated = info[width_id].1.unwrap();
+            if let Some(actual) = info[operand].1 {
+                if actual != annotated {
+                    return Err(ArithLintError::InconsistentWidth {
+                        node,
+                        expr: e.to_string(),
+                        operand,
+                        annotated,
+                        actual,
+                    });
+                }
+            }
+            if let Arith::Symbol(name) = &nodes[operand] {
+                let other = *symbols.entry(name.as_str()).or_insert(annotated);
+                if other != annotated {
+                    return Err(ArithLintError::SymbolWidth {
+                        node,
+                        name: name.clone(),
+                        width: annotated,
+                        other,
+                    });
+                }
+            }
+        }
+
+        let expected_output = match e {
+            // w, w_a, a, w_b, b
+            Arith::Concat(_) => Some(width(1).saturating_add(width(3))),
+            // w, n, w_a, a
+            Arith::Repeat(_) => Some(width(1).saturating_mul(width(2))),
+            _ => None,
+        };
+        if let Some(expected) = expected_output {
+            if width(0) != expected {
+                return Err(ArithLintError::OutputWidth {
+                    node,
+                    expr: e.to_string(),
+                    width: width(0),
+                    expected,
+                });
+            }
+        }
+
+        let entry = match e {
+            Arith::Width(w) => (VarKind::Width, Some((*w).into())),
+            Arith::WidthMaxPlus1(_) => (
+                VarKind::Width,
+                Some(eval_width_max_plus_1(width(0), width(1))),
+            ),
+            Arith::WidthLeftShift(_) => (
+                VarKind::Width,
+                Some(eval_width_left_shift(width(0), width(1))),
+            ),
+            Arith::WidthAdd(_) => (VarKind::Width, Some(width(0).saturating_add(width(1)))),
+            Arith::WidthMul(_) => (VarKind::Width, Some(width(0).saturating_mul(width(1)))),
+            Arith::Sign(_) => (VarKind::Sign, None),
+            Arith::Symbol(name) => (VarKind::Value, symbols.get(name.as_str()).copied()),
+            Arith::Const(_) => (VarKind::Value, None),
+            Arith::Greater(_) | Arith::GreaterEqual(_) => (VarKind::Value, Some(1)),
+            // the result width is always the first child
+            _ => (VarKind::Value, Some(width(0))),
+        };
+        info.push(entry);
+    }
+
+    let node = nodes.len().checked_sub(1).ok_or(ArithLintError::Empty)?;
+    match info[node].0 {
+        VarKind::Value => Ok(()),
+        found => Err(ArithLintError::Root {
+            node,
+            expr: nodes[node].to_string(),
+            found,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::to_arith;
+    use patronus::expr::Context;
+
+    fn lint(src: &str) -> Result<(), ArithLintError> {
+        lint_arith(&src.parse().unwrap())
+    }
+
+    #[test]
+    fn test_lint_arith() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("a", 4);
+        let b = ctx.bv_symbol("b", 8);
+        let e = ctx.build(|c| c.mul(c.zero_extend(a, 4), c.sub(b, c.zero_extend(a, 4))));
+        assert_eq!(lint_arith(&to_arith(&ctx, e).unwrap()), Ok(()));
+        assert_eq!(
+            lint("(+ W<8> (max+1 W<2> W<3>) unsign a W<2> unsign b)"),
+            Ok(())
+        );
+
+        // node 0 is the symbol `a`, which cannot be used as a width
+        assert_eq!(
+            lint("(+ a W<4> unsign a W<4> unsign b)"),
+            Err(ArithLintError::KindMismatch {
+                node: 7,
+                expr: "+".to_string(),
+                position: 0,
+                child: 0,
+                found: "a".to_string(),
+                expected: VarKind::Width,
+            })
+        );
+        assert!(matches!(
+            lint("(+ W<8> W<4> W<4> a W<4> unsign b)"),
+            Err(ArithLintError::KindMismatch {
+                position: 2,
+                expected: VarKind::Sign,
+                ..
+            })
+        ));
+        // the inner addition has 8 bits, but is annotated as a 4-bit operand
+        assert!(matches!(
+            lint("(+ W<8> W<4> unsign (+ W<8> W<4> unsign a W<4> unsign b) W<4> unsign b)"),
+            Err(ArithLintError::InconsistentWidth {
+                annotated: 4,
+                actual: 8,
+                ..
+            })
+        ));
+        assert!(matches!(
+            lint("(+ W<8> W<4> unsign a W<8> unsign a)"),
+            Err(ArithLintError::SymbolWidth {
+                width: 8,
+                other: 4,
+                ..
+            })
+        ));
+        assert!(matches!(
+            lint("(concat W<8> W<4> a W<2> b)"),
+            Err(ArithLintError::OutputWidth {
+                width: 8,
+                expected: 6,
+                ..
+            })
+        ));
+        assert!(matches!(
+            lint("(max+1 W<2> W<3>)"),
+            Err(ArithLintError::Root {
+                node: 2,
+                found: VarKind::Width,
+                ..
+            })
+        ));
+        assert_eq!(lint_arith(&RecExpr::default()), Err(ArithLintError::Empty));
+    }
+}
@@ -0,0 +1,306 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+/*!
+# Optimization Pipeline
+
+Strings together the engines of patronus and this crate into a single flow:
+
+1. **load** a btor2 file
+2. **simplify** the system with the passes enabled in the [`Config`]
+3. **optimize** every maximal datapath expression that can be represented in the
+   arithmetic IR with an e-graph, see [`EqualityRewriter`]
+4. **verify** that the optimized system is equivalent to the loaded one, by bounded model
+   checking of a miter with the embedded SAT solver up to `bmc.max_bound`
+5. **export** the optimized system as btor2
+
+Hooks can be registered for every [`Stage`]. They run after the stage and may inspect or
+modify the system, e.g., to print statistics or to write the result in another format.
+Simplification, optimization and verification can be skipped. Verification compares all
+outputs and bad states, it fails for systems that cannot be bit-blasted, e.g., because they
+contain arrays or divisions.
+
+!*/
+
+use crate::{create_rewrites_from_config, to_arith, ArithRewrite, EGraphError, EqualityRewriter};
+use patronus::btor2;
+use patronus::config::Config;
+use patronus::equiv::{latency_miter, MiterError};
+use patronus::expr::{Context, ExprRef, ForEachChild};
+use patronus::mc::{check_with_sat, ModelCheckResult};
+use patronus::sat::BlastError;
+use patronus::system::{PassManager, TransitionSystem};
+use rustc_hash::FxHashSet;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Prefix of the outputs that expose bad states to the equivalence check.
+const BAD_OUTPUT_PREFIX: &str = "pipeline.bad_";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stage {
+    Load,
+    Simplify,
+    Optimize,
+    Verify,
+    Export,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PipelineError {
+    #[error("failed to parse `{0}`")]
+    Parse(PathBuf),
+    #[error(transparent)]
+    EGraph(#[from] EGraphError),
+    #[error(transparent)]
+    Miter(#[from] MiterError),
+    #[error(transparent)]
+    Blast(#[from] BlastError),
+    #[error("the optimized system differs from the original in step {0}")]
+    NotEquivalent(u64),
+}
+
+pub type PipelineHook = Box<dyn FnMut(&mut Context, &mut TransitionSystem)>;
+
+pub struct PipelineResult {
+    pub ctx: Context,
+    /// the system as it was loaded
+    pub original: TransitionSystem,
+    pub sys: TransitionSystem,
+    /// number of expressions replaced by the e-graph
+    pub replaced: usize,
+    /// no difference exists up to and including this step, `None` if verification was skipped
+    pub verified_bound: Option<u64>,
+    pub btor2: String,
+    /// wall clock time of every stage that ran, in order
+    pub timings: Vec<(Stage, Duration)>,
+}
+
+pub struct Pipeline {
+    config: Config,
+    rules: Vec<ArithRewrite>,
+    skipped: FxHashSet<Stage>,
+    hooks: Vec<(Stage, PipelineHook)>,
+}
+
+impl Pipeline {
+    /// Uses the rules named in the e-graph section of `config`, or all rules if it names none.
+    pub fn new(config: Config) -> Result<Self, EGraphError> {
+        let rules = create_rewrites_from_config(&config.egraphs)?;
+        Ok(Self::with_rules(config, rules))
+    }
+
+    pub fn with_rules(config: Config, rules: Vec<ArithRewrite>) -> Self {
+        Self {
+            config,
+            rules,
+            skipped: FxHashSet::default(),
+            hooks: vec![],
+        }
+    }
+
+    /// Skips a stage. Loading and exporting cannot be skipped.
+    pub fn skip(mut self, stage: Stage) -> Self {
+        assert!(
+            !matches!(stage, Stage::Load | Stage::Export),
+            "{stage:?} cannot be skipped"
+        );
+        self.skipped.insert(stage);
+        self
+    }
+
+    /// Runs `hook` after `stage`. Hooks of the same stage run in the order they were added.
+    pub fn on(
+        mut self,
+        stage: Stage,
+        hook: impl FnMut(&mut Context, &mut TransitionSystem) + 'static,
+    ) -> Self {
+        self.hooks.push((stage, Box::new(hook)));
+        self
+    }
+
+    pub fn run_file(&mut self, path: impl AsRef<Path>) -> Result<PipelineResult, PipelineError> {
+        let start = Instant::now();
+        let path = path.as_ref();
+        let (ctx, sys) =
+            btor2::parse_file(path).ok_or_else(|| PipelineError::Parse(path.to_path_buf()))?;
+        self.run_from(ctx, sys, start)
+    }
+
+    /// Runs all stages after loading on a system that was created in another way.
+    pub fn run(
+        &mut self,
+        ctx: Context,
+        sys: TransitionSystem,
+    ) -> Result<PipelineResult, PipelineError> {
+        self.run_from(ctx, sys, Instant::now())
+    }
+
+    fn run_from(
+        &mut self,
+        mut ctx: Context,
+        mut sys: TransitionSystem,
+        start: Instant,
+    ) -> Result<PipelineResult, PipelineError> {
+        let mut timings = vec![];
+        self.finish(Stage::Load, start, &mut timings, &mut ctx, &mut sys);
+        let original = sys.clone();
+
+        if !self.skipped.contains(&Stage::Simplify) {
+            let start = Instant::now();
+            PassManager::from_config(&self.config.passes).run(&mut ctx, &mut sys);
+            self.finish(Stage::Simplify, start, &mut timings, &mut ctx, &mut sys);
+        }
+
+        let mut replaced = 0;
+        if !self.skipped.contains(&Stage::Optimize) {
+            let start = Instant::now();
+            let roots = datapath_roots(&ctx, &sys);
+            if !roots.is_empty() {
+                let rewriter =
+                    EqualityRewriter::new(&ctx, &roots, &self.rules, &self.config.egraphs)?;
+                replaced = rewriter.apply_to_system(&mut ctx, &mut sys)?;
+            }
+            self.finish(Stage::Optimize, start, &mut timings, &mut ctx, &mut sys);
+        }
+
+        let mut verified_bound = None;
+        if !self.skipped.contains(&Stage::Verify) {
+            let start = Instant::now();
+            let k_max = self.config.bmc.max_bound;
+            verify(&mut ctx, &original, &sys, k_max)?;
+            verified_bound = Some(k_max);
+            self.finish(Stage::Verify, start, &mut timings, &mut ctx, &mut sys);
+        }
+
+        let start = Instant::now();
+        let btor2 = btor2::serialize_to_str(&ctx, &sys);
+        self.finish(Stage::Export, start, &mut timings, &mut ctx, &mut sys);
+
+        Ok(PipelineResult {
+            ctx,
+            original,
+            sys,
+            replaced,
+            verified_bound,
+            btor2,
+            timings,
+        })
+    }
+
+    fn finish(
+        &mut self,
+        stage: Stage,
+        start: Instant,
+        timings: &mut Vec<(Stage, Duration)>,
+        ctx: &mut Context,
+        sys: &mut TransitionSystem,
+    ) {
+        timings.push((stage, start.elapsed()));
+        for (_, hook) in self.hooks.iter_mut().filter(|(s, _)| *s == stage) {
+            hook(ctx, sys);
+        }
+    }
+}
+
+/// Largest expressions that can be converted to the arithmetic IR. Symbols and literals are
+/// left out since there is nothing to optimize.
+fn datapath_roots(ctx: &Context, sys: &TransitionSystem) -> Vec<ExprRef> {
+    let mut roots = vec![];
+    let mut visited = FxHashSet::default();
+    let mut todo = sys.get_all_exprs();
+    while let Some(e) = todo.pop() {
+        if !visited.insert(e) || ctx[e].num_children() == 0 {
+            continue;
+        }
+        if to_arith(ctx, e).is_ok() {
+            roots.push(e);
+        } else {
+            ctx[e].for_each_child(|&c| todo.push(c));
+        }
+    }
+    roots.sort();
+    roots
+}
+
+/// Checks that `optimized` behaves like `original` for `k_max` steps.
+fn verify(
+    ctx: &mut Context,
+    original: &TransitionSystem,
+    optimized: &TransitionSystem,
+    k_max: u64,
+) -> Result<(), PipelineError> {
+    let expose_bad_states = |ctx: &mut Context, sys: &TransitionSystem| {
+        let mut sys = sys.clone();
+        for (ii, bad) in sys.bad_states.clone().into_iter().enumerate() {
+            sys.add_output(ctx, format!("{BAD_OUTPUT_PREFIX}{ii}").into(), bad);
+        }
+        sys
+    };
+    let lhs = expose_bad_states(ctx, original);
+    let rhs = expose_bad_states(ctx, optimized);
+    let miter = latency_miter(ctx, &lhs, &rhs, 0)?;
+    match check_with_sat(ctx, &miter, k_max)? {
+        ModelCheckResult::Success => Ok(()),
+        ModelCheckResult::Fail(wit) => Err(PipelineError::NotEquivalent(
+            wit.inputs.len().saturating_sub(1) as u64,
+        )),
+        ModelCheckResult::Cancelled(_) => unreachable!("the check cannot be cancelled"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use patronus::examples::alu;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_pipeline() {
+        let mut config = Config::default();
+        config.bmc.max_bound = 1;
+        let hooks_run = Rc::new(Cell::new(0));
+        let count = hooks_run.clone();
+        let (ctx, sys) = alu(4);
+        let result = Pipeline::new(config.clone())
+            .unwrap()
+            .on(Stage::Optimize, move |_, _| count.set(count.get() + 1))
+            .run(ctx, sys)
+            .unwrap();
+        assert_eq!(hooks_run.get(), 1);
+        assert_eq!(result.verified_bound, Some(1));
+        let stages: Vec<Stage> = result.timings.iter().map(|(s, _)| *s).collect();
+        assert_eq!(
+            stages,
+            [
+                Stage::Load,
+                Stage::Simplify,
+                Stage::Optimize,
+                Stage::Verify,
+                Stage::Export
+            ]
+        );
+        assert!(result.btor2.contains("output"));
+
+        // a hook that breaks the design is caught by the equivalence check
+        let (ctx, sys) = alu(4);
+        let broken = Pipeline::new(config.clone())
+            .unwrap()
+            .on(Stage::Optimize, |ctx, sys| {
+                let zero = ctx.zero(4);
+                sys.outputs[0].expr = zero;
+            })
+            .run(ctx, sys);
+        assert!(matches!(broken, Err(PipelineError::NotEquivalent(0))));
+
+        let (ctx, sys) = alu(4);
+        let unverified = Pipeline::new(config)
+            .unwrap()
+            .skip(Stage::Verify)
+            .run(ctx, sys)
+            .unwrap();
+        assert_eq!(unverified.verified_bound, None);
+        assert_eq!(unverified.timings.len(), 4);
+    }
+}
@@ -6,16 +6,21 @@
 
 We use our own custom struct to define rewrite rules. This allows us to
 introspect them in order to check re-write conditions or debug matches.
+Conditions are declarative [`WidthConstraint`]s over the width and sign variables.
 
 !*/
 
-use crate::arithmetic::{eval_width_left_shift, eval_width_max_plus_1};
-use crate::{get_const_width_or_sign, is_bin_op, Arith, EGraph, WidthConstantFold};
+use crate::{
+    get_const, get_const_width_or_sign, is_bin_op, with_memory_limit, Arith, ArithScheduler,
+    EGraph, EGraphError, Sign, WidthConstantFold, WidthConstraint,
+};
 use egg::{
-    ConditionalApplier, ENodeOrVar, Id, Language, Pattern, PatternAst, Searcher, Subst, Var,
+    Applier, ConditionalApplier, ENodeOrVar, Id, Language, Pattern, PatternAst, Searcher, Subst,
+    Symbol, Var,
 };
+use patronus::config::EGraphConfig;
 use patronus::expr::WidthInt;
-use std::cmp::max;
+use patronus::mc::{CancellationToken, ProgressObserver};
 
 /// our version of the egg re-write macro
 macro_rules! arith_rewrite {
@@ -23,14 +28,52 @@ macro_rules! arith_rewrite {
         $name:expr;
         $lhs:expr => $rhs:expr
     ) => {{
-        ArithRewrite::new::<&str>($name, $lhs, $rhs, [], None)
+        ArithRewrite::new($name, $lhs, $rhs, None)
+    }};
+    (
+        $name:expr;
+        $lhs:expr => $rhs:expr;
+        if $cond:expr
+    ) => {{
+        ArithRewrite::new($name, $lhs, $rhs, Some($cond))
+    }};
+    (
+        $name:expr;
+        $lhs:expr => $rhs:expr;
+        with $value:expr => width $width:expr
+    ) => {{
+        ArithRewrite::try_new($name, $lhs, $rhs, None, &[($value, $width)], &[])
+            .unwrap_or_else(|e| panic!("{e}"))
+    }};
+    (
+        $name:expr;
+        $lhs:expr => $rhs:expr;
+        with $value:expr => log2 $operand:expr
+    ) => {{
+        ArithRewrite::try_new(
+            $name,
+            $lhs,
+            $rhs,
+            None,
+            &[],
+            &[($value, ConstFn::Log2, $operand)],
+        )
+        .unwrap_or_else(|e| panic!("{e}"))
     }};
     (
         $name:expr;
         $lhs:expr => $rhs:expr;
-        if $vars:expr, $cond:expr
+        with $value:expr => pow2 $operand:expr
     ) => {{
-        ArithRewrite::new($name, $lhs, $rhs, $vars, Some($cond))
+        ArithRewrite::try_new(
+            $name,
+            $lhs,
+            $rhs,
+            None,
+            &[],
+            &[($value, ConstFn::Pow2, $operand)],
+        )
+        .unwrap_or_else(|e| panic!("{e}"))
     }};
 }
 
@@ -41,6 +84,69 @@ pub fn create_rewrites() -> Vec<ArithRewrite> {
         arith_rewrite!("commute-add"; "(+ ?wo ?wa ?sa ?a ?wb ?sb ?b)" => "(+ ?wo ?wb ?sb ?b ?wa ?sa ?a)"),
         // a * b => b * a
         arith_rewrite!("commute-mul"; "(* ?wo ?wa ?sa ?a ?wb ?sb ?b)" => "(* ?wo ?wb ?sb ?b ?wa ?sa ?a)"),
+        // (a + b) + c => a + (b + c)
+        arith_rewrite!("assoc-add-left";
+            "(+ ?wo ?wab ?sab (+ ?wab ?wa ?sa ?a ?wb ?sb ?b) ?wc ?sc ?c)" =>
+            // the new inner sum is computed with the output width and thus never needs an extension
+            "(+ ?wo ?wa ?sa ?a ?wo unsign (+ ?wo ?wb ?sb ?b ?wc ?sc ?c))";
+            // either the inner sum has the output width, or it cannot overflow and is extended
+            // with the same sign as its operands
+            if "?wab >= ?wo || (?wab >= max+1(?wa, ?wb) && ?sa == ?sab && ?sb == ?sab)"),
+        // a + (b + c) => (a + b) + c
+        arith_rewrite!("assoc-add-right";
+            "(+ ?wo ?wa ?sa ?a ?wbc ?sbc (+ ?wbc ?wb ?sb ?b ?wc ?sc ?c))" =>
+            "(+ ?wo ?wo unsign (+ ?wo ?wa ?sa ?a ?wb ?sb ?b) ?wc ?sc ?c)";
+            if "?wbc >= ?wo || (?wbc >= max+1(?wb, ?wc) && ?sb == ?sbc && ?sc == ?sbc)"),
+        // (a * b) * c => a * (b * c)
+        arith_rewrite!("assoc-mul-left";
+            "(* ?wo ?wab ?sab (* ?wab ?wa ?sa ?a ?wb ?sb ?b) ?wc ?sc ?c)" =>
+            "(* ?wo ?wa ?sa ?a ?wo unsign (* ?wo ?wb ?sb ?b ?wc ?sc ?c))";
+            // a product of wa and wb bits always fits into wa + wb bits
+            if "?wab >= ?wo || (?wab >= ?wa + ?wb && ?sa == ?sab && ?sb == ?sab)"),
+        // a * (b * c) => (a * b) * c
+        arith_rewrite!("assoc-mul-right";
+            "(* ?wo ?wa ?sa ?a ?wbc ?sbc (* ?wbc ?wb ?sb ?b ?wc ?sc ?c))" =>
+            "(* ?wo ?wo unsign (* ?wo ?wa ?sa ?a ?wb ?sb ?b) ?wc ?sc ?c)";
+            if "?wbc >= ?wo || (?wbc >= ?wb + ?wc && ?sb == ?sbc && ?sc == ?sbc)"),
+        // a * (b + c) => a * b + a * c
+        arith_rewrite!("distribute-mul-add";
+            "(* ?wo ?wa ?sa ?a ?wbc ?sbc (+ ?wbc ?wb ?sb ?b ?wc ?sc ?c))" =>
+            // both products are computed with the output width, any overflow is thus the same
+            // as in the product of the original sum
+            "(+ ?wo ?wo unsign (* ?wo ?wa ?sa ?a ?wb ?sb ?b) ?wo unsign (* ?wo ?wa ?sa ?a ?wc ?sc ?c))";
+            if "?wbc >= ?wo || (?wbc >= max+1(?wb, ?wc) && ?sb == ?sbc && ?sc == ?sbc)"),
+        // a * b + a * c => a * (b + c)
+        arith_rewrite!("factor-mul-add";
+            "(+ ?wo ?wab ?sab (* ?wab ?wa ?sa ?a ?wb ?sb ?b) ?wac ?sac (* ?wac ?wa ?sa ?a ?wc ?sc ?c))" =>
+            "(* ?wo ?wa ?sa ?a ?wo unsign (+ ?wo ?wb ?sb ?b ?wc ?sc ?c))";
+            // neither product may overflow, unless it already has the output width
+            if "(?wab >= ?wo || (?wab >= ?wa + ?wb && ?sa == ?sab && ?sb == ?sab)) && (?wac >= ?wo || (?wac >= ?wa + ?wc && ?sa == ?sac && ?sc == ?sac))"),
+        // a - b => a + (-b)
+        arith_rewrite!("sub-to-add-neg";
+            "(- ?wo ?wa ?sa ?a ?wb ?sb ?b)" =>
+            "(+ ?wo ?wa ?sa ?a ?wo unsign (neg ?wo ?wb ?sb ?b))"),
+        // a + (-b) => a - b
+        arith_rewrite!("add-neg-to-sub";
+            // the negation has the output width, thus its extension does not matter
+            "(+ ?wo ?wa ?sa ?a ?wo ?sn (neg ?wo ?wb ?sb ?b))" =>
+            "(- ?wo ?wa ?sa ?a ?wb ?sb ?b)"),
+        // a - (-b) => a + b
+        arith_rewrite!("sub-neg-to-add";
+            "(- ?wo ?wa ?sa ?a ?wo ?sn (neg ?wo ?wb ?sb ?b))" =>
+            "(+ ?wo ?wa ?sa ?a ?wb ?sb ?b)"),
+        // -(-a) => a
+        arith_rewrite!("neg-neg";
+            "(neg ?wo ?wo ?sn (neg ?wo ?wo ?sa ?a))" => "?a"),
+        // 0 - a => -a
+        arith_rewrite!("sub-zero-to-neg";
+            "(- ?wo ?wz ?sz 0 ?wa ?sa ?a)" =>
+            "(neg ?wo ?wa ?sa ?a)"),
+        // a + sext(-b) => a + (-b)
+        arith_rewrite!("sign-extend-neg-add";
+            "(+ ?wo ?wa ?sa ?a ?wn sign (neg ?wn ?wb ?sb ?b))" =>
+            "(+ ?wo ?wa ?sa ?a ?wo unsign (neg ?wo ?wb ?sb ?b))";
+            // the negation of a wb-bit value always fits into wb + 1 signed bits
+            if "?wn > ?wb"),
         // (a << b) << x => a << (b + c)
         arith_rewrite!("merge-left-shift";
             // we require that b, c and (b + c) are all unsigned
@@ -48,8 +154,7 @@ pub fn create_rewrites() -> Vec<ArithRewrite> {
             // the value being shifted has to be consistently signed or unsigned
             "(<< ?wo ?wab ?sa (<< ?wab ?wa ?sa ?a ?wb unsign ?b) ?wc unsign ?c)" =>
             "(<< ?wo ?wa ?sa ?a (max+1 ?wb ?wc) unsign (+ (max+1 ?wb ?wc) ?wb unsign ?b ?wc unsign ?c))";
-            // wab >= wo
-            if["?wo", "?wab"], |w| w[1] >= w[0]),
+            if "?wab >= ?wo"),
         // a << (b + c) => (a << b) << x
         arith_rewrite!("unmerge-left-shift";
             // we require that b, c and (b + c) are all unsigned
@@ -58,80 +163,306 @@ pub fn create_rewrites() -> Vec<ArithRewrite> {
             "(<< ?wo ?wa ?sa ?a ?wbc unsign (+ ?wbc ?wb unsign ?b ?wc unsign ?c))" =>
             // RHS: we set wab to the minimum not to overflow
             "(<< ?wo (wlsh ?wa ?wb) ?sa (<< (wlsh ?wa ?wb) ?wa ?sa ?a ?wb unsign ?b) ?wc unsign ?c)";
-            // ?wbc >= max(wb, wc) + 1
-            if["?wbc", "?wb", "?wc"], |w| w[0] >= (max(w[1], w[2]) + 1)),
+            if "?wbc >= max+1(?wb, ?wc)"),
+        // a * 2^k => a << k
+        arith_rewrite!("mul-pow2-to-shift";
+            // a signed constant could be negative
+            "(* ?wo ?wa ?sa ?a ?wb unsign ?c)" =>
+            // k < wb since 2^k fits into wb bits
+            "(<< ?wo ?wa ?sa ?a ?wb unsign ?k)";
+            with "?k" => log2 "?c"),
+        // a << k => a * 2^k
+        arith_rewrite!("shift-to-mul-pow2";
+            "(<< ?wo ?wa ?sa ?a ?wb unsign ?k)" =>
+            // 2^k is truncated to the output width, which does not change the product
+            "(* ?wo ?wa ?sa ?a ?wo unsign ?c)";
+            with "?c" => pow2 "?k"),
         // a * 2 <=> a + a
         arith_rewrite!("mult-to-add";
             "(* ?wo ?wa ?sa ?a ?wb ?sb 2)" =>
             "(+ ?wo ?wa ?sa ?a ?wa ?sa ?a)";
-            // (!sb && wb > 1) || (sb && wb > 2) || (wo <= wb)
-           if["?wb", "?sb", "?wo"],
-            |w| (w[1] == 0 && w[0] > 1) || (w[1] == 1 && w[0] > 2) || w[2] <= w[0]),
+            // unsign is 0, sign is 1
+            if "(?sb == 0 && ?wb > 1) || (?sb == 1 && ?wb > 2) || ?wo <= ?wb"),
         // (a * b) << c => (a << c) * b
         arith_rewrite!("left-shift-mult";
-            // TODO: currently all signs are forced to unsigned
             "(<< ?wo ?wab unsign (* ?wab ?wa unsign ?a ?wb unsign ?b) ?wc unsign ?c)" =>
             // RHS: we set wac to the minimum not to overflow
             "(* ?wo (wlsh ?wa ?wc) unsign (<< (wlsh ?wa ?wc) ?wa unsign ?a ?wc unsign ?c) ?wb unsign ?b)";
             // we want to determine that there is no overflow
             // lhs: wab >= wa + wb && wo >= wab + max_shift(wc)
             // rhs: wac >= wa + max_shift(c) && wo >= wac + wb
-            if["?wab", "?wa", "?wb", "?wo", "?wc"], |w| mul_no_ov(w[0], w[1], w[2]) && lsh_no_ov(w[3], w[0], w[4])),
+            if "?wab >= ?wa + ?wb && ?wo >= wlsh(?wab, ?wc)"),
+        // signed version of the rule above, the shift amount is always unsigned
+        arith_rewrite!("left-shift-mult-signed";
+            "(<< ?wo ?wab sign (* ?wab ?wa sign ?a ?wb sign ?b) ?wc unsign ?c)" =>
+            "(* ?wo (wlsh ?wa ?wc) sign (<< (wlsh ?wa ?wc) ?wa sign ?a ?wc unsign ?c) ?wb sign ?b)";
+            // a signed product of wa and wb bits fits into wa + wb bits,
+            // thus the same no-overflow conditions apply
+            if "?wab >= ?wa + ?wb && ?wo >= wlsh(?wab, ?wc)"),
+        // a ++ b => (a << w_b) + b
+        arith_rewrite!("concat-to-shift-add";
+            "(concat ?wo ?wa ?a ?wb ?b)" =>
+            // the shift amount w_b always fits into w_o = w_a + w_b bits
+            "(+ ?wo ?wo unsign (<< ?wo ?wa unsign ?a ?wo unsign ?n) ?wb unsign ?b)";
+            with "?n" => width "?wb"),
+        // a ++ 0 => a << w_b
+        arith_rewrite!("concat-zero-to-shift";
+            "(concat ?wo ?wa ?a ?wb 0)" =>
+            "(<< ?wo ?wa unsign ?a ?wo unsign ?n)";
+            with "?n" => width "?wb"),
+        // a ++ a => repeat(2, a)
+        arith_rewrite!("concat-to-repeat";
+            "(concat ?wo ?wa ?a ?wa ?a)" =>
+            "(repeat ?wo W<2> ?wa ?a)"),
+        // a ++ repeat(n, a) => repeat(n + 1, a)
+        arith_rewrite!("concat-repeat";
+            "(concat ?wo ?wa ?a ?wr (repeat ?wr ?wn ?wa ?a))" =>
+            "(repeat ?wo (w+ ?wn W<1>) ?wa ?a)"),
+        // repeat(n, repeat(m, a)) => repeat(n * m, a)
+        arith_rewrite!("repeat-repeat";
+            "(repeat ?wo ?wn ?wr (repeat ?wr ?wm ?wa ?a))" =>
+            "(repeat ?wo (w* ?wn ?wm) ?wa ?a)"),
+        // rol(rol(a, b), c) => rol(a, b + c)
+        arith_rewrite!("rotate-left-compose";
+            // the amount is taken modulo the width, thus the sum must not wrap
+            "(rol ?w (rol ?w ?a ?wb ?b) ?wc ?c)" =>
+            "(rol ?w ?a (max+1 ?wb ?wc) (+ (max+1 ?wb ?wc) ?wb unsign ?b ?wc unsign ?c))"),
+        // ror(ror(a, b), c) => ror(a, b + c)
+        arith_rewrite!("rotate-right-compose";
+            "(ror ?w (ror ?w ?a ?wb ?b) ?wc ?c)" =>
+            "(ror ?w ?a (max+1 ?wb ?wc) (+ (max+1 ?wb ?wc) ?wb unsign ?b ?wc unsign ?c))"),
+        // ror(rol(a, b), b) => a
+        arith_rewrite!("rotate-left-right-cancel";
+            "(ror ?w (rol ?w ?a ?wb ?b) ?wb ?b)" => "?a"),
+        // rol(ror(a, b), b) => a
+        arith_rewrite!("rotate-right-left-cancel";
+            "(rol ?w (ror ?w ?a ?wb ?b) ?wb ?b)" => "?a"),
+        // rol(rol(a, n), m) => rol(a, n + m)
+        arith_rewrite!("rotate-const-compose";
+            "(rol-const ?w ?n (rol-const ?w ?m ?a))" =>
+            "(rol-const ?w (w+ ?n ?m) ?a)"),
+        // rol(a, w) => a
+        arith_rewrite!("rotate-by-width";
+            "(rol-const ?w ?n ?a)" => "?a";
+            if "?n == ?w"),
+        // sat(a + b) => sat(b + a)
+        arith_rewrite!("commute-sat-add";
+            "(sat+ ?wo ?s ?wa ?sa ?a ?wb ?sb ?b)" => "(sat+ ?wo ?s ?wb ?sb ?b ?wa ?sa ?a)"),
+        // sat(a + b) => a + b
+        arith_rewrite!("sat-add-no-overflow";
+            // the sum of two unsigned values fits into max+1 bits
+            "(sat+ ?wo unsign ?wa unsign ?a ?wb unsign ?b)" =>
+            "(+ ?wo ?wa unsign ?a ?wb unsign ?b)";
+            if "?wo >= max+1(?wa, ?wb)"),
+        // signed version of the rule above
+        arith_rewrite!("sat-add-signed-no-overflow";
+            "(sat+ ?wo sign ?wa sign ?a ?wb sign ?b)" =>
+            "(+ ?wo ?wa sign ?a ?wb sign ?b)";
+            if "?wo >= max+1(?wa, ?wb)"),
+        // sat(a - b) => a - b
+        arith_rewrite!("sat-sub-signed-no-overflow";
+            // the unsigned version can always underflow
+            "(sat- ?wo sign ?wa sign ?a ?wb sign ?b)" =>
+            "(- ?wo ?wa sign ?a ?wb sign ?b)";
+            if "?wo >= max+1(?wa, ?wb)"),
+        // a > b => a >s b
+        arith_rewrite!("greater-unsigned-to-signed";
+            // zero-extended operands are never negative
+            "(> ?w unsign ?wa unsign ?a ?wb unsign ?b)" =>
+            "(> ?w sign ?wa unsign ?a ?wb unsign ?b)";
+            if "?w > ?wa && ?w > ?wb"),
+        // a >s b => a > b
+        arith_rewrite!("greater-signed-to-unsigned";
+            "(> ?w sign ?wa unsign ?a ?wb unsign ?b)" =>
+            "(> ?w unsign ?wa unsign ?a ?wb unsign ?b)";
+            if "?w > ?wa && ?w > ?wb"),
+        // a >= b => a >=s b
+        arith_rewrite!("greater-equal-unsigned-to-signed";
+            "(>= ?w unsign ?wa unsign ?a ?wb unsign ?b)" =>
+            "(>= ?w sign ?wa unsign ?a ?wb unsign ?b)";
+            if "?w > ?wa && ?w > ?wb"),
+        // a >=s b => a >= b
+        arith_rewrite!("greater-equal-signed-to-unsigned";
+            "(>= ?w sign ?wa unsign ?a ?wb unsign ?b)" =>
+            "(>= ?w unsign ?wa unsign ?a ?wb unsign ?b)";
+            if "?w > ?wa && ?w > ?wb"),
+        // once both operands are extended by at least one bit, the order of their values does
+        // not depend on the width of the comparison, no matter how they are extended
+        arith_rewrite!("greater-narrow";
+            "(> ?w ?s ?wa ?sa ?a ?wb ?sb ?b)" =>
+            "(> (max+1 ?wa ?wb) ?s ?wa ?sa ?a ?wb ?sb ?b)";
+            if "?w > max+1(?wa, ?wb)"),
+        arith_rewrite!("greater-equal-narrow";
+            "(>= ?w ?s ?wa ?sa ?a ?wb ?sb ?b)" =>
+            "(>= (max+1 ?wa ?wb) ?s ?wa ?sa ?a ?wb ?sb ?b)";
+            if "?w > max+1(?wa, ?wb)"),
     ]
 }
 
-/// Determines if there is no overflow possible for this addition.
-fn add_no_ov(wo: WidthInt, wa: WidthInt, wb: WidthInt) -> bool {
-    wo >= eval_width_max_plus_1(wa, wb)
-}
-
-/// Determines if there is no overflow possible for this multiplication.
-fn mul_no_ov(wo: WidthInt, wa: WidthInt, wb: WidthInt) -> bool {
-    wo >= wa + wb
-}
-
-/// Determines if there is no overflow possible for this left shift.
-fn lsh_no_ov(wo: WidthInt, wa: WidthInt, wb: WidthInt) -> bool {
-    wo >= eval_width_left_shift(wa, wb)
-}
-
+#[derive(Clone)]
 pub struct ArithRewrite {
     name: String,
     /// most general lhs pattern
     lhs: Pattern<Arith>,
     /// rhs pattern with all widths derived from the lhs, maybe be the same as rhs
     rhs_derived: Pattern<Arith>,
-    /// variables use by the condition
-    cond_vars: Vec<Var>,
     /// condition of the re_write
-    cond: Option<fn(&[WidthInt]) -> bool>,
+    cond: Option<WidthConstraint>,
+    /// rhs variables that are bound to a constant with the value of a lhs width variable
+    width_values: Vec<(Var, Var)>,
+    /// rhs variables that are bound to a constant computed from a constant lhs operand
+    const_values: Vec<(Var, ConstFn, Var)>,
+}
+
+/// Computes the value of an rhs constant from a lhs constant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConstFn {
+    /// `log2(c)`, the rule only applies if `c` is a power of two
+    Log2,
+    /// `2^c`, the rule only applies if the result fits into 64 bits
+    Pow2,
+}
+
+impl ConstFn {
+    pub fn apply(self, value: u64) -> Option<u64> {
+        match self {
+            ConstFn::Log2 => value
+                .is_power_of_two()
+                .then(|| value.trailing_zeros() as u64),
+            ConstFn::Pow2 => (value < u64::BITS as u64).then(|| 1u64 << value),
+        }
+    }
 }
 
 pub type Rewrite = egg::Rewrite<Arith, WidthConstantFold>;
 
 impl ArithRewrite {
-    fn new<S: AsRef<str>>(
+    pub(crate) fn new(name: &str, lhs: &str, rhs_derived: &str, cond: Option<&str>) -> Self {
+        Self::try_new(name, lhs, rhs_derived, cond, &[], &[]).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Parses and validates a rule. `width_values` binds rhs variables to a constant that is
+    /// equal to a lhs width, e.g., in order to shift by the width of an operand.
+    /// `const_values` binds rhs variables to a function of a constant lhs operand, the rule only
+    /// applies if the operand is a constant.
+    pub fn try_new(
         name: &str,
         lhs: &str,
         rhs_derived: &str,
-        cond_vars: impl IntoIterator<Item = S>,
-        cond: Option<fn(&[WidthInt]) -> bool>,
-    ) -> Self {
-        let cond_vars = cond_vars
-            .into_iter()
-            .map(|n| n.as_ref().parse().unwrap())
-            .collect();
-        let lhs = lhs.parse::<_>().unwrap();
-        check_width_consistency(&lhs);
-        let rhs_derived = rhs_derived.parse::<_>().unwrap();
-        check_width_consistency(&rhs_derived);
-        Self {
-            name: name.to_string(),
+        cond: Option<&str>,
+        width_values: &[(&str, &str)],
+        const_values: &[(&str, ConstFn, &str)],
+    ) -> Result<Self, RuleError> {
+        let rule = name.to_string();
+        let parse_pattern = |pattern: &str| {
+            pattern
+                .parse::<Pattern<Arith>>()
+                .map_err(|e| RuleError::Parse {
+                    rule: rule.clone(),
+                    src: pattern.to_string(),
+                    msg: e.to_string(),
+                })
+        };
+        let lhs = parse_pattern(lhs)?;
+        let rhs_derived = parse_pattern(rhs_derived)?;
+        let cond = cond
+            .map(|c| {
+                c.parse::<WidthConstraint>().map_err(|e| RuleError::Parse {
+                    rule: rule.clone(),
+                    src: c.to_string(),
+                    msg: e.to_string(),
+                })
+            })
+            .transpose()?;
+        let var = |v: &str| {
+            v.parse::<Var>().map_err(|e| RuleError::Parse {
+                rule: rule.clone(),
+                src: v.to_string(),
+                msg: e.to_string(),
+            })
+        };
+        let width_values = width_values
+            .iter()
+            .map(|(value, width)| Ok((var(value)?, var(width)?)))
+            .collect::<Result<Vec<_>, RuleError>>()?;
+        let const_values = const_values
+            .iter()
+            .map(|(value, f, operand)| Ok((var(value)?, *f, var(operand)?)))
+            .collect::<Result<Vec<_>, RuleError>>()?;
+        let rule = Self {
+            name: rule,
             lhs,
             rhs_derived,
             cond,
-            cond_vars,
+            width_values,
+            const_values,
+        };
+        rule.validate()?;
+        Ok(rule)
+    }
+
+    /// Checks that widths are consistent, that every rhs and condition variable is bound
+    /// by the lhs and that no variable is used as both a value and a width or sign.
+    fn validate(&self) -> Result<(), RuleError> {
+        let err = |kind| RuleError::Invalid {
+            rule: self.name.clone(),
+            kind,
+        };
+        check_width_consistency(&self.lhs).map_err(err)?;
+        check_width_consistency(&self.rhs_derived).map_err(err)?;
+        let lhs = var_kinds(&self.lhs).map_err(err)?;
+        let rhs = var_kinds(&self.rhs_derived).map_err(err)?;
+        let bound = |v: Var| lhs.iter().find(|(var, _)| *var == v).map(|(_, k)| *k);
+        for &(value, width) in self.width_values.iter() {
+            match bound(width) {
+                Some(VarKind::Width) => {}
+                Some(kind) => {
+                    return Err(err(InvalidRule::KindMismatch(width, kind, VarKind::Width)))
+                }
+                None => return Err(err(InvalidRule::UnboundVariable(width))),
+            }
+            if let Some(kind) = bound(value) {
+                return Err(err(InvalidRule::KindMismatch(value, kind, VarKind::Value)));
+            }
+        }
+        for &(value, _, operand) in self.const_values.iter() {
+            match bound(operand) {
+                Some(VarKind::Value) => {}
+                Some(kind) => {
+                    return Err(err(InvalidRule::KindMismatch(
+                        operand,
+                        kind,
+                        VarKind::Value,
+                    )))
+                }
+                None => return Err(err(InvalidRule::UnboundVariable(operand))),
+            }
+            if let Some(kind) = bound(value) {
+                return Err(err(InvalidRule::KindMismatch(value, kind, VarKind::Value)));
+            }
+        }
+        let derived = |var: Var| {
+            self.width_values.iter().any(|(v, _)| *v == var)
+                || self.const_values.iter().any(|(v, _, _)| *v == var)
+        };
+        for &(var, kind) in rhs.iter() {
+            let lhs_kind = match bound(var) {
+                Some(lhs_kind) => lhs_kind,
+                None if derived(var) => VarKind::Value,
+                None => return Err(err(InvalidRule::UnboundVariable(var))),
+            };
+            if lhs_kind.is_value() != kind.is_value() {
+                return Err(err(InvalidRule::KindMismatch(var, lhs_kind, kind)));
+            }
+        }
+        if let Some(cond) = &self.cond {
+            for var in cond.vars() {
+                if bound(var).map_or(true, |k| k.is_value()) {
+                    return Err(err(InvalidRule::ConditionVariable(var)));
+                }
+            }
         }
+        Ok(())
     }
 
     pub fn name(&self) -> &str {
@@ -142,43 +473,45 @@ impl ArithRewrite {
         (&self.lhs.ast, &self.rhs_derived.ast)
     }
 
+    /// Pairs of rhs variables and the lhs width variable whose value they take.
+    pub fn width_values(&self) -> &[(Var, Var)] {
+        &self.width_values
+    }
+
+    /// Rhs variables, the function that computes them and the lhs operand that it is applied to.
+    pub fn const_values(&self) -> &[(Var, ConstFn, Var)] {
+        &self.const_values
+    }
+
+    /// Returns the condition under which the rule applies, `None` for unconditional rules.
+    pub fn condition(&self) -> Option<&WidthConstraint> {
+        self.cond.as_ref()
+    }
+
     pub fn to_egg(&self) -> Vec<Rewrite> {
         // TODO: support bi-directional rules
-        if let Some(cond) = self.cond {
-            let vars: Vec<Var> = self.cond_vars.clone();
+        let applier = WidthValueApplier {
+            applier: self.rhs_derived.clone(),
+            width_values: self.width_values.clone(),
+            const_values: self.const_values.clone(),
+        };
+        if let Some(cond) = self.cond.clone() {
             let condition = move |egraph: &mut EGraph, _, subst: &Subst| {
-                let values: Vec<WidthInt> = vars
-                    .iter()
-                    .map(|v| {
-                        get_const_width_or_sign(egraph, subst[*v])
-                            .expect("failed to find constant width")
-                    })
-                    .collect();
-                cond(values.as_slice())
-            };
-            let cond_app = ConditionalApplier {
-                condition,
-                applier: self.rhs_derived.clone(),
+                // if any width is not a constant, we cannot show that the rule applies
+                cond.eval(|v| get_const_width_or_sign(egraph, subst[v]))
+                    .unwrap_or(false)
             };
+            let cond_app = ConditionalApplier { condition, applier };
             vec![Rewrite::new(self.name.clone(), self.lhs.clone(), cond_app).unwrap()]
         } else {
-            vec![Rewrite::new(
-                self.name.clone(),
-                self.lhs.clone(),
-                self.rhs_derived.clone(),
-            )
-            .unwrap()]
+            vec![Rewrite::new(self.name.clone(), self.lhs.clone(), applier).unwrap()]
         }
     }
 
     pub fn eval_condition(&self, a: &[(Var, WidthInt)]) -> bool {
-        if let Some(cond) = self.cond {
-            let values: Vec<WidthInt> = self
-                .cond_vars
-                .iter()
-                .map(|v| a.iter().find(|(k, _)| k == v).unwrap().1)
-                .collect();
-            cond(values.as_slice())
+        if let Some(cond) = &self.cond {
+            cond.eval(|v| a.iter().find(|(k, _)| *k == v).map(|(_, w)| *w))
+                .expect("all condition variables need to be assigned")
         } else {
             // unconditional rewrite
             true
@@ -208,7 +541,52 @@ impl ArithRewrite {
     }
 }
 
-fn substitution_to_assignment(
+/// Adds constants for all width and const values to the substitution before applying the rhs.
+struct WidthValueApplier {
+    applier: Pattern<Arith>,
+    width_values: Vec<(Var, Var)>,
+    const_values: Vec<(Var, ConstFn, Var)>,
+}
+
+impl Applier<Arith, WidthConstantFold> for WidthValueApplier {
+    fn apply_one(
+        &self,
+        egraph: &mut EGraph,
+        eclass: Id,
+        subst: &Subst,
+        searcher_ast: Option<&PatternAst<Arith>>,
+        rule_name: Symbol,
+    ) -> Vec<Id> {
+        if self.width_values.is_empty() && self.const_values.is_empty() {
+            return self
+                .applier
+                .apply_one(egraph, eclass, subst, searcher_ast, rule_name);
+        }
+        let mut subst = subst.clone();
+        for &(value, width) in self.width_values.iter() {
+            let Some(width) = get_const_width_or_sign(egraph, subst[width]) else {
+                return vec![];
+            };
+            let constant = egraph.add(Arith::Const(width as u64));
+            subst.insert(value, constant);
+        }
+        for &(value, f, operand) in self.const_values.iter() {
+            let Some(result) = get_const(egraph, subst[operand]).and_then(|c| f.apply(c)) else {
+                return vec![];
+            };
+            let constant = egraph.add(Arith::Const(result));
+            subst.insert(value, constant);
+        }
+        self.applier
+            .apply_one(egraph, eclass, &subst, searcher_ast, rule_name)
+    }
+
+    fn get_pattern_ast(&self) -> Option<&PatternAst<Arith>> {
+        self.applier.get_pattern_ast()
+    }
+}
+
+pub(crate) fn substitution_to_assignment(
     egraph: &EGraph,
     s: &Subst,
     pattern: &PatternAst<Arith>,
@@ -234,47 +612,298 @@ pub struct ArithMatch {
     pub cond_res: bool,
 }
 
+/// Describes how a rule treats the sign of its operands.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignAudit {
+    pub rule: String,
+    /// operands in the lhs that have to be unsigned for the rule to match
+    pub forced_unsigned: usize,
+    /// operands in the lhs that have to be signed for the rule to match
+    pub forced_signed: usize,
+    /// rules that match the same shape with different operand signs
+    pub variants: Vec<String>,
+}
+
+impl SignAudit {
+    /// A rule is unsigned-only if it never matches signed operands and there is no other rule
+    /// that covers the signed case.
+    pub fn is_unsigned_only(&self) -> bool {
+        self.forced_unsigned > 0 && self.variants.is_empty()
+    }
+}
+
+/// Reports for every rule which operand signs it supports. Shift amounts are always unsigned
+/// and thus ignored.
+pub fn audit_signs(rules: &[ArithRewrite]) -> Vec<SignAudit> {
+    let shapes: Vec<_> = rules
+        .iter()
+        .map(|r| sign_agnostic_shape(&r.lhs.ast, root_id(&r.lhs.ast), false))
+        .collect();
+    rules
+        .iter()
+        .zip(shapes.iter())
+        .map(|(rule, shape)| {
+            let (forced_unsigned, forced_signed) = count_forced_signs(&rule.lhs.ast);
+            let variants = rules
+                .iter()
+                .zip(shapes.iter())
+                .filter(|(other, other_shape)| other.name != rule.name && *other_shape == shape)
+                .map(|(other, _)| other.name.clone())
+                .collect();
+            SignAudit {
+                rule: rule.name.clone(),
+                forced_unsigned,
+                forced_signed,
+                variants,
+            }
+        })
+        .collect()
+}
+
+fn root_id(pattern: &PatternAst<Arith>) -> Id {
+    Id::from(pattern.as_ref().len() - 1)
+}
+
+fn is_shift(expr: &Arith) -> bool {
+    matches!(
+        expr,
+        Arith::LeftShift(_) | Arith::RightShift(_) | Arith::ArithmeticRightShift(_)
+    )
+}
+
+/// Renders the pattern with all operand signs replaced by a placeholder.
+fn sign_agnostic_shape(pattern: &PatternAst<Arith>, id: Id, is_sign: bool) -> String {
+    match &pattern[id] {
+        _ if is_sign => "_".to_string(),
+        ENodeOrVar::Var(v) => v.to_string(),
+        ENodeOrVar::ENode(expr) if expr.is_leaf() => expr.to_string(),
+        ENodeOrVar::ENode(expr) => {
+            let children = expr
+                .children()
+                .iter()
+                .enumerate()
+                .map(|(ii, &c)| {
+                    sign_agnostic_shape(pattern, c, is_bin_op(expr) && (ii == 2 || ii == 5))
+                })
+                .collect::<Vec<_>>();
+            format!("({expr} {})", children.join(" "))
+        }
+    }
+}
+
+/// Counts constant signs of operands, skipping over shift amounts.
+fn count_forced_signs(pattern: &PatternAst<Arith>) -> (usize, usize) {
+    let (mut unsigned, mut signed) = (0, 0);
+    let mut todo = vec![root_id(pattern)];
+    while let Some(id) = todo.pop() {
+        let ENodeOrVar::ENode(expr) = &pattern[id] else {
+            continue;
+        };
+        if !is_bin_op(expr) {
+            continue;
+        }
+        // w, w_a, s_a, a, w_b, s_b, b
+        let c = expr.children();
+        let mut operands = vec![(c[2], c[3])];
+        if !is_shift(expr) {
+            operands.push((c[5], c[6]));
+        }
+        for (sign, operand) in operands {
+            match &pattern[sign] {
+                ENodeOrVar::ENode(Arith::Sign(Sign::Unsigned)) => unsigned += 1,
+                ENodeOrVar::ENode(Arith::Sign(Sign::Signed)) => signed += 1,
+                _ => {}
+            }
+            todo.push(operand);
+        }
+    }
+    (unsigned, signed)
+}
+
 /// Checks that input and output widths of operations are consistent.
-fn check_width_consistency(pattern: &Pattern<Arith>) {
+fn check_width_consistency(pattern: &Pattern<Arith>) -> Result<(), InvalidRule> {
     let exprs = pattern.ast.as_ref();
     for e_node_or_var in exprs.iter() {
         if let ENodeOrVar::ENode(expr) = e_node_or_var {
-            if is_bin_op(expr) {
-                // w, w_a, s_a, a, w_b, s_b, b
-                let a_width_id = usize::from(expr.children()[1]);
-                let a_id = usize::from(expr.children()[3]);
-                if let Some(a_op_out_width_id) = get_output_width_id(&exprs[a_id]) {
-                    assert_eq!(
-                        a_width_id, a_op_out_width_id,
-                        "In `{expr}`, subexpression `{}` has inconsistent width: {} != {}",
-                        &exprs[a_id], &exprs[a_width_id], &exprs[a_op_out_width_id]
-                    );
+            for (width_id, op_id) in operand_width_ids(expr) {
+                if let Some(op_out_width_id) = get_output_width_id(&exprs[op_id]) {
+                    if width_id != op_out_width_id {
+                        return Err(InvalidRule::InconsistentWidth {
+                            expr: expr.to_string(),
+                            operand: exprs[op_id].to_string(),
+                            width: exprs[width_id].to_string(),
+                            output_width: exprs[op_out_width_id].to_string(),
+                        });
+                    }
                 }
-                let b_width_id = usize::from(expr.children()[4]);
-                let b_id = usize::from(expr.children()[6]);
-                if let Some(b_op_out_width_id) = get_output_width_id(&exprs[b_id]) {
-                    assert_eq!(
-                        b_width_id, b_op_out_width_id,
-                        "In `{expr}`, subexpression `{}` has inconsistent width: {} != {}",
-                        &exprs[b_id], &exprs[b_width_id], &exprs[b_op_out_width_id]
-                    );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Role of a pattern variable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VarKind {
+    Value,
+    Width,
+    Sign,
+}
+
+impl VarKind {
+    fn is_value(self) -> bool {
+        self == VarKind::Value
+    }
+}
+
+impl std::fmt::Display for VarKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VarKind::Value => write!(f, "value"),
+            VarKind::Width => write!(f, "width"),
+            VarKind::Sign => write!(f, "sign"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum RuleError {
+    #[error("rule `{rule}`: failed to parse `{src}`: {msg}")]
+    Parse {
+        rule: String,
+        src: String,
+        msg: String,
+    },
+    #[error("rule `{rule}`: {kind}")]
+    Invalid { rule: String, kind: InvalidRule },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum InvalidRule {
+    #[error(
+        "in `{expr}`, subexpression `{operand}` has inconsistent width: {width} != {output_width}"
+    )]
+    InconsistentWidth {
+        expr: String,
+        operand: String,
+        width: String,
+        output_width: String,
+    },
+    #[error("`{0}` is not bound by the left-hand side")]
+    UnboundVariable(Var),
+    #[error("`{0}` is used as a {1} and as a {2}")]
+    KindMismatch(Var, VarKind, VarKind),
+    #[error("condition variable `{0}` is not a width or sign of the left-hand side")]
+    ConditionVariable(Var),
+}
+
+/// Collects every variable of the pattern together with its role.
+fn var_kinds(pattern: &Pattern<Arith>) -> Result<Vec<(Var, VarKind)>, InvalidRule> {
+    let exprs = pattern.ast.as_ref();
+    let mut out: Vec<(Var, VarKind)> = vec![];
+    let mut add = |var: Var, kind: VarKind| match out.iter().find(|(v, _)| *v == var) {
+        Some(&(_, prev)) if prev.is_value() != kind.is_value() => {
+            Err(InvalidRule::KindMismatch(var, prev, kind))
+        }
+        Some(_) => Ok(()),
+        None => {
+            out.push((var, kind));
+            Ok(())
+        }
+    };
+    // the root is always a value
+    if let Some(ENodeOrVar::Var(var)) = exprs.last() {
+        add(*var, VarKind::Value)?;
+    }
+    for e_node_or_var in exprs.iter() {
+        if let ENodeOrVar::ENode(expr) = e_node_or_var {
+            for (child, kind) in expr.children().iter().zip(child_kinds(expr)) {
+                if let ENodeOrVar::Var(var) = &exprs[usize::from(*child)] {
+                    add(*var, kind)?;
                 }
             }
         }
     }
+    Ok(out)
+}
+
+/// returns the role of every child of `expr`
+pub(crate) fn child_kinds(expr: &Arith) -> Vec<VarKind> {
+    use VarKind::*;
+    match expr {
+        // w, w_a, s_a, a, w_b, s_b, b
+        _ if is_bin_op(expr) => vec![Width, Width, Sign, Value, Width, Sign, Value],
+        // w, w_a, s_a, a
+        Arith::Negate(_) => vec![Width, Width, Sign, Value],
+        // w, w_a, a, w_b, b
+        Arith::Concat(_) => vec![Width, Width, Value, Width, Value],
+        // w, n, w_a, a
+        Arith::Repeat(_) => vec![Width, Width, Width, Value],
+        // w, a, w_b, b
+        Arith::RotateLeft(_) | Arith::RotateRight(_) => vec![Width, Value, Width, Value],
+        // w, n, a
+        Arith::RotateLeftConst(_) => vec![Width, Width, Value],
+        // w, s, w_a, s_a, a, w_b, s_b, b
+        Arith::SaturatingAdd(_)
+        | Arith::SaturatingSub(_)
+        | Arith::Greater(_)
+        | Arith::GreaterEqual(_) => {
+            vec![Width, Sign, Width, Sign, Value, Width, Sign, Value]
+        }
+        Arith::WidthMaxPlus1(_)
+        | Arith::WidthLeftShift(_)
+        | Arith::WidthAdd(_)
+        | Arith::WidthMul(_) => vec![Width, Width],
+        _ => vec![],
+    }
+}
+
+/// returns pairs of the egg ids of operand width and operand
+pub(crate) fn operand_width_ids(expr: &Arith) -> Vec<(usize, usize)> {
+    let c = |ii: usize| usize::from(expr.children()[ii]);
+    match expr {
+        // w, w_a, s_a, a, w_b, s_b, b
+        _ if is_bin_op(expr) => vec![(c(1), c(3)), (c(4), c(6))],
+        // w, w_a, s_a, a
+        Arith::Negate(_) => vec![(c(1), c(3))],
+        // w, w_a, a, w_b, b
+        Arith::Concat(_) => vec![(c(1), c(2)), (c(3), c(4))],
+        // w, n, w_a, a
+        Arith::Repeat(_) => vec![(c(2), c(3))],
+        // w, a, w_b, b
+        Arith::RotateLeft(_) | Arith::RotateRight(_) => vec![(c(0), c(1)), (c(2), c(3))],
+        // w, n, a
+        Arith::RotateLeftConst(_) => vec![(c(0), c(2))],
+        // w, s, w_a, s_a, a, w_b, s_b, b
+        Arith::SaturatingAdd(_)
+        | Arith::SaturatingSub(_)
+        | Arith::Greater(_)
+        | Arith::GreaterEqual(_) => vec![(c(2), c(4)), (c(5), c(7))],
+        _ => vec![],
+    }
 }
 
-/// returns the egg id of the output width, if `expr` has one
+/// returns the egg id of the output width, if `expr` has one, comparisons always have 1 bit
 fn get_output_width_id(expr: &ENodeOrVar<Arith>) -> Option<usize> {
-    if let ENodeOrVar::ENode(expr) = expr {
-        if is_bin_op(expr) {
-            // w, w_a, s_a, a, w_b, s_b, b
+    match expr {
+        ENodeOrVar::ENode(expr)
+            if is_bin_op(expr)
+                || matches!(
+                    expr,
+                    Arith::Negate(_)
+                        | Arith::Concat(_)
+                        | Arith::Repeat(_)
+                        | Arith::RotateLeft(_)
+                        | Arith::RotateRight(_)
+                        | Arith::RotateLeftConst(_)
+                        | Arith::SaturatingAdd(_)
+                        | Arith::SaturatingSub(_)
+                ) =>
+        {
+            // the output width is always the first child
             Some(usize::from(expr.children()[0]))
-        } else {
-            None
         }
-    } else {
-        None
+        _ => None,
     }
 }
 
@@ -290,18 +919,116 @@ pub fn create_egg_rewrites() -> Vec<Rewrite> {
         .unwrap_or(vec![])
 }
 
+/// Returns the rules named in `config`, or all rules if the config does not name any.
+pub fn create_rewrites_from_config(
+    config: &EGraphConfig,
+) -> Result<Vec<ArithRewrite>, EGraphError> {
+    let all = create_rewrites();
+    if config.rules.is_empty() {
+        return Ok(all);
+    }
+    config
+        .rules
+        .iter()
+        .map(|name| {
+            all.iter()
+                .find(|r| r.name() == name)
+                .cloned()
+                .ok_or_else(|| EGraphError::UnknownRule(name.clone()))
+        })
+        .collect()
+}
+
+/// Applies the limits and the rule schedule from `config` to `runner`.
+pub fn configure_runner(
+    runner: egg::Runner<Arith, WidthConstantFold>,
+    config: &EGraphConfig,
+) -> egg::Runner<Arith, WidthConstantFold> {
+    let runner = runner.with_scheduler(ArithScheduler::from_config(&config.scheduler));
+    let runner = match config.memory_limit {
+        Some(limit) => with_memory_limit(runner, limit),
+        None => runner,
+    };
+    match config.iter_limit {
+        Some(limit) => runner.with_iter_limit(limit),
+        None => runner,
+    }
+}
+
+/// Creates an egg runner that stops saturating once `token` is cancelled.
+/// The e-graph of the runner contains all equalities that were found up to that point.
+pub fn cancellable_runner(token: CancellationToken) -> egg::Runner<Arith, WidthConstantFold> {
+    egg::Runner::default().with_hook(move |_| {
+        if token.is_cancelled() {
+            Err("cancelled".to_string())
+        } else {
+            Ok(())
+        }
+    })
+}
+
+/// Reports the size of the e-graph to `observer` before every iteration.
+pub fn with_progress(
+    runner: egg::Runner<Arith, WidthConstantFold>,
+    mut observer: impl ProgressObserver + 'static,
+) -> egg::Runner<Arith, WidthConstantFold> {
+    runner.with_hook(move |r| {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            iteration = r.iterations.len(),
+            nodes = r.egraph.total_number_of_nodes(),
+            classes = r.egraph.number_of_classes(),
+            "e-graph iteration"
+        );
+        observer.egraph_iteration(
+            r.iterations.len(),
+            r.egraph.total_number_of_nodes(),
+            r.egraph.number_of_classes(),
+        );
+        Ok(())
+    })
+}
+
+/// Tells the runner whether to continue saturating after a hook ran.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HookAction {
+    Continue,
+    /// Stops the runner with [`egg::StopReason::Other`] and the given reason.
+    Stop(String),
+}
+
+/// Calls `hook` after every completed iteration with the number of iterations so far, the
+/// e-graph and the roots of the runner. The hook can stop saturation early, e.g., once two
+/// roots were merged. egg does not call hooks after an iteration that hit a limit or
+/// saturated the e-graph.
+pub fn with_egraph_hook(
+    runner: egg::Runner<Arith, WidthConstantFold>,
+    mut hook: impl FnMut(usize, &EGraph, &[Id]) -> HookAction + 'static,
+) -> egg::Runner<Arith, WidthConstantFold> {
+    runner.with_hook(move |r| {
+        // egg runs hooks before every iteration, the first call happens before any rewrite
+        if r.iterations.is_empty() {
+            return Ok(());
+        }
+        match hook(r.iterations.len(), &r.egraph, &r.roots) {
+            HookAction::Continue => Ok(()),
+            HookAction::Stop(reason) => Err(reason),
+        }
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::arithmetic::verification_fig_1;
+    use crate::arithmetic::{verification_distributivity, verification_fig_1};
     use crate::to_arith;
     use patronus::expr::{Context, SerializableIrNode};
     #[test]
     fn test_data_path_verification_fig_1_rewrites() {
         let mut ctx = Context::default();
         let (spec, implementation) = verification_fig_1(&mut ctx);
-        let spec_e = to_arith(&ctx, spec);
-        let impl_e = to_arith(&ctx, implementation);
+        let spec_e = to_arith(&ctx, spec).unwrap();
+        let impl_e = to_arith(&ctx, implementation).unwrap();
 
         // run egraph operations
         let egg_rewrites = create_egg_rewrites();
@@ -335,8 +1062,8 @@ mod tests {
         assert_eq!(in_smt_expr.serialize_to_str(&ctx), "add(A, B)");
 
         // run egraph operations
-        let egg_expr_in = to_arith(&ctx, in_smt_expr);
-        let egg_expr_in_2 = to_arith(&ctx, in_smt_expr_2);
+        let egg_expr_in = to_arith(&ctx, in_smt_expr).unwrap();
+        let egg_expr_in_2 = to_arith(&ctx, in_smt_expr_2).unwrap();
         let egg_rewrites = create_egg_rewrites();
         let runner = egg::Runner::default()
             .with_expr(&egg_expr_in)
@@ -350,4 +1077,343 @@ mod tests {
             "inputs should be equivalent with commute-add"
         );
     }
+
+    #[test]
+    fn test_concat_rewrites() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("A", 8);
+        let b = ctx.bv_symbol("B", 4);
+        // A ++ 0 == zext(A) << 4
+        let padded = ctx.build(|c| c.concat(a, c.zero(4)));
+        let shifted = ctx.build(|c| c.shift_left(c.zero_extend(a, 4), c.bit_vec_val(4, 12)));
+        // A ++ B == (zext(A) << 4) + zext(B)
+        let packed = ctx.concat(a, b);
+        let added = ctx.build(|c| {
+            c.add(
+                c.shift_left(c.zero_extend(a, 4), c.bit_vec_val(4, 12)),
+                c.zero_extend(b, 8),
+            )
+        });
+        let runner = egg::Runner::default()
+            .with_expr(&to_arith(&ctx, padded).unwrap())
+            .with_expr(&to_arith(&ctx, shifted).unwrap())
+            .with_expr(&to_arith(&ctx, packed).unwrap())
+            .with_expr(&to_arith(&ctx, added).unwrap())
+            .run(&create_egg_rewrites());
+        let class = |ii: usize| runner.egraph.find(runner.roots[ii]);
+        assert_eq!(class(0), class(1));
+        assert_eq!(class(2), class(3));
+
+        // (A ++ A) ++ (A ++ A) == A ++ (A ++ (A ++ A))
+        let pairs = ctx.build(|c| c.concat(c.concat(a, a), c.concat(a, a)));
+        let chain = ctx.build(|c| c.concat(a, c.concat(a, c.concat(a, a))));
+        let runner = egg::Runner::default()
+            .with_expr(&to_arith(&ctx, pairs).unwrap())
+            .with_expr(&to_arith(&ctx, chain).unwrap())
+            .run(&create_egg_rewrites());
+        assert_eq!(
+            runner.egraph.find(runner.roots[0]),
+            runner.egraph.find(runner.roots[1])
+        );
+    }
+
+    #[test]
+    fn test_rotate_rewrites() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("A", 8);
+        let b = ctx.bv_symbol("B", 8);
+        let c = ctx.bv_symbol("C", 8);
+        // rol(rol(A, B), C) == rol(A, B + C)
+        let nested = ctx.build(|x| x.rotate_left(x.rotate_left(a, b), c));
+        let merged =
+            ctx.build(|x| x.rotate_left(a, x.add(x.zero_extend(b, 1), x.zero_extend(c, 1))));
+        // ror(rol(A, B), B) == A
+        let undone = ctx.build(|x| x.rotate_right(x.rotate_left(a, b), b));
+        // rol(rol(A, 3), 5) == A
+        let full = ctx.build(|x| x.rotate_left_by(x.rotate_left_by(a, 3), 5));
+        let runner = egg::Runner::default()
+            .with_expr(&to_arith(&ctx, nested).unwrap())
+            .with_expr(&to_arith(&ctx, merged).unwrap())
+            .with_expr(&to_arith(&ctx, undone).unwrap())
+            .with_expr(&to_arith(&ctx, a).unwrap())
+            .with_expr(&to_arith(&ctx, full).unwrap())
+            .run(&create_egg_rewrites());
+        let class = |ii: usize| runner.egraph.find(runner.roots[ii]);
+        assert_eq!(class(0), class(1));
+        assert_eq!(class(2), class(3));
+        assert_eq!(class(4), class(3));
+    }
+
+    #[test]
+    fn test_strength_reduction_rewrites() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("A", 8);
+        // zext(A) * 8 == zext(A) << 3
+        let mul = ctx.build(|c| c.mul(c.zero_extend(a, 4), c.bit_vec_val(8, 12)));
+        let shifted = ctx.build(|c| c.shift_left(c.zero_extend(a, 4), c.bit_vec_val(3, 12)));
+        // 6 is not a power of two
+        let mul_6 = ctx.build(|c| c.mul(c.zero_extend(a, 4), c.bit_vec_val(6, 12)));
+        let runner = egg::Runner::default()
+            .with_expr(&to_arith(&ctx, mul).unwrap())
+            .with_expr(&to_arith(&ctx, shifted).unwrap())
+            .with_expr(&to_arith(&ctx, mul_6).unwrap())
+            .run(&create_egg_rewrites());
+        let class = |ii: usize| runner.egraph.find(runner.roots[ii]);
+        assert_eq!(class(0), class(1));
+        assert_ne!(class(0), class(2));
+
+        assert_eq!(ConstFn::Log2.apply(8), Some(3));
+        assert_eq!(ConstFn::Log2.apply(6), None);
+        assert_eq!(ConstFn::Pow2.apply(3), Some(8));
+        assert_eq!(ConstFn::Pow2.apply(64), None);
+    }
+
+    #[test]
+    fn test_negation_rewrites() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("A", 12);
+        let b = ctx.bv_symbol("B", 8);
+        let b12 = ctx.zero_extend(b, 4);
+        let pairs = [
+            // A - B == A + (-B)
+            (ctx.sub(a, b12), ctx.build(|c| c.add(a, c.negate(b12)))),
+            // A - (-B) == A + B
+            (ctx.build(|c| c.sub(a, c.negate(b12))), ctx.add(a, b12)),
+            // -(-A) == A
+            (ctx.build(|c| c.negate(c.negate(a))), a),
+            // 0 - A == -A
+            (ctx.build(|c| c.sub(c.zero(12), a)), ctx.negate(a)),
+            // A + sext(-zext(B)) == A - B
+            (
+                ctx.build(|c| c.add(a, c.sign_extend(c.negate(c.zero_extend(b, 1)), 3))),
+                ctx.sub(a, b12),
+            ),
+        ];
+        for (lhs, rhs) in pairs {
+            let runner = egg::Runner::default()
+                .with_expr(&to_arith(&ctx, lhs).unwrap())
+                .with_expr(&to_arith(&ctx, rhs).unwrap())
+                .run(&create_egg_rewrites());
+            assert_eq!(
+                runner.egraph.find(runner.roots[0]),
+                runner.egraph.find(runner.roots[1]),
+                "{} == {}",
+                lhs.serialize_to_str(&ctx),
+                rhs.serialize_to_str(&ctx)
+            );
+        }
+    }
+
+    #[test]
+    fn test_distributivity_rewrites() {
+        let mut ctx = Context::default();
+        let (spec, implementation) = verification_distributivity(&mut ctx);
+        let spec_e = to_arith(&ctx, spec).unwrap();
+        let impl_e = to_arith(&ctx, implementation).unwrap();
+        let runner = egg::Runner::default()
+            .with_expr(&spec_e)
+            .with_expr(&impl_e)
+            .run(&create_egg_rewrites());
+        let spec_class = runner.egraph.find(runner.roots[0]);
+        let impl_class = runner.egraph.find(runner.roots[1]);
+        assert_eq!(spec_class, impl_class, "should prove equality!");
+    }
+
+    #[test]
+    fn test_saturating_rewrites() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("A", 8);
+        let b = ctx.bv_symbol("B", 8);
+        // a saturating sum of two zero extended values never overflows
+        let saturated =
+            ctx.build(|x| x.saturating_add(x.zero_extend(a, 1), x.zero_extend(b, 1), false));
+        let sum = ctx.build(|x| x.add(x.zero_extend(a, 1), x.zero_extend(b, 1)));
+        let diff_saturated =
+            ctx.build(|x| x.saturating_sub(x.sign_extend(a, 2), x.sign_extend(b, 2), true));
+        let diff = ctx.build(|x| x.sub(x.sign_extend(a, 2), x.sign_extend(b, 2)));
+        // the signed saturating sum of two 8-bit values can overflow
+        let overflow = ctx.saturating_add(a, b, true);
+        let commuted = ctx.saturating_add(b, a, true);
+        let wrapping = ctx.add(a, b);
+        let runner = egg::Runner::default()
+            .with_expr(&to_arith(&ctx, saturated).unwrap())
+            .with_expr(&to_arith(&ctx, sum).unwrap())
+            .with_expr(&to_arith(&ctx, diff_saturated).unwrap())
+            .with_expr(&to_arith(&ctx, diff).unwrap())
+            .with_expr(&to_arith(&ctx, overflow).unwrap())
+            .with_expr(&to_arith(&ctx, commuted).unwrap())
+            .with_expr(&to_arith(&ctx, wrapping).unwrap())
+            .run(&create_egg_rewrites());
+        let class = |ii: usize| runner.egraph.find(runner.roots[ii]);
+        assert_eq!(class(0), class(1));
+        assert_eq!(class(2), class(3));
+        assert_eq!(class(4), class(5));
+        assert_ne!(class(4), class(6));
+    }
+
+    #[test]
+    fn test_cancelled_runner() {
+        let mut ctx = Context::default();
+        let (spec, _) = verification_fig_1(&mut ctx);
+        let spec_e = to_arith(&ctx, spec).unwrap();
+        let token = CancellationToken::default();
+        token.cancel();
+        let runner = cancellable_runner(token)
+            .with_expr(&spec_e)
+            .run(&create_egg_rewrites());
+        assert!(matches!(
+            runner.stop_reason,
+            Some(egg::StopReason::Other(ref reason)) if reason == "cancelled"
+        ));
+    }
+
+    #[test]
+    fn test_sign_audit() {
+        let rules = create_rewrites();
+        let audit = audit_signs(&rules);
+        assert!(audit.iter().all(|a| !a.is_unsigned_only()), "{audit:?}");
+        let mult = audit.iter().find(|a| a.rule == "left-shift-mult").unwrap();
+        assert_eq!(mult.forced_unsigned, 3);
+        assert_eq!(mult.variants, ["left-shift-mult-signed"]);
+        let signed = audit
+            .iter()
+            .find(|a| a.rule == "left-shift-mult-signed")
+            .unwrap();
+        assert_eq!((signed.forced_unsigned, signed.forced_signed), (0, 3));
+        // shift amounts do not count
+        let merge = audit.iter().find(|a| a.rule == "merge-left-shift").unwrap();
+        assert_eq!(merge.forced_unsigned, 0);
+
+        // without the signed variant, the rule only covers unsigned datapaths
+        let without: Vec<_> = rules
+            .into_iter()
+            .filter(|r| r.name() != "left-shift-mult-signed")
+            .collect();
+        let unsigned_only: Vec<_> = audit_signs(&without)
+            .into_iter()
+            .filter(|a| a.is_unsigned_only())
+            .map(|a| a.rule)
+            .collect();
+        assert_eq!(unsigned_only, ["left-shift-mult"]);
+    }
+
+    #[test]
+    fn test_rule_validation() {
+        let invalid = |lhs, rhs, cond| match ArithRewrite::try_new("r", lhs, rhs, cond, &[], &[]) {
+            Err(RuleError::Invalid { kind, .. }) => kind,
+            other => panic!("expected an invalid rule, got {:?}", other.err()),
+        };
+        let var = |v: &str| v.parse::<Var>().unwrap();
+        let add = "(+ ?wo ?wa ?sa ?a ?wb ?sb ?b)";
+        assert_eq!(
+            invalid(add, "(+ ?wo ?wa ?sa ?a ?wb ?sb ?c)", None),
+            InvalidRule::UnboundVariable(var("?c"))
+        );
+        assert_eq!(
+            invalid(add, "(+ ?wo ?wa ?sa ?a ?wb ?sb ?wa)", None),
+            InvalidRule::KindMismatch(var("?wa"), VarKind::Width, VarKind::Value)
+        );
+        assert_eq!(
+            invalid(add, "(+ ?wo ?wa ?sa ?a ?wb ?sb ?b)", Some("?a >= ?wo")),
+            InvalidRule::ConditionVariable(var("?a"))
+        );
+        assert_eq!(
+            invalid(add, "(+ ?wo ?wa ?sa ?a ?wb ?sb ?b)", Some("?wx >= ?wo")),
+            InvalidRule::ConditionVariable(var("?wx"))
+        );
+        assert!(matches!(
+            invalid(
+                "(+ ?wo ?wa ?sa (+ ?wx ?wb ?sb ?b ?wc ?sc ?c) ?wd ?sd ?d)",
+                "?b",
+                None
+            ),
+            InvalidRule::InconsistentWidth { .. }
+        ));
+        assert!(matches!(
+            ArithRewrite::try_new("r", "(+ ?wo", "?a", None, &[], &[]),
+            Err(RuleError::Parse { .. })
+        ));
+        // width values bind rhs variables
+        let shift = "(<< ?wo ?wa unsign ?a ?wo unsign ?n)";
+        let concat = "(concat ?wo ?wa ?a ?wb 0)";
+        assert!(ArithRewrite::try_new("r", concat, shift, None, &[("?n", "?wb")], &[]).is_ok());
+        assert!(ArithRewrite::try_new("r", concat, shift, None, &[], &[]).is_err());
+    }
+
+    #[test]
+    fn test_rewrites_from_config() {
+        let mut config = EGraphConfig::default();
+        assert_eq!(
+            create_rewrites_from_config(&config).unwrap().len(),
+            create_rewrites().len()
+        );
+        config.rules = vec!["commute-mul".to_string(), "commute-add".to_string()];
+        let names: Vec<_> = create_rewrites_from_config(&config)
+            .unwrap()
+            .iter()
+            .map(|r| r.name().to_string())
+            .collect();
+        assert_eq!(names, ["commute-mul", "commute-add"]);
+        config.rules.push("does-not-exist".to_string());
+        assert!(matches!(
+            create_rewrites_from_config(&config),
+            Err(EGraphError::UnknownRule(ref name)) if name == "does-not-exist"
+        ));
+    }
+
+    #[test]
+    fn test_runner_progress() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        struct CountIterations(Rc<Cell<usize>>);
+        impl ProgressObserver for CountIterations {
+            fn egraph_iteration(&mut self, _iteration: usize, nodes: usize, _classes: usize) {
+                assert!(nodes > 0);
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let mut ctx = Context::default();
+        let (spec, _) = verification_fig_1(&mut ctx);
+        let spec_e = to_arith(&ctx, spec).unwrap();
+        let count = Rc::new(Cell::new(0));
+        let runner = with_progress(egg::Runner::default(), CountIterations(count.clone()))
+            .with_expr(&spec_e)
+            .run(&create_egg_rewrites());
+        // hooks are skipped when a limit is hit, otherwise they run before every iteration
+        assert!(count.get() > 0);
+        assert!(count.get() <= runner.iterations.len());
+    }
+
+    #[test]
+    fn test_egraph_hook_stops_early() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        let mut ctx = Context::default();
+        let (spec, implementation) = verification_fig_1(&mut ctx);
+        let spec_e = to_arith(&ctx, spec).unwrap();
+        let impl_e = to_arith(&ctx, implementation).unwrap();
+        let merged_at = Rc::new(Cell::new(None));
+        let merged = merged_at.clone();
+        let runner = with_egraph_hook(egg::Runner::default(), move |iteration, egraph, roots| {
+            assert!(iteration > 0);
+            if egraph.find(roots[0]) == egraph.find(roots[1]) {
+                merged.set(Some(iteration));
+                HookAction::Stop("merged".to_string())
+            } else {
+                HookAction::Continue
+            }
+        })
+        .with_expr(&spec_e)
+        .with_expr(&impl_e)
+        .run(&create_egg_rewrites());
+        assert!(
+            matches!(&runner.stop_reason, Some(egg::StopReason::Other(r)) if r == "merged"),
+            "{:?}",
+            runner.stop_reason
+        );
+        assert_eq!(merged_at.get(), Some(runner.iterations.len()));
+    }
 }
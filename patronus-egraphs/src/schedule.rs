@@ -0,0 +1,173 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+/*!
+# Rule Scheduling
+
+Rules like `merge-left-shift` can quickly blow up the e-graph before other rules get a chance
+to fire. [`ArithScheduler`] extends egg's backoff scheduler with per-rule limits and the
+ability to permanently disable a rule after a fixed number of applications.
+
+!*/
+
+use crate::{Arith, Rewrite, WidthConstantFold};
+use egg::{BackoffScheduler, RewriteScheduler, SearchMatches};
+use patronus::config::SchedulerConfig;
+use rustc_hash::FxHashMap;
+
+pub struct ArithScheduler {
+    backoff: BackoffScheduler,
+    max_applications: FxHashMap<String, usize>,
+    applications: FxHashMap<String, usize>,
+}
+
+impl Default for ArithScheduler {
+    fn default() -> Self {
+        Self::from_config(&SchedulerConfig::default())
+    }
+}
+
+impl ArithScheduler {
+    pub fn from_config(config: &SchedulerConfig) -> Self {
+        let mut scheduler = Self {
+            backoff: BackoffScheduler::default()
+                .with_initial_match_limit(config.initial_match_limit)
+                .with_ban_length(config.ban_length),
+            max_applications: FxHashMap::default(),
+            applications: FxHashMap::default(),
+        };
+        for (name, rule) in config.rules.iter() {
+            if let Some(limit) = rule.match_limit {
+                scheduler = scheduler.with_match_limit(name, limit);
+            }
+            if let Some(length) = rule.ban_length {
+                scheduler = scheduler.with_ban_length(name, length);
+            }
+            if let Some(max) = rule.max_applications {
+                scheduler = scheduler.with_max_applications(name, max);
+            }
+            if rule.never_ban {
+                scheduler = scheduler.never_ban(name);
+            }
+        }
+        scheduler
+    }
+
+    /// Number of matches after which the rule is banned for the first time.
+    pub fn with_match_limit(mut self, rule: &str, limit: usize) -> Self {
+        self.backoff = self.backoff.rule_match_limit(rule, limit);
+        self
+    }
+
+    /// Number of iterations that the rule is banned for the first time.
+    pub fn with_ban_length(mut self, rule: &str, length: usize) -> Self {
+        self.backoff = self.backoff.rule_ban_length(rule, length);
+        self
+    }
+
+    /// Stops applying the rule once it has been applied `max` times.
+    pub fn with_max_applications(mut self, rule: &str, max: usize) -> Self {
+        self.max_applications.insert(rule.to_string(), max);
+        self
+    }
+
+    pub fn never_ban(mut self, rule: &str) -> Self {
+        self.backoff = self.backoff.do_not_ban(rule);
+        self
+    }
+
+    fn is_exhausted(&self, rule: &str) -> bool {
+        match self.max_applications.get(rule) {
+            Some(&max) => self.applications.get(rule).copied().unwrap_or(0) >= max,
+            None => false,
+        }
+    }
+}
+
+impl RewriteScheduler<Arith, WidthConstantFold> for ArithScheduler {
+    fn can_stop(&mut self, iteration: usize) -> bool {
+        self.backoff.can_stop(iteration)
+    }
+
+    fn search_rewrite<'a>(
+        &mut self,
+        iteration: usize,
+        egraph: &crate::EGraph,
+        rewrite: &'a Rewrite,
+    ) -> Vec<SearchMatches<'a, Arith>> {
+        if self.is_exhausted(rewrite.name.as_str()) {
+            vec![]
+        } else {
+            self.backoff.search_rewrite(iteration, egraph, rewrite)
+        }
+    }
+
+    fn apply_rewrite(
+        &mut self,
+        iteration: usize,
+        egraph: &mut crate::EGraph,
+        rewrite: &Rewrite,
+        mut matches: Vec<SearchMatches<Arith>>,
+    ) -> usize {
+        // never exceed the maximum number of applications within a single iteration
+        if let Some(&max) = self.max_applications.get(rewrite.name.as_str()) {
+            let mut remaining = max.saturating_sub(
+                self.applications
+                    .get(rewrite.name.as_str())
+                    .copied()
+                    .unwrap_or(0),
+            );
+            for m in matches.iter_mut() {
+                m.substs.truncate(remaining);
+                remaining -= m.substs.len();
+            }
+            matches.retain(|m| !m.substs.is_empty());
+        }
+        let applied = self
+            .backoff
+            .apply_rewrite(iteration, egraph, rewrite, matches);
+        *self
+            .applications
+            .entry(rewrite.name.to_string())
+            .or_default() += applied;
+        applied
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arithmetic::verification_fig_1;
+    use crate::{create_egg_rewrites, to_arith};
+    use patronus::config::RuleSchedule;
+    use patronus::expr::Context;
+
+    fn total_applications(runner: &egg::Runner<Arith, WidthConstantFold>, rule: &str) -> usize {
+        runner
+            .iterations
+            .iter()
+            .flat_map(|i| i.applied.get(&egg::Symbol::from(rule)))
+            .sum()
+    }
+
+    #[test]
+    fn test_max_applications() {
+        let mut ctx = Context::default();
+        let (spec, _) = verification_fig_1(&mut ctx);
+        let spec_e = to_arith(&ctx, spec).unwrap();
+        let mut config = SchedulerConfig::default();
+        config.rules.insert(
+            "commute-mul".to_string(),
+            RuleSchedule {
+                max_applications: Some(1),
+                ..Default::default()
+            },
+        );
+        let runner = egg::Runner::default()
+            .with_scheduler(ArithScheduler::from_config(&config))
+            .with_iter_limit(5)
+            .with_expr(&spec_e)
+            .run(&create_egg_rewrites());
+        assert_eq!(total_applications(&runner, "commute-mul"), 1);
+    }
+}
@@ -0,0 +1,211 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+/*!
+# E-Graph Snapshots
+
+Saves an e-graph together with its analysis data and roots as JSON, so that the result of
+a long saturation run can be explored offline, e.g., with [`crate::ArithRewrite::find_lhs_matches`].
+
+!*/
+
+use crate::{Arith, EGraph};
+use egg::{FromOp, Id, Language};
+use patronus::expr::WidthInt;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SnapshotError {
+    #[error("failed to read or write snapshot")]
+    Io(#[from] std::io::Error),
+    #[error("invalid snapshot: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("unknown e-node `{0}`")]
+    InvalidNode(String),
+    #[error("e-class {0} does not exist")]
+    UnknownClass(u32),
+    #[error("{0} e-nodes only refer to classes that are never defined")]
+    Unreachable(usize),
+}
+
+/// Serializable version of an e-graph. Classes are identified by their canonical id.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EGraphSnapshot {
+    pub classes: Vec<ClassSnapshot>,
+    /// e.g., the roots of the runner that produced the e-graph
+    pub roots: Vec<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClassSnapshot {
+    pub id: u32,
+    /// result of the width constant folding analysis
+    pub data: Option<WidthInt>,
+    pub nodes: Vec<NodeSnapshot>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeSnapshot {
+    pub op: String,
+    pub children: Vec<u32>,
+}
+
+impl EGraphSnapshot {
+    pub fn new(egraph: &EGraph, roots: &[Id]) -> Self {
+        let id = |i: Id| usize::from(egraph.find(i)) as u32;
+        let mut classes: Vec<_> = egraph
+            .classes()
+            .map(|c| ClassSnapshot {
+                id: id(c.id),
+                data: c.data,
+                nodes: c
+                    .nodes
+                    .iter()
+                    .map(|n| NodeSnapshot {
+                        op: n.to_string(),
+                        children: n.children().iter().map(|&c| id(c)).collect(),
+                    })
+                    .collect(),
+            })
+            .collect();
+        // the class order of egg is not deterministic
+        classes.sort_by_key(|c| c.id);
+        let roots = roots.iter().map(|&r| id(r)).collect();
+        Self { classes, roots }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("snapshots can always be serialized")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, SnapshotError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SnapshotError> {
+        let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        serde_json::to_writer(file, self)?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SnapshotError> {
+        let file = std::io::BufReader::new(std::fs::File::open(path)?);
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    /// Rebuilds the e-graph and returns it together with the ids of the roots.
+    pub fn to_egraph(&self) -> Result<(EGraph, Vec<Id>), SnapshotError> {
+        let mut egraph = EGraph::default();
+        let mut ids: FxHashMap<u32, Id> = FxHashMap::default();
+        let mut todo: Vec<(u32, &NodeSnapshot)> = self
+            .classes
+            .iter()
+            .flat_map(|c| c.nodes.iter().map(move |n| (c.id, n)))
+            .collect();
+
+        // a node can only be added once all of its children exist, since every e-class
+        // represents at least one finite term, we always make progress
+        while !todo.is_empty() {
+            let before = todo.len();
+            let mut remaining = Vec::with_capacity(todo.len());
+            for (class, node) in todo {
+                let children: Option<Vec<Id>> =
+                    node.children.iter().map(|c| ids.get(c).copied()).collect();
+                let Some(children) = children else {
+                    remaining.push((class, node));
+                    continue;
+                };
+                let node = Arith::from_op(&node.op, children)
+                    .map_err(|_| SnapshotError::InvalidNode(node.op.clone()))?;
+                let added = egraph.add(node);
+                match ids.get(&class) {
+                    Some(&existing) => {
+                        egraph.union(existing, added);
+                    }
+                    None => {
+                        ids.insert(class, added);
+                    }
+                }
+            }
+            if remaining.len() == before {
+                return Err(SnapshotError::Unreachable(remaining.len()));
+            }
+            todo = remaining;
+        }
+        egraph.rebuild();
+
+        let roots = self
+            .roots
+            .iter()
+            .map(|r| {
+                ids.get(r)
+                    .map(|&id| egraph.find(id))
+                    .ok_or(SnapshotError::UnknownClass(*r))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok((egraph, roots))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arithmetic::verification_fig_1;
+    use crate::{create_egg_rewrites, create_rewrites, to_arith};
+    use patronus::expr::Context;
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let mut ctx = Context::default();
+        let (spec, implementation) = verification_fig_1(&mut ctx);
+        let runner = egg::Runner::default()
+            .with_expr(&to_arith(&ctx, spec).unwrap())
+            .with_expr(&to_arith(&ctx, implementation).unwrap())
+            .run(&create_egg_rewrites());
+
+        let snapshot = EGraphSnapshot::new(&runner.egraph, &runner.roots);
+        let loaded = EGraphSnapshot::from_json(&snapshot.to_json()).unwrap();
+        assert_eq!(loaded, snapshot);
+
+        let (egraph, roots) = loaded.to_egraph().unwrap();
+        assert_eq!(
+            egraph.number_of_classes(),
+            runner.egraph.number_of_classes()
+        );
+        assert_eq!(
+            egraph.total_number_of_nodes(),
+            runner.egraph.total_number_of_nodes()
+        );
+        // spec and implementation are still proven to be equivalent
+        assert_eq!(roots[0], roots[1]);
+        for rule in create_rewrites() {
+            assert_eq!(
+                rule.find_lhs_matches(&egraph).len(),
+                rule.find_lhs_matches(&runner.egraph).len(),
+                "{}",
+                rule.name()
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_snapshot() {
+        let snapshot = EGraphSnapshot {
+            classes: vec![ClassSnapshot {
+                id: 0,
+                data: None,
+                nodes: vec![NodeSnapshot {
+                    op: "+".to_string(),
+                    children: vec![1],
+                }],
+            }],
+            roots: vec![0],
+        };
+        assert!(matches!(
+            snapshot.to_egraph(),
+            Err(SnapshotError::Unreachable(1))
+        ));
+    }
+}
@@ -0,0 +1,186 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+/*!
+# Incremental E-Graph Sessions
+
+When candidate implementations are generated one after another, rebuilding the e-graph for
+every candidate repeats all the work that was done for the spec and for earlier candidates.
+An [`EGraphSession`] keeps a single e-graph alive between queries. New expressions are added
+to the already saturated e-graph and saturation only continues from there. An expression
+whose nodes are all already part of the e-graph, e.g., because a rule derived it earlier,
+does not require any further iterations.
+
+!*/
+
+use crate::limits::stop_result;
+use crate::{
+    configure_runner, with_egraph_hook, Arith, ArithRewrite, EGraph, EGraphEquivResult, HookAction,
+    Rewrite,
+};
+use egg::{Id, RecExpr};
+use patronus::config::EGraphConfig;
+
+pub struct EGraphSession {
+    egraph: EGraph,
+    rules: Vec<Rewrite>,
+    config: EGraphConfig,
+    /// e-class of every expression that was added, in order
+    roots: Vec<Id>,
+    /// no rule can add anything to the e-graph
+    saturated: bool,
+    /// iterations over all runs
+    iterations: usize,
+}
+
+impl EGraphSession {
+    /// The limits of `config` apply to every single saturation run.
+    pub fn new(rules: &[ArithRewrite], config: &EGraphConfig) -> Self {
+        Self {
+            egraph: EGraph::default(),
+            rules: rules.iter().flat_map(|r| r.to_egg()).collect(),
+            config: config.clone(),
+            roots: vec![],
+            saturated: true,
+            iterations: 0,
+        }
+    }
+
+    /// Adds `expr` to the e-graph and returns its index.
+    pub fn add(&mut self, expr: &RecExpr<Arith>) -> usize {
+        let nodes_before = self.egraph.total_number_of_nodes();
+        let root = self.egraph.add_expr(expr);
+        self.egraph.rebuild();
+        if self.egraph.total_number_of_nodes() != nodes_before {
+            self.saturated = false;
+        }
+        self.roots.push(root);
+        self.roots.len() - 1
+    }
+
+    pub fn len(&self) -> usize {
+        self.roots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roots.is_empty()
+    }
+
+    pub fn is_saturated(&self) -> bool {
+        self.saturated
+    }
+
+    /// Number of iterations of all saturation runs so far.
+    pub fn iterations(&self) -> usize {
+        self.iterations
+    }
+
+    pub fn egraph(&self) -> &EGraph {
+        &self.egraph
+    }
+
+    /// Canonical e-class of the expression at `index`.
+    pub fn class_of(&self, index: usize) -> Id {
+        self.egraph.find(self.roots[index])
+    }
+
+    /// Whether the expressions at `a` and `b` are already known to be equivalent.
+    pub fn same_class(&self, a: usize, b: usize) -> bool {
+        self.class_of(a) == self.class_of(b)
+    }
+
+    /// Saturates the e-graph within the limits of the config. Returns immediately if nothing
+    /// was added since the last time the e-graph saturated.
+    pub fn run(&mut self) -> EGraphEquivResult {
+        if self.saturated {
+            return EGraphEquivResult::Saturated;
+        }
+        self.saturate(None)
+    }
+
+    /// Checks whether the expressions at `a` and `b` are equivalent. Saturation continues only
+    /// until they are merged.
+    pub fn check(&mut self, a: usize, b: usize) -> EGraphEquivResult {
+        if self.same_class(a, b) {
+            return EGraphEquivResult::Equivalent;
+        }
+        if self.saturated {
+            return EGraphEquivResult::Saturated;
+        }
+        let result = self.saturate(Some((a, b)));
+        if self.same_class(a, b) {
+            EGraphEquivResult::Equivalent
+        } else {
+            result
+        }
+    }
+
+    fn saturate(&mut self, until_merged: Option<(usize, usize)>) -> EGraphEquivResult {
+        let egraph = std::mem::take(&mut self.egraph);
+        let mut runner = configure_runner(egg::Runner::default(), &self.config).with_egraph(egraph);
+        runner.roots = self.roots.clone();
+        if let Some((a, b)) = until_merged {
+            runner = with_egraph_hook(runner, move |_, egraph, roots| {
+                if egraph.find(roots[a]) == egraph.find(roots[b]) {
+                    HookAction::Stop("equivalent".to_string())
+                } else {
+                    HookAction::Continue
+                }
+            });
+        }
+        let runner = runner.run(&self.rules);
+        self.iterations += runner.iterations.len();
+        let result = stop_result(&runner);
+        self.saturated = result == EGraphEquivResult::Saturated;
+        self.egraph = runner.egraph;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_rewrites, to_arith};
+    use patronus::expr::Context;
+
+    #[test]
+    fn test_incremental_session() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("a", 8);
+        let b = ctx.bv_symbol("b", 8);
+        let exprs = [ctx.add(a, b), ctx.add(b, a), ctx.mul(a, b), ctx.mul(b, a)];
+        let [sum, swapped_sum, product, swapped_product] =
+            exprs.map(|e| to_arith(&ctx, e).unwrap());
+        let rules: Vec<ArithRewrite> = create_rewrites()
+            .into_iter()
+            .filter(|r| r.name() == "commute-add" || r.name() == "commute-mul")
+            .collect();
+        let mut session = EGraphSession::new(&rules, &EGraphConfig::default());
+
+        let sum = session.add(&sum);
+        assert_eq!(session.run(), EGraphEquivResult::Saturated);
+        let iterations = session.iterations();
+        assert!(iterations > 0);
+
+        // `b + a` was already derived, no further iterations are needed
+        let swapped_sum = session.add(&swapped_sum);
+        assert!(session.is_saturated());
+        assert_eq!(
+            session.check(sum, swapped_sum),
+            EGraphEquivResult::Equivalent
+        );
+        assert_eq!(session.iterations(), iterations);
+
+        let product = session.add(&product);
+        let swapped_product = session.add(&swapped_product);
+        assert!(!session.is_saturated());
+        assert_eq!(
+            session.check(product, swapped_product),
+            EGraphEquivResult::Equivalent
+        );
+        assert!(session.iterations() > iterations);
+        assert_eq!(session.run(), EGraphEquivResult::Saturated);
+        assert_eq!(session.check(sum, product), EGraphEquivResult::Saturated);
+        assert_eq!(session.len(), 4);
+    }
+}
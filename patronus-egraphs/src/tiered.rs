@@ -0,0 +1,308 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+/*!
+# Tiered Equivalence Checking
+
+Every engine is good at something different: random simulation finds most differences almost
+for free, e-graph saturation proves datapath rewrites that are hard for bit-level solvers and
+an SMT solver decides everything else, given enough time. [`check_equiv`] runs the three
+stages in this order, each with its own budget, and stops at the first stage that reaches a
+verdict. The report of every stage that ran is returned, such that slow or inconclusive
+stages can be tuned.
+
+An SMT query that runs into the timeout is not interrupted. It keeps running on a background
+thread until the solver returns, its result is discarded.
+
+!*/
+
+use crate::limits::check_equivalence_with_runner;
+use crate::{configure_runner, create_rewrites_from_config, to_arith, EGraphEquivResult, Rewrite};
+use baa::{BitVecOps, BitVecValue, Value};
+use patronus::config::EGraphConfig;
+use patronus::equiv::{prove_equiv, EquivBackend, EquivOptions, EquivResult};
+use patronus::expr::{eval_bv_expr, Context, ExprRef, ForEachChild, TypeCheck};
+use patronus::random::new_rng;
+use patronus::smt::{SmtLibSolver, BITWUZLA};
+use rustc_hash::FxHashSet;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TieredOptions {
+    /// number of random assignments to evaluate, zero skips the simulation stage
+    pub random_samples: usize,
+    pub seed: u64,
+    /// `None` skips the e-graph stage
+    pub egraph: Option<EGraphConfig>,
+    pub egraph_time_limit: Duration,
+    /// `None` skips the SMT stage
+    pub solver: Option<SmtLibSolver>,
+    pub smt_timeout: Duration,
+}
+
+impl Default for TieredOptions {
+    fn default() -> Self {
+        Self {
+            random_samples: 1000,
+            seed: patronus::random::default_seed(),
+            egraph: Some(EGraphConfig::default()),
+            egraph_time_limit: Duration::from_secs(5),
+            solver: Some(BITWUZLA),
+            smt_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Simulation,
+    EGraph,
+    Smt,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    Equivalent,
+    /// An assignment to all symbols under which the two expressions differ.
+    NotEquivalent(Vec<(ExprRef, Value)>),
+    /// No stage reached a verdict within its budget.
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StageOutcome {
+    /// Simulation did not find a difference in the given number of samples.
+    NoDifference(usize),
+    Counterexample(Vec<(ExprRef, Value)>),
+    EGraph(EGraphEquivResult),
+    Proven,
+    Timeout,
+    /// The stage could not be applied, e.g., because the e-graph does not support an operator.
+    Skipped(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StageReport {
+    pub stage: Stage,
+    pub outcome: StageOutcome,
+    pub time: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TieredResult {
+    pub verdict: Verdict,
+    /// `None` if the result is [`Verdict::Unknown`]
+    pub decided_by: Option<Stage>,
+    /// reports of all stages that ran, in order
+    pub stages: Vec<StageReport>,
+}
+
+/// Checks whether `a` and `b` are equivalent with simulation, then the e-graph and finally an
+/// SMT solver.
+pub fn check_equiv(ctx: &Context, a: ExprRef, b: ExprRef, opts: &TieredOptions) -> TieredResult {
+    assert_eq!(
+        a.get_bv_type(ctx),
+        b.get_bv_type(ctx),
+        "can only compare bit-vector expressions of the same width"
+    );
+    let mut stages = vec![];
+    let mut run = |stage: Stage, f: &mut dyn FnMut() -> StageOutcome| {
+        let start = Instant::now();
+        let outcome = f();
+        let verdict = match &outcome {
+            StageOutcome::Counterexample(assignment) => {
+                Some(Verdict::NotEquivalent(assignment.clone()))
+            }
+            StageOutcome::EGraph(EGraphEquivResult::Equivalent) | StageOutcome::Proven => {
+                Some(Verdict::Equivalent)
+            }
+            _ => None,
+        };
+        stages.push(StageReport {
+            stage,
+            outcome,
+            time: start.elapsed(),
+        });
+        verdict
+    };
+
+    let mut verdict = None;
+    if opts.random_samples > 0 {
+        verdict = verdict.or_else(|| {
+            run(Stage::Simulation, &mut || {
+                simulate(ctx, a, b, opts.random_samples, opts.seed)
+            })
+            .map(|v| (v, Stage::Simulation))
+        });
+    }
+    if let Some(config) = &opts.egraph {
+        verdict = verdict.or_else(|| {
+            run(Stage::EGraph, &mut || {
+                saturate(ctx, a, b, config, opts.egraph_time_limit)
+            })
+            .map(|v| (v, Stage::EGraph))
+        });
+    }
+    if let Some(solver) = &opts.solver {
+        verdict = verdict.or_else(|| {
+            run(Stage::Smt, &mut || {
+                solve(ctx, a, b, solver, opts.smt_timeout)
+            })
+            .map(|v| (v, Stage::Smt))
+        });
+    }
+
+    let (verdict, decided_by) = match verdict {
+        Some((verdict, stage)) => (verdict, Some(stage)),
+        None => (Verdict::Unknown, None),
+    };
+    TieredResult {
+        verdict,
+        decided_by,
+        stages,
+    }
+}
+
+fn simulate(ctx: &Context, a: ExprRef, b: ExprRef, samples: usize, seed: u64) -> StageOutcome {
+    let symbols = collect_symbols(ctx, [a, b]);
+    let Some(widths) = symbols
+        .iter()
+        .map(|s| s.get_bv_type(ctx))
+        .collect::<Option<Vec<_>>>()
+    else {
+        return StageOutcome::Skipped("arrays are not simulated".to_string());
+    };
+    let mut rng = new_rng(seed);
+    for _ in 0..samples {
+        let assignment: Vec<(ExprRef, BitVecValue)> = symbols
+            .iter()
+            .zip(widths.iter())
+            .map(|(&s, &w)| (s, BitVecValue::random(&mut rng, w)))
+            .collect();
+        let (va, vb) = (
+            eval_bv_expr(ctx, assignment.as_slice(), a),
+            eval_bv_expr(ctx, assignment.as_slice(), b),
+        );
+        if !va.is_equal(&vb) {
+            let assignment = assignment
+                .into_iter()
+                .map(|(s, v)| (s, Value::BitVec(v)))
+                .collect();
+            return StageOutcome::Counterexample(assignment);
+        }
+    }
+    StageOutcome::NoDifference(samples)
+}
+
+fn saturate(
+    ctx: &Context,
+    a: ExprRef,
+    b: ExprRef,
+    config: &EGraphConfig,
+    time_limit: Duration,
+) -> StageOutcome {
+    let converted = to_arith(ctx, a).and_then(|a| Ok((a, to_arith(ctx, b)?)));
+    let (lhs, rhs) = match converted {
+        Ok(exprs) => exprs,
+        Err(e) => return StageOutcome::Skipped(e.to_string()),
+    };
+    let rules: Vec<Rewrite> = match create_rewrites_from_config(config) {
+        Ok(rules) => rules.iter().flat_map(|r| r.to_egg()).collect(),
+        Err(e) => return StageOutcome::Skipped(e.to_string()),
+    };
+    let runner = configure_runner(egg::Runner::default().with_time_limit(time_limit), config);
+    StageOutcome::EGraph(check_equivalence_with_runner(runner, &lhs, &rhs, &rules).result)
+}
+
+fn solve(
+    ctx: &Context,
+    a: ExprRef,
+    b: ExprRef,
+    solver: &SmtLibSolver,
+    timeout: Duration,
+) -> StageOutcome {
+    let opts = EquivOptions {
+        backend: EquivBackend::Smt(solver.clone()),
+        bdd: None,
+    };
+    // the query runs on its own copy of the context, such that it can be abandoned
+    let mut ctx = ctx.clone();
+    let (send, receive) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let result = prove_equiv(&mut ctx, a, b, &opts).map_err(|e| e.to_string());
+        // the receiver is gone if we timed out
+        let _ = send.send(result);
+    });
+    match receive.recv_timeout(timeout) {
+        Ok(Ok(EquivResult::Equivalent)) => StageOutcome::Proven,
+        Ok(Ok(EquivResult::NotEquivalent(assignment))) => StageOutcome::Counterexample(assignment),
+        Ok(Err(e)) => StageOutcome::Skipped(e),
+        Err(_) => StageOutcome::Timeout,
+    }
+}
+
+/// All symbols in the order of their first appearance.
+fn collect_symbols(ctx: &Context, roots: [ExprRef; 2]) -> Vec<ExprRef> {
+    let mut visited = FxHashSet::default();
+    let mut symbols = vec![];
+    let mut todo: Vec<ExprRef> = roots.into_iter().rev().collect();
+    while let Some(e) = todo.pop() {
+        if !visited.insert(e) {
+            continue;
+        }
+        if ctx[e].is_symbol() {
+            symbols.push(e);
+        }
+        let mut children = vec![];
+        ctx[e].for_each_child(|&c| children.push(c));
+        todo.extend(children.into_iter().rev());
+    }
+    symbols
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arithmetic::verification_fig_1;
+
+    #[test]
+    fn test_tiered_stages() {
+        let mut ctx = Context::default();
+        let (spec, implementation) = verification_fig_1(&mut ctx);
+        let opts = TieredOptions {
+            solver: None,
+            seed: 1,
+            ..Default::default()
+        };
+
+        // simulation cannot prove equivalence, but the e-graph can
+        let res = check_equiv(&ctx, spec, implementation, &opts);
+        assert_eq!(res.verdict, Verdict::Equivalent);
+        assert_eq!(res.decided_by, Some(Stage::EGraph));
+        assert_eq!(res.stages.len(), 2);
+        assert_eq!(
+            res.stages[0].outcome,
+            StageOutcome::NoDifference(opts.random_samples)
+        );
+
+        // an obvious difference is found without building an e-graph
+        let a = ctx.bv_symbol("a", 8);
+        let b = ctx.bv_symbol("b", 8);
+        let (lhs, rhs) = (ctx.sub(a, b), ctx.sub(b, a));
+        let res = check_equiv(&ctx, lhs, rhs, &opts);
+        assert!(matches!(res.verdict, Verdict::NotEquivalent(_)));
+        assert_eq!(res.decided_by, Some(Stage::Simulation));
+        assert_eq!(res.stages.len(), 1);
+
+        // nothing decides if all stages are skipped
+        let nothing = TieredOptions {
+            random_samples: 0,
+            egraph: None,
+            solver: None,
+            ..Default::default()
+        };
+        let res = check_equiv(&ctx, lhs, rhs, &nothing);
+        assert_eq!(res.verdict, Verdict::Unknown);
+        assert!(res.stages.is_empty());
+    }
+}
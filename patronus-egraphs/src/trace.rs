@@ -0,0 +1,220 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+/*!
+# Proof Traces
+
+Records the sequence of rewrites that proves two arithmetic expressions equivalent.
+The trace is derived from egg's explanations and lists every rule application together with
+the widths, signs and sub-terms that the rule variables were bound to. Traces can be exported
+as JSON, e.g., to accompany a publication as a machine-checkable artifact.
+
+!*/
+
+use crate::rewrites::substitution_to_assignment;
+use crate::{configure_runner, Arith, ArithRewrite, EGraph, Rewrite};
+use egg::{AstSize, Extractor, FlatTerm, Id, Language, Pattern, RecExpr, Searcher};
+use patronus::config::EGraphConfig;
+use patronus::expr::WidthInt;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceStep {
+    pub rule: String,
+    /// `false` if the rule was applied from right to left
+    pub forward: bool,
+    /// complete term before and after the rewrite
+    pub before: String,
+    pub after: String,
+    /// values of all width and sign variables of the rule
+    pub widths: BTreeMap<String, WidthInt>,
+    /// sub-terms that all other variables of the rule were bound to
+    pub substitution: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofTrace {
+    pub lhs: String,
+    pub rhs: String,
+    /// rewrites that turn `lhs` into `rhs`
+    pub steps: Vec<TraceStep>,
+}
+
+impl ProofTrace {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("traces can always be serialized")
+    }
+
+    pub fn from_json(src: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(src)
+    }
+}
+
+/// Tries to prove `lhs` and `rhs` equivalent with `rules`. Returns `None` if saturation
+/// stops before the two expressions end up in the same e-class.
+pub fn prove_with_trace(
+    lhs: &RecExpr<Arith>,
+    rhs: &RecExpr<Arith>,
+    rules: &[ArithRewrite],
+    config: &EGraphConfig,
+) -> Option<ProofTrace> {
+    let egg_rules: Vec<Rewrite> = rules.iter().flat_map(|r| r.to_egg()).collect();
+    let mut runner = configure_runner(egg::Runner::default().with_explanations_enabled(), config)
+        .with_expr(lhs)
+        .with_expr(rhs)
+        .run(&egg_rules);
+    if runner.egraph.find(runner.roots[0]) != runner.egraph.find(runner.roots[1]) {
+        return None;
+    }
+    let mut explanation = runner.explain_equivalence(lhs, rhs);
+    let flat = explanation.make_flat_explanation();
+    let steps = flat
+        .windows(2)
+        .map(|terms| trace_step(rules, &terms[0], &terms[1]))
+        .collect();
+    Some(ProofTrace {
+        lhs: lhs.to_string(),
+        rhs: rhs.to_string(),
+        steps,
+    })
+}
+
+fn trace_step(
+    rules: &[ArithRewrite],
+    before: &FlatTerm<Arith>,
+    after: &FlatTerm<Arith>,
+) -> TraceStep {
+    let (path, rule, forward) =
+        find_rewrite(after).expect("every step of an explanation applies a rule");
+    // the lhs of the rule matches the term before a forward and after a backward rewrite
+    let matched = subterm(if forward { before } else { after }, &path);
+    let (widths, substitution) = rules
+        .iter()
+        .find(|r| r.name() == rule)
+        .map(|r| match_lhs(r, matched))
+        .unwrap_or_default();
+    TraceStep {
+        rule,
+        forward,
+        before: to_rec_expr(before).to_string(),
+        after: to_rec_expr(after).to_string(),
+        widths,
+        substitution,
+    }
+}
+
+/// Returns the path to the rewritten sub-term and the name and direction of the rule.
+fn find_rewrite(term: &FlatTerm<Arith>) -> Option<(Vec<usize>, String, bool)> {
+    if let Some(rule) = term.forward_rule {
+        return Some((vec![], rule.to_string(), true));
+    }
+    if let Some(rule) = term.backward_rule {
+        return Some((vec![], rule.to_string(), false));
+    }
+    term.children.iter().enumerate().find_map(|(ii, child)| {
+        let (mut path, rule, forward) = find_rewrite(child)?;
+        path.insert(0, ii);
+        Some((path, rule, forward))
+    })
+}
+
+fn subterm<'a>(term: &'a FlatTerm<Arith>, path: &[usize]) -> &'a FlatTerm<Arith> {
+    path.iter().fold(term, |t, &ii| &t.children[ii])
+}
+
+fn to_rec_expr(term: &FlatTerm<Arith>) -> RecExpr<Arith> {
+    fn add(term: &FlatTerm<Arith>, out: &mut RecExpr<Arith>) -> Id {
+        let children: Vec<Id> = term.children.iter().map(|c| add(c, out)).collect();
+        let mut children = children.into_iter();
+        let node = term.node.clone().map_children(|_| children.next().unwrap());
+        out.add(node)
+    }
+    let mut out = RecExpr::default();
+    add(term, &mut out);
+    out
+}
+
+/// Re-discovers the substitution by matching the lhs of the rule against the rewritten term.
+fn match_lhs(
+    rule: &ArithRewrite,
+    term: &FlatTerm<Arith>,
+) -> (BTreeMap<String, WidthInt>, BTreeMap<String, String>) {
+    let mut egraph = EGraph::default();
+    let root = egraph.add_expr(&to_rec_expr(term));
+    egraph.rebuild();
+    let lhs: Pattern<Arith> = Pattern::new(rule.patterns().0.clone());
+    let Some(subst) = lhs
+        .search_eclass(&egraph, root)
+        .and_then(|m| m.substs.into_iter().next())
+    else {
+        return Default::default();
+    };
+    let assignment = substitution_to_assignment(&egraph, &subst, &lhs.ast);
+    let extractor = Extractor::new(&egraph, AstSize);
+    let substitution = lhs
+        .vars()
+        .into_iter()
+        .filter(|v| !assignment.iter().any(|(a, _)| a == v))
+        .map(|v| (v.to_string(), extractor.find_best(subst[v]).1.to_string()))
+        .collect();
+    let widths = assignment
+        .into_iter()
+        .map(|(v, w)| (v.to_string(), w))
+        .collect();
+    (widths, substitution)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arithmetic::verification_fig_1;
+    use crate::{create_rewrites, to_arith};
+    use patronus::expr::Context;
+
+    #[test]
+    fn test_commute_add_trace() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("A", 16);
+        let b = ctx.bv_symbol("B", 16);
+        let lhs = ctx.add(a, b);
+        let rhs = ctx.add(b, a);
+        let trace = prove_with_trace(
+            &to_arith(&ctx, lhs).unwrap(),
+            &to_arith(&ctx, rhs).unwrap(),
+            &create_rewrites(),
+            &EGraphConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(trace.steps.len(), 1);
+        let step = &trace.steps[0];
+        assert_eq!(step.rule, "commute-add");
+        assert_eq!(step.widths["?wa"], 16);
+        assert_eq!(step.widths["?sa"], 0);
+        let a = if step.forward { "A" } else { "B" };
+        assert_eq!(step.substitution["?a"], a);
+        assert_eq!(step.before, trace.lhs);
+        assert_eq!(step.after, trace.rhs);
+    }
+
+    #[test]
+    fn test_fig_1_trace_roundtrip() {
+        let mut ctx = Context::default();
+        let (spec, implementation) = verification_fig_1(&mut ctx);
+        let rules = create_rewrites();
+        let trace = prove_with_trace(
+            &to_arith(&ctx, spec).unwrap(),
+            &to_arith(&ctx, implementation).unwrap(),
+            &rules,
+            &EGraphConfig::default(),
+        )
+        .unwrap();
+        assert!(!trace.steps.is_empty());
+        assert!(trace
+            .steps
+            .iter()
+            .all(|s| rules.iter().any(|r| r.name() == s.rule)));
+        assert_eq!(trace.steps.last().unwrap().after, trace.rhs);
+        assert_eq!(ProofTrace::from_json(&trace.to_json()).unwrap(), trace);
+    }
+}
@@ -0,0 +1,229 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+/*!
+# Width Inference
+
+[`to_arith`] derives the width and sign of every operand from explicit zero and sign
+extensions. Expressions that come from other front ends often compute everything at the
+output width instead. Here we determine the smallest width and sign that can represent every
+value of an operand and make it explicit by narrowing the operand and extending it again.
+The result is equivalent to the original expression.
+
+!*/
+
+use crate::{to_arith, Arith, EGraphError, Sign};
+use baa::BitVecOps;
+use egg::RecExpr;
+use patronus::expr::*;
+use std::cmp::max;
+
+/// Converts `e` after making the minimal width and sign of all operands explicit.
+pub fn to_arith_with_inferred_widths(
+    ctx: &mut Context,
+    e: ExprRef,
+) -> Result<RecExpr<Arith>, EGraphError> {
+    let e = infer_extensions(ctx, e);
+    to_arith(ctx, e)
+}
+
+/// Replaces every operand of an arithmetic operation that needs fewer bits than its width with
+/// an extension of the narrowed operand.
+pub fn infer_extensions(ctx: &mut Context, e: ExprRef) -> ExprRef {
+    simple_transform_expr(ctx, e, |ctx, e, children| {
+        let expr = ctx[e].clone();
+        let (a, b) = match expr {
+            Expr::BVAdd(..)
+            | Expr::BVSub(..)
+            | Expr::BVMul(..)
+            | Expr::BVShiftLeft(..)
+            | Expr::BVShiftRight(..)
+            | Expr::BVArithmeticShiftRight(..) => (children[0], children[1]),
+            _ => return None,
+        };
+        let (a, b) = (extend_operand(ctx, a), extend_operand(ctx, b));
+        let res = match expr {
+            Expr::BVAdd(..) => ctx.add(a, b),
+            Expr::BVSub(..) => ctx.sub(a, b),
+            Expr::BVMul(..) => ctx.mul(a, b),
+            Expr::BVShiftLeft(..) => ctx.shift_left(a, b),
+            Expr::BVShiftRight(..) => ctx.shift_right(a, b),
+            Expr::BVArithmeticShiftRight(..) => ctx.arithmetic_shift_right(a, b),
+            _ => unreachable!(),
+        };
+        Some(res)
+    })
+}
+
+fn extend_operand(ctx: &mut Context, e: ExprRef) -> ExprRef {
+    let width = e.get_bv_type(ctx).unwrap();
+    let (needed, sign) = needed_width(ctx, e);
+    if needed >= width {
+        return e;
+    }
+    let narrowed = narrow(ctx, e, needed);
+    match sign {
+        Sign::Unsigned => ctx.zero_extend(narrowed, width - needed),
+        Sign::Signed => ctx.sign_extend(narrowed, width - needed),
+    }
+}
+
+/// Smallest width and sign that represent every value of `e`.
+fn needed_width(ctx: &Context, e: ExprRef) -> (WidthInt, Sign) {
+    let width = e.get_bv_type(ctx).unwrap();
+    let full = (width, Sign::Unsigned);
+    match ctx[e] {
+        Expr::BVLiteral(value) => match value.get(ctx).to_u64() {
+            Some(value) => literal_width(value, width),
+            None => full,
+        },
+        Expr::BVZeroExt { e, .. } => match needed_width(ctx, e) {
+            (w, Sign::Unsigned) => (w, Sign::Unsigned),
+            (_, Sign::Signed) => (e.get_bv_type(ctx).unwrap(), Sign::Unsigned),
+        },
+        Expr::BVSignExt { e, .. } => {
+            let inner = e.get_bv_type(ctx).unwrap();
+            match needed_width(ctx, e) {
+                // the msb is zero, thus sign and zero extension are the same
+                (w, Sign::Unsigned) if w < inner => (w, Sign::Unsigned),
+                (w, Sign::Signed) => (w, Sign::Signed),
+                _ => (inner, Sign::Signed),
+            }
+        }
+        Expr::BVAdd(a, b, _) | Expr::BVMul(a, b, _) => {
+            let ((wa, sa), (wb, sb)) = (needed_width(ctx, a), needed_width(ctx, b));
+            if sa != sb {
+                return full;
+            }
+            let w = if matches!(ctx[e], Expr::BVAdd(..)) {
+                max(wa, wb).saturating_add(1)
+            } else {
+                wa.saturating_add(wb)
+            };
+            if w < width {
+                (w, sa)
+            } else {
+                full
+            }
+        }
+        _ => full,
+    }
+}
+
+fn literal_width(value: u64, width: WidthInt) -> (WidthInt, Sign) {
+    let unsigned = max(1, u64::BITS - value.leading_zeros());
+    if width > u64::BITS || (value >> (width - 1)) & 1 == 0 {
+        return (unsigned, Sign::Unsigned);
+    }
+    let sign_extended = if width < u64::BITS {
+        value | (u64::MAX << width)
+    } else {
+        value
+    };
+    // one sign bit plus everything below the leading ones
+    let signed = u64::BITS - (!sign_extended).leading_zeros() + 1;
+    if signed < unsigned {
+        (signed, Sign::Signed)
+    } else {
+        (unsigned, Sign::Unsigned)
+    }
+}
+
+/// Returns an expression of `width` bits that represents the same value as `e`.
+/// Requires that `width` is at least [`needed_width`].
+fn narrow(ctx: &mut Context, e: ExprRef, width: WidthInt) -> ExprRef {
+    if e.get_bv_type(ctx).unwrap() == width {
+        return e;
+    }
+    match ctx[e].clone() {
+        Expr::BVLiteral(value) => {
+            let value = value.get(ctx).to_u64().unwrap();
+            let mask = if width < u64::BITS {
+                (1u64 << width) - 1
+            } else {
+                u64::MAX
+            };
+            ctx.bit_vec_val(value & mask, width)
+        }
+        Expr::BVZeroExt { e: inner, .. } | Expr::BVSignExt { e: inner, .. } => {
+            let inner_width = inner.get_bv_type(ctx).unwrap();
+            if width > inner_width {
+                if matches!(ctx[e], Expr::BVZeroExt { .. }) {
+                    ctx.zero_extend(inner, width - inner_width)
+                } else {
+                    ctx.sign_extend(inner, width - inner_width)
+                }
+            } else {
+                narrow(ctx, inner, width)
+            }
+        }
+        Expr::BVAdd(a, b, _) => {
+            let (a, b) = (narrow(ctx, a, width), narrow(ctx, b, width));
+            ctx.add(a, b)
+        }
+        Expr::BVMul(a, b, _) => {
+            let (a, b) = (narrow(ctx, a, width), narrow(ctx, b, width));
+            ctx.mul(a, b)
+        }
+        _ => unreachable!("{} cannot be narrowed", e.serialize_to_str(ctx)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use patronus::equiv::{prove_equiv, EquivBackend, EquivOptions, EquivResult};
+
+    fn assert_equiv(ctx: &mut Context, a: ExprRef, b: ExprRef) {
+        let opts = EquivOptions {
+            backend: EquivBackend::Sat,
+            bdd: None,
+        };
+        assert_eq!(
+            prove_equiv(ctx, a, b, &opts).unwrap(),
+            EquivResult::Equivalent
+        );
+    }
+
+    #[test]
+    fn test_literal_width() {
+        assert_eq!(literal_width(3, 8), (2, Sign::Unsigned));
+        assert_eq!(literal_width(0, 8), (1, Sign::Unsigned));
+        assert_eq!(literal_width(0xfe, 8), (2, Sign::Signed));
+        assert_eq!(literal_width(0xff, 8), (1, Sign::Signed));
+        assert_eq!(literal_width(0x80, 8), (8, Sign::Unsigned));
+    }
+
+    #[test]
+    fn test_infer_widths() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("a", 4);
+        let b = ctx.bv_symbol("b", 4);
+        let c = ctx.bv_symbol("c", 8);
+        // (zext(a) + zext(b)) * c, everything computed with 8 bits
+        let e = ctx.build(|x| {
+            x.mul(
+                x.add(x.zero_extend(a, 4), x.zero_extend(b, 4)),
+                x.add(c, x.bit_vec_val(3, 8)),
+            )
+        });
+        let inferred = infer_extensions(&mut ctx, e);
+        assert_equiv(&mut ctx, e, inferred);
+        assert_eq!(
+            to_arith_with_inferred_widths(&mut ctx, e)
+                .unwrap()
+                .to_string(),
+            "(* W<8> W<5> unsign (+ W<5> W<4> unsign a W<4> unsign b) \
+             W<8> unsign (+ W<8> W<8> unsign c W<2> unsign 3))"
+        );
+
+        // a negative constant
+        let e = ctx.build(|x| x.add(x.sign_extend(a, 4), x.bit_vec_val(0xfe, 8)));
+        let inferred = infer_extensions(&mut ctx, e);
+        assert_equiv(&mut ctx, e, inferred);
+        assert_eq!(
+            to_arith(&ctx, inferred).unwrap().to_string(),
+            "(+ W<8> W<4> sign a W<2> sign 2)"
+        );
+    }
+}
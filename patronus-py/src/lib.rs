@@ -0,0 +1,247 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+//! # Python Bindings
+//!
+//! Thin wrappers around `Context`, `TransitionSystem` and the interpreter.
+//! Bit-vector values are exchanged as Python `int`s, which support arbitrary widths.
+//! For use with numpy, `Simulator.get_words` returns the value as a list of
+//! 64-bit words with the least significant word first.
+
+use baa::{BitVecOps, BitVecValue, Value};
+use patronus::expr::{self, ExprRef, SerializableIrNode, TypeCheck, WidthInt};
+use patronus::sim::{InitKind, Interpreter, Simulator as _};
+use patronus::system;
+use pyo3::exceptions::{PyKeyError, PyValueError};
+use pyo3::prelude::*;
+use std::mem::ManuallyDrop;
+
+/// Reference to an expression inside a `Context`.
+#[pyclass(frozen, eq, hash)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct Expr(ExprRef);
+
+#[pyclass(unsendable)]
+#[derive(Clone, Default)]
+struct Context(expr::Context);
+
+#[pymethods]
+impl Context {
+    #[new]
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn bv_symbol(&mut self, name: &str, width: WidthInt) -> Expr {
+        Expr(self.0.bv_symbol(name, width))
+    }
+
+    fn bit_vec_val(&mut self, value: &Bound<'_, PyAny>, width: WidthInt) -> PyResult<Expr> {
+        let value = int_to_bit_vec(value, width)?;
+        Ok(Expr(self.0.bv_lit(&value)))
+    }
+
+    fn parse_expr(&mut self, s: &str) -> Expr {
+        Expr(expr::parse_expr(&mut self.0, s))
+    }
+
+    fn serialize(&self, e: Expr) -> String {
+        e.0.serialize_to_str(&self.0)
+    }
+
+    fn width(&self, e: Expr) -> Option<WidthInt> {
+        e.0.get_bv_type(&self.0)
+    }
+
+    fn not_(&mut self, e: Expr) -> Expr {
+        Expr(self.0.not(e.0))
+    }
+    fn and_(&mut self, a: Expr, b: Expr) -> Expr {
+        Expr(self.0.and(a.0, b.0))
+    }
+    fn or_(&mut self, a: Expr, b: Expr) -> Expr {
+        Expr(self.0.or(a.0, b.0))
+    }
+    fn xor(&mut self, a: Expr, b: Expr) -> Expr {
+        Expr(self.0.xor(a.0, b.0))
+    }
+    fn add(&mut self, a: Expr, b: Expr) -> Expr {
+        Expr(self.0.add(a.0, b.0))
+    }
+    fn sub(&mut self, a: Expr, b: Expr) -> Expr {
+        Expr(self.0.sub(a.0, b.0))
+    }
+    fn mul(&mut self, a: Expr, b: Expr) -> Expr {
+        Expr(self.0.mul(a.0, b.0))
+    }
+    fn equal(&mut self, a: Expr, b: Expr) -> Expr {
+        Expr(self.0.equal(a.0, b.0))
+    }
+    fn ite(&mut self, cond: Expr, tru: Expr, fals: Expr) -> Expr {
+        Expr(self.0.ite(cond.0, tru.0, fals.0))
+    }
+    fn concat(&mut self, a: Expr, b: Expr) -> Expr {
+        Expr(self.0.concat(a.0, b.0))
+    }
+    fn slice(&mut self, e: Expr, hi: WidthInt, lo: WidthInt) -> Expr {
+        Expr(self.0.slice(e.0, hi, lo))
+    }
+    fn zero_extend(&mut self, e: Expr, by: WidthInt) -> Expr {
+        Expr(self.0.zero_extend(e.0, by))
+    }
+    fn sign_extend(&mut self, e: Expr, by: WidthInt) -> Expr {
+        Expr(self.0.sign_extend(e.0, by))
+    }
+}
+
+#[pyclass(unsendable)]
+#[derive(Clone)]
+struct TransitionSystem(system::TransitionSystem);
+
+#[pymethods]
+impl TransitionSystem {
+    #[getter]
+    fn name(&self) -> String {
+        self.0.name.clone()
+    }
+
+    fn states(&self) -> Vec<Expr> {
+        self.0.states.iter().map(|s| Expr(s.symbol)).collect()
+    }
+
+    fn inputs(&self) -> Vec<Expr> {
+        self.0.inputs.iter().map(|&i| Expr(i)).collect()
+    }
+
+    fn serialize(&self, ctx: &Context) -> String {
+        self.0.serialize_to_str(&ctx.0)
+    }
+
+    fn lookup(&self, ctx: &Context, name: &str) -> Option<Expr> {
+        self.0.get_name_map(&ctx.0).get(name).map(|&e| Expr(e))
+    }
+}
+
+/// Loads a btor2 file and returns the context together with the transition system.
+#[pyfunction]
+fn load_btor2(filename: &str) -> PyResult<(Context, TransitionSystem)> {
+    match patronus::btor2::parse_file(filename) {
+        Some((ctx, sys)) => Ok((Context(ctx), TransitionSystem(sys))),
+        None => Err(PyValueError::new_err(format!("failed to load {filename}"))),
+    }
+}
+
+/// Interpreter based simulator. Owns a copy of the context and the system that it simulates.
+#[pyclass(unsendable)]
+struct Simulator {
+    /// borrows from `design`, thus it needs to be dropped first
+    sim: ManuallyDrop<Interpreter<'static>>,
+    design: *mut (expr::Context, system::TransitionSystem),
+}
+
+impl Drop for Simulator {
+    fn drop(&mut self) {
+        unsafe {
+            ManuallyDrop::drop(&mut self.sim);
+            drop(Box::from_raw(self.design));
+        }
+    }
+}
+
+impl Simulator {
+    fn ctx(&self) -> &expr::Context {
+        // SAFETY: `design` stays valid for the lifetime of `self`
+        unsafe { &(*self.design).0 }
+    }
+}
+
+#[pymethods]
+impl Simulator {
+    #[new]
+    fn new(ctx: &Context, sys: &TransitionSystem) -> Self {
+        let design = Box::into_raw(Box::new((ctx.0.clone(), sys.0.clone())));
+        // SAFETY: `design` is only freed in `drop`, after the interpreter is gone
+        let (ctx, sys) = unsafe { &*design };
+        let sim = ManuallyDrop::new(Interpreter::new(ctx, sys));
+        Self { sim, design }
+    }
+
+    /// Initializes states and inputs to zero or, if a `seed` is provided, to random values.
+    #[pyo3(signature = (seed=None))]
+    fn init(&mut self, seed: Option<u64>) {
+        let kind = match seed {
+            None => InitKind::Zero,
+            Some(seed) => InitKind::Random(seed),
+        };
+        self.sim.init(kind);
+    }
+
+    fn step(&mut self) {
+        self.sim.step();
+    }
+
+    #[getter]
+    fn step_count(&self) -> u64 {
+        self.sim.step_count()
+    }
+
+    fn set(&mut self, e: Expr, value: &Bound<'_, PyAny>) -> PyResult<()> {
+        let width =
+            e.0.get_bv_type(self.ctx())
+                .ok_or_else(|| PyValueError::new_err("only bit-vectors can be set"))?;
+        let value = int_to_bit_vec(value, width)?;
+        self.sim
+            .set(e.0, &value)
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    fn get<'py>(&self, py: Python<'py>, e: Expr) -> PyResult<Bound<'py, PyAny>> {
+        let value = self.get_bit_vec(e)?;
+        let int = py.import_bound("builtins")?.getattr("int")?;
+        int.call1((value.to_hex_str(), 16))
+    }
+
+    /// Returns the value as 64-bit words, least significant word first.
+    fn get_words(&self, e: Expr) -> PyResult<Vec<u64>> {
+        Ok(self.get_bit_vec(e)?.words().to_vec())
+    }
+
+    fn take_snapshot(&mut self) -> u32 {
+        self.sim.take_snapshot()
+    }
+
+    fn restore_snapshot(&mut self, id: u32) -> PyResult<()> {
+        self.sim
+            .restore_snapshot(id)
+            .map_err(|e| PyKeyError::new_err(e.to_string()))
+    }
+}
+
+impl Simulator {
+    fn get_bit_vec(&self, e: Expr) -> PyResult<BitVecValue> {
+        match self.sim.get(e.0) {
+            Value::BitVec(v) => Ok(v),
+            Value::Array(_) => Err(PyKeyError::new_err("arrays are not supported")),
+        }
+    }
+}
+
+/// Converts a non-negative python integer into a bit-vector of the requested width.
+fn int_to_bit_vec(value: &Bound<'_, PyAny>, width: WidthInt) -> PyResult<BitVecValue> {
+    let hex: String = value.call_method1("__format__", ("x",))?.extract()?;
+    if hex.starts_with('-') {
+        return Err(PyValueError::new_err("negative values are not supported"));
+    }
+    BitVecValue::from_str_radix(&hex, 16, width)
+        .map_err(|_| PyValueError::new_err(format!("{hex} does not fit into {width} bits")))
+}
+
+#[pymodule]
+fn patronus_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Expr>()?;
+    m.add_class::<Context>()?;
+    m.add_class::<TransitionSystem>()?;
+    m.add_class::<Simulator>()?;
+    m.add_function(wrap_pyfunction!(load_btor2, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,20 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use patronus::bench::{run_simulation, suite};
+use patronus::sim::Backend;
+
+fn simulation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("simulation");
+    for bench in suite() {
+        group.bench_function(&bench.name, |b| {
+            b.iter(|| run_simulation(&bench, Backend::Interpreter, 100))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, simulation);
+criterion_main!(benches);
@@ -0,0 +1,165 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Benchmarks
+//! Generated systems that represent common workloads together with functions that time how
+//! long it takes to simulate them. The suite is used to track performance regressions and
+//! can be run by users to size their expectations for their own hardware.
+//! Only available with the `bench` feature.
+
+use crate::expr::{Context, ExprRef, TypeCheck, WidthInt};
+use crate::sim::{create, Backend, InitKind, Simulator};
+use crate::system::{State, TransitionSystem};
+use std::time::{Duration, Instant};
+
+/// A generated transition system together with the context it was created in.
+pub struct BenchSystem {
+    pub name: String,
+    pub ctx: Context,
+    pub sys: TransitionSystem,
+}
+
+impl BenchSystem {
+    fn new(name: String) -> Self {
+        let sys = TransitionSystem::new(name.clone());
+        Self {
+            name,
+            ctx: Context::default(),
+            sys,
+        }
+    }
+
+    fn input(&mut self, name: &str, width: WidthInt) -> ExprRef {
+        let input = self.ctx.bv_symbol(name, width);
+        self.sys.add_input(&self.ctx, input);
+        input
+    }
+
+    fn state(&mut self, symbol: ExprRef, next: ExprRef) {
+        let width = symbol.get_bv_type(&self.ctx).unwrap();
+        let init = self.ctx.zero(width);
+        self.sys.add_state(
+            &self.ctx,
+            State {
+                symbol,
+                init: Some(init),
+                next: Some(next),
+            },
+        );
+    }
+}
+
+/// A register that is updated through a chain of alternating left and right shifts by
+/// input controlled amounts.
+pub fn shift_network(width: WidthInt, stages: usize) -> BenchSystem {
+    let mut bench = BenchSystem::new(format!("shift_network_{width}x{stages}"));
+    let data = bench.input("data", width);
+    let amounts: Vec<_> = (0..stages)
+        .map(|ii| bench.input(&format!("shamt_{ii}"), width))
+        .collect();
+    let reg = bench.ctx.bv_symbol("reg", width);
+    let mut value = bench.ctx.add(reg, data);
+    for (ii, &amount) in amounts.iter().enumerate() {
+        value = if ii % 2 == 0 {
+            bench.ctx.shift_left(value, amount)
+        } else {
+            bench.ctx.shift_right(value, amount)
+        };
+    }
+    bench.state(reg, value);
+    bench.sys.add_output(&mut bench.ctx, "out".into(), reg);
+    bench
+}
+
+/// An accumulator that adds the product of `leaves` inputs, multiplied in a balanced tree.
+pub fn multiplier_tree(width: WidthInt, leaves: usize) -> BenchSystem {
+    assert!(leaves > 0, "a multiplier tree needs at least one leaf");
+    let mut bench = BenchSystem::new(format!("multiplier_tree_{width}x{leaves}"));
+    let mut level: Vec<_> = (0..leaves)
+        .map(|ii| bench.input(&format!("x_{ii}"), width))
+        .collect();
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| match *pair {
+                [a, b] => bench.ctx.mul(a, b),
+                [a] => a,
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+    let acc = bench.ctx.bv_symbol("acc", width);
+    let next = bench.ctx.add(acc, level[0]);
+    bench.state(acc, next);
+    bench.sys.add_output(&mut bench.ctx, "out".into(), acc);
+    bench
+}
+
+/// `count` independent counters with different increments that share an enable input.
+pub fn counters(width: WidthInt, count: usize) -> BenchSystem {
+    let mut bench = BenchSystem::new(format!("counters_{width}x{count}"));
+    let en = bench.input("en", 1);
+    for ii in 0..count {
+        let counter = bench.ctx.bv_symbol(&format!("count_{ii}"), width);
+        let next = bench.ctx.build(|c| {
+            c.ite(
+                en,
+                c.add(counter, c.bit_vec_val(ii as u64 + 1, width)),
+                counter,
+            )
+        });
+        bench.state(counter, next);
+    }
+    bench
+}
+
+/// The standard benchmark suite: every generator at a narrow, a machine word and a wide width.
+pub fn suite() -> Vec<BenchSystem> {
+    [8, 64, 256]
+        .into_iter()
+        .flat_map(|width| {
+            [
+                shift_network(width, 8),
+                multiplier_tree(width, 16),
+                counters(width, 64),
+            ]
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchResult {
+    pub name: String,
+    pub steps: u64,
+    pub elapsed: Duration,
+}
+
+impl BenchResult {
+    pub fn steps_per_second(&self) -> f64 {
+        self.steps as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+/// Simulates `bench` for `steps` cycles with random inputs that are fixed after init.
+pub fn run_simulation(bench: &BenchSystem, backend: Backend, steps: u64) -> BenchResult {
+    let (mut sim, _) = create(&bench.ctx, &bench.sys, backend);
+    sim.init(InitKind::Random(0));
+    let start = Instant::now();
+    for _ in 0..steps {
+        sim.step();
+    }
+    BenchResult {
+        name: bench.name.clone(),
+        steps,
+        elapsed: start.elapsed(),
+    }
+}
+
+/// Runs [`run_simulation`] on every system in the [`suite`].
+pub fn run_simulation_suite(backend: Backend, steps: u64) -> Vec<BenchResult> {
+    suite()
+        .iter()
+        .map(|b| run_simulation(b, backend, steps))
+        .collect()
+}
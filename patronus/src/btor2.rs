@@ -1,12 +1,15 @@
 // Copyright 2023 The Regents of the University of California
 // released under BSD 3-Clause License
 // author: Kevin Laeufer <laeufer@berkeley.edu>
+mod cache;
 mod parse;
 mod serialize;
 mod witness;
 
+pub use cache::{CachedDesign, DesignCache};
 pub use parse::{
-    parse_file, parse_file_with_ctx, parse_str, DEFAULT_INPUT_PREFIX, DEFAULT_STATE_PREFIX,
+    parse_file, parse_file_with_ctx, parse_file_with_options, parse_str, ParseOptions,
+    DEFAULT_INPUT_PREFIX, DEFAULT_STATE_PREFIX,
 };
 pub use serialize::{serialize, serialize_to_str};
 pub use witness::{parse_witness, parse_witnesses, print_witness, witness_to_string};
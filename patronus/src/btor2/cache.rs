@@ -0,0 +1,117 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Design Cache
+//! Long running processes, like a simulation server, often load the same design over and over
+//! again in an edit-compile-check loop. [`DesignCache`] keeps every parsed design in memory
+//! and only parses a file again once its modification time or size changed.
+
+use super::parse_file;
+use crate::expr::Context;
+use crate::system::TransitionSystem;
+use rustc_hash::FxHashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+pub type CachedDesign = Arc<(Context, TransitionSystem)>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl Stamp {
+    fn of(path: &Path) -> Option<Self> {
+        let meta = std::fs::metadata(path).ok()?;
+        Some(Self {
+            modified: meta.modified().ok(),
+            len: meta.len(),
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct DesignCache {
+    entries: FxHashMap<PathBuf, (Stamp, CachedDesign)>,
+    hits: usize,
+    misses: usize,
+}
+
+impl DesignCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the parsed design in `path`, parsing it only if it is not cached or changed on
+    /// disk. Returns `None` if the file cannot be read or parsed.
+    pub fn load(&mut self, path: impl AsRef<Path>) -> Option<CachedDesign> {
+        let path = path.as_ref();
+        let key = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let stamp = Stamp::of(&key)?;
+        match self.entries.get(&key) {
+            Some((cached, design)) if *cached == stamp => {
+                self.hits += 1;
+                Some(design.clone())
+            }
+            _ => {
+                self.misses += 1;
+                let design = Arc::new(parse_file(&key)?);
+                self.entries.insert(key, (stamp, design.clone()));
+                Some(design)
+            }
+        }
+    }
+
+    /// Number of loads that were answered from the cache.
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// Number of loads that required parsing a file.
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_design_cache() {
+        let mut cache = DesignCache::new();
+        let first = cache.load("../inputs/unittest/delay.btor").unwrap();
+        let second = cache.load("../inputs/unittest/delay.btor").unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+        assert_eq!(first.1.states.len(), 2);
+
+        // a changed file is parsed again
+        let path = std::env::temp_dir().join(format!("design_cache_{}.btor", std::process::id()));
+        std::fs::write(&path, "1 sort bitvec 8\n2 input 1 a\n").unwrap();
+        let before = cache.load(&path).unwrap();
+        std::fs::write(&path, "1 sort bitvec 8\n2 input 1 a\n3 input 1 b\n").unwrap();
+        let after = cache.load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(before.1.inputs.len(), 1);
+        assert_eq!(after.1.inputs.len(), 2);
+        assert_eq!(cache.misses(), 3);
+        assert!(cache.load("does/not/exist.btor").is_none());
+        assert_eq!(cache.len(), 2);
+    }
+}
@@ -10,8 +10,30 @@ use rustc_hash::{FxHashMap, FxHashSet};
 use smallvec::SmallVec;
 use std::borrow::Cow;
 
+/// Options that mostly matter when parsing very large files.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ParseOptions {
+    /// Do not record the names of intermediate signals, which saves memory on large designs.
+    /// Names of states, inputs, outputs, bad states and constraints are always kept.
+    pub skip_signal_names: bool,
+    /// Expected number of lines, used to pre-allocate internal tables.
+    /// When parsing a file, this is estimated from the file size if not provided.
+    pub line_count_hint: Option<usize>,
+    /// Report init expressions that depend on each other or on states that are declared later,
+    /// see [`validate_states`].
+    pub validate_states: bool,
+    /// Warn about constraints that simplify to true or false, see [`find_trivial_constraints`].
+    pub check_constraints: bool,
+    /// Records every named intermediate signal as a define, such that it survives
+    /// simplifications and can be inspected in the simulator.
+    pub keep_named_nets: bool,
+}
+
+/// Rough average number of bytes in a btor2 line, used to estimate the line count from the file size.
+const AVERAGE_LINE_LENGTH: u64 = 24;
+
 pub fn parse_str(ctx: &mut Context, input: &str, name: Option<&str>) -> Option<TransitionSystem> {
-    match Parser::new(ctx).parse(input.as_bytes(), name) {
+    match Parser::new(ctx, ParseOptions::default()).parse(input.as_bytes(), name) {
         Ok(sys) => Some(sys),
         Err(errors) => {
             report_errors(errors, "str", input);
@@ -29,18 +51,39 @@ pub fn parse_file<P: AsRef<std::path::Path>>(filename: P) -> Option<(Context, Tr
 pub fn parse_file_with_ctx<P: AsRef<std::path::Path>>(
     filename: P,
     ctx: &mut Context,
+) -> Option<TransitionSystem> {
+    parse_file_with_options(filename, ctx, ParseOptions::default())
+}
+
+/// Parses the file line by line, without ever loading it into memory completely.
+pub fn parse_file_with_options<P: AsRef<std::path::Path>>(
+    filename: P,
+    ctx: &mut Context,
+    mut options: ParseOptions,
 ) -> Option<TransitionSystem> {
     let path = filename.as_ref();
-    let f = std::fs::File::open(path).expect("Failed to open btor file!");
+    let f = match std::fs::File::open(path) {
+        Ok(f) => f,
+        Err(e) => {
+            report_io_error(path, &e);
+            return None;
+        }
+    };
+    if options.line_count_hint.is_none() {
+        options.line_count_hint = f
+            .metadata()
+            .ok()
+            .map(|m| (m.len() / AVERAGE_LINE_LENGTH) as usize);
+    }
     let reader = std::io::BufReader::new(f);
     let backup_name = path.file_stem().and_then(|n| n.to_str());
-    match Parser::new(ctx).parse(reader, backup_name) {
+    match Parser::new(ctx, options).parse(reader, backup_name) {
         Ok(sys) => Some(sys),
         Err(errors) => {
             report_errors(
                 errors,
                 path.file_name().unwrap().to_str().unwrap(),
-                &std::fs::read_to_string(path).unwrap(),
+                &std::fs::read_to_string(path).unwrap_or_default(),
             );
             None
         }
@@ -61,6 +104,9 @@ struct Parser<'a> {
     signal_map: FxHashMap<LineId, ExprRef>,
     /// keeps track of names in order to uniquify them
     unique_names: FxHashSet<String>,
+    options: ParseOptions,
+    /// location of the init line of every state index, only recorded when validating states
+    init_lines: FxHashMap<usize, (usize, usize)>,
 }
 
 type LineId = u32;
@@ -72,7 +118,8 @@ pub const DEFAULT_BAD_STATE_PREFIX: &str = "_bad";
 pub const DEFAULT_CONSTRAINT_PREFIX: &str = "_constraint";
 
 impl<'a> Parser<'a> {
-    fn new(ctx: &'a mut Context) -> Self {
+    fn new(ctx: &'a mut Context, options: ParseOptions) -> Self {
+        let lines = options.line_count_hint.unwrap_or_default();
         Parser {
             ctx,
             sys: TransitionSystem::new("".to_string()),
@@ -80,14 +127,16 @@ impl<'a> Parser<'a> {
             offset: 0,
             type_map: FxHashMap::default(),
             state_map: FxHashMap::default(),
-            signal_map: FxHashMap::default(),
+            signal_map: FxHashMap::with_capacity_and_hasher(lines, Default::default()),
             unique_names: FxHashSet::default(),
+            options,
+            init_lines: FxHashMap::default(),
         }
     }
 
     fn parse(
         &mut self,
-        input: impl std::io::BufRead,
+        mut input: impl std::io::BufRead,
         backup_name: Option<&str>,
     ) -> Result<TransitionSystem, Errors> {
         // ensure that default input and state names are reserved in order to get nicer names
@@ -103,10 +152,16 @@ impl<'a> Parser<'a> {
             .map(|s| s.to_string()),
         );
 
-        for line_res in input.lines() {
-            let line = line_res.expect("failed to read line");
-            let _ignore_errors = self.parse_line(&line);
-            self.offset += line.len() + 1; // TODO: this assumes that the line terminates with a single character
+        // re-use the same line buffer in order to avoid an allocation per line
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let num_bytes = input.read_line(&mut line).expect("failed to read line");
+            if num_bytes == 0 {
+                break;
+            }
+            let _ignore_errors = self.parse_line(line.trim_end_matches(['\n', '\r']));
+            self.offset += num_bytes;
         }
 
         // get a better name if none could be determined from the file content
@@ -121,6 +176,15 @@ impl<'a> Parser<'a> {
         // better_name = zext(__state__, 0)
         improve_state_names(self.ctx, &mut self.sys);
 
+        // needs to happen before states are demoted, since we identify states by their index
+        if self.options.validate_states {
+            self.report_invalid_states();
+        }
+
+        if self.options.check_constraints {
+            self.warn_trivial_constraints();
+        }
+
         // demote states without next or init to input
         for state in self.sys.states.iter() {
             if state.init.is_none() && state.next.is_none() {
@@ -142,6 +206,49 @@ impl<'a> Parser<'a> {
         }
     }
 
+    fn report_invalid_states(&mut self) {
+        let validation = validate_states(self.ctx, &self.sys);
+        let index: FxHashMap<ExprRef, usize> = self
+            .sys
+            .states
+            .iter()
+            .enumerate()
+            .map(|(ii, s)| (s.symbol, ii))
+            .collect();
+        for cycle in validation.init_cycles.iter() {
+            let (start, end) = self.init_lines[&index[&cycle.states[0]]];
+            let msg = cycle.describe(self.ctx);
+            self.errors.push(ParserError {
+                msg,
+                explain: "init expression depends on itself".to_string(),
+                start,
+                end,
+            });
+        }
+        for &(state, dependency) in validation.out_of_order.iter() {
+            let (start, end) = self.init_lines[&index[&state]];
+            let msg = format!(
+                "init expression of {} refers to {}, which is initialized later",
+                self.ctx.get_symbol_name(state).unwrap(),
+                self.ctx.get_symbol_name(dependency).unwrap()
+            );
+            self.errors.push(ParserError {
+                msg,
+                explain: "move the init of the referenced state before this line".to_string(),
+                start,
+                end,
+            });
+        }
+    }
+
+    fn warn_trivial_constraints(&mut self) {
+        let trivial = find_trivial_constraints(self.ctx, &self.sys, None)
+            .expect("structural checks do not use a solver");
+        for constraint in trivial {
+            report_warning(&constraint.describe(self.ctx, &self.sys));
+        }
+    }
+
     fn parse_line(&mut self, line: &str) -> ParseLineResult {
         let cont = tokenize_line(line);
         let tokens = &cont.tokens;
@@ -210,7 +317,13 @@ impl<'a> Parser<'a> {
                             self.sys.constraints.push(expr);
                             self.get_label_name(&cont, DEFAULT_CONSTRAINT_PREFIX)
                         }
-                        "fair" => todo!("support fairness constraints"),
+                        "fair" => {
+                            return self.add_error(
+                                line,
+                                op,
+                                "fairness constraints are not supported".to_owned(),
+                            );
+                        }
                         _ => unreachable!(),
                     };
 
@@ -221,7 +334,11 @@ impl<'a> Parser<'a> {
                 }
                 other => {
                     if OTHER_OPS_SET.contains(other) {
-                        panic!("TODO: implement support for {other} operation")
+                        return self.add_error(
+                            line,
+                            op,
+                            format!("the {other} operation is not supported"),
+                        );
                     } else {
                         return self.invalid_op_error(line, op);
                     }
@@ -232,6 +349,8 @@ impl<'a> Parser<'a> {
             self.signal_map.insert(line_id, e);
             // try to find a name
             let name = match cont.tokens.get(token_count) {
+                // aliases of states are still needed to improve state names
+                _ if self.options.skip_signal_names && !self.ctx[e].is_symbol() => None,
                 None => None,
                 Some(name) => {
                     if include_name(name) {
@@ -244,6 +363,9 @@ impl<'a> Parser<'a> {
             // add name if available
             if let Some(name) = name {
                 self.sys.names[e] = Some(name);
+                if self.options.keep_named_nets && !self.ctx[e].is_symbol() {
+                    self.sys.defines.push(Define { name, expr: e });
+                }
             }
         }
         Ok(())
@@ -269,16 +391,13 @@ impl<'a> Parser<'a> {
     ) -> ParseLineResult<ExprRef> {
         // first we check for internal consistency
         if let Err(e) = expr.type_check(self.ctx) {
-            let _ = self.add_error(line, line, format!("Failed to type check: {}", e.get_msg()));
-            return Err(());
+            return self.add_error(line, line, format!("Failed to type check: {}", e.get_msg()));
         }
         // then we make sure that the type of the expression is actually the type that was
         // declared in the btor2 line
         let actual_tpe = expr.get_type(self.ctx);
         if actual_tpe != expected_type {
-            let _ =
-                self.add_error(line, line, format!("Expression has the type {actual_tpe}, but the declared btpr2 type is {expected_type}", ));
-            Err(())
+            self.add_error(line, line, format!("Expression has the type {actual_tpe}, but the declared btpr2 type is {expected_type}", ))
         } else {
             Ok(expr)
         }
@@ -428,7 +547,8 @@ impl<'a> Parser<'a> {
                 self.ctx.not(inner)
             }
             "xor" => self.ctx.xor(a, b),
-            "rol" | "ror" => todo!("Add support for bit rotates."),
+            "rol" => self.ctx.rotate_left(a, b),
+            "ror" => self.ctx.rotate_right(a, b),
             "sll" => self.ctx.shift_left(a, b),
             "sra" => self.ctx.arithmetic_shift_right(a, b),
             "srl" => self.ctx.shift_right(a, b),
@@ -440,8 +560,13 @@ impl<'a> Parser<'a> {
             "srem" => self.ctx.signed_remainder(a, b),
             "urem" => self.ctx.remainder(a, b),
             "sub" => self.ctx.sub(a, b),
-            "saddo" | "uaddo" | "sdivo" | "udivo" | "smulo" | "umulo" | "ssubo" | "usubo" => {
-                todo!("Add support for overflow operators")
+            op
+            @ ("saddo" | "uaddo" | "sdivo" | "udivo" | "smulo" | "umulo" | "ssubo" | "usubo") => {
+                return self.add_error(
+                    line,
+                    op,
+                    format!("the overflow operator {op} is not supported"),
+                );
             }
             "concat" => self.ctx.concat(a, b),
             "eq" => self.ctx.equal(a, b),
@@ -550,6 +675,12 @@ impl<'a> Parser<'a> {
         )?;
 
         if is_init_not_next {
+            if self.options.validate_states {
+                self.init_lines.insert(
+                    state_ref.to_index(),
+                    (self.offset, self.offset + line.len()),
+                );
+            }
             self.sys
                 .modify_state(state_ref, |state| state.init = Some(expr));
         } else {
@@ -561,14 +692,11 @@ impl<'a> Parser<'a> {
 
     fn parse_line_id(&mut self, line: &str, token: &str) -> ParseLineResult<LineId> {
         match token.parse::<LineId>().ok() {
-            None => {
-                let _ = self.add_error(
-                    line,
-                    token,
-                    "Expected valid non-negative integer ID.".to_owned(),
-                );
-                Err(())
-            }
+            None => self.add_error(
+                line,
+                token,
+                "Expected valid non-negative integer ID.".to_owned(),
+            ),
             Some(id) => Ok(id),
         }
     }
@@ -612,14 +740,11 @@ impl<'a> Parser<'a> {
     ) -> ParseLineResult<ExprRef> {
         match BitVecValue::from_str_radix(token, base, width) {
             Ok(val) => Ok(self.ctx.bv_lit(&val)),
-            Err(_) => {
-                let _ = self.add_error(
-                    line,
-                    token,
-                    format!("Failed to parse as an integer of base {base}"),
-                );
-                Err(())
-            }
+            Err(_) => self.add_error(
+                line,
+                token,
+                format!("Failed to parse as an integer of base {base}"),
+            ),
         }
     }
 
@@ -627,30 +752,22 @@ impl<'a> Parser<'a> {
         let tpe = self.get_tpe_from_id(line, token)?;
         match tpe {
             Type::BV(width) => Ok(width),
-            Type::Array(tpe) => {
-                let _ = self.add_error(
-                    line,
-                    token,
-                    format!(
-                        "Points to an array type `{tpe:?}`, but a bit-vector type is required!"
-                    ),
-                );
-                Err(())
-            }
+            Type::Array(tpe) => self.add_error(
+                line,
+                token,
+                format!("Points to an array type `{tpe:?}`, but a bit-vector type is required!"),
+            ),
         }
     }
 
     fn get_tpe_from_id(&mut self, line: &str, token: &str) -> ParseLineResult<Type> {
         let tpe_id = self.parse_line_id(line, token)?;
         match self.type_map.get(&tpe_id) {
-            None => {
-                let _ = self.add_error(
-                    line,
-                    token,
-                    format!("ID `{tpe_id}` does not point to a valid type!"),
-                );
-                Err(())
-            }
+            None => self.add_error(
+                line,
+                token,
+                format!("ID `{tpe_id}` does not point to a valid type!"),
+            ),
             Some(tpe) => Ok(*tpe),
         }
     }
@@ -658,14 +775,11 @@ impl<'a> Parser<'a> {
     fn get_state_from_id(&mut self, line: &str, token: &str) -> ParseLineResult<StateRef> {
         let state_id = self.parse_line_id(line, token)?;
         match self.state_map.get(&state_id) {
-            None => {
-                let _ = self.add_error(
-                    line,
-                    token,
-                    format!("ID `{state_id}` does not point to a valid state!"),
-                );
-                Err(())
-            }
+            None => self.add_error(
+                line,
+                token,
+                format!("ID `{state_id}` does not point to a valid state!"),
+            ),
             Some(state) => Ok(*state),
         }
     }
@@ -673,14 +787,11 @@ impl<'a> Parser<'a> {
     fn get_expr_from_line_id(&mut self, line: &str, token: &str) -> ParseLineResult<ExprRef> {
         let signal_id = self.parse_line_id(line, token)?;
         let expr_ref = match self.signal_map.get(&signal_id) {
-            None => {
-                let _ = self.add_error(
-                    line,
-                    token,
-                    format!("ID `{signal_id}` does not point to a valid signal!"),
-                );
-                Err(())
-            }
+            None => self.add_error(
+                line,
+                token,
+                format!("ID `{signal_id}` does not point to a valid signal!"),
+            ),
             Some(signal) => Ok(*signal),
         }?;
         Ok(expr_ref)
@@ -726,22 +837,19 @@ impl<'a> Parser<'a> {
     ) -> ParseLineResult<WidthInt> {
         match token.parse::<WidthInt>() {
             Ok(width) => Ok(width),
-            Err(_) => {
-                let _ = self.add_error(
-                    line,
-                    token,
-                    format!(
-                        "Not a valid {kind}. An integer between {} and {} is required!",
-                        WidthInt::MAX,
-                        WidthInt::MIN
-                    ),
-                );
-                Err(())
-            }
+            Err(_) => self.add_error(
+                line,
+                token,
+                format!(
+                    "Not a valid {kind}. An integer between {} and {} is required!",
+                    WidthInt::MAX,
+                    WidthInt::MIN
+                ),
+            ),
         }
     }
 
-    fn add_error(&mut self, line: &str, token: &str, msg: String) -> ParseLineResult {
+    fn add_error<T>(&mut self, line: &str, token: &str, msg: String) -> ParseLineResult<T> {
         let explain = "".to_owned(); // TODO: how do we best utilize both msg and explain?
         let start = str_offset(token, line);
         let end = start + token.len();
@@ -923,6 +1031,27 @@ fn report_error(error: ParserError, file: &codespan_reporting::files::SimpleFile
     codespan_reporting::term::emit(&mut writer.lock(), &config, file, &diagnostic).unwrap();
 }
 
+fn report_io_error(path: &std::path::Path, error: &std::io::Error) {
+    let diagnostic = codespan_reporting::diagnostic::Diagnostic::<()>::error()
+        .with_message(format!("failed to open {}: {error}", path.display()));
+    let file = codespan_reporting::files::SimpleFile::new("", "");
+    let writer = codespan_reporting::term::termcolor::StandardStream::stderr(
+        codespan_reporting::term::termcolor::ColorChoice::Auto,
+    );
+    let config = codespan_reporting::term::Config::default();
+    codespan_reporting::term::emit(&mut writer.lock(), &config, &file, &diagnostic).unwrap();
+}
+
+fn report_warning(msg: &str) {
+    let diagnostic = codespan_reporting::diagnostic::Diagnostic::<()>::warning().with_message(msg);
+    let file = codespan_reporting::files::SimpleFile::new("", "");
+    let writer = codespan_reporting::term::termcolor::StandardStream::stderr(
+        codespan_reporting::term::termcolor::ColorChoice::Auto,
+    );
+    let config = codespan_reporting::term::Config::default();
+    codespan_reporting::term::emit(&mut writer.lock(), &config, &file, &diagnostic).unwrap();
+}
+
 fn str_offset(needle: &str, haystack: &str) -> usize {
     let offset = (needle.as_ptr() as usize) - (haystack.as_ptr() as usize);
     assert!(
@@ -1012,7 +1141,7 @@ mod tests {
 
     fn parse_private(code: &str) -> Result<TransitionSystem, Errors> {
         let mut ctx = Context::default();
-        Parser::new(&mut ctx).parse(code.as_bytes(), None)
+        Parser::new(&mut ctx, ParseOptions::default()).parse(code.as_bytes(), None)
     }
 
     #[test]
@@ -1025,4 +1154,53 @@ mod tests {
         parse_private("0").expect_err("missing op");
         parse_private("0 ").expect_err("missing op");
     }
+
+    #[test]
+    fn parse_missing_file() {
+        assert!(parse_file("does/not/exist.btor").is_none());
+    }
+
+    #[test]
+    fn validate_state_init() {
+        let code = "1 sort bitvec 8\n2 state 1 a\n3 state 1 b\n4 init 1 2 3\n5 init 1 3 2\n";
+        let options = ParseOptions {
+            validate_states: true,
+            ..Default::default()
+        };
+        // without validation, the cycle goes unnoticed
+        let sys = parse_private(code).unwrap();
+        assert_eq!(sys.states.len(), 2);
+        let mut ctx = Context::default();
+        let errors = Parser::new(&mut ctx, options)
+            .parse(code.as_bytes(), None)
+            .unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].msg, "cycle in init expressions of a, b");
+        assert_eq!(&code[errors[0].start..errors[0].end], "4 init 1 2 3");
+    }
+
+    #[test]
+    fn keep_named_nets() {
+        use crate::sim::{InitKind, Interpreter, Simulator};
+        let code = "1 sort bitvec 8\n2 input 1 a\n3 add 1 2 2 sum\n4 state 1 r\n5 next 1 4 2\n";
+        let options = ParseOptions {
+            keep_named_nets: true,
+            ..Default::default()
+        };
+        assert!(parse_private(code).unwrap().defines.is_empty());
+        let mut ctx = Context::default();
+        let sys = Parser::new(&mut ctx, options)
+            .parse(code.as_bytes(), None)
+            .unwrap();
+        assert_eq!(sys.defines.len(), 1);
+        let sum = sys.lookup_define(&ctx, "sum").unwrap();
+        assert!(sys.serialize_to_str(&ctx).contains("define sum : bv<8>"));
+
+        let mut sim = Interpreter::new(&ctx, &sys);
+        sim.init(InitKind::Zero);
+        let a = sys.lookup_input(&ctx, "a").unwrap();
+        sim.set(a, &BitVecValue::from_u64(3, 8)).unwrap();
+        sim.update();
+        assert_eq!(sim.get(sum).try_into_u64().unwrap(), 6);
+    }
 }
@@ -210,6 +210,7 @@ fn parse_assignment<'a>(tokens: &'a [&'a str]) -> (usize, &'a str, InitValue) {
     }
 }
 
+/// Panics if the witness contains values that cannot be printed, e.g., array inputs.
 pub fn witness_to_string(witness: &Witness) -> String {
     let mut buf = Vec::new();
     print_witness(&mut buf, witness).expect("Failed to write to string!");
@@ -282,9 +283,11 @@ fn print_witness_input_value(
         Value::BitVec(value) => {
             writeln!(out, "{id} {} {}{suffix}", value.to_bit_str(), name)
         }
-        Value::Array(_) => {
-            todo!("add support for array type inputs")
-        }
+        // btor2 witnesses only assign bit-vector inputs
+        Value::Array(_) => Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            format!("array input `{name}` cannot be printed"),
+        )),
     }
 }
 
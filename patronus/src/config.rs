@@ -0,0 +1,241 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Configuration
+//! Settings shared by our tools and engines which can be loaded from a TOML file,
+//! making it possible to reproduce a run from a checked-in configuration.
+//!
+//! ```toml
+//! solver = "yices2"
+//!
+//! [bmc]
+//! max_bound = 50
+//!
+//! [egraphs]
+//! rules = ["commute-add", "commute-mul"]
+//!
+//! [limits]
+//! max_width = 4096
+//! ```
+
+use crate::expr::WidthLimits;
+use crate::sim::{Backend, EvalOrder};
+use crate::smt::{SmtLibSolver, BITWUZLA, YICES2};
+use crate::system::PassConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("failed to read config file")]
+    Io(#[from] std::io::Error),
+    #[error("failed to parse config file: {0}")]
+    Parse(#[from] toml::de::Error),
+}
+
+/// Top level configuration. Missing entries are filled in with their defaults.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub solver: SolverChoice,
+    pub bmc: BmcConfig,
+    pub sim: SimConfig,
+    pub egraphs: EGraphConfig,
+    pub passes: PassConfig,
+    pub limits: WidthLimits,
+}
+
+impl Config {
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self, ConfigError> {
+        let content = std::fs::read_to_string(path)?;
+        Self::from_toml_str(&content)
+    }
+
+    pub fn from_toml_str(content: &str) -> Result<Self, ConfigError> {
+        Ok(toml::from_str(content)?)
+    }
+
+    pub fn to_toml_string(&self) -> String {
+        toml::to_string(self).expect("all config values can be serialized")
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SolverChoice {
+    #[default]
+    Bitwuzla,
+    Yices2,
+}
+
+impl SolverChoice {
+    pub fn solver(&self) -> SmtLibSolver {
+        match self {
+            SolverChoice::Bitwuzla => BITWUZLA,
+            SolverChoice::Yices2 => YICES2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BmcConfig {
+    /// maximum number of steps to unroll
+    pub max_bound: u64,
+    /// give up after the given number of seconds
+    pub timeout_secs: Option<u64>,
+}
+
+impl Default for BmcConfig {
+    fn default() -> Self {
+        Self {
+            max_bound: 25,
+            timeout_secs: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimConfig {
+    pub backend: Backend,
+    /// seed for random initialization, see [`crate::random::default_seed`] for the fallback
+    pub seed: Option<u64>,
+    pub eval_order: EvalOrder,
+    /// directory in which compiled bytecode is cached across runs, see [`crate::sim::ProgramCache`]
+    pub program_cache: Option<std::path::PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EGraphConfig {
+    /// names of the rewrite rules to use, all rules are used if empty
+    pub rules: Vec<String>,
+    pub cost_model: CostModel,
+    /// maximum number of saturation iterations
+    pub iter_limit: Option<usize>,
+    /// approximate upper bound on the memory used by the e-graph in bytes
+    pub memory_limit: Option<usize>,
+    pub scheduler: SchedulerConfig,
+}
+
+/// Controls how often rewrite rules are applied. Rules that match too often are banned
+/// for a number of iterations, with limits that grow exponentially.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SchedulerConfig {
+    /// number of matches after which a rule is banned for the first time
+    pub initial_match_limit: usize,
+    /// number of iterations a rule is banned for the first time
+    pub ban_length: usize,
+    /// overrides for individual rules, indexed by rule name
+    pub rules: BTreeMap<String, RuleSchedule>,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        // same defaults as egg's `BackoffScheduler`
+        Self {
+            initial_match_limit: 1_000,
+            ban_length: 5,
+            rules: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuleSchedule {
+    pub match_limit: Option<usize>,
+    pub ban_length: Option<usize>,
+    /// permanently disable the rule after it was applied this many times
+    pub max_applications: Option<usize>,
+    /// never ban the rule, no matter how often it matches
+    pub never_ban: bool,
+}
+
+/// Cost function used to extract the best expression from an e-graph.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CostModel {
+    /// number of nodes
+    #[default]
+    AstSize,
+    /// depth of the expression tree
+    AstDepth,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config = Config::from_toml_str(
+            r#"
+solver = "yices2"
+
+[bmc]
+max_bound = 50
+
+[sim]
+backend = "interpreter"
+seed = 7
+eval_order = "eager"
+program_cache = ".patronus/bytecode"
+
+[egraphs]
+rules = ["commute-add"]
+cost_model = "ast-depth"
+memory_limit = 1048576
+
+[egraphs.scheduler]
+ban_length = 3
+
+[egraphs.scheduler.rules.merge-left-shift]
+max_applications = 100
+
+[passes]
+simplify = false
+
+[limits]
+max_width = 4096
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.solver, SolverChoice::Yices2);
+        assert_eq!(config.bmc.max_bound, 50);
+        assert_eq!(config.bmc.timeout_secs, None);
+        assert_eq!(config.sim.backend, Backend::Interpreter);
+        assert_eq!(config.sim.seed, Some(7));
+        assert_eq!(config.sim.eval_order, EvalOrder::Eager);
+        assert_eq!(
+            config.sim.program_cache,
+            Some(std::path::PathBuf::from(".patronus/bytecode"))
+        );
+        assert_eq!(config.egraphs.rules, ["commute-add"]);
+        assert_eq!(config.egraphs.cost_model, CostModel::AstDepth);
+        assert_eq!(config.egraphs.memory_limit, Some(1 << 20));
+        assert_eq!(config.egraphs.scheduler.ban_length, 3);
+        assert_eq!(config.egraphs.scheduler.initial_match_limit, 1_000);
+        assert_eq!(
+            config.egraphs.scheduler.rules["merge-left-shift"].max_applications,
+            Some(100)
+        );
+        assert!(!config.passes.simplify);
+        assert!(!config.passes.replace_anonymous_inputs);
+        assert_eq!(config.limits.max_width, 4096);
+        assert_eq!(config.limits.max_index_width, 32);
+
+        // round trip
+        let again = Config::from_toml_str(&config.to_toml_string()).unwrap();
+        assert_eq!(config, again);
+    }
+
+    #[test]
+    fn test_empty_config_uses_defaults() {
+        assert_eq!(Config::from_toml_str("").unwrap(), Config::default());
+        assert!(Config::from_toml_str("unknown = 1").is_err());
+    }
+}
@@ -0,0 +1,225 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Combinational Equivalence
+//! Checks whether two bit-vector expressions evaluate to the same value for every assignment
+//! of their symbols. Boolean control logic is compared with BDDs which is often much faster
+//! than starting an SMT solver. Everything else is handed to an SMT solver or bit-blasted
+//! and solved with the embedded SAT solver. For narrow expressions, [`exhaustive_equiv`]
+//! decides equivalence by evaluating all assignments.
+//! Sequential equivalence, possibly modulo a fixed latency, is reduced to model checking
+//! a miter system. The same solver machinery is used by [`smt_simplify`] to find
+//! sub-expressions that are constant under some assumptions.
+//! [`observability_dont_cares`] computes the conditions under which outputs and bad states
+//! ignore an internal signal.
+
+mod bdd;
+mod dont_care;
+mod exhaustive;
+mod sequential;
+mod smt_simplify;
+
+pub use bdd::{prove_equiv_bdd, BddOptions, VariableOrder};
+pub use dont_care::{
+    observability_dont_care, observability_dont_cares, DontCare, DontCareOptions, Observability,
+};
+pub use exhaustive::exhaustive_equiv;
+pub use sequential::{latency_miter, MiterError, MITER_RHS_PREFIX};
+pub use smt_simplify::{smt_simplify, SimplifyBudget};
+
+use crate::expr::traversal::{top_down, TraversalCmd};
+use crate::expr::{Context, ExprRef, TypeCheck};
+use crate::mc::get_smt_value;
+use crate::sat::BlastError;
+#[cfg(feature = "sat")]
+use crate::sat::{decode, BitBlaster, SatSolver};
+use crate::smt::{
+    CheckSatResponse, Logic, SmtLibSolver, Solver, SolverContext, SolverMetaData, BITWUZLA,
+};
+use baa::Value;
+use rustc_hash::FxHashSet;
+
+#[derive(Debug, thiserror::Error)]
+pub enum EquivError {
+    #[error(transparent)]
+    Smt(#[from] crate::smt::Error),
+    #[error(transparent)]
+    Blast(#[from] BlastError),
+}
+
+pub type Result<T> = std::result::Result<T, EquivError>;
+
+/// Decides equivalence whenever the BDD is not applicable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EquivBackend {
+    Smt(SmtLibSolver),
+    /// Bit-blasts both expressions, works without an external solver.
+    #[cfg(feature = "sat")]
+    Sat,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EquivOptions {
+    pub backend: EquivBackend,
+    /// Try BDDs before falling back to the solver. `None` always uses the solver.
+    pub bdd: Option<BddOptions>,
+}
+
+impl Default for EquivOptions {
+    fn default() -> Self {
+        Self {
+            backend: EquivBackend::Smt(BITWUZLA),
+            bdd: Some(BddOptions::default()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EquivResult {
+    Equivalent,
+    /// An assignment to all symbols under which the two expressions differ.
+    NotEquivalent(Vec<(ExprRef, Value)>),
+}
+
+/// Proves that `a` and `b` are equivalent or finds a counterexample.
+pub fn prove_equiv(
+    ctx: &mut Context,
+    a: ExprRef,
+    b: ExprRef,
+    opts: &EquivOptions,
+) -> Result<EquivResult> {
+    assert_eq!(
+        a.get_bv_type(ctx),
+        b.get_bv_type(ctx),
+        "can only compare bit-vector expressions of the same width"
+    );
+    if let Some(bdd_opts) = &opts.bdd {
+        if let Some(res) = prove_equiv_bdd(ctx, a, b, bdd_opts) {
+            return Ok(res);
+        }
+    }
+    match &opts.backend {
+        EquivBackend::Smt(solver) => prove_equiv_smt(ctx, a, b, solver),
+        #[cfg(feature = "sat")]
+        EquivBackend::Sat => Ok(prove_equiv_sat(ctx, a, b)?),
+    }
+}
+
+#[cfg(feature = "sat")]
+fn prove_equiv_sat(
+    ctx: &mut Context,
+    a: ExprRef,
+    b: ExprRef,
+) -> std::result::Result<EquivResult, BlastError> {
+    let miter = ctx.build(|c| c.not(c.equal(a, b)));
+    let mut blaster = BitBlaster::new(ctx);
+    blaster.assert(miter)?;
+    let Some(model) = SatSolver::default().solve(blaster.cnf(), &[]) else {
+        return Ok(EquivResult::Equivalent);
+    };
+    let assignment = collect_symbols(ctx, [a, b])
+        .into_iter()
+        .map(|s| {
+            let bits = blaster.bits(s)?;
+            Ok((s, Value::BitVec(decode(&model, &bits))))
+        })
+        .collect::<std::result::Result<_, BlastError>>()?;
+    Ok(EquivResult::NotEquivalent(assignment))
+}
+
+fn prove_equiv_smt(
+    ctx: &mut Context,
+    a: ExprRef,
+    b: ExprRef,
+    solver: &SmtLibSolver,
+) -> Result<EquivResult> {
+    let mut smt_ctx = solver.start(None::<std::fs::File>)?;
+    smt_ctx.set_logic(if solver.supports_uf() {
+        Logic::QfAufbv
+    } else {
+        Logic::QfAbv
+    })?;
+    let symbols = collect_symbols(ctx, [a, b]);
+    for &symbol in symbols.iter() {
+        smt_ctx.declare_const(ctx, symbol)?;
+    }
+    let miter = ctx.build(|c| c.not(c.equal(a, b)));
+    smt_ctx.assert(ctx, miter)?;
+    match smt_ctx.check_sat()? {
+        CheckSatResponse::Unsat => Ok(EquivResult::Equivalent),
+        CheckSatResponse::Sat => {
+            let assignment = symbols
+                .into_iter()
+                .map(|s| Ok((s, get_smt_value(ctx, &mut smt_ctx, s)?)))
+                .collect::<crate::smt::Result<_>>()?;
+            Ok(EquivResult::NotEquivalent(assignment))
+        }
+        CheckSatResponse::Unknown => Err(crate::smt::Error::UnexpectedResponse(
+            solver.name().to_string(),
+            "unknown".to_string(),
+        )
+        .into()),
+    }
+}
+
+/// All symbols in the order of their first appearance.
+pub(crate) fn collect_symbols(
+    ctx: &Context,
+    roots: impl IntoIterator<Item = ExprRef>,
+) -> Vec<ExprRef> {
+    let mut visited = FxHashSet::default();
+    let mut symbols = vec![];
+    for root in roots {
+        top_down(ctx, root, |ctx, e| {
+            if !visited.insert(e) {
+                return TraversalCmd::Stop;
+            }
+            if ctx[e].is_symbol() {
+                symbols.push(e);
+            }
+            TraversalCmd::Continue
+        });
+    }
+    symbols
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "sat")]
+    fn test_sat_backend() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("a", 8);
+        let b = ctx.bv_symbol("b", 8);
+        let opts = EquivOptions {
+            backend: EquivBackend::Sat,
+            bdd: None,
+        };
+        let lhs = ctx.build(|c| c.mul(a, c.bit_vec_val(2, 8)));
+        let rhs = ctx.build(|c| c.shift_left(a, c.one(8)));
+        assert_eq!(
+            prove_equiv(&mut ctx, lhs, rhs, &opts).unwrap(),
+            EquivResult::Equivalent
+        );
+        let lhs = ctx.sub(a, b);
+        let rhs = ctx.sub(b, a);
+        let EquivResult::NotEquivalent(assignment) =
+            prove_equiv(&mut ctx, lhs, rhs, &opts).unwrap()
+        else {
+            panic!("expected a counterexample");
+        };
+        let values: Vec<_> = assignment
+            .into_iter()
+            .map(|(s, v)| match v {
+                Value::BitVec(v) => (s, v),
+                Value::Array(_) => unreachable!(),
+            })
+            .collect();
+        let lhs_value = crate::expr::eval_bv_expr(&ctx, values.as_slice(), lhs);
+        let rhs_value = crate::expr::eval_bv_expr(&ctx, values.as_slice(), rhs);
+        assert_ne!(lhs_value, rhs_value);
+    }
+}
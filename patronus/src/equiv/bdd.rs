@@ -0,0 +1,247 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+use crate::equiv::EquivResult;
+use crate::expr::{Context, Expr, ExprRef, ForEachChild, TypeCheck};
+use baa::{BitVecValue, Value};
+use boolean_expression::{BDDFunc, BDD, BDD_ZERO};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+/// Determines the order of variables in the BDD, starting at the root.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum VariableOrder {
+    /// In the order in which they are first encountered in a depth first traversal.
+    #[default]
+    Appearance,
+    /// Variables that feed into more operations come first.
+    Fanout,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BddOptions {
+    /// Expressions with more variables are left to the solver, since the BDD might blow up.
+    pub max_vars: usize,
+    pub order: VariableOrder,
+}
+
+impl Default for BddOptions {
+    fn default() -> Self {
+        Self {
+            max_vars: 32,
+            order: VariableOrder::default(),
+        }
+    }
+}
+
+/// Compares two 1-bit expressions with a BDD. Any sub-expression that is not boolean logic
+/// becomes a variable. Returns `None` if the BDD is not applicable or if a difference was found
+/// that might not be real because some variables stand for non-boolean logic.
+pub fn prove_equiv_bdd(
+    ctx: &Context,
+    a: ExprRef,
+    b: ExprRef,
+    opts: &BddOptions,
+) -> Option<EquivResult> {
+    if !a.is_bool(ctx) || !b.is_bool(ctx) {
+        return None;
+    }
+    let vars = find_variables(ctx, [a, b], opts.order);
+    if vars.len() > opts.max_vars {
+        return None;
+    }
+    let mut bdd = BDD::new();
+    // labels are allocated in the order in which terminals are created
+    for &var in vars.iter() {
+        bdd.terminal(var);
+    }
+    let mut cache = FxHashMap::default();
+    let a_bdd = to_bdd(ctx, &mut bdd, &mut cache, a);
+    let b_bdd = to_bdd(ctx, &mut bdd, &mut cache, b);
+    let diff = bdd.xor(a_bdd, b_bdd);
+    if diff == BDD_ZERO {
+        return Some(EquivResult::Equivalent);
+    }
+    if !vars.iter().all(|&v| ctx[v].is_symbol()) {
+        return None;
+    }
+    // any path to the one terminal is a counterexample
+    let mut remaining = diff;
+    let assignment = vars
+        .into_iter()
+        .map(|var| {
+            let low = bdd.restrict(remaining, var, false);
+            let value = low == BDD_ZERO;
+            remaining = if value {
+                bdd.restrict(remaining, var, true)
+            } else {
+                low
+            };
+            (var, Value::BitVec(BitVecValue::from_u64(value as u64, 1)))
+        })
+        .collect();
+    Some(EquivResult::NotEquivalent(assignment))
+}
+
+/// Boolean operations are translated into the BDD, everything else becomes a variable.
+fn is_bool_op(ctx: &Context, e: ExprRef) -> bool {
+    let all_bool = |children: &[ExprRef]| children.iter().all(|c| c.is_bool(ctx));
+    match ctx[e] {
+        Expr::BVLiteral(_) => true,
+        Expr::BVNot(a, 1) => all_bool(&[a]),
+        Expr::BVAnd(a, b, 1)
+        | Expr::BVOr(a, b, 1)
+        | Expr::BVXor(a, b, 1)
+        | Expr::BVImplies(a, b)
+        | Expr::BVEqual(a, b) => all_bool(&[a, b]),
+        Expr::BVIte { cond, tru, fals } => all_bool(&[cond, tru, fals]),
+        _ => false,
+    }
+}
+
+fn find_variables(ctx: &Context, roots: [ExprRef; 2], order: VariableOrder) -> Vec<ExprRef> {
+    let mut fanout: FxHashMap<ExprRef, usize> = FxHashMap::default();
+    let mut visited = FxHashSet::default();
+    let mut vars = vec![];
+    let mut todo: Vec<ExprRef> = roots.into_iter().rev().collect();
+    while let Some(e) = todo.pop() {
+        if is_bool_op(ctx, e) {
+            if !visited.insert(e) {
+                continue;
+            }
+            let mut children = vec![];
+            ctx[e].for_each_child(|&c| children.push(c));
+            todo.extend(children.into_iter().rev());
+        } else {
+            let count = fanout.entry(e).or_default();
+            if *count == 0 {
+                vars.push(e);
+            }
+            *count += 1;
+        }
+    }
+    if order == VariableOrder::Fanout {
+        // stable sort keeps the order of appearance for variables with the same fanout
+        vars.sort_by_key(|v| std::cmp::Reverse(fanout[v]));
+    }
+    vars
+}
+
+fn to_bdd(
+    ctx: &Context,
+    bdd: &mut BDD<ExprRef>,
+    cache: &mut FxHashMap<ExprRef, BDDFunc>,
+    e: ExprRef,
+) -> BDDFunc {
+    if let Some(&f) = cache.get(&e) {
+        return f;
+    }
+    let f = if is_bool_op(ctx, e) {
+        match ctx[e].clone() {
+            Expr::BVLiteral(value) => bdd.constant(value.is_true()),
+            Expr::BVNot(a, _) => {
+                let a = to_bdd(ctx, bdd, cache, a);
+                bdd.not(a)
+            }
+            Expr::BVAnd(a, b, _) => {
+                let (a, b) = (to_bdd(ctx, bdd, cache, a), to_bdd(ctx, bdd, cache, b));
+                bdd.and(a, b)
+            }
+            Expr::BVOr(a, b, _) => {
+                let (a, b) = (to_bdd(ctx, bdd, cache, a), to_bdd(ctx, bdd, cache, b));
+                bdd.or(a, b)
+            }
+            Expr::BVXor(a, b, _) => {
+                let (a, b) = (to_bdd(ctx, bdd, cache, a), to_bdd(ctx, bdd, cache, b));
+                bdd.xor(a, b)
+            }
+            Expr::BVImplies(a, b) => {
+                let (a, b) = (to_bdd(ctx, bdd, cache, a), to_bdd(ctx, bdd, cache, b));
+                bdd.implies(a, b)
+            }
+            Expr::BVEqual(a, b) => {
+                let (a, b) = (to_bdd(ctx, bdd, cache, a), to_bdd(ctx, bdd, cache, b));
+                let diff = bdd.xor(a, b);
+                bdd.not(diff)
+            }
+            Expr::BVIte { cond, tru, fals } => {
+                let cond = to_bdd(ctx, bdd, cache, cond);
+                let tru = to_bdd(ctx, bdd, cache, tru);
+                let fals = to_bdd(ctx, bdd, cache, fals);
+                bdd.ite(cond, tru, fals)
+            }
+            _ => unreachable!("not a boolean operation"),
+        }
+    } else {
+        bdd.terminal(e)
+    };
+    cache.insert(e, f);
+    f
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_de_morgan() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("a", 1);
+        let b = ctx.bv_symbol("b", 1);
+        let lhs = ctx.build(|c| c.not(c.and(a, b)));
+        let rhs = ctx.build(|c| c.or(c.not(a), c.not(b)));
+        for order in [VariableOrder::Appearance, VariableOrder::Fanout] {
+            let opts = BddOptions {
+                order,
+                ..Default::default()
+            };
+            assert_eq!(
+                prove_equiv_bdd(&ctx, lhs, rhs, &opts),
+                Some(EquivResult::Equivalent)
+            );
+        }
+    }
+
+    #[test]
+    fn test_counterexample() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("a", 1);
+        let b = ctx.bv_symbol("b", 1);
+        let lhs = ctx.or(a, b);
+        let rhs = ctx.xor(a, b);
+        let Some(EquivResult::NotEquivalent(assignment)) =
+            prove_equiv_bdd(&ctx, lhs, rhs, &BddOptions::default())
+        else {
+            panic!("expected a counterexample");
+        };
+        // a | b and a ^ b only differ if both are true
+        for (_, value) in assignment {
+            assert_eq!(value, Value::BitVec(BitVecValue::from_u64(1, 1)));
+        }
+    }
+
+    #[test]
+    fn test_not_applicable() {
+        let mut ctx = Context::default();
+        let x = ctx.bv_symbol("x", 8);
+        let y = ctx.bv_symbol("y", 8);
+        let a = ctx.bv_symbol("a", 1);
+        let opts = BddOptions::default();
+        // opaque comparisons can still be proven equivalent
+        let lhs = ctx.build(|c| c.and(a, c.greater(x, y)));
+        let rhs = ctx.build(|c| c.and(c.greater(x, y), a));
+        assert_eq!(
+            prove_equiv_bdd(&ctx, lhs, rhs, &opts),
+            Some(EquivResult::Equivalent)
+        );
+        // but a difference might not be real
+        let rhs = ctx.build(|c| c.and(c.greater(y, x), a));
+        assert_eq!(prove_equiv_bdd(&ctx, lhs, rhs, &opts), None);
+        // too many variables
+        let few = BddOptions {
+            max_vars: 1,
+            ..Default::default()
+        };
+        assert_eq!(prove_equiv_bdd(&ctx, lhs, lhs, &few), None);
+    }
+}
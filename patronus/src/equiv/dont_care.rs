@@ -0,0 +1,218 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Observability Don't-Cares
+//! An internal signal only matters for an output if changing its value can change the output.
+//! The observability don't-care condition of a signal `s` with respect to a root expression `o`
+//! holds for exactly those assignments of the remaining symbols under which `o` evaluates to
+//! the same value, no matter which value `s` takes. Synthesis style simplifications may replace
+//! `s` with anything that agrees with it whenever the condition is false.
+//!
+//! For narrow signals, the condition is computed exactly by comparing all cofactors of `o`.
+//! Wider signals are only checked for global insensitivity, i.e., their condition is either
+//! `true` or `false`. In both cases, the solver classifies the condition as always true,
+//! never true or truly conditional.
+
+use super::{prove_equiv, EquivOptions, EquivResult, Result};
+use crate::expr::traversal::{top_down, TraversalCmd};
+use crate::expr::{
+    simple_transform_expr, simplify_single_expression, Context, ExprRef, TypeCheck, WidthInt,
+};
+use crate::system::TransitionSystem;
+use rustc_hash::FxHashSet;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DontCareOptions {
+    pub equiv: EquivOptions,
+    /// signals of up to this width get an exact condition, requires `2^width` cofactors
+    pub max_exact_width: WidthInt,
+}
+
+impl Default for DontCareOptions {
+    fn default() -> Self {
+        Self {
+            equiv: EquivOptions::default(),
+            max_exact_width: 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Observability {
+    /// The signal never influences the root, the don't-care condition is `true`.
+    Never,
+    /// The signal matters only under some assignments.
+    Conditional,
+    /// The condition is `false`. For signals that are too wide for an exact condition, this
+    /// only means that the root is sensitive to the signal under some assignment.
+    Always,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DontCare {
+    pub root: ExprRef,
+    /// holds whenever `root` does not depend on the value of the signal
+    pub condition: ExprRef,
+    pub observability: Observability,
+}
+
+/// Computes the don't-care conditions of `signal` for all outputs followed by all bad states
+/// of `sys`.
+pub fn observability_dont_cares(
+    ctx: &mut Context,
+    sys: &TransitionSystem,
+    signal: ExprRef,
+    opts: &DontCareOptions,
+) -> Result<Vec<DontCare>> {
+    let roots: Vec<ExprRef> = sys
+        .outputs
+        .iter()
+        .map(|o| o.expr)
+        .chain(sys.bad_states.iter().copied())
+        .collect();
+    roots
+        .into_iter()
+        .map(|root| observability_dont_care(ctx, root, signal, opts))
+        .collect()
+}
+
+/// Computes the condition under which `root` is insensitive to the value of `signal`.
+pub fn observability_dont_care(
+    ctx: &mut Context,
+    root: ExprRef,
+    signal: ExprRef,
+    opts: &DontCareOptions,
+) -> Result<DontCare> {
+    if !depends_on(ctx, root, signal) {
+        return Ok(DontCare {
+            root,
+            condition: ctx.get_true(),
+            observability: Observability::Never,
+        });
+    }
+
+    let condition = match signal.get_bv_type(ctx) {
+        Some(width) if width <= opts.max_exact_width => {
+            // all cofactors need to agree with the first one
+            let cofactors: Vec<ExprRef> = (0..(1u128 << width))
+                .map(|value| {
+                    let value = ctx.bit_vec_val(value, width);
+                    substitute(ctx, root, signal, value)
+                })
+                .collect();
+            let agree = cofactors[1..]
+                .iter()
+                .map(|&c| ctx.equal(c, cofactors[0]))
+                .collect::<Vec<_>>();
+            let condition = agree
+                .into_iter()
+                .reduce(|a, b| ctx.and(a, b))
+                .unwrap_or_else(|| ctx.get_true());
+            simplify_single_expression(ctx, condition)
+        }
+        _ => {
+            // a name from the design would turn the fresh symbol into a real signal
+            let name = ctx.fresh_name(&format!("__dont_care_{}", signal.index()));
+            let name = ctx.string(name.into());
+            let tpe = signal.get_type(ctx);
+            let fresh = ctx.symbol(name, tpe);
+            let replaced = substitute(ctx, root, signal, fresh);
+            match prove_equiv(ctx, root, replaced, &opts.equiv)? {
+                EquivResult::Equivalent => ctx.get_true(),
+                EquivResult::NotEquivalent(_) => ctx.get_false(),
+            }
+        }
+    };
+
+    let observability = classify(ctx, condition, opts)?;
+    let condition = match observability {
+        Observability::Never => ctx.get_true(),
+        Observability::Always => ctx.get_false(),
+        Observability::Conditional => condition,
+    };
+    Ok(DontCare {
+        root,
+        condition,
+        observability,
+    })
+}
+
+fn classify(
+    ctx: &mut Context,
+    condition: ExprRef,
+    opts: &DontCareOptions,
+) -> Result<Observability> {
+    let (tru, fals) = (ctx.get_true(), ctx.get_false());
+    if prove_equiv(ctx, condition, tru, &opts.equiv)? == EquivResult::Equivalent {
+        Ok(Observability::Never)
+    } else if prove_equiv(ctx, condition, fals, &opts.equiv)? == EquivResult::Equivalent {
+        Ok(Observability::Always)
+    } else {
+        Ok(Observability::Conditional)
+    }
+}
+
+fn substitute(ctx: &mut Context, root: ExprRef, signal: ExprRef, value: ExprRef) -> ExprRef {
+    simple_transform_expr(ctx, root, |_, e, _| (e == signal).then_some(value))
+}
+
+fn depends_on(ctx: &Context, root: ExprRef, signal: ExprRef) -> bool {
+    let mut visited = FxHashSet::default();
+    let mut found = false;
+    top_down(ctx, root, |_, e| {
+        if found || !visited.insert(e) {
+            return TraversalCmd::Stop;
+        }
+        found = e == signal;
+        TraversalCmd::Continue
+    });
+    found
+}
+
+#[cfg(all(test, feature = "sat"))]
+mod tests {
+    use super::*;
+    use crate::equiv::EquivBackend;
+
+    #[test]
+    fn test_mux_dont_care() {
+        let mut ctx = Context::default();
+        let sel = ctx.bv_symbol("sel", 1);
+        let a = ctx.bv_symbol("a", 2);
+        let b = ctx.bv_symbol("b", 8);
+        let a_wide = ctx.build(|c| c.zero_extend(a, 6));
+        let mux = ctx.build(|c| c.ite(sel, a_wide, b));
+        let opts = DontCareOptions {
+            equiv: EquivOptions {
+                backend: EquivBackend::Sat,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        // `a` only matters if the mux selects it
+        let res = observability_dont_care(&mut ctx, mux, a, &opts).unwrap();
+        assert_eq!(res.observability, Observability::Conditional);
+        let not_sel = ctx.not(sel);
+        assert_eq!(
+            prove_equiv(&mut ctx, res.condition, not_sel, &opts.equiv).unwrap(),
+            EquivResult::Equivalent
+        );
+
+        // the select signal does not matter whenever both inputs agree
+        let res = observability_dont_care(&mut ctx, mux, sel, &opts).unwrap();
+        assert_eq!(res.observability, Observability::Conditional);
+
+        // `b` is too wide for an exact condition
+        let res = observability_dont_care(&mut ctx, mux, b, &opts).unwrap();
+        assert_eq!(res.observability, Observability::Always);
+        assert_eq!(res.condition, ctx.get_false());
+
+        // signals outside of the cone never matter
+        let other = ctx.bv_symbol("other", 4);
+        let res = observability_dont_care(&mut ctx, mux, other, &opts).unwrap();
+        assert_eq!(res.observability, Observability::Never);
+        assert_eq!(res.condition, ctx.get_true());
+    }
+}
@@ -0,0 +1,99 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Exhaustive Equivalence
+//! Decides equivalence of narrow expressions by evaluating both for every assignment of their
+//! symbols. Since only the expression evaluator is involved, this is a handy oracle for unit
+//! tests of rewrites, solver backends and simulators.
+
+use super::{collect_symbols, EquivResult};
+use crate::expr::{eval_bv_expr, Context, ExprRef, TypeCheck, WidthInt};
+use crate::mc::ExhaustiveError;
+use baa::{BitVecOps, BitVecValue, Value};
+
+/// Enumeration needs to fit into a 64-bit counter.
+const MAX_BITS: WidthInt = 63;
+
+/// Proves that `a` and `b` are equivalent by enumerating all assignments to their symbols.
+/// Fails if the symbols have more than `max_width` bits in total (at most 63) or if any of
+/// them is an array.
+pub fn exhaustive_equiv(
+    ctx: &Context,
+    a: ExprRef,
+    b: ExprRef,
+    max_width: WidthInt,
+) -> Result<EquivResult, ExhaustiveError> {
+    assert_eq!(
+        a.get_bv_type(ctx),
+        b.get_bv_type(ctx),
+        "can only compare bit-vector expressions of the same width"
+    );
+    let symbols = collect_symbols(ctx, [a, b]);
+    let widths = symbols
+        .iter()
+        .map(|&s| {
+            s.get_bv_type(ctx)
+                .ok_or_else(|| ExhaustiveError::Array(ctx.get_symbol_name(s).unwrap().to_string()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let bits: WidthInt = widths.iter().sum();
+    let limit = max_width.min(MAX_BITS);
+    if bits > limit {
+        return Err(ExhaustiveError::TooManyBits { bits, limit });
+    }
+
+    let mut assignment: Vec<(ExprRef, BitVecValue)> = Vec::with_capacity(symbols.len());
+    for counter in 0..(1u64 << bits) {
+        assignment.clear();
+        let mut remaining = counter;
+        for (&symbol, &width) in symbols.iter().zip(widths.iter()) {
+            let mask = (1u64 << width) - 1;
+            assignment.push((symbol, BitVecValue::from_u64(remaining & mask, width)));
+            remaining >>= width;
+        }
+        let (va, vb) = (
+            eval_bv_expr(ctx, assignment.as_slice(), a),
+            eval_bv_expr(ctx, assignment.as_slice(), b),
+        );
+        if !va.is_equal(&vb) {
+            let assignment = assignment
+                .drain(..)
+                .map(|(s, v)| (s, Value::BitVec(v)))
+                .collect();
+            return Ok(EquivResult::NotEquivalent(assignment));
+        }
+    }
+    Ok(EquivResult::Equivalent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exhaustive_equiv() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("a", 4);
+        let b = ctx.bv_symbol("b", 4);
+        let two = ctx.bit_vec_val(2, 4);
+        let lhs = ctx.build(|c| c.mul(c.add(a, b), two));
+        let rhs = ctx.build(|c| c.add(c.shift_left(a, c.one(4)), c.add(b, b)));
+        assert_eq!(
+            exhaustive_equiv(&ctx, lhs, rhs, 8).unwrap(),
+            EquivResult::Equivalent
+        );
+
+        let (lhs, rhs) = (ctx.sub(a, b), ctx.sub(b, a));
+        let EquivResult::NotEquivalent(assignment) = exhaustive_equiv(&ctx, lhs, rhs, 8).unwrap()
+        else {
+            panic!("a - b and b - a differ");
+        };
+        assert_eq!(assignment.len(), 2);
+
+        assert_eq!(
+            exhaustive_equiv(&ctx, lhs, rhs, 7),
+            Err(ExhaustiveError::TooManyBits { bits: 8, limit: 7 })
+        );
+    }
+}
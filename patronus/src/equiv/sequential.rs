@@ -0,0 +1,212 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+use crate::expr::*;
+use crate::system::{State, TransitionSystem};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+/// Prefix for the states of the second system in a miter.
+pub const MITER_RHS_PREFIX: &str = "rhs.";
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum MiterError {
+    #[error("input `{0}` of the second system does not exist in the first system")]
+    MissingInput(String),
+    #[error("output `{0}` does not exist in the second system")]
+    MissingOutput(String),
+    #[error("`{0}` has different types in the two systems")]
+    TypeMismatch(String),
+}
+
+/// Combines two systems that share a context into one system whose bad states are reachable
+/// iff an output of `rhs` differs from the same output of `lhs` delayed by `latency` steps.
+/// Inputs are shared and matched by name, outputs are matched by name as well. Comparisons
+/// start once `latency` steps have passed. The result can be checked by any model checker.
+pub fn latency_miter(
+    ctx: &mut Context,
+    lhs: &TransitionSystem,
+    rhs: &TransitionSystem,
+    latency: u32,
+) -> Result<TransitionSystem, MiterError> {
+    let mut miter = TransitionSystem::new(format!("{}_vs_{}", lhs.name, rhs.name));
+    for &input in lhs.inputs.iter() {
+        miter.add_input(ctx, input);
+    }
+    for state in lhs.states.iter() {
+        miter.add_state(ctx, state.clone());
+    }
+    miter.constraints.extend(lhs.constraints.iter().copied());
+
+    // inputs of the rhs are connected to the lhs, states are renamed to avoid collisions
+    let mut used: FxHashSet<String> = lhs
+        .inputs
+        .iter()
+        .chain(lhs.states.iter().map(|s| &s.symbol))
+        .chain(rhs.states.iter().map(|s| &s.symbol))
+        .map(|&e| ctx.get_symbol_name(e).unwrap().to_string())
+        .collect();
+    let mut substitute = FxHashMap::default();
+    for &input in rhs.inputs.iter() {
+        let name = ctx.get_symbol_name(input).unwrap().to_string();
+        let lhs_input = lhs
+            .lookup_input(ctx, &name)
+            .ok_or_else(|| MiterError::MissingInput(name.clone()))?;
+        if lhs_input.get_type(ctx) != input.get_type(ctx) {
+            return Err(MiterError::TypeMismatch(name));
+        }
+        substitute.insert(input, lhs_input);
+    }
+    for state in rhs.states.iter() {
+        let name = format!(
+            "{MITER_RHS_PREFIX}{}",
+            ctx.get_symbol_name(state.symbol).unwrap()
+        );
+        let name = unique_name(&mut used, name);
+        let name = ctx.string(name.into());
+        let renamed = ctx.symbol(name, state.symbol.get_type(ctx));
+        substitute.insert(state.symbol, renamed);
+    }
+    let translate = |ctx: &mut Context, e: ExprRef| {
+        simple_transform_expr(ctx, e, |_, e, _| substitute.get(&e).copied())
+    };
+    for state in rhs.states.iter() {
+        let symbol = translate(ctx, state.symbol);
+        let init = state.init.map(|e| translate(ctx, e));
+        let next = state.next.map(|e| translate(ctx, e));
+        miter.add_state(ctx, State { symbol, init, next });
+    }
+    for &constraint in rhs.constraints.iter() {
+        let constraint = translate(ctx, constraint);
+        miter.constraints.push(constraint);
+    }
+
+    // the comparison becomes valid after `latency` steps
+    let mut valid = ctx.one(1);
+    let mut delay = |ctx: &mut Context, miter: &mut TransitionSystem, name: String, e: ExprRef| {
+        let width = e.get_bv_type(ctx).unwrap();
+        let name = unique_name(&mut used, name);
+        let name = ctx.string(name.into());
+        let symbol = ctx.symbol(name, Type::BV(width));
+        let init = ctx.zero(width);
+        miter.add_state(
+            ctx,
+            State {
+                symbol,
+                init: Some(init),
+                next: Some(e),
+            },
+        );
+        symbol
+    };
+    for ii in 0..latency {
+        valid = delay(ctx, &mut miter, format!("miter.valid_{ii}"), valid);
+    }
+
+    for output in lhs.outputs.iter() {
+        let name = ctx[output.name].clone();
+        let rhs_output = rhs
+            .lookup_output(ctx, &name)
+            .ok_or_else(|| MiterError::MissingOutput(name.clone()))?;
+        let width = output.expr.get_bv_type(ctx);
+        if width.is_none() || width != rhs_output.get_bv_type(ctx) {
+            return Err(MiterError::TypeMismatch(name));
+        }
+        let mut expected = output.expr;
+        for ii in 0..latency {
+            expected = delay(ctx, &mut miter, format!("miter.{name}_{ii}"), expected);
+        }
+        let actual = translate(ctx, rhs_output);
+        let bad = ctx.build(|c| c.and(valid, c.not(c.equal(expected, actual))));
+        miter.bad_states.push(bad);
+    }
+    Ok(miter)
+}
+
+/// Appends a counter to `name` if it is already used by one of the systems in the miter.
+fn unique_name(used: &mut FxHashSet<String>, name: String) -> String {
+    let name = if used.contains(&name) {
+        (0u64..)
+            .map(|ii| format!("{name}_{ii}"))
+            .find(|n| !used.contains(n))
+            .unwrap()
+    } else {
+        name
+    };
+    used.insert(name.clone());
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::examples::alu;
+    use crate::mc::{check_exhaustive, ExhaustiveOptions, ModelCheckResult};
+    use crate::system::pipeline_inputs;
+
+    #[test]
+    fn test_pipelined_alu_latency() {
+        let (mut ctx, sys) = alu(2);
+        let mut pipelined = sys.clone();
+        pipeline_inputs(&mut ctx, &mut pipelined, 2);
+        let check = |ctx: &mut Context, latency| {
+            let miter = latency_miter(ctx, &sys, &pipelined, latency).unwrap();
+            let res = check_exhaustive(ctx, &miter, 4, ExhaustiveOptions::default()).unwrap();
+            matches!(res, ModelCheckResult::Success)
+        };
+        assert!(check(&mut ctx, 2));
+        assert!(!check(&mut ctx, 1));
+        assert!(!check(&mut ctx, 0));
+    }
+
+    #[test]
+    fn test_missing_output() {
+        let (mut ctx, sys) = alu(2);
+        let mut other = sys.clone();
+        other.outputs.pop();
+        assert!(matches!(
+            latency_miter(&mut ctx, &sys, &other, 0),
+            Err(MiterError::MissingOutput(name)) if name == "zero"
+        ));
+    }
+
+    #[test]
+    fn test_rhs_states_do_not_alias_lhs() {
+        let mut ctx = Context::default();
+        let mut lhs = TransitionSystem::new("lhs".to_string());
+        let mut rhs = TransitionSystem::new("rhs".to_string());
+        let zero = ctx.zero(1);
+        // the lhs state `rhs.r` never changes, the rhs state `r` toggles
+        let lhs_state = ctx.bv_symbol("rhs.r", 1);
+        lhs.add_state(
+            &ctx,
+            State {
+                symbol: lhs_state,
+                init: Some(zero),
+                next: Some(lhs_state),
+            },
+        );
+        let rhs_state = ctx.bv_symbol("r", 1);
+        let toggle = ctx.not(rhs_state);
+        rhs.add_state(
+            &ctx,
+            State {
+                symbol: rhs_state,
+                init: Some(zero),
+                next: Some(toggle),
+            },
+        );
+        lhs.add_output(&mut ctx, "o".into(), lhs_state);
+        rhs.add_output(&mut ctx, "o".into(), rhs_state);
+
+        let miter = latency_miter(&mut ctx, &lhs, &rhs, 0).unwrap();
+        let names: Vec<_> = miter
+            .states
+            .iter()
+            .map(|s| ctx.get_symbol_name(s.symbol).unwrap().to_string())
+            .collect();
+        assert_eq!(names, ["rhs.r", "rhs.r_0"]);
+        let res = check_exhaustive(&mut ctx, &miter, 2, ExhaustiveOptions::default()).unwrap();
+        assert!(matches!(res, ModelCheckResult::Fail(_)));
+    }
+}
@@ -0,0 +1,153 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Solver Based Simplification
+//! Structural rewrites only see the shape of an expression. A solver can prove that a
+//! sub-expression always evaluates to the same value under some assumptions, e.g., that a
+//! guard is always true in the reachable states. We take the value of every sub-expression in
+//! a single model as the candidate constant and then ask the solver to prove it, starting at
+//! the root. Proven constants are substituted and the result is simplified structurally, which
+//! removes redundant guards and muxes.
+//!
+//! Every candidate costs one query. Once the time budget is used up, no more queries are
+//! issued and only the simplifications proven so far are applied. A single query is never
+//! interrupted, thus the budget can be exceeded by the duration of one query.
+
+use super::collect_symbols;
+use crate::expr::traversal::{top_down, TraversalCmd};
+use crate::expr::{simple_transform_expr, simplify_single_expression, Context, ExprRef, TypeCheck};
+use crate::mc::get_smt_value;
+use crate::smt::{
+    CheckSatResponse, Logic, SmtLibSolver, Solver, SolverContext, SolverMetaData, BITWUZLA,
+};
+use baa::Value;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimplifyBudget {
+    pub solver: SmtLibSolver,
+    /// stop proving candidates after this much time
+    pub time: Duration,
+    /// maximum number of candidates to check
+    pub max_queries: usize,
+}
+
+impl Default for SimplifyBudget {
+    fn default() -> Self {
+        Self {
+            solver: BITWUZLA,
+            time: Duration::from_secs(1),
+            max_queries: 1000,
+        }
+    }
+}
+
+/// Replaces sub-expressions of `expr` that are constant whenever all `assumptions` hold.
+/// Returns `expr` unchanged if the assumptions are contradictory.
+pub fn smt_simplify(
+    ctx: &mut Context,
+    expr: ExprRef,
+    assumptions: &[ExprRef],
+    budget: &SimplifyBudget,
+) -> crate::smt::Result<ExprRef> {
+    let start = Instant::now();
+    let solver = &budget.solver;
+    let mut smt_ctx = solver.start(None::<std::fs::File>)?;
+    smt_ctx.set_logic(if solver.supports_uf() {
+        Logic::QfAufbv
+    } else {
+        Logic::QfAbv
+    })?;
+    for symbol in collect_symbols(ctx, assumptions.iter().copied().chain([expr])) {
+        smt_ctx.declare_const(ctx, symbol)?;
+    }
+    for &assumption in assumptions.iter() {
+        smt_ctx.assert(ctx, assumption)?;
+    }
+    if smt_ctx.check_sat()? != CheckSatResponse::Sat {
+        return Ok(expr);
+    }
+
+    // the value in the first model is the only possible constant for every sub-expression
+    let candidates = candidates(ctx, expr);
+    let mut values = Vec::with_capacity(candidates.len());
+    for &e in candidates.iter() {
+        match get_smt_value(ctx, &mut smt_ctx, e)? {
+            Value::BitVec(value) => values.push((e, ctx.bv_lit(&value))),
+            Value::Array(_) => unreachable!("only bit-vectors are candidates"),
+        }
+    }
+
+    let mut proven: FxHashMap<ExprRef, ExprRef> = FxHashMap::default();
+    // expressions inside of a proven constant do not need to be checked
+    let mut covered: FxHashSet<ExprRef> = FxHashSet::default();
+    for (e, value) in values.into_iter().take(budget.max_queries) {
+        if start.elapsed() >= budget.time {
+            break;
+        }
+        if covered.contains(&e) {
+            continue;
+        }
+        smt_ctx.push()?;
+        let differs = ctx.build(|c| c.not(c.equal(e, value)));
+        smt_ctx.assert(ctx, differs)?;
+        let res = smt_ctx.check_sat()?;
+        smt_ctx.pop()?;
+        if res == CheckSatResponse::Unsat {
+            proven.insert(e, value);
+            top_down(ctx, e, |_, child| {
+                if covered.insert(child) {
+                    TraversalCmd::Continue
+                } else {
+                    TraversalCmd::Stop
+                }
+            });
+        }
+    }
+
+    if proven.is_empty() {
+        return Ok(expr);
+    }
+    let substituted = simple_transform_expr(ctx, expr, |_, e, _| proven.get(&e).copied());
+    Ok(simplify_single_expression(ctx, substituted))
+}
+
+/// Non-trivial bit-vector sub-expressions, parents before their children.
+fn candidates(ctx: &Context, expr: ExprRef) -> Vec<ExprRef> {
+    let mut visited = FxHashSet::default();
+    let mut out = vec![];
+    top_down(ctx, expr, |ctx, e| {
+        if !visited.insert(e) {
+            return TraversalCmd::Stop;
+        }
+        if e.get_type(ctx).is_bit_vector() && !ctx[e].is_symbol() && !ctx[e].is_bv_lit() {
+            out.push(e);
+        }
+        TraversalCmd::Continue
+    });
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remove_redundant_guard() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("a", 8);
+        let b = ctx.bv_symbol("b", 8);
+        // `a > 3` always holds when `a > 10`
+        let e = ctx.build(|c| c.ite(c.greater(a, c.bit_vec_val(3, 8)), a, b));
+        let assumption = ctx.build(|c| c.greater(a, c.bit_vec_val(10, 8)));
+        let simplified =
+            smt_simplify(&mut ctx, e, &[assumption], &SimplifyBudget::default()).unwrap();
+        assert_eq!(simplified, a);
+
+        // without the assumption, nothing can be proven
+        let unchanged = smt_simplify(&mut ctx, e, &[], &SimplifyBudget::default()).unwrap();
+        assert_eq!(unchanged, e);
+    }
+}
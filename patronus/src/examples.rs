@@ -0,0 +1,262 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Example Systems
+//! Width parametric designs that can be shared by tests, fuzzers and documentation.
+//! Every generator creates a fresh [`Context`] together with the system.
+
+use crate::expr::{Context, ExprRef, WidthInt};
+use crate::system::{State, TransitionSystem};
+
+/// ALU operations, selected by the 3-bit `op` input.
+pub const ALU_ADD: u64 = 0;
+pub const ALU_SUB: u64 = 1;
+pub const ALU_AND: u64 = 2;
+pub const ALU_OR: u64 = 3;
+pub const ALU_XOR: u64 = 4;
+pub const ALU_SHIFT_LEFT: u64 = 5;
+pub const ALU_SHIFT_RIGHT: u64 = 6;
+/// signed less than, the result is zero extended
+pub const ALU_LESS_SIGNED: u64 = 7;
+
+/// A combinational ALU with inputs `a`, `b` and `op` and outputs `out` and `zero`.
+pub fn alu(width: WidthInt) -> (Context, TransitionSystem) {
+    let mut ctx = Context::default();
+    let mut sys = TransitionSystem::new(format!("alu_{width}"));
+    let a = ctx.bv_symbol("a", width);
+    let b = ctx.bv_symbol("b", width);
+    let op = ctx.bv_symbol("op", 3);
+    for input in [a, b, op] {
+        sys.add_input(&ctx, input);
+    }
+    let out = ctx.build(|c| {
+        let results = [
+            (ALU_ADD, c.add(a, b)),
+            (ALU_SUB, c.sub(a, b)),
+            (ALU_AND, c.and(a, b)),
+            (ALU_OR, c.or(a, b)),
+            (ALU_XOR, c.xor(a, b)),
+            (ALU_SHIFT_LEFT, c.shift_left(a, b)),
+            (ALU_SHIFT_RIGHT, c.shift_right(a, b)),
+        ];
+        let less = c.zero_extend(c.greater_signed(b, a), width - 1);
+        results
+            .into_iter()
+            .rev()
+            .fold(less, |other, (code, result)| {
+                c.ite(c.equal(op, c.bit_vec_val(code, 3)), result, other)
+            })
+    });
+    let zero = ctx.build(|c| c.equal(out, c.zero(width)));
+    sys.add_output(&mut ctx, "out".into(), out);
+    sys.add_output(&mut ctx, "zero".into(), zero);
+    (ctx, sys)
+}
+
+/// A FIFO with inputs `push`, `pop` and `data_in` and outputs `data_out`, `empty` and `full`.
+/// Pushing into a full or popping from an empty FIFO has no effect.
+/// The bad state is reached if the FIFO is full and empty at the same time.
+pub fn fifo(depth: u64, width: WidthInt) -> (Context, TransitionSystem) {
+    assert!(
+        depth >= 2 && depth.is_power_of_two(),
+        "depth needs to be a power of two, not {depth}"
+    );
+    let addr_width = depth.ilog2() as WidthInt;
+    // pointers have an additional bit in order to distinguish a full from an empty FIFO
+    let ptr_width = addr_width + 1;
+
+    let mut ctx = Context::default();
+    let mut sys = TransitionSystem::new(format!("fifo_{depth}x{width}"));
+    let push = ctx.bv_symbol("push", 1);
+    let pop = ctx.bv_symbol("pop", 1);
+    let data_in = ctx.bv_symbol("data_in", width);
+    for input in [push, pop, data_in] {
+        sys.add_input(&ctx, input);
+    }
+    let mem = ctx.array_symbol("mem", addr_width, width);
+    let wr = ctx.bv_symbol("wr", ptr_width);
+    let rd = ctx.bv_symbol("rd", ptr_width);
+
+    let empty = ctx.equal(rd, wr);
+    let full = ctx.build(|c| c.equal(c.sub(wr, rd), c.bit_vec_val(depth, ptr_width)));
+    let do_push = ctx.build(|c| c.and(push, c.not(full)));
+    let do_pop = ctx.build(|c| c.and(pop, c.not(empty)));
+    let mem_next = ctx.build(|c| {
+        c.ite(
+            do_push,
+            c.array_store(mem, c.slice(wr, addr_width - 1, 0), data_in),
+            mem,
+        )
+    });
+    let wr_next = ctx.build(|c| c.ite(do_push, c.add(wr, c.one(ptr_width)), wr));
+    let rd_next = ctx.build(|c| c.ite(do_pop, c.add(rd, c.one(ptr_width)), rd));
+    let data_out = ctx.build(|c| c.array_read(mem, c.slice(rd, addr_width - 1, 0)));
+
+    sys.add_state(
+        &ctx,
+        State {
+            symbol: mem,
+            init: None,
+            next: Some(mem_next),
+        },
+    );
+    for (symbol, next) in [(wr, wr_next), (rd, rd_next)] {
+        let init = ctx.zero(ptr_width);
+        sys.add_state(
+            &ctx,
+            State {
+                symbol,
+                init: Some(init),
+                next: Some(next),
+            },
+        );
+    }
+    sys.add_output(&mut ctx, "data_out".into(), data_out);
+    sys.add_output(&mut ctx, "empty".into(), empty);
+    sys.add_output(&mut ctx, "full".into(), full);
+    let bad = ctx.and(full, empty);
+    sys.bad_states.push(bad);
+    (ctx, sys)
+}
+
+/// Feedback taps (1-based bit positions) that result in a maximal length sequence.
+const LFSR_TAPS: &[(WidthInt, &[WidthInt])] = &[
+    (3, &[3, 2]),
+    (4, &[4, 3]),
+    (5, &[5, 3]),
+    (6, &[6, 5]),
+    (7, &[7, 6]),
+    (8, &[8, 6, 5, 4]),
+    (16, &[16, 15, 13, 4]),
+    (32, &[32, 22, 2, 1]),
+    (64, &[64, 63, 61, 60]),
+];
+
+/// A Fibonacci LFSR that starts at one and is observable through the `out` output.
+/// Widths 3 to 8, 16, 32 and 64 produce a maximal length sequence, all other widths use the
+/// two most significant bits as taps.
+pub fn lfsr(width: WidthInt) -> (Context, TransitionSystem) {
+    assert!(width >= 2, "an LFSR needs at least two bits");
+    let taps: Vec<WidthInt> = LFSR_TAPS
+        .iter()
+        .find(|(w, _)| *w == width)
+        .map(|(_, taps)| taps.to_vec())
+        .unwrap_or_else(|| vec![width, width - 1]);
+
+    let mut ctx = Context::default();
+    let mut sys = TransitionSystem::new(format!("lfsr_{width}"));
+    let state = ctx.bv_symbol("state", width);
+    let next = ctx.build(|c| {
+        let feedback = taps
+            .iter()
+            .map(|&t| c.slice(state, t - 1, t - 1))
+            .reduce(|a, b| c.xor(a, b))
+            .unwrap();
+        c.concat(c.slice(state, width - 2, 0), feedback)
+    });
+    let init = ctx.one(width);
+    sys.add_state(
+        &ctx,
+        State {
+            symbol: state,
+            init: Some(init),
+            next: Some(next),
+        },
+    );
+    sys.add_output(&mut ctx, "out".into(), state);
+    (ctx, sys)
+}
+
+/// Convenience function to look up an input of an example system by name.
+pub fn input(ctx: &Context, sys: &TransitionSystem, name: &str) -> ExprRef {
+    sys.lookup_input(ctx, name)
+        .unwrap_or_else(|| panic!("{} has no input named `{name}`", sys.name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::{InitKind, Interpreter, Simulator};
+    use baa::BitVecValue;
+
+    fn output(sim: &Interpreter, ctx: &Context, sys: &TransitionSystem, name: &str) -> u64 {
+        sim.get(sys.lookup_output(ctx, name).unwrap())
+            .try_into_u64()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_alu() {
+        let (ctx, sys) = alu(8);
+        let mut sim = Interpreter::new(&ctx, &sys);
+        sim.init(InitKind::Zero);
+        let (a, b, op) = (
+            input(&ctx, &sys, "a"),
+            input(&ctx, &sys, "b"),
+            input(&ctx, &sys, "op"),
+        );
+        sim.set(a, &BitVecValue::from_u64(3, 8)).unwrap();
+        sim.set(b, &BitVecValue::from_u64(5, 8)).unwrap();
+        for (code, expected) in [
+            (ALU_ADD, 8),
+            (ALU_SUB, 254),
+            (ALU_AND, 1),
+            (ALU_OR, 7),
+            (ALU_XOR, 6),
+            (ALU_SHIFT_LEFT, 96),
+            (ALU_SHIFT_RIGHT, 0),
+            (ALU_LESS_SIGNED, 1),
+        ] {
+            sim.set(op, &BitVecValue::from_u64(code, 3)).unwrap();
+            assert_eq!(output(&sim, &ctx, &sys, "out"), expected, "op {code}");
+        }
+    }
+
+    #[test]
+    fn test_fifo() {
+        let (ctx, sys) = fifo(2, 8);
+        let mut sim = Interpreter::new(&ctx, &sys);
+        sim.init(InitKind::Zero);
+        let (push, pop, data_in) = (
+            input(&ctx, &sys, "push"),
+            input(&ctx, &sys, "pop"),
+            input(&ctx, &sys, "data_in"),
+        );
+        assert_eq!(output(&sim, &ctx, &sys, "empty"), 1);
+        sim.set(push, &BitVecValue::from_u64(1, 1)).unwrap();
+        for value in [7, 8, 9] {
+            sim.set(data_in, &BitVecValue::from_u64(value, 8)).unwrap();
+            sim.step();
+        }
+        // the last push is dropped since the FIFO is full
+        assert_eq!(output(&sim, &ctx, &sys, "full"), 1);
+        sim.set(push, &BitVecValue::from_u64(0, 1)).unwrap();
+        sim.set(pop, &BitVecValue::from_u64(1, 1)).unwrap();
+        let mut popped = vec![];
+        for _ in 0..3 {
+            popped.push(output(&sim, &ctx, &sys, "data_out"));
+            sim.step();
+        }
+        assert_eq!(popped[..2], [7, 8]);
+        assert_eq!(output(&sim, &ctx, &sys, "empty"), 1);
+    }
+
+    #[test]
+    fn test_lfsr_period() {
+        for width in [4, 8] {
+            let (ctx, sys) = lfsr(width);
+            let mut sim = Interpreter::new(&ctx, &sys);
+            sim.init(InitKind::Zero);
+            let mut period = 0;
+            loop {
+                sim.step();
+                period += 1;
+                if output(&sim, &ctx, &sys, "out") == 1 {
+                    break;
+                }
+            }
+            assert_eq!(period, (1 << width) - 1);
+        }
+    }
+}
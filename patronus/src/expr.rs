@@ -1,31 +1,56 @@
 // Copyright 2023 The Regents of the University of California
 // released under BSD 3-Clause License
 // author: Kevin Laeufer <laeufer@berkeley.edu>
+mod attributes;
+mod canonicalize;
 mod context;
+mod enums;
 mod eval;
+mod fixed;
+mod float;
 mod foreach;
+mod frozen;
+mod limits;
 mod meta;
 mod nodes;
+mod ops;
 mod parse;
+mod rotate;
+mod saturate;
 mod serialize;
 mod simplify;
 mod transform;
 pub mod traversal;
 mod types;
 
-pub use context::{Builder, Context, ExprRef, StringRef};
-pub use eval::{eval_array_expr, eval_bv_expr, eval_expr, SymbolValueStore};
+pub use attributes::{Attributes, SourceLocation, ATTR_CLOCK, ATTR_KEEP, ATTR_RESET};
+pub use canonicalize::{canonicalize_single_expression, Canonicalizer};
+pub(crate) use context::hash_array;
+pub use context::{Builder, Context, ContextStats, ExprRef, KindStats, StringRef};
+pub use enums::{EnumEncoding, EnumType};
+pub use eval::{
+    check_evaluable, eval, eval_array_expr, eval_bv_expr, eval_expr, try_eval_expr, Assignment,
+    GetExprValue, SymbolValueDelta, SymbolValueStore,
+};
+pub(crate) use eval::{eval_expr_observed, put_bv, put_u64, take_bv, take_u64};
+pub use fixed::{Overflow, QFormat};
+pub use float::FloatFormat;
 pub use foreach::ForEachChild;
+pub use frozen::FrozenContext;
+pub use limits::{LimitError, WidthLimits};
 pub use meta::{
     get_fixed_point, DenseExprMetaData, DenseExprSet, ExprMap, ExprSet, SparseExprMap,
     SparseExprSet,
 };
-pub use nodes::{ArrayType, BVLitValue, Expr, Type, WidthInt};
-pub use parse::parse_expr;
+pub use nodes::{ArrayLitValue, ArrayType, BVLitValue, Expr, Type, WidthInt};
+pub use ops::BuilderExpr;
+pub use parse::{parse_expr, try_parse_expr};
+pub use rotate::Rotation;
+pub use saturate::{Rounding, Saturating, SaturatingOp};
 pub use serialize::SerializableIrNode;
 pub(crate) use serialize::{serialize_expr, serialize_expr_ref};
 pub(crate) use simplify::simplify;
 pub use simplify::{simplify_single_expression, Simplifier};
-pub use transform::simple_transform_expr;
-pub(crate) use transform::{do_transform_expr, ExprTransformMode};
-pub use types::{TypeCheck, TypeCheckError};
+pub use transform::{copy_expr, simple_transform_expr};
+pub(crate) use transform::{do_transform_expr, expr_with_children, ExprTransformMode};
+pub use types::{ExprError, TypeCheck, TypeCheckError};
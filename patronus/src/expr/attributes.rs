@@ -0,0 +1,198 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Attributes
+//! Frontends often know where a signal was declared and what its role is, e.g., that it is a
+//! clock or that it should not be optimized away. This information can be attached to any
+//! expression in the [`Context`]. Expression transforms and [`copy_expr`](crate::expr::copy_expr)
+//! carry attributes over to the expressions they create and the system serializer prints them
+//! as comments.
+
+use super::{Context, EnumType, ExprRef, QFormat, StringRef};
+use std::io::Write;
+
+pub const ATTR_CLOCK: &str = "clock";
+pub const ATTR_RESET: &str = "reset";
+pub const ATTR_KEEP: &str = "keep";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SourceLocation {
+    pub file: StringRef,
+    pub line: u32,
+    pub column: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Attributes {
+    pub source: Option<SourceLocation>,
+    /// user attributes like [`ATTR_CLOCK`], [`ATTR_RESET`] or [`ATTR_KEEP`]
+    pub flags: Vec<StringRef>,
+    pub enum_type: Option<EnumType>,
+    pub q_format: Option<QFormat>,
+}
+
+impl Attributes {
+    /// Adds everything from `other` that is missing in `self`. Our source location takes
+    /// precedence.
+    fn merge(&mut self, other: &Attributes) {
+        if self.source.is_none() {
+            self.source = other.source;
+        }
+        if self.enum_type.is_none() {
+            self.enum_type = other.enum_type.clone();
+        }
+        if self.q_format.is_none() {
+            self.q_format = other.q_format;
+        }
+        for &flag in other.flags.iter() {
+            if !self.flags.contains(&flag) {
+                self.flags.push(flag);
+            }
+        }
+    }
+
+    pub fn serialize<W: Write>(&self, ctx: &Context, writer: &mut W) -> std::io::Result<()> {
+        let mut sep = "";
+        if let Some(loc) = &self.source {
+            write!(writer, "@{}:{}", ctx[loc.file], loc.line)?;
+            if let Some(column) = loc.column {
+                write!(writer, ":{column}")?;
+            }
+            sep = " ";
+        }
+        for &flag in self.flags.iter() {
+            write!(writer, "{sep}{}", ctx[flag])?;
+            sep = " ";
+        }
+        if let Some(tpe) = &self.enum_type {
+            write!(writer, "{sep}enum {}", tpe.name)?;
+            sep = " ";
+        }
+        if let Some(format) = &self.q_format {
+            write!(writer, "{sep}{format}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Context {
+    pub fn attributes(&self, e: ExprRef) -> Option<&Attributes> {
+        self.attributes.get(&e)
+    }
+
+    pub fn source_location(&self, e: ExprRef) -> Option<&SourceLocation> {
+        self.attributes(e)?.source.as_ref()
+    }
+
+    pub fn set_source_location(&mut self, e: ExprRef, file: &str, line: u32, column: Option<u32>) {
+        let file = self.string(file.into());
+        self.attributes.entry(e).or_default().source = Some(SourceLocation { file, line, column });
+    }
+
+    pub fn add_attribute(&mut self, e: ExprRef, name: &str) {
+        let flag = self.string(name.into());
+        let attrs = self.attributes.entry(e).or_default();
+        if !attrs.flags.contains(&flag) {
+            attrs.flags.push(flag);
+        }
+    }
+
+    pub fn has_attribute(&self, e: ExprRef, name: &str) -> bool {
+        self.attributes(e)
+            .is_some_and(|a| a.flags.iter().any(|&f| self[f] == name))
+    }
+
+    /// All expressions that carry the attribute `name`.
+    pub fn with_attribute<'a>(&'a self, name: &'a str) -> impl Iterator<Item = ExprRef> + 'a {
+        self.attributes
+            .iter()
+            .filter(move |(_, a)| a.flags.iter().any(|&f| self[f] == name))
+            .map(|(&e, _)| e)
+    }
+
+    /// Adds the attributes of `from` to `to`, e.g., after `from` was replaced by `to`.
+    pub fn copy_attributes(&mut self, from: ExprRef, to: ExprRef) {
+        if from == to {
+            return;
+        }
+        if let Some(attrs) = self.attributes.get(&from).cloned() {
+            self.attributes.entry(to).or_default().merge(&attrs);
+        }
+    }
+
+    /// Adds the attributes of `from` in `src` to `to` in `self`.
+    pub(crate) fn import_attributes(&mut self, src: &Context, from: ExprRef, to: ExprRef) {
+        let Some(attrs) = src.attributes(from) else {
+            return;
+        };
+        let imported = Attributes {
+            source: attrs.source.map(|loc| SourceLocation {
+                file: self.string(src[loc.file].as_str().into()),
+                ..loc
+            }),
+            flags: attrs
+                .flags
+                .iter()
+                .map(|&f| self.string(src[f].as_str().into()))
+                .collect(),
+            enum_type: attrs.enum_type.clone(),
+            q_format: attrs.q_format,
+        };
+        self.attributes.entry(to).or_default().merge(&imported);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::{copy_expr, simple_transform_expr, SerializableIrNode, SparseExprMap};
+    use crate::system::TransitionSystem;
+
+    #[test]
+    fn test_attributes_survive_transforms() {
+        let mut ctx = Context::default();
+        let clk = ctx.bv_symbol("clk", 1);
+        let a = ctx.bv_symbol("a", 8);
+        let sum = ctx.build(|c| c.add(a, c.one(8)));
+        ctx.add_attribute(clk, ATTR_CLOCK);
+        ctx.add_attribute(sum, ATTR_KEEP);
+        ctx.add_attribute(sum, ATTR_KEEP);
+        ctx.set_source_location(sum, "counter.v", 12, Some(3));
+        assert!(ctx.has_attribute(clk, ATTR_CLOCK));
+        assert!(!ctx.has_attribute(a, ATTR_CLOCK));
+        assert_eq!(ctx.with_attribute(ATTR_CLOCK).collect::<Vec<_>>(), [clk]);
+        assert_eq!(ctx.attributes(sum).unwrap().flags.len(), 1);
+
+        // replace `a` with `b`
+        let b = ctx.bv_symbol("b", 8);
+        let renamed = simple_transform_expr(&mut ctx, sum, |_, e, _| (e == a).then_some(b));
+        assert_ne!(renamed, sum);
+        assert!(ctx.has_attribute(renamed, ATTR_KEEP));
+        assert_eq!(ctx.source_location(renamed).unwrap().line, 12);
+
+        let mut dst = Context::default();
+        let copied = copy_expr(&ctx, &mut dst, &mut SparseExprMap::default(), renamed);
+        let mut out = vec![];
+        dst.attributes(copied)
+            .unwrap()
+            .serialize(&dst, &mut out)
+            .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "@counter.v:12:3 keep");
+    }
+
+    #[test]
+    fn test_serialize_attributes() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let clk = ctx.bv_symbol("clk", 1);
+        let a = ctx.bv_symbol("a", 8);
+        sys.add_input(&ctx, clk);
+        sys.add_input(&ctx, a);
+        ctx.add_attribute(clk, ATTR_CLOCK);
+        ctx.set_source_location(a, "top.v", 3, None);
+        let out = sys.serialize_to_str(&ctx);
+        assert!(out.contains("input clk : bv<1> ; clock\n"), "{out}");
+        assert!(out.contains("input a : bv<8> ; @top.v:3\n"), "{out}");
+    }
+}
@@ -0,0 +1,196 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Canonicalization
+//! Brings chains of commutative and associative operators into a canonical shape:
+//! all operands of a chain are sorted by a structural key and the chain is rebuilt
+//! left-associatively, literals come last. Thus `(b + a) + c` and `a + (c + b)` result in
+//! the same expression, which improves deduplication through hash consing.
+
+use super::{do_transform_expr, Context, Expr, ExprRef, ForEachChild, SparseExprMap, TypeCheck};
+use crate::expr::transform::ExprTransformMode;
+use baa::BitVecOps;
+use rustc_hash::{FxHashMap, FxHasher};
+use std::hash::{Hash, Hasher};
+
+/// Canonicalizes a single expression.
+pub fn canonicalize_single_expression(ctx: &mut Context, expr: ExprRef) -> ExprRef {
+    Canonicalizer::default().canonicalize(ctx, expr)
+}
+
+/// Canonicalizes expressions and caches the results as well as the structural keys.
+#[derive(Debug, Default)]
+pub struct Canonicalizer {
+    cache: SparseExprMap<Option<ExprRef>>,
+    keys: FxHashMap<ExprRef, u64>,
+}
+
+impl Canonicalizer {
+    pub fn canonicalize(&mut self, ctx: &mut Context, e: ExprRef) -> ExprRef {
+        let keys = &mut self.keys;
+        do_transform_expr(
+            ctx,
+            ExprTransformMode::SingleStep,
+            &mut self.cache,
+            vec![e],
+            |ctx, expr, children| canonicalize(ctx, keys, expr, children),
+        );
+        self.cache[e].unwrap()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    And,
+    Or,
+    Xor,
+    Add,
+    Mul,
+    Equal,
+}
+
+impl Op {
+    fn from_expr(expr: &Expr) -> Option<Self> {
+        match expr {
+            Expr::BVAnd(..) => Some(Op::And),
+            Expr::BVOr(..) => Some(Op::Or),
+            Expr::BVXor(..) => Some(Op::Xor),
+            Expr::BVAdd(..) => Some(Op::Add),
+            Expr::BVMul(..) => Some(Op::Mul),
+            Expr::BVEqual(..) => Some(Op::Equal),
+            _ => None,
+        }
+    }
+
+    /// equality is commutative, but not associative
+    fn is_associative(&self) -> bool {
+        !matches!(self, Op::Equal)
+    }
+
+    fn build(&self, ctx: &mut Context, a: ExprRef, b: ExprRef) -> ExprRef {
+        match self {
+            Op::And => ctx.and(a, b),
+            Op::Or => ctx.or(a, b),
+            Op::Xor => ctx.xor(a, b),
+            Op::Add => ctx.add(a, b),
+            Op::Mul => ctx.mul(a, b),
+            Op::Equal => ctx.equal(a, b),
+        }
+    }
+}
+
+/// Canonicalizes one expression, assuming that all its children are already canonical.
+fn canonicalize(
+    ctx: &mut Context,
+    keys: &mut FxHashMap<ExprRef, u64>,
+    expr: ExprRef,
+    children: &[ExprRef],
+) -> Option<ExprRef> {
+    let op = Op::from_expr(&ctx[expr])?;
+    let mut operands = Vec::with_capacity(children.len());
+    if op.is_associative() {
+        for &child in children {
+            collect_chain(ctx, op, child, &mut operands);
+        }
+    } else {
+        operands.extend_from_slice(children);
+    }
+    operands.sort_by_cached_key(|&e| (ctx[e].is_bv_lit(), structural_key(ctx, keys, e), e));
+    let mut operands = operands.into_iter();
+    let first = operands.next().unwrap();
+    Some(operands.fold(first, |acc, e| op.build(ctx, acc, e)))
+}
+
+fn collect_chain(ctx: &Context, op: Op, e: ExprRef, out: &mut Vec<ExprRef>) {
+    let mut todo = vec![e];
+    while let Some(e) = todo.pop() {
+        if Op::from_expr(&ctx[e]) == Some(op) {
+            // visit the left operand first
+            let mut children = vec![];
+            ctx[e].for_each_child(|&c| children.push(c));
+            todo.extend(children.into_iter().rev());
+        } else {
+            out.push(e);
+        }
+    }
+}
+
+/// A key that only depends on the structure of the expression, not on the order in which
+/// expressions were added to the context.
+fn structural_key(ctx: &Context, keys: &mut FxHashMap<ExprRef, u64>, e: ExprRef) -> u64 {
+    let mut todo = vec![e];
+    while let Some(&e) = todo.last() {
+        if keys.contains_key(&e) {
+            todo.pop();
+            continue;
+        }
+        let mut missing = false;
+        ctx[e].for_each_child(|c| {
+            if !keys.contains_key(c) {
+                todo.push(*c);
+                missing = true;
+            }
+        });
+        if missing {
+            continue;
+        }
+        todo.pop();
+        let mut hasher = FxHasher::default();
+        let expr = &ctx[e];
+        std::mem::discriminant(expr).hash(&mut hasher);
+        e.get_type(ctx).hash(&mut hasher);
+        match expr {
+            Expr::BVLiteral(value) => value.get(ctx).to_bit_str().hash(&mut hasher),
+            Expr::BVSlice { hi, lo, .. } => (hi, lo).hash(&mut hasher),
+            Expr::BVZeroExt { by, .. } | Expr::BVSignExt { by, .. } => by.hash(&mut hasher),
+            _ => {
+                if let Some(name) = ctx.get_symbol_name(e) {
+                    name.hash(&mut hasher);
+                }
+            }
+        }
+        expr.for_each_child(|c| keys[c].hash(&mut hasher));
+        keys.insert(e, hasher.finish());
+    }
+    keys[&e]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::SerializableIrNode;
+
+    #[test]
+    fn test_canonicalize_add_chain() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("a", 8);
+        let b = ctx.bv_symbol("b", 8);
+        let c = ctx.bv_symbol("c", 8);
+        let left = ctx.build(|x| x.add(x.add(b, a), x.add(c, x.one(8))));
+        let right = ctx.build(|x| x.add(x.one(8), x.add(a, x.add(c, b))));
+        let left = canonicalize_single_expression(&mut ctx, left);
+        let right = canonicalize_single_expression(&mut ctx, right);
+        assert_eq!(left, right);
+        // the literal is always the last operand
+        let Expr::BVAdd(_, last, _) = ctx[left] else {
+            panic!("expected an addition");
+        };
+        assert!(ctx[last].is_bv_lit());
+    }
+
+    #[test]
+    fn test_canonicalize_is_independent_of_creation_order() {
+        let mut ctx1 = Context::default();
+        let a1 = ctx1.bv_symbol("a", 4);
+        let b1 = ctx1.bv_symbol("b", 4);
+        let e1 = ctx1.build(|x| x.equal(x.mul(a1, b1), x.and(b1, a1)));
+        let mut ctx2 = Context::default();
+        let b2 = ctx2.bv_symbol("b", 4);
+        let a2 = ctx2.bv_symbol("a", 4);
+        let e2 = ctx2.build(|x| x.equal(x.and(a2, b2), x.mul(b2, a2)));
+        let e1 = canonicalize_single_expression(&mut ctx1, e1);
+        let e2 = canonicalize_single_expression(&mut ctx2, e2);
+        assert_eq!(e1.serialize_to_str(&ctx1), e2.serialize_to_str(&ctx2));
+    }
+}
@@ -14,15 +14,19 @@
 //! are no checks to ensure that a [`ExprRef`] or [`StringRef`] from different contexts are
 //! not matched. Thus working with more than one [`Context`] object can be dangerous.
 
+use crate::expr::attributes::Attributes;
 use crate::expr::nodes::*;
-use crate::expr::TypeCheck;
+use crate::expr::{FloatFormat, Overflow, QFormat, Rounding, TypeCheck};
 use baa::{
-    ArrayOps, BitVecValue, BitVecValueIndex, BitVecValueRef, IndexToRef, SparseArrayValue, Value,
+    ArrayMutOps, ArrayOps, ArrayValue, BitVecOps, BitVecValue, BitVecValueIndex, BitVecValueRef,
+    IndexToRef, SparseArrayValue, Value,
 };
-use rustc_hash::FxBuildHasher;
+use rustc_hash::{FxBuildHasher, FxHashMap, FxHasher};
 use std::borrow::Borrow;
 use std::cell::RefCell;
-use std::fmt::{Debug, Formatter};
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Display, Formatter};
+use std::hash::{Hash, Hasher};
 use std::num::NonZeroU32;
 use std::ops::Index;
 
@@ -75,6 +79,11 @@ pub struct Context {
     strings: indexmap::IndexSet<String, FxBuildHasher>,
     exprs: indexmap::IndexSet<Expr, FxBuildHasher>,
     values: baa::ValueInterner,
+    /// contents of all array literals
+    arrays: Vec<ArrayValue>,
+    /// content hash to the indices of all array literals with that hash
+    array_pool: FxHashMap<u64, Vec<usize>>,
+    pub(super) attributes: FxHashMap<ExprRef, Attributes>,
     // cached special values
     true_expr_ref: ExprRef,
     false_expr_ref: ExprRef,
@@ -87,6 +96,9 @@ impl Default for Context {
             strings: Default::default(),
             exprs: Default::default(),
             values: Default::default(),
+            arrays: Default::default(),
+            array_pool: Default::default(),
+            attributes: Default::default(),
             true_expr_ref: ExprRef::from_index(0),
             false_expr_ref: ExprRef::from_index(0),
         };
@@ -103,6 +115,18 @@ impl Context {
         self[reference].get_symbol_name(self)
     }
 
+    /// Returns `prefix`, followed by a counter if needed, such that the name was never used
+    /// in this context. A symbol created with the name can thus not alias an existing one.
+    pub fn fresh_name(&self, prefix: &str) -> String {
+        if !self.strings.contains(prefix) {
+            return prefix.to_string();
+        }
+        (0u64..)
+            .map(|ii| format!("{prefix}_{ii}"))
+            .find(|name| !self.strings.contains(name.as_str()))
+            .unwrap()
+    }
+
     pub(crate) fn add_expr(&mut self, value: Expr) -> ExprRef {
         let (index, _) = self.exprs.insert_full(value);
         ExprRef::from_index(index)
@@ -120,6 +144,116 @@ impl Context {
     pub(crate) fn get_bv_value(&self, index: impl Borrow<BitVecValueIndex>) -> BitVecValueRef<'_> {
         self.values.words().get_ref(index)
     }
+
+    pub(crate) fn get_array_value(&self, value: ArrayLitValue) -> &ArrayValue {
+        &self.arrays[value.index()]
+    }
+
+    /// Counts all interned expressions by kind and estimates how much memory they use.
+    pub fn stats(&self) -> ContextStats {
+        let node_bytes = std::mem::size_of::<Expr>() + std::mem::size_of::<u64>();
+        let word_bytes = |width: WidthInt| width.div_ceil(64) as usize * 8;
+        let mut kinds: FxHashMap<std::mem::Discriminant<Expr>, (&Expr, KindStats)> =
+            FxHashMap::default();
+        for expr in self.exprs.iter() {
+            let value_bytes = match expr {
+                Expr::BVLiteral(value) => word_bytes(value.width()),
+                Expr::ArrayLiteral {
+                    value,
+                    index_width,
+                    data_width,
+                } => {
+                    let sparse: SparseArrayValue = value.get(self).into();
+                    let entries = sparse.non_default_entries().count() + 1;
+                    entries * (word_bytes(*index_width) + word_bytes(*data_width))
+                }
+                _ => 0,
+            };
+            let (_, stats) = kinds
+                .entry(std::mem::discriminant(expr))
+                .or_insert((expr, KindStats::default()));
+            stats.count += 1;
+            stats.bytes += node_bytes + value_bytes;
+        }
+        let exprs = kinds
+            .into_values()
+            .map(|(sample, stats)| {
+                let name: String = format!("{sample:?}")
+                    .chars()
+                    .take_while(|c| c.is_alphanumeric())
+                    .collect();
+                (name, stats)
+            })
+            .collect();
+        ContextStats {
+            exprs,
+            strings: self.strings.len(),
+            string_bytes: self
+                .strings
+                .iter()
+                .map(|s| s.len() + std::mem::size_of::<String>())
+                .sum(),
+            array_values: self.arrays.len(),
+        }
+    }
+}
+
+/// Hashes the contents of an array, independent of whether it is stored densely or sparsely.
+pub(crate) fn hash_array(value: &ArrayValue) -> u64 {
+    let hash_words = |values: &[&BitVecValue]| {
+        let mut hasher = FxHasher::default();
+        for v in values {
+            v.width().hash(&mut hasher);
+            v.words().hash(&mut hasher);
+        }
+        hasher.finish()
+    };
+    let sparse: SparseArrayValue = value.into();
+    // entries are combined with an addition since their order is not defined
+    sparse
+        .non_default_entries()
+        .fold(hash_words(&[&sparse.default()]), |acc, (index, data)| {
+            acc.wrapping_add(hash_words(&[&index, &data]))
+        })
+}
+
+/// Number of nodes and memory usage of all expressions in a [`Context`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContextStats {
+    /// statistics for every kind of expression, e.g., `BVAdd`
+    pub exprs: BTreeMap<String, KindStats>,
+    pub strings: usize,
+    pub string_bytes: usize,
+    /// number of distinct array literal values
+    pub array_values: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KindStats {
+    pub count: usize,
+    /// approximate size of the nodes and of the values of literals
+    pub bytes: usize,
+}
+
+impl ContextStats {
+    pub fn total_bytes(&self) -> usize {
+        self.exprs.values().map(|k| k.bytes).sum::<usize>() + self.string_bytes
+    }
+}
+
+impl Display for ContextStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{:<22}{:>10}{:>12}", "kind", "count", "bytes")?;
+        for (kind, stats) in self.exprs.iter() {
+            writeln!(f, "{kind:<22}{:>10}{:>12}", stats.count, stats.bytes)?;
+        }
+        writeln!(
+            f,
+            "{:<22}{:>10}{:>12}",
+            "strings", self.strings, self.string_bytes
+        )?;
+        writeln!(f, "{:<22}{:>22}", "total", self.total_bytes())
+    }
 }
 
 impl Index<ExprRef> for Context {
@@ -190,6 +324,50 @@ impl Context {
             }
         }
     }
+    /// A single node that contains all of `value`. In contrast to [`Context::lit`], this does
+    /// not create one store per word. Equal arrays map to the same expression.
+    pub fn array_lit(&mut self, value: &ArrayValue) -> ExprRef {
+        let candidates = self.array_pool.entry(hash_array(value)).or_default();
+        let existing = candidates
+            .iter()
+            .copied()
+            .find(|&ii| self.arrays[ii].is_equal(value) == Some(true));
+        let index = match existing {
+            Some(index) => index,
+            None => {
+                self.arrays.push(value.clone());
+                candidates.push(self.arrays.len() - 1);
+                self.arrays.len() - 1
+            }
+        };
+        self.add_expr(Expr::ArrayLiteral {
+            value: ArrayLitValue::new(index),
+            index_width: value.index_width(),
+            data_width: value.data_width(),
+        })
+    }
+
+    /// Array literal backed by a little-endian byte buffer, e.g., a ROM image. Every word
+    /// occupies `data_width` rounded up to full bytes. Words past the end of `bytes` are zero.
+    pub fn array_lit_from_bytes(&mut self, tpe: ArrayType, bytes: &[u8]) -> ExprRef {
+        let word_bytes = tpe.data_width.div_ceil(8) as usize;
+        let mut value = ArrayValue::new_sparse(tpe.index_width, &BitVecValue::zero(tpe.data_width));
+        for (ii, word) in bytes.chunks(word_bytes).enumerate() {
+            if word.iter().all(|&b| b == 0) {
+                continue;
+            }
+            let data = word
+                .iter()
+                .rev()
+                .map(|&b| BitVecValue::from_u64(b as u64, 8))
+                .reduce(|hi, lo| hi.concat(&lo))
+                .unwrap();
+            let data = data.slice(tpe.data_width - 1, 0);
+            let index = BitVecValue::from_u64(ii as u64, tpe.index_width);
+            value.store(&index, &data);
+        }
+        self.array_lit(&value)
+    }
     pub fn bv_lit<'a>(&mut self, value: impl Into<BitVecValueRef<'a>>) -> ExprRef {
         let index = self.values.get_index(value);
         self.add_expr(Expr::BVLiteral(BVLitValue::new(index)))
@@ -403,6 +581,71 @@ impl Context {
         })
     }
 
+    /// Declares an uninterpreted function that takes `num_args` arguments and returns a bit-vector.
+    pub fn function(&mut self, name: &str, num_args: u32, width: WidthInt) -> ExprRef {
+        assert!(
+            num_args > 0,
+            "functions need at least one argument, use a symbol instead"
+        );
+        assert!(width > 0, "0-bit bitvectors are not allowed");
+        let name_ref = self.string(name.into());
+        self.add_expr(Expr::BVFunction {
+            name: name_ref,
+            num_args,
+            width,
+        })
+    }
+
+    /// Applies an uninterpreted function to all of its arguments.
+    pub fn apply(&mut self, func: ExprRef, args: &[ExprRef]) -> ExprRef {
+        let Expr::BVFunction {
+            num_args, width, ..
+        } = self[func]
+        else {
+            panic!("only uninterpreted functions can be applied");
+        };
+        assert_eq!(args.len(), num_args as usize, "wrong number of arguments");
+        args.iter().fold(func, |func, &arg| {
+            self.add_expr(Expr::BVApply { func, arg, width })
+        })
+    }
+
+    /// Returns the function and all arguments if `e` is the application of an uninterpreted
+    /// function. Partial applications are included.
+    pub fn get_application(&self, e: ExprRef) -> Option<(ExprRef, Vec<ExprRef>)> {
+        match self[e] {
+            Expr::BVApply { func, arg, .. } => self.get_application_args(func, arg),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn get_application_args(
+        &self,
+        func: ExprRef,
+        last_arg: ExprRef,
+    ) -> Option<(ExprRef, Vec<ExprRef>)> {
+        let mut args = vec![last_arg];
+        let mut f = func;
+        while let Expr::BVApply { func, arg, .. } = self[f] {
+            args.push(arg);
+            f = func;
+        }
+        args.reverse();
+        matches!(self[f], Expr::BVFunction { .. }).then_some((f, args))
+    }
+
+    /// Returns true iff `e` applies an uninterpreted function to fewer arguments than it expects.
+    /// Partial applications only occur inside of complete ones.
+    pub fn is_partial_application(&self, e: ExprRef) -> bool {
+        match self.get_application(e) {
+            Some((func, args)) => match self[func] {
+                Expr::BVFunction { num_args, .. } => args.len() < num_args as usize,
+                _ => unreachable!(),
+            },
+            None => false,
+        }
+    }
+
     pub fn build(&mut self, foo: impl FnOnce(Builder) -> ExprRef) -> ExprRef {
         let builder = Builder::new(self);
         foo(builder)
@@ -506,9 +749,71 @@ impl<'a> Builder<'a> {
     pub fn shift_right(&self, a: ExprRef, b: ExprRef) -> ExprRef {
         self.ctx.borrow_mut().shift_right(a, b)
     }
+    pub fn rotate_left(&self, e: ExprRef, amount: ExprRef) -> ExprRef {
+        self.ctx.borrow_mut().rotate_left(e, amount)
+    }
+    pub fn rotate_right(&self, e: ExprRef, amount: ExprRef) -> ExprRef {
+        self.ctx.borrow_mut().rotate_right(e, amount)
+    }
+    pub fn rotate_left_by(&self, e: ExprRef, amount: u64) -> ExprRef {
+        self.ctx.borrow_mut().rotate_left_by(e, amount)
+    }
+    pub fn rotate_right_by(&self, e: ExprRef, amount: u64) -> ExprRef {
+        self.ctx.borrow_mut().rotate_right_by(e, amount)
+    }
     pub fn add(&self, a: ExprRef, b: ExprRef) -> ExprRef {
         self.ctx.borrow_mut().add(a, b)
     }
+    pub fn saturating_add(&self, a: ExprRef, b: ExprRef, signed: bool) -> ExprRef {
+        self.ctx.borrow_mut().saturating_add(a, b, signed)
+    }
+    pub fn saturating_sub(&self, a: ExprRef, b: ExprRef, signed: bool) -> ExprRef {
+        self.ctx.borrow_mut().saturating_sub(a, b, signed)
+    }
+    pub fn saturate(&self, e: ExprRef, width: WidthInt, signed: bool) -> ExprRef {
+        self.ctx.borrow_mut().saturate(e, width, signed)
+    }
+    pub fn round(&self, e: ExprRef, frac_bits: WidthInt, mode: Rounding) -> ExprRef {
+        self.ctx.borrow_mut().round(e, frac_bits, mode)
+    }
+    pub fn fixed_add(&self, a: ExprRef, b: ExprRef) -> ExprRef {
+        self.ctx.borrow_mut().fixed_add(a, b)
+    }
+    pub fn fixed_sub(&self, a: ExprRef, b: ExprRef) -> ExprRef {
+        self.ctx.borrow_mut().fixed_sub(a, b)
+    }
+    pub fn fixed_mul(&self, a: ExprRef, b: ExprRef) -> ExprRef {
+        self.ctx.borrow_mut().fixed_mul(a, b)
+    }
+    pub fn fixed_convert(
+        &self,
+        e: ExprRef,
+        to: QFormat,
+        rounding: Rounding,
+        overflow: Overflow,
+    ) -> ExprRef {
+        self.ctx
+            .borrow_mut()
+            .fixed_convert(e, to, rounding, overflow)
+    }
+    pub fn fp_add(&self, a: ExprRef, b: ExprRef, format: FloatFormat) -> ExprRef {
+        self.ctx.borrow_mut().fp_add(a, b, format)
+    }
+    pub fn fp_sub(&self, a: ExprRef, b: ExprRef, format: FloatFormat) -> ExprRef {
+        self.ctx.borrow_mut().fp_sub(a, b, format)
+    }
+    pub fn fp_mul(&self, a: ExprRef, b: ExprRef, format: FloatFormat) -> ExprRef {
+        self.ctx.borrow_mut().fp_mul(a, b, format)
+    }
+    pub fn fp_neg(&self, a: ExprRef, format: FloatFormat) -> ExprRef {
+        self.ctx.borrow_mut().fp_neg(a, format)
+    }
+    pub fn fp_eq(&self, a: ExprRef, b: ExprRef, format: FloatFormat) -> ExprRef {
+        self.ctx.borrow_mut().fp_eq(a, b, format)
+    }
+    pub fn fp_lt(&self, a: ExprRef, b: ExprRef, format: FloatFormat) -> ExprRef {
+        self.ctx.borrow_mut().fp_lt(a, b, format)
+    }
     pub fn sub(&self, a: ExprRef, b: ExprRef) -> ExprRef {
         self.ctx.borrow_mut().sub(a, b)
     }
@@ -559,12 +864,20 @@ impl<'a> Builder<'a> {
     pub fn array_read(&self, array: ExprRef, index: ExprRef) -> ExprRef {
         self.ctx.borrow_mut().array_read(array, index)
     }
+
+    pub fn function(&self, name: &str, num_args: u32, width: WidthInt) -> ExprRef {
+        self.ctx.borrow_mut().function(name, num_args, width)
+    }
+
+    pub fn apply(&self, func: ExprRef, args: &[ExprRef]) -> ExprRef {
+        self.ctx.borrow_mut().apply(func, args)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::expr::SerializableIrNode;
+    use crate::expr::{SerializableIrNode, Type};
 
     #[test]
     fn ir_type_size() {
@@ -621,6 +934,96 @@ mod tests {
         assert_eq!(expr.serialize_to_str(&ctx), "and(a, b)");
     }
 
+    #[test]
+    fn test_array_lit() {
+        let mut ctx = Context::default();
+        let tpe = ArrayType {
+            index_width: 4,
+            data_width: 12,
+        };
+        // two bytes per word
+        let rom = ctx.array_lit_from_bytes(tpe, &[0x34, 0x12, 0, 0, 0xff, 0xff]);
+        assert_eq!(rom.type_check(&ctx).unwrap(), Type::Array(tpe));
+        let Expr::ArrayLiteral { value, .. } = ctx[rom] else {
+            unreachable!()
+        };
+        let read = |ii: u64| {
+            value
+                .get(&ctx)
+                .select(&BitVecValue::from_u64(ii, 4))
+                .to_u64()
+        };
+        assert_eq!(read(0), Some(0x234));
+        assert_eq!(read(1), Some(0));
+        assert_eq!(read(2), Some(0xfff));
+        assert_eq!(read(15), Some(0));
+        assert_eq!(
+            rom.serialize_to_str(&ctx),
+            "array(default=12'x000, 4'b0000=12'x234, 4'b0010=12'xfff)"
+        );
+        // equal contents are interned
+        let copy = value.get(&ctx).clone();
+        assert_eq!(ctx.array_lit(&copy), rom);
+    }
+
+    #[test]
+    fn test_constant_pooling_and_stats() {
+        let mut ctx = Context::default();
+        let tpe = ArrayType {
+            index_width: 10,
+            data_width: 32,
+        };
+        let bytes: Vec<u8> = (0..4096u32).map(|ii| (ii * 7) as u8).collect();
+        let table = ctx.array_lit_from_bytes(tpe, &bytes);
+        // the same table loaded twice is only stored once
+        assert_eq!(ctx.array_lit_from_bytes(tpe, &bytes), table);
+        let other = ctx.array_lit_from_bytes(tpe, &bytes[4..]);
+        assert_ne!(other, table);
+
+        let wide = BitVecValue::ones(1000);
+        let a = ctx.bv_lit(&wide);
+        assert_eq!(ctx.bv_lit(&wide), a);
+        let b = ctx.bv_symbol("b", 1000);
+        let _sum = ctx.add(a, b);
+
+        let stats = ctx.stats();
+        assert_eq!(stats.array_values, 2);
+        assert_eq!(stats.exprs["ArrayLiteral"].count, 2);
+        assert_eq!(stats.exprs["BVAdd"].count, 1);
+        assert_eq!(stats.exprs["BVSymbol"].count, 1);
+        // true, false and the wide constant
+        assert_eq!(stats.exprs["BVLiteral"].count, 3);
+        assert!(stats.exprs["BVLiteral"].bytes > 1000 / 8);
+        assert!(stats.exprs["ArrayLiteral"].bytes > 2 * 1024 * 4);
+        assert!(stats.total_bytes() > stats.exprs["ArrayLiteral"].bytes);
+        assert!(stats.to_string().contains("BVAdd"));
+    }
+
+    #[test]
+    fn test_uninterpreted_function() {
+        let mut ctx = Context::default();
+        let f = ctx.function("f", 2, 8);
+        let a = ctx.bv_symbol("a", 8);
+        let b = ctx.bv_symbol("b", 4);
+        let app = ctx.apply(f, &[a, b]);
+        assert_eq!(app.type_check(&ctx).unwrap(), Type::BV(8));
+        assert_eq!(app.serialize_to_str(&ctx), "f(a, b)");
+        assert_eq!(ctx.get_application(app), Some((f, vec![a, b])));
+        assert!(!ctx.is_partial_application(app));
+        let Expr::BVApply { func, .. } = ctx[app] else {
+            unreachable!()
+        };
+        assert!(ctx.is_partial_application(func));
+
+        // applying to too many arguments is a type error
+        let too_many = ctx.add_expr(Expr::BVApply {
+            func: app,
+            arg: a,
+            width: 8,
+        });
+        assert!(too_many.type_check(&ctx).is_err());
+    }
+
     #[test]
     fn test_bit_vec_val() {
         let mut ctx = Context::default();
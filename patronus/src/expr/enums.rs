@@ -0,0 +1,158 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Enumerated Types
+//! State registers of controllers often hold one of a few named values. An [`EnumType`] can be
+//! attached to a bit-vector symbol as part of its [`Attributes`](crate::expr::Attributes).
+//! The system serializer and FSM extraction then show the names of values instead of their
+//! raw bit patterns and [`Context::format_value`] does the same for traces.
+
+use super::{Context, ExprRef, TypeCheck, WidthInt};
+use baa::{BitVecMutOps, BitVecOps, BitVecValue};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnumEncoding {
+    /// variants are numbered consecutively starting at zero
+    Binary,
+    /// exactly one bit is set per variant
+    OneHot,
+    /// user defined values
+    Custom,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnumType {
+    pub name: String,
+    pub width: WidthInt,
+    pub encoding: EnumEncoding,
+    pub variants: Vec<(String, BitVecValue)>,
+}
+
+impl EnumType {
+    pub fn binary(name: &str, variants: &[&str]) -> Self {
+        let width = (usize::BITS - variants.len().saturating_sub(1).leading_zeros()).max(1);
+        let variants = variants
+            .iter()
+            .enumerate()
+            .map(|(ii, v)| (v.to_string(), BitVecValue::from_u64(ii as u64, width)))
+            .collect();
+        Self {
+            name: name.to_string(),
+            width,
+            encoding: EnumEncoding::Binary,
+            variants,
+        }
+    }
+
+    pub fn one_hot(name: &str, variants: &[&str]) -> Self {
+        let width = variants.len() as WidthInt;
+        let variants = variants
+            .iter()
+            .enumerate()
+            .map(|(ii, v)| {
+                let mut value = BitVecValue::zero(width);
+                value.set_bit(ii as WidthInt);
+                (v.to_string(), value)
+            })
+            .collect();
+        Self {
+            name: name.to_string(),
+            width,
+            encoding: EnumEncoding::OneHot,
+            variants,
+        }
+    }
+
+    pub fn custom(name: &str, width: WidthInt, variants: &[(&str, u64)]) -> Self {
+        let variants = variants
+            .iter()
+            .map(|(v, value)| (v.to_string(), BitVecValue::from_u64(*value, width)))
+            .collect();
+        Self {
+            name: name.to_string(),
+            width,
+            encoding: EnumEncoding::Custom,
+            variants,
+        }
+    }
+
+    /// Name of the variant encoded by `value`, if there is one.
+    pub fn variant_name(&self, value: &impl BitVecOps) -> Option<&str> {
+        self.variants
+            .iter()
+            .find(|(_, v)| v.width() == value.width() && v.is_equal(value))
+            .map(|(name, _)| name.as_str())
+    }
+
+    pub fn value_of(&self, variant: &str) -> Option<&BitVecValue> {
+        self.variants
+            .iter()
+            .find(|(name, _)| name == variant)
+            .map(|(_, value)| value)
+    }
+}
+
+impl Context {
+    /// Declares that `symbol` holds values of the enum `tpe`.
+    pub fn set_enum_type(&mut self, symbol: ExprRef, tpe: EnumType) {
+        assert!(
+            self[symbol].is_symbol(),
+            "only symbols can have an enum type"
+        );
+        assert_eq!(
+            symbol.get_bv_type(self),
+            Some(tpe.width),
+            "enum {} does not match the width of its symbol",
+            tpe.name
+        );
+        self.attributes.entry(symbol).or_default().enum_type = Some(tpe);
+    }
+
+    pub fn enum_type(&self, e: ExprRef) -> Option<&EnumType> {
+        self.attributes(e)?.enum_type.as_ref()
+    }
+
+    /// Formats a value of `e` with its variant name if `e` has an enum type and as hex otherwise.
+    pub fn format_value(&self, e: ExprRef, value: &impl BitVecOps) -> String {
+        match self.enum_type(e).and_then(|t| t.variant_name(value)) {
+            Some(name) => name.to_string(),
+            None => format!("{}'x{}", value.width(), value.to_hex_str()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_enum_encodings() {
+        let binary = EnumType::binary("State", &["Idle", "Busy", "Done"]);
+        assert_eq!(binary.width, 2);
+        assert_eq!(binary.value_of("Done").unwrap().to_u64(), Some(2));
+        let one_hot = EnumType::one_hot("State", &["Idle", "Busy", "Done"]);
+        assert_eq!(one_hot.width, 3);
+        assert_eq!(one_hot.value_of("Done").unwrap().to_u64(), Some(4));
+        assert_eq!(EnumType::binary("Single", &["Only"]).width, 1);
+        let custom = EnumType::custom("Op", 4, &[("Load", 3), ("Store", 9)]);
+        assert_eq!(
+            custom.variant_name(&BitVecValue::from_u64(9, 4)),
+            Some("Store")
+        );
+        assert_eq!(custom.variant_name(&BitVecValue::from_u64(1, 4)), None);
+    }
+
+    #[test]
+    fn test_format_value() {
+        let mut ctx = Context::default();
+        let state = ctx.bv_symbol("state", 2);
+        let other = ctx.bv_symbol("other", 2);
+        ctx.set_enum_type(state, EnumType::binary("State", &["Idle", "Busy", "Done"]));
+        let value = BitVecValue::from_u64(1, 2);
+        assert_eq!(ctx.format_value(state, &value), "Busy");
+        assert_eq!(ctx.format_value(other, &value), "2'x1");
+        let invalid = BitVecValue::from_u64(3, 2);
+        assert_eq!(ctx.format_value(state, &invalid), "2'x3");
+    }
+}
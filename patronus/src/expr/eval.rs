@@ -2,12 +2,16 @@
 // released under BSD 3-Clause License
 // author: Kevin Laeufer <laeufer@cornell.edu>
 
-use crate::expr::{Context, Expr, ExprRef, ForEachChild, TypeCheck};
+use crate::expr::traversal::{top_down, TraversalCmd};
+use crate::expr::{
+    ArrayType, Context, Expr, ExprError, ExprRef, ForEachChild, SerializableIrNode, Type,
+    TypeCheck, WidthInt,
+};
 use baa::{
     ArrayMutOps, ArrayOps, ArrayValue, BitVecMutOps, BitVecOps, BitVecValue, BitVecValueIndex,
-    BitVecValueRef, IndexToMutRef, IndexToRef, Value, Word,
+    BitVecValueRef, IndexToMutRef, IndexToRef, SparseArrayValue, Value, Word,
 };
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
 use smallvec::SmallVec;
 use std::collections::HashMap;
 
@@ -61,6 +65,10 @@ impl SymbolValueStore {
         }
     }
 
+    pub fn is_defined(&self, symbol: ExprRef) -> bool {
+        self.lookup.contains_key(&symbol)
+    }
+
     pub fn clear(&mut self) {
         self.arrays.clear();
         self.bit_vec_words.clear();
@@ -68,6 +76,227 @@ impl SymbolValueStore {
     }
 }
 
+/// Values that changed between two stores that define the same symbols in the same order.
+/// Created by [`SymbolValueStore::diff`].
+#[derive(Default, Clone)]
+pub struct SymbolValueDelta {
+    bit_vec_words: Vec<(SymbolValueStoreIndex, Word)>,
+    arrays: Vec<(SymbolValueStoreIndex, ArrayValue)>,
+}
+
+impl SymbolValueDelta {
+    pub fn is_empty(&self) -> bool {
+        self.bit_vec_words.is_empty() && self.arrays.is_empty()
+    }
+}
+
+impl SymbolValueStore {
+    /// Changes that turn `base` into `self`. Returns `None` if the two stores do not have the
+    /// same layout, i.e., were not defined with the same symbols in the same order.
+    pub fn diff(&self, base: &Self) -> Option<SymbolValueDelta> {
+        if self.lookup != base.lookup
+            || self.bit_vec_words.len() != base.bit_vec_words.len()
+            || self.arrays.len() != base.arrays.len()
+        {
+            return None;
+        }
+        let bit_vec_words = self
+            .bit_vec_words
+            .iter()
+            .zip(base.bit_vec_words.iter())
+            .enumerate()
+            .filter(|(_, (new, old))| new != old)
+            .map(|(ii, (new, _))| (ii as SymbolValueStoreIndex, *new))
+            .collect();
+        let arrays = self
+            .arrays
+            .iter()
+            .zip(base.arrays.iter())
+            .enumerate()
+            .filter(|(_, (new, old))| !arrays_equal(new, old))
+            .map(|(ii, (new, _))| (ii as SymbolValueStoreIndex, new.clone()))
+            .collect();
+        Some(SymbolValueDelta {
+            bit_vec_words,
+            arrays,
+        })
+    }
+
+    /// Applies changes that were computed with [`SymbolValueStore::diff`] against a store with
+    /// the same layout.
+    pub fn apply(&mut self, delta: &SymbolValueDelta) {
+        for &(ii, word) in delta.bit_vec_words.iter() {
+            self.bit_vec_words[ii as usize] = word;
+        }
+        for (ii, value) in delta.arrays.iter() {
+            self.arrays[*ii as usize] = value.clone();
+        }
+    }
+}
+
+fn arrays_equal(a: &ArrayValue, b: &ArrayValue) -> bool {
+    if a.index_width() != b.index_width() || a.data_width() != b.data_width() {
+        return false;
+    }
+    let (sparse_a, sparse_b): (SparseArrayValue, SparseArrayValue) = (a.into(), b.into());
+    sparse_a.default().is_equal(&sparse_b.default())
+        && sparse_a
+            .non_default_entries()
+            .chain(sparse_b.non_default_entries())
+            .all(|(addr, _)| a.select(&addr).is_equal(&b.select(&addr)))
+}
+
+// Binary encoding that is used to spill simulator snapshots to disk. Bit-vector words are
+// stored as raw little endian integers, arrays by their default value and non-default entries.
+impl SymbolValueStore {
+    /// Approximate number of bytes needed to store all values.
+    pub(crate) fn size_in_bytes(&self) -> usize {
+        self.bit_vec_words.len() * std::mem::size_of::<Word>()
+            + self.lookup.len() * 2 * std::mem::size_of::<SymbolValueStoreIndex>()
+            + self.arrays.iter().map(array_size_in_bytes).sum::<usize>()
+    }
+
+    pub(crate) fn encode(&self, out: &mut Vec<u8>) {
+        put_u64(out, self.lookup.len() as u64);
+        for (symbol, index) in self.lookup.iter() {
+            put_u64(out, symbol.index() as u64);
+            put_u64(out, *index as u64);
+        }
+        put_u64(out, self.bit_vec_words.len() as u64);
+        for word in self.bit_vec_words.iter() {
+            out.extend_from_slice(&word.to_le_bytes());
+        }
+        put_u64(out, self.arrays.len() as u64);
+        for array in self.arrays.iter() {
+            encode_array(out, array);
+        }
+    }
+
+    /// Reverses [`SymbolValueStore::encode`] and advances `input` past the store.
+    pub(crate) fn decode(input: &mut &[u8]) -> Option<Self> {
+        let mut out = Self::default();
+        for _ in 0..take_u64(input)? {
+            let symbol = ExprRef::from_index(take_u64(input)? as usize);
+            let index = take_u64(input)? as SymbolValueStoreIndex;
+            out.lookup.insert(symbol, index);
+        }
+        for _ in 0..take_u64(input)? {
+            out.bit_vec_words.push(take_word(input)?);
+        }
+        for _ in 0..take_u64(input)? {
+            out.arrays.push(decode_array(input)?);
+        }
+        Some(out)
+    }
+}
+
+impl SymbolValueDelta {
+    /// Approximate number of bytes needed to store all changes.
+    pub(crate) fn size_in_bytes(&self) -> usize {
+        self.bit_vec_words.len()
+            * (std::mem::size_of::<Word>() + std::mem::size_of::<SymbolValueStoreIndex>())
+            + self
+                .arrays
+                .iter()
+                .map(|(_, a)| std::mem::size_of::<SymbolValueStoreIndex>() + array_size_in_bytes(a))
+                .sum::<usize>()
+    }
+
+    pub(crate) fn encode(&self, out: &mut Vec<u8>) {
+        put_u64(out, self.bit_vec_words.len() as u64);
+        for (index, word) in self.bit_vec_words.iter() {
+            put_u64(out, *index as u64);
+            out.extend_from_slice(&word.to_le_bytes());
+        }
+        put_u64(out, self.arrays.len() as u64);
+        for (index, array) in self.arrays.iter() {
+            put_u64(out, *index as u64);
+            encode_array(out, array);
+        }
+    }
+
+    /// Reverses [`SymbolValueDelta::encode`] and advances `input` past the delta.
+    pub(crate) fn decode(input: &mut &[u8]) -> Option<Self> {
+        let mut out = Self::default();
+        for _ in 0..take_u64(input)? {
+            let index = take_u64(input)? as SymbolValueStoreIndex;
+            out.bit_vec_words.push((index, take_word(input)?));
+        }
+        for _ in 0..take_u64(input)? {
+            let index = take_u64(input)? as SymbolValueStoreIndex;
+            out.arrays.push((index, decode_array(input)?));
+        }
+        Some(out)
+    }
+}
+
+fn array_size_in_bytes(array: &ArrayValue) -> usize {
+    let sparse: SparseArrayValue = array.into();
+    let entry = (array.index_width() + array.data_width()).div_ceil(Word::BITS) as usize
+        * std::mem::size_of::<Word>();
+    (sparse.non_default_entries().count() + 1) * entry
+}
+
+pub(crate) fn put_u64(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+pub(crate) fn take_u64(input: &mut &[u8]) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        take_bytes(input, 8)?.try_into().unwrap(),
+    ))
+}
+
+fn take_word(input: &mut &[u8]) -> Option<Word> {
+    let bytes = take_bytes(input, std::mem::size_of::<Word>())?;
+    Some(Word::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn take_bytes<'a>(input: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if input.len() < len {
+        return None;
+    }
+    let (bytes, rest) = input.split_at(len);
+    *input = rest;
+    Some(bytes)
+}
+
+pub(crate) fn put_bv(out: &mut Vec<u8>, value: &BitVecValue) {
+    let hex = value.to_hex_str();
+    put_u64(out, hex.len() as u64);
+    out.extend_from_slice(hex.as_bytes());
+}
+
+pub(crate) fn take_bv(input: &mut &[u8], width: WidthInt) -> Option<BitVecValue> {
+    let len = take_u64(input)? as usize;
+    let hex = take_bytes(input, len)?;
+    BitVecValue::from_str_radix(std::str::from_utf8(hex).ok()?, 16, width).ok()
+}
+
+fn encode_array(out: &mut Vec<u8>, array: &ArrayValue) {
+    let sparse: SparseArrayValue = array.into();
+    put_u64(out, array.index_width() as u64);
+    put_u64(out, array.data_width() as u64);
+    put_bv(out, &sparse.default());
+    let entries: Vec<_> = sparse.non_default_entries().collect();
+    put_u64(out, entries.len() as u64);
+    for (addr, data) in entries.iter() {
+        put_bv(out, addr);
+        put_bv(out, data);
+    }
+}
+
+fn decode_array(input: &mut &[u8]) -> Option<ArrayValue> {
+    let index_width = take_u64(input)? as WidthInt;
+    let data_width = take_u64(input)? as WidthInt;
+    let mut array = ArrayValue::new_sparse(index_width, &take_bv(input, data_width)?);
+    for _ in 0..take_u64(input)? {
+        let addr = take_bv(input, index_width)?;
+        array.store(&addr, &take_bv(input, data_width)?);
+    }
+    Some(array)
+}
+
 impl GetExprValue for SymbolValueStore {
     fn get_bv(&self, ctx: &Context, symbol: ExprRef) -> Option<BitVecValue> {
         let width = symbol.get_bv_type(ctx)?;
@@ -144,6 +373,77 @@ impl GetExprValue for [(ExprRef, ArrayValue)] {
     }
 }
 
+/// Maps symbols to values, either through their [`ExprRef`] or through their name.
+#[derive(Debug, Clone, Default)]
+pub struct Assignment {
+    by_expr: FxHashMap<ExprRef, Value>,
+    by_name: FxHashMap<String, Value>,
+}
+
+impl Assignment {
+    pub fn insert(&mut self, symbol: ExprRef, value: impl Into<Value>) {
+        self.by_expr.insert(symbol, value.into());
+    }
+
+    pub fn insert_by_name(&mut self, name: impl Into<String>, value: impl Into<Value>) {
+        self.by_name.insert(name.into(), value.into());
+    }
+
+    /// Values assigned by reference take precedence over values assigned by name.
+    pub fn get(&self, ctx: &Context, symbol: ExprRef) -> Option<&Value> {
+        self.by_expr.get(&symbol).or_else(|| {
+            ctx.get_symbol_name(symbol)
+                .and_then(|name| self.by_name.get(name))
+        })
+    }
+}
+
+/// Evaluates `expr` with all symbols taking the values from `assignment`.
+/// Unlike [`eval_expr`], a missing symbol results in an error instead of a panic.
+pub fn eval(ctx: &Context, expr: ExprRef, assignment: &Assignment) -> Result<Value, ExprError> {
+    let mut symbols = vec![];
+    top_down(ctx, expr, |ctx, e| {
+        if ctx[e].is_symbol() {
+            symbols.push(e);
+        }
+        TraversalCmd::Continue
+    });
+    symbols.sort();
+    symbols.dedup();
+
+    let mut store = SymbolValueStore::default();
+    for symbol in symbols.into_iter() {
+        let name = || ctx.get_symbol_name(symbol).unwrap().to_string();
+        let value = assignment
+            .get(ctx, symbol)
+            .ok_or_else(|| ExprError::MissingSymbol(name()))?;
+        let expected = symbol.get_type(ctx);
+        let actual = value_type(value);
+        if expected != actual {
+            return Err(ExprError::TypeMismatch {
+                name: name(),
+                expected,
+                actual,
+            });
+        }
+        match value {
+            Value::BitVec(value) => store.define_bv(symbol, value),
+            Value::Array(value) => store.define_array(symbol, value.clone()),
+        }
+    }
+    try_eval_expr(ctx, &store, expr)
+}
+
+fn value_type(value: &Value) -> Type {
+    match value {
+        Value::BitVec(v) => Type::BV(v.width()),
+        Value::Array(v) => Type::Array(ArrayType {
+            index_width: v.index_width(),
+            data_width: v.data_width(),
+        }),
+    }
+}
+
 type BitVecStack = SmallVec<[BitVecValue; 4]>;
 type ArrayStack = SmallVec<[ArrayValue; 2]>;
 
@@ -162,6 +462,7 @@ fn bin_op(stack: &mut BitVecStack, op: impl Fn(BitVecValue, BitVecValue) -> BitV
     stack.push(res);
 }
 
+/// Panics if `expr` cannot be evaluated, see [`try_eval_expr`].
 pub fn eval_bv_expr(
     ctx: &Context,
     symbols: &(impl GetExprValue + ?Sized),
@@ -172,12 +473,14 @@ pub fn eval_bv_expr(
         "Not a bit-vector expression: {:?}",
         ctx[expr]
     );
-    let (mut bv_stack, array_stack) = eval_expr_internal(ctx, symbols, expr);
+    let (mut bv_stack, array_stack) =
+        eval_expr_internal(ctx, symbols, expr, &mut |_| {}).unwrap_or_else(|e| panic!("{e}"));
     debug_assert!(array_stack.is_empty());
     debug_assert_eq!(bv_stack.len(), 1);
     bv_stack.pop().unwrap()
 }
 
+/// Panics if `expr` cannot be evaluated, see [`try_eval_expr`].
 pub fn eval_array_expr(
     ctx: &Context,
     symbols: &(impl GetExprValue + ?Sized),
@@ -188,30 +491,92 @@ pub fn eval_array_expr(
         "Not an array expression: {:?}",
         ctx[expr]
     );
-    let (bv_stack, mut array_stack) = eval_expr_internal(ctx, symbols, expr);
+    let (bv_stack, mut array_stack) =
+        eval_expr_internal(ctx, symbols, expr, &mut |_| {}).unwrap_or_else(|e| panic!("{e}"));
     debug_assert!(bv_stack.is_empty());
     debug_assert_eq!(array_stack.len(), 1);
     array_stack.pop().unwrap()
 }
 
+/// Panics if `expr` cannot be evaluated, see [`try_eval_expr`].
 pub fn eval_expr(ctx: &Context, symbols: &(impl GetExprValue + ?Sized), expr: ExprRef) -> Value {
-    let (mut bv_stack, mut array_stack) = eval_expr_internal(ctx, symbols, expr);
+    try_eval_expr(ctx, symbols, expr).unwrap_or_else(|e| panic!("{e}"))
+}
+
+/// Evaluates `expr`. Fails if a symbol has no value or if `expr` contains an operation that
+/// cannot be evaluated, like a division.
+pub fn try_eval_expr(
+    ctx: &Context,
+    symbols: &(impl GetExprValue + ?Sized),
+    expr: ExprRef,
+) -> Result<Value, ExprError> {
+    try_eval_expr_observed(ctx, symbols, expr, |_| {})
+}
+
+/// Like [`eval_expr`], but calls `on_eval` for every expression node that is computed,
+/// i.e., that does not already have a value in `symbols`.
+pub(crate) fn eval_expr_observed(
+    ctx: &Context,
+    symbols: &(impl GetExprValue + ?Sized),
+    expr: ExprRef,
+    on_eval: impl FnMut(ExprRef),
+) -> Value {
+    try_eval_expr_observed(ctx, symbols, expr, on_eval).unwrap_or_else(|e| panic!("{e}"))
+}
+
+fn try_eval_expr_observed(
+    ctx: &Context,
+    symbols: &(impl GetExprValue + ?Sized),
+    expr: ExprRef,
+    mut on_eval: impl FnMut(ExprRef),
+) -> Result<Value, ExprError> {
+    let (mut bv_stack, mut array_stack) = eval_expr_internal(ctx, symbols, expr, &mut on_eval)?;
     debug_assert_eq!(bv_stack.len() + array_stack.len(), 1);
     if let Some(value) = bv_stack.pop() {
         debug_assert!(ctx[expr].is_bv_type());
-        Value::BitVec(value)
+        Ok(Value::BitVec(value))
     } else {
         let value = array_stack.pop().unwrap();
         debug_assert!(ctx[expr].is_array_type());
-        Value::Array(value)
+        Ok(Value::Array(value))
+    }
+}
+
+/// Returns an error for the first expression reachable from `exprs` that can never be evaluated,
+/// i.e., an uninterpreted function or a division.
+pub fn check_evaluable(
+    ctx: &Context,
+    exprs: impl IntoIterator<Item = ExprRef>,
+) -> Result<(), ExprError> {
+    let mut visited = FxHashSet::default();
+    let mut todo: Vec<ExprRef> = exprs.into_iter().collect();
+    while let Some(e) = todo.pop() {
+        if !visited.insert(e) {
+            continue;
+        }
+        match &ctx[e] {
+            Expr::BVFunction { name, .. } => {
+                return Err(ExprError::UninterpretedFunction(ctx[*name].to_string()));
+            }
+            Expr::BVSignedDiv(_, _, _)
+            | Expr::BVUnsignedDiv(_, _, _)
+            | Expr::BVSignedMod(_, _, _)
+            | Expr::BVSignedRem(_, _, _)
+            | Expr::BVUnsignedRem(_, _, _) => {
+                return Err(ExprError::Unsupported(e.serialize_to_str(ctx)));
+            }
+            other => other.for_each_child(|c| todo.push(*c)),
+        }
     }
+    Ok(())
 }
 
 fn eval_expr_internal(
     ctx: &Context,
     values: &(impl GetExprValue + ?Sized),
     expr: ExprRef,
-) -> (BitVecStack, ArrayStack) {
+    on_eval: &mut impl FnMut(ExprRef),
+) -> Result<(BitVecStack, ArrayStack), ExprError> {
     let mut bv_stack: BitVecStack = SmallVec::with_capacity(4);
     let mut array_stack: ArrayStack = SmallVec::with_capacity(2);
     let mut todo: SmallVec<[(ExprRef, bool); 4]> = SmallVec::with_capacity(4);
@@ -253,12 +618,11 @@ fn eval_expr_internal(
         }
 
         // Otherwise, all arguments are available on the stack for us to use.
+        on_eval(e);
         match expr {
             // nullary
-            Expr::BVSymbol { name, width } => {
-                // we should not get here
-                // TODO: turn into return Err
-                panic!("No value found for symbol: {} : bv<{width}>", ctx[*name]);
+            Expr::BVSymbol { name, .. } => {
+                return Err(ExprError::MissingSymbol(ctx[*name].to_string()));
             }
             Expr::BVLiteral(value) => bv_stack.push(value.get(ctx).into()),
             // unary
@@ -292,13 +656,13 @@ fn eval_expr_internal(
             Expr::BVShiftRight(_, _, _) => bin_op(&mut bv_stack, |a, b| a.shift_right(&b)),
             Expr::BVAdd(_, _, _) => bin_op(&mut bv_stack, |a, b| a.add(&b)),
             Expr::BVMul(_, _, _) => bin_op(&mut bv_stack, |a, b| a.mul(&b)),
-            // div, rem and mod are still TODO
+            // div, rem and mod are not supported yet
             Expr::BVSignedDiv(_, _, _)
             | Expr::BVUnsignedDiv(_, _, _)
             | Expr::BVSignedMod(_, _, _)
             | Expr::BVSignedRem(_, _, _)
             | Expr::BVUnsignedRem(_, _, _) => {
-                todo!("implement eval support for {:?}", ctx[e])
+                return Err(ExprError::Unsupported(e.serialize_to_str(ctx)));
             }
             Expr::BVSub(_, _, _) => bin_op(&mut bv_stack, |a, b| a.sub(&b)),
             // BVArrayRead needs array support!
@@ -322,18 +686,10 @@ fn eval_expr_internal(
                     .unwrap_or_else(|| panic!("index argument is missing"));
                 bv_stack.push(array.select(&index));
             }
-            Expr::ArraySymbol {
-                name,
-                index_width,
-                data_width,
-            } => {
-                // we should not get here
-                // TODO: turn into return Err
-                panic!(
-                    "No value found for symbol: {} : bv<{index_width}> -> bv<{data_width}>",
-                    ctx[*name]
-                );
+            Expr::ArraySymbol { name, .. } => {
+                return Err(ExprError::MissingSymbol(ctx[*name].to_string()));
             }
+            Expr::ArrayLiteral { value, .. } => array_stack.push(value.get(ctx).clone()),
             Expr::ArrayConstant { index_width, .. } => {
                 let default = bv_stack
                     .pop()
@@ -371,16 +727,25 @@ fn eval_expr_internal(
                     array_stack.pop().unwrap(); // just discard tru
                 }
             }
+            // uninterpreted functions can only be evaluated if a value for the application is provided
+            Expr::BVFunction { name, .. } => {
+                return Err(ExprError::UninterpretedFunction(ctx[*name].to_string()));
+            }
+            Expr::BVApply { .. } => {
+                unreachable!("evaluating the function argument would already have failed")
+            }
         }
     }
 
     debug_assert_eq!(bv_stack.len() + array_stack.len(), 1);
-    (bv_stack, array_stack)
+    Ok((bv_stack, array_stack))
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{eval_array_expr, eval_bv_expr, SymbolValueStore};
+    use super::{
+        check_evaluable, eval, eval_array_expr, eval_bv_expr, Assignment, SymbolValueStore,
+    };
     use crate::expr::*;
     use baa::*;
 
@@ -470,4 +835,45 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_eval_with_assignment() {
+        let mut c = Context::default();
+        let a = c.bv_symbol("a", 8);
+        let b = c.bv_symbol("b", 8);
+        let expr = c.add(a, b);
+
+        let mut assignment = Assignment::default();
+        assignment.insert(a, BitVecValue::from_u64(3, 8));
+        assert!(matches!(
+            eval(&c, expr, &assignment),
+            Err(ExprError::MissingSymbol(name)) if name == "b"
+        ));
+
+        assignment.insert_by_name("b", BitVecValue::from_u64(4, 8));
+        let res = eval(&c, expr, &assignment).unwrap();
+        assert_eq!(res.try_into_u64().unwrap(), 7);
+
+        assignment.insert_by_name("b", BitVecValue::from_u64(4, 9));
+        assert!(matches!(
+            eval(&c, expr, &assignment),
+            Err(ExprError::TypeMismatch { .. })
+        ));
+
+        let quotient = c.div(a, b);
+        assignment.insert_by_name("b", BitVecValue::from_u64(4, 8));
+        assert!(matches!(
+            eval(&c, quotient, &assignment),
+            Err(ExprError::Unsupported(_))
+        ));
+
+        let f = c.function("f", 1, 8);
+        let app = c.apply(f, &[a]);
+        assert!(matches!(
+            eval(&c, app, &assignment),
+            Err(ExprError::UninterpretedFunction(name)) if name == "f"
+        ));
+        assert!(check_evaluable(&c, [expr]).is_ok());
+        assert!(check_evaluable(&c, [expr, app]).is_err());
+    }
 }
@@ -0,0 +1,336 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Fixed-Point Arithmetic
+//! DSP datapaths are usually specified in terms of fixed-point numbers. A [`QFormat`] describes
+//! how the bits of a bit-vector are split into integer and fractional bits. It is attached to
+//! an expression as part of its [`Attributes`](crate::expr::Attributes), just like an enum type.
+//! The fixed-point operations read the formats of their operands, align them and compute a
+//! result that is exact unless an explicit conversion drops bits. Everything lowers to
+//! ordinary bit-vector expressions, thus a fixed-point spec can be checked against RTL with
+//! any of our equivalence checking or model checking backends.
+//!
+//! Since expressions are hash-consed, two identical computations share their format. The
+//! format that was set last wins.
+
+use super::{Context, ExprRef, Rounding, SerializableIrNode, TypeCheck, WidthInt};
+use baa::BitVecValue;
+use std::cmp::max;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// `int_bits` include the sign bit of signed numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct QFormat {
+    pub signed: bool,
+    pub int_bits: WidthInt,
+    pub frac_bits: WidthInt,
+}
+
+impl QFormat {
+    pub fn signed(int_bits: WidthInt, frac_bits: WidthInt) -> Self {
+        Self {
+            signed: true,
+            int_bits,
+            frac_bits,
+        }
+    }
+
+    pub fn unsigned(int_bits: WidthInt, frac_bits: WidthInt) -> Self {
+        Self {
+            signed: false,
+            int_bits,
+            frac_bits,
+        }
+    }
+
+    pub fn width(&self) -> WidthInt {
+        self.int_bits + self.frac_bits
+    }
+
+    /// Integer bits that are needed to represent all values in a signed format.
+    fn signed_int_bits(&self) -> WidthInt {
+        if self.signed {
+            self.int_bits
+        } else {
+            self.int_bits + 1
+        }
+    }
+
+    /// The smallest format that can represent all values of `self` and `other`.
+    pub fn union(&self, other: &Self) -> Self {
+        let signed = self.signed || other.signed;
+        let int_bits = if signed {
+            max(self.signed_int_bits(), other.signed_int_bits())
+        } else {
+            max(self.int_bits, other.int_bits)
+        };
+        Self {
+            signed,
+            int_bits,
+            frac_bits: max(self.frac_bits, other.frac_bits),
+        }
+    }
+}
+
+/// Signed formats are written as `Q3.5` and unsigned formats as `UQ3.5`.
+impl Display for QFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let prefix = if self.signed { "Q" } else { "UQ" };
+        write!(f, "{prefix}{}.{}", self.int_bits, self.frac_bits)
+    }
+}
+
+impl FromStr for QFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (signed, bits) = match s.strip_prefix("UQ") {
+            Some(bits) => (false, bits),
+            None => (true, s.strip_prefix('Q').ok_or(())?),
+        };
+        let (int_bits, frac_bits) = bits.split_once('.').ok_or(())?;
+        let format = Self {
+            signed,
+            int_bits: int_bits.parse().map_err(|_| ())?,
+            frac_bits: frac_bits.parse().map_err(|_| ())?,
+        };
+        if format.width() == 0 {
+            Err(())
+        } else {
+            Ok(format)
+        }
+    }
+}
+
+/// What happens to values that do not fit into the integer bits of the target format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Overflow {
+    /// drops the most significant bits
+    #[default]
+    Wrap,
+    /// clamps to the smallest or largest value
+    Saturate,
+}
+
+impl Context {
+    pub fn fixed_symbol(&mut self, name: &str, format: QFormat) -> ExprRef {
+        let symbol = self.bv_symbol(name, format.width());
+        self.set_q_format(symbol, format);
+        symbol
+    }
+
+    /// Creates the value closest to `value`. Values that are out of range wrap around.
+    pub fn fixed_val(&mut self, value: f64, format: QFormat) -> ExprRef {
+        let width = format.width();
+        assert!(
+            width <= 64,
+            "fixed-point constants are limited to 64 bits, {format} is too wide"
+        );
+        let raw = (value * (format.frac_bits as f64).exp2()).round() as i64 as u64;
+        let raw = if width < 64 {
+            raw & ((1u64 << width) - 1)
+        } else {
+            raw
+        };
+        let lit = self.bv_lit(&BitVecValue::from_u64(raw, width));
+        self.set_q_format(lit, format);
+        lit
+    }
+
+    pub fn set_q_format(&mut self, e: ExprRef, format: QFormat) {
+        assert_eq!(
+            e.get_bv_type(self),
+            Some(format.width()),
+            "{format} does not match the width of the expression"
+        );
+        self.attributes.entry(e).or_default().q_format = Some(format);
+    }
+
+    pub fn q_format(&self, e: ExprRef) -> Option<QFormat> {
+        self.attributes(e)?.q_format
+    }
+
+    fn expect_q_format(&self, e: ExprRef) -> QFormat {
+        self.q_format(e)
+            .unwrap_or_else(|| panic!("{} has no fixed-point format", e.serialize_to_str(self)))
+    }
+
+    /// Converts `e` to the format `to`. Fractional bits are removed with `rounding`, integer
+    /// bits according to `overflow`.
+    pub fn fixed_convert(
+        &mut self,
+        e: ExprRef,
+        to: QFormat,
+        rounding: Rounding,
+        overflow: Overflow,
+    ) -> ExprRef {
+        let mut from = self.expect_q_format(e);
+        let mut e = e;
+        if to.frac_bits > from.frac_bits {
+            let zeros = self.zero(to.frac_bits - from.frac_bits);
+            e = self.concat(e, zeros);
+        } else if to.frac_bits < from.frac_bits {
+            if rounding == Rounding::NearestEven {
+                // an additional integer bit ensures that rounding up never wraps
+                e = self.extend(e, 1, from.signed);
+                from.int_bits += 1;
+            }
+            e = self.round(e, from.frac_bits - to.frac_bits, rounding);
+        }
+        let width = e.get_bv_type(self).unwrap();
+        let result = match overflow {
+            Overflow::Wrap if to.width() >= width => {
+                self.extend(e, to.width() - width, from.signed)
+            }
+            Overflow::Wrap => self.slice(e, to.width() - 1, 0),
+            Overflow::Saturate => {
+                let e = match (from.signed, to.signed) {
+                    (true, false) => {
+                        // negative values turn into zero
+                        let sign = self.slice(e, width - 1, width - 1);
+                        let zero = self.zero(width);
+                        self.ite(sign, zero, e)
+                    }
+                    // makes room for the sign bit
+                    (false, true) => self.zero_extend(e, 1),
+                    _ => e,
+                };
+                self.saturate(e, to.width(), to.signed)
+            }
+        };
+        self.set_q_format(result, to);
+        result
+    }
+
+    /// Converts without any loss into a format that is at least as wide as `e`'s.
+    fn fixed_widen(&mut self, e: ExprRef, to: QFormat) -> ExprRef {
+        self.fixed_convert(e, to, Rounding::Truncate, Overflow::Wrap)
+    }
+
+    /// Exact sum with one additional integer bit.
+    pub fn fixed_add(&mut self, a: ExprRef, b: ExprRef) -> ExprRef {
+        let mut format = self.expect_q_format(a).union(&self.expect_q_format(b));
+        format.int_bits += 1;
+        let (a, b) = (self.fixed_widen(a, format), self.fixed_widen(b, format));
+        let sum = self.add(a, b);
+        self.set_q_format(sum, format);
+        sum
+    }
+
+    /// Exact difference with one additional integer bit. The result is always signed.
+    pub fn fixed_sub(&mut self, a: ExprRef, b: ExprRef) -> ExprRef {
+        let mut format = self.expect_q_format(a).union(&self.expect_q_format(b));
+        format.int_bits = if format.signed {
+            format.int_bits + 1
+        } else {
+            format.int_bits + 2
+        };
+        format.signed = true;
+        let (a, b) = (self.fixed_widen(a, format), self.fixed_widen(b, format));
+        let diff = self.sub(a, b);
+        self.set_q_format(diff, format);
+        diff
+    }
+
+    /// Exact product, integer and fractional bits of the operands add up. An unsigned operand
+    /// of a signed product is first converted into a signed format.
+    pub fn fixed_mul(&mut self, a: ExprRef, b: ExprRef) -> ExprRef {
+        let (mut format_a, mut format_b) = (self.expect_q_format(a), self.expect_q_format(b));
+        let signed = format_a.signed || format_b.signed;
+        let mut operands = [a, b];
+        for (e, format) in operands.iter_mut().zip([&mut format_a, &mut format_b]) {
+            if signed && !format.signed {
+                *format = QFormat::signed(format.int_bits + 1, format.frac_bits);
+                *e = self.fixed_widen(*e, *format);
+            }
+        }
+        let format = QFormat {
+            signed,
+            int_bits: format_a.int_bits + format_b.int_bits,
+            frac_bits: format_a.frac_bits + format_b.frac_bits,
+        };
+        let [a, b] = operands;
+        let a = self.extend(a, format_b.width(), signed);
+        let b = self.extend(b, format_a.width(), signed);
+        let product = self.mul(a, b);
+        self.set_q_format(product, format);
+        product
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::eval_bv_expr;
+
+    #[test]
+    fn test_fixed_point() {
+        let mut ctx = Context::default();
+        let a = ctx.fixed_symbol("a", QFormat::signed(4, 4));
+        let b = ctx.fixed_symbol("b", QFormat::unsigned(2, 6));
+        let eval = |ctx: &Context, e: ExprRef, a_value: f64, b_value: f64| {
+            let inputs = [
+                (
+                    a,
+                    BitVecValue::from_u64((a_value * 16.0) as i64 as u64 & 0xff, 8),
+                ),
+                (b, BitVecValue::from_u64((b_value * 64.0) as u64, 8)),
+            ];
+            let format = ctx.q_format(e).unwrap();
+            let raw = eval_bv_expr(ctx, inputs.as_slice(), e).to_u64().unwrap();
+            // sign extend and scale
+            let shift = 64 - format.width();
+            let raw = if format.signed {
+                ((raw << shift) as i64) >> shift
+            } else {
+                raw as i64
+            };
+            raw as f64 / (format.frac_bits as f64).exp2()
+        };
+
+        let sum = ctx.fixed_add(a, b);
+        assert_eq!(ctx.q_format(sum), Some(QFormat::signed(5, 6)));
+        assert_eq!(eval(&ctx, sum, -7.5, 3.25), -4.25);
+        assert_eq!(eval(&ctx, sum, 7.9375, 3.984375), 11.921875);
+
+        let diff = ctx.fixed_sub(b, b);
+        assert_eq!(ctx.q_format(diff), Some(QFormat::signed(4, 6)));
+        assert_eq!(eval(&ctx, diff, 0.0, 1.5), 0.0);
+
+        let product = ctx.fixed_mul(a, b);
+        assert_eq!(ctx.q_format(product), Some(QFormat::signed(7, 10)));
+        assert_eq!(eval(&ctx, product, -2.5, 1.5), -3.75);
+        assert_eq!(eval(&ctx, product, -8.0, 3.984375), -31.875);
+
+        // conversions
+        let narrow = QFormat::signed(4, 1);
+        let truncated = ctx.fixed_convert(a, narrow, Rounding::Truncate, Overflow::Wrap);
+        let rounded = ctx.fixed_convert(a, narrow, Rounding::NearestEven, Overflow::Saturate);
+        assert_eq!(eval(&ctx, truncated, 1.75, 0.0), 1.5);
+        assert_eq!(eval(&ctx, rounded, 1.75, 0.0), 2.0);
+        // rounding up the largest value saturates
+        assert_eq!(eval(&ctx, rounded, 7.9375, 0.0), 7.5);
+        assert_eq!(eval(&ctx, truncated, -0.25, 0.0), -0.5);
+        let small = QFormat::signed(2, 4);
+        let saturated = ctx.fixed_convert(a, small, Rounding::Truncate, Overflow::Saturate);
+        let wrapped = ctx.fixed_convert(a, small, Rounding::Truncate, Overflow::Wrap);
+        assert_eq!(eval(&ctx, saturated, 3.0, 0.0), 1.9375);
+        assert_eq!(eval(&ctx, saturated, -3.0, 0.0), -2.0);
+        assert_eq!(eval(&ctx, wrapped, 3.0, 0.0), -1.0);
+        let to_unsigned = ctx.fixed_convert(
+            a,
+            QFormat::unsigned(4, 4),
+            Rounding::Truncate,
+            Overflow::Saturate,
+        );
+        assert_eq!(eval(&ctx, to_unsigned, -3.0, 0.0), 0.0);
+
+        let half = ctx.fixed_val(-0.5, QFormat::signed(2, 2));
+        assert_eq!(eval(&ctx, half, 0.0, 0.0), -0.5);
+        assert_eq!("UQ2.6".parse(), Ok(QFormat::unsigned(2, 6)));
+        assert_eq!(QFormat::signed(4, 4).to_string(), "Q4.4");
+        assert!("Q0.0".parse::<QFormat>().is_err());
+    }
+}
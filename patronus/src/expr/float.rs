@@ -0,0 +1,541 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Floating-Point Arithmetic
+//! IEEE-754 binary floating-point numbers are stored in plain bit-vectors. The operations
+//! below expand into a bit-vector implementation of the operator, much like a softfloat
+//! library, which means that FPU datapaths can be simulated and checked with bounded model
+//! checking without any floating-point support in our solvers or the interpreter.
+//!
+//! All arithmetic rounds to the nearest value with ties to even and supports subnormal numbers.
+//! Every NaN result is the canonical quiet NaN with a cleared sign bit. Comparisons follow the
+//! standard, i.e., NaNs are unordered and `-0 == +0`.
+
+use super::{Context, ExprRef, TypeCheck, WidthInt};
+use baa::BitVecValue;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FloatFormat {
+    pub exp_bits: WidthInt,
+    /// number of stored significand bits, i.e., without the hidden bit
+    pub frac_bits: WidthInt,
+}
+
+impl FloatFormat {
+    /// IEEE-754 half precision
+    pub const F16: Self = Self {
+        exp_bits: 5,
+        frac_bits: 10,
+    };
+    /// IEEE-754 single precision
+    pub const F32: Self = Self {
+        exp_bits: 8,
+        frac_bits: 23,
+    };
+
+    pub fn new(exp_bits: WidthInt, frac_bits: WidthInt) -> Self {
+        assert!(
+            exp_bits >= 2 && frac_bits >= 1,
+            "floats need at least two exponent bits and one significand bit"
+        );
+        assert!(exp_bits < 32, "exponents are limited to 31 bits");
+        Self {
+            exp_bits,
+            frac_bits,
+        }
+    }
+
+    pub fn width(&self) -> WidthInt {
+        1 + self.exp_bits + self.frac_bits
+    }
+
+    fn bias(&self) -> u64 {
+        (1u64 << (self.exp_bits - 1)) - 1
+    }
+
+    /// The biased exponent of infinities and NaNs.
+    fn max_exp(&self) -> u64 {
+        (1u64 << self.exp_bits) - 1
+    }
+
+    /// Width of the signed exponent arithmetic. Needs to hold the sum of two exponents as well
+    /// as the leading zero count of a product.
+    fn calc_exp_width(&self) -> WidthInt {
+        let lz_bits = u64::BITS - (2 * self.frac_bits as u64 + 5).leading_zeros();
+        self.exp_bits.max(lz_bits) + 3
+    }
+}
+
+/// A float split into its fields. Subnormals have an exponent of one and a cleared hidden bit.
+struct Unpacked {
+    sign: ExprRef,
+    /// biased exponent, `exp_bits` wide
+    exp: ExprRef,
+    /// significand including the hidden bit, `frac_bits + 1` wide
+    sig: ExprRef,
+    is_zero: ExprRef,
+    is_inf: ExprRef,
+    is_nan: ExprRef,
+}
+
+impl Context {
+    /// Creates a single precision constant.
+    pub fn fp32_val(&mut self, value: f32) -> ExprRef {
+        self.bv_lit(&BitVecValue::from_u64(value.to_bits() as u64, 32))
+    }
+
+    pub fn fp_neg(&mut self, a: ExprRef, format: FloatFormat) -> ExprRef {
+        let w = format.width();
+        let sign = self.slice(a, w - 1, w - 1);
+        let sign = self.not(sign);
+        let magnitude = self.slice(a, w - 2, 0);
+        self.concat(sign, magnitude)
+    }
+
+    pub fn fp_abs(&mut self, a: ExprRef, format: FloatFormat) -> ExprRef {
+        let w = format.width();
+        let magnitude = self.slice(a, w - 2, 0);
+        self.zero_extend(magnitude, 1)
+    }
+
+    pub fn fp_is_nan(&mut self, a: ExprRef, format: FloatFormat) -> ExprRef {
+        self.fp_unpack(a, format).is_nan
+    }
+
+    pub fn fp_add(&mut self, a: ExprRef, b: ExprRef, format: FloatFormat) -> ExprRef {
+        self.check_fp_operands(a, b, format);
+        let (e, m) = (format.exp_bits, format.frac_bits);
+        let x = format.calc_exp_width();
+        let ua = self.fp_unpack(a, format);
+        let ub = self.fp_unpack(b, format);
+
+        // order the operands by magnitude
+        let mag_a = self.slice(a, e + m - 1, 0);
+        let mag_b = self.slice(b, e + m - 1, 0);
+        let a_is_big = self.greater_or_equal(mag_a, mag_b);
+        let sign_big = self.ite(a_is_big, ua.sign, ub.sign);
+        let exp_big = self.ite(a_is_big, ua.exp, ub.exp);
+        let exp_small = self.ite(a_is_big, ub.exp, ua.exp);
+        let sig_big = self.ite(a_is_big, ua.sig, ub.sig);
+        let sig_small = self.ite(a_is_big, ub.sig, ua.sig);
+
+        // align the smaller significand, with guard, round and sticky bits
+        let n = m + 4;
+        let guard_bits = self.zero(3);
+        let big = self.concat(sig_big, guard_bits);
+        let small = self.concat(sig_small, guard_bits);
+        let diff = self.sub(exp_big, exp_small);
+        // shifting by more than `n - 1` bits only leaves the sticky bit
+        let amount = self.fp_shift_amount(diff, n as u64 - 1, n);
+        let small = self.fp_shift_right_sticky(small, amount);
+
+        // one additional bit for the carry
+        let big = self.zero_extend(big, 1);
+        let small = self.zero_extend(small, 1);
+        let is_sub = self.xor(ua.sign, ub.sign);
+        let sum = self.add(big, small);
+        let difference = self.sub(big, small);
+        let result = self.ite(is_sub, difference, sum);
+
+        // normalize, the exponent may not drop below one
+        let lz = self.fp_leading_zeros(result, x);
+        let exp_big = self.zero_extend(exp_big, x - e);
+        let shift = self.fp_min(lz, exp_big, false);
+        let amount = self.fp_shift_amount(shift, n as u64, n + 1);
+        let normalized = self.shift_left(result, amount);
+        let one = self.one(x);
+        let exp = self.add(exp_big, one);
+        let exp = self.sub(exp, shift);
+
+        // an exact zero is positive, unless both operands are negative
+        let zeros = self.zero(n + 1);
+        let is_zero = self.equal(result, zeros);
+        let both_negative = self.and(ua.sign, ub.sign);
+        let sign = self.ite(is_zero, both_negative, sign_big);
+        let finite = self.fp_round_and_pack(sign, exp, normalized, format);
+
+        // special cases
+        let infs = self.and(ua.is_inf, ub.is_inf);
+        let inf_minus_inf = self.and(infs, is_sub);
+        let any_nan = self.or(ua.is_nan, ub.is_nan);
+        let is_nan = self.or(any_nan, inf_minus_inf);
+        let nan = self.fp_nan(format);
+        let result = self.ite(ub.is_inf, b, finite);
+        let result = self.ite(ua.is_inf, a, result);
+        self.ite(is_nan, nan, result)
+    }
+
+    pub fn fp_sub(&mut self, a: ExprRef, b: ExprRef, format: FloatFormat) -> ExprRef {
+        let neg_b = self.fp_neg(b, format);
+        self.fp_add(a, neg_b, format)
+    }
+
+    pub fn fp_mul(&mut self, a: ExprRef, b: ExprRef, format: FloatFormat) -> ExprRef {
+        self.check_fp_operands(a, b, format);
+        let (e, m) = (format.exp_bits, format.frac_bits);
+        let x = format.calc_exp_width();
+        let ua = self.fp_unpack(a, format);
+        let ub = self.fp_unpack(b, format);
+        let sign = self.xor(ua.sign, ub.sign);
+
+        // exact product of the significands
+        let n = 2 * m + 2;
+        let sig_a = self.zero_extend(ua.sig, m + 1);
+        let sig_b = self.zero_extend(ub.sig, m + 1);
+        let product = self.mul(sig_a, sig_b);
+
+        // exponent if the most significant bit of the product is set
+        let exp_a = self.zero_extend(ua.exp, x - e);
+        let exp_b = self.zero_extend(ub.exp, x - e);
+        let bias = self.bit_vec_val(format.bias() - 1, x);
+        let exp_sum = self.add(exp_a, exp_b);
+        let exp_top = self.sub(exp_sum, bias);
+
+        // normalize: shift left by the number of leading zeros, unless the exponent would drop
+        // below one, in which case the result is subnormal and we may need to shift right
+        let lz = self.fp_leading_zeros(product, x);
+        let one = self.one(x);
+        let max_shift = self.sub(exp_top, one);
+        let shift = self.fp_min(lz, max_shift, true);
+        let zero = self.zero(x);
+        let shift_is_negative = self.greater_signed(zero, shift);
+        let left_amount = self.fp_shift_amount(shift, n as u64 - 1, n);
+        let left = self.shift_left(product, left_amount);
+        let right_amount = self.sub(zero, shift);
+        let right_amount = self.fp_shift_amount(right_amount, n as u64 - 1, n);
+        let right = self.fp_shift_right_sticky(product, right_amount);
+        let normalized = self.ite(shift_is_negative, right, left);
+        let exp = self.sub(exp_top, shift);
+        let finite = self.fp_round_and_pack(sign, exp, normalized, format);
+
+        // special cases
+        let inf_times_zero_a = self.and(ua.is_inf, ub.is_zero);
+        let inf_times_zero_b = self.and(ua.is_zero, ub.is_inf);
+        let inf_times_zero = self.or(inf_times_zero_a, inf_times_zero_b);
+        let any_nan = self.or(ua.is_nan, ub.is_nan);
+        let is_nan = self.or(any_nan, inf_times_zero);
+        let is_inf = self.or(ua.is_inf, ub.is_inf);
+        let nan = self.fp_nan(format);
+        let inf = self.fp_inf(sign, format);
+        let result = self.ite(is_inf, inf, finite);
+        self.ite(is_nan, nan, result)
+    }
+
+    /// `a == b`, false if any operand is NaN, true for `-0 == +0`.
+    pub fn fp_eq(&mut self, a: ExprRef, b: ExprRef, format: FloatFormat) -> ExprRef {
+        self.check_fp_operands(a, b, format);
+        let ua = self.fp_unpack(a, format);
+        let ub = self.fp_unpack(b, format);
+        let same = self.equal(a, b);
+        let zeros = self.and(ua.is_zero, ub.is_zero);
+        let equal = self.or(same, zeros);
+        let unordered = self.or(ua.is_nan, ub.is_nan);
+        let ordered = self.not(unordered);
+        self.and(ordered, equal)
+    }
+
+    /// `a < b`, false if any operand is NaN.
+    pub fn fp_lt(&mut self, a: ExprRef, b: ExprRef, format: FloatFormat) -> ExprRef {
+        self.check_fp_operands(a, b, format);
+        let ua = self.fp_unpack(a, format);
+        let ub = self.fp_unpack(b, format);
+        // map to integers that are ordered like the floats
+        let key_a = self.fp_order_key(a, ua.sign, format);
+        let key_b = self.fp_order_key(b, ub.sign, format);
+        let less = self.greater(key_b, key_a);
+        let zeros = self.and(ua.is_zero, ub.is_zero);
+        let unordered = self.or(ua.is_nan, ub.is_nan);
+        let excluded = self.or(zeros, unordered);
+        let included = self.not(excluded);
+        self.and(included, less)
+    }
+
+    /// `a <= b`, false if any operand is NaN.
+    pub fn fp_le(&mut self, a: ExprRef, b: ExprRef, format: FloatFormat) -> ExprRef {
+        let lt = self.fp_lt(a, b, format);
+        let eq = self.fp_eq(a, b, format);
+        self.or(lt, eq)
+    }
+
+    fn check_fp_operands(&self, a: ExprRef, b: ExprRef, format: FloatFormat) {
+        debug_assert_eq!(a.get_bv_type(self), Some(format.width()));
+        debug_assert_eq!(b.get_bv_type(self), Some(format.width()));
+    }
+
+    fn fp_unpack(&mut self, x: ExprRef, format: FloatFormat) -> Unpacked {
+        let (e, m) = (format.exp_bits, format.frac_bits);
+        let sign = self.slice(x, e + m, e + m);
+        let exp = self.slice(x, e + m - 1, m);
+        let frac = self.slice(x, m - 1, 0);
+        let (exp_zeros, exp_ones, frac_zeros) = (self.zero(e), self.ones(e), self.zero(m));
+        let exp_is_zero = self.equal(exp, exp_zeros);
+        let exp_is_max = self.equal(exp, exp_ones);
+        let frac_is_zero = self.equal(frac, frac_zeros);
+        let hidden = self.not(exp_is_zero);
+        let sig = self.concat(hidden, frac);
+        let one = self.one(e);
+        let exp = self.ite(exp_is_zero, one, exp);
+        let frac_is_not_zero = self.not(frac_is_zero);
+        Unpacked {
+            sign,
+            exp,
+            sig,
+            is_zero: self.and(exp_is_zero, frac_is_zero),
+            is_inf: self.and(exp_is_max, frac_is_zero),
+            is_nan: self.and(exp_is_max, frac_is_not_zero),
+        }
+    }
+
+    fn fp_nan(&mut self, format: FloatFormat) -> ExprRef {
+        let m = format.frac_bits;
+        let quiet = self.one(1);
+        let frac = if m > 1 {
+            let rest = self.zero(m - 1);
+            self.concat(quiet, rest)
+        } else {
+            quiet
+        };
+        let exp = self.ones(format.exp_bits);
+        let magnitude = self.concat(exp, frac);
+        self.zero_extend(magnitude, 1)
+    }
+
+    fn fp_inf(&mut self, sign: ExprRef, format: FloatFormat) -> ExprRef {
+        let exp = self.ones(format.exp_bits);
+        let frac = self.zero(format.frac_bits);
+        let magnitude = self.concat(exp, frac);
+        self.concat(sign, magnitude)
+    }
+
+    /// Positive numbers get their sign bit set, negative numbers are inverted.
+    fn fp_order_key(&mut self, x: ExprRef, sign: ExprRef, format: FloatFormat) -> ExprRef {
+        let w = format.width();
+        let inverted = self.not(x);
+        let magnitude = self.slice(x, w - 2, 0);
+        let one = self.one(1);
+        let positive = self.concat(one, magnitude);
+        self.ite(sign, inverted, positive)
+    }
+
+    /// Rounds and encodes a finite result. `normalized` has its hidden bit in the most
+    /// significant position, followed by the fraction, the guard bit and at least one more bit
+    /// that only matters for the sticky bit. `exp` is a signed biased exponent which is
+    /// only used if the hidden bit is set.
+    fn fp_round_and_pack(
+        &mut self,
+        sign: ExprRef,
+        exp: ExprRef,
+        normalized: ExprRef,
+        format: FloatFormat,
+    ) -> ExprRef {
+        let (e, m) = (format.exp_bits, format.frac_bits);
+        let n = normalized.get_bv_type(self).unwrap();
+        let x = exp.get_bv_type(self).unwrap();
+        debug_assert!(n >= m + 3);
+        let hidden = self.slice(normalized, n - 1, n - 1);
+        let frac = self.slice(normalized, n - 2, n - 1 - m);
+        let lsb = self.slice(normalized, n - 1 - m, n - 1 - m);
+        let guard = self.slice(normalized, n - 2 - m, n - 2 - m);
+        let rest = self.slice(normalized, n - 3 - m, 0);
+        let rest_zeros = self.zero(n - 2 - m);
+        let rest_is_zero = self.equal(rest, rest_zeros);
+        let sticky = self.not(rest_is_zero);
+        let sticky_or_odd = self.or(sticky, lsb);
+        let round_up = self.and(guard, sticky_or_odd);
+
+        // a carry out of the fraction correctly increments the exponent
+        let exp_bits = self.slice(exp, e - 1, 0);
+        let exp_zeros = self.zero(e);
+        let exp_field = self.ite(hidden, exp_bits, exp_zeros);
+        let packed = self.concat(exp_field, frac);
+        let increment = self.zero_extend(round_up, e + m - 1);
+        let rounded = self.add(packed, increment);
+
+        let max_exp = self.bit_vec_val(format.max_exp(), x);
+        let too_large = self.greater_or_equal_signed(exp, max_exp);
+        let overflow = self.and(hidden, too_large);
+        let (exp_ones, frac_zeros) = (self.ones(e), self.zero(m));
+        let inf = self.concat(exp_ones, frac_zeros);
+        let magnitude = self.ite(overflow, inf, rounded);
+        self.concat(sign, magnitude)
+    }
+
+    /// Number of leading zeros of `e` as a `width`-bit value.
+    fn fp_leading_zeros(&mut self, e: ExprRef, width: WidthInt) -> ExprRef {
+        let n = e.get_bv_type(self).unwrap();
+        // the most significant bit needs to be checked last, i.e., in the outermost ite
+        let mut count = self.bit_vec_val(n, width);
+        for ii in 0..n {
+            let bit = self.slice(e, ii, ii);
+            let zeros = self.bit_vec_val(n - 1 - ii, width);
+            count = self.ite(bit, zeros, count);
+        }
+        count
+    }
+
+    fn fp_min(&mut self, a: ExprRef, b: ExprRef, signed: bool) -> ExprRef {
+        let a_is_greater = if signed {
+            self.greater_signed(a, b)
+        } else {
+            self.greater(a, b)
+        };
+        self.ite(a_is_greater, b, a)
+    }
+
+    /// Turns an unsigned amount into a `width`-bit shift amount of at most `limit`.
+    fn fp_shift_amount(&mut self, amount: ExprRef, limit: u64, width: WidthInt) -> ExprRef {
+        let amount_width = amount.get_bv_type(self).unwrap();
+        debug_assert!(limit < (1u64 << width.min(63)));
+        let clamped = if amount_width < u64::BITS && limit >= (1u64 << amount_width) {
+            // the amount can never exceed the limit
+            amount
+        } else {
+            let limit = self.bit_vec_val(limit, amount_width);
+            let too_large = self.greater(amount, limit);
+            self.ite(too_large, limit, amount)
+        };
+        if amount_width > width {
+            self.slice(clamped, width - 1, 0)
+        } else {
+            self.zero_extend(clamped, width - amount_width)
+        }
+    }
+
+    /// Shifts right and sets the least significant bit if any one bits were shifted out.
+    fn fp_shift_right_sticky(&mut self, e: ExprRef, amount: ExprRef) -> ExprRef {
+        let width = e.get_bv_type(self).unwrap();
+        let shifted = self.shift_right(e, amount);
+        let restored = self.shift_left(shifted, amount);
+        let exact = self.equal(restored, e);
+        let lost = self.not(exact);
+        let lost = self.zero_extend(lost, width - 1);
+        self.or(shifted, lost)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::eval_bv_expr;
+    use baa::BitVecOps;
+
+    #[test]
+    fn test_fp32_against_native() {
+        let mut ctx = Context::default();
+        let f = FloatFormat::F32;
+        let a = ctx.bv_symbol("a", 32);
+        let b = ctx.bv_symbol("b", 32);
+        let add = ctx.fp_add(a, b, f);
+        let sub = ctx.fp_sub(a, b, f);
+        let mul = ctx.fp_mul(a, b, f);
+        let eq = ctx.fp_eq(a, b, f);
+        let lt = ctx.fp_lt(a, b, f);
+        let le = ctx.fp_le(a, b, f);
+        let eval = |ctx: &Context, e: ExprRef, x: f32, y: f32| {
+            let inputs = [
+                (a, BitVecValue::from_u64(x.to_bits() as u64, 32)),
+                (b, BitVecValue::from_u64(y.to_bits() as u64, 32)),
+            ];
+            eval_bv_expr(ctx, inputs.as_slice(), e).to_u64().unwrap()
+        };
+        let check = |actual: u64, expected: f32, op: &str, x: f32, y: f32| {
+            let actual = f32::from_bits(actual as u32);
+            if expected.is_nan() {
+                assert!(
+                    actual.is_nan(),
+                    "{x:e} {op} {y:e} = {actual:e}, expected NaN"
+                );
+            } else {
+                assert_eq!(
+                    actual.to_bits(),
+                    expected.to_bits(),
+                    "{x:e} {op} {y:e} = {actual:e}, expected {expected:e}"
+                );
+            }
+        };
+
+        let min_sub = f32::from_bits(1);
+        let values = [
+            0.0,
+            -0.0,
+            1.0,
+            -1.0,
+            1.5,
+            3.0,
+            0.1,
+            -0.3,
+            1.0e-3,
+            16_777_216.0,
+            1.0 + f32::EPSILON,
+            3.4e38,
+            -3.4e38,
+            f32::MAX,
+            f32::MIN_POSITIVE,
+            -f32::MIN_POSITIVE,
+            f32::MIN_POSITIVE * 0.75,
+            min_sub,
+            -min_sub,
+            min_sub * 3.0,
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+            f32::NAN,
+        ];
+        for &x in values.iter() {
+            for &y in values.iter() {
+                check(eval(&ctx, add, x, y), x + y, "+", x, y);
+                check(eval(&ctx, sub, x, y), x - y, "-", x, y);
+                check(eval(&ctx, mul, x, y), x * y, "*", x, y);
+                assert_eq!(eval(&ctx, eq, x, y) == 1, x == y, "{x:e} == {y:e}");
+                assert_eq!(eval(&ctx, lt, x, y) == 1, x < y, "{x:e} < {y:e}");
+                assert_eq!(eval(&ctx, le, x, y) == 1, x <= y, "{x:e} <= {y:e}");
+            }
+        }
+        // ties are rounded to even: 2^24 + 1 is halfway between two floats
+        check(
+            eval(&ctx, add, 16_777_216.0, 1.0),
+            16_777_216.0,
+            "+",
+            16_777_216.0,
+            1.0,
+        );
+        check(
+            eval(&ctx, add, 16_777_218.0, 1.0),
+            16_777_220.0,
+            "+",
+            16_777_218.0,
+            1.0,
+        );
+    }
+
+    #[test]
+    fn test_fp16() {
+        let mut ctx = Context::default();
+        let f = FloatFormat::F16;
+        let a = ctx.bv_symbol("a", 16);
+        let b = ctx.bv_symbol("b", 16);
+        let add = ctx.fp_add(a, b, f);
+        let mul = ctx.fp_mul(a, b, f);
+        let eval = |ctx: &Context, e: ExprRef, x: u64, y: u64| {
+            let inputs = [
+                (a, BitVecValue::from_u64(x, 16)),
+                (b, BitVecValue::from_u64(y, 16)),
+            ];
+            eval_bv_expr(ctx, inputs.as_slice(), e).to_u64().unwrap()
+        };
+        // 1 + 1 = 2
+        assert_eq!(eval(&ctx, add, 0x3c00, 0x3c00), 0x4000);
+        // 65504 + 65504 = inf
+        assert_eq!(eval(&ctx, add, 0x7bff, 0x7bff), 0x7c00);
+        // the smallest subnormal doubled
+        assert_eq!(eval(&ctx, add, 0x0001, 0x0001), 0x0002);
+        // 1 * 3 = 3
+        assert_eq!(eval(&ctx, mul, 0x3c00, 0x4200), 0x4200);
+        // half of the smallest subnormal is a tie that rounds to zero
+        assert_eq!(eval(&ctx, mul, 0x0001, 0x3800), 0x0000);
+        // -2 * 0.5 = -1
+        assert_eq!(eval(&ctx, mul, 0xc000, 0x3800), 0xbc00);
+        // inf * 0 = NaN
+        assert_eq!(eval(&ctx, mul, 0x7c00, 0x0000), 0x7e00);
+    }
+}
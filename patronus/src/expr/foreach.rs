@@ -128,7 +128,8 @@ impl ForEachChild<ExprRef> for Expr {
                 (visitor)(tru);
                 (visitor)(fals);
             }
-            Expr::ArraySymbol { .. } => {} // no children
+            Expr::ArraySymbol { .. } => {}  // no children
+            Expr::ArrayLiteral { .. } => {} // no children
             Expr::ArrayConstant { e, .. } => {
                 (visitor)(e);
             }
@@ -146,6 +147,11 @@ impl ForEachChild<ExprRef> for Expr {
                 (visitor)(tru);
                 (visitor)(fals);
             }
+            Expr::BVFunction { .. } => {} // no children
+            Expr::BVApply { func, arg, .. } => {
+                (visitor)(func);
+                (visitor)(arg);
+            }
         }
     }
 
@@ -182,10 +188,13 @@ impl ForEachChild<ExprRef> for Expr {
             Expr::BVArrayRead { .. } => 2,
             Expr::BVIte { .. } => 3,
             Expr::ArraySymbol { .. } => 0,
+            Expr::ArrayLiteral { .. } => 0,
             Expr::ArrayConstant { .. } => 1,
             Expr::ArrayEqual(_, _) => 2,
             Expr::ArrayStore { .. } => 3,
             Expr::ArrayIte { .. } => 3,
+            Expr::BVFunction { .. } => 0,
+            Expr::BVApply { .. } => 2,
         }
     }
 }
@@ -0,0 +1,110 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Frozen Context
+//! A [`Context`] needs to be borrowed mutably to create new expressions, which makes it hard to
+//! share between threads. Most users of a parsed design, like simulators, only ever read
+//! expressions. Freezing a context moves it behind an [`Arc`], such that every thread can hold
+//! a cheap handle to the same expressions without copying them. Engines that need to create
+//! new expressions, e.g., to build SMT queries, call [`FrozenContext::fork`] to get a private
+//! copy that they can modify.
+
+use crate::expr::Context;
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// An immutable [`Context`] that can be shared between threads.
+#[derive(Clone)]
+pub struct FrozenContext(Arc<Context>);
+
+impl FrozenContext {
+    /// A mutable copy of all expressions. Expression references from the frozen context remain
+    /// valid in the copy.
+    pub fn fork(&self) -> Context {
+        self.0.as_ref().clone()
+    }
+
+    /// Returns the context if this is the only handle to it, otherwise a copy.
+    pub fn into_inner(self) -> Context {
+        Arc::try_unwrap(self.0).unwrap_or_else(|ctx| ctx.as_ref().clone())
+    }
+
+    /// Number of handles to the same context, including this one.
+    pub fn handles(&self) -> usize {
+        Arc::strong_count(&self.0)
+    }
+}
+
+impl Deref for FrozenContext {
+    type Target = Context;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl From<Context> for FrozenContext {
+    fn from(ctx: Context) -> Self {
+        Self(Arc::new(ctx))
+    }
+}
+
+impl Context {
+    /// Makes the context immutable so that it can be shared between threads.
+    pub fn freeze(self) -> FrozenContext {
+        self.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::{InitKind, Interpreter, Simulator};
+    use crate::system::{State, TransitionSystem};
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_concurrent_simulations() {
+        assert_send_sync::<FrozenContext>();
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("counter".to_string());
+        let count = ctx.bv_symbol("count", 8);
+        let next = ctx.build(|c| c.add(count, c.one(8)));
+        let init = ctx.zero(8);
+        sys.add_state(
+            &ctx,
+            State {
+                symbol: count,
+                init: Some(init),
+                next: Some(next),
+            },
+        );
+        let ctx = ctx.freeze();
+        let sys = std::sync::Arc::new(sys);
+
+        let threads: Vec<_> = (1..=4u64)
+            .map(|steps| {
+                let (ctx, sys) = (ctx.clone(), sys.clone());
+                std::thread::spawn(move || {
+                    let mut sim = Interpreter::new(&ctx, &sys);
+                    sim.init(InitKind::Zero);
+                    for _ in 0..steps {
+                        sim.step();
+                    }
+                    sim.get(count).try_into_u64().unwrap()
+                })
+            })
+            .collect();
+        let results: Vec<u64> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+        assert_eq!(results, [1, 2, 3, 4]);
+
+        // a fork can be extended without affecting the shared context
+        let mut fork = ctx.fork();
+        let extra = fork.bv_symbol("extra", 8);
+        assert_eq!(fork.get_symbol_name(extra), Some("extra"));
+        assert_eq!(ctx.get_symbol_name(count), Some("count"));
+        assert_eq!(ctx.handles(), 1);
+    }
+}
@@ -0,0 +1,133 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Width Limits
+//! Nothing in our IR prevents a frontend from creating a million bit wide signal or an array
+//! with a 64-bit index. Most engines would not fail on such inputs, they would just become
+//! unusably slow or overflow while computing derived widths. Engines check their inputs
+//! against [`WidthLimits`] and return a [`LimitError`] that names the offending expression,
+//! which allows the caller to fall back to an engine that can deal with the input.
+
+use crate::expr::{Context, ExprRef, ForEachChild, SerializableIrNode, Type, TypeCheck, WidthInt};
+use rustc_hash::FxHashSet;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WidthLimits {
+    /// maximum number of bits of a bit-vector or of an array element
+    pub max_width: WidthInt,
+    /// maximum number of index bits of an array, i.e., log2 of the number of elements
+    pub max_index_width: WidthInt,
+}
+
+impl Default for WidthLimits {
+    fn default() -> Self {
+        Self {
+            max_width: 1 << 16,
+            max_index_width: 32,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum LimitError {
+    #[error("{engine}: `{expr}` is {width} bits wide, which exceeds the limit of {limit} bits")]
+    TooWide {
+        engine: &'static str,
+        expr: String,
+        width: WidthInt,
+        limit: WidthInt,
+    },
+    #[error(
+        "{engine}: the array `{expr}` has a {index_width}-bit index, which exceeds the limit of {limit} bits"
+    )]
+    ArrayTooLarge {
+        engine: &'static str,
+        expr: String,
+        index_width: WidthInt,
+        limit: WidthInt,
+    },
+}
+
+impl WidthLimits {
+    /// Checks `roots` and all their sub-expressions. `engine` is used in the error message.
+    pub fn check(
+        &self,
+        ctx: &Context,
+        roots: impl IntoIterator<Item = ExprRef>,
+        engine: &'static str,
+    ) -> Result<(), LimitError> {
+        let mut visited = FxHashSet::default();
+        let mut todo: Vec<ExprRef> = roots.into_iter().collect();
+        while let Some(e) = todo.pop() {
+            if !visited.insert(e) {
+                continue;
+            }
+            self.check_type(ctx, e, engine)?;
+            ctx[e].for_each_child(|&c| todo.push(c));
+        }
+        Ok(())
+    }
+
+    fn check_type(
+        &self,
+        ctx: &Context,
+        e: ExprRef,
+        engine: &'static str,
+    ) -> Result<(), LimitError> {
+        let (width, index_width) = match ctx[e].get_type(ctx) {
+            Type::BV(width) => (width, None),
+            Type::Array(tpe) => (tpe.data_width, Some(tpe.index_width)),
+        };
+        if width > self.max_width {
+            return Err(LimitError::TooWide {
+                engine,
+                expr: e.serialize_to_str(ctx),
+                width,
+                limit: self.max_width,
+            });
+        }
+        match index_width {
+            Some(index_width) if index_width > self.max_index_width => {
+                Err(LimitError::ArrayTooLarge {
+                    engine,
+                    expr: e.serialize_to_str(ctx),
+                    index_width,
+                    limit: self.max_index_width,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_width_limits() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("a", 100);
+        let b = ctx.bv_symbol("b", 100);
+        let wide = ctx.build(|c| c.concat(a, b));
+        let mem = ctx.array_symbol("mem", 40, 8);
+        let limits = WidthLimits {
+            max_width: 128,
+            max_index_width: 32,
+        };
+        assert_eq!(limits.check(&ctx, [a, b], "test"), Ok(()));
+        let err = limits.check(&ctx, [wide], "egraph").unwrap_err();
+        assert!(matches!(err, LimitError::TooWide { width: 200, .. }));
+        assert!(err.to_string().starts_with("egraph: "));
+        assert!(matches!(
+            limits.check(&ctx, [mem], "bdd"),
+            Err(LimitError::ArrayTooLarge {
+                index_width: 40,
+                ..
+            })
+        ));
+    }
+}
@@ -5,7 +5,7 @@
 
 use crate::expr::context::{ExprRef, StringRef};
 use crate::expr::Context;
-use baa::{BitVecValueIndex, BitVecValueRef};
+use baa::{ArrayValue, BitVecValueIndex, BitVecValueRef};
 use std::fmt::Debug;
 
 /// This type restricts the maximum width that a bit-vector type is allowed to have in our IR.
@@ -36,6 +36,24 @@ impl BVLitValue {
     }
 }
 
+/// Type wrapping an index to a constant array value stored in the [`Context`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct ArrayLitValue(u32);
+
+impl ArrayLitValue {
+    pub(crate) fn new(index: usize) -> Self {
+        Self(index as u32)
+    }
+
+    pub(crate) fn index(&self) -> usize {
+        self.0 as usize
+    }
+
+    pub fn get<'c>(&self, ctx: &'c Context) -> &'c ArrayValue {
+        ctx.get_array_value(*self)
+    }
+}
+
 /// Represents a SMT bit-vector or array expression.
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
 pub enum Expr {
@@ -94,6 +112,21 @@ pub enum Expr {
         index: ExprRef,
         width: WidthInt,
     },
+    // uninterpreted functions
+    /// Uninterpreted function with `num_args` arguments that returns a bit-vector.
+    /// Only valid as the innermost `func` of a `BVApply`.
+    BVFunction {
+        name: StringRef,
+        num_args: u32,
+        width: WidthInt,
+    },
+    /// Applies a function to one more argument. Functions with several arguments are
+    /// represented as nested applications: `f(a, b)` is `apply(apply(f, a), b)`.
+    BVApply {
+        func: ExprRef,
+        arg: ExprRef,
+        width: WidthInt,
+    },
     // ternary op
     BVIte {
         cond: ExprRef,
@@ -109,6 +142,13 @@ pub enum Expr {
         index_width: WidthInt,
         data_width: WidthInt,
     },
+    /// Constant array contents, e.g., a ROM. Unlike a chain of stores on an `ArrayConstant`,
+    /// this only needs a single node.
+    ArrayLiteral {
+        value: ArrayLitValue,
+        index_width: WidthInt,
+        data_width: WidthInt,
+    },
     // unary
     ArrayConstant {
         e: ExprRef,
@@ -154,11 +194,13 @@ impl Expr {
         matches!(self, Expr::BVLiteral { .. })
     }
 
-    /// Returns the reference to the symbol name. Returns `None` if the expression is not a symbol.
+    /// Returns the reference to the symbol name. Returns `None` if the expression is not a symbol
+    /// or an uninterpreted function.
     pub fn get_symbol_name_ref(&self) -> Option<StringRef> {
         match self {
             Expr::BVSymbol { name, .. } => Some(*name),
             Expr::ArraySymbol { name, .. } => Some(*name),
+            Expr::BVFunction { name, .. } => Some(*name),
             _ => None,
         }
     }
@@ -0,0 +1,147 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Operator Overloading
+//! Expressions that are bound to a [`Builder`] through [`Builder::ex`] can be combined with
+//! the usual Rust operators, which makes formulas in tests and examples easier to read:
+//!
+//! ```
+//! # use patronus::expr::*;
+//! let mut ctx = Context::default();
+//! let a = ctx.bv_symbol("a", 8);
+//! let b = ctx.bv_symbol("b", 8);
+//! let e = ctx.build(|c| {
+//!     let (a, b) = (c.ex(a), c.ex(b));
+//!     ((a + b) & !b).into()
+//! });
+//! assert_eq!(e, ctx.build(|c| c.and(c.add(a, b), c.not(b))));
+//! ```
+//!
+//! Operators are overloaded for bit-vectors only. `>>` is a logical shift and `-x` is the
+//! two's complement negation. Comparisons cannot be expressed with `==` or `<` since those
+//! traits need to return a `bool`, instead there are methods like [`BuilderExpr::equal`].
+
+use super::{Builder, ExprRef};
+use std::ops::{Add, BitAnd, BitOr, BitXor, Mul, Neg, Not, Shl, Shr, Sub};
+
+/// An expression together with the builder that is used to create new expressions from it.
+#[derive(Clone, Copy)]
+pub struct BuilderExpr<'b, 'c> {
+    builder: &'b Builder<'c>,
+    expr: ExprRef,
+}
+
+impl<'c> Builder<'c> {
+    /// Binds `expr` to this builder in order to use operators on it.
+    pub fn ex(&self, expr: ExprRef) -> BuilderExpr<'_, 'c> {
+        BuilderExpr {
+            builder: self,
+            expr,
+        }
+    }
+}
+
+impl BuilderExpr<'_, '_> {
+    pub fn expr(self) -> ExprRef {
+        self.expr
+    }
+
+    fn bind(self, expr: ExprRef) -> Self {
+        Self {
+            builder: self.builder,
+            expr,
+        }
+    }
+
+    pub fn equal(self, other: impl Into<ExprRef>) -> Self {
+        self.bind(self.builder.equal(self.expr, other.into()))
+    }
+
+    pub fn greater(self, other: impl Into<ExprRef>) -> Self {
+        self.bind(self.builder.greater(self.expr, other.into()))
+    }
+
+    pub fn greater_signed(self, other: impl Into<ExprRef>) -> Self {
+        self.bind(self.builder.greater_signed(self.expr, other.into()))
+    }
+
+    pub fn implies(self, other: impl Into<ExprRef>) -> Self {
+        self.bind(self.builder.implies(self.expr, other.into()))
+    }
+
+    /// Uses `self` as the condition to select between `tru` and `fals`.
+    pub fn ite(self, tru: impl Into<ExprRef>, fals: impl Into<ExprRef>) -> Self {
+        self.bind(self.builder.ite(self.expr, tru.into(), fals.into()))
+    }
+}
+
+impl From<BuilderExpr<'_, '_>> for ExprRef {
+    fn from(value: BuilderExpr<'_, '_>) -> Self {
+        value.expr
+    }
+}
+
+macro_rules! binary_op {
+    ($trait:ident, $method:ident, $builder_method:ident) => {
+        impl<R: Into<ExprRef>> $trait<R> for BuilderExpr<'_, '_> {
+            type Output = Self;
+
+            fn $method(self, rhs: R) -> Self {
+                self.bind(self.builder.$builder_method(self.expr, rhs.into()))
+            }
+        }
+    };
+}
+
+binary_op!(Add, add, add);
+binary_op!(Sub, sub, sub);
+binary_op!(Mul, mul, mul);
+binary_op!(BitAnd, bitand, and);
+binary_op!(BitOr, bitor, or);
+binary_op!(BitXor, bitxor, xor);
+binary_op!(Shl, shl, shift_left);
+binary_op!(Shr, shr, shift_right);
+
+impl Not for BuilderExpr<'_, '_> {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        self.bind(self.builder.not(self.expr))
+    }
+}
+
+impl Neg for BuilderExpr<'_, '_> {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        self.bind(self.builder.negate(self.expr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::expr::*;
+
+    #[test]
+    fn test_operators_match_builder() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("a", 8);
+        let b = ctx.bv_symbol("b", 8);
+        let with_ops = ctx.build(|c| {
+            let (a, b) = (c.ex(a), c.ex(b));
+            let sum = (a + b) * b - a;
+            (sum ^ (a << c.one(8)) | -(b >> a))
+                .equal(b)
+                .ite(a, b)
+                .into()
+        });
+        let with_calls = ctx.build(|c| {
+            let sum = c.sub(c.mul(c.add(a, b), b), a);
+            let shifted = c.shift_left(a, c.one(8));
+            let lhs = c.or(c.xor(sum, shifted), c.negate(c.shift_right(b, a)));
+            c.ite(c.equal(lhs, b), a, b)
+        });
+        assert_eq!(with_ops, with_calls);
+    }
+}
@@ -2,19 +2,26 @@
 // released under BSD 3-Clause License
 // author: Kevin Laeufer <laeufer@cornell.edu>
 
-use crate::expr::{Context, ExprRef, TypeCheck, WidthInt};
+use crate::expr::{Context, ExprError, ExprRef, TypeCheck, WidthInt};
 use baa::BitVecValue;
 use regex::{Captures, Match, Regex, RegexSet};
 use rustc_hash::FxHashMap;
 
+/// Parses and type checks `inp`. Panics if the input is invalid, see [`try_parse_expr`].
 pub fn parse_expr(ctx: &mut Context, inp: &str) -> ExprRef {
+    try_parse_expr(ctx, inp).unwrap_or_else(|e| panic!("{inp}: {e}"))
+}
+
+/// Parses and type checks `inp`.
+pub fn try_parse_expr(ctx: &mut Context, inp: &str) -> Result<ExprRef> {
     let mut parser = Parser::new(ctx, inp);
-    let expr = parser.parse_expr_all();
-    expr.type_check(ctx)
-        .unwrap_or_else(|_| panic!("{inp} failed to type-check"));
-    expr
+    let expr = parser.parse_expr_all()?;
+    expr.type_check(ctx)?;
+    Ok(expr)
 }
 
+type Result<T> = std::result::Result<T, ExprError>;
+
 struct Parser<'a> {
     ctx: &'a mut Context,
     inp: &'a str,
@@ -40,84 +47,98 @@ impl<'a> Parser<'a> {
         Self { ctx, inp, symbols }
     }
 
-    fn parse_expr_all(&mut self) -> ExprRef {
-        let e = self.parse_expr();
-        assert!(self.inp.is_empty(), "could not pars: {}", self.inp);
-        e
+    /// An error at the current position.
+    fn error(&self, msg: impl Into<String>) -> ExprError {
+        ExprError::Parse {
+            msg: msg.into(),
+            at: self.inp.to_string(),
+        }
+    }
+
+    fn parse_expr_all(&mut self) -> Result<ExprRef> {
+        let e = self.parse_expr()?;
+        if self.inp.is_empty() {
+            Ok(e)
+        } else {
+            Err(self.error("unexpected input after the expression"))
+        }
     }
 
-    fn parse_expr(&mut self) -> ExprRef {
-        let mut e = self
-            .try_parse_fun()
-            .or_else(|| self.try_pars_bv_lit())
-            .or_else(|| self.try_parse_symbol())
-            .unwrap_or_else(|| panic!("failed to parse: {}", self.inp));
+    fn parse_expr(&mut self) -> Result<ExprRef> {
+        let mut e = match self.try_parse_fun()? {
+            Some(e) => e,
+            None => match self.try_pars_bv_lit()? {
+                Some(e) => e,
+                None => self
+                    .try_parse_symbol()?
+                    .ok_or_else(|| self.error("expected an expression"))?,
+            },
+        };
 
         while let Some(c) = SLICE_REGEX.captures(self.inp) {
             if let Some(bit) = c.get(2) {
-                let bit = Self::width_int(bit);
+                let bit = self.width_int(bit)?;
                 self.consume_c(&c);
                 e = self.ctx.slice(e, bit, bit);
             } else if let (Some(msb), Some(lsb)) = (c.get(4), c.get(5)) {
-                let msb = Self::width_int(msb);
-                let lsb = Self::width_int(lsb);
+                let msb = self.width_int(msb)?;
+                let lsb = self.width_int(lsb)?;
                 self.consume_c(&c);
                 e = self.ctx.slice(e, msb, lsb);
             } else {
                 unreachable!("unexpected slice! @ {}", self.inp)
             }
         }
-        e
+        Ok(e)
     }
 
-    fn width_int(m: Match) -> WidthInt {
-        m.as_str().parse().unwrap()
+    fn width_int(&self, m: Match) -> Result<WidthInt> {
+        m.as_str()
+            .parse()
+            .map_err(|_| self.error(format!("`{}` is not a valid width", m.as_str())))
     }
 
-    fn try_parse_fun(&mut self) -> Option<ExprRef> {
+    fn try_parse_fun(&mut self) -> Result<Option<ExprRef>> {
         let fun = ANY_FUNCTION_REGEX.matches(self.inp);
         if let Some(fun_id) = fun.into_iter().next() {
             self.consume_r(&FUNCTION_REGEX[fun_id]);
-            let args = self.parse_args(fun_id);
-            Some(self.make_fun(fun_id, args))
+            let args = self.parse_args(fun_id)?;
+            Ok(Some(self.make_fun(fun_id, args)?))
         } else {
-            None
+            Ok(None)
         }
     }
 
-    fn try_pars_bv_lit(&mut self) -> Option<ExprRef> {
+    fn bv_lit(&mut self, m: &Captures, radix: u32) -> Result<ExprRef> {
+        let width = self.width_int(m.get(1).unwrap())?;
+        let value_str = m.get(2).unwrap().as_str();
+        let value = BitVecValue::from_str_radix(value_str, radix, width)
+            .map_err(|_| self.error(format!("`{value_str}` does not fit into {width} bits")))?;
+        self.consume_c(m);
+        Ok(self.ctx.bv_lit(&value))
+    }
+
+    fn try_pars_bv_lit(&mut self) -> Result<Option<ExprRef>> {
         if let Some(m) = BIN_LIT_REGEX.captures(self.inp) {
-            let width: WidthInt = m.get(1).unwrap().as_str().parse().unwrap();
-            let value_str = m.get(2).unwrap().as_str();
-            let value = BitVecValue::from_str_radix(value_str, 2, width).unwrap();
-            self.consume_c(&m);
-            Some(self.ctx.bv_lit(&value))
+            self.bv_lit(&m, 2).map(Some)
         } else if let Some(m) = DEC_LIT_REGEX.captures(self.inp) {
-            let width: WidthInt = m.get(1).unwrap().as_str().parse().unwrap();
-            let value_str = m.get(2).unwrap().as_str();
-            let value = BitVecValue::from_str_radix(value_str, 10, width).unwrap();
-            self.consume_c(&m);
-            Some(self.ctx.bv_lit(&value))
+            self.bv_lit(&m, 10).map(Some)
         } else if let Some(m) = HEX_LIT_REGEX.captures(self.inp) {
-            let width: WidthInt = m.get(1).unwrap().as_str().parse().unwrap();
-            let value_str = m.get(2).unwrap().as_str();
-            let value = BitVecValue::from_str_radix(value_str, 16, width).unwrap();
-            self.consume_c(&m);
-            Some(self.ctx.bv_lit(&value))
+            self.bv_lit(&m, 16).map(Some)
         } else if let Some(c) = TRUE_FALSE_REGEX.captures(self.inp) {
             self.consume_c(&c);
             if c.get(2).is_some() {
-                Some(self.ctx.get_true())
+                Ok(Some(self.ctx.get_true()))
             } else {
                 debug_assert!(c.get(3).is_some());
-                Some(self.ctx.get_false())
+                Ok(Some(self.ctx.get_false()))
             }
         } else {
-            None
+            Ok(None)
         }
     }
 
-    fn try_parse_symbol(&mut self) -> Option<ExprRef> {
+    fn try_parse_symbol(&mut self) -> Result<Option<ExprRef>> {
         if let Some(c) = SYMBOL_REGEX.captures(self.inp) {
             let escaped_name = c.get(3).map(|m| {
                 let len = m.as_str().len();
@@ -127,37 +148,38 @@ impl<'a> Parser<'a> {
 
             // do we have an explicit bv type?
             if let Some(width) = c.get(5) {
-                let width: WidthInt = width.as_str().parse().unwrap();
+                let width = self.width_int(width)?;
                 let new_sym = self.ctx.bv_symbol(name, width);
                 // compare width
                 if let Some(other) = self.symbols.get(name) {
                     let other_width = self.ctx[*other].get_bv_type(self.ctx).unwrap();
-                    assert_eq!(
-                        width, other_width,
-                        "Two symbols with same name {name} have different widths!"
-                    );
+                    if width != other_width {
+                        return Err(self.error(format!(
+                            "symbol `{name}` is used with width {width} and {other_width}"
+                        )));
+                    }
                 } else {
                     // remember width
                     self.symbols.insert(name.to_string(), new_sym);
                 }
                 self.consume_c(&c);
-                Some(new_sym)
+                Ok(Some(new_sym))
             } else {
                 let other = *self
                     .symbols
                     .get(name)
-                    .unwrap_or_else(|| panic!("symbol of unknown type: `{name}` @ {}", self.inp));
+                    .ok_or_else(|| self.error(format!("symbol of unknown type: `{name}`")))?;
                 let width = self.ctx[other].get_bv_type(self.ctx).unwrap();
                 self.consume_c(&c);
-                Some(self.ctx.bv_symbol(name, width))
+                Ok(Some(self.ctx.bv_symbol(name, width)))
             }
         } else {
-            None
+            Ok(None)
         }
     }
 
-    fn make_fun(&mut self, fun_id: usize, args: Vec<Arg>) -> ExprRef {
-        match (fun_id, args.as_slice()) {
+    fn make_fun(&mut self, fun_id: usize, args: Vec<Arg>) -> Result<ExprRef> {
+        let e = match (fun_id, args.as_slice()) {
             (0, [Arg::E(e), Arg::C(by)]) => self.ctx.zero_extend(*e, *by),
             (1, [Arg::E(e), Arg::C(by)]) => self.ctx.sign_extend(*e, *by),
             (2, [Arg::E(e)]) => self.ctx.not(*e),
@@ -184,20 +206,29 @@ impl<'a> Parser<'a> {
             (23, [Arg::E(a), Arg::E(b)]) => self.ctx.remainder(*a, *b),
             (24, [Arg::E(a), Arg::E(b)]) => self.ctx.sub(*a, *b),
             (25, [Arg::E(a), Arg::E(b), Arg::E(c)]) => self.ctx.ite(*a, *b, *c),
-            _ => todo!("implement: {}({:?})", FUNCTIONS[fun_id], args),
-        }
+            _ => {
+                return Err(self.error(format!(
+                    "invalid arguments for {}: {args:?}",
+                    FUNCTIONS[fun_id]
+                )))
+            }
+        };
+        Ok(e)
     }
 
-    fn parse_args(&mut self, fun_id: usize) -> Vec<Arg> {
+    fn parse_args(&mut self, fun_id: usize) -> Result<Vec<Arg>> {
         let mut args = vec![];
         let arg_types = FUNCTION_ARGS[fun_id];
         for (ii, at) in arg_types.iter().enumerate() {
             match at {
                 ArgTpe::E => {
-                    args.push(Arg::E(self.parse_expr()));
+                    args.push(Arg::E(self.parse_expr()?));
                 }
                 ArgTpe::C => {
-                    args.push(Arg::C(self.try_parse_width_int().unwrap()));
+                    let by = self
+                        .try_parse_width_int()
+                        .ok_or_else(|| self.error("expected a width"))?;
+                    args.push(Arg::C(by));
                 }
             }
             let is_last = ii + 1 == arg_types.len();
@@ -205,26 +236,26 @@ impl<'a> Parser<'a> {
                 if let Some(m) = CLOSE_REGEX.find(self.inp) {
                     self.consume_m(&m);
                 } else {
-                    panic!(
-                        "failed to find end of function {} @ {}",
-                        FUNCTIONS[fun_id], self.inp
-                    );
+                    return Err(self.error(format!(
+                        "failed to find end of function {}",
+                        FUNCTIONS[fun_id]
+                    )));
                 }
             } else if let Some(m) = COMMA_REGEX.find(self.inp) {
                 self.consume_m(&m);
-            } else if !is_last && CLOSE_REGEX.is_match(self.inp) {
-                panic!(
-                    "Expected another argument for {}({:?},..) @ `{}`",
-                    FUNCTIONS[fun_id], args, self.inp
-                );
+            } else if CLOSE_REGEX.is_match(self.inp) {
+                return Err(self.error(format!(
+                    "expected another argument for {}({:?},..)",
+                    FUNCTIONS[fun_id], args
+                )));
             } else {
-                panic!(
-                    "failed to find end of argument in function {}, expected `,` or `)` @ `{}`",
-                    FUNCTIONS[fun_id], self.inp
-                );
+                return Err(self.error(format!(
+                    "failed to find end of argument in function {}, expected `,` or `)`",
+                    FUNCTIONS[fun_id]
+                )));
             }
         }
-        args
+        Ok(args)
     }
 
     fn try_parse_width_int(&mut self) -> Option<WidthInt> {
@@ -356,6 +387,27 @@ mod tests {
         assert!(SLICE_REGEX.captures(", c:bv<5>[4:3]").is_none())
     }
 
+    #[test]
+    fn test_parse_errors() {
+        let mut ctx = Context::default();
+        assert!(matches!(
+            try_parse_expr(&mut ctx, "and(a : bv<1>"),
+            Err(ExprError::Parse { .. })
+        ));
+        assert!(matches!(
+            try_parse_expr(&mut ctx, "and(a : bv<1>, a : bv<2>)"),
+            Err(ExprError::Parse { .. })
+        ));
+        assert!(matches!(
+            try_parse_expr(&mut ctx, "zext(b, 3)"),
+            Err(ExprError::Parse { .. })
+        ));
+        assert!(matches!(
+            try_parse_expr(&mut ctx, "and(a : bv<1>, b : bv<2>)"),
+            Err(ExprError::TypeCheck(_))
+        ));
+    }
+
     #[test]
     fn simple_parse() {
         let mut ctx = Context::default();
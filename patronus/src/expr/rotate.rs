@@ -0,0 +1,220 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Rotations
+//! Our IR follows SMTLib which only knows rotations by a constant amount. Instead of adding
+//! new nodes, rotations are expanded into core operations when they are created. A rotation
+//! by a constant turns into a concatenation of two slices, while a rotation by a dynamic amount
+//! turns into a chain of `ite`s that each rotate by a constant, one for every bit of the amount.
+//! The chain takes the amount modulo the width without requiring a remainder operation. Thus,
+//! the evaluator and the SMT and btor2 backends support rotations without any changes.
+//! [`Context::get_rotation`] recognizes the expanded form, e.g., in order to treat it as a
+//! single operation in the e-graph.
+
+use super::{Context, Expr, ExprRef, TypeCheck, WidthInt};
+use baa::BitVecOps;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rotation {
+    /// rotates left by a dynamic amount
+    Left(ExprRef),
+    /// rotates right by a dynamic amount
+    Right(ExprRef),
+    /// rotates left by a constant amount that is smaller than the width
+    LeftBy(WidthInt),
+}
+
+impl Context {
+    pub fn rotate_left(&mut self, e: ExprRef, amount: ExprRef) -> ExprRef {
+        self.rotate(e, amount, true)
+    }
+
+    pub fn rotate_right(&mut self, e: ExprRef, amount: ExprRef) -> ExprRef {
+        self.rotate(e, amount, false)
+    }
+
+    pub fn rotate_left_by(&mut self, e: ExprRef, amount: u64) -> ExprRef {
+        let width = e.get_bv_type(self).unwrap();
+        let amount = (amount % width as u64) as WidthInt;
+        if amount == 0 {
+            e
+        } else {
+            let lsbs = self.slice(e, width - amount - 1, 0);
+            let msbs = self.slice(e, width - 1, width - amount);
+            self.concat(lsbs, msbs)
+        }
+    }
+
+    pub fn rotate_right_by(&mut self, e: ExprRef, amount: u64) -> ExprRef {
+        let width = e.get_bv_type(self).unwrap() as u64;
+        self.rotate_left_by(e, width - amount % width)
+    }
+
+    fn rotate(&mut self, e: ExprRef, amount: ExprRef, left: bool) -> ExprRef {
+        let width = e.get_bv_type(self).unwrap();
+        if let Expr::BVLiteral(value) = self[amount] {
+            if let Some(value) = value.get(self).to_u64() {
+                return if left {
+                    self.rotate_left_by(e, value)
+                } else {
+                    self.rotate_right_by(e, value)
+                };
+            }
+        }
+        if width == 1 {
+            return e;
+        }
+        // bit `ii` of the amount rotates by `2^ii mod width`
+        let mut result = e;
+        for (ii, by) in stage_amounts(amount.get_bv_type(self).unwrap(), width).enumerate() {
+            let rotated = if left {
+                self.rotate_left_by(result, by)
+            } else {
+                self.rotate_right_by(result, by)
+            };
+            let bit = self.slice(amount, ii as WidthInt, ii as WidthInt);
+            result = self.ite(bit, rotated, result);
+        }
+        result
+    }
+
+    /// Returns the rotated expression and the kind of rotation if `expr` is the expansion
+    /// of a rotation.
+    pub fn get_rotation(&self, expr: &Expr) -> Option<(ExprRef, Rotation)> {
+        match *expr {
+            Expr::BVConcat(lsbs, msbs, width) => {
+                let Expr::BVSlice { e, hi, lo: 0 } = self[lsbs] else {
+                    return None;
+                };
+                let Expr::BVSlice {
+                    e: e_msbs,
+                    hi: hi_msbs,
+                    lo,
+                } = self[msbs]
+                else {
+                    return None;
+                };
+                let matches = e == e_msbs
+                    && e.get_bv_type(self) == Some(width)
+                    && hi_msbs == width - 1
+                    && lo == hi + 1;
+                matches.then_some((e, Rotation::LeftBy(width - lo)))
+            }
+            Expr::BVIte { cond, .. } => {
+                let Expr::BVSlice { e: amount, hi, lo } = self[cond] else {
+                    return None;
+                };
+                if hi != lo || amount.get_bv_type(self) != Some(hi + 1) {
+                    return None;
+                }
+                if let Some(e) = self.rotation_chain(expr, amount, true) {
+                    Some((e, Rotation::Left(amount)))
+                } else {
+                    let e = self.rotation_chain(expr, amount, false)?;
+                    Some((e, Rotation::Right(amount)))
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Walks the `ite` chain of a dynamic rotation from the most significant bit of the amount
+    /// down and returns the expression that is rotated.
+    fn rotation_chain(&self, expr: &Expr, amount: ExprRef, left: bool) -> Option<ExprRef> {
+        let Expr::BVIte { tru, .. } = *expr else {
+            return None;
+        };
+        let width = tru.get_bv_type(self)?;
+        if width == 1 {
+            return None;
+        }
+        let stages: Vec<WidthInt> = stage_amounts(amount.get_bv_type(self)?, width).collect();
+        let mut current = *expr;
+        for (ii, by) in stages.into_iter().enumerate().rev() {
+            let Expr::BVIte { cond, tru, fals } = current else {
+                return None;
+            };
+            let is_bit = matches!(self[cond], Expr::BVSlice { e, hi, lo } if e == amount && hi == ii as WidthInt && lo == hi);
+            let left_by = if left || by == 0 { by } else { width - by };
+            let is_rotation = if left_by == 0 {
+                tru == fals
+            } else {
+                self.get_rotation(&self[tru]) == Some((fals, Rotation::LeftBy(left_by)))
+            };
+            if !is_bit || !is_rotation {
+                return None;
+            }
+            if ii == 0 {
+                return Some(fals);
+            }
+            current = self[fals];
+        }
+        None
+    }
+}
+
+/// The constant rotation for every bit of an amount with `amount_width` bits.
+fn stage_amounts(amount_width: WidthInt, width: WidthInt) -> impl Iterator<Item = WidthInt> {
+    std::iter::successors(Some(1 % width), move |by| Some(by * 2 % width))
+        .take(amount_width as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::eval_bv_expr;
+    use baa::BitVecValue;
+
+    #[test]
+    fn test_rotations() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("a", 8);
+        let b = ctx.bv_symbol("b", 8);
+        let c = ctx.bv_symbol("c", 3);
+        let a_value = BitVecValue::from_u64(0b1001_0110, 8);
+        let eval = |ctx: &Context, e: ExprRef, amount: ExprRef, value: u64| {
+            let width = amount.get_bv_type(ctx).unwrap();
+            let inputs = [
+                (a, a_value.clone()),
+                (amount, BitVecValue::from_u64(value, width)),
+            ];
+            eval_bv_expr(ctx, inputs.as_slice(), e).to_u64().unwrap()
+        };
+
+        let rol = ctx.rotate_left(a, b);
+        let ror = ctx.rotate_right(a, b);
+        assert_eq!(ctx.get_rotation(&ctx[rol]), Some((a, Rotation::Left(b))));
+        assert_eq!(ctx.get_rotation(&ctx[ror]), Some((a, Rotation::Right(b))));
+        for (amount, left, right) in [
+            (0, 0b1001_0110, 0b1001_0110),
+            (3, 0b1011_0100, 0b1101_0010),
+            (8, 0b1001_0110, 0b1001_0110),
+            (11, 0b1011_0100, 0b1101_0010),
+        ] {
+            assert_eq!(eval(&ctx, rol, b, amount), left, "rol {amount}");
+            assert_eq!(eval(&ctx, ror, b, amount), right, "ror {amount}");
+        }
+
+        // a narrower amount is extended
+        let rol_narrow = ctx.rotate_left(a, c);
+        assert_eq!(
+            ctx.get_rotation(&ctx[rol_narrow]),
+            Some((a, Rotation::Left(c)))
+        );
+        assert_eq!(eval(&ctx, rol_narrow, c, 3), 0b1011_0100);
+
+        // constant amounts turn into slices
+        let three = ctx.bit_vec_val(3, 8);
+        let rol_3 = ctx.rotate_left(a, three);
+        assert_eq!(rol_3, ctx.rotate_right_by(a, 5));
+        assert_eq!(rol_3, ctx.rotate_left_by(a, 11));
+        assert_eq!(
+            ctx.get_rotation(&ctx[rol_3]),
+            Some((a, Rotation::LeftBy(3)))
+        );
+        assert_eq!(eval(&ctx, rol_3, b, 0), 0b1011_0100);
+        assert_eq!(ctx.rotate_left_by(a, 8), a);
+        assert_eq!(ctx.rotate_right_by(a, 0), a);
+    }
+}
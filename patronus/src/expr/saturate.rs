@@ -0,0 +1,278 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Saturating and Rounding Arithmetic
+//! DSP datapaths clamp results instead of wrapping around and drop fractional bits with a
+//! well-defined rounding mode. Just like [rotations](super::Rotation), these operations are
+//! expanded into core operations when they are created, so that all backends support them.
+//!
+//! A saturating addition or subtraction is computed with one extra bit. The final
+//! `ite(overflow, limit, sum[w-1:0])` is recognized by [`Context::get_saturating`], e.g., in
+//! order to reason about it in the e-graph. Rounding only consists of slices and an addition
+//! and is not recognized.
+
+use super::{Context, Expr, ExprRef, TypeCheck, WidthInt};
+use baa::{BitVecMutOps, BitVecOps, BitVecValue, BitVecValueRef};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SaturatingOp {
+    Add,
+    Sub,
+}
+
+/// A saturating operation on two operands of the same width.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Saturating {
+    pub op: SaturatingOp,
+    pub signed: bool,
+    pub a: ExprRef,
+    pub b: ExprRef,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Rounding {
+    /// drops the fractional bits, i.e., rounds towards negative infinity
+    #[default]
+    Truncate,
+    /// rounds to the nearest value, ties are rounded to the even value
+    NearestEven,
+}
+
+impl Context {
+    pub fn saturating_add(&mut self, a: ExprRef, b: ExprRef, signed: bool) -> ExprRef {
+        self.saturating(SaturatingOp::Add, a, b, signed)
+    }
+
+    pub fn saturating_sub(&mut self, a: ExprRef, b: ExprRef, signed: bool) -> ExprRef {
+        self.saturating(SaturatingOp::Sub, a, b, signed)
+    }
+
+    fn saturating(&mut self, op: SaturatingOp, a: ExprRef, b: ExprRef, signed: bool) -> ExprRef {
+        let width = a.get_bv_type(self).unwrap();
+        debug_assert_eq!(width, b.get_bv_type(self).unwrap());
+        let a_ext = self.extend(a, 1, signed);
+        let b_ext = self.extend(b, 1, signed);
+        let sum = match op {
+            SaturatingOp::Add => self.add(a_ext, b_ext),
+            SaturatingOp::Sub => self.sub(a_ext, b_ext),
+        };
+        let msb = self.slice(sum, width, width);
+        let result = self.slice(sum, width - 1, 0);
+        if signed {
+            // the extra bit differs from the sign bit iff the result does not fit
+            let sign = self.slice(sum, width - 1, width - 1);
+            let overflow = self.xor(msb, sign);
+            let limit = self.signed_limit(msb, width);
+            self.ite(overflow, limit, result)
+        } else {
+            // the extra bit is the carry of an addition and the borrow of a subtraction
+            let limit = match op {
+                SaturatingOp::Add => self.ones(width),
+                SaturatingOp::Sub => self.zero(width),
+            };
+            self.ite(msb, limit, result)
+        }
+    }
+
+    /// The smallest signed value if `negative` is true, the largest otherwise.
+    fn signed_limit(&mut self, negative: ExprRef, width: WidthInt) -> ExprRef {
+        let mut min = BitVecValue::zero(width);
+        min.set_bit(width - 1);
+        let mut max = BitVecValue::ones(width);
+        max.clear_bit(width - 1);
+        let (min, max) = (self.bv_lit(&min), self.bv_lit(&max));
+        self.ite(negative, min, max)
+    }
+
+    /// Narrows `e` to `width` bits. Values that do not fit are clamped to the smallest or
+    /// largest value. Extends `e` if it is narrower than `width`.
+    pub fn saturate(&mut self, e: ExprRef, width: WidthInt, signed: bool) -> ExprRef {
+        let e_width = e.get_bv_type(self).unwrap();
+        if width >= e_width {
+            return self.extend(e, width - e_width, signed);
+        }
+        let result = self.slice(e, width - 1, 0);
+        if signed {
+            // all dropped bits need to be equal to the new sign bit
+            let upper = self.slice(e, e_width - 1, width - 1);
+            let (zeros, ones) = (
+                self.zero(e_width - width + 1),
+                self.ones(e_width - width + 1),
+            );
+            let is_positive = self.equal(upper, zeros);
+            let is_negative = self.equal(upper, ones);
+            let fits = self.or(is_positive, is_negative);
+            let sign = self.slice(e, e_width - 1, e_width - 1);
+            let limit = self.signed_limit(sign, width);
+            self.ite(fits, result, limit)
+        } else {
+            let upper = self.slice(e, e_width - 1, width);
+            let zeros = self.zero(e_width - width);
+            let fits = self.equal(upper, zeros);
+            let limit = self.ones(width);
+            self.ite(fits, result, limit)
+        }
+    }
+
+    /// Removes the `frac_bits` least significant bits of a fixed-point value. Works for signed
+    /// and unsigned values. Rounding up the largest value wraps around, use [`Context::saturate`]
+    /// on a wider value if that is a concern.
+    pub fn round(&mut self, e: ExprRef, frac_bits: WidthInt, mode: Rounding) -> ExprRef {
+        let width = e.get_bv_type(self).unwrap();
+        assert!(
+            frac_bits < width,
+            "cannot remove {frac_bits} bits from a {width}-bit value"
+        );
+        if frac_bits == 0 {
+            return e;
+        }
+        let truncated = self.slice(e, width - 1, frac_bits);
+        match mode {
+            Rounding::Truncate => truncated,
+            Rounding::NearestEven => {
+                let guard = self.slice(e, frac_bits - 1, frac_bits - 1);
+                let lsb = self.slice(e, frac_bits, frac_bits);
+                // ties are only rounded up if the result would be odd otherwise
+                let sticky_or_odd = if frac_bits > 1 {
+                    let rest = self.slice(e, frac_bits - 2, 0);
+                    let zeros = self.zero(frac_bits - 1);
+                    let is_zero = self.equal(rest, zeros);
+                    let sticky = self.not(is_zero);
+                    self.or(sticky, lsb)
+                } else {
+                    lsb
+                };
+                let round_up = self.and(guard, sticky_or_odd);
+                let increment = self.zero_extend(round_up, width - frac_bits - 1);
+                self.add(truncated, increment)
+            }
+        }
+    }
+
+    /// Returns the operation if `expr` is the expansion of a saturating addition or
+    /// subtraction.
+    pub fn get_saturating(&self, expr: &Expr) -> Option<Saturating> {
+        let Expr::BVIte { cond, tru, fals } = *expr else {
+            return None;
+        };
+        let Expr::BVSlice { e: sum, hi, lo: 0 } = self[fals] else {
+            return None;
+        };
+        let width = hi + 1;
+        let (op, a_ext, b_ext) = match self[sum] {
+            Expr::BVAdd(a, b, w) if w == width + 1 => (SaturatingOp::Add, a, b),
+            Expr::BVSub(a, b, w) if w == width + 1 => (SaturatingOp::Sub, a, b),
+            _ => return None,
+        };
+        let (a, b, signed) = match (self[a_ext], self[b_ext]) {
+            (Expr::BVZeroExt { e: a, by: 1, .. }, Expr::BVZeroExt { e: b, by: 1, .. }) => {
+                (a, b, false)
+            }
+            (Expr::BVSignExt { e: a, by: 1, .. }, Expr::BVSignExt { e: b, by: 1, .. }) => {
+                (a, b, true)
+            }
+            _ => return None,
+        };
+        let is_bit_of_sum = |bit_expr: ExprRef, bit| matches!(self[bit_expr], Expr::BVSlice { e, hi, lo } if e == sum && hi == bit && lo == bit);
+        let matches = if signed {
+            matches!(self[cond], Expr::BVXor(msb, sign, _) if is_bit_of_sum(msb, width) && is_bit_of_sum(sign, width - 1))
+                && matches!(self[tru], Expr::BVIte { cond, .. } if is_bit_of_sum(cond, width))
+        } else {
+            let is_limit = |value: BitVecValueRef| match op {
+                SaturatingOp::Add => value.is_equal(&BitVecValue::ones(width)),
+                SaturatingOp::Sub => value.is_zero(),
+            };
+            is_bit_of_sum(cond, width)
+                && matches!(self[tru], Expr::BVLiteral(value) if is_limit(value.get(self)))
+        };
+        matches.then_some(Saturating { op, signed, a, b })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::eval_bv_expr;
+
+    #[test]
+    fn test_saturating_and_rounding() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("a", 8);
+        let b = ctx.bv_symbol("b", 8);
+        let eval = |ctx: &Context, e: ExprRef, a_value: u64, b_value: u64| {
+            let inputs = [
+                (a, BitVecValue::from_u64(a_value, 8)),
+                (b, BitVecValue::from_u64(b_value, 8)),
+            ];
+            eval_bv_expr(ctx, inputs.as_slice(), e).to_u64().unwrap()
+        };
+
+        let add = ctx.saturating_add(a, b, false);
+        let sub = ctx.saturating_sub(a, b, false);
+        let add_signed = ctx.saturating_add(a, b, true);
+        let sub_signed = ctx.saturating_sub(a, b, true);
+        assert_eq!(eval(&ctx, add, 100, 27), 127);
+        assert_eq!(eval(&ctx, add, 200, 100), 255);
+        assert_eq!(eval(&ctx, sub, 100, 27), 73);
+        assert_eq!(eval(&ctx, sub, 27, 100), 0);
+        // 100 + 100 > 127
+        assert_eq!(eval(&ctx, add_signed, 100, 100), 0x7f);
+        // -100 + -100 < -128
+        assert_eq!(eval(&ctx, add_signed, 0x9c, 0x9c), 0x80);
+        // -1 + 2 = 1
+        assert_eq!(eval(&ctx, add_signed, 0xff, 2), 1);
+        // -100 - 100 < -128
+        assert_eq!(eval(&ctx, sub_signed, 0x9c, 100), 0x80);
+        // 100 - -100 > 127
+        assert_eq!(eval(&ctx, sub_signed, 100, 0x9c), 0x7f);
+
+        let expected = |op, signed| Some(Saturating { op, signed, a, b });
+        assert_eq!(
+            ctx.get_saturating(&ctx[add]),
+            expected(SaturatingOp::Add, false)
+        );
+        assert_eq!(
+            ctx.get_saturating(&ctx[sub]),
+            expected(SaturatingOp::Sub, false)
+        );
+        assert_eq!(
+            ctx.get_saturating(&ctx[add_signed]),
+            expected(SaturatingOp::Add, true)
+        );
+        assert_eq!(
+            ctx.get_saturating(&ctx[sub_signed]),
+            expected(SaturatingOp::Sub, true)
+        );
+        let ite = ctx.build(|c| c.ite(c.equal(a, b), a, b));
+        assert_eq!(ctx.get_saturating(&ctx[ite]), None);
+
+        // narrowing
+        let narrow = ctx.saturate(a, 4, false);
+        let narrow_signed = ctx.saturate(a, 4, true);
+        assert_eq!(eval(&ctx, narrow, 9, 0), 9);
+        assert_eq!(eval(&ctx, narrow, 17, 0), 15);
+        assert_eq!(eval(&ctx, narrow_signed, 7, 0), 7);
+        assert_eq!(eval(&ctx, narrow_signed, 9, 0), 7);
+        // -3 fits, -9 does not
+        assert_eq!(eval(&ctx, narrow_signed, 0xfd, 0), 0xd);
+        assert_eq!(eval(&ctx, narrow_signed, 0xf7, 0), 0x8);
+
+        // rounding with two fractional bits
+        let truncated = ctx.round(a, 2, Rounding::Truncate);
+        let rounded = ctx.round(a, 2, Rounding::NearestEven);
+        for (value, truncate, nearest_even) in [
+            // 2.25, 2.5, 2.75, 3.5
+            (0b1001, 2, 2),
+            (0b1010, 2, 2),
+            (0b1011, 2, 3),
+            (0b1110, 3, 4),
+            // -1.5 rounds to -2
+            (0b1111_1010, 0b11_1110, 0b11_1110),
+        ] {
+            assert_eq!(eval(&ctx, truncated, value, 0), truncate, "{value:b}");
+            assert_eq!(eval(&ctx, rounded, value, 0), nearest_even, "{value:b}");
+        }
+        assert_eq!(ctx.round(a, 0, Rounding::NearestEven), a);
+    }
+}
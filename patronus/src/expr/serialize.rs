@@ -5,7 +5,7 @@
 
 use super::Type;
 use super::{Context, Expr, ExprRef};
-use baa::BitVecOps;
+use baa::{BitVecOps, SparseArrayValue};
 use std::io::Write;
 
 pub trait SerializableIrNode {
@@ -40,13 +40,7 @@ where
 {
     match expr {
         Expr::BVSymbol { name, .. } => write!(writer, "{}", ctx[*name]),
-        Expr::BVLiteral(value) => {
-            if value.width() <= 8 {
-                write!(writer, "{}'b{}", value.width(), value.get(ctx).to_bit_str())
-            } else {
-                write!(writer, "{}'x{}", value.width(), value.get(ctx).to_hex_str())
-            }
-        }
+        Expr::BVLiteral(value) => serialize_bv_value(&value.get(ctx), writer),
         Expr::BVZeroExt { e, by, .. } => {
             write!(writer, "zext(")?;
             if (serialize_child)(e, writer)? {
@@ -344,6 +338,18 @@ where
             write!(writer, ")")
         }
         Expr::ArraySymbol { name, .. } => write!(writer, "{}", ctx[*name]),
+        Expr::ArrayLiteral { value, .. } => {
+            let sparse: SparseArrayValue = value.get(ctx).into();
+            write!(writer, "array(default=")?;
+            serialize_bv_value(&sparse.default(), writer)?;
+            for (index, data) in sparse.non_default_entries() {
+                write!(writer, ", ")?;
+                serialize_bv_value(&index, writer)?;
+                write!(writer, "=")?;
+                serialize_bv_value(&data, writer)?;
+            }
+            write!(writer, ")")
+        }
         Expr::ArrayConstant { e, index_width, .. } => {
             write!(writer, "([")?;
             if (serialize_child)(e, writer)? {
@@ -376,6 +382,23 @@ where
             }
             write!(writer, "]")
         }
+        Expr::BVFunction { name, .. } => write!(writer, "{}", ctx[*name]),
+        Expr::BVApply { func, arg, .. } => {
+            // a partial application can only occur when serializing a single node
+            let (func, args) = ctx
+                .get_application_args(*func, *arg)
+                .expect("only uninterpreted functions can be applied");
+            write!(writer, "{}(", ctx.get_symbol_name(func).unwrap())?;
+            for (ii, arg) in args.iter().enumerate() {
+                if ii > 0 {
+                    write!(writer, ", ")?;
+                }
+                if (serialize_child)(arg, writer)? {
+                    serialize_expr_ref(arg, ctx, writer, serialize_child)?;
+                }
+            }
+            write!(writer, ")")
+        }
         Expr::ArrayIte { cond, tru, fals } => {
             write!(writer, "ite(")?;
             if (serialize_child)(cond, writer)? {
@@ -394,6 +417,14 @@ where
     }
 }
 
+fn serialize_bv_value(value: &impl BitVecOps, writer: &mut impl Write) -> std::io::Result<()> {
+    if value.width() <= 8 {
+        write!(writer, "{}'b{}", value.width(), value.to_bit_str())
+    } else {
+        write!(writer, "{}'x{}", value.width(), value.to_hex_str())
+    }
+}
+
 /// De-reference and serialize.
 #[inline]
 pub(crate) fn serialize_expr_ref<F, W>(
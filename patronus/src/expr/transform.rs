@@ -86,6 +86,7 @@ pub(crate) fn do_transform_expr<T: ExprMap<Option<ExprRef>>>(
         };
         // remember the transformed version
         transformed[expr_ref] = Some(new_expr_ref);
+        ctx.copy_attributes(expr_ref, new_expr_ref);
 
         // in fixed point mode, we might not be done yet
         let is_at_fixed_point = expr_ref == new_expr_ref;
@@ -100,7 +101,13 @@ pub(crate) fn do_transform_expr<T: ExprMap<Option<ExprRef>>>(
 }
 
 fn update_expr_children(ctx: &mut Context, expr_ref: ExprRef, children: &[ExprRef]) -> ExprRef {
-    let new_expr = match (&ctx[expr_ref], children) {
+    let new_expr = expr_with_children(&ctx[expr_ref], children);
+    ctx.add_expr(new_expr)
+}
+
+/// Re-creates `expr` with different children. Only valid for expressions with children.
+pub(crate) fn expr_with_children(expr: &Expr, children: &[ExprRef]) -> Expr {
+    match (expr, children) {
         (Expr::BVSymbol { .. }, _) => panic!("No children, should never get here."),
         (Expr::BVLiteral { .. }, _) => panic!("No children, should never get here."),
         (Expr::BVZeroExt { by, width, .. }, [e]) => Expr::BVZeroExt {
@@ -152,6 +159,7 @@ fn update_expr_children(ctx: &mut Context, expr_ref: ExprRef, children: &[ExprRe
             fals: *fals,
         },
         (Expr::ArraySymbol { .. }, _) => panic!("No children, should never get here."),
+        (Expr::ArrayLiteral { .. }, _) => panic!("No children, should never get here."),
         (
             Expr::ArrayConstant {
                 index_width,
@@ -175,9 +183,64 @@ fn update_expr_children(ctx: &mut Context, expr_ref: ExprRef, children: &[ExprRe
             tru: *tru,
             fals: *fals,
         },
-        (other, _) => {
-            todo!("implement code to re-create expression `{other:?}` with updated children")
+        (Expr::BVApply { width, .. }, [func, arg]) => Expr::BVApply {
+            func: *func,
+            arg: *arg,
+            width: *width,
+        },
+        // every expression with children is covered above
+        (other, children) => {
+            unreachable!("`{other:?}` cannot have {} children", children.len())
         }
-    };
-    ctx.add_expr(new_expr)
+    }
+}
+
+/// Re-creates `e` in the context `dst`. Expressions that already have an entry in `copied`
+/// are not copied again, which can be used to substitute sub-expressions.
+pub fn copy_expr(
+    src: &Context,
+    dst: &mut Context,
+    copied: &mut impl ExprMap<Option<ExprRef>>,
+    e: ExprRef,
+) -> ExprRef {
+    let mut todo = vec![e];
+    let mut children = Vec::with_capacity(4);
+    while let Some(&expr_ref) = todo.last() {
+        if copied[expr_ref].is_some() {
+            todo.pop();
+            continue;
+        }
+        children.clear();
+        let mut all_copied = true;
+        src[expr_ref].for_each_child(|c| match copied[*c] {
+            Some(new_child) => children.push(new_child),
+            None => {
+                all_copied = false;
+                todo.push(*c);
+            }
+        });
+        if !all_copied {
+            continue;
+        }
+        todo.pop();
+        let new_expr_ref = match &src[expr_ref] {
+            Expr::BVSymbol { name, width } => dst.bv_symbol(&src[*name], *width),
+            Expr::ArraySymbol {
+                name,
+                index_width,
+                data_width,
+            } => dst.array_symbol(&src[*name], *index_width, *data_width),
+            Expr::BVFunction {
+                name,
+                num_args,
+                width,
+            } => dst.function(&src[*name], *num_args, *width),
+            Expr::BVLiteral(value) => dst.bv_lit(value.get(src)),
+            Expr::ArrayLiteral { value, .. } => dst.array_lit(value.get(src)),
+            other => dst.add_expr(expr_with_children(other, &children)),
+        };
+        dst.import_attributes(src, expr_ref, new_expr_ref);
+        copied[expr_ref] = Some(new_expr_ref);
+    }
+    copied[e].unwrap()
 }
@@ -20,6 +20,33 @@ impl TypeCheckError {
     }
 }
 
+/// Errors that library users may want to recover from when working with expressions.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ExprError {
+    #[error("no value found for symbol `{0}`")]
+    MissingSymbol(String),
+    #[error("value assigned to `{name}` has type {actual:?}, expected {expected:?}")]
+    TypeMismatch {
+        name: String,
+        expected: Type,
+        actual: Type,
+    },
+    #[error("type check failed: {0}")]
+    TypeCheck(String),
+    #[error("`{0}` cannot be evaluated")]
+    Unsupported(String),
+    #[error("uninterpreted function `{0}` cannot be evaluated")]
+    UninterpretedFunction(String),
+    #[error("{msg} @ `{at}`")]
+    Parse { msg: String, at: String },
+}
+
+impl From<TypeCheckError> for ExprError {
+    fn from(value: TypeCheckError) -> Self {
+        ExprError::TypeCheck(value.msg)
+    }
+}
+
 impl Type {
     fn expect_bv(&self, op: &str) -> Result<WidthInt, TypeCheckError> {
         match self {
@@ -101,6 +128,7 @@ impl Expr {
         matches!(
             self,
             Expr::ArraySymbol { .. }
+                | Expr::ArrayLiteral { .. }
                 | Expr::ArrayConstant { .. }
                 | Expr::ArrayIte { .. }
                 | Expr::ArrayStore { .. }
@@ -264,6 +292,14 @@ impl TypeCheck for Expr {
                 index_width,
                 data_width,
             })),
+            Expr::ArrayLiteral {
+                index_width,
+                data_width,
+                ..
+            } => Ok(Type::Array(ArrayType {
+                index_width,
+                data_width,
+            })),
             Expr::ArrayConstant {
                 e,
                 index_width,
@@ -294,6 +330,37 @@ impl TypeCheck for Expr {
                 cond.get_type(ctx).expect_bv_of(1, "ite condition")?;
                 expect_same_size_arrays(ctx, "both ite branches", tru, fals)
             }
+            Expr::BVFunction { width, .. } => Ok(Type::BV(width)),
+            Expr::BVApply { func, width, .. } => {
+                // walk down to the function in order to count the number of arguments
+                let mut applied = 1;
+                let mut f = func;
+                loop {
+                    match ctx[f] {
+                        Expr::BVApply { func, .. } => {
+                            applied += 1;
+                            f = func;
+                        }
+                        Expr::BVFunction { num_args, .. } if applied > num_args => {
+                            return Err(TypeCheckError {
+                                msg: format!(
+                                    "{} expects {num_args} arguments, but is applied to {applied}",
+                                    ctx[f].get_symbol_name(ctx).unwrap()
+                                ),
+                            });
+                        }
+                        Expr::BVFunction { .. } => {
+                            return f.get_type(ctx).expect_bv_of(width, "function application");
+                        }
+                        _ => {
+                            return Err(TypeCheckError {
+                                msg: "only uninterpreted functions can be applied to arguments"
+                                    .to_string(),
+                            })
+                        }
+                    }
+                }
+            }
         }
     }
 
@@ -350,16 +417,23 @@ impl TypeCheck for Expr {
                 index_width,
                 data_width,
             }),
-            Expr::ArrayConstant {
-                e: _,
+            Expr::ArrayLiteral {
+                index_width,
+                data_width,
+                ..
+            }
+            | Expr::ArrayConstant {
                 index_width,
                 data_width,
+                ..
             } => Type::Array(ArrayType {
                 index_width,
                 data_width,
             }),
             Expr::ArrayEqual(_, _) => Type::BV(1),
             Expr::ArrayStore { array, .. } => array.get_type(ctx),
+            Expr::BVFunction { width, .. } => Type::BV(width),
+            Expr::BVApply { width, .. } => Type::BV(width),
             Expr::ArrayIte {
                 cond: _,
                 tru: _,
@@ -5,9 +5,18 @@
 extern crate lazy_static;
 extern crate core;
 
+#[cfg(feature = "bench")]
+pub mod bench;
 pub mod btor2;
+pub mod config;
+pub mod equiv;
+pub mod examples;
 pub mod expr;
 pub mod mc;
+pub mod plugin;
+pub mod random;
+pub mod sat;
 pub mod sim;
 pub mod smt;
+pub mod synth;
 pub mod system;
@@ -2,11 +2,46 @@
 // released under BSD 3-Clause License
 // author: Kevin Laeufer <laeufer@berkeley.edu>
 
+mod batch;
+mod bdd;
+mod cancel;
+mod cegar;
+mod complete;
+mod exhaustive;
+mod gray_box;
+mod lemmas;
+mod mining;
+mod progress;
+mod random_walk;
+mod record;
+mod report;
+#[cfg(feature = "sat")]
+mod sat;
 mod smt;
+mod symmetry;
+mod testbench;
 mod types;
 
+pub use batch::{run_batch, run_task, run_task_worker, BatchTask, Isolation};
+pub use bdd::{bdd_reachability, ReachError, ReachOptions, Reachability};
+pub use cancel::CancellationToken;
+pub use cegar::{is_real_counterexample, CegarOptions, CegarRun};
+pub use exhaustive::{check_exhaustive, ExhaustiveError, ExhaustiveOptions};
+pub use gray_box::{check_gray_box, GrayBoxOptions, GrayBoxReport};
+pub use lemmas::{LemmaGraph, LemmaNode, LemmaOptions};
+pub use mining::{Candidate, CandidateKind, InvariantMiner};
+pub use progress::ProgressObserver;
+pub use random_walk::{random_walks, WalkOptions, WalkReport};
+pub use record::{CheckKind, CheckStatus, ResultRecord, RESULT_RECORD_VERSION};
+pub use report::{CexReport, TraceRow};
+#[cfg(feature = "sat")]
+pub use sat::{check_with_sat, encode_bmc};
 pub use smt::{
-    check_assuming, check_assuming_end, get_smt_value, ModelCheckResult, SmtModelChecker,
-    SmtModelCheckerOptions, TransitionSystemEncoding, UnrollSmtEncoding,
+    check_assuming, check_assuming_end, get_smt_model, get_smt_value, ModelCheckResult,
+    SmtModelChecker, SmtModelCheckerOptions, TransitionSystemEncoding, UnrollSmtEncoding,
 };
+pub use symmetry::{
+    validate_symmetry, Lane, PropertyResult, PropertyStatus, SymmetryError, SymmetryGroup,
+};
+pub use testbench::{Testbench, TestbenchSignal};
 pub use types::{InitValue, Witness};
@@ -0,0 +1,276 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Batch Experiments
+//! Runs a list of checking tasks, each naming a design file, an optional property, an engine
+//! from the [plugin registry](crate::plugin::registry) and a configuration, and collects one
+//! [`ResultRecord`] per task.
+//!
+//! The time limit of a task is taken from `bmc.timeout_secs` of its configuration and is
+//! enforced with a [`CancellationToken`]. Tasks can optionally run in a worker process, see
+//! [`Isolation`]. Only then is the memory limit enforced and a task that ignores its
+//! cancellation token is killed, once its time limit is exceeded by a grace period.
+
+use crate::config::Config;
+use crate::expr::Context;
+use crate::mc::{CancellationToken, CheckKind, CheckStatus, ModelCheckResult, ResultRecord};
+use crate::plugin::registry;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchTask {
+    pub design: PathBuf,
+    /// index of the bad state to check, `None` checks all of them
+    #[serde(default)]
+    pub property: Option<usize>,
+    pub engine: String,
+    #[serde(default)]
+    pub config: Config,
+    /// maximum resident memory of the worker process, requires [`Isolation`]
+    #[serde(default)]
+    pub memory_limit_bytes: Option<u64>,
+}
+
+impl BatchTask {
+    /// Parses a JSON array of tasks.
+    pub fn parse_list(src: &str) -> serde_json::Result<Vec<Self>> {
+        serde_json::from_str(src)
+    }
+
+    fn time_limit(&self) -> Option<Duration> {
+        self.config.bmc.timeout_secs.map(Duration::from_secs)
+    }
+
+    fn record(&self, status: CheckStatus, detail: impl Into<String>) -> ResultRecord {
+        ResultRecord::new(CheckKind::Bmc, &self.engine, &self.design_name(), status)
+            .with_detail(detail)
+            .with_config(&self.config)
+    }
+
+    fn design_name(&self) -> String {
+        let name = self.design.display().to_string();
+        match self.property {
+            Some(p) => format!("{name}#{p}"),
+            None => name,
+        }
+    }
+}
+
+/// Command that starts a worker process. The worker receives a [`BatchTask`] as JSON on its
+/// standard input and needs to print the [`ResultRecord`] as JSON to its standard output,
+/// e.g., with [`run_task_worker`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Isolation {
+    pub program: PathBuf,
+    pub args: Vec<String>,
+    /// additional time a worker gets after its time limit, before it is killed
+    pub grace: Duration,
+}
+
+impl Isolation {
+    pub fn new(program: impl Into<PathBuf>, args: &[&str]) -> Self {
+        Self {
+            program: program.into(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+            grace: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Runs all tasks in order. Tasks that fail to load or to run result in records with
+/// [`CheckStatus::Error`], they do not abort the batch.
+pub fn run_batch(tasks: &[BatchTask], isolation: Option<&Isolation>) -> Vec<ResultRecord> {
+    tasks
+        .iter()
+        .map(|task| match isolation {
+            Some(isolation) => run_isolated(task, isolation),
+            None => run_task(task),
+        })
+        .collect()
+}
+
+/// Runs a single task in the current process.
+pub fn run_task(task: &BatchTask) -> ResultRecord {
+    let start = Instant::now();
+    let (frontend, engine) = {
+        let registry = registry();
+        let frontend = registry.frontend_for(&task.design);
+        let engine = registry.engine(&task.engine);
+        match (frontend, engine) {
+            (Ok(frontend), Ok(engine)) => (frontend, engine),
+            (Err(e), _) | (_, Err(e)) => return task.record(CheckStatus::Error, e.to_string()),
+        }
+    };
+    let mut ctx = Context::default();
+    let mut sys = match frontend.parse(&mut ctx, &task.design) {
+        Ok(sys) => sys,
+        Err(e) => return task.record(CheckStatus::Error, e.to_string()),
+    };
+    if let Some(property) = task.property {
+        if property >= sys.bad_states.len() {
+            let msg = format!("{} only has {} properties", sys.name, sys.bad_states.len());
+            return task.record(CheckStatus::Error, msg);
+        }
+        sys.bad_states = vec![sys.bad_states[property]];
+    }
+    let parse_time = start.elapsed();
+
+    let token = task
+        .time_limit()
+        .map(CancellationToken::with_timeout)
+        .unwrap_or_default();
+    let k_max = task.config.bmc.max_bound;
+    let start = Instant::now();
+    match engine.check(&mut ctx, &sys, k_max, &token) {
+        Ok(res) => {
+            let mut record = ResultRecord::from_model_check(
+                CheckKind::Bmc,
+                &task.engine,
+                &task.design_name(),
+                &res,
+                k_max,
+            )
+            .with_time("parse", parse_time)
+            .with_time("check", start.elapsed())
+            .with_config(&task.config);
+            // property indices refer to the original system
+            if let (Some(property), ModelCheckResult::Fail(_)) = (task.property, &res) {
+                record.failed_properties = vec![property as u32];
+            }
+            record
+        }
+        Err(e) => task.record(CheckStatus::Error, e.to_string()),
+    }
+}
+
+/// Entry point for worker processes, see [`Isolation`].
+pub fn run_task_worker(input: &mut impl Read, output: &mut impl Write) -> std::io::Result<()> {
+    let mut src = String::new();
+    input.read_to_string(&mut src)?;
+    let task: BatchTask = serde_json::from_str(&src)?;
+    let record = run_task(&task);
+    writeln!(output, "{}", record.to_json())
+}
+
+fn run_isolated(task: &BatchTask, isolation: &Isolation) -> ResultRecord {
+    let start = Instant::now();
+    let child = Command::new(&isolation.program)
+        .args(isolation.args.iter())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => return task.record(CheckStatus::Error, format!("failed to start worker: {e}")),
+    };
+    let task_json = serde_json::to_string(task).expect("tasks can always be serialized");
+    // a worker that exits without reading its input is reported below
+    let _ = child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(task_json.as_bytes());
+    // read the output concurrently, such that a chatty worker cannot block on a full pipe
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+    let stdout = std::thread::spawn(move || {
+        let mut out = String::new();
+        let _ = stdout.read_to_string(&mut out);
+        out
+    });
+    let stderr = std::thread::spawn(move || {
+        let mut out = String::new();
+        let _ = stderr.read_to_string(&mut out);
+        out
+    });
+
+    let kill_after = task.time_limit().map(|t| t + isolation.grace);
+    let mut killed = None;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break Some(status),
+            Ok(None) => {}
+            Err(_) => break None,
+        }
+        if kill_after.is_some_and(|limit| start.elapsed() > limit) {
+            killed = Some("time limit exceeded".to_string());
+        } else if let (Some(limit), Some(used)) =
+            (task.memory_limit_bytes, resident_bytes(child.id()))
+        {
+            if used > limit {
+                killed = Some(format!("memory limit exceeded ({used} bytes)"));
+            }
+        }
+        if killed.is_some() {
+            let _ = child.kill();
+            let _ = child.wait();
+            break None;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+
+    if let Some(reason) = killed {
+        return task
+            .record(CheckStatus::Unknown, reason)
+            .with_time("total", start.elapsed());
+    }
+    match status {
+        Some(status) if status.success() => match ResultRecord::from_json(&stdout) {
+            Ok(record) => record.with_time("total", start.elapsed()),
+            Err(e) => task.record(CheckStatus::Error, format!("invalid worker output: {e}")),
+        },
+        _ => task.record(
+            CheckStatus::Error,
+            format!("worker failed: {}", stderr.trim()),
+        ),
+    }
+}
+
+/// Resident memory of a process. Only available on Linux.
+fn resident_bytes(pid: u32) -> Option<u64> {
+    let status = std::fs::read_to_string(format!("/proc/{pid}/status")).ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_batch() {
+        let task = |engine: &str| BatchTask {
+            design: PathBuf::from("../inputs/unittest/delay.btor"),
+            property: None,
+            engine: engine.to_string(),
+            config: Config::default(),
+            memory_limit_bytes: None,
+        };
+        let records = run_batch(&[task("sat-bmc"), task("no-such-engine")], None);
+        assert_eq!(records.len(), 2);
+        assert_ne!(records[0].status, CheckStatus::Error);
+        assert_eq!(records[0].max_bound, Some(Config::default().bmc.max_bound));
+        assert!(records[0].timings.contains_key("check"));
+        assert_eq!(records[1].status, CheckStatus::Error);
+
+        // a worker that never answers is killed after its time limit
+        let mut slow = task("sat-bmc");
+        slow.config.bmc.timeout_secs = Some(1);
+        let mut sleep = Isolation::new("sleep", &["30"]);
+        sleep.grace = Duration::from_millis(100);
+        let start = Instant::now();
+        let records = run_batch(&[slow], Some(&sleep));
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert_eq!(records[0].status, CheckStatus::Unknown);
+        assert_eq!(records[0].detail.as_deref(), Some("time limit exceeded"));
+    }
+}
@@ -0,0 +1,612 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # BDD Reachability
+//! Computes the exact set of reachable states of small designs with binary decision diagrams.
+//! Starting from the initial states, we repeatedly compute the image under the transition
+//! relation until no new states are found. The result contains the number of reachable states
+//! and an invariant that describes them exactly, which other engines can use as a constraint,
+//! and which makes it easy to validate the results of approximate engines on small examples.
+//!
+//! The transition relation is bit-blasted without any abstraction, thus only designs with
+//! a small number of state bits and without arrays, division or uninterpreted functions are
+//! supported.
+
+use crate::expr::{Context, Expr, ExprRef, ForEachChild, SerializableIrNode, TypeCheck};
+use crate::system::TransitionSystem;
+use baa::BitVecOps;
+use boolean_expression::{BDDFunc, BDD, BDD_ONE, BDD_ZERO};
+use rustc_hash::FxHashMap;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReachOptions {
+    /// Systems with more state bits are rejected, since the BDDs might blow up.
+    pub max_state_bits: u32,
+}
+
+impl Default for ReachOptions {
+    fn default() -> Self {
+        Self { max_state_bits: 32 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ReachError {
+    #[error("the system has {bits} state bits, which exceeds the limit of {limit}")]
+    TooManyStateBits { bits: u32, limit: u32 },
+    #[error("cannot translate `{0}` into a BDD")]
+    Unsupported(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reachability {
+    /// number of reachable states
+    pub states: u128,
+    /// number of image computations that found new states, i.e., the largest distance
+    /// between an initial state and a reachable state
+    pub depth: u64,
+    /// 1-bit expression over the state symbols that is true for exactly the reachable states
+    pub invariant: ExprRef,
+    /// indices of bad states that hold in at least one reachable state
+    pub failed_safety: Vec<u32>,
+}
+
+/// BDD variables. States are identified by their index and bits are interleaved, such that
+/// the current and the next value of a bit are next to each other in the variable order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Var {
+    Current(u32, u32),
+    Next(u32, u32),
+    Input(u32, u32),
+}
+
+pub fn bdd_reachability(
+    ctx: &mut Context,
+    sys: &TransitionSystem,
+    opts: &ReachOptions,
+) -> Result<Reachability, ReachError> {
+    let mut state_bits = 0;
+    for state in sys.states.iter() {
+        let width = state
+            .symbol
+            .get_bv_type(ctx)
+            .ok_or_else(|| ReachError::Unsupported(state.symbol.serialize_to_str(ctx)))?;
+        state_bits += width;
+    }
+    if state_bits > opts.max_state_bits {
+        return Err(ReachError::TooManyStateBits {
+            bits: state_bits,
+            limit: opts.max_state_bits,
+        });
+    }
+
+    let mut blaster = BddBlaster::new(ctx);
+    let mut current = vec![];
+    let mut next = vec![];
+    let mut next_bits = vec![];
+    for (ii, state) in sys.states.iter().enumerate() {
+        let width = state.symbol.get_bv_type(ctx).unwrap();
+        let mut bits = vec![];
+        for bit in 0..width {
+            let (c, n) = (Var::Current(ii as u32, bit), Var::Next(ii as u32, bit));
+            bits.push(blaster.bdd.terminal(c));
+            next_bits.push(blaster.bdd.terminal(n));
+            current.push(c);
+            next.push(n);
+        }
+        blaster.bind(state.symbol, bits);
+    }
+    let mut inputs = vec![];
+    for (ii, &input) in sys.inputs.iter().enumerate() {
+        let width = input
+            .get_bv_type(ctx)
+            .ok_or_else(|| ReachError::Unsupported(input.serialize_to_str(ctx)))?;
+        let bits = (0..width)
+            .map(|bit| {
+                inputs.push(Var::Input(ii as u32, bit));
+                blaster.bdd.terminal(Var::Input(ii as u32, bit))
+            })
+            .collect();
+        blaster.bind(input, bits);
+    }
+
+    // constraints need to hold in every step, including the last one
+    let mut constraints = BDD_ONE;
+    for &c in sys.constraints.iter() {
+        let bit = blaster.bit(c)?;
+        constraints = blaster.bdd.and(constraints, bit);
+    }
+    let valid = blaster.exists(constraints, &inputs);
+
+    let mut init = constraints;
+    let mut trans = constraints;
+    let mut next_offset = 0;
+    for state in sys.states.iter() {
+        let width = state.symbol.get_bv_type(ctx).unwrap() as usize;
+        let symbol = blaster.bits(state.symbol)?;
+        if let Some(value) = state.init {
+            let value = blaster.bits(value)?;
+            let is_init = blaster.equal(&symbol, &value);
+            init = blaster.bdd.and(init, is_init);
+        }
+        if let Some(value) = state.next {
+            let value = blaster.bits(value)?;
+            let is_next = blaster.equal(&next_bits[next_offset..next_offset + width], &value);
+            trans = blaster.bdd.and(trans, is_next);
+        }
+        next_offset += width;
+    }
+    let init = blaster.exists(init, &inputs);
+
+    let mut reach = init;
+    let mut frontier = init;
+    let mut depth = 0;
+    let current_and_inputs: Vec<Var> = current.iter().chain(inputs.iter()).copied().collect();
+    loop {
+        let step = blaster.bdd.and(frontier, trans);
+        let image = blaster.exists(step, &current_and_inputs);
+        let image = blaster.rename(image, &next, &current);
+        let image = blaster.bdd.and(image, valid);
+        let not_reached = blaster.bdd.not(reach);
+        let new = blaster.bdd.and(image, not_reached);
+        if new == BDD_ZERO {
+            break;
+        }
+        reach = blaster.bdd.or(reach, new);
+        frontier = new;
+        depth += 1;
+    }
+
+    let mut failed_safety = vec![];
+    for (ii, &bad) in sys.bad_states.iter().enumerate() {
+        let bit = blaster.bit(bad)?;
+        let hit = blaster.bdd.and(reach, bit);
+        let hit = blaster.bdd.and(hit, constraints);
+        if hit != BDD_ZERO {
+            failed_safety.push(ii as u32);
+        }
+    }
+
+    let mut bdd = blaster.bdd;
+    let states = count(&mut bdd, reach, &current, 0, &mut FxHashMap::default());
+    let symbols: Vec<ExprRef> = sys.states.iter().map(|s| s.symbol).collect();
+    let invariant = to_expr(
+        ctx,
+        &mut bdd,
+        &symbols,
+        reach,
+        &current,
+        0,
+        &mut FxHashMap::default(),
+    );
+    Ok(Reachability {
+        states,
+        depth,
+        invariant,
+        failed_safety,
+    })
+}
+
+/// Number of assignments to `vars[ii..]` that satisfy `f`.
+fn count(
+    bdd: &mut BDD<Var>,
+    f: BDDFunc,
+    vars: &[Var],
+    ii: usize,
+    cache: &mut FxHashMap<(BDDFunc, usize), u128>,
+) -> u128 {
+    if f == BDD_ZERO {
+        return 0;
+    }
+    if f == BDD_ONE {
+        return 1 << (vars.len() - ii);
+    }
+    if let Some(&n) = cache.get(&(f, ii)) {
+        return n;
+    }
+    let lo = bdd.restrict(f, vars[ii], false);
+    let hi = bdd.restrict(f, vars[ii], true);
+    let n = count(bdd, lo, vars, ii + 1, cache) + count(bdd, hi, vars, ii + 1, cache);
+    cache.insert((f, ii), n);
+    n
+}
+
+/// Translates a function over the current state bits into a 1-bit expression.
+fn to_expr(
+    ctx: &mut Context,
+    bdd: &mut BDD<Var>,
+    symbols: &[ExprRef],
+    f: BDDFunc,
+    vars: &[Var],
+    ii: usize,
+    cache: &mut FxHashMap<BDDFunc, ExprRef>,
+) -> ExprRef {
+    if f == BDD_ZERO {
+        return ctx.get_false();
+    }
+    if f == BDD_ONE {
+        return ctx.get_true();
+    }
+    if let Some(&e) = cache.get(&f) {
+        return e;
+    }
+    let lo = bdd.restrict(f, vars[ii], false);
+    let hi = bdd.restrict(f, vars[ii], true);
+    let e = if lo == hi {
+        to_expr(ctx, bdd, symbols, lo, vars, ii + 1, cache)
+    } else {
+        let Var::Current(state, bit) = vars[ii] else {
+            unreachable!("only current state bits remain")
+        };
+        let symbol = symbols[state as usize];
+        let cond = if symbol.get_bv_type(ctx) == Some(1) {
+            symbol
+        } else {
+            ctx.slice(symbol, bit, bit)
+        };
+        match (hi, lo) {
+            (BDD_ONE, BDD_ZERO) => cond,
+            (BDD_ZERO, BDD_ONE) => ctx.not(cond),
+            _ => {
+                let tru = to_expr(ctx, bdd, symbols, hi, vars, ii + 1, cache);
+                let fals = to_expr(ctx, bdd, symbols, lo, vars, ii + 1, cache);
+                ctx.ite(cond, tru, fals)
+            }
+        }
+    };
+    cache.insert(f, e);
+    e
+}
+
+/// Translates bit-vector expressions into one BDD per bit, least significant bit first.
+/// Mirrors the SAT [`BitBlaster`](crate::sat::BitBlaster).
+struct BddBlaster<'a> {
+    ctx: &'a Context,
+    bdd: BDD<Var>,
+    bindings: FxHashMap<ExprRef, Vec<BDDFunc>>,
+    cache: FxHashMap<ExprRef, Vec<BDDFunc>>,
+}
+
+impl<'a> BddBlaster<'a> {
+    fn new(ctx: &'a Context) -> Self {
+        Self {
+            ctx,
+            bdd: BDD::new(),
+            bindings: FxHashMap::default(),
+            cache: FxHashMap::default(),
+        }
+    }
+
+    fn bind(&mut self, symbol: ExprRef, bits: Vec<BDDFunc>) {
+        self.bindings.insert(symbol, bits);
+    }
+
+    fn bit(&mut self, e: ExprRef) -> Result<BDDFunc, ReachError> {
+        Ok(self.bits(e)?[0])
+    }
+
+    fn bits(&mut self, root: ExprRef) -> Result<Vec<BDDFunc>, ReachError> {
+        let ctx = self.ctx;
+        let mut todo = vec![root];
+        while let Some(&e) = todo.last() {
+            if self.cache.contains_key(&e) {
+                todo.pop();
+                continue;
+            }
+            let mut missing = vec![];
+            ctx[e].for_each_child(|c| {
+                if !self.cache.contains_key(c) {
+                    missing.push(*c);
+                }
+            });
+            if missing.is_empty() {
+                todo.pop();
+                let bits = self.blast(e)?;
+                self.cache.insert(e, bits);
+            } else {
+                todo.extend(missing);
+            }
+        }
+        Ok(self.cache[&root].clone())
+    }
+
+    fn blast(&mut self, e: ExprRef) -> Result<Vec<BDDFunc>, ReachError> {
+        let ctx = self.ctx;
+        let get = |s: &Self, c: ExprRef| s.cache[&c].clone();
+        let bits = match ctx[e].clone() {
+            Expr::BVSymbol { .. } => match self.bindings.get(&e) {
+                Some(bits) => bits.clone(),
+                None => return Err(ReachError::Unsupported(e.serialize_to_str(ctx))),
+            },
+            Expr::BVLiteral(value) => {
+                let value = value.get(ctx);
+                (0..value.width())
+                    .map(|ii| self.bdd.constant(value.is_bit_set(ii)))
+                    .collect()
+            }
+            Expr::BVZeroExt { e, by, .. } => {
+                let mut bits = get(self, e);
+                bits.extend(std::iter::repeat(BDD_ZERO).take(by as usize));
+                bits
+            }
+            Expr::BVSignExt { e, by, .. } => {
+                let mut bits = get(self, e);
+                let msb = *bits.last().unwrap();
+                bits.extend(std::iter::repeat(msb).take(by as usize));
+                bits
+            }
+            Expr::BVSlice { e, hi, lo } => get(self, e)[lo as usize..=hi as usize].to_vec(),
+            Expr::BVNot(e, _) => get(self, e).into_iter().map(|b| self.bdd.not(b)).collect(),
+            Expr::BVNegate(e, width) => {
+                let zero = vec![BDD_ZERO; width as usize];
+                self.sub(&zero, &get(self, e))
+            }
+            Expr::BVEqual(a, b) => {
+                let (a, b) = (get(self, a), get(self, b));
+                vec![self.equal(&a, &b)]
+            }
+            Expr::BVImplies(a, b) => {
+                let (a, b) = (get(self, a)[0], get(self, b)[0]);
+                vec![self.bdd.implies(a, b)]
+            }
+            Expr::BVGreater(a, b) => {
+                let (a, b) = (get(self, a), get(self, b));
+                vec![self.less(&b, &a)]
+            }
+            Expr::BVGreaterEqual(a, b) => {
+                let (a, b) = (get(self, a), get(self, b));
+                let less = self.less(&a, &b);
+                vec![self.bdd.not(less)]
+            }
+            Expr::BVGreaterSigned(a, b, _) => {
+                let (a, b) = (get(self, a), get(self, b));
+                let (a, b) = (self.flip_msb(a), self.flip_msb(b));
+                vec![self.less(&b, &a)]
+            }
+            Expr::BVGreaterEqualSigned(a, b, _) => {
+                let (a, b) = (get(self, a), get(self, b));
+                let (a, b) = (self.flip_msb(a), self.flip_msb(b));
+                let less = self.less(&a, &b);
+                vec![self.bdd.not(less)]
+            }
+            Expr::BVConcat(a, b, _) => {
+                let mut bits = get(self, b);
+                bits.extend(get(self, a));
+                bits
+            }
+            Expr::BVAnd(a, b, _) => self.bitwise(get(self, a), get(self, b), BDD::and),
+            Expr::BVOr(a, b, _) => self.bitwise(get(self, a), get(self, b), BDD::or),
+            Expr::BVXor(a, b, _) => self.bitwise(get(self, a), get(self, b), BDD::xor),
+            Expr::BVShiftLeft(a, b, _) => self.shift(&get(self, a), &get(self, b), Shift::Left),
+            Expr::BVShiftRight(a, b, _) => self.shift(&get(self, a), &get(self, b), Shift::Right),
+            Expr::BVArithmeticShiftRight(a, b, _) => {
+                self.shift(&get(self, a), &get(self, b), Shift::ArithmeticRight)
+            }
+            Expr::BVAdd(a, b, _) => {
+                let (a, b) = (get(self, a), get(self, b));
+                self.add(&a, &b, BDD_ZERO)
+            }
+            Expr::BVSub(a, b, _) => {
+                let (a, b) = (get(self, a), get(self, b));
+                self.sub(&a, &b)
+            }
+            Expr::BVMul(a, b, _) => {
+                let (a, b) = (get(self, a), get(self, b));
+                self.mul(&a, &b)
+            }
+            Expr::BVIte { cond, tru, fals } => {
+                let cond = get(self, cond)[0];
+                self.bitwise(get(self, tru), get(self, fals), |bdd, t, f| {
+                    bdd.ite(cond, t, f)
+                })
+            }
+            _ => return Err(ReachError::Unsupported(e.serialize_to_str(ctx))),
+        };
+        Ok(bits)
+    }
+
+    /// Existential quantification of all `vars`.
+    fn exists(&mut self, f: BDDFunc, vars: &[Var]) -> BDDFunc {
+        vars.iter().fold(f, |f, &var| {
+            let lo = self.bdd.restrict(f, var, false);
+            let hi = self.bdd.restrict(f, var, true);
+            self.bdd.or(lo, hi)
+        })
+    }
+
+    /// Replaces every variable in `from` with the one at the same position in `to`.
+    /// Requires that `f` does not depend on any variable in `to`.
+    fn rename(&mut self, f: BDDFunc, from: &[Var], to: &[Var]) -> BDDFunc {
+        from.iter().zip(to.iter()).fold(f, |f, (&from, &to)| {
+            let lo = self.bdd.restrict(f, from, false);
+            let hi = self.bdd.restrict(f, from, true);
+            let var = self.bdd.terminal(to);
+            self.bdd.ite(var, hi, lo)
+        })
+    }
+
+    fn bitwise(
+        &mut self,
+        a: Vec<BDDFunc>,
+        b: Vec<BDDFunc>,
+        mut op: impl FnMut(&mut BDD<Var>, BDDFunc, BDDFunc) -> BDDFunc,
+    ) -> Vec<BDDFunc> {
+        a.into_iter()
+            .zip(b)
+            .map(|(a, b)| op(&mut self.bdd, a, b))
+            .collect()
+    }
+
+    fn equal(&mut self, a: &[BDDFunc], b: &[BDDFunc]) -> BDDFunc {
+        a.iter().zip(b.iter()).fold(BDD_ONE, |acc, (&a, &b)| {
+            let differ = self.bdd.xor(a, b);
+            let same = self.bdd.not(differ);
+            self.bdd.and(acc, same)
+        })
+    }
+
+    /// unsigned less than
+    fn less(&mut self, a: &[BDDFunc], b: &[BDDFunc]) -> BDDFunc {
+        // starting from the least significant bit, the most significant difference decides
+        a.iter().zip(b.iter()).fold(BDD_ZERO, |less, (&a, &b)| {
+            let differ = self.bdd.xor(a, b);
+            self.bdd.ite(differ, b, less)
+        })
+    }
+
+    /// ripple carry adder
+    fn add(&mut self, a: &[BDDFunc], b: &[BDDFunc], carry_in: BDDFunc) -> Vec<BDDFunc> {
+        let mut carry = carry_in;
+        a.iter()
+            .zip(b.iter())
+            .map(|(&a, &b)| {
+                let half = self.bdd.xor(a, b);
+                let sum = self.bdd.xor(half, carry);
+                let generate = self.bdd.and(a, b);
+                let propagate = self.bdd.and(half, carry);
+                carry = self.bdd.or(generate, propagate);
+                sum
+            })
+            .collect()
+    }
+
+    fn sub(&mut self, a: &[BDDFunc], b: &[BDDFunc]) -> Vec<BDDFunc> {
+        let not_b: Vec<_> = b.iter().map(|&b| self.bdd.not(b)).collect();
+        self.add(a, &not_b, BDD_ONE)
+    }
+
+    /// shift and add multiplier
+    fn mul(&mut self, a: &[BDDFunc], b: &[BDDFunc]) -> Vec<BDDFunc> {
+        let width = a.len();
+        let mut product = vec![BDD_ZERO; width];
+        for (shift, &b) in b.iter().enumerate() {
+            let partial: Vec<_> = (0..width)
+                .map(|ii| {
+                    if ii < shift {
+                        BDD_ZERO
+                    } else {
+                        self.bdd.and(a[ii - shift], b)
+                    }
+                })
+                .collect();
+            product = self.add(&product, &partial, BDD_ZERO);
+        }
+        product
+    }
+
+    /// barrel shifter
+    fn shift(&mut self, a: &[BDDFunc], amount: &[BDDFunc], kind: Shift) -> Vec<BDDFunc> {
+        let width = a.len();
+        let fill = match kind {
+            Shift::ArithmeticRight => *a.last().unwrap(),
+            _ => BDD_ZERO,
+        };
+        let mut value = a.to_vec();
+        for (stage, &bit) in amount.iter().enumerate() {
+            let by = 1usize.checked_shl(stage as u32).unwrap_or(usize::MAX);
+            let shifted: Vec<_> = (0..width)
+                .map(|ii| {
+                    let src = match kind {
+                        Shift::Left => ii.checked_sub(by),
+                        _ => ii.checked_add(by).filter(|s| *s < width),
+                    };
+                    src.map(|s| value[s]).unwrap_or(fill)
+                })
+                .collect();
+            value = self.bitwise(shifted, value, |bdd, shifted, old| {
+                bdd.ite(bit, shifted, old)
+            });
+        }
+        value
+    }
+
+    /// Flipping the sign bit turns a signed into an unsigned comparison.
+    fn flip_msb(&mut self, mut bits: Vec<BDDFunc>) -> Vec<BDDFunc> {
+        let msb = bits.last_mut().unwrap();
+        *msb = self.bdd.not(*msb);
+        bits
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Shift {
+    Left,
+    Right,
+    ArithmeticRight,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::eval_bv_expr;
+    use crate::system::State;
+    use baa::BitVecValue;
+
+    /// A counter that wraps around at 5, with a 1-bit enable input.
+    fn mod5_counter(ctx: &mut Context) -> (TransitionSystem, ExprRef) {
+        let mut sys = TransitionSystem::new("mod5".to_string());
+        let en = ctx.bv_symbol("en", 1);
+        sys.add_input(ctx, en);
+        let count = ctx.bv_symbol("count", 4);
+        let next = ctx.build(|c| {
+            let inc = c.ite(
+                c.equal(count, c.bit_vec_val(4, 4)),
+                c.zero(4),
+                c.add(count, c.one(4)),
+            );
+            c.ite(en, inc, count)
+        });
+        let init = ctx.zero(4);
+        sys.add_state(
+            ctx,
+            State {
+                symbol: count,
+                init: Some(init),
+                next: Some(next),
+            },
+        );
+        (sys, count)
+    }
+
+    #[test]
+    fn test_counter_reachability() {
+        let mut ctx = Context::default();
+        let (mut sys, count) = mod5_counter(&mut ctx);
+        let reach_five = ctx.build(|c| c.equal(count, c.bit_vec_val(5, 4)));
+        let reach_four = ctx.build(|c| c.equal(count, c.bit_vec_val(4, 4)));
+        sys.bad_states.push(reach_five);
+        sys.bad_states.push(reach_four);
+
+        let result = bdd_reachability(&mut ctx, &sys, &ReachOptions::default()).unwrap();
+        assert_eq!(result.states, 5);
+        assert_eq!(result.depth, 4);
+        assert_eq!(result.failed_safety, [1]);
+
+        // the invariant holds in exactly the reachable states
+        for value in 0..16u64 {
+            let symbols = [(count, BitVecValue::from_u64(value, 4))];
+            let holds = eval_bv_expr(&ctx, symbols.as_slice(), result.invariant);
+            assert_eq!(holds.is_true(), value < 5, "count = {value}");
+        }
+    }
+
+    #[test]
+    fn test_constraints_and_limits() {
+        let mut ctx = Context::default();
+        let (mut sys, _) = mod5_counter(&mut ctx);
+        // the counter can never advance if the enable is always off
+        let en = sys.inputs[0];
+        let off = ctx.not(en);
+        sys.constraints.push(off);
+        let result = bdd_reachability(&mut ctx, &sys, &ReachOptions::default()).unwrap();
+        assert_eq!(result.states, 1);
+        assert_eq!(result.depth, 0);
+
+        let tiny = ReachOptions { max_state_bits: 3 };
+        assert_eq!(
+            bdd_reachability(&mut ctx, &sys, &tiny),
+            Err(ReachError::TooManyStateBits { bits: 4, limit: 3 })
+        );
+    }
+}
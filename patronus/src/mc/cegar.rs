@@ -0,0 +1,238 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Counterexample Guided Abstraction Refinement
+//! Model checks a system with an abstracted datapath and reintroduces concrete operator
+//! semantics whenever the solver finds a counterexample that cannot be reproduced by
+//! simulating the original system.
+
+use crate::expr::{Context, ExprRef, WidthInt};
+use crate::mc::{InitValue, ModelCheckResult, SmtModelChecker, Witness};
+use crate::sim::{InitKind, Interpreter, Simulator};
+use crate::smt::{Solver, SolverMetaData};
+use crate::system::{abstract_datapath, OperatorClass, TransitionSystem};
+use baa::{BitVecOps, Value};
+
+type Result<T> = crate::smt::Result<T>;
+
+#[derive(Debug, Clone)]
+pub struct CegarOptions {
+    /// operators that are replaced by uninterpreted functions
+    pub classes: Vec<OperatorClass>,
+    /// narrower operators are always kept concrete
+    pub min_width: WidthInt,
+    /// after this many refinements, all remaining operators are made concrete at once
+    pub max_refinements: usize,
+}
+
+impl Default for CegarOptions {
+    fn default() -> Self {
+        Self {
+            classes: vec![OperatorClass::Mul, OperatorClass::Div, OperatorClass::Rem],
+            min_width: 8,
+            max_refinements: 8,
+        }
+    }
+}
+
+/// Result of a model checking run with abstraction refinement.
+pub struct CegarRun {
+    /// Always agrees with the result of checking the concrete system.
+    pub result: ModelCheckResult,
+    /// names of the functions that had to be made concrete, in order
+    pub refined: Vec<String>,
+    /// number of spurious counterexamples that were encountered
+    pub spurious: usize,
+}
+
+impl<S: Solver<std::fs::File>> SmtModelChecker<S> {
+    /// Checks `sys` with an abstracted datapath. Requires a solver with support for
+    /// uninterpreted functions, otherwise [`crate::smt::Error::UninterpretedFunctionsUnsupported`]
+    /// is returned.
+    pub fn check_with_abstraction(
+        &self,
+        ctx: &mut Context,
+        sys: &TransitionSystem,
+        k_max: u64,
+        opts: &CegarOptions,
+    ) -> Result<CegarRun> {
+        if !self.solver().supports_uf() {
+            return Err(crate::smt::Error::UninterpretedFunctionsUnsupported(
+                self.solver().name().to_string(),
+            ));
+        }
+        let mut abs_sys = sys.clone();
+        let mut abstraction = abstract_datapath(ctx, &mut abs_sys, &opts.classes, opts.min_width);
+        let mut refined = vec![];
+        let mut spurious = 0;
+        loop {
+            let wit = match self.check(ctx, &abs_sys, k_max)? {
+                ModelCheckResult::Fail(wit) if !abstraction.is_empty() => wit,
+                // the abstraction over-approximates the system, thus proofs are always valid
+                result => {
+                    return Ok(CegarRun {
+                        result,
+                        refined,
+                        spurious,
+                    })
+                }
+            };
+            if is_real_counterexample(ctx, sys, &wit) {
+                return Ok(CegarRun {
+                    result: ModelCheckResult::Fail(wit),
+                    refined,
+                    spurious,
+                });
+            }
+            spurious += 1;
+
+            // pick the narrowest function that the violated properties depend on
+            let roots = wit
+                .failed_safety
+                .iter()
+                .map(|&ii| abs_sys.bad_states[ii as usize])
+                .collect::<Vec<_>>();
+            let in_cone = abstraction.functions_in_cone(ctx, &abs_sys, &roots);
+            let candidate = abstraction
+                .functions()
+                .iter()
+                .filter(|f| in_cone.is_empty() || in_cone.contains(&f.func))
+                .min_by_key(|f| f.width)
+                .map(|f| f.func);
+            match candidate {
+                Some(func) if refined.len() < opts.max_refinements => {
+                    refined.push(ctx.get_symbol_name(func).unwrap().to_string());
+                    abstraction.refine(ctx, &mut abs_sys, func);
+                }
+                _ => {
+                    refined.extend(
+                        abstraction
+                            .functions()
+                            .iter()
+                            .map(|f| ctx.get_symbol_name(f.func).unwrap().to_string()),
+                    );
+                    abstraction.refine_all(ctx, &mut abs_sys);
+                }
+            }
+        }
+    }
+}
+
+/// Replays a witness on the concrete system in order to check whether it actually violates
+/// one of the properties that the model checker reported.
+pub fn is_real_counterexample(ctx: &Context, sys: &TransitionSystem, wit: &Witness) -> bool {
+    let mut sim = Interpreter::new(ctx, sys);
+    sim.init(InitKind::Zero);
+    // states with an init expression are initialized by the simulator
+    for (state, value) in sys.states.iter().zip(wit.init.iter()) {
+        if let (None, InitValue::BitVec(value)) = (state.init, value) {
+            sim.set(state.symbol, value).unwrap();
+        }
+    }
+    let last = wit.inputs.len().saturating_sub(1);
+    for (k, inputs) in wit.inputs.iter().enumerate() {
+        for (&input, value) in sys.inputs.iter().zip(inputs.iter()) {
+            if let Some(Value::BitVec(value)) = value {
+                sim.set(input, value).unwrap();
+            }
+        }
+        if !sys.constraints.iter().all(|&c| holds(&sim, c)) {
+            return false;
+        }
+        if k == last {
+            return wit
+                .failed_safety
+                .iter()
+                .any(|&ii| holds(&sim, sys.bad_states[ii as usize]));
+        }
+        sim.step();
+    }
+    false
+}
+
+fn holds(sim: &impl Simulator, e: ExprRef) -> bool {
+    match sim.get(e) {
+        Value::BitVec(value) => value.is_true(),
+        Value::Array(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mc::SmtModelCheckerOptions;
+    use crate::smt::{BITWUZLA, YICES2};
+
+    fn checker_with(solver: crate::smt::SmtLibSolver) -> SmtModelChecker<crate::smt::SmtLibSolver> {
+        let opts = SmtModelCheckerOptions {
+            check_constraints: false,
+            check_bad_states_individually: false,
+            save_smt_replay: false,
+            log_queries: false,
+        };
+        SmtModelChecker::new(solver, opts)
+    }
+
+    fn checker() -> SmtModelChecker<crate::smt::SmtLibSolver> {
+        checker_with(BITWUZLA)
+    }
+
+    fn system_with_bad_state(
+        ctx: &mut Context,
+        bad: impl FnOnce(&mut Context, ExprRef) -> ExprRef,
+    ) -> TransitionSystem {
+        let mut sys = TransitionSystem::new("test".to_string());
+        let a = ctx.bv_symbol("a", 8);
+        sys.add_input(ctx, a);
+        let bad = bad(ctx, a);
+        sys.bad_states.push(bad);
+        sys
+    }
+
+    #[test]
+    fn test_spurious_counterexample_is_refined() {
+        let mut ctx = Context::default();
+        // a * 0 is never one, but an uninterpreted function could return anything
+        let sys = system_with_bad_state(&mut ctx, |ctx, a| {
+            ctx.build(|c| c.equal(c.mul(a, c.zero(8)), c.one(8)))
+        });
+        let run = checker()
+            .check_with_abstraction(&mut ctx, &sys, 2, &CegarOptions::default())
+            .unwrap();
+        assert!(matches!(run.result, ModelCheckResult::Success));
+        assert_eq!(run.spurious, 1);
+        assert_eq!(run.refined, ["__abs_mul8"]);
+    }
+
+    #[test]
+    fn test_real_counterexample_is_reported() {
+        let mut ctx = Context::default();
+        let sys = system_with_bad_state(&mut ctx, |ctx, a| {
+            ctx.build(|c| c.equal(c.mul(a, c.bit_vec_val(2, 8)), c.bit_vec_val(6, 8)))
+        });
+        let run = checker()
+            .check_with_abstraction(&mut ctx, &sys, 2, &CegarOptions::default())
+            .unwrap();
+        let ModelCheckResult::Fail(wit) = run.result else {
+            panic!("expected a counterexample");
+        };
+        assert!(is_real_counterexample(&ctx, &sys, &wit));
+    }
+
+    #[test]
+    fn test_solver_without_uf_support() {
+        let mut ctx = Context::default();
+        let sys = system_with_bad_state(&mut ctx, |ctx, a| {
+            ctx.build(|c| c.equal(c.mul(a, c.zero(8)), c.one(8)))
+        });
+        let err = checker_with(YICES2)
+            .check_with_abstraction(&mut ctx, &sys, 2, &CegarOptions::default())
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            crate::smt::Error::UninterpretedFunctionsUnsupported(_)
+        ));
+    }
+}
@@ -0,0 +1,193 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Witness Completion
+//! Failures observed in the field often come with a partial trace: only some signals were
+//! logged and the rest of the inputs and the starting state are unknown. Completing such a
+//! trace asks the solver for values of all missing inputs and states, such that the result is
+//! an execution of the system that satisfies all constraints. The completed witness can then
+//! be replayed in a simulator like any other counterexample.
+
+use crate::expr::{Context, ExprRef};
+use crate::mc::smt::{check_assuming, TransitionSystemEncoding, UnrollSmtEncoding};
+use crate::mc::{InitValue, SmtModelChecker, Witness};
+use crate::smt::{CheckSatResponse, Solver, SolverContext};
+use crate::system::analysis::count_expr_uses;
+use crate::system::TransitionSystem;
+use baa::{ArrayOps, Value};
+
+type Result<T> = crate::smt::Result<T>;
+
+impl<S: Solver<std::fs::File>> SmtModelChecker<S> {
+    /// Fills in all unknown init and input values of `partial`. Known values are kept as they
+    /// are and the length of the trace is given by `partial.inputs`. Returns `None` if no
+    /// execution of `sys` is consistent with the known values.
+    /// Bad states do not need to be reached, `failed_safety` of the result lists the ones that
+    /// are violated in the last step.
+    pub fn complete_witness(
+        &self,
+        ctx: &mut Context,
+        sys: &TransitionSystem,
+        partial: &Witness,
+    ) -> Result<Option<Witness>> {
+        assert!(
+            partial.init.is_empty() || partial.init.len() == sys.states.len(),
+            "expected {} init values, got {}",
+            sys.states.len(),
+            partial.init.len()
+        );
+        for (k, inputs) in partial.inputs.iter().enumerate() {
+            assert_eq!(
+                inputs.len(),
+                sys.inputs.len(),
+                "wrong number of input values in step {k}"
+            );
+        }
+        let k_max = partial.inputs.len().saturating_sub(1) as u64;
+
+        let mut smt_ctx = self.start_solver()?;
+        let mut enc = UnrollSmtEncoding::new(ctx, sys, false);
+        enc.define_header(ctx, &mut smt_ctx)?;
+        enc.init_at(ctx, &mut smt_ctx, 0)?;
+        for k in 0..=k_max {
+            if k > 0 {
+                enc.unroll(ctx, &mut smt_ctx)?;
+            }
+            for &constraint in sys.constraints.iter() {
+                let expr = enc.get_at(ctx, constraint, k);
+                smt_ctx.assert(ctx, expr)?;
+            }
+        }
+
+        let mut known = vec![];
+        for (state, value) in sys.states.iter().zip(partial.init.iter()) {
+            let symbol = enc.get_at(ctx, state.symbol, 0);
+            known.extend(init_value_is(ctx, symbol, value));
+        }
+        for (k, inputs) in partial.inputs.iter().enumerate() {
+            for (&input, value) in sys.inputs.iter().zip(inputs.iter()) {
+                if let Some(value) = value {
+                    let symbol = enc.get_at(ctx, input, k as u64);
+                    let lit = ctx.lit(value);
+                    known.push(ctx.equal(symbol, lit));
+                }
+            }
+        }
+
+        if check_assuming(ctx, &mut smt_ctx, known)? != CheckSatResponse::Sat {
+            return Ok(None);
+        }
+        let use_counts = count_expr_uses(ctx, sys);
+        let wit = self.get_witness(
+            sys,
+            ctx,
+            &use_counts,
+            &mut smt_ctx,
+            &enc,
+            k_max,
+            &sys.bad_states,
+        )?;
+        Ok(Some(wit))
+    }
+}
+
+/// Constraints that force `symbol` to the known parts of `value`. For arrays, only the
+/// relevant indices are fixed, unless there are none.
+fn init_value_is(ctx: &mut Context, symbol: ExprRef, value: &InitValue) -> Vec<ExprRef> {
+    match value {
+        InitValue::None => vec![],
+        InitValue::BitVec(value) => {
+            let lit = ctx.bv_lit(value);
+            vec![ctx.equal(symbol, lit)]
+        }
+        InitValue::Array(value, indices) if indices.is_empty() => {
+            let lit = ctx.lit(Value::Array(value.clone()));
+            vec![ctx.equal(symbol, lit)]
+        }
+        InitValue::Array(value, indices) => indices
+            .iter()
+            .map(|index| {
+                let data = value.select(index);
+                ctx.build(|c| c.equal(c.array_read(symbol, c.bv_lit(index)), c.bv_lit(&data)))
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mc::SmtModelCheckerOptions;
+    use crate::smt::BITWUZLA;
+    use crate::system::State;
+    use baa::{BitVecOps, BitVecValue};
+
+    fn checker() -> SmtModelChecker<crate::smt::SmtLibSolver> {
+        let opts = SmtModelCheckerOptions {
+            check_constraints: false,
+            check_bad_states_individually: false,
+            save_smt_replay: false,
+            log_queries: false,
+        };
+        SmtModelChecker::new(BITWUZLA, opts)
+    }
+
+    #[test]
+    fn test_complete_witness() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("acc".to_string());
+        let a = ctx.bv_symbol("a", 8);
+        let b = ctx.bv_symbol("b", 8);
+        sys.add_input(&ctx, a);
+        sys.add_input(&ctx, b);
+        let acc = ctx.bv_symbol("acc", 8);
+        let next = ctx.build(|c| c.add(acc, c.add(a, b)));
+        let init = ctx.zero(8);
+        sys.add_state(
+            &ctx,
+            State {
+                symbol: acc,
+                init: Some(init),
+                next: Some(next),
+            },
+        );
+        // b is never larger than 3
+        let small = ctx.build(|c| c.greater_or_equal(c.bit_vec_val(3, 8), b));
+        sys.constraints.push(small);
+        let bad = ctx.build(|c| c.equal(acc, c.bit_vec_val(20, 8)));
+        sys.bad_states.push(bad);
+
+        // only `a` was logged
+        let val = |v: u64| Some(Value::BitVec(BitVecValue::from_u64(v, 8)));
+        let partial = Witness {
+            inputs: vec![vec![val(8), None], vec![val(7), None], vec![None, None]],
+            ..Default::default()
+        };
+        let wit = checker()
+            .complete_witness(&mut ctx, &sys, &partial)
+            .unwrap()
+            .expect("a completion exists");
+        assert_eq!(wit.inputs.len(), 3);
+        assert_eq!(wit.init.len(), 1);
+        let get = |v: &Option<Value>| match v {
+            Some(Value::BitVec(v)) => v.to_u64().unwrap(),
+            _ => panic!("missing value"),
+        };
+        assert_eq!(get(&wit.inputs[0][0]), 8);
+        assert_eq!(get(&wit.inputs[1][0]), 7);
+        for inputs in wit.inputs.iter() {
+            assert!(get(&inputs[1]) <= 3);
+        }
+
+        // a logged value that violates the constraint cannot be part of any execution
+        let partial = Witness {
+            inputs: vec![vec![val(8), val(5)]],
+            ..Default::default()
+        };
+        let result = checker()
+            .complete_witness(&mut ctx, &sys, &partial)
+            .unwrap();
+        assert!(result.is_none());
+    }
+}
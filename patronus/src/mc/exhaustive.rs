@@ -0,0 +1,265 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Bounded Exhaustive Simulation
+//! Explores all input combinations breadth-first up to a fixed depth with the interpreter.
+//! No solver is involved, which makes this the easiest checker to trust. It is only feasible
+//! for tiny control blocks with few input bits.
+
+use crate::expr::{Context, ExprRef, TypeCheck, WidthInt};
+use crate::mc::{InitValue, ModelCheckResult, Witness};
+use crate::sim::{InitKind, Interpreter, Simulator};
+use crate::system::TransitionSystem;
+use baa::{BitVecOps, BitVecValue, Value};
+use rustc_hash::FxHashSet;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExhaustiveOptions {
+    /// Maximum number of input bits that are enumerated in every step. Uninitialized states
+    /// are enumerated in the first step and count towards this limit as well.
+    pub max_input_bits: WidthInt,
+}
+
+impl Default for ExhaustiveOptions {
+    fn default() -> Self {
+        Self { max_input_bits: 16 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ExhaustiveError {
+    #[error("{bits} bits need to be enumerated, more than the limit of {limit}")]
+    TooManyBits { bits: WidthInt, limit: WidthInt },
+    #[error("array `{0}` cannot be enumerated")]
+    Array(String),
+}
+
+/// A state reached after some number of steps together with the inputs that lead to it.
+struct Node {
+    snapshot: u32,
+    /// snapshot of the initial state
+    root: u32,
+    inputs: Vec<Vec<Option<Value>>>,
+}
+
+/// Checks all bad states for every input sequence of up to `k_max + 1` steps. Reached states
+/// are only explored once.
+pub fn check_exhaustive(
+    ctx: &Context,
+    sys: &TransitionSystem,
+    k_max: u64,
+    opts: ExhaustiveOptions,
+) -> Result<ModelCheckResult, ExhaustiveError> {
+    let inputs = bv_symbols(ctx, sys.inputs.iter().copied())?;
+    // uninitialized arrays are zero, since we cannot enumerate them
+    let uninit = bv_symbols(
+        ctx,
+        sys.states
+            .iter()
+            .filter(|s| s.init.is_none() && s.symbol.get_type(ctx).is_bit_vector())
+            .map(|s| s.symbol),
+    )?;
+    let input_bits = total_bits(&inputs);
+    let first_bits = input_bits + total_bits(&uninit);
+    if first_bits > opts.max_input_bits.min(63) {
+        return Err(ExhaustiveError::TooManyBits {
+            bits: first_bits,
+            limit: opts.max_input_bits,
+        });
+    }
+    // states can only be deduplicated if they can be compared
+    let dedup = sys
+        .states
+        .iter()
+        .all(|s| s.symbol.get_type(ctx).is_bit_vector());
+
+    let mut sim = Interpreter::new(ctx, sys);
+    let mut visited = FxHashSet::default();
+    let mut frontier = vec![];
+    for assignment in 0..(1u64 << total_bits(&uninit)) {
+        sim.init(InitKind::Zero);
+        apply(&mut sim, &uninit, assignment);
+        if dedup && !visited.insert(state_key(sys, &sim)) {
+            continue;
+        }
+        let snapshot = sim.take_snapshot();
+        frontier.push(Node {
+            snapshot,
+            root: snapshot,
+            inputs: vec![],
+        });
+    }
+
+    for _ in 0..=k_max {
+        let mut next = vec![];
+        for node in frontier.iter() {
+            for assignment in 0..(1u64 << input_bits) {
+                sim.restore_snapshot(node.snapshot).unwrap();
+                let values = apply(&mut sim, &inputs, assignment);
+                if !sys.constraints.iter().all(|&c| holds(&sim, c)) {
+                    continue;
+                }
+                let failed: Vec<u32> = (0..sys.bad_states.len() as u32)
+                    .filter(|&ii| holds(&sim, sys.bad_states[ii as usize]))
+                    .collect();
+                if !failed.is_empty() {
+                    let mut trace = node.inputs.clone();
+                    trace.push(values);
+                    sim.restore_snapshot(node.root).unwrap();
+                    let init = initial_state(sys, &sim);
+                    return Ok(ModelCheckResult::Fail(make_witness(
+                        ctx, sys, init, trace, failed,
+                    )));
+                }
+                sim.step();
+                if dedup && !visited.insert(state_key(sys, &sim)) {
+                    continue;
+                }
+                let mut trace = node.inputs.clone();
+                trace.push(values);
+                next.push(Node {
+                    snapshot: sim.take_snapshot(),
+                    root: node.root,
+                    inputs: trace,
+                });
+            }
+        }
+        if next.is_empty() {
+            break;
+        }
+        frontier = next;
+    }
+    Ok(ModelCheckResult::Success)
+}
+
+fn bv_symbols(
+    ctx: &Context,
+    symbols: impl Iterator<Item = ExprRef>,
+) -> Result<Vec<(ExprRef, WidthInt)>, ExhaustiveError> {
+    symbols
+        .map(|s| {
+            s.get_bv_type(ctx)
+                .map(|w| (s, w))
+                .ok_or_else(|| ExhaustiveError::Array(ctx.get_symbol_name(s).unwrap().into()))
+        })
+        .collect()
+}
+
+fn total_bits(symbols: &[(ExprRef, WidthInt)]) -> WidthInt {
+    symbols.iter().map(|(_, w)| *w).sum()
+}
+
+/// Distributes the bits of `assignment` over `symbols` and returns the assigned values.
+fn apply(
+    sim: &mut Interpreter,
+    symbols: &[(ExprRef, WidthInt)],
+    mut assignment: u64,
+) -> Vec<Option<Value>> {
+    symbols
+        .iter()
+        .map(|&(symbol, width)| {
+            let value = BitVecValue::from_u64(assignment & ((1u64 << width) - 1), width);
+            assignment >>= width;
+            sim.set(symbol, &value).unwrap();
+            Some(Value::BitVec(value))
+        })
+        .collect()
+}
+
+pub(super) fn holds(sim: &impl Simulator, e: ExprRef) -> bool {
+    match sim.get(e) {
+        Value::BitVec(value) => value.is_true(),
+        Value::Array(_) => false,
+    }
+}
+
+fn state_key(sys: &TransitionSystem, sim: &impl Simulator) -> Vec<String> {
+    sys.states
+        .iter()
+        .map(|s| match sim.get(s.symbol) {
+            Value::BitVec(value) => value.to_hex_str(),
+            Value::Array(_) => unreachable!("arrays are never deduplicated"),
+        })
+        .collect()
+}
+
+pub(super) fn initial_state(sys: &TransitionSystem, sim: &impl Simulator) -> Vec<InitValue> {
+    sys.states
+        .iter()
+        .map(|s| match sim.get(s.symbol) {
+            Value::BitVec(value) => InitValue::BitVec(value),
+            Value::Array(value) => InitValue::Array(value, vec![]),
+        })
+        .collect()
+}
+
+pub(super) fn make_witness(
+    ctx: &Context,
+    sys: &TransitionSystem,
+    init: Vec<InitValue>,
+    inputs: Vec<Vec<Option<Value>>>,
+    failed_safety: Vec<u32>,
+) -> Witness {
+    let name = |e: ExprRef| Some(ctx.get_symbol_name(e).unwrap().to_string());
+    Witness {
+        init,
+        init_names: sys.states.iter().map(|s| name(s.symbol)).collect(),
+        inputs,
+        input_names: sys.inputs.iter().map(|&i| name(i)).collect(),
+        failed_safety,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::examples::fifo;
+    use crate::system::State;
+
+    #[test]
+    fn test_fifo_is_safe() {
+        let (ctx, sys) = fifo(2, 1);
+        let res = check_exhaustive(&ctx, &sys, 3, ExhaustiveOptions::default()).unwrap();
+        assert!(matches!(res, ModelCheckResult::Success));
+    }
+
+    #[test]
+    fn test_counter_fails_at_depth() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("counter".into());
+        let en = ctx.bv_symbol("en", 1);
+        sys.add_input(&ctx, en);
+        let count = ctx.bv_symbol("count", 3);
+        let next = ctx.build(|c| c.ite(en, c.add(count, c.one(3)), count));
+        let init = ctx.zero(3);
+        sys.add_state(
+            &ctx,
+            State {
+                symbol: count,
+                init: Some(init),
+                next: Some(next),
+            },
+        );
+        let bad = ctx.build(|c| c.equal(count, c.bit_vec_val(5, 3)));
+        sys.bad_states.push(bad);
+
+        let opts = ExhaustiveOptions::default();
+        assert!(matches!(
+            check_exhaustive(&ctx, &sys, 4, opts).unwrap(),
+            ModelCheckResult::Success
+        ));
+        let ModelCheckResult::Fail(wit) = check_exhaustive(&ctx, &sys, 5, opts).unwrap() else {
+            panic!("expected a counterexample");
+        };
+        assert_eq!(wit.inputs.len(), 6);
+        assert_eq!(wit.failed_safety, [0]);
+        assert!(crate::mc::is_real_counterexample(&ctx, &sys, &wit));
+
+        let too_small = ExhaustiveOptions { max_input_bits: 0 };
+        assert!(matches!(
+            check_exhaustive(&ctx, &sys, 5, too_small),
+            Err(ExhaustiveError::TooManyBits { bits: 1, limit: 0 })
+        ));
+    }
+}
@@ -0,0 +1,172 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Gray-Box Checking
+//! Bounded model checking from the initial state only reaches bugs that are at most `k` steps
+//! deep and gets more expensive with every step. Concrete simulation on the other hand is
+//! cheap but unlikely to hit a specific corner case. Gray-box checking combines both: a random
+//! simulation prefix brings the system into a deep state, from which a short BMC suffix
+//! exhaustively explores all inputs. Repeating this from several random prefixes often finds
+//! bugs that are far out of reach for pure BMC.
+
+use crate::expr::{Context, ExprRef, TypeCheck, WidthInt};
+use crate::mc::exhaustive::{holds, initial_state, make_witness};
+use crate::mc::{ModelCheckResult, SmtModelChecker, Witness};
+use crate::random::{default_seed, new_rng};
+use crate::sim::{InitKind, Interpreter, Simulator};
+use crate::smt::Solver;
+use crate::system::TransitionSystem;
+use baa::{BitVecValue, Value};
+use rand::Rng;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GrayBoxOptions {
+    /// number of concrete steps before switching to BMC
+    pub prefix: u64,
+    /// bound for the symbolic check starting at the end of the prefix
+    pub suffix: u64,
+    /// number of random prefixes to try
+    pub runs: usize,
+    pub seed: u64,
+}
+
+impl Default for GrayBoxOptions {
+    fn default() -> Self {
+        Self {
+            prefix: 100,
+            suffix: 10,
+            runs: 10,
+            seed: default_seed(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GrayBoxReport {
+    /// number of prefixes that were simulated
+    pub runs: usize,
+    /// number of prefixes that were abandoned because they violated a constraint
+    pub discarded: usize,
+    /// A trace from the initial state to a bad state. The first inputs are the ones of the
+    /// simulation prefix.
+    pub witness: Option<Witness>,
+}
+
+/// Simulates random prefixes and then runs `checker` starting from the state reached at the
+/// end of each prefix. Stops at the first bad state found.
+pub fn check_gray_box<S: Solver<std::fs::File>>(
+    ctx: &mut Context,
+    sys: &TransitionSystem,
+    checker: &SmtModelChecker<S>,
+    opts: GrayBoxOptions,
+) -> crate::smt::Result<GrayBoxReport> {
+    let inputs: Vec<(ExprRef, WidthInt)> = sys
+        .inputs
+        .iter()
+        .flat_map(|&i| Some((i, i.get_bv_type(ctx)?)))
+        .collect();
+    let mut rng = new_rng(opts.seed);
+    let mut report = GrayBoxReport {
+        runs: 0,
+        discarded: 0,
+        witness: None,
+    };
+
+    'runs: for _ in 0..opts.runs {
+        report.runs += 1;
+        // the simulation borrows the context, thus we only extract values from it
+        let (init, mut trace, reached) = {
+            let mut sim = Interpreter::new(ctx, sys);
+            sim.init(InitKind::Random(rng.gen()));
+            let init = initial_state(sys, &sim);
+            let mut trace = vec![];
+            for _ in 0..opts.prefix {
+                let values = inputs
+                    .iter()
+                    .map(|&(input, width)| {
+                        let value = BitVecValue::random(&mut rng, width);
+                        sim.set(input, &value).unwrap();
+                        Some(Value::BitVec(value))
+                    })
+                    .collect();
+                trace.push(values);
+                if !sys.constraints.iter().all(|&c| holds(&sim, c)) {
+                    report.discarded += 1;
+                    continue 'runs;
+                }
+                let failed: Vec<u32> = (0..sys.bad_states.len() as u32)
+                    .filter(|&ii| holds(&sim, sys.bad_states[ii as usize]))
+                    .collect();
+                if !failed.is_empty() {
+                    report.witness = Some(make_witness(ctx, sys, init, trace, failed));
+                    return Ok(report);
+                }
+                sim.step();
+            }
+            let reached: Vec<Value> = sys.states.iter().map(|s| sim.get(s.symbol)).collect();
+            (init, trace, reached)
+        };
+
+        let mut restricted = sys.clone();
+        for (state, value) in restricted.states.iter_mut().zip(reached.iter()) {
+            state.init = Some(ctx.lit(value));
+        }
+        if let ModelCheckResult::Fail(wit) = checker.check(ctx, &restricted, opts.suffix)? {
+            trace.extend(wit.inputs);
+            report.witness = Some(make_witness(ctx, sys, init, trace, wit.failed_safety));
+            return Ok(report);
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::examples::lfsr;
+    use crate::mc::{is_real_counterexample, SmtModelCheckerOptions};
+    use crate::smt::BITWUZLA;
+
+    #[test]
+    fn test_deep_lfsr_bug() {
+        let (mut ctx, mut sys) = lfsr(8);
+        let state = sys.states[0].symbol;
+        // find the value of the LFSR after 30 steps
+        let deep = {
+            let mut sim = Interpreter::new(&ctx, &sys);
+            sim.init(InitKind::Zero);
+            for _ in 0..30 {
+                sim.step();
+            }
+            sim.get(state).try_into_u64().unwrap()
+        };
+        let bad = ctx.build(|c| c.equal(state, c.bit_vec_val(deep, 8)));
+        sys.bad_states.push(bad);
+
+        let checker = SmtModelChecker::new(
+            BITWUZLA,
+            SmtModelCheckerOptions {
+                check_constraints: false,
+                check_bad_states_individually: false,
+                save_smt_replay: false,
+                log_queries: false,
+            },
+        );
+        // plain BMC with the same bound as the suffix does not reach the bug
+        let res = checker.check(&mut ctx, &sys, 10).unwrap();
+        assert!(matches!(res, ModelCheckResult::Success));
+
+        let opts = GrayBoxOptions {
+            prefix: 25,
+            suffix: 10,
+            runs: 1,
+            seed: 1,
+        };
+        let report = check_gray_box(&mut ctx, &sys, &checker, opts).unwrap();
+        assert_eq!(report.runs, 1);
+        let wit = report.witness.unwrap();
+        assert_eq!(wit.inputs.len(), 31);
+        assert!(is_real_counterexample(&ctx, &sys, &wit));
+    }
+}
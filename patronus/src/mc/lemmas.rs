@@ -0,0 +1,211 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Lemmas
+//! Checks bad states one after the other. Once a property holds up to the bound, later checks
+//! may assume it as a constraint. Within the same bound this does not remove any trace and
+//! thus cannot hide a violation, but it often makes the remaining checks much cheaper.
+//! The order of checks and which properties were assumed are recorded in a lemma graph.
+
+use crate::expr::{Context, ExprRef};
+use crate::mc::{ModelCheckResult, PropertyStatus, SmtModelChecker};
+use crate::smt::Solver;
+use crate::system::TransitionSystem;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LemmaOptions {
+    /// Assume all properties that hold as constraints when checking later properties.
+    pub assume_proven: bool,
+}
+
+impl Default for LemmaOptions {
+    fn default() -> Self {
+        Self {
+            assume_proven: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LemmaNode {
+    /// index of the bad state
+    pub property: usize,
+    pub name: String,
+    pub status: PropertyStatus,
+    /// properties that were assumed while checking this one
+    pub assumed: Vec<usize>,
+}
+
+/// Properties in the order in which they were checked.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LemmaGraph {
+    pub nodes: Vec<LemmaNode>,
+}
+
+#[derive(Serialize)]
+struct RawNode<'a> {
+    property: usize,
+    name: &'a str,
+    status: &'static str,
+    fails_at: Option<u64>,
+    assumed: &'a [usize],
+}
+
+impl LemmaGraph {
+    pub fn get(&self, property: usize) -> Option<&LemmaNode> {
+        self.nodes.iter().find(|n| n.property == property)
+    }
+
+    pub fn to_json(&self) -> String {
+        let raw: Vec<RawNode> = self
+            .nodes
+            .iter()
+            .map(|n| RawNode {
+                property: n.property,
+                name: &n.name,
+                status: match n.status {
+                    PropertyStatus::Holds => "holds",
+                    PropertyStatus::Fails(_) => "fails",
+                },
+                fails_at: match n.status {
+                    PropertyStatus::Holds => None,
+                    PropertyStatus::Fails(step) => Some(step),
+                },
+                assumed: &n.assumed,
+            })
+            .collect();
+        serde_json::to_string_pretty(&raw).expect("lemma graphs can always be serialized")
+    }
+
+    /// Edges point from a lemma to every property that assumed it.
+    pub fn to_dot(&self) -> String {
+        let mut out = "digraph lemmas {\n".to_string();
+        for node in self.nodes.iter() {
+            let color = match node.status {
+                PropertyStatus::Holds => "green",
+                PropertyStatus::Fails(_) => "red",
+            };
+            out.push_str(&format!(
+                "  p{} [label=\"{}\", color={color}];\n",
+                node.property, node.name
+            ));
+        }
+        for node in self.nodes.iter() {
+            for lemma in node.assumed.iter() {
+                out.push_str(&format!("  p{lemma} -> p{};\n", node.property));
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+impl<S: Solver<std::fs::File>> SmtModelChecker<S> {
+    /// Checks the bad states in `order` one at a time. Bad states that are not part of `order`
+    /// are ignored.
+    pub fn check_with_lemmas(
+        &self,
+        ctx: &mut Context,
+        sys: &TransitionSystem,
+        k_max: u64,
+        order: &[usize],
+        opts: &LemmaOptions,
+    ) -> crate::smt::Result<LemmaGraph> {
+        let mut graph = LemmaGraph::default();
+        let mut proven: Vec<usize> = vec![];
+        for &property in order.iter() {
+            let bad = sys.bad_states[property];
+            let mut single = sys.clone();
+            single.bad_states = vec![bad];
+            let assumed = if opts.assume_proven {
+                proven.clone()
+            } else {
+                vec![]
+            };
+            for &lemma in assumed.iter() {
+                let holds = ctx.not(sys.bad_states[lemma]);
+                single.constraints.push(holds);
+            }
+            let status = match self.check(ctx, &single, k_max)? {
+                ModelCheckResult::Success => {
+                    proven.push(property);
+                    PropertyStatus::Holds
+                }
+                ModelCheckResult::Fail(wit) => PropertyStatus::Fails(wit.inputs.len() as u64 - 1),
+                ModelCheckResult::Cancelled(_) => unreachable!("no cancellation token"),
+            };
+            graph.nodes.push(LemmaNode {
+                property,
+                name: property_name(ctx, sys, property, bad),
+                status,
+                assumed,
+            });
+        }
+        Ok(graph)
+    }
+}
+
+fn property_name(ctx: &Context, sys: &TransitionSystem, property: usize, bad: ExprRef) -> String {
+    sys.names[bad]
+        .map(|n| ctx[n].clone())
+        .unwrap_or_else(|| format!("bad{property}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mc::SmtModelCheckerOptions;
+    use crate::smt::BITWUZLA;
+    use crate::system::State;
+
+    #[test]
+    fn test_check_with_lemmas() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("counter".into());
+        let count = ctx.bv_symbol("count", 4);
+        let next = ctx.build(|c| c.add(count, c.one(4)));
+        let init = ctx.zero(4);
+        sys.add_state(
+            &ctx,
+            State {
+                symbol: count,
+                init: Some(init),
+                next: Some(next),
+            },
+        );
+        for limit in [15u64, 3, 14] {
+            let bad = ctx.build(|c| c.equal(count, c.bit_vec_val(limit, 4)));
+            sys.bad_states.push(bad);
+        }
+        let checker = SmtModelChecker::new(
+            BITWUZLA,
+            SmtModelCheckerOptions {
+                check_constraints: false,
+                check_bad_states_individually: true,
+                save_smt_replay: false,
+                log_queries: false,
+            },
+        );
+        let graph = checker
+            .check_with_lemmas(&mut ctx, &sys, 5, &[0, 1, 2], &LemmaOptions::default())
+            .unwrap();
+        assert_eq!(graph.get(0).unwrap().status, PropertyStatus::Holds);
+        assert_eq!(graph.get(1).unwrap().status, PropertyStatus::Fails(3));
+        // failing properties are never assumed
+        assert_eq!(graph.get(2).unwrap().status, PropertyStatus::Holds);
+        assert_eq!(graph.get(2).unwrap().assumed, [0]);
+        assert!(graph.to_dot().contains("p0 -> p2;"));
+        let json: serde_json::Value = serde_json::from_str(&graph.to_json()).unwrap();
+        assert_eq!(json[1]["fails_at"], 3);
+
+        let opts = LemmaOptions {
+            assume_proven: false,
+        };
+        let graph = checker
+            .check_with_lemmas(&mut ctx, &sys, 5, &[0, 2], &opts)
+            .unwrap();
+        assert!(graph.nodes.iter().all(|n| n.assumed.is_empty()));
+    }
+}
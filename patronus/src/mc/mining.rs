@@ -0,0 +1,212 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Invariant Mining
+//! Proposes candidate invariants over the states of a system from simulation traces. Every
+//! candidate held in all observed steps, but that does not make it an invariant. Candidates
+//! need to be proven, e.g., by adding them as strengthening lemmas for k-induction.
+
+use crate::expr::{Context, ExprRef, TypeCheck, WidthInt};
+use crate::sim::Simulator;
+use crate::system::TransitionSystem;
+use baa::{BitVecOps, BitVecValue, Value};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CandidateKind {
+    /// the state always has the same value
+    Constant(ExprRef, BitVecValue),
+    /// two states always have the same value
+    Equal(ExprRef, ExprRef),
+    /// unsigned lower and upper bound of the state, at least one of them is not trivial
+    Range(ExprRef, u64, u64),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub kind: CandidateKind,
+    /// 1-bit expression that encodes the candidate
+    pub expr: ExprRef,
+}
+
+/// Ranges are only tracked for states up to this width.
+const MAX_RANGE_WIDTH: WidthInt = 64;
+
+struct Observed {
+    symbol: ExprRef,
+    width: WidthInt,
+    first: BitVecValue,
+    constant: bool,
+    min: u64,
+    max: u64,
+}
+
+/// Collects the values of all bit-vector states while one or more traces are simulated.
+pub struct InvariantMiner {
+    observed: Vec<Observed>,
+    /// pairs of states (indices into `observed`) that have been equal so far
+    equal: Vec<(usize, usize)>,
+    steps: u64,
+}
+
+impl InvariantMiner {
+    pub fn new(ctx: &Context, sys: &TransitionSystem) -> Self {
+        let observed: Vec<_> = sys
+            .states
+            .iter()
+            .flat_map(|s| {
+                let width = s.symbol.get_bv_type(ctx)?;
+                Some(Observed {
+                    symbol: s.symbol,
+                    width,
+                    first: BitVecValue::zero(width),
+                    constant: true,
+                    min: u64::MAX,
+                    max: 0,
+                })
+            })
+            .collect();
+        let mut equal = vec![];
+        for ii in 0..observed.len() {
+            for jj in (ii + 1)..observed.len() {
+                if observed[ii].width == observed[jj].width {
+                    equal.push((ii, jj));
+                }
+            }
+        }
+        Self {
+            observed,
+            equal,
+            steps: 0,
+        }
+    }
+
+    /// Records the current state of `sim`. Call this once per step of every trace, including
+    /// right after initialization.
+    pub fn observe(&mut self, sim: &impl Simulator) {
+        let values: Vec<BitVecValue> = self
+            .observed
+            .iter()
+            .map(|o| match sim.get(o.symbol) {
+                Value::BitVec(value) => value,
+                Value::Array(_) => unreachable!("only bit-vector states are observed"),
+            })
+            .collect();
+        for (o, value) in self.observed.iter_mut().zip(values.iter()) {
+            if self.steps == 0 {
+                o.first = value.clone();
+            } else if o.constant && o.first != *value {
+                o.constant = false;
+            }
+            if o.width <= MAX_RANGE_WIDTH {
+                let v = value.to_u64().unwrap();
+                o.min = o.min.min(v);
+                o.max = o.max.max(v);
+            }
+        }
+        self.equal.retain(|&(a, b)| values[a] == values[b]);
+        self.steps += 1;
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Turns everything that held in all observed steps into candidates. States that are
+    /// equal to a constant or to an earlier state only appear in that candidate.
+    pub fn candidates(&self, ctx: &mut Context) -> Vec<Candidate> {
+        if self.steps == 0 {
+            return vec![];
+        }
+        let mut out = vec![];
+        for o in self.observed.iter().filter(|o| o.constant) {
+            let expr = ctx.build(|c| c.equal(o.symbol, c.bv_lit(&o.first)));
+            out.push(Candidate {
+                kind: CandidateKind::Constant(o.symbol, o.first.clone()),
+                expr,
+            });
+        }
+        let mut redundant = vec![false; self.observed.len()];
+        for &(a, b) in self.equal.iter() {
+            // equalities between constants follow from the constant candidates
+            if self.observed[a].constant || redundant[b] {
+                continue;
+            }
+            redundant[b] = true;
+            let (a, b) = (self.observed[a].symbol, self.observed[b].symbol);
+            out.push(Candidate {
+                kind: CandidateKind::Equal(a, b),
+                expr: ctx.equal(a, b),
+            });
+        }
+        for (ii, o) in self.observed.iter().enumerate() {
+            if o.constant || redundant[ii] || o.width > MAX_RANGE_WIDTH {
+                continue;
+            }
+            let all_ones = u64::MAX >> (64 - o.width);
+            let lower = (o.min > 0).then(|| {
+                ctx.build(|c| c.greater_or_equal(o.symbol, c.bit_vec_val(o.min, o.width)))
+            });
+            let upper = (o.max < all_ones).then(|| {
+                ctx.build(|c| c.greater_or_equal(c.bit_vec_val(o.max, o.width), o.symbol))
+            });
+            let expr = match (lower, upper) {
+                (Some(l), Some(u)) => ctx.and(l, u),
+                (Some(e), None) | (None, Some(e)) => e,
+                (None, None) => continue,
+            };
+            out.push(Candidate {
+                kind: CandidateKind::Range(o.symbol, o.min, o.max),
+                expr,
+            });
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::examples::{fifo, input};
+    use crate::random::new_rng;
+    use crate::sim::{InitKind, Interpreter};
+    use rand::Rng;
+
+    #[test]
+    fn test_fifo_candidates() {
+        let (mut ctx, sys) = fifo(4, 8);
+        let mut miner = InvariantMiner::new(&ctx, &sys);
+        let (push, pop) = (input(&ctx, &sys, "push"), input(&ctx, &sys, "pop"));
+        {
+            let mut sim = Interpreter::new(&ctx, &sys);
+            let mut rng = new_rng(0);
+            for _ in 0..4 {
+                sim.init(InitKind::Zero);
+                miner.observe(&sim);
+                for _ in 0..20 {
+                    // only push, never pop
+                    sim.set(push, &BitVecValue::from_u64(rng.gen_range(0..2), 1))
+                        .unwrap();
+                    sim.set(pop, &BitVecValue::zero(1)).unwrap();
+                    sim.step();
+                    miner.observe(&sim);
+                }
+            }
+        }
+        assert_eq!(miner.steps(), 4 * 21);
+        let candidates = miner.candidates(&mut ctx);
+        let rd = sys.get_state_by_name(&ctx, "rd").unwrap().symbol;
+        // the read pointer never moves
+        assert!(candidates
+            .iter()
+            .any(|c| matches!(&c.kind, CandidateKind::Constant(s, v) if *s == rd && v.is_zero())));
+        // the write pointer stops once the FIFO is full
+        let wr = sys.get_state_by_name(&ctx, "wr").unwrap().symbol;
+        assert!(candidates
+            .iter()
+            .any(|c| c.kind == CandidateKind::Range(wr, 0, 4)));
+        for c in candidates.iter() {
+            assert!(c.expr.is_bool(&ctx));
+        }
+    }
+}
@@ -0,0 +1,19 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+use std::time::Duration;
+
+/// Gets notified periodically by long-running engines. All methods default to doing nothing,
+/// thus an observer only needs to implement the events it is interested in.
+pub trait ProgressObserver {
+    /// The model checker finished checking bound `k`.
+    /// `solver_time` is the time spent in the SMT solver for this bound.
+    fn bound_checked(&mut self, _k: u64, _solver_time: Duration) {}
+
+    /// An e-graph saturation iteration is about to start.
+    fn egraph_iteration(&mut self, _iteration: usize, _nodes: usize, _classes: usize) {}
+}
+
+/// Ignores all progress updates.
+impl ProgressObserver for () {}
@@ -0,0 +1,177 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Random Walks
+//! Many short randomized simulation runs from the initial state. Restarting frequently
+//! explores states close to init more evenly than one long run. Reached states are hashed in
+//! order to estimate how much of the reachable state space has been covered.
+
+use crate::expr::{Context, ExprRef, TypeCheck, WidthInt};
+use crate::mc::exhaustive::{holds, initial_state, make_witness};
+use crate::mc::Witness;
+use crate::random::{default_seed, new_rng};
+use crate::sim::{InitKind, Interpreter, Simulator};
+use crate::system::TransitionSystem;
+use baa::{BitVecOps, BitVecValue, Value};
+use rand::Rng;
+use rustc_hash::{FxHashSet, FxHasher};
+use std::hash::{Hash, Hasher};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WalkOptions {
+    /// number of restarts
+    pub walks: usize,
+    /// maximum number of steps per walk
+    pub depth: u64,
+    pub seed: u64,
+    /// end the exploration at the first bad state instead of counting all hits
+    pub stop_at_bad: bool,
+}
+
+impl Default for WalkOptions {
+    fn default() -> Self {
+        Self {
+            walks: 100,
+            depth: 100,
+            seed: default_seed(),
+            stop_at_bad: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WalkReport {
+    pub walks: usize,
+    pub steps: u64,
+    /// Number of distinct bit-vector state assignments seen. Array states are ignored.
+    pub distinct_states: usize,
+    /// Number of distinct states seen after each walk. Once this curve flattens out, most
+    /// states reachable by random inputs have likely been visited.
+    pub discovered: Vec<usize>,
+    /// Number of times each bad state was hit.
+    pub bad_hits: Vec<usize>,
+    /// The shortest trace to a bad state.
+    pub witness: Option<Witness>,
+}
+
+/// Runs randomized walks with restarts. A walk ends when it hits a bad state, when it violates
+/// a constraint or after `depth` steps.
+pub fn random_walks(ctx: &Context, sys: &TransitionSystem, opts: WalkOptions) -> WalkReport {
+    let inputs: Vec<(ExprRef, WidthInt)> = sys
+        .inputs
+        .iter()
+        .flat_map(|&i| Some((i, i.get_bv_type(ctx)?)))
+        .collect();
+    let mut rng = new_rng(opts.seed);
+    let mut sim = Interpreter::new(ctx, sys);
+    let mut visited = FxHashSet::default();
+    let mut report = WalkReport {
+        walks: 0,
+        steps: 0,
+        distinct_states: 0,
+        discovered: vec![],
+        bad_hits: vec![0; sys.bad_states.len()],
+        witness: None,
+    };
+
+    'walks: for _ in 0..opts.walks {
+        report.walks += 1;
+        sim.init(InitKind::Random(rng.gen()));
+        let init = initial_state(sys, &sim);
+        let mut trace = vec![];
+        for _ in 0..=opts.depth {
+            visited.insert(state_hash(sys, &sim));
+            let values = inputs
+                .iter()
+                .map(|&(input, width)| {
+                    let value = BitVecValue::random(&mut rng, width);
+                    sim.set(input, &value).unwrap();
+                    Some(Value::BitVec(value))
+                })
+                .collect();
+            trace.push(values);
+            if !sys.constraints.iter().all(|&c| holds(&sim, c)) {
+                break;
+            }
+            let failed: Vec<u32> = (0..sys.bad_states.len() as u32)
+                .filter(|&ii| holds(&sim, sys.bad_states[ii as usize]))
+                .collect();
+            if !failed.is_empty() {
+                for &ii in failed.iter() {
+                    report.bad_hits[ii as usize] += 1;
+                }
+                let shorter = report
+                    .witness
+                    .as_ref()
+                    .map_or(true, |w| w.inputs.len() > trace.len());
+                if shorter {
+                    report.witness = Some(make_witness(ctx, sys, init, trace, failed));
+                }
+                report.discovered.push(visited.len());
+                if opts.stop_at_bad {
+                    break 'walks;
+                }
+                continue 'walks;
+            }
+            sim.step();
+            report.steps += 1;
+        }
+        report.discovered.push(visited.len());
+    }
+    report.distinct_states = visited.len();
+    report
+}
+
+fn state_hash(sys: &TransitionSystem, sim: &impl Simulator) -> u64 {
+    let mut hasher = FxHasher::default();
+    for state in sys.states.iter() {
+        if let Value::BitVec(value) = sim.get(state.symbol) {
+            value.to_hex_str().hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::examples::{fifo, lfsr};
+
+    #[test]
+    fn test_fifo_never_fails() {
+        let (ctx, sys) = fifo(4, 2);
+        let report = random_walks(&ctx, &sys, WalkOptions::default());
+        assert_eq!(report.walks, 100);
+        assert_eq!(report.bad_hits, [0]);
+        assert!(report.witness.is_none());
+        assert!(report.distinct_states > 1);
+        assert_eq!(report.discovered.len(), 100);
+        assert!(report.discovered.windows(2).all(|w| w[0] <= w[1]));
+    }
+
+    #[test]
+    fn test_lfsr() {
+        let (mut ctx, mut sys) = lfsr(8);
+        let state = sys.states[0].symbol;
+        let opts = WalkOptions {
+            walks: 10,
+            depth: 20,
+            seed: 7,
+            stop_at_bad: false,
+        };
+        // without inputs, every walk visits the same states
+        let report = random_walks(&ctx, &sys, opts);
+        assert_eq!(report.distinct_states, 21);
+        assert_eq!(report.discovered, [21; 10]);
+        assert_eq!(report.steps, 10 * 21);
+
+        let bad = ctx.build(|c| c.equal(state, c.bit_vec_val(4, 8)));
+        sys.bad_states.push(bad);
+        let report = random_walks(&ctx, &sys, opts);
+        assert_eq!(report.bad_hits, [10]);
+        let wit = report.witness.unwrap();
+        assert_eq!(wit.inputs.len(), 3);
+        assert!(crate::mc::is_real_counterexample(&ctx, &sys, &wit));
+    }
+}
@@ -0,0 +1,197 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Result Records
+//! A normalized JSON record of a single check, shared by all engines such that CI dashboards
+//! and experiment scripts can consume bounded model checking, induction, sequential
+//! equivalence and e-graph equivalence results in the same way. Witnesses are not embedded,
+//! the record only refers to the file or id under which the witness was saved.
+
+use crate::mc::ModelCheckResult;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Incremented whenever the meaning of a field changes.
+pub const RESULT_RECORD_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CheckKind {
+    Bmc,
+    Induction,
+    /// sequential equivalence of two systems
+    Sec,
+    EGraphEquiv,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    /// all properties hold or the designs are equivalent
+    Pass,
+    /// a property is violated or the designs differ
+    Fail,
+    /// the engine stopped without a verdict, e.g., because of a timeout
+    Unknown,
+    /// the engine could not be run
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResultRecord {
+    pub version: u32,
+    pub kind: CheckKind,
+    pub engine: String,
+    pub design: String,
+    pub status: CheckStatus,
+    /// human readable explanation of the status
+    pub detail: Option<String>,
+    /// no violation exists up to and including this step
+    pub proven_bound: Option<u64>,
+    /// largest step the engine was asked to check
+    pub max_bound: Option<u64>,
+    /// step in which a property is violated
+    pub fail_step: Option<u64>,
+    /// indices of the violated bad states
+    pub failed_properties: Vec<u32>,
+    /// wall clock time of each phase in seconds
+    pub timings: BTreeMap<String, f64>,
+    /// path or id of the saved witness
+    pub witness: Option<String>,
+    /// engine settings used for the check
+    pub config: serde_json::Value,
+}
+
+impl ResultRecord {
+    pub fn new(kind: CheckKind, engine: &str, design: &str, status: CheckStatus) -> Self {
+        Self {
+            version: RESULT_RECORD_VERSION,
+            kind,
+            engine: engine.to_string(),
+            design: design.to_string(),
+            status,
+            detail: None,
+            proven_bound: None,
+            max_bound: None,
+            fail_step: None,
+            failed_properties: vec![],
+            timings: BTreeMap::new(),
+            witness: None,
+            config: serde_json::Value::Null,
+        }
+    }
+
+    /// Summarizes the result of a bounded check up to `k_max`, e.g., BMC or a sequential
+    /// equivalence check of a miter.
+    pub fn from_model_check(
+        kind: CheckKind,
+        engine: &str,
+        design: &str,
+        res: &ModelCheckResult,
+        k_max: u64,
+    ) -> Self {
+        let status = match res {
+            ModelCheckResult::Success => CheckStatus::Pass,
+            ModelCheckResult::Fail(_) => CheckStatus::Fail,
+            ModelCheckResult::Cancelled(_) => CheckStatus::Unknown,
+        };
+        let mut record = Self::new(kind, engine, design, status);
+        record.max_bound = Some(k_max);
+        match res {
+            ModelCheckResult::Success => {
+                record.proven_bound = Some(k_max);
+            }
+            ModelCheckResult::Fail(wit) => {
+                let step = wit.inputs.len().saturating_sub(1) as u64;
+                record.fail_step = Some(step);
+                record.proven_bound = step.checked_sub(1);
+                record.failed_properties = wit.failed_safety.clone();
+            }
+            ModelCheckResult::Cancelled(steps) => {
+                record.proven_bound = steps.checked_sub(1);
+                record.detail = Some(format!("cancelled after checking {steps} steps"));
+            }
+        }
+        record
+    }
+
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    pub fn with_time(mut self, phase: &str, time: Duration) -> Self {
+        self.timings.insert(phase.to_string(), time.as_secs_f64());
+        self
+    }
+
+    pub fn with_witness(mut self, reference: impl Into<String>) -> Self {
+        self.witness = Some(reference.into());
+        self
+    }
+
+    pub fn with_config(mut self, config: &impl Serialize) -> Self {
+        self.config = serde_json::to_value(config).expect("configs can always be serialized");
+        self
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("records can always be serialized")
+    }
+
+    /// Serializes several records as a JSON array.
+    pub fn list_to_json(records: &[Self]) -> String {
+        serde_json::to_string_pretty(records).expect("records can always be serialized")
+    }
+
+    pub fn from_json(src: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(src)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::BmcConfig;
+    use crate::mc::Witness;
+
+    #[test]
+    fn test_record_roundtrip() {
+        let wit = Witness {
+            inputs: vec![vec![]; 3],
+            failed_safety: vec![1],
+            ..Default::default()
+        };
+        let record = ResultRecord::from_model_check(
+            CheckKind::Bmc,
+            "smt-bmc",
+            "count2",
+            &ModelCheckResult::Fail(wit),
+            10,
+        )
+        .with_time("check", Duration::from_millis(1500))
+        .with_witness("count2.wit")
+        .with_config(&BmcConfig::default());
+        assert_eq!(record.status, CheckStatus::Fail);
+        assert_eq!(record.fail_step, Some(2));
+        assert_eq!(record.proven_bound, Some(1));
+        assert_eq!(record.failed_properties, [1]);
+
+        let json = record.to_json();
+        assert!(json.contains("\"status\": \"fail\""));
+        assert!(json.contains("\"kind\": \"bmc\""));
+        assert_eq!(ResultRecord::from_json(&json).unwrap(), record);
+
+        let cancelled = ResultRecord::from_model_check(
+            CheckKind::Sec,
+            "sat-bmc",
+            "miter",
+            &ModelCheckResult::Cancelled(0),
+            10,
+        );
+        assert_eq!(cancelled.status, CheckStatus::Unknown);
+        assert_eq!(cancelled.proven_bound, None);
+    }
+}
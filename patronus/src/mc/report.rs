@@ -0,0 +1,287 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Counterexample Reports
+//! Replays a [`Witness`] and renders it as a self-contained HTML page. The page shows the
+//! values of all inputs, states and outputs in every step and highlights values that changed.
+//! For the violated property, every sub-expression gets its own row. In each step, the
+//! sub-expressions that determine the value of the property are highlighted, e.g., for a
+//! false `and` only the first false operand, and for a mux only the condition and the
+//! selected branch.
+
+use crate::expr::traversal::{top_down, TraversalCmd};
+use crate::expr::*;
+use crate::mc::{InitValue, Witness};
+use crate::sim::{InitKind, Interpreter, Simulator};
+use crate::system::TransitionSystem;
+use baa::{BitVecOps, Value};
+use rustc_hash::FxHashSet;
+use std::fmt::Write;
+
+/// Values of a single expression over all steps of a trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceRow {
+    pub name: String,
+    pub expr: ExprRef,
+    pub values: Vec<String>,
+    pub highlighted: Vec<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CexReport {
+    pub name: String,
+    /// index of the violated bad state
+    pub property: Option<u32>,
+    pub steps: usize,
+    /// inputs, states and outputs, highlighted when they change
+    pub signals: Vec<TraceRow>,
+    /// sub-expressions of the violated property, starting with the property itself and
+    /// highlighted when they determine its value
+    pub terms: Vec<TraceRow>,
+}
+
+impl CexReport {
+    pub fn new(ctx: &Context, sys: &TransitionSystem, wit: &Witness) -> Self {
+        let property = wit.failed_safety.first().copied();
+        let mut signals: Vec<TraceRow> = sys
+            .inputs
+            .iter()
+            .chain(sys.states.iter().map(|s| &s.symbol))
+            .map(|&e| row(ctx.get_symbol_name(e).unwrap().to_string(), e))
+            .chain(sys.outputs.iter().map(|o| row(ctx[o.name].clone(), o.expr)))
+            .collect();
+        let mut terms: Vec<TraceRow> = match property {
+            Some(ii) => {
+                let bad = sys.bad_states[ii as usize];
+                let mut exprs = vec![];
+                top_down(ctx, bad, |ctx, e| {
+                    if !ctx[e].is_bv_lit() {
+                        exprs.push(e);
+                    }
+                    TraversalCmd::Continue
+                });
+                // parents are always created after their children
+                exprs.sort_unstable_by(|a, b| b.cmp(a));
+                exprs.dedup();
+                exprs
+                    .into_iter()
+                    .map(|e| row(ctx[e].serialize_to_str(ctx), e))
+                    .collect()
+            }
+            None => vec![],
+        };
+
+        let mut sim = Interpreter::new(ctx, sys);
+        sim.init(InitKind::Zero);
+        // states with an init expression are initialized by the simulator
+        for (state, value) in sys.states.iter().zip(wit.init.iter()) {
+            if let (None, InitValue::BitVec(value)) = (state.init, value) {
+                sim.set(state.symbol, value).unwrap();
+            }
+        }
+        for (k, inputs) in wit.inputs.iter().enumerate() {
+            for (&input, value) in sys.inputs.iter().zip(inputs.iter()) {
+                if let Some(Value::BitVec(value)) = value {
+                    sim.set(input, value).unwrap();
+                }
+            }
+            for r in signals.iter_mut() {
+                let value = format_value(ctx, r.expr, &sim.get(r.expr));
+                let changed = k > 0 && r.values[k - 1] != value;
+                r.values.push(value);
+                r.highlighted.push(changed);
+            }
+            if let Some(root) = terms.first().map(|r| r.expr) {
+                let relevant = justify(ctx, &sim, root);
+                for r in terms.iter_mut() {
+                    r.values.push(format_value(ctx, r.expr, &sim.get(r.expr)));
+                    r.highlighted.push(relevant.contains(&r.expr));
+                }
+            }
+            sim.step();
+        }
+
+        let name = property.map(|ii| {
+            let bad = sys.bad_states[ii as usize];
+            sys.names[bad]
+                .map(|n| ctx[n].clone())
+                .unwrap_or_else(|| format!("bad{ii}"))
+        });
+        Self {
+            name: name.unwrap_or_default(),
+            property,
+            steps: wit.inputs.len(),
+            signals,
+            terms,
+        }
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut out = String::new();
+        out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        writeln!(out, "<title>Counterexample {}</title>", escape(&self.name)).unwrap();
+        out.push_str(STYLE);
+        out.push_str("</head>\n<body>\n");
+        match self.property {
+            Some(ii) => writeln!(
+                out,
+                "<h1>Property <code>{}</code> (bad state {ii}) fails after {} steps</h1>",
+                escape(&self.name),
+                self.steps.saturating_sub(1)
+            )
+            .unwrap(),
+            None => out.push_str("<h1>Trace</h1>\n"),
+        }
+        out.push_str("<h2>Signals</h2>\n");
+        self.table(&mut out, &self.signals, "changed");
+        if !self.terms.is_empty() {
+            out.push_str("<h2>Property</h2>\n");
+            self.table(&mut out, &self.terms, "relevant");
+        }
+        out.push_str("</body>\n</html>\n");
+        out
+    }
+
+    fn table(&self, out: &mut String, rows: &[TraceRow], class: &str) {
+        out.push_str("<table>\n<tr><th></th>");
+        for k in 0..self.steps {
+            write!(out, "<th>{k}</th>").unwrap();
+        }
+        out.push_str("</tr>\n");
+        for r in rows.iter() {
+            write!(out, "<tr><td><code>{}</code></td>", escape(&r.name)).unwrap();
+            for (value, &highlighted) in r.values.iter().zip(r.highlighted.iter()) {
+                if highlighted {
+                    write!(out, "<td class=\"{class}\">{}</td>", escape(value)).unwrap();
+                } else {
+                    write!(out, "<td>{}</td>", escape(value)).unwrap();
+                }
+            }
+            out.push_str("</tr>\n");
+        }
+        out.push_str("</table>\n");
+    }
+}
+
+const STYLE: &str = "<style>
+body { font-family: sans-serif; }
+table { border-collapse: collapse; font-family: monospace; }
+td, th { border: 1px solid #ccc; padding: 2px 6px; text-align: right; }
+td:first-child { text-align: left; }
+td.changed { background: #fff3b0; }
+td.relevant { background: #ffb3b3; }
+</style>
+";
+
+fn row(name: String, expr: ExprRef) -> TraceRow {
+    TraceRow {
+        name,
+        expr,
+        values: vec![],
+        highlighted: vec![],
+    }
+}
+
+fn format_value(ctx: &Context, e: ExprRef, value: &Value) -> String {
+    match value {
+        Value::BitVec(v) => ctx.format_value(e, v),
+        Value::Array(_) => "[array]".to_string(),
+    }
+}
+
+/// Sub-expressions of `root` that are needed to explain its current value.
+fn justify(ctx: &Context, sim: &impl Simulator, root: ExprRef) -> FxHashSet<ExprRef> {
+    let is_true = |e: ExprRef| matches!(sim.get(e), Value::BitVec(v) if v.is_true());
+    let mut out = FxHashSet::default();
+    let mut todo = vec![root];
+    while let Some(e) = todo.pop() {
+        if !out.insert(e) {
+            continue;
+        }
+        match ctx[e] {
+            // a single controlling operand is enough
+            Expr::BVAnd(a, b, 1) if !is_true(e) => {
+                todo.push(if is_true(a) { b } else { a });
+            }
+            Expr::BVOr(a, b, 1) if is_true(e) => {
+                todo.push(if is_true(a) { a } else { b });
+            }
+            Expr::BVIte { cond, tru, fals } => {
+                todo.push(cond);
+                todo.push(if is_true(cond) { tru } else { fals });
+            }
+            ref expr => expr.for_each_child(|&c| todo.push(c)),
+        }
+    }
+    out
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mc::{check_exhaustive, ExhaustiveOptions, ModelCheckResult};
+    use crate::system::State;
+
+    #[test]
+    fn test_counter_report() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("counter".into());
+        let en = ctx.bv_symbol("en", 1);
+        let other = ctx.bv_symbol("other", 1);
+        sys.add_input(&ctx, en);
+        sys.add_input(&ctx, other);
+        let count = ctx.bv_symbol("count", 2);
+        let next = ctx.build(|c| c.ite(en, c.add(count, c.one(2)), count));
+        let init = ctx.zero(2);
+        sys.add_state(
+            &ctx,
+            State {
+                symbol: count,
+                init: Some(init),
+                next: Some(next),
+            },
+        );
+        let is_three = ctx.build(|c| c.equal(count, c.bit_vec_val(3, 2)));
+        let bad = ctx.build(|c| c.or(is_three, c.and(other, c.not(other))));
+        sys.bad_states.push(bad);
+        sys.names[bad] = Some(ctx.string("count_lt_3".into()));
+
+        let ModelCheckResult::Fail(wit) =
+            check_exhaustive(&ctx, &sys, 5, ExhaustiveOptions::default()).unwrap()
+        else {
+            panic!("expected a counterexample");
+        };
+        let report = CexReport::new(&ctx, &sys, &wit);
+        assert_eq!(report.property, Some(0));
+        assert_eq!(report.steps, 4);
+        let count_row = report.signals.iter().find(|r| r.expr == count).unwrap();
+        assert_eq!(count_row.values, ["2'x0", "2'x1", "2'x2", "2'x3"]);
+        assert_eq!(count_row.highlighted, [false, true, true, true]);
+
+        assert_eq!(report.terms[0].expr, bad);
+        let relevant = |e: ExprRef| {
+            report
+                .terms
+                .iter()
+                .find(|r| r.expr == e)
+                .unwrap()
+                .highlighted[3]
+        };
+        assert!(relevant(is_three));
+        assert!(relevant(count));
+        // the `and` is false and does not contribute to the violation
+        assert!(!relevant(other));
+
+        let html = report.to_html();
+        assert!(html.contains("<code>count_lt_3</code>"));
+        assert!(html.contains("<td class=\"relevant\">1'x1</td>"));
+    }
+}
@@ -0,0 +1,261 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+use crate::expr::{Context, ExprRef};
+use crate::mc::{InitValue, ModelCheckResult, Witness};
+use crate::sat::{decode, BitBlaster, BlastError, Cnf, Lit, Model, SatSolver};
+use crate::system::TransitionSystem;
+use baa::Value;
+
+/// Bounded model checking by bit-blasting the unrolled system. Does not need an SMT solver.
+/// Returns the shortest counterexample, just like [`crate::mc::SmtModelChecker`].
+pub fn check_with_sat(
+    ctx: &Context,
+    sys: &TransitionSystem,
+    k_max: u64,
+) -> Result<ModelCheckResult, BlastError> {
+    let mut unroller = Unroller::new(ctx, sys)?;
+    let mut solver = SatSolver::default();
+    for k in 0..=k_max {
+        let bad = unroller.step()?;
+        let any_bad = unroller.blaster.or_all(&bad);
+        if let Some(model) = solver.solve(unroller.blaster.cnf(), &[any_bad]) {
+            return Ok(ModelCheckResult::Fail(unroller.witness(&model, &bad)));
+        }
+        if k < k_max {
+            unroller.next()?;
+        }
+    }
+    Ok(ModelCheckResult::Success)
+}
+
+/// Encodes whether a bad state can be reached in exactly `k` steps. The result is
+/// satisfiable iff a counterexample of that length exists and can be exported to DIMACS.
+pub fn encode_bmc(ctx: &Context, sys: &TransitionSystem, k: u64) -> Result<Cnf, BlastError> {
+    let mut unroller = Unroller::new(ctx, sys)?;
+    for _ in 0..k {
+        unroller.step()?;
+        unroller.next()?;
+    }
+    let bad = unroller.step()?;
+    let any_bad = unroller.blaster.or_all(&bad);
+    let mut cnf = unroller.blaster.into_cnf();
+    cnf.add_clause(&[any_bad]);
+    Ok(cnf)
+}
+
+struct Unroller<'a> {
+    ctx: &'a Context,
+    sys: &'a TransitionSystem,
+    blaster: BitBlaster<'a>,
+    init: Vec<Vec<Lit>>,
+    /// state values in the current step
+    states: Vec<Vec<Lit>>,
+    /// input values of all steps
+    inputs: Vec<Vec<Vec<Lit>>>,
+    /// number of calls to [`Unroller::step`]
+    steps: u64,
+}
+
+impl<'a> Unroller<'a> {
+    fn new(ctx: &'a Context, sys: &'a TransitionSystem) -> Result<Self, BlastError> {
+        let mut blaster = BitBlaster::new(ctx);
+        // the states and inputs of step 0 are bound before blasting the init expressions,
+        // since those may refer to them
+        let states = sys
+            .states
+            .iter()
+            .map(|state| blaster.bits(state.symbol))
+            .collect::<Result<Vec<_>, _>>()?;
+        let inputs = sys
+            .inputs
+            .iter()
+            .map(|&input| blaster.bits(input))
+            .collect::<Result<Vec<_>, _>>()?;
+        for (state, bits) in sys.states.iter().zip(states.iter()) {
+            if let Some(init) = state.init {
+                let init = blaster.bits(init)?;
+                blaster.assert_equal(bits, &init);
+            }
+        }
+        Ok(Self {
+            ctx,
+            sys,
+            blaster,
+            init: states.clone(),
+            states,
+            inputs: vec![inputs],
+            steps: 0,
+        })
+    }
+
+    /// Creates the inputs of the current step, asserts all constraints and returns one
+    /// literal per bad state.
+    fn step(&mut self) -> Result<Vec<Lit>, BlastError> {
+        // the symbols of step 0 were already bound by `Unroller::new`
+        if self.steps > 0 {
+            self.blaster.new_frame();
+            for (state, bits) in self.sys.states.iter().zip(self.states.iter()) {
+                self.blaster.bind(state.symbol, bits.clone());
+            }
+            let inputs = self
+                .sys
+                .inputs
+                .iter()
+                .map(|&input| self.blaster.bits(input))
+                .collect::<Result<_, _>>()?;
+            self.inputs.push(inputs);
+        }
+        self.steps += 1;
+        for &constraint in self.sys.constraints.iter() {
+            self.blaster.assert(constraint)?;
+        }
+        self.sys
+            .bad_states
+            .iter()
+            .map(|&bad| self.blaster.bit(bad))
+            .collect()
+    }
+
+    /// Advances to the next step. Must be called after [`Unroller::step`].
+    fn next(&mut self) -> Result<(), BlastError> {
+        let mut next_states = vec![];
+        for (state, bits) in self.sys.states.iter().zip(self.states.iter()) {
+            let next = match state.next {
+                // constant states keep their bits, no need to blast the next function
+                Some(_) if state.is_const() => bits.clone(),
+                Some(next) => self.blaster.bits(next)?,
+                // without a next state function, the state can take on any value
+                None => self.blaster.fresh(bits.len() as u32),
+            };
+            next_states.push(next);
+        }
+        self.states = next_states;
+        Ok(())
+    }
+
+    fn witness(&self, model: &Model, bad: &[Lit]) -> Witness {
+        let name = |e: ExprRef| Some(self.ctx.get_symbol_name(e).unwrap().to_string());
+        Witness {
+            init: self
+                .init
+                .iter()
+                .map(|bits| InitValue::BitVec(decode(model, bits)))
+                .collect(),
+            init_names: self.sys.states.iter().map(|s| name(s.symbol)).collect(),
+            inputs: self
+                .inputs
+                .iter()
+                .map(|step| {
+                    step.iter()
+                        .map(|bits| Some(Value::BitVec(decode(model, bits))))
+                        .collect()
+                })
+                .collect(),
+            input_names: self.sys.inputs.iter().map(|&i| name(i)).collect(),
+            failed_safety: (0..bad.len() as u32)
+                .filter(|&ii| model.value(bad[ii as usize]))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mc::is_real_counterexample;
+    use crate::system::State;
+
+    fn counter(ctx: &mut Context) -> TransitionSystem {
+        let mut sys = TransitionSystem::new("counter".into());
+        let en = ctx.bv_symbol("en", 1);
+        sys.add_input(ctx, en);
+        let count = ctx.bv_symbol("count", 4);
+        let next = ctx.build(|c| c.ite(en, c.add(count, c.one(4)), count));
+        let init = ctx.zero(4);
+        sys.add_state(
+            ctx,
+            State {
+                symbol: count,
+                init: Some(init),
+                next: Some(next),
+            },
+        );
+        let bad = ctx.build(|c| c.equal(count, c.bit_vec_val(5, 4)));
+        sys.bad_states.push(bad);
+        sys
+    }
+
+    #[test]
+    fn test_counter() {
+        let mut ctx = Context::default();
+        let sys = counter(&mut ctx);
+        assert!(matches!(
+            check_with_sat(&ctx, &sys, 4).unwrap(),
+            ModelCheckResult::Success
+        ));
+        let ModelCheckResult::Fail(wit) = check_with_sat(&ctx, &sys, 10).unwrap() else {
+            panic!("expected a counterexample");
+        };
+        assert_eq!(wit.inputs.len(), 6);
+        assert!(is_real_counterexample(&ctx, &sys, &wit));
+    }
+
+    #[test]
+    fn test_init_depends_on_state() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("init".into());
+        let a = ctx.bv_symbol("a", 4);
+        let b = ctx.bv_symbol("b", 4);
+        let a_init = ctx.bit_vec_val(3, 4);
+        let b_init = ctx.build(|c| c.add(a, c.one(4)));
+        sys.add_state(
+            &ctx,
+            State {
+                symbol: a,
+                init: Some(a_init),
+                next: Some(a),
+            },
+        );
+        sys.add_state(
+            &ctx,
+            State {
+                symbol: b,
+                init: Some(b_init),
+                next: Some(b),
+            },
+        );
+        let bad = ctx.build(|c| c.not(c.equal(b, c.bit_vec_val(4, 4))));
+        sys.bad_states.push(bad);
+        assert!(matches!(
+            check_with_sat(&ctx, &sys, 3).unwrap(),
+            ModelCheckResult::Success
+        ));
+
+        let bad = ctx.build(|c| c.equal(b, c.bit_vec_val(4, 4)));
+        sys.bad_states = vec![bad];
+        let ModelCheckResult::Fail(wit) = check_with_sat(&ctx, &sys, 0).unwrap() else {
+            panic!("expected a counterexample");
+        };
+        assert!(is_real_counterexample(&ctx, &sys, &wit));
+    }
+
+    #[test]
+    fn test_encode_bmc() {
+        let mut ctx = Context::default();
+        let sys = counter(&mut ctx);
+        let mut solver = SatSolver::default();
+        assert!(solver
+            .solve(&encode_bmc(&ctx, &sys, 4).unwrap(), &[])
+            .is_none());
+        let mut solver = SatSolver::default();
+        assert!(solver
+            .solve(&encode_bmc(&ctx, &sys, 7).unwrap(), &[])
+            .is_some());
+        assert!(encode_bmc(&ctx, &sys, 1)
+            .unwrap()
+            .to_dimacs()
+            .starts_with("p cnf "));
+    }
+}
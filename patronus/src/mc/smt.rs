@@ -6,12 +6,16 @@
 use crate::expr::*;
 use crate::mc::types::InitValue;
 use crate::mc::Witness;
+use crate::mc::{CancellationToken, ProgressObserver};
 use crate::smt::*;
-use crate::system::analysis::{analyze_for_serialization, count_expr_uses, UseCountInt, Uses};
-use crate::system::{State, TransitionSystem};
+use crate::system::analysis::{
+    analyze_for_serialization, count_expr_uses, find_uninterpreted_functions, UseCountInt, Uses,
+};
+use crate::system::{init_order, State, TransitionSystem};
 use baa::*;
 use rustc_hash::FxHashSet;
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
 #[derive(Debug, Clone, Copy)]
 pub struct SmtModelCheckerOptions {
@@ -21,6 +25,8 @@ pub struct SmtModelCheckerOptions {
     pub check_bad_states_individually: bool,
     /// If true, the communication with the SMT solver will be logged into a `replay.smt` file.
     pub save_smt_replay: bool,
+    /// If true, every query is saved into the `queries` directory, see [`read_query_log`].
+    pub log_queries: bool,
 }
 
 pub struct SmtModelChecker<S: Solver<std::fs::File>> {
@@ -35,39 +41,61 @@ impl<S: Solver<std::fs::File>> SmtModelChecker<S> {
         Self { solver, opts }
     }
 
+    pub fn solver(&self) -> &S {
+        &self.solver
+    }
+
     pub fn check(
         &self,
         ctx: &mut Context,
         sys: &TransitionSystem,
         k_max: u64,
     ) -> Result<ModelCheckResult> {
-        assert!(k_max > 0 && k_max <= 2000, "unreasonable k_max={}", k_max);
-        let replay_file = if self.opts.save_smt_replay {
-            Some(std::fs::File::create("replay.smt")?)
-        } else {
-            None
-        };
-        let mut smt_ctx = self.solver.start(replay_file)?;
+        self.check_with_cancellation(ctx, sys, k_max, &CancellationToken::default())
+    }
 
-        // z3 only supports the non-standard as-const array syntax when the logic is set to ALL
-        let logic = if self.solver.name() == "z3" {
-            Logic::All
-        } else if self.solver.supports_uf() {
-            Logic::QfAufbv
-        } else {
-            Logic::QfAbv
-        };
-        smt_ctx.set_logic(logic)?;
+    /// Like `check`, but returns [`ModelCheckResult::Cancelled`] once `token` is cancelled.
+    /// The token is checked before every new bound.
+    pub fn check_with_cancellation(
+        &self,
+        ctx: &mut Context,
+        sys: &TransitionSystem,
+        k_max: u64,
+        token: &CancellationToken,
+    ) -> Result<ModelCheckResult> {
+        self.check_with_progress(ctx, sys, k_max, token, &mut ())
+    }
+
+    /// Like `check_with_cancellation`, but notifies `observer` after every bound.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(solver = self.solver.name(), k_max = k_max))
+    )]
+    pub fn check_with_progress(
+        &self,
+        ctx: &mut Context,
+        sys: &TransitionSystem,
+        k_max: u64,
+        token: &CancellationToken,
+        observer: &mut impl ProgressObserver,
+    ) -> Result<ModelCheckResult> {
+        assert!(k_max > 0 && k_max <= 2000, "unreasonable k_max={}", k_max);
+        let mut smt_ctx = self.start_solver()?;
 
         // TODO: maybe add support for the more compact SMT encoding
         let mut enc = UnrollSmtEncoding::new(ctx, sys, false);
-        enc.define_header(&mut smt_ctx)?;
+        enc.define_header(ctx, &mut smt_ctx)?;
         enc.init_at(ctx, &mut smt_ctx, 0)?;
 
         let constraints = sys.constraints.clone();
         let bad_states = sys.bad_states.clone();
 
         for k in 0..=k_max {
+            if token.is_cancelled() {
+                return Ok(ModelCheckResult::Cancelled(k));
+            }
+            let mut solver_time = Duration::ZERO;
+
             // assume all constraints hold in this step
             for expr_ref in constraints.iter() {
                 let expr = enc.get_at(ctx, *expr_ref, k);
@@ -76,7 +104,9 @@ impl<S: Solver<std::fs::File>> SmtModelChecker<S> {
 
             // make sure the constraints are not contradictory
             if self.opts.check_constraints {
+                let start = Instant::now();
                 let res = smt_ctx.check_sat()?;
+                solver_time += start.elapsed();
                 assert_eq!(
                     res,
                     CheckSatResponse::Sat,
@@ -88,7 +118,9 @@ impl<S: Solver<std::fs::File>> SmtModelChecker<S> {
             if self.opts.check_bad_states_individually {
                 for (_bs_id, expr_ref) in bad_states.iter().enumerate() {
                     let expr = enc.get_at(ctx, *expr_ref, k);
+                    let start = Instant::now();
                     let res = check_assuming(&ctx, &mut smt_ctx, [expr])?;
+                    solver_time += start.elapsed();
 
                     // count expression uses
                     let use_counts = count_expr_uses(ctx, sys);
@@ -112,7 +144,9 @@ impl<S: Solver<std::fs::File>> SmtModelChecker<S> {
                     .map(|expr_ref| enc.get_at(ctx, *expr_ref, k))
                     .collect::<Vec<_>>();
                 let any_bad = all_bads.into_iter().reduce(|a, b| ctx.or(a, b)).unwrap();
+                let start = Instant::now();
                 let res = check_assuming(&ctx, &mut smt_ctx, [any_bad])?;
+                solver_time += start.elapsed();
 
                 // count expression uses
                 let use_counts = count_expr_uses(ctx, sys);
@@ -131,6 +165,10 @@ impl<S: Solver<std::fs::File>> SmtModelChecker<S> {
                 check_assuming_end(&mut smt_ctx)?;
             }
 
+            #[cfg(feature = "tracing")]
+            tracing::debug!(k, ?solver_time, "checked bound");
+            observer.bound_checked(k, solver_time);
+
             // advance
             enc.unroll(ctx, &mut smt_ctx)?;
         }
@@ -139,8 +177,32 @@ impl<S: Solver<std::fs::File>> SmtModelChecker<S> {
         Ok(ModelCheckResult::Success)
     }
 
+    /// Launches the solver according to our options and selects a logic.
+    pub(super) fn start_solver(&self) -> Result<S::Context> {
+        let replay_file = if self.opts.save_smt_replay {
+            Some(std::fs::File::create("replay.smt")?)
+        } else {
+            None
+        };
+        let mut smt_ctx = self.solver.start(replay_file)?;
+        if self.opts.log_queries {
+            smt_ctx.log_queries(std::path::Path::new("queries"))?;
+        }
+
+        // z3 only supports the non-standard as-const array syntax when the logic is set to ALL
+        let logic = if self.solver.name() == "z3" {
+            Logic::All
+        } else if self.solver.supports_uf() {
+            Logic::QfAufbv
+        } else {
+            Logic::QfAbv
+        };
+        smt_ctx.set_logic(logic)?;
+        Ok(smt_ctx)
+    }
+
     #[allow(clippy::too_many_arguments)]
-    fn get_witness(
+    pub(super) fn get_witness(
         &self,
         sys: &TransitionSystem,
         ctx: &mut Context,
@@ -169,19 +231,22 @@ impl<S: Solver<std::fs::File>> SmtModelChecker<S> {
         // collect initial values
         for (state_cnt, state) in sys.states.iter().enumerate() {
             let sym_at = enc.get_at(ctx, state.symbol, 0);
-            let value = get_smt_value(ctx, smt_ctx, sym_at)?;
+            let value_expr = smt_ctx.get_value(ctx, sym_at)?;
             // we assume that state ids are monotonically increasing with +1
             assert_eq!(wit.init.len(), state_cnt);
             // convert to a witness value
-            let wit_value = match value {
-                Value::Array(v) => {
-                    // TODO: narrow down the relevant indices
-                    let indices = (0..v.num_elements())
-                        .map(|ii| BitVecValue::from_u64(ii as u64, v.index_width()))
-                        .collect::<Vec<_>>();
-                    InitValue::Array(v, indices)
-                }
-                Value::BitVec(v) => InitValue::BitVec(v),
+            let wit_value = match ArrayModel::from_expr(ctx, value_expr) {
+                // avoids enumerating all entries of large memories
+                Some(model) => InitValue::Array(model.to_array_value(), model.relevant_indices()),
+                None => match eval_expr(ctx, &HashMap::new(), value_expr) {
+                    Value::Array(v) => {
+                        let indices = (0..v.num_elements())
+                            .map(|ii| BitVecValue::from_u64(ii as u64, v.index_width()))
+                            .collect::<Vec<_>>();
+                        InitValue::Array(v, indices)
+                    }
+                    Value::BitVec(v) => InitValue::BitVec(v),
+                },
             };
             wit.init.push(wit_value);
             // also save state name
@@ -247,13 +312,28 @@ pub fn get_smt_value(
     Ok(value)
 }
 
+/// Evaluates all constants in the current model. Arrays are returned as [`Value::Array`].
+pub fn get_smt_model(
+    ctx: &mut Context,
+    smt_ctx: &mut impl SolverContext,
+) -> Result<Vec<(ExprRef, Value)>> {
+    let model = smt_ctx.get_model(ctx)?;
+    let values = model
+        .into_iter()
+        .map(|(symbol, value_expr)| (symbol, eval_expr(ctx, &HashMap::new(), value_expr)))
+        .collect();
+    Ok(values)
+}
+
 pub enum ModelCheckResult {
     Success,
     Fail(Witness),
+    /// Checking was aborted. No violation exists in the first `n` steps.
+    Cancelled(u64),
 }
 
 pub trait TransitionSystemEncoding {
-    fn define_header(&self, smt_ctx: &mut impl SolverContext) -> Result<()>;
+    fn define_header(&self, ctx: &Context, smt_ctx: &mut impl SolverContext) -> Result<()>;
     fn init_at(
         &mut self,
         ctx: &mut Context,
@@ -275,8 +355,12 @@ pub struct UnrollSmtEncoding {
     signals: Vec<Option<SmtSignalInfo>>,
     /// system states
     states: Vec<State>,
+    /// indices of `states` in the order in which they are initialized, see [`init_order`]
+    init_order: Vec<usize>,
     /// symbols of signals at every step
     symbols_at: Vec<Vec<ExprRef>>,
+    /// uninterpreted functions and their argument types
+    functions: Vec<(ExprRef, Vec<Type>)>,
 }
 
 #[derive(Clone)]
@@ -352,6 +436,8 @@ impl UnrollSmtEncoding {
         let current_step = None;
         let offset = None;
         let states = sys.states.clone();
+        let init_order = init_order(ctx, sys);
+        let functions = find_uninterpreted_functions(ctx, sys);
 
         Self {
             offset,
@@ -359,7 +445,9 @@ impl UnrollSmtEncoding {
             signals,
             signal_order,
             states,
+            init_order,
             symbols_at: Vec::new(),
+            functions,
         }
     }
 
@@ -440,8 +528,11 @@ impl UnrollSmtEncoding {
 }
 
 impl TransitionSystemEncoding for UnrollSmtEncoding {
-    fn define_header(&self, _smt_ctx: &mut impl SolverContext) -> Result<()> {
-        // nothing to do in this encoding
+    fn define_header(&self, ctx: &Context, smt_ctx: &mut impl SolverContext) -> Result<()> {
+        // uninterpreted functions are shared by all steps
+        for (func, args) in self.functions.iter() {
+            smt_ctx.declare_fun(ctx, *func, args)?;
+        }
         Ok(())
     }
 
@@ -463,8 +554,9 @@ impl TransitionSystemEncoding for UnrollSmtEncoding {
             self.define_signals(ctx, smt_ctx, 0, &|info: &SmtSignalInfo| info.uses.init > 0)?;
         }
 
-        // declare/define initial states
-        for state in self.states.iter() {
+        // declare/define initial states, init expressions may refer to other states
+        for &ii in self.init_order.iter() {
+            let state = &self.states[ii];
             let symbol_at = if state.is_const() {
                 state.symbol
             } else {
@@ -0,0 +1,283 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Symmetry Reduction
+//! Designs with replicated lanes often come with one assertion per lane. If swapping the
+//! signals of two lanes maps the transition system onto itself, the two assertions must have
+//! the same result, and only one of them needs to be checked.
+
+use crate::equiv::{prove_equiv, EquivBackend, EquivError, EquivOptions, EquivResult};
+use crate::expr::{simple_transform_expr, Context, ExprRef, TypeCheck};
+use crate::mc::{ModelCheckResult, SmtModelChecker};
+use crate::smt::SmtLibSolver;
+use crate::system::TransitionSystem;
+use rustc_hash::FxHashMap;
+
+/// One lane of a symmetry group: the bad state that is checked for the lane and the states
+/// and inputs that belong to it. Signals of different lanes correspond by position.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lane {
+    pub bad_state: usize,
+    pub signals: Vec<ExprRef>,
+}
+
+/// Lanes that are interchangeable. The first lane is the representative that gets checked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymmetryGroup {
+    pub lanes: Vec<Lane>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SymmetryError {
+    #[error("lane {lane} does not match the representative: {reason}")]
+    Malformed { lane: usize, reason: String },
+    #[error("swapping lane {lane} with the representative changes {what}")]
+    NotSymmetric { lane: usize, what: String },
+    #[error(transparent)]
+    Equiv(#[from] EquivError),
+    #[error(transparent)]
+    Smt(#[from] crate::smt::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PropertyStatus {
+    /// The bad state is unreachable up to the bound.
+    Holds,
+    /// The bad state is reachable in the given step.
+    Fails(u64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PropertyResult {
+    pub status: PropertyStatus,
+    /// Bad state whose result was reused instead of checking this one.
+    pub inferred_from: Option<usize>,
+}
+
+impl SmtModelChecker<SmtLibSolver> {
+    /// Determines the status of every bad state individually. Only the representative of
+    /// each symmetry group is checked, after all groups were validated with the solver.
+    pub fn check_symmetric(
+        &self,
+        ctx: &mut Context,
+        sys: &TransitionSystem,
+        k_max: u64,
+        groups: &[SymmetryGroup],
+    ) -> Result<Vec<PropertyResult>, SymmetryError> {
+        let mut inferred_from = vec![None; sys.bad_states.len()];
+        for group in groups.iter() {
+            validate_symmetry(ctx, sys, group, self.solver())?;
+            let representative = group.lanes[0].bad_state;
+            for lane in group.lanes.iter().skip(1) {
+                inferred_from[lane.bad_state] = Some(representative);
+            }
+        }
+
+        // check the remaining properties until they all hold or fail
+        let mut status: Vec<Option<PropertyStatus>> = vec![None; sys.bad_states.len()];
+        let mut reduced = sys.clone();
+        loop {
+            let open: Vec<usize> = (0..sys.bad_states.len())
+                .filter(|&ii| inferred_from[ii].is_none() && status[ii].is_none())
+                .collect();
+            if open.is_empty() {
+                break;
+            }
+            reduced.bad_states = open.iter().map(|&ii| sys.bad_states[ii]).collect();
+            match self.check(ctx, &reduced, k_max)? {
+                ModelCheckResult::Fail(wit) => {
+                    assert!(!wit.failed_safety.is_empty(), "witness without failures");
+                    let step = wit.inputs.len() as u64 - 1;
+                    for &failed in wit.failed_safety.iter() {
+                        status[open[failed as usize]] = Some(PropertyStatus::Fails(step));
+                    }
+                }
+                ModelCheckResult::Success => {
+                    for ii in open {
+                        status[ii] = Some(PropertyStatus::Holds);
+                    }
+                }
+                ModelCheckResult::Cancelled(_) => unreachable!("no cancellation token"),
+            }
+        }
+
+        Ok((0..sys.bad_states.len())
+            .map(|ii| {
+                let source = inferred_from[ii].unwrap_or(ii);
+                PropertyResult {
+                    status: status[source].unwrap(),
+                    inferred_from: inferred_from[ii],
+                }
+            })
+            .collect())
+    }
+}
+
+/// Proves that swapping the signals of any lane with the representative leaves the init and
+/// next state functions as well as the constraints unchanged and maps the bad states onto
+/// each other.
+pub fn validate_symmetry(
+    ctx: &mut Context,
+    sys: &TransitionSystem,
+    group: &SymmetryGroup,
+    solver: &SmtLibSolver,
+) -> Result<(), SymmetryError> {
+    let opts = EquivOptions {
+        backend: EquivBackend::Smt(solver.clone()),
+        ..Default::default()
+    };
+    let Some((representative, lanes)) = group.lanes.split_first() else {
+        return Ok(());
+    };
+    let states = sys.state_map();
+    let inputs = sys.input_set();
+    for (ii, lane) in lanes.iter().enumerate() {
+        let lane_id = ii + 1;
+        let malformed = |reason: String| SymmetryError::Malformed {
+            lane: lane_id,
+            reason,
+        };
+        if lane.signals.len() != representative.signals.len() {
+            return Err(malformed(format!(
+                "{} instead of {} signals",
+                lane.signals.len(),
+                representative.signals.len()
+            )));
+        }
+        let mut swap = FxHashMap::default();
+        for (&a, &b) in representative.signals.iter().zip(lane.signals.iter()) {
+            for s in [a, b] {
+                if !states.contains_key(&s) && !inputs.contains(&s) {
+                    return Err(malformed(format!(
+                        "{} is neither a state nor an input",
+                        ctx.get_symbol_name(s).unwrap_or("expression")
+                    )));
+                }
+            }
+            if a.get_type(ctx) != b.get_type(ctx)
+                || states.contains_key(&a) != states.contains_key(&b)
+            {
+                return Err(malformed(format!(
+                    "{} and {} do not have the same kind",
+                    ctx.get_symbol_name(a).unwrap(),
+                    ctx.get_symbol_name(b).unwrap()
+                )));
+            }
+            swap.insert(a, b);
+            swap.insert(b, a);
+        }
+
+        let mut equal = |ctx: &mut Context, a: Option<ExprRef>, b: Option<ExprRef>| match (a, b) {
+            (None, None) => Ok(true),
+            (Some(a), Some(b)) => {
+                let swapped = simple_transform_expr(ctx, a, |_, e, _| swap.get(&e).copied());
+                Ok(prove_equiv(ctx, swapped, b, &opts)? == EquivResult::Equivalent)
+            }
+            _ => Ok::<_, SymmetryError>(false),
+        };
+        let not_symmetric = |what: String| SymmetryError::NotSymmetric {
+            lane: lane_id,
+            what,
+        };
+
+        for state in sys.states.iter() {
+            let image = states[&swap.get(&state.symbol).copied().unwrap_or(state.symbol)];
+            let name = ctx.get_symbol_name(state.symbol).unwrap().to_string();
+            if !equal(ctx, state.init, image.init)? {
+                return Err(not_symmetric(format!("the init value of {name}")));
+            }
+            if !equal(ctx, state.next, image.next)? {
+                return Err(not_symmetric(format!("the next state of {name}")));
+            }
+        }
+        let constraints = sys.constraints.iter().copied().reduce(|a, b| ctx.and(a, b));
+        if !equal(ctx, constraints, constraints)? {
+            return Err(not_symmetric("the constraints".to_string()));
+        }
+        let bad = |lane: &Lane| sys.bad_states[lane.bad_state];
+        if !equal(ctx, Some(bad(representative)), Some(bad(lane)))? {
+            return Err(not_symmetric("the bad state".to_string()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mc::SmtModelCheckerOptions;
+    use crate::smt::BITWUZLA;
+    use crate::system::State;
+
+    /// Two independent counters with one bad state each.
+    fn two_lanes(ctx: &mut Context, limits: [u64; 2]) -> (TransitionSystem, Vec<SymmetryGroup>) {
+        let mut sys = TransitionSystem::new("lanes".into());
+        let mut lanes = vec![];
+        for (ii, limit) in limits.into_iter().enumerate() {
+            let en = ctx.bv_symbol(&format!("en{ii}"), 1);
+            sys.add_input(ctx, en);
+            let count = ctx.bv_symbol(&format!("count{ii}"), 3);
+            let next = ctx.build(|c| c.ite(en, c.add(count, c.one(3)), count));
+            let init = ctx.zero(3);
+            sys.add_state(
+                ctx,
+                State {
+                    symbol: count,
+                    init: Some(init),
+                    next: Some(next),
+                },
+            );
+            let bad = ctx.build(|c| c.equal(count, c.bit_vec_val(limit, 3)));
+            sys.bad_states.push(bad);
+            lanes.push(Lane {
+                bad_state: ii,
+                signals: vec![count, en],
+            });
+        }
+        (sys, vec![SymmetryGroup { lanes }])
+    }
+
+    fn checker() -> SmtModelChecker<SmtLibSolver> {
+        let opts = SmtModelCheckerOptions {
+            check_constraints: false,
+            check_bad_states_individually: true,
+            save_smt_replay: false,
+            log_queries: false,
+        };
+        SmtModelChecker::new(BITWUZLA, opts)
+    }
+
+    #[test]
+    fn test_symmetric_lanes() {
+        let mut ctx = Context::default();
+        let (sys, groups) = two_lanes(&mut ctx, [5, 5]);
+        let results = checker()
+            .check_symmetric(&mut ctx, &sys, 3, &groups)
+            .unwrap();
+        assert_eq!(results[0].status, PropertyStatus::Holds);
+        assert_eq!(results[1].status, PropertyStatus::Holds);
+        assert_eq!(results[1].inferred_from, Some(0));
+
+        let results = checker()
+            .check_symmetric(&mut ctx, &sys, 8, &groups)
+            .unwrap();
+        assert_eq!(results[0].status, PropertyStatus::Fails(5));
+        assert_eq!(results[1].status, PropertyStatus::Fails(5));
+    }
+
+    #[test]
+    fn test_invalid_symmetry() {
+        let mut ctx = Context::default();
+        let (sys, groups) = two_lanes(&mut ctx, [5, 6]);
+        assert!(matches!(
+            validate_symmetry(&mut ctx, &sys, &groups[0], &BITWUZLA),
+            Err(SymmetryError::NotSymmetric { lane: 1, .. })
+        ));
+        // without symmetry, both properties are checked separately
+        let results = checker().check_symmetric(&mut ctx, &sys, 8, &[]).unwrap();
+        assert_eq!(results[0].status, PropertyStatus::Fails(5));
+        assert_eq!(results[1].status, PropertyStatus::Fails(6));
+        assert_eq!(results[1].inferred_from, None);
+    }
+}
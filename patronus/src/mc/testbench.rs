@@ -0,0 +1,349 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Testbench Generation
+//! Turns a counterexample or a recorded simulation into a standalone testbench that replays
+//! the same stimulus, such that every bug found by a model checker can become a regression
+//! test. Two flavors are supported:
+//! - a Rust `#[test]` that loads the btor2 file of the design and replays the trace with our
+//!   [`Interpreter`](crate::sim::Interpreter), checking that the same bad states are reached
+//! - a SystemVerilog module that instantiates the design and drives its inputs, with one clock
+//!   cycle per step
+
+use crate::expr::{Context, TypeCheck, WidthInt};
+use crate::mc::{InitValue, Witness};
+use crate::sim::{parse_value, Stimulus, StimulusError};
+use crate::system::TransitionSystem;
+use baa::{BitVecOps, BitVecValue, Value};
+use std::fmt::Write;
+
+/// A signal that is assigned by the testbench.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestbenchSignal {
+    pub name: String,
+    pub width: WidthInt,
+}
+
+/// Stimulus of a trace in a form that does not depend on a [`Context`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Testbench {
+    pub name: String,
+    /// states with a known starting value, which overrides the init expression
+    pub init: Vec<(TestbenchSignal, BitVecValue)>,
+    pub inputs: Vec<TestbenchSignal>,
+    /// one entry per step and input, `None` leaves the input unchanged
+    pub steps: Vec<Vec<Option<BitVecValue>>>,
+    /// bad states that are expected to be violated in the last step
+    pub expected_bad: Vec<u32>,
+}
+
+impl Testbench {
+    /// Replays the init values and inputs of `wit`. Array values are skipped.
+    pub fn from_witness(ctx: &Context, sys: &TransitionSystem, wit: &Witness) -> Self {
+        let init = sys
+            .states
+            .iter()
+            .zip(wit.init.iter())
+            .flat_map(|(state, value)| match value {
+                InitValue::BitVec(value) => Some((signal(ctx, state.symbol)?, value.clone())),
+                _ => None,
+            })
+            .collect();
+        let inputs: Vec<_> = sys.inputs.iter().map(|&i| signal(ctx, i)).collect();
+        let steps = wit
+            .inputs
+            .iter()
+            .map(|values| {
+                values
+                    .iter()
+                    .zip(inputs.iter())
+                    .filter(|(_, s)| s.is_some())
+                    .map(|(value, _)| match value {
+                        Some(Value::BitVec(value)) => Some(value.clone()),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .collect();
+        Self {
+            name: sys.name.clone(),
+            init,
+            inputs: inputs.into_iter().flatten().collect(),
+            steps,
+            expected_bad: wit.failed_safety.clone(),
+        }
+    }
+
+    /// Replays a stimulus, e.g., one that was recorded with a
+    /// [`StimulusRecorder`](crate::sim::StimulusRecorder). No bad states are expected.
+    pub fn from_stimulus(
+        ctx: &Context,
+        sys: &TransitionSystem,
+        stimulus: &Stimulus,
+    ) -> Result<Self, StimulusError> {
+        let inputs = stimulus
+            .signals
+            .iter()
+            .map(|name| {
+                sys.lookup_input(ctx, name)
+                    .and_then(|i| signal(ctx, i))
+                    .ok_or_else(|| StimulusError::UnknownInput(name.clone()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut steps = Vec::with_capacity(stimulus.steps.len());
+        for (step, values) in stimulus.steps.iter().enumerate() {
+            let mut parsed = Vec::with_capacity(values.len());
+            for (input, value) in inputs.iter().zip(values.iter()) {
+                parsed.push(match value {
+                    None => None,
+                    Some(value) => Some(parse_value(value, input.width).ok_or_else(|| {
+                        StimulusError::InvalidValue {
+                            step,
+                            signal: input.name.clone(),
+                            value: value.clone(),
+                        }
+                    })?),
+                });
+            }
+            steps.push(parsed);
+        }
+        Ok(Self {
+            name: sys.name.clone(),
+            init: vec![],
+            inputs,
+            steps,
+            expected_bad: vec![],
+        })
+    }
+
+    /// A Rust integration test that parses the btor2 file at `design` and replays the trace.
+    pub fn to_rust(&self, design: &str) -> String {
+        let mut out = String::new();
+        writeln!(out, "// replays a trace of `{}`", self.name).unwrap();
+        out.push_str("use patronus::sim::{parse_value, InitKind, Interpreter, Simulator};\n\n");
+        out.push_str("#[test]\n");
+        writeln!(out, "fn replay_{}() {{", rust_ident(&self.name)).unwrap();
+        writeln!(
+            out,
+            "    let (ctx, sys) = patronus::btor2::parse_file({design:?}).expect(\"failed to parse design\");"
+        )
+        .unwrap();
+        out.push_str(
+            "    let state = |name: &str| sys.get_state_by_name(&ctx, name).expect(name).symbol;\n",
+        );
+        out.push_str("    let input = |name: &str| sys.lookup_input(&ctx, name).expect(name);\n");
+        out.push_str("    let mut sim = Interpreter::new(&ctx, &sys);\n");
+        out.push_str("    sim.init(InitKind::Zero);\n");
+        for (state, value) in self.init.iter() {
+            writeln!(
+                out,
+                "    sim.set(state({:?}), &parse_value(\"0x{}\", {}).unwrap()).unwrap();",
+                state.name,
+                value.to_hex_str(),
+                state.width
+            )
+            .unwrap();
+        }
+        for (k, values) in self.steps.iter().enumerate() {
+            if k > 0 {
+                out.push_str("    sim.step();\n");
+            }
+            writeln!(out, "    // step {k}").unwrap();
+            for (input, value) in self.inputs.iter().zip(values.iter()) {
+                if let Some(value) = value {
+                    writeln!(
+                        out,
+                        "    sim.set(input({:?}), &parse_value(\"0x{}\", {}).unwrap()).unwrap();",
+                        input.name,
+                        value.to_hex_str(),
+                        input.width
+                    )
+                    .unwrap();
+                }
+            }
+        }
+        for bad in self.expected_bad.iter() {
+            writeln!(
+                out,
+                "    assert!(\n        matches!(sim.get(sys.bad_states[{bad}]), baa::Value::BitVec(v) if baa::BitVecOps::is_true(&v)),\n        \"bad state {bad} is not reached\"\n    );"
+            )
+            .unwrap();
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// A SystemVerilog testbench for a module with the same name as the system, an input for
+    /// every system input and a `clock` port. Inputs are driven while the clock is low.
+    pub fn to_system_verilog(&self, clock: &str) -> String {
+        let module = sv_ident(&self.name);
+        let mut out = String::new();
+        writeln!(
+            out,
+            "module {}_tb;",
+            self.name.replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+        )
+        .unwrap();
+        writeln!(out, "  reg {} = 1'b0;", sv_ident(clock)).unwrap();
+        for input in self.inputs.iter() {
+            writeln!(
+                out,
+                "  reg [{}:0] {};",
+                input.width - 1,
+                sv_ident(&input.name)
+            )
+            .unwrap();
+        }
+        let ports = std::iter::once(clock)
+            .chain(self.inputs.iter().map(|i| i.name.as_str()))
+            .map(|name| format!(".{0}({0})", sv_ident(name)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        writeln!(out, "  {module} dut({ports});").unwrap();
+        out.push_str("  initial begin\n");
+        for (state, value) in self.init.iter() {
+            writeln!(
+                out,
+                "    dut.{} = {};",
+                sv_ident(&state.name),
+                sv_value(value)
+            )
+            .unwrap();
+        }
+        for (k, values) in self.steps.iter().enumerate() {
+            if k > 0 {
+                writeln!(
+                    out,
+                    "    #1 {0} = 1'b1;\n    #1 {0} = 1'b0;",
+                    sv_ident(clock)
+                )
+                .unwrap();
+            }
+            writeln!(out, "    // step {k}").unwrap();
+            for (input, value) in self.inputs.iter().zip(values.iter()) {
+                if let Some(value) = value {
+                    writeln!(out, "    {} = {};", sv_ident(&input.name), sv_value(value)).unwrap();
+                }
+            }
+        }
+        for bad in self.expected_bad.iter() {
+            writeln!(out, "    // bad state {bad} is expected to be violated now").unwrap();
+        }
+        out.push_str("    #1 $finish;\n  end\nendmodule\n");
+        out
+    }
+}
+
+fn signal(ctx: &Context, symbol: crate::expr::ExprRef) -> Option<TestbenchSignal> {
+    Some(TestbenchSignal {
+        name: ctx.get_symbol_name(symbol)?.to_string(),
+        width: symbol.get_bv_type(ctx)?,
+    })
+}
+
+fn rust_ident(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Uses an escaped identifier for names that are not legal SystemVerilog identifiers.
+fn sv_ident(name: &str) -> String {
+    let simple = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+    if simple {
+        name.to_string()
+    } else {
+        format!("\\{name} ")
+    }
+}
+
+fn sv_value(value: &BitVecValue) -> String {
+    format!("{}'h{}", value.width(), value.to_hex_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::State;
+
+    fn counter(ctx: &mut Context) -> TransitionSystem {
+        let mut sys = TransitionSystem::new("counter".to_string());
+        let en = ctx.bv_symbol("en", 1);
+        sys.add_input(ctx, en);
+        let count = ctx.bv_symbol("count", 4);
+        let next = ctx.build(|c| c.ite(en, c.add(count, c.one(4)), count));
+        sys.add_state(
+            ctx,
+            State {
+                symbol: count,
+                init: None,
+                next: Some(next),
+            },
+        );
+        let bad = ctx.build(|c| c.equal(count, c.bit_vec_val(3, 4)));
+        sys.bad_states.push(bad);
+        sys
+    }
+
+    #[test]
+    fn test_testbench_from_witness() {
+        let mut ctx = Context::default();
+        let sys = counter(&mut ctx);
+        let one = Some(Value::BitVec(BitVecValue::from_u64(1, 1)));
+        let wit = Witness {
+            init: vec![InitValue::BitVec(BitVecValue::from_u64(1, 4))],
+            inputs: vec![one.clone(), one, None]
+                .into_iter()
+                .map(|v| vec![v])
+                .collect(),
+            failed_safety: vec![0],
+            ..Default::default()
+        };
+        let tb = Testbench::from_witness(&ctx, &sys, &wit);
+        assert_eq!(tb.init.len(), 1);
+        assert_eq!(tb.steps.len(), 3);
+        assert_eq!(tb.steps[2], [None]);
+
+        let rust = tb.to_rust("counter.btor");
+        assert!(rust.contains("fn replay_counter()"));
+        assert!(rust.contains("parse_file(\"counter.btor\")"));
+        assert!(rust.contains("sim.set(state(\"count\"), &parse_value(\"0x1\", 4).unwrap())"));
+        assert_eq!(rust.matches("sim.step();").count(), 2);
+        assert!(rust.contains("sys.bad_states[0]"));
+
+        let sv = tb.to_system_verilog("clock");
+        assert!(sv.contains("module counter_tb;"));
+        assert!(sv.contains("counter dut(.clock(clock), .en(en));"));
+        assert!(sv.contains("dut.count = 4'h1;"));
+        assert_eq!(sv.matches("en = 1'h1;").count(), 2);
+    }
+
+    #[test]
+    fn test_testbench_from_stimulus() {
+        let mut ctx = Context::default();
+        let sys = counter(&mut ctx);
+        let stimulus = Stimulus::from_csv("en\n1\nx\n0\n").unwrap();
+        let tb = Testbench::from_stimulus(&ctx, &sys, &stimulus).unwrap();
+        assert_eq!(tb.steps.len(), 3);
+        assert_eq!(tb.steps[1], [None]);
+        assert!(tb.expected_bad.is_empty());
+        assert!(tb.to_rust("counter.btor").contains("input(\"en\")"));
+
+        let unknown = Stimulus::from_csv("nope\n1\n").unwrap();
+        assert!(matches!(
+            Testbench::from_stimulus(&ctx, &sys, &unknown),
+            Err(StimulusError::UnknownInput(_))
+        ));
+        assert_eq!(sv_ident("a.b"), "\\a.b ");
+    }
+}
@@ -0,0 +1,484 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Plugins
+//! External crates can add model checking engines, simulators, frontends and transformation
+//! passes without patching patronus. Everything a crate provides is bundled in a [`Plugin`],
+//! which adds its components to a [`PluginRegistry`] under a unique name. A driver registers
+//! all plugins it links against once with [`register_plugin`]. Afterwards, components are
+//! looked up by name in the global [`registry`], e.g., by the `--engine` option of the `bmc`
+//! tool or with [`crate::system::PassManager::add_plugin_passes`].
+//!
+//! The built-in engines, the interpreter and the btor2 frontend are always registered.
+//! The `sat-bmc` engine is only available with the `sat` feature.
+
+use crate::btor2;
+use crate::expr::{Context, ExprRef};
+#[cfg(feature = "sat")]
+use crate::mc::check_with_sat;
+use crate::mc::{CancellationToken, ModelCheckResult, SmtModelChecker, SmtModelCheckerOptions};
+use crate::sim::{InitKind, Interpreter, SimError, Simulator};
+use crate::smt::{SmtLibSolver, BITWUZLA};
+use crate::system::{Pass, TransitionSystem};
+use baa::{BitVecValueRef, Value};
+use std::path::Path;
+use std::sync::{Arc, RwLock, RwLockReadGuard};
+
+#[derive(Debug, thiserror::Error)]
+pub enum PluginError {
+    #[error("{kind} `{name}` is already registered")]
+    Duplicate { kind: &'static str, name: String },
+    #[error("no {kind} named `{name}` is registered")]
+    Unknown { kind: &'static str, name: String },
+    #[error("no frontend is registered for `{0}`")]
+    NoFrontend(String),
+    /// An error reported by a plugin component.
+    #[error("{0}")]
+    Failed(String),
+}
+
+pub type Result<T> = std::result::Result<T, PluginError>;
+
+/// A model checking engine.
+pub trait CheckerEngine: Send + Sync {
+    /// Unique name used to select the engine.
+    fn name(&self) -> &'static str;
+    /// Checks all bad states of `sys` up to `k_max` steps. Engines should return
+    /// [`ModelCheckResult::Cancelled`] once `token` is cancelled.
+    fn check(
+        &self,
+        ctx: &mut Context,
+        sys: &TransitionSystem,
+        k_max: u64,
+        token: &CancellationToken,
+    ) -> Result<ModelCheckResult>;
+}
+
+/// The part of [`Simulator`] that can be used through a trait object.
+pub trait DynSimulator {
+    fn init(&mut self, kind: InitKind);
+    fn step(&mut self);
+    fn set(
+        &mut self,
+        expr: ExprRef,
+        value: BitVecValueRef<'_>,
+    ) -> std::result::Result<(), SimError>;
+    fn update(&mut self);
+    fn get(&self, expr: ExprRef) -> Value;
+    fn step_count(&self) -> u64;
+}
+
+impl<S: Simulator> DynSimulator for S {
+    fn init(&mut self, kind: InitKind) {
+        Simulator::init(self, kind)
+    }
+
+    fn step(&mut self) {
+        Simulator::step(self)
+    }
+
+    fn set(
+        &mut self,
+        expr: ExprRef,
+        value: BitVecValueRef<'_>,
+    ) -> std::result::Result<(), SimError> {
+        Simulator::set(self, expr, value)
+    }
+
+    fn update(&mut self) {
+        Simulator::update(self)
+    }
+
+    fn get(&self, expr: ExprRef) -> Value {
+        Simulator::get(self, expr)
+    }
+
+    fn step_count(&self) -> u64 {
+        Simulator::step_count(self)
+    }
+}
+
+/// Creates simulators.
+pub trait SimBackend: Send + Sync {
+    /// Unique name used to select the backend.
+    fn name(&self) -> &'static str;
+    fn create<'a>(
+        &self,
+        ctx: &'a Context,
+        sys: &'a TransitionSystem,
+    ) -> Result<Box<dyn DynSimulator + 'a>>;
+}
+
+/// Loads a transition system from a file.
+pub trait Frontend: Send + Sync {
+    /// Unique name used to select the frontend.
+    fn name(&self) -> &'static str;
+    /// File extensions, without the leading dot, that this frontend is used for by default.
+    fn extensions(&self) -> &[&'static str];
+    fn parse(&self, ctx: &mut Context, path: &Path) -> Result<TransitionSystem>;
+}
+
+type PassFactory = Box<dyn Fn() -> Box<dyn Pass> + Send + Sync>;
+
+/// Bundles all components that a crate contributes.
+pub trait Plugin {
+    fn register(&self, registry: &mut PluginRegistry) -> Result<()>;
+}
+
+/// Components by name, in the order in which they were registered.
+#[derive(Default)]
+pub struct PluginRegistry {
+    engines: Vec<Arc<dyn CheckerEngine>>,
+    sim_backends: Vec<Arc<dyn SimBackend>>,
+    frontends: Vec<Arc<dyn Frontend>>,
+    passes: Vec<(&'static str, PassFactory)>,
+}
+
+impl PluginRegistry {
+    /// An empty registry without any built-in components.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::new();
+        BuiltinPlugin
+            .register(&mut registry)
+            .expect("built-in names are unique");
+        registry
+    }
+
+    pub fn load(&mut self, plugin: &dyn Plugin) -> Result<()> {
+        plugin.register(self)
+    }
+
+    pub fn add_engine(&mut self, engine: impl CheckerEngine + 'static) -> Result<()> {
+        check_unique(
+            "engine",
+            engine.name(),
+            self.engines.iter().map(|e| e.name()),
+        )?;
+        self.engines.push(Arc::new(engine));
+        Ok(())
+    }
+
+    pub fn add_sim_backend(&mut self, backend: impl SimBackend + 'static) -> Result<()> {
+        let names = self.sim_backends.iter().map(|b| b.name());
+        check_unique("simulator backend", backend.name(), names)?;
+        self.sim_backends.push(Arc::new(backend));
+        Ok(())
+    }
+
+    pub fn add_frontend(&mut self, frontend: impl Frontend + 'static) -> Result<()> {
+        let names = self.frontends.iter().map(|f| f.name());
+        check_unique("frontend", frontend.name(), names)?;
+        self.frontends.push(Arc::new(frontend));
+        Ok(())
+    }
+
+    /// Passes are created anew for every pass manager, since they may carry state.
+    pub fn add_pass(
+        &mut self,
+        name: &'static str,
+        create: impl Fn() -> Box<dyn Pass> + Send + Sync + 'static,
+    ) -> Result<()> {
+        check_unique("pass", name, self.passes.iter().map(|(n, _)| *n))?;
+        self.passes.push((name, Box::new(create)));
+        Ok(())
+    }
+
+    pub fn engine(&self, name: &str) -> Result<Arc<dyn CheckerEngine>> {
+        find("engine", name, &self.engines, |e| e.name())
+    }
+
+    pub fn sim_backend(&self, name: &str) -> Result<Arc<dyn SimBackend>> {
+        find("simulator backend", name, &self.sim_backends, |b| b.name())
+    }
+
+    pub fn frontend(&self, name: &str) -> Result<Arc<dyn Frontend>> {
+        find("frontend", name, &self.frontends, |f| f.name())
+    }
+
+    /// Picks the frontend by file extension. Later registrations take precedence, such that
+    /// plugins can replace a built-in frontend.
+    pub fn frontend_for(&self, path: &Path) -> Result<Arc<dyn Frontend>> {
+        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        self.frontends
+            .iter()
+            .rev()
+            .find(|f| f.extensions().iter().any(|&e| e == ext))
+            .cloned()
+            .ok_or_else(|| PluginError::NoFrontend(path.display().to_string()))
+    }
+
+    pub fn create_pass(&self, name: &str) -> Result<Box<dyn Pass>> {
+        self.passes
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, create)| create())
+            .ok_or_else(|| unknown("pass", name))
+    }
+
+    pub fn engine_names(&self) -> Vec<&'static str> {
+        self.engines.iter().map(|e| e.name()).collect()
+    }
+
+    pub fn sim_backend_names(&self) -> Vec<&'static str> {
+        self.sim_backends.iter().map(|b| b.name()).collect()
+    }
+
+    pub fn frontend_names(&self) -> Vec<&'static str> {
+        self.frontends.iter().map(|f| f.name()).collect()
+    }
+
+    pub fn pass_names(&self) -> Vec<&'static str> {
+        self.passes.iter().map(|(n, _)| *n).collect()
+    }
+}
+
+fn check_unique<'a>(
+    kind: &'static str,
+    name: &str,
+    mut existing: impl Iterator<Item = &'a str>,
+) -> Result<()> {
+    if existing.any(|n| n == name) {
+        Err(PluginError::Duplicate {
+            kind,
+            name: name.to_string(),
+        })
+    } else {
+        Ok(())
+    }
+}
+
+fn unknown(kind: &'static str, name: &str) -> PluginError {
+    PluginError::Unknown {
+        kind,
+        name: name.to_string(),
+    }
+}
+
+fn find<T: ?Sized>(
+    kind: &'static str,
+    name: &str,
+    items: &[Arc<T>],
+    name_of: impl Fn(&T) -> &'static str,
+) -> Result<Arc<T>> {
+    items
+        .iter()
+        .find(|i| name_of(i.as_ref()) == name)
+        .cloned()
+        .ok_or_else(|| unknown(kind, name))
+}
+
+lazy_static! {
+    static ref REGISTRY: RwLock<PluginRegistry> = RwLock::new(PluginRegistry::with_builtins());
+}
+
+/// Adds all components of `plugin` to the global registry.
+pub fn register_plugin(plugin: &dyn Plugin) -> Result<()> {
+    REGISTRY
+        .write()
+        .expect("plugin registry lock poisoned")
+        .load(plugin)
+}
+
+/// The global registry. Must not be held while calling [`register_plugin`].
+pub fn registry() -> RwLockReadGuard<'static, PluginRegistry> {
+    REGISTRY.read().expect("plugin registry lock poisoned")
+}
+
+/// The components that ship with patronus.
+struct BuiltinPlugin;
+
+impl Plugin for BuiltinPlugin {
+    fn register(&self, registry: &mut PluginRegistry) -> Result<()> {
+        registry.add_engine(SmtBmc(BITWUZLA))?;
+        #[cfg(feature = "sat")]
+        registry.add_engine(SatBmc)?;
+        registry.add_sim_backend(InterpreterBackend)?;
+        registry.add_frontend(Btor2Frontend)?;
+        Ok(())
+    }
+}
+
+/// Bounded model checking with an SMT solver, see [`SmtModelChecker`].
+struct SmtBmc(SmtLibSolver);
+
+impl CheckerEngine for SmtBmc {
+    fn name(&self) -> &'static str {
+        "smt-bmc"
+    }
+
+    fn check(
+        &self,
+        ctx: &mut Context,
+        sys: &TransitionSystem,
+        k_max: u64,
+        token: &CancellationToken,
+    ) -> Result<ModelCheckResult> {
+        let opts = SmtModelCheckerOptions {
+            check_constraints: true,
+            check_bad_states_individually: true,
+            save_smt_replay: false,
+            log_queries: false,
+        };
+        SmtModelChecker::new(self.0.clone(), opts)
+            .check_with_cancellation(ctx, sys, k_max, token)
+            .map_err(|e| PluginError::Failed(e.to_string()))
+    }
+}
+
+/// Bounded model checking with the embedded SAT solver, see [`check_with_sat`].
+#[cfg(feature = "sat")]
+struct SatBmc;
+
+#[cfg(feature = "sat")]
+impl CheckerEngine for SatBmc {
+    fn name(&self) -> &'static str {
+        "sat-bmc"
+    }
+
+    fn check(
+        &self,
+        ctx: &mut Context,
+        sys: &TransitionSystem,
+        k_max: u64,
+        _token: &CancellationToken,
+    ) -> Result<ModelCheckResult> {
+        check_with_sat(ctx, sys, k_max).map_err(|e| PluginError::Failed(e.to_string()))
+    }
+}
+
+struct InterpreterBackend;
+
+impl SimBackend for InterpreterBackend {
+    fn name(&self) -> &'static str {
+        "interpreter"
+    }
+
+    fn create<'a>(
+        &self,
+        ctx: &'a Context,
+        sys: &'a TransitionSystem,
+    ) -> Result<Box<dyn DynSimulator + 'a>> {
+        Ok(Box::new(Interpreter::new(ctx, sys)))
+    }
+}
+
+struct Btor2Frontend;
+
+impl Frontend for Btor2Frontend {
+    fn name(&self) -> &'static str {
+        "btor2"
+    }
+
+    fn extensions(&self) -> &[&'static str] {
+        &["btor", "btor2"]
+    }
+
+    fn parse(&self, ctx: &mut Context, path: &Path) -> Result<TransitionSystem> {
+        let opts = btor2::ParseOptions {
+            check_constraints: true,
+            ..Default::default()
+        };
+        btor2::parse_file_with_options(path, ctx, opts)
+            .ok_or_else(|| PluginError::Failed(format!("failed to parse {}", path.display())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::PassManager;
+
+    struct CountStates;
+
+    impl Pass for CountStates {
+        fn name(&self) -> &'static str {
+            "count-states"
+        }
+
+        fn run(&mut self, _ctx: &mut Context, sys: &mut TransitionSystem) {
+            sys.name = format!("{} states", sys.states.len());
+        }
+    }
+
+    struct AlwaysSafe;
+
+    impl CheckerEngine for AlwaysSafe {
+        fn name(&self) -> &'static str {
+            "always-safe"
+        }
+
+        fn check(
+            &self,
+            _ctx: &mut Context,
+            _sys: &TransitionSystem,
+            _k_max: u64,
+            _token: &CancellationToken,
+        ) -> Result<ModelCheckResult> {
+            Ok(ModelCheckResult::Success)
+        }
+    }
+
+    struct TestPlugin;
+
+    impl Plugin for TestPlugin {
+        fn register(&self, registry: &mut PluginRegistry) -> Result<()> {
+            registry.add_engine(AlwaysSafe)?;
+            registry.add_pass("count-states", || Box::new(CountStates))
+        }
+    }
+
+    #[test]
+    fn test_plugin_registry() {
+        let mut registry = PluginRegistry::with_builtins();
+        registry.load(&TestPlugin).unwrap();
+        let expected: &[&str] = if cfg!(feature = "sat") {
+            &["smt-bmc", "sat-bmc", "always-safe"]
+        } else {
+            &["smt-bmc", "always-safe"]
+        };
+        assert_eq!(registry.engine_names(), expected);
+        // names need to be unique
+        assert!(matches!(
+            registry.load(&TestPlugin),
+            Err(PluginError::Duplicate { kind: "engine", .. })
+        ));
+        assert!(registry.engine("bdd").is_err());
+
+        let frontend = registry
+            .frontend_for(Path::new("../inputs/unittest/delay.btor"))
+            .unwrap();
+        assert_eq!(frontend.name(), "btor2");
+        let mut ctx = Context::default();
+        let mut sys = frontend
+            .parse(&mut ctx, Path::new("../inputs/unittest/delay.btor"))
+            .unwrap();
+        assert!(registry.frontend_for(Path::new("design.v")).is_err());
+
+        let engine = registry.engine("always-safe").unwrap();
+        let res = engine.check(&mut ctx, &sys, 4, &CancellationToken::default());
+        assert!(matches!(res, Ok(ModelCheckResult::Success)));
+
+        let mut sim = registry
+            .sim_backend("interpreter")
+            .unwrap()
+            .create(&ctx, &sys)
+            .unwrap();
+        sim.init(InitKind::Zero);
+        sim.step();
+        assert_eq!(sim.step_count(), 1);
+        drop(sim);
+
+        // plugin passes are added disabled
+        let mut pm = PassManager::new();
+        pm.add_plugin_passes(&registry);
+        assert!(pm.enabled_passes().is_empty());
+        assert!(pm.set_enabled("count-states", true));
+        pm.run(&mut ctx, &mut sys);
+        assert_eq!(sys.name, format!("{} states", sys.states.len()));
+    }
+}
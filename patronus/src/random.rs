@@ -0,0 +1,63 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+//! # Randomness
+//!
+//! All randomized utilities in patronus derive their random numbers from a single `u64` seed.
+//! Whenever a randomized run fails, the seed should be reported, so that the run can be
+//! replayed exactly by setting the `PATRONUS_SEED` environment variable.
+
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
+
+/// Environment variable that overrides the default seed.
+pub const SEED_ENV_VAR: &str = "PATRONUS_SEED";
+
+/// Seed that is used when no seed is specified.
+pub const DEFAULT_SEED: u64 = 0;
+
+/// Returns the seed from the `PATRONUS_SEED` environment variable or the [`DEFAULT_SEED`].
+pub fn default_seed() -> u64 {
+    parse_seed(std::env::var(SEED_ENV_VAR).ok().as_deref()).unwrap_or(DEFAULT_SEED)
+}
+
+fn parse_seed(value: Option<&str>) -> Option<u64> {
+    let value = value?.trim();
+    if let Some(hex) = value.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16).ok()
+    } else {
+        value.parse().ok()
+    }
+}
+
+/// Creates the random number generator that should be used by all randomized utilities.
+pub fn new_rng(seed: u64) -> SmallRng {
+    SmallRng::seed_from_u64(seed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_parse_seed() {
+        assert_eq!(parse_seed(None), None);
+        assert_eq!(parse_seed(Some("123")), Some(123));
+        assert_eq!(parse_seed(Some(" 0xff ")), Some(255));
+        assert_eq!(parse_seed(Some("abc")), None);
+    }
+
+    #[test]
+    fn test_rng_is_reproducible() {
+        let a: Vec<u64> = new_rng(7)
+            .sample_iter(rand::distributions::Standard)
+            .take(4)
+            .collect();
+        let b: Vec<u64> = new_rng(7)
+            .sample_iter(rand::distributions::Standard)
+            .take(4)
+            .collect();
+        assert_eq!(a, b);
+    }
+}
@@ -0,0 +1,20 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # SAT
+//! Bit-blasts bit-vector expressions into CNF which can be solved with an embedded CDCL
+//! solver (requires the `sat` feature) or exported in the DIMACS format, optionally together with a [`variable_order`]
+//! hint for external tools. This does not require an external SMT solver,
+//! but only supports operations that are cheap to bit-blast: no arrays, division or
+//! uninterpreted functions.
+
+mod blast;
+mod cnf;
+mod order;
+
+pub use blast::{decode, BitBlaster, BlastError};
+#[cfg(feature = "sat")]
+pub use cnf::SatSolver;
+pub use cnf::{Cnf, Lit, Model};
+pub use order::variable_order;
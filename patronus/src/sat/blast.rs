@@ -0,0 +1,464 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+use crate::expr::{Context, Expr, ExprRef, ForEachChild, SerializableIrNode, TypeCheck};
+use crate::sat::{Cnf, Lit, Model};
+use baa::{BitVecOps, BitVecValue};
+use rustc_hash::FxHashMap;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum BlastError {
+    #[error("cannot bit-blast `{0}`")]
+    Unsupported(String),
+}
+
+/// Translates bit-vector expressions into clauses with the Tseitin encoding.
+/// Bits are stored least significant bit first.
+pub struct BitBlaster<'a> {
+    ctx: &'a Context,
+    cnf: Cnf,
+    tru: Lit,
+    bindings: FxHashMap<ExprRef, Vec<Lit>>,
+    cache: FxHashMap<ExprRef, Vec<Lit>>,
+}
+
+impl<'a> BitBlaster<'a> {
+    pub fn new(ctx: &'a Context) -> Self {
+        let mut cnf = Cnf::default();
+        let tru = cnf.new_var();
+        cnf.add_clause(&[tru]);
+        Self {
+            ctx,
+            cnf,
+            tru,
+            bindings: FxHashMap::default(),
+            cache: FxHashMap::default(),
+        }
+    }
+
+    pub fn cnf(&self) -> &Cnf {
+        &self.cnf
+    }
+
+    pub fn into_cnf(self) -> Cnf {
+        self.cnf
+    }
+
+    /// Forgets all symbol bindings and translated expressions. Used to unroll a system, where
+    /// the same symbol stands for a different value in every step.
+    pub fn new_frame(&mut self) {
+        self.bindings.clear();
+        self.cache.clear();
+    }
+
+    /// Lets `symbol` evaluate to `bits` in the current frame.
+    pub fn bind(&mut self, symbol: ExprRef, bits: Vec<Lit>) {
+        debug_assert_eq!(symbol.get_bv_type(self.ctx), Some(bits.len() as u32));
+        debug_assert!(!self.cache.contains_key(&symbol), "symbol was already used");
+        self.bindings.insert(symbol, bits);
+    }
+
+    pub fn fresh(&mut self, width: u32) -> Vec<Lit> {
+        (0..width).map(|_| self.cnf.new_var()).collect()
+    }
+
+    /// Adds a clause that requires the 1-bit expression `e` to be true.
+    pub fn assert(&mut self, e: ExprRef) -> Result<(), BlastError> {
+        let bit = self.bit(e)?;
+        self.cnf.add_clause(&[bit]);
+        Ok(())
+    }
+
+    /// Adds clauses that require `a` and `b` to be equal.
+    pub fn assert_equal(&mut self, a: &[Lit], b: &[Lit]) {
+        let eq = self.equal(a, b);
+        self.cnf.add_clause(&[eq]);
+    }
+
+    /// Literal for a 1-bit expression.
+    pub fn bit(&mut self, e: ExprRef) -> Result<Lit, BlastError> {
+        let bits = self.bits(e)?;
+        debug_assert_eq!(bits.len(), 1);
+        Ok(bits[0])
+    }
+
+    /// Literals for a bit-vector expression. Symbols that were not bound are assigned
+    /// fresh variables.
+    pub fn bits(&mut self, root: ExprRef) -> Result<Vec<Lit>, BlastError> {
+        let ctx = self.ctx;
+        let mut todo = vec![root];
+        while let Some(&e) = todo.last() {
+            if self.cache.contains_key(&e) {
+                todo.pop();
+                continue;
+            }
+            let mut missing = vec![];
+            ctx[e].for_each_child(|c| {
+                if !self.cache.contains_key(c) {
+                    missing.push(*c);
+                }
+            });
+            if missing.is_empty() {
+                todo.pop();
+                let bits = self.blast(e)?;
+                self.cache.insert(e, bits);
+            } else {
+                todo.extend(missing);
+            }
+        }
+        Ok(self.cache[&root].clone())
+    }
+
+    pub fn or_all(&mut self, bits: &[Lit]) -> Lit {
+        bits.iter().fold(-self.tru, |acc, &b| self.or(acc, b))
+    }
+
+    fn blast(&mut self, e: ExprRef) -> Result<Vec<Lit>, BlastError> {
+        let ctx = self.ctx;
+        let get = |s: &Self, c: ExprRef| s.cache[&c].clone();
+        let bits = match ctx[e].clone() {
+            Expr::BVSymbol { width, .. } => match self.bindings.get(&e) {
+                Some(bits) => bits.clone(),
+                None => {
+                    let bits = self.fresh(width);
+                    self.bindings.insert(e, bits.clone());
+                    bits
+                }
+            },
+            Expr::BVLiteral(value) => {
+                let value = value.get(ctx);
+                (0..value.width())
+                    .map(|ii| {
+                        if value.is_bit_set(ii) {
+                            self.tru
+                        } else {
+                            -self.tru
+                        }
+                    })
+                    .collect()
+            }
+            Expr::BVZeroExt { e, by, .. } => {
+                let mut bits = get(self, e);
+                bits.extend(std::iter::repeat(-self.tru).take(by as usize));
+                bits
+            }
+            Expr::BVSignExt { e, by, .. } => {
+                let mut bits = get(self, e);
+                let msb = *bits.last().unwrap();
+                bits.extend(std::iter::repeat(msb).take(by as usize));
+                bits
+            }
+            Expr::BVSlice { e, hi, lo } => get(self, e)[lo as usize..=hi as usize].to_vec(),
+            Expr::BVNot(e, _) => get(self, e).into_iter().map(|b| -b).collect(),
+            Expr::BVNegate(e, width) => {
+                let zero = vec![-self.tru; width as usize];
+                self.sub(&zero, &get(self, e))
+            }
+            Expr::BVEqual(a, b) => {
+                let (a, b) = (get(self, a), get(self, b));
+                vec![self.equal(&a, &b)]
+            }
+            Expr::BVImplies(a, b) => {
+                let (a, b) = (get(self, a)[0], get(self, b)[0]);
+                vec![self.or(-a, b)]
+            }
+            Expr::BVGreater(a, b) => {
+                let (a, b) = (get(self, a), get(self, b));
+                vec![self.less(&b, &a)]
+            }
+            Expr::BVGreaterEqual(a, b) => {
+                let (a, b) = (get(self, a), get(self, b));
+                vec![-self.less(&a, &b)]
+            }
+            Expr::BVGreaterSigned(a, b, _) => {
+                let (a, b) = (flip_msb(get(self, a)), flip_msb(get(self, b)));
+                vec![self.less(&b, &a)]
+            }
+            Expr::BVGreaterEqualSigned(a, b, _) => {
+                let (a, b) = (flip_msb(get(self, a)), flip_msb(get(self, b)));
+                vec![-self.less(&a, &b)]
+            }
+            Expr::BVConcat(a, b, _) => {
+                let mut bits = get(self, b);
+                bits.extend(get(self, a));
+                bits
+            }
+            Expr::BVAnd(a, b, _) => self.bitwise(get(self, a), get(self, b), Self::and),
+            Expr::BVOr(a, b, _) => self.bitwise(get(self, a), get(self, b), Self::or),
+            Expr::BVXor(a, b, _) => self.bitwise(get(self, a), get(self, b), Self::xor),
+            Expr::BVShiftLeft(a, b, _) => self.shift(&get(self, a), &get(self, b), Shift::Left),
+            Expr::BVShiftRight(a, b, _) => self.shift(&get(self, a), &get(self, b), Shift::Right),
+            Expr::BVArithmeticShiftRight(a, b, _) => {
+                self.shift(&get(self, a), &get(self, b), Shift::ArithmeticRight)
+            }
+            Expr::BVAdd(a, b, _) => {
+                let (a, b) = (get(self, a), get(self, b));
+                self.add(&a, &b, -self.tru)
+            }
+            Expr::BVSub(a, b, _) => {
+                let (a, b) = (get(self, a), get(self, b));
+                self.sub(&a, &b)
+            }
+            Expr::BVMul(a, b, _) => {
+                let (a, b) = (get(self, a), get(self, b));
+                self.mul(&a, &b)
+            }
+            Expr::BVIte { cond, tru, fals } => {
+                let cond = get(self, cond)[0];
+                self.bitwise(get(self, tru), get(self, fals), |s, t, f| s.mux(cond, t, f))
+            }
+            _ => return Err(BlastError::Unsupported(e.serialize_to_str(ctx))),
+        };
+        Ok(bits)
+    }
+
+    fn and(&mut self, a: Lit, b: Lit) -> Lit {
+        let fals = -self.tru;
+        if a == fals || b == fals || a == -b {
+            fals
+        } else if a == self.tru || a == b {
+            b
+        } else if b == self.tru {
+            a
+        } else {
+            let o = self.cnf.new_var();
+            self.cnf.add_clause(&[-o, a]);
+            self.cnf.add_clause(&[-o, b]);
+            self.cnf.add_clause(&[o, -a, -b]);
+            o
+        }
+    }
+
+    fn or(&mut self, a: Lit, b: Lit) -> Lit {
+        -self.and(-a, -b)
+    }
+
+    fn xor(&mut self, a: Lit, b: Lit) -> Lit {
+        if a == b {
+            -self.tru
+        } else if a == -b {
+            self.tru
+        } else if a.abs() == self.tru {
+            if a == self.tru {
+                -b
+            } else {
+                b
+            }
+        } else if b.abs() == self.tru {
+            self.xor(b, a)
+        } else {
+            let o = self.cnf.new_var();
+            self.cnf.add_clause(&[-o, a, b]);
+            self.cnf.add_clause(&[-o, -a, -b]);
+            self.cnf.add_clause(&[o, -a, b]);
+            self.cnf.add_clause(&[o, a, -b]);
+            o
+        }
+    }
+
+    fn mux(&mut self, cond: Lit, tru: Lit, fals: Lit) -> Lit {
+        if cond == self.tru || tru == fals {
+            tru
+        } else if cond == -self.tru {
+            fals
+        } else {
+            let o = self.cnf.new_var();
+            self.cnf.add_clause(&[-cond, -tru, o]);
+            self.cnf.add_clause(&[-cond, tru, -o]);
+            self.cnf.add_clause(&[cond, -fals, o]);
+            self.cnf.add_clause(&[cond, fals, -o]);
+            o
+        }
+    }
+
+    fn bitwise(
+        &mut self,
+        a: Vec<Lit>,
+        b: Vec<Lit>,
+        mut op: impl FnMut(&mut Self, Lit, Lit) -> Lit,
+    ) -> Vec<Lit> {
+        a.into_iter().zip(b).map(|(a, b)| op(self, a, b)).collect()
+    }
+
+    fn equal(&mut self, a: &[Lit], b: &[Lit]) -> Lit {
+        a.iter().zip(b.iter()).fold(self.tru, |acc, (&a, &b)| {
+            let same = -self.xor(a, b);
+            self.and(acc, same)
+        })
+    }
+
+    /// unsigned less than
+    fn less(&mut self, a: &[Lit], b: &[Lit]) -> Lit {
+        // starting from the least significant bit, the most significant difference decides
+        a.iter().zip(b.iter()).fold(-self.tru, |less, (&a, &b)| {
+            let differ = self.xor(a, b);
+            self.mux(differ, b, less)
+        })
+    }
+
+    /// ripple carry adder
+    fn add(&mut self, a: &[Lit], b: &[Lit], carry_in: Lit) -> Vec<Lit> {
+        let mut carry = carry_in;
+        a.iter()
+            .zip(b.iter())
+            .map(|(&a, &b)| {
+                let half = self.xor(a, b);
+                let sum = self.xor(half, carry);
+                let generate = self.and(a, b);
+                let propagate = self.and(half, carry);
+                carry = self.or(generate, propagate);
+                sum
+            })
+            .collect()
+    }
+
+    fn sub(&mut self, a: &[Lit], b: &[Lit]) -> Vec<Lit> {
+        let not_b: Vec<_> = b.iter().map(|b| -b).collect();
+        self.add(a, &not_b, self.tru)
+    }
+
+    /// shift and add multiplier
+    fn mul(&mut self, a: &[Lit], b: &[Lit]) -> Vec<Lit> {
+        let width = a.len();
+        let mut product = vec![-self.tru; width];
+        for (shift, &b) in b.iter().enumerate() {
+            let partial: Vec<_> = (0..width)
+                .map(|ii| {
+                    if ii < shift {
+                        -self.tru
+                    } else {
+                        self.and(a[ii - shift], b)
+                    }
+                })
+                .collect();
+            product = self.add(&product, &partial, -self.tru);
+        }
+        product
+    }
+
+    /// barrel shifter
+    fn shift(&mut self, a: &[Lit], amount: &[Lit], kind: Shift) -> Vec<Lit> {
+        let width = a.len();
+        let fill = match kind {
+            Shift::ArithmeticRight => *a.last().unwrap(),
+            _ => -self.tru,
+        };
+        let mut value = a.to_vec();
+        for (stage, &bit) in amount.iter().enumerate() {
+            let by = 1usize.checked_shl(stage as u32).unwrap_or(usize::MAX);
+            let shifted: Vec<_> = (0..width)
+                .map(|ii| {
+                    let src = match kind {
+                        Shift::Left => ii.checked_sub(by),
+                        _ => ii.checked_add(by).filter(|s| *s < width),
+                    };
+                    src.map(|s| value[s]).unwrap_or(fill)
+                })
+                .collect();
+            value = self.bitwise(shifted, value, |s, shifted, old| s.mux(bit, shifted, old));
+        }
+        value
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Shift {
+    Left,
+    Right,
+    ArithmeticRight,
+}
+
+/// Flipping the sign bit turns a signed into an unsigned comparison.
+fn flip_msb(mut bits: Vec<Lit>) -> Vec<Lit> {
+    let msb = bits.last_mut().unwrap();
+    *msb = -*msb;
+    bits
+}
+
+/// Reads the value of `bits` from a satisfying assignment.
+pub fn decode(model: &Model, bits: &[Lit]) -> BitVecValue {
+    let bit_str: String = bits
+        .iter()
+        .rev()
+        .map(|&b| if model.value(b) { '1' } else { '0' })
+        .collect();
+    BitVecValue::from_bit_str(&bit_str).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "sat")]
+    use crate::expr::eval_bv_expr;
+    #[cfg(feature = "sat")]
+    use crate::sat::SatSolver;
+
+    /// Checks that the bit-blasted expression agrees with the evaluator on a counterexample
+    /// to `e == 0`.
+    #[cfg(feature = "sat")]
+    fn check_against_eval(ctx: &mut Context, e: ExprRef, symbols: &[ExprRef]) {
+        let width = e.get_bv_type(ctx).unwrap();
+        let is_zero = ctx.build(|c| c.equal(e, c.zero(width)));
+        let mut blaster = BitBlaster::new(ctx);
+        let bit = blaster.bit(is_zero).unwrap();
+        let result = blaster.bits(e).unwrap();
+        let symbol_bits: Vec<_> = symbols.iter().map(|&s| blaster.bits(s).unwrap()).collect();
+        let cnf = blaster.into_cnf();
+        let model = SatSolver::default().solve(&cnf, &[-bit]).unwrap();
+        let assignment: Vec<_> = symbols
+            .iter()
+            .zip(symbol_bits.iter())
+            .map(|(&s, bits)| (s, decode(&model, bits)))
+            .collect();
+        assert_eq!(
+            eval_bv_expr(ctx, assignment.as_slice(), e),
+            decode(&model, &result),
+            "{}",
+            e.serialize_to_str(ctx)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "sat")]
+    fn test_agrees_with_eval() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("a", 8);
+        let b = ctx.bv_symbol("b", 8);
+        let exprs = [
+            ctx.add(a, b),
+            ctx.sub(a, b),
+            ctx.mul(a, b),
+            ctx.shift_left(a, b),
+            ctx.shift_right(a, b),
+            ctx.arithmetic_shift_right(a, b),
+            ctx.build(|c| c.concat(c.slice(a, 3, 0), c.slice(b, 7, 4))),
+            ctx.build(|c| c.ite(c.greater_signed(a, b), c.not(a), b)),
+            ctx.build(|c| c.zero_extend(c.greater_or_equal(a, b), 7)),
+        ];
+        for e in exprs {
+            check_against_eval(&mut ctx, e, &[a, b]);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "sat")]
+    fn test_commutative_add() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("a", 16);
+        let b = ctx.bv_symbol("b", 16);
+        let differ = ctx.build(|c| c.not(c.equal(c.add(a, b), c.add(b, a))));
+        let mut blaster = BitBlaster::new(&ctx);
+        blaster.assert(differ).unwrap();
+        assert!(SatSolver::default().solve(blaster.cnf(), &[]).is_none());
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("a", 8);
+        let div = ctx.build(|c| c.div(a, c.bit_vec_val(3, 8)));
+        let mut blaster = BitBlaster::new(&ctx);
+        assert!(matches!(blaster.bits(div), Err(BlastError::Unsupported(_))));
+    }
+}
@@ -0,0 +1,172 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+use std::io::Write;
+
+/// Keeps the comment lines of a variable order short.
+const ORDER_VARS_PER_LINE: usize = 32;
+
+/// A literal in DIMACS notation: a positive or negated variable index, starting at one.
+pub type Lit = i32;
+
+/// A formula in conjunctive normal form.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cnf {
+    num_vars: u32,
+    clauses: Vec<Vec<Lit>>,
+}
+
+impl Cnf {
+    pub fn new_var(&mut self) -> Lit {
+        self.num_vars += 1;
+        self.num_vars as Lit
+    }
+
+    pub fn add_clause(&mut self, clause: &[Lit]) {
+        debug_assert!(clause
+            .iter()
+            .all(|l| *l != 0 && l.unsigned_abs() <= self.num_vars));
+        self.clauses.push(clause.to_vec());
+    }
+
+    pub fn num_vars(&self) -> u32 {
+        self.num_vars
+    }
+
+    pub fn clauses(&self) -> &[Vec<Lit>] {
+        &self.clauses
+    }
+
+    pub fn write_dimacs(&self, out: &mut impl Write) -> std::io::Result<()> {
+        writeln!(out, "p cnf {} {}", self.num_vars, self.clauses.len())?;
+        for clause in self.clauses.iter() {
+            for lit in clause.iter() {
+                write!(out, "{lit} ")?;
+            }
+            writeln!(out, "0")?;
+        }
+        Ok(())
+    }
+
+    pub fn to_dimacs(&self) -> String {
+        let mut out = Vec::new();
+        self.write_dimacs(&mut out)
+            .expect("writing to a Vec<u8> never fails");
+        String::from_utf8(out).unwrap()
+    }
+
+    /// Precedes the formula with `c order` comments which, concatenated, list the variables
+    /// in `order`, e.g., the one computed by [`crate::sat::variable_order`]. Solvers ignore
+    /// comments, thus the file remains valid DIMACS.
+    pub fn write_dimacs_with_order(
+        &self,
+        out: &mut impl Write,
+        order: &[u32],
+    ) -> std::io::Result<()> {
+        for chunk in order.chunks(ORDER_VARS_PER_LINE) {
+            write!(out, "c order")?;
+            for var in chunk.iter() {
+                write!(out, " {var}")?;
+            }
+            writeln!(out)?;
+        }
+        self.write_dimacs(out)
+    }
+
+    pub fn to_dimacs_with_order(&self, order: &[u32]) -> String {
+        let mut out = Vec::new();
+        self.write_dimacs_with_order(&mut out, order)
+            .expect("writing to a Vec<u8> never fails");
+        String::from_utf8(out).unwrap()
+    }
+}
+
+/// A satisfying assignment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Model {
+    /// value of every variable, indexed by the variable id
+    values: Vec<bool>,
+}
+
+impl Model {
+    pub fn value(&self, lit: Lit) -> bool {
+        let value = self
+            .values
+            .get(lit.unsigned_abs() as usize)
+            .copied()
+            .unwrap_or(false);
+        if lit > 0 {
+            value
+        } else {
+            !value
+        }
+    }
+}
+
+/// Embedded CDCL solver that supports adding clauses between calls to [`SatSolver::solve`].
+#[cfg(feature = "sat")]
+#[derive(Default)]
+pub struct SatSolver {
+    solver: varisat::Solver<'static>,
+    /// number of clauses of the formula that were already added to the solver
+    num_clauses: usize,
+}
+
+#[cfg(feature = "sat")]
+impl SatSolver {
+    /// Solves `cnf` under the `assumptions`. The formula may only grow between calls.
+    pub fn solve(&mut self, cnf: &Cnf, assumptions: &[Lit]) -> Option<Model> {
+        use varisat::ExtendFormula;
+        for clause in cnf.clauses[self.num_clauses..].iter() {
+            let clause: Vec<_> = clause.iter().map(|&l| to_varisat(l)).collect();
+            self.solver.add_clause(&clause);
+        }
+        self.num_clauses = cnf.clauses.len();
+        let assumptions: Vec<_> = assumptions.iter().map(|&l| to_varisat(l)).collect();
+        self.solver.assume(&assumptions);
+        let sat = self
+            .solver
+            .solve()
+            .expect("solver does not use proofs or callbacks that could fail");
+        if !sat {
+            return None;
+        }
+        let mut values = vec![false; cnf.num_vars as usize + 1];
+        for lit in self.solver.model().unwrap() {
+            if let Some(value) = values.get_mut(lit.var().to_dimacs() as usize) {
+                *value = lit.is_positive();
+            }
+        }
+        Some(Model { values })
+    }
+}
+
+#[cfg(feature = "sat")]
+fn to_varisat(lit: Lit) -> varisat::Lit {
+    varisat::Lit::from_dimacs(lit as isize)
+}
+
+#[cfg(all(test, feature = "sat"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dimacs_and_solve() {
+        let mut cnf = Cnf::default();
+        let a = cnf.new_var();
+        let b = cnf.new_var();
+        cnf.add_clause(&[a, b]);
+        cnf.add_clause(&[-a]);
+        assert_eq!(cnf.to_dimacs(), "p cnf 2 2\n1 2 0\n-1 0\n");
+        let mut solver = SatSolver::default();
+        let model = solver.solve(&cnf, &[]).unwrap();
+        assert!(!model.value(a));
+        assert!(model.value(b));
+        assert!(solver.solve(&cnf, &[-b]).is_none());
+        // assumptions only apply to a single call
+        assert!(solver.solve(&cnf, &[]).is_some());
+        cnf.add_clause(&[-b, a]);
+        assert!(solver.solve(&cnf, &[]).is_none());
+    }
+}
@@ -0,0 +1,105 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Variable Order
+//! A hint for external SAT and BDD tools in which order variables should be decided or placed.
+//! The bit-blaster always creates the inputs of a gate before its output, thus the largest
+//! variable of every clause is treated as the output of a gate and all other variables as its
+//! inputs. Starting from the asserted unit clauses, the cone of influence is traversed depth
+//! first, visiting the input with the deepest fan-in cone first, and variables are ordered
+//! after their inputs. Bits that feed into the same gates thus end up next to each other.
+//! Variables outside of the cone of influence of any asserted literal are appended in the
+//! order in which they were created.
+
+use super::Cnf;
+
+/// Orders all variables of `cnf`. Every variable appears exactly once.
+pub fn variable_order(cnf: &Cnf) -> Vec<u32> {
+    let num_vars = cnf.num_vars() as usize;
+    let mut fanin: Vec<Vec<u32>> = vec![vec![]; num_vars + 1];
+    let mut roots = vec![];
+    for clause in cnf.clauses() {
+        let Some(out) = clause.iter().map(|l| l.unsigned_abs()).max() else {
+            continue;
+        };
+        if clause.len() == 1 {
+            roots.push(out);
+        }
+        for var in clause.iter().map(|l| l.unsigned_abs()) {
+            if var != out && !fanin[out as usize].contains(&var) {
+                fanin[out as usize].push(var);
+            }
+        }
+    }
+
+    // inputs always have a smaller index than the output of their gate
+    let mut depth = vec![0u32; num_vars + 1];
+    for (var, inputs) in fanin.iter().enumerate().skip(1) {
+        let inputs_depth = inputs.iter().map(|&f| depth[f as usize] + 1).max();
+        depth[var] = inputs_depth.unwrap_or(0);
+    }
+    let deepest_first =
+        |vars: &mut [u32]| vars.sort_by_key(|&v| std::cmp::Reverse(depth[v as usize]));
+    for inputs in fanin.iter_mut() {
+        deepest_first(inputs);
+    }
+    deepest_first(&mut roots);
+
+    let mut order = Vec::with_capacity(num_vars);
+    let mut visited = vec![false; num_vars + 1];
+    for root in roots {
+        if visited[root as usize] {
+            continue;
+        }
+        visited[root as usize] = true;
+        let mut todo = vec![(root, 0usize)];
+        while let Some(&(var, next)) = todo.last() {
+            match fanin[var as usize].get(next) {
+                Some(&input) => {
+                    todo.last_mut().unwrap().1 += 1;
+                    if !visited[input as usize] {
+                        visited[input as usize] = true;
+                        todo.push((input, 0));
+                    }
+                }
+                None => {
+                    order.push(var);
+                    todo.pop();
+                }
+            }
+        }
+    }
+    order.extend((1..=num_vars as u32).filter(|&v| !visited[v as usize]));
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_and_gate_order() {
+        let mut cnf = Cnf::default();
+        let tru = cnf.new_var();
+        cnf.add_clause(&[tru]);
+        let (a, b, c) = (cnf.new_var(), cnf.new_var(), cnf.new_var());
+        // z = a & (b | c)
+        let or = cnf.new_var();
+        cnf.add_clause(&[-b, or]);
+        cnf.add_clause(&[-c, or]);
+        cnf.add_clause(&[-or, b, c]);
+        let z = cnf.new_var();
+        cnf.add_clause(&[-z, a]);
+        cnf.add_clause(&[-z, or]);
+        cnf.add_clause(&[z, -a, -or]);
+        cnf.add_clause(&[z]);
+        let unused = cnf.new_var();
+
+        let order = variable_order(&cnf);
+        // the deeper `or` gate comes before `a`
+        assert_eq!(order, [b, c, or, a, z, tru, unused].map(|v| v as u32));
+        let dimacs = cnf.to_dimacs_with_order(&order);
+        assert!(dimacs.starts_with("c order 3 4 5 2 6 1 7\np cnf 7 8\n"));
+    }
+}
@@ -1,8 +1,64 @@
 // Copyright 2023 The Regents of the University of California
 // released under BSD 3-Clause License
 // author: Kevin Laeufer <laeufer@berkeley.edu>
+mod activity;
+mod backend;
+mod batch;
+mod bytecode;
+mod cosim;
+mod fault;
+mod golden;
+mod gpu;
 mod interface;
 mod interpreter;
+mod lockstep;
+mod memory_trace;
+mod monitor;
+mod offline;
+mod overflow;
+mod perf;
+mod program_cache;
+#[cfg(feature = "wellen")]
+mod recorded;
+mod replay;
+mod revisit;
+mod snapshot;
+mod state_image;
+mod stimulus;
+mod symbolic_init;
+mod two_phase;
+mod waves;
+mod wide;
+mod xprop;
 
+pub use activity::ActivityStats;
+pub use backend::{create, Backend, BackendChoice};
+pub use batch::{BatchError, BatchSimulator};
+pub use cosim::{
+    Cosim, CosimError, Divergence, ExternalSimulator, NamedSimulator, ProcessSimulator,
+};
+pub use fault::{Fault, FaultCampaign, FaultOutcome, FaultReport};
+pub use golden::{
+    Expectation, GoldenChecker, GoldenError, GoldenMismatch, GoldenReport, GoldenVectors,
+};
+pub use gpu::{lower_to_wgsl, GpuDevice, GpuError, GpuKernel, GpuRunner};
 pub use interface::*;
 pub use interpreter::*;
+pub use lockstep::{LockstepError, LockstepRunner, Mismatch, SignalDiff};
+pub use memory_trace::{MemoryRecorder, MemoryTrace, MemoryWaves};
+pub use monitor::Monitored;
+pub use offline::{OfflineChecker, OfflineReport, Violation, ViolationKind};
+pub use overflow::{ArithOp, OverflowChecker, OverflowEvent, OverflowOptions, Signedness};
+pub use perf::PerfReport;
+pub use program_cache::ProgramCache;
+#[cfg(feature = "wellen")]
+pub use recorded::{RecordedTrace, WaveformError, WaveformOptions};
+pub use replay::{ReplayError, ReplayEvent, ReplayLog, ReplayRecorder};
+pub use revisit::Revisit;
+pub use snapshot::SnapshotSpill;
+pub use state_image::{StateImage, StateImageError, STATE_IMAGE_VERSION};
+pub use stimulus::{parse_value, Stimulus, StimulusError, StimulusRecorder};
+pub use symbolic_init::{install_init, solve_init, InitError, InitResult};
+pub use two_phase::StaleRead;
+pub use waves::{SignalWaves, WaveRecorder, Waves, WavesIter};
+pub use xprop::{check_reset_coverage, ResetCoverage, UnresetState, XSimulator, XValue};
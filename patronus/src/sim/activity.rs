@@ -0,0 +1,126 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Activity Tracking
+//! In most designs only a small part of the state changes in every cycle. If none of the
+//! inputs and states that a next state function reads changed since the previous step, the
+//! function returns the same value as before, which is the current value of its state.
+//! We precompute the support of every next state function and only re-evaluate the ones whose
+//! support intersects the set of symbols that changed.
+
+use crate::expr::{Context, ExprRef, ForEachChild};
+use crate::system::TransitionSystem;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+/// Number of next state functions that were evaluated or skipped since sparse evaluation was
+/// enabled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ActivityStats {
+    pub evaluated: u64,
+    pub skipped: u64,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ActivityTracker {
+    /// dense index for every state and input
+    index: FxHashMap<ExprRef, u32>,
+    /// for every next state function, the indices of the symbols it reads
+    support: Vec<Vec<u32>>,
+    /// index of the state that every next state function updates
+    target: Vec<u32>,
+    changed: Vec<bool>,
+    /// set after an operation that could have changed any value
+    all_changed: bool,
+    stats: ActivityStats,
+}
+
+impl ActivityTracker {
+    /// `next_functions` contains `(state, next)` pairs.
+    pub(crate) fn new(
+        ctx: &Context,
+        sys: &TransitionSystem,
+        next_functions: &[(ExprRef, ExprRef)],
+    ) -> Self {
+        let index: FxHashMap<ExprRef, u32> = sys
+            .states
+            .iter()
+            .map(|s| s.symbol)
+            .chain(sys.inputs.iter().copied())
+            .enumerate()
+            .map(|(ii, e)| (e, ii as u32))
+            .collect();
+        let support = next_functions
+            .iter()
+            .map(|&(_, next)| support(ctx, &index, next))
+            .collect();
+        let target = next_functions
+            .iter()
+            .map(|(state, _)| index[state])
+            .collect();
+        Self {
+            changed: vec![false; index.len()],
+            index,
+            support,
+            target,
+            all_changed: true,
+            stats: ActivityStats::default(),
+        }
+    }
+
+    /// Records that `symbol` was modified outside of a step.
+    pub(crate) fn mark(&mut self, symbol: ExprRef) {
+        if let Some(&ii) = self.index.get(&symbol) {
+            self.changed[ii as usize] = true;
+        }
+    }
+
+    pub(crate) fn mark_all(&mut self) {
+        self.all_changed = true;
+    }
+
+    /// Whether the `ii`-th next state function needs to be evaluated in the current step.
+    /// A state that was modified from the outside always needs to be updated.
+    pub(crate) fn needs_eval(&mut self, ii: usize) -> bool {
+        let needed = self.all_changed
+            || self.changed[self.target[ii] as usize]
+            || self.support[ii].iter().any(|&s| self.changed[s as usize]);
+        if needed {
+            self.stats.evaluated += 1;
+        } else {
+            self.stats.skipped += 1;
+        }
+        needed
+    }
+
+    /// Starts tracking changes for the next step, `changed_states` are the states whose value
+    /// was updated by the step that just finished.
+    pub(crate) fn finish_step(&mut self, changed_states: impl Iterator<Item = ExprRef>) {
+        self.changed.fill(false);
+        self.all_changed = false;
+        for state in changed_states {
+            self.mark(state);
+        }
+    }
+
+    pub(crate) fn stats(&self) -> ActivityStats {
+        self.stats
+    }
+}
+
+fn support(ctx: &Context, index: &FxHashMap<ExprRef, u32>, root: ExprRef) -> Vec<u32> {
+    let mut visited = FxHashSet::default();
+    let mut todo = vec![root];
+    let mut out = vec![];
+    while let Some(e) = todo.pop() {
+        if !visited.insert(e) {
+            continue;
+        }
+        if let Some(&ii) = index.get(&e) {
+            out.push(ii);
+        }
+        ctx[e].for_each_child(|&c| todo.push(c));
+    }
+    out.sort_unstable();
+    out
+}
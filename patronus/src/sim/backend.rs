@@ -0,0 +1,65 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+use super::{EvalOrder, Interpreter};
+use crate::expr::{Context, SerializableIrNode};
+use crate::system::TransitionSystem;
+use serde::{Deserialize, Serialize};
+
+/// Simulator implementation that should be used.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// Picks the fastest backend that supports the transition system.
+    #[default]
+    Auto,
+    /// Interpreter with the default evaluation order.
+    Interpreter,
+    /// Interpreter that executes the system compiled to bytecode, see [`EvalOrder::Bytecode`].
+    Bytecode,
+}
+
+/// Records which backend was selected by [`create`] and why.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BackendChoice {
+    pub requested: Backend,
+    pub selected: Backend,
+    pub reason: String,
+}
+
+/// Creates a simulator for `sys`. With [`Backend::Auto`] and [`Backend::Bytecode`], the system
+/// is compiled to bytecode. If it contains an operation that the bytecode does not support,
+/// we fall back to the interpreter with [`EvalOrder::Eager`].
+pub fn create<'a>(
+    ctx: &'a Context,
+    sys: &'a TransitionSystem,
+    backend: Backend,
+) -> (Interpreter<'a>, BackendChoice) {
+    let mut sim = Interpreter::new(ctx, sys);
+    let (selected, reason) = match backend {
+        Backend::Interpreter => (
+            Backend::Interpreter,
+            "interpreter was explicitly requested".to_string(),
+        ),
+        Backend::Auto | Backend::Bytecode => match sim.try_set_eval_order(EvalOrder::Bytecode) {
+            Ok(()) => (
+                Backend::Bytecode,
+                "all expressions can be compiled to bytecode".to_string(),
+            ),
+            Err(e) => (
+                Backend::Interpreter,
+                format!(
+                    "`{}` cannot be compiled to bytecode, falling back to the eager interpreter",
+                    e.0.serialize_to_str(ctx)
+                ),
+            ),
+        },
+    };
+    let choice = BackendChoice {
+        requested: backend,
+        selected,
+        reason,
+    };
+    (sim, choice)
+}
@@ -0,0 +1,304 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Batch Simulation
+//! Simulates several independent runs of the same system at once. Every signal stores one
+//! value per run, called a lane, and every instruction of the [bytecode](super::bytecode) is
+//! executed for all lanes before moving on to the next one. The cost of walking the schedule
+//! is thus shared between all runs, which makes constrained-random testing with many short
+//! runs a lot cheaper than using one [`Interpreter`](super::Interpreter) per run.
+//! Like the bytecode, batch simulation only supports bit-vector operations.
+
+use super::bytecode::Program;
+use super::interpreter::schedule;
+use super::{InitKind, InitValueGenerator, SimError};
+use crate::expr::{eval_bv_expr, Context, ExprRef, GetExprValue, TypeCheck};
+use crate::system::{init_order, TransitionSystem};
+use baa::{ArrayValue, BitVecOps, BitVecValue, BitVecValueRef, Value};
+use rustc_hash::FxHashMap;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum BatchError {
+    #[error("{0:?} is not supported in batch simulation, only bit-vector operations are")]
+    Unsupported(ExprRef),
+    #[error("batch simulation needs at least one lane")]
+    NoLanes,
+}
+
+pub struct BatchSimulator<'a> {
+    ctx: &'a Context,
+    sys: &'a TransitionSystem,
+    program: Program,
+    lanes: usize,
+    /// register `r` of lane `l` is stored at `r * lanes + l`
+    regs: Vec<BitVecValue>,
+    /// register of every state and input
+    symbols: FxHashMap<ExprRef, u32>,
+    /// registers of every state and of its next state function
+    next: Vec<(u32, u32)>,
+    init_order: Vec<usize>,
+    /// set when inputs or states changed since the program last ran
+    stale: bool,
+    step_count: u64,
+}
+
+impl<'a> BatchSimulator<'a> {
+    pub fn new(
+        ctx: &'a Context,
+        sys: &'a TransitionSystem,
+        lanes: usize,
+    ) -> Result<Self, BatchError> {
+        if lanes == 0 {
+            return Err(BatchError::NoLanes);
+        }
+        let mut program =
+            Program::compile(ctx, &schedule(ctx, sys)).map_err(|e| BatchError::Unsupported(e.0))?;
+        let mut symbols = FxHashMap::default();
+        for symbol in sys
+            .states
+            .iter()
+            .map(|s| s.symbol)
+            .chain(sys.inputs.iter().copied())
+        {
+            let reg = program
+                .operand(ctx, symbol)
+                .map_err(|e| BatchError::Unsupported(e.0))?;
+            symbols.insert(symbol, reg);
+        }
+        let mut next = vec![];
+        for state in sys.states.iter() {
+            if let Some(expr) = state.next {
+                let reg = program
+                    .operand(ctx, expr)
+                    .map_err(|e| BatchError::Unsupported(e.0))?;
+                next.push((symbols[&state.symbol], reg));
+            }
+        }
+        Ok(Self {
+            ctx,
+            sys,
+            regs: program.new_lane_registers(lanes),
+            program,
+            lanes,
+            symbols,
+            next,
+            init_order: init_order(ctx, sys),
+            stale: true,
+            step_count: 0,
+        })
+    }
+
+    pub fn lanes(&self) -> usize {
+        self.lanes
+    }
+
+    fn at(&self, reg: u32, lane: usize) -> usize {
+        reg as usize * self.lanes + lane
+    }
+
+    /// Initializes states and inputs of all lanes. Random values differ between lanes.
+    pub fn init(&mut self, kind: InitKind) {
+        let mut gen = InitValueGenerator::from_kind(kind);
+        for lane in 0..self.lanes {
+            for symbol in self
+                .sys
+                .states
+                .iter()
+                .map(|s| s.symbol)
+                .chain(self.sys.inputs.iter().copied())
+            {
+                let tpe = self.ctx[symbol].get_type(self.ctx);
+                let index = self.at(self.symbols[&symbol], lane);
+                self.regs[index] = match gen.gen(tpe) {
+                    Value::BitVec(value) => value,
+                    Value::Array(_) => unreachable!("array symbols are rejected by `new`"),
+                };
+            }
+            self.eval_init(lane);
+        }
+        self.step_count = 0;
+        self.stale = true;
+        self.update();
+    }
+
+    fn eval_init(&mut self, lane: usize) {
+        for &ii in self.init_order.iter() {
+            let state = &self.sys.states[ii];
+            let value = eval_bv_expr(self.ctx, &self.lane(lane), state.init.unwrap());
+            let index = self.at(self.symbols[&state.symbol], lane);
+            self.regs[index] = value;
+        }
+    }
+
+    fn lane(&self, lane: usize) -> Lane<'_> {
+        Lane {
+            symbols: &self.symbols,
+            regs: &self.regs,
+            lanes: self.lanes,
+            lane,
+        }
+    }
+
+    /// Changes the value of an input or state in a single lane.
+    pub fn set<'b>(
+        &mut self,
+        lane: usize,
+        symbol: ExprRef,
+        value: impl Into<BitVecValueRef<'b>>,
+    ) -> Result<(), SimError> {
+        let value = value.into();
+        let reg = match self.symbols.get(&symbol) {
+            Some(&reg) if lane < self.lanes => reg,
+            _ => return Err(SimError::UnknownSymbol(symbol)),
+        };
+        let index = self.at(reg, lane);
+        let expected = self.regs[index].width();
+        if expected != value.width() {
+            return Err(SimError::WidthMismatch {
+                expected,
+                actual: value.width(),
+            });
+        }
+        self.regs[index] = value.into();
+        self.stale = true;
+        Ok(())
+    }
+
+    /// Recomputes all combinational signals after inputs were changed with
+    /// [`BatchSimulator::set`].
+    pub fn update(&mut self) {
+        if self.stale {
+            self.program.run_lanes(self.lanes, &mut self.regs);
+            self.stale = false;
+        }
+    }
+
+    /// Advances all lanes by one step.
+    pub fn step(&mut self) {
+        self.update();
+        // read all next states before updating, since they might refer to other states
+        let values: Vec<BitVecValue> = self
+            .next
+            .iter()
+            .flat_map(|&(_, next)| (0..self.lanes).map(move |lane| (next, lane)))
+            .map(|(next, lane)| self.regs[self.at(next, lane)].clone())
+            .collect();
+        let targets = self
+            .next
+            .iter()
+            .flat_map(|&(state, _)| (0..self.lanes).map(move |lane| (state, lane)));
+        for ((state, lane), value) in targets.zip(values) {
+            let index = self.at(state, lane);
+            self.regs[index] = value;
+        }
+        self.step_count += 1;
+        self.stale = true;
+    }
+
+    /// Value of a bit-vector expression in a single lane. Combinational signals reflect changes
+    /// made with [`BatchSimulator::set`] or [`BatchSimulator::step`] only after calling
+    /// [`BatchSimulator::update`].
+    pub fn get(&self, lane: usize, expr: ExprRef) -> Option<BitVecValue> {
+        if lane >= self.lanes {
+            return None;
+        }
+        match self.program.register(expr) {
+            Some(reg) => Some(self.regs[self.at(reg, lane)].clone()),
+            None if expr.get_bv_type(self.ctx).is_some() => {
+                Some(eval_bv_expr(self.ctx, &self.lane(lane), expr))
+            }
+            None => None,
+        }
+    }
+
+    /// Lanes in which the bad state `index` is currently active.
+    pub fn failing_lanes(&self, index: usize) -> Vec<usize> {
+        let bad = self.sys.bad_states[index];
+        (0..self.lanes)
+            .filter(|&lane| self.get(lane, bad).is_some_and(|v| v.is_true()))
+            .collect()
+    }
+
+    pub fn step_count(&self) -> u64 {
+        self.step_count
+    }
+}
+
+/// Symbol values of a single lane.
+struct Lane<'a> {
+    symbols: &'a FxHashMap<ExprRef, u32>,
+    regs: &'a [BitVecValue],
+    lanes: usize,
+    lane: usize,
+}
+
+impl GetExprValue for Lane<'_> {
+    fn get_bv(&self, _ctx: &Context, symbol: ExprRef) -> Option<BitVecValue> {
+        let reg = *self.symbols.get(&symbol)? as usize;
+        Some(self.regs[reg * self.lanes + self.lane].clone())
+    }
+
+    fn get_array(&self, _ctx: &Context, _symbol: ExprRef) -> Option<ArrayValue> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::{Interpreter, Simulator};
+    use crate::system::State;
+
+    #[test]
+    fn test_lanes_match_interpreter() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("acc".to_string());
+        let a = ctx.bv_symbol("a", 8);
+        sys.add_input(&ctx, a);
+        let acc = ctx.bv_symbol("acc", 8);
+        let next = ctx.build(|c| c.add(acc, c.xor(a, c.shift_left(acc, c.one(8)))));
+        let init = ctx.bit_vec_val(3, 8);
+        sys.add_state(
+            &ctx,
+            State {
+                symbol: acc,
+                init: Some(init),
+                next: Some(next),
+            },
+        );
+        let bad = ctx.build(|c| c.equal(acc, c.bit_vec_val(7, 8)));
+        sys.bad_states.push(bad);
+
+        let lanes = 4;
+        let mut batch = BatchSimulator::new(&ctx, &sys, lanes).unwrap();
+        batch.init(InitKind::Zero);
+        let mut reference: Vec<_> = (0..lanes).map(|_| Interpreter::new(&ctx, &sys)).collect();
+        for sim in reference.iter_mut() {
+            sim.init(InitKind::Zero);
+        }
+        for step in 0..20u64 {
+            for (lane, sim) in reference.iter_mut().enumerate() {
+                let value = BitVecValue::from_u64((step * 13 + lane as u64 * 71) % 256, 8);
+                batch.set(lane, a, &value).unwrap();
+                sim.set(a, &value).unwrap();
+            }
+            batch.update();
+            for (lane, sim) in reference.iter().enumerate() {
+                for e in [acc, next, bad] {
+                    assert_eq!(
+                        batch.get(lane, e).unwrap().to_u64().unwrap(),
+                        sim.get(e).try_into_u64().unwrap()
+                    );
+                }
+                assert_eq!(
+                    batch.failing_lanes(0).contains(&lane),
+                    sim.get(bad).try_into_u64().unwrap() == 1
+                );
+            }
+            batch.step();
+            reference.iter_mut().for_each(|sim| sim.step());
+        }
+        assert_eq!(batch.step_count(), 20);
+    }
+}
@@ -0,0 +1,484 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Bytecode
+//! Flattens the expressions of a system into a linear list of instructions that read their
+//! operands from and write their result to numbered registers. Executing the program is a
+//! single loop without any hash lookups or recursion, which makes it faster than evaluating
+//! the expression graph, while still being portable, unlike a JIT.
+//! Only bit-vector operations are supported, systems with arrays need to use one of the other
+//! evaluation strategies.
+//! Operations on operands wider than [`WIDE_THRESHOLD`] bits are executed by the kernels in
+//! [`super::wide`], which write their result directly into the destination register.
+//! Compiled programs can be stored on disk with [`super::ProgramCache`].
+
+use super::wide::{Kernels, WideOp, WIDE_THRESHOLD};
+use crate::expr::{
+    put_bv, put_u64, take_bv, take_u64, Context, Expr, ExprRef, ForEachChild, GetExprValue,
+    TypeCheck, WidthInt,
+};
+use baa::{BitVecOps, BitVecValue};
+use rustc_hash::FxHashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    ZeroExt(WidthInt),
+    SignExt(WidthInt),
+    Slice(WidthInt, WidthInt),
+    Not,
+    Negate,
+    Equal,
+    Implies,
+    Greater,
+    GreaterSigned,
+    GreaterEqual,
+    GreaterEqualSigned,
+    Concat,
+    And,
+    Or,
+    Xor,
+    ShiftLeft,
+    ShiftRight,
+    ArithmeticShiftRight,
+    Add,
+    Mul,
+    Sub,
+    Ite,
+    /// operation on wide operands
+    Wide(WideOp),
+}
+
+/// Operations without parameters, encoded by their position.
+const SIMPLE_OPS: [Op; 19] = [
+    Op::Not,
+    Op::Negate,
+    Op::Equal,
+    Op::Implies,
+    Op::Greater,
+    Op::GreaterSigned,
+    Op::GreaterEqual,
+    Op::GreaterEqualSigned,
+    Op::Concat,
+    Op::And,
+    Op::Or,
+    Op::Xor,
+    Op::ShiftLeft,
+    Op::ShiftRight,
+    Op::ArithmeticShiftRight,
+    Op::Add,
+    Op::Mul,
+    Op::Sub,
+    Op::Ite,
+];
+
+impl Op {
+    fn encode(self, out: &mut Vec<u8>) {
+        let (tag, a, b) = match self {
+            Op::ZeroExt(by) => (0, by, 0),
+            Op::SignExt(by) => (1, by, 0),
+            Op::Slice(hi, lo) => (2, hi, lo),
+            Op::Wide(op) => (3, op as WidthInt, 0),
+            simple => {
+                let index = SIMPLE_OPS.iter().position(|&o| o == simple).unwrap();
+                (4 + index as u64, 0, 0)
+            }
+        };
+        put_u64(out, tag);
+        put_u64(out, a as u64);
+        put_u64(out, b as u64);
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        let tag = take_u64(input)?;
+        let a = WidthInt::try_from(take_u64(input)?).ok()?;
+        let b = WidthInt::try_from(take_u64(input)?).ok()?;
+        match tag {
+            0 => Some(Op::ZeroExt(a)),
+            1 => Some(Op::SignExt(a)),
+            2 => Some(Op::Slice(a, b)),
+            3 => WideOp::ALL.get(a as usize).map(|&op| Op::Wide(op)),
+            _ => SIMPLE_OPS.get(tag as usize - 4).copied(),
+        }
+    }
+
+    fn wide(self) -> Option<WideOp> {
+        match self {
+            Op::And => Some(WideOp::And),
+            Op::Or => Some(WideOp::Or),
+            Op::Xor => Some(WideOp::Xor),
+            Op::Add => Some(WideOp::Add),
+            Op::Sub => Some(WideOp::Sub),
+            Op::Equal => Some(WideOp::Equal),
+            Op::Greater => Some(WideOp::Greater),
+            Op::ShiftLeft => Some(WideOp::ShiftLeft),
+            Op::ShiftRight => Some(WideOp::ShiftRight),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Instr {
+    op: Op,
+    dst: u32,
+    /// unused operands are zero
+    args: [u32; 3],
+}
+
+/// An expression that cannot be compiled to bytecode.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{0:?} is not supported by the bytecode")]
+pub(crate) struct UnsupportedExpr(pub(crate) ExprRef);
+
+#[derive(Debug, Clone)]
+pub(crate) struct Program {
+    instructions: Vec<Instr>,
+    /// the expression computed by every instruction
+    exprs: Vec<ExprRef>,
+    /// register of every symbol, symbols are loaded before the program runs
+    symbols: Vec<(ExprRef, u32)>,
+    /// register of every expression whose value is available after running the program
+    lookup: FxHashMap<ExprRef, u32>,
+    /// initial register contents, literals never change
+    registers: Vec<BitVecValue>,
+    /// selected once, when the program is compiled
+    kernels: Kernels,
+}
+
+impl Program {
+    /// Compiles `schedule`, a list of expressions where children always come before their
+    /// parents, as computed for eager evaluation.
+    pub(crate) fn compile(ctx: &Context, schedule: &[ExprRef]) -> Result<Self, UnsupportedExpr> {
+        let mut program = Self {
+            instructions: Vec::with_capacity(schedule.len()),
+            exprs: Vec::with_capacity(schedule.len()),
+            symbols: vec![],
+            lookup: FxHashMap::default(),
+            registers: vec![],
+            kernels: Kernels::detect(),
+        };
+        for &e in schedule.iter() {
+            let mut children = [0u32; 3];
+            let mut count = 0;
+            let mut error = None;
+            ctx[e].for_each_child(|&c| match program.operand(ctx, c) {
+                Ok(reg) => {
+                    children[count] = reg;
+                    count += 1;
+                }
+                Err(err) => error = Some(err),
+            });
+            if let Some(err) = error {
+                return Err(err);
+            }
+            let op = match ctx[e] {
+                Expr::BVZeroExt { by, .. } => Op::ZeroExt(by),
+                Expr::BVSignExt { by, .. } => Op::SignExt(by),
+                Expr::BVSlice { hi, lo, .. } => Op::Slice(hi, lo),
+                Expr::BVNot(_, _) => Op::Not,
+                Expr::BVNegate(_, _) => Op::Negate,
+                Expr::BVEqual(_, _) => Op::Equal,
+                Expr::BVImplies(_, _) => Op::Implies,
+                Expr::BVGreater(_, _) => Op::Greater,
+                Expr::BVGreaterSigned(_, _, _) => Op::GreaterSigned,
+                Expr::BVGreaterEqual(_, _) => Op::GreaterEqual,
+                Expr::BVGreaterEqualSigned(_, _, _) => Op::GreaterEqualSigned,
+                Expr::BVConcat(_, _, _) => Op::Concat,
+                Expr::BVAnd(_, _, _) => Op::And,
+                Expr::BVOr(_, _, _) => Op::Or,
+                Expr::BVXor(_, _, _) => Op::Xor,
+                Expr::BVShiftLeft(_, _, _) => Op::ShiftLeft,
+                Expr::BVShiftRight(_, _, _) => Op::ShiftRight,
+                Expr::BVArithmeticShiftRight(_, _, _) => Op::ArithmeticShiftRight,
+                Expr::BVAdd(_, _, _) => Op::Add,
+                Expr::BVMul(_, _, _) => Op::Mul,
+                Expr::BVSub(_, _, _) => Op::Sub,
+                Expr::BVIte { .. } => Op::Ite,
+                _ => return Err(UnsupportedExpr(e)),
+            };
+            let op = match op.wide() {
+                Some(wide) if program.registers[children[0] as usize].width() > WIDE_THRESHOLD => {
+                    Op::Wide(wide)
+                }
+                _ => op,
+            };
+            let width = e.get_bv_type(ctx).ok_or(UnsupportedExpr(e))?;
+            let dst = program.alloc(e, BitVecValue::zero(width));
+            program.instructions.push(Instr {
+                op,
+                dst,
+                args: children,
+            });
+            program.exprs.push(e);
+        }
+        Ok(program)
+    }
+
+    /// Register that holds the value of `e`, leaf expressions get their register on first use.
+    pub(crate) fn operand(&mut self, ctx: &Context, e: ExprRef) -> Result<u32, UnsupportedExpr> {
+        if let Some(&reg) = self.lookup.get(&e) {
+            return Ok(reg);
+        }
+        match &ctx[e] {
+            Expr::BVSymbol { width, .. } => {
+                let reg = self.alloc(e, BitVecValue::zero(*width));
+                self.symbols.push((e, reg));
+                Ok(reg)
+            }
+            Expr::BVLiteral(value) => Ok(self.alloc(e, value.get(ctx).into())),
+            // the schedule guarantees that all other children were compiled before
+            _ => Err(UnsupportedExpr(e)),
+        }
+    }
+
+    fn alloc(&mut self, e: ExprRef, initial: BitVecValue) -> u32 {
+        let reg = self.registers.len() as u32;
+        self.registers.push(initial);
+        self.lookup.insert(e, reg);
+        reg
+    }
+
+    pub(crate) fn instruction_count(&self) -> usize {
+        self.instructions.len()
+    }
+
+    /// Registers with the right widths and all literals.
+    pub(crate) fn new_registers(&self) -> Vec<BitVecValue> {
+        self.registers.clone()
+    }
+
+    /// Loads all symbols from `values` and then executes every instruction. Expressions that
+    /// appear in `forced` keep their forced value.
+    pub(crate) fn run(
+        &self,
+        ctx: &Context,
+        values: &impl GetExprValue,
+        forced: &FxHashMap<ExprRef, BitVecValue>,
+        regs: &mut [BitVecValue],
+    ) {
+        for &(symbol, reg) in self.symbols.iter() {
+            regs[reg as usize] = values
+                .get_bv(ctx, symbol)
+                .expect("all symbols need to have a value");
+        }
+        for (instr, e) in self.instructions.iter().zip(self.exprs.iter()) {
+            let dst = instr.dst as usize;
+            self.exec(instr.op, regs, dst, instr.args.map(|r| r as usize));
+            // checking the forced values is skipped in the common case
+            if !forced.is_empty() {
+                if let Some(value) = forced.get(e) {
+                    regs[dst] = value.clone();
+                }
+            }
+        }
+    }
+
+    /// Executes every instruction once for each of the `lanes` independent sets of registers.
+    /// Register `r` of lane `l` is stored at `r * lanes + l`, see [`Program::new_lane_registers`].
+    /// Symbols are not loaded, their registers need to be written directly.
+    pub(crate) fn run_lanes(&self, lanes: usize, regs: &mut [BitVecValue]) {
+        for instr in self.instructions.iter() {
+            for lane in 0..lanes {
+                let at = |r: u32| r as usize * lanes + lane;
+                self.exec(instr.op, regs, at(instr.dst), instr.args.map(at));
+            }
+        }
+    }
+
+    /// Registers for [`Program::run_lanes`], every lane starts out as [`Program::new_registers`].
+    pub(crate) fn new_lane_registers(&self, lanes: usize) -> Vec<BitVecValue> {
+        self.registers
+            .iter()
+            .flat_map(|r| vec![r.clone(); lanes])
+            .collect()
+    }
+
+    /// Expressions computed by the instructions, in execution order.
+    pub(crate) fn exprs(&self) -> &[ExprRef] {
+        &self.exprs
+    }
+
+    /// Register of `e`, `None` if `e` is neither compiled nor a symbol or literal it reads.
+    pub(crate) fn register(&self, e: ExprRef) -> Option<u32> {
+        self.lookup.get(&e).copied()
+    }
+
+    /// Computes `op` on the registers `a`, `b` and `c` and stores the result in `dst`.
+    fn exec(&self, op: Op, regs: &mut [BitVecValue], dst: usize, [a, b, c]: [usize; 3]) {
+        let value = match op {
+            Op::Wide(op) => {
+                // the destination is always allocated after the operands
+                let (operands, results) = regs.split_at_mut(dst);
+                super::wide::eval(
+                    self.kernels,
+                    op,
+                    &mut results[0],
+                    &operands[a],
+                    &operands[b],
+                );
+                return;
+            }
+            Op::ZeroExt(by) => regs[a].zero_extend(by),
+            Op::SignExt(by) => regs[a].sign_extend(by),
+            Op::Slice(hi, lo) => regs[a].slice(hi, lo),
+            Op::Not => regs[a].not(),
+            Op::Negate => regs[a].negate(),
+            Op::Equal => regs[a].is_equal(&regs[b]).into(),
+            Op::Implies => regs[a].not().or(&regs[b]),
+            Op::Greater => regs[a].is_greater(&regs[b]).into(),
+            Op::GreaterSigned => regs[a].is_greater_signed(&regs[b]).into(),
+            Op::GreaterEqual => regs[a].is_greater_or_equal(&regs[b]).into(),
+            Op::GreaterEqualSigned => regs[a].is_greater_or_equal_signed(&regs[b]).into(),
+            Op::Concat => regs[a].concat(&regs[b]),
+            Op::And => regs[a].and(&regs[b]),
+            Op::Or => regs[a].or(&regs[b]),
+            Op::Xor => regs[a].xor(&regs[b]),
+            Op::ShiftLeft => regs[a].shift_left(&regs[b]),
+            Op::ShiftRight => regs[a].shift_right(&regs[b]),
+            Op::ArithmeticShiftRight => regs[a].arithmetic_shift_right(&regs[b]),
+            Op::Add => regs[a].add(&regs[b]),
+            Op::Mul => regs[a].mul(&regs[b]),
+            Op::Sub => regs[a].sub(&regs[b]),
+            Op::Ite => {
+                if regs[a].is_true() {
+                    regs[b].clone()
+                } else {
+                    regs[c].clone()
+                }
+            }
+        };
+        regs[dst] = value;
+    }
+
+    /// Value of `e` after the program ran, `None` if `e` was not compiled.
+    pub(crate) fn get(&self, regs: &[BitVecValue], e: ExprRef) -> Option<BitVecValue> {
+        self.lookup.get(&e).map(|&reg| regs[reg as usize].clone())
+    }
+
+    /// Serializes everything but the kernels, which are selected again when decoding.
+    pub(crate) fn encode(&self, out: &mut Vec<u8>) {
+        put_u64(out, self.registers.len() as u64);
+        for value in self.registers.iter() {
+            put_u64(out, value.width() as u64);
+            put_bv(out, value);
+        }
+        put_u64(out, self.instructions.len() as u64);
+        for (instr, e) in self.instructions.iter().zip(self.exprs.iter()) {
+            instr.op.encode(out);
+            put_u64(out, instr.dst as u64);
+            for arg in instr.args {
+                put_u64(out, arg as u64);
+            }
+            put_u64(out, e.index() as u64);
+        }
+        put_u64(out, self.symbols.len() as u64);
+        for &(symbol, reg) in self.symbols.iter() {
+            put_u64(out, symbol.index() as u64);
+            put_u64(out, reg as u64);
+        }
+        put_u64(out, self.lookup.len() as u64);
+        for (e, &reg) in self.lookup.iter() {
+            put_u64(out, e.index() as u64);
+            put_u64(out, reg as u64);
+        }
+    }
+
+    /// Returns `None` if `input` is not a program produced by [`Program::encode`].
+    pub(crate) fn decode(input: &mut &[u8]) -> Option<Self> {
+        let mut program = Self {
+            instructions: vec![],
+            exprs: vec![],
+            symbols: vec![],
+            lookup: FxHashMap::default(),
+            registers: vec![],
+            kernels: Kernels::detect(),
+        };
+        for _ in 0..take_u64(input)? {
+            let width = WidthInt::try_from(take_u64(input)?).ok()?;
+            program.registers.push(take_bv(input, width)?);
+        }
+        // a corrupted register index would make the program panic when it runs
+        let num_regs = program.registers.len() as u64;
+        let take_reg = |input: &mut &[u8]| take_u64(input).filter(|&r| r < num_regs);
+        let take_expr = |input: &mut &[u8]| {
+            let index = take_u64(input).filter(|&i| i < u32::MAX as u64)?;
+            Some(ExprRef::from_index(index as usize))
+        };
+        for _ in 0..take_u64(input)? {
+            let op = Op::decode(input)?;
+            let dst = take_reg(input)? as u32;
+            let mut args = [0u32; 3];
+            for arg in args.iter_mut() {
+                *arg = take_reg(input)? as u32;
+            }
+            // wide operations require the destination to be allocated after the operands
+            if matches!(op, Op::Wide(_)) && (args[0] >= dst || args[1] >= dst) {
+                return None;
+            }
+            program.instructions.push(Instr { op, dst, args });
+            program.exprs.push(take_expr(input)?);
+        }
+        for _ in 0..take_u64(input)? {
+            let symbol = take_expr(input)?;
+            program.symbols.push((symbol, take_reg(input)? as u32));
+        }
+        for _ in 0..take_u64(input)? {
+            let e = take_expr(input)?;
+            program.lookup.insert(e, take_reg(input)? as u32);
+        }
+        Some(program)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::expr::Context;
+    use crate::sim::{EvalOrder, InitKind, Interpreter, Simulator};
+    use crate::system::{State, TransitionSystem};
+    use baa::BitVecValue;
+
+    #[test]
+    fn test_bytecode_matches_interpreter() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("mix".to_string());
+        let a = ctx.bv_symbol("a", 8);
+        sys.add_input(&ctx, a);
+        let s = ctx.bv_symbol("s", 8);
+        let t = ctx.bv_symbol("t", 4);
+        let s_next = ctx.build(|c| {
+            let sum = c.add(s, c.mul(a, c.zero_extend(t, 4)));
+            c.ite(c.greater(a, s), c.xor(sum, a), c.shift_left(sum, c.one(8)))
+        });
+        let t_next = ctx.build(|c| c.sub(c.slice(s, 7, 4), c.slice(a, 3, 0)));
+        for (symbol, next) in [(s, s_next), (t, t_next)] {
+            sys.add_state(
+                &ctx,
+                State {
+                    symbol,
+                    init: None,
+                    next: Some(next),
+                },
+            );
+        }
+
+        let mut lazy = Interpreter::new(&ctx, &sys);
+        let mut compiled = Interpreter::with_eval_order(&ctx, &sys, EvalOrder::Bytecode);
+        assert_eq!(compiled.eval_order(), EvalOrder::Bytecode);
+        lazy.init(InitKind::Random(3));
+        compiled.init(InitKind::Random(3));
+        for ii in 0..50u64 {
+            let value = BitVecValue::from_u64(ii * 37 % 256, 8);
+            lazy.set(a, &value).unwrap();
+            compiled.set(a, &value).unwrap();
+            lazy.step();
+            compiled.step();
+            for symbol in [s, t, s_next] {
+                assert_eq!(
+                    lazy.get(symbol).try_into_u64().unwrap(),
+                    compiled.get(symbol).try_into_u64().unwrap()
+                );
+            }
+        }
+    }
+}
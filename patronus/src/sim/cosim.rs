@@ -0,0 +1,340 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Co-Simulation
+//! Drives a patronus [`Simulator`] and an external simulator with the same inputs and compares
+//! outputs and states after every input assignment. The first mismatch is reported.
+//!
+//! External simulators, like a Verilated model wrapped in a small C++ harness, can be attached
+//! as a separate process through [`ProcessSimulator`], which uses a line based protocol on
+//! stdin and stdout:
+//!
+//! | command              | response                                     |
+//! |----------------------|----------------------------------------------|
+//! | `set <name> <value>` | none                                         |
+//! | `get <name>`         | `<value>` or `x` if the signal is not known  |
+//! | `step`               | none                                         |
+//!
+//! Values use the same syntax as [`Stimulus`] files.
+
+use super::stimulus::{lookup_bv_input, parse_value};
+use super::{SimError, Simulator, Stimulus, StimulusError};
+use crate::expr::{Context, ExprRef, TypeCheck, WidthInt};
+use crate::system::TransitionSystem;
+use baa::{BitVecOps, BitVecValue, Value};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+#[derive(Debug, thiserror::Error)]
+pub enum CosimError {
+    #[error("failed to communicate with the external simulator")]
+    Io(#[from] std::io::Error),
+    #[error("unexpected response from the external simulator: `{0}`")]
+    Protocol(String),
+    #[error(transparent)]
+    Stimulus(#[from] StimulusError),
+    #[error(transparent)]
+    Sim(#[from] SimError),
+}
+
+type Result<T> = std::result::Result<T, CosimError>;
+
+/// A simulator that is not part of patronus and only knows about signal names.
+pub trait ExternalSimulator {
+    fn set(&mut self, name: &str, value: &BitVecValue) -> Result<()>;
+    /// Returns `None` if the external simulator does not know about the signal,
+    /// e.g., because it was optimized away.
+    fn get(&mut self, name: &str, width: WidthInt) -> Result<Option<BitVecValue>>;
+    fn step(&mut self) -> Result<()>;
+}
+
+/// Talks to an external simulator process, see the module documentation for the protocol.
+pub struct ProcessSimulator {
+    child: Child,
+    stdin: BufWriter<ChildStdin>,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl ProcessSimulator {
+    pub fn spawn(cmd: &mut Command) -> Result<Self> {
+        let mut child = cmd.stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()?;
+        let stdin = BufWriter::new(child.stdin.take().unwrap());
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        Ok(Self {
+            child,
+            stdin,
+            stdout,
+        })
+    }
+}
+
+impl ExternalSimulator for ProcessSimulator {
+    fn set(&mut self, name: &str, value: &BitVecValue) -> Result<()> {
+        writeln!(self.stdin, "set {name} 0x{}", value.to_hex_str())?;
+        Ok(())
+    }
+
+    fn get(&mut self, name: &str, width: WidthInt) -> Result<Option<BitVecValue>> {
+        writeln!(self.stdin, "get {name}")?;
+        self.stdin.flush()?;
+        let mut line = String::new();
+        self.stdout.read_line(&mut line)?;
+        let line = line.trim();
+        if line.eq_ignore_ascii_case("x") {
+            Ok(None)
+        } else {
+            parse_value(line, width)
+                .map(Some)
+                .ok_or_else(|| CosimError::Protocol(line.to_string()))
+        }
+    }
+
+    fn step(&mut self) -> Result<()> {
+        writeln!(self.stdin, "step")?;
+        Ok(())
+    }
+}
+
+impl Drop for ProcessSimulator {
+    fn drop(&mut self) {
+        // make sure that all pending commands are delivered before terminating the process
+        let _ = self.stdin.flush();
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Exposes a patronus simulator through the name based interface, e.g., in order to compare
+/// two simulator backends or two versions of a system.
+pub struct NamedSimulator<'a, S: Simulator> {
+    ctx: &'a Context,
+    sys: &'a TransitionSystem,
+    sim: S,
+}
+
+impl<'a, S: Simulator> NamedSimulator<'a, S> {
+    pub fn new(ctx: &'a Context, sys: &'a TransitionSystem, sim: S) -> Self {
+        Self { ctx, sys, sim }
+    }
+
+    fn lookup(&self, name: &str) -> Option<ExprRef> {
+        self.sys
+            .lookup_input(self.ctx, name)
+            .or_else(|| self.sys.lookup_output(self.ctx, name))
+            .or_else(|| self.sys.get_state_by_name(self.ctx, name).map(|s| s.symbol))
+    }
+}
+
+impl<'a, S: Simulator> ExternalSimulator for NamedSimulator<'a, S> {
+    fn set(&mut self, name: &str, value: &BitVecValue) -> Result<()> {
+        let (input, _) = lookup_bv_input(self.ctx, self.sys, name)?;
+        self.sim.set(input, value)?;
+        Ok(())
+    }
+
+    fn get(&mut self, name: &str, _width: WidthInt) -> Result<Option<BitVecValue>> {
+        Ok(self.lookup(name).and_then(|e| match self.sim.get(e) {
+            Value::BitVec(value) => Some(value),
+            Value::Array(_) => None,
+        }))
+    }
+
+    fn step(&mut self) -> Result<()> {
+        self.sim.step();
+        Ok(())
+    }
+}
+
+/// First signal that did not match between the two simulators.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub step: usize,
+    pub signal: String,
+    /// value computed by patronus
+    pub expected: BitVecValue,
+    /// value computed by the external simulator
+    pub actual: BitVecValue,
+}
+
+/// Runs a patronus simulator and an external simulator in lockstep.
+pub struct Cosim<'a, S: Simulator, E: ExternalSimulator> {
+    ctx: &'a Context,
+    sys: &'a TransitionSystem,
+    sim: S,
+    ext: E,
+    signals: Vec<(String, ExprRef, WidthInt)>,
+}
+
+impl<'a, S: Simulator, E: ExternalSimulator> Cosim<'a, S, E> {
+    /// Compares all bit-vector outputs and states. The simulator needs to be initialized
+    /// with the same values as the external simulator.
+    pub fn new(ctx: &'a Context, sys: &'a TransitionSystem, sim: S, ext: E) -> Self {
+        let outputs = sys
+            .outputs
+            .iter()
+            .map(|o| (ctx[o.name].to_string(), o.expr));
+        let states = sys
+            .states
+            .iter()
+            .map(|s| (ctx.get_symbol_name(s.symbol).unwrap().to_string(), s.symbol));
+        let signals = outputs
+            .chain(states)
+            .flat_map(|(name, e)| Some((name, e, e.get_bv_type(ctx)?)))
+            .collect();
+        Self {
+            ctx,
+            sys,
+            sim,
+            ext,
+            signals,
+        }
+    }
+
+    /// Only compare outputs, e.g., when the external simulator does not expose internal state.
+    pub fn outputs_only(mut self) -> Self {
+        let ctx = self.ctx;
+        let outputs: Vec<&str> = self
+            .sys
+            .outputs
+            .iter()
+            .map(|o| ctx[o.name].as_str())
+            .collect();
+        self.signals
+            .retain(|(name, _, _)| outputs.contains(&name.as_str()));
+        self
+    }
+
+    /// Plays back `stimulus` on both simulators and returns the first divergence.
+    pub fn run(&mut self, stimulus: &Stimulus) -> Result<Option<Divergence>> {
+        let inputs = stimulus
+            .signals
+            .iter()
+            .map(|name| lookup_bv_input(self.ctx, self.sys, name))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        for (step, values) in stimulus.steps.iter().enumerate() {
+            for ((name, &(input, width)), value) in stimulus
+                .signals
+                .iter()
+                .zip(inputs.iter())
+                .zip(values.iter())
+            {
+                let Some(value) = value else {
+                    continue;
+                };
+                let parsed =
+                    parse_value(value, width).ok_or_else(|| StimulusError::InvalidValue {
+                        step,
+                        signal: name.clone(),
+                        value: value.clone(),
+                    })?;
+                self.sim.set(input, &parsed)?;
+                self.ext.set(name, &parsed)?;
+            }
+            if let Some(divergence) = self.compare(step)? {
+                return Ok(Some(divergence));
+            }
+            self.sim.step();
+            self.ext.step()?;
+        }
+        Ok(None)
+    }
+
+    fn compare(&mut self, step: usize) -> Result<Option<Divergence>> {
+        for (name, expr, width) in self.signals.iter() {
+            let Some(actual) = self.ext.get(name, *width)? else {
+                continue;
+            };
+            let Value::BitVec(expected) = self.sim.get(*expr) else {
+                continue;
+            };
+            if expected != actual {
+                return Ok(Some(Divergence {
+                    step,
+                    signal: name.clone(),
+                    expected,
+                    actual,
+                }));
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn into_simulators(self) -> (S, E) {
+        (self.sim, self.ext)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::{InitKind, Interpreter};
+    use crate::system::State;
+
+    fn accumulator(ctx: &mut Context, buggy: bool) -> TransitionSystem {
+        let mut sys = TransitionSystem::new("acc".to_string());
+        let inp = ctx.bv_symbol("inp", 8);
+        sys.add_input(ctx, inp);
+        let acc = ctx.bv_symbol("acc", 8);
+        let next = if buggy {
+            // saturates at 16
+            ctx.build(|c| {
+                let sum = c.add(acc, inp);
+                c.ite(
+                    c.greater(sum, c.bit_vec_val(16, 8)),
+                    c.bit_vec_val(16, 8),
+                    sum,
+                )
+            })
+        } else {
+            ctx.add(acc, inp)
+        };
+        let init = ctx.zero(8);
+        sys.add_state(
+            ctx,
+            State {
+                symbol: acc,
+                init: Some(init),
+                next: Some(next),
+            },
+        );
+        sys.add_output(ctx, "out".into(), acc);
+        sys
+    }
+
+    #[test]
+    fn test_cosim_reports_first_divergence() {
+        let mut ctx = Context::default();
+        let golden = accumulator(&mut ctx, false);
+        let mut ctx2 = Context::default();
+        let buggy = accumulator(&mut ctx2, true);
+        let stimulus = Stimulus::from_csv("inp\n5\n5\n5\n5\n5\n").unwrap();
+
+        let mut sim = Interpreter::new(&ctx, &golden);
+        sim.init(InitKind::Zero);
+        let mut ext_sim = Interpreter::new(&ctx2, &buggy);
+        ext_sim.init(InitKind::Zero);
+        let ext = NamedSimulator::new(&ctx2, &buggy, ext_sim);
+
+        let mut cosim = Cosim::new(&ctx, &golden, sim, ext);
+        let divergence = cosim.run(&stimulus).unwrap().unwrap();
+        assert_eq!(divergence.step, 4);
+        assert_eq!(divergence.signal, "out");
+        assert_eq!(divergence.expected.to_u64().unwrap(), 20);
+        assert_eq!(divergence.actual.to_u64().unwrap(), 16);
+    }
+
+    #[test]
+    fn test_cosim_agrees_with_itself() {
+        let mut ctx = Context::default();
+        let sys = accumulator(&mut ctx, false);
+        let stimulus = Stimulus::from_csv("inp\n1\n2\n3\n").unwrap();
+        let mut sim = Interpreter::new(&ctx, &sys);
+        sim.init(InitKind::Zero);
+        let mut other = Interpreter::new(&ctx, &sys);
+        other.init(InitKind::Zero);
+        let ext = NamedSimulator::new(&ctx, &sys, other);
+        let mut cosim = Cosim::new(&ctx, &sys, sim, ext).outputs_only();
+        assert_eq!(cosim.run(&stimulus).unwrap(), None);
+    }
+}
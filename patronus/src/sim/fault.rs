@@ -0,0 +1,292 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Fault Injection
+//! Flips bits of bit-vector states during a simulation and classifies the effect of every
+//! fault by comparing against a fault free run, e.g., for an FMEDA. A flipped value is forced
+//! for the duration of the fault and then released, so that the state continues to evolve from
+//! the corrupted value. The bad states of the system act as the safety checkers: a fault is
+//! detected if any bad state fires that does not fire in the fault free run.
+
+use super::{InitKind, Interpreter, Simulator};
+use crate::expr::{Context, ExprRef, TypeCheck, WidthInt};
+use crate::random::new_rng;
+use crate::system::TransitionSystem;
+use baa::{BitVecMutOps, BitVecOps, BitVecValue, Value};
+use rand::Rng;
+
+/// Inverts a single bit of a state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fault {
+    pub state: ExprRef,
+    pub bit: WidthInt,
+    /// step at which the bit is flipped
+    pub cycle: u64,
+    /// number of steps for which the flipped value is held, a single event upset lasts one step
+    pub duration: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultOutcome {
+    /// a bad state fired that does not fire in the fault free run
+    Detected { cycle: u64, bad_state: usize },
+    /// an output differed from the fault free run without being detected
+    Corrupted { cycle: u64 },
+    /// outputs were never affected, but the state differs at the end of the run
+    Latent,
+    /// the fault had no observable effect
+    Masked,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FaultReport {
+    pub results: Vec<(Fault, FaultOutcome)>,
+}
+
+impl FaultReport {
+    fn count(&self, f: impl Fn(&FaultOutcome) -> bool) -> usize {
+        self.results.iter().filter(|(_, o)| f(o)).count()
+    }
+
+    pub fn detected(&self) -> usize {
+        self.count(|o| matches!(o, FaultOutcome::Detected { .. }))
+    }
+
+    pub fn corrupted(&self) -> usize {
+        self.count(|o| matches!(o, FaultOutcome::Corrupted { .. }))
+    }
+
+    pub fn latent(&self) -> usize {
+        self.count(|o| matches!(o, FaultOutcome::Latent))
+    }
+
+    pub fn masked(&self) -> usize {
+        self.count(|o| matches!(o, FaultOutcome::Masked))
+    }
+
+    /// Fraction of faults with a visible effect that were detected.
+    /// Returns `None` if no fault had a visible effect.
+    pub fn diagnostic_coverage(&self) -> Option<f64> {
+        let dangerous = self.detected() + self.corrupted();
+        (dangerous > 0).then(|| self.detected() as f64 / dangerous as f64)
+    }
+}
+
+/// Observable values of a single step.
+#[derive(Debug, Clone, PartialEq)]
+struct Observation {
+    outputs: Vec<Option<BitVecValue>>,
+    bad_states: Vec<bool>,
+}
+
+/// Runs the same stimulus once per fault.
+pub struct FaultCampaign<'a> {
+    ctx: &'a Context,
+    sys: &'a TransitionSystem,
+    init: InitKind,
+    /// input values for every step
+    stimulus: Vec<Vec<(ExprRef, BitVecValue)>>,
+}
+
+impl<'a> FaultCampaign<'a> {
+    /// The number of simulated steps is determined by the length of the `stimulus`.
+    pub fn new(
+        ctx: &'a Context,
+        sys: &'a TransitionSystem,
+        init: InitKind,
+        stimulus: Vec<Vec<(ExprRef, BitVecValue)>>,
+    ) -> Self {
+        Self {
+            ctx,
+            sys,
+            init,
+            stimulus,
+        }
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.stimulus.len() as u64
+    }
+
+    /// Single event upsets in random bits of random bit-vector states.
+    pub fn random_faults(&self, count: usize, seed: u64) -> Vec<Fault> {
+        let states: Vec<(ExprRef, WidthInt)> = self
+            .sys
+            .states
+            .iter()
+            .flat_map(|s| Some((s.symbol, s.symbol.get_bv_type(self.ctx)?)))
+            .collect();
+        if states.is_empty() || self.cycles() == 0 {
+            return vec![];
+        }
+        let mut rng = new_rng(seed);
+        (0..count)
+            .map(|_| {
+                let (state, width) = states[rng.gen_range(0..states.len())];
+                Fault {
+                    state,
+                    bit: rng.gen_range(0..width),
+                    cycle: rng.gen_range(0..self.cycles()),
+                    duration: 1,
+                }
+            })
+            .collect()
+    }
+
+    pub fn run(&self, faults: &[Fault]) -> FaultReport {
+        let (golden, golden_end) = self.simulate(None);
+        let results = faults
+            .iter()
+            .map(|&fault| {
+                let (observed, end) = self.simulate(Some(fault));
+                let outcome = classify(&golden, &observed, golden_end == end);
+                (fault, outcome)
+            })
+            .collect();
+        FaultReport { results }
+    }
+
+    /// Returns the observations of every step and the final state.
+    fn simulate(&self, fault: Option<Fault>) -> (Vec<Observation>, Vec<Option<BitVecValue>>) {
+        let mut sim = Interpreter::new(self.ctx, self.sys);
+        sim.init(self.init);
+        let outputs: Vec<ExprRef> = self.sys.outputs.iter().map(|o| o.expr).collect();
+        let states: Vec<ExprRef> = self.sys.states.iter().map(|s| s.symbol).collect();
+        let mut observations = Vec::with_capacity(self.stimulus.len());
+        for (cycle, inputs) in self.stimulus.iter().enumerate() {
+            let cycle = cycle as u64;
+            for (input, value) in inputs.iter() {
+                sim.set(*input, value).expect("invalid stimulus");
+            }
+            if let Some(f) = fault {
+                if cycle == f.cycle {
+                    let mut value = get_bv(&sim, f.state);
+                    if value.is_bit_set(f.bit) {
+                        value.clear_bit(f.bit);
+                    } else {
+                        value.set_bit(f.bit);
+                    }
+                    sim.force(f.state, &value).unwrap();
+                }
+            }
+            observations.push(Observation {
+                outputs: sim.get_many(&outputs),
+                bad_states: self
+                    .sys
+                    .bad_states
+                    .iter()
+                    .map(|&b| !get_bv(&sim, b).is_zero())
+                    .collect(),
+            });
+            // the next step starts from the flipped value
+            if fault.is_some_and(|f| cycle + 1 == f.cycle + f.duration) {
+                sim.release(fault.unwrap().state);
+            }
+            sim.step();
+        }
+        (observations, sim.get_many(&states))
+    }
+}
+
+fn get_bv(sim: &Interpreter, e: ExprRef) -> BitVecValue {
+    match sim.get(e) {
+        Value::BitVec(value) => value,
+        Value::Array(_) => unreachable!("{e:?} is a bit-vector"),
+    }
+}
+
+fn classify(golden: &[Observation], observed: &[Observation], same_end: bool) -> FaultOutcome {
+    let mut corrupted = None;
+    for (cycle, (g, o)) in golden.iter().zip(observed.iter()).enumerate() {
+        let cycle = cycle as u64;
+        let new_bad = g
+            .bad_states
+            .iter()
+            .zip(o.bad_states.iter())
+            .position(|(&g, &o)| o && !g);
+        if let Some(bad_state) = new_bad {
+            return FaultOutcome::Detected { cycle, bad_state };
+        }
+        if corrupted.is_none() && g.outputs != o.outputs {
+            corrupted = Some(cycle);
+        }
+    }
+    match corrupted {
+        Some(cycle) => FaultOutcome::Corrupted { cycle },
+        None if !same_end => FaultOutcome::Latent,
+        None => FaultOutcome::Masked,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::State;
+
+    /// A counter that is duplicated and compared, the copy is not observable.
+    fn lockstep_counter(ctx: &mut Context) -> TransitionSystem {
+        let mut sys = TransitionSystem::new("lockstep".to_string());
+        let en = ctx.bv_symbol("en", 1);
+        sys.add_input(ctx, en);
+        let (a, b, unused) = (
+            ctx.bv_symbol("a", 4),
+            ctx.bv_symbol("b", 4),
+            ctx.bv_symbol("unused", 4),
+        );
+        for (symbol, next) in [
+            (a, ctx.build(|c| c.ite(en, c.add(a, c.one(4)), a))),
+            (b, ctx.build(|c| c.ite(en, c.add(b, c.one(4)), b))),
+            (unused, unused),
+        ] {
+            let init = Some(ctx.zero(4));
+            let next = Some(next);
+            sys.add_state(ctx, State { symbol, init, next });
+        }
+        sys.add_output(ctx, "count".into(), a);
+        let mismatch = ctx.build(|c| c.not(c.equal(a, b)));
+        sys.bad_states.push(mismatch);
+        sys
+    }
+
+    #[test]
+    fn test_fault_campaign() {
+        let mut ctx = Context::default();
+        let sys = lockstep_counter(&mut ctx);
+        let en = sys.lookup_input(&ctx, "en").unwrap();
+        let (a, unused) = (sys.states[0].symbol, sys.states[2].symbol);
+        let stimulus = vec![vec![(en, BitVecValue::from_u64(1, 1))]; 8];
+        let campaign = FaultCampaign::new(&ctx, &sys, InitKind::Zero, stimulus);
+
+        let fault = |state, cycle| Fault {
+            state,
+            bit: 1,
+            cycle,
+            duration: 1,
+        };
+        let report = campaign.run(&[fault(a, 2), fault(unused, 3)]);
+        assert_eq!(
+            report.results[0].1,
+            FaultOutcome::Detected {
+                cycle: 2,
+                bad_state: 0
+            }
+        );
+        assert_eq!(report.results[1].1, FaultOutcome::Latent);
+        assert_eq!(report.diagnostic_coverage(), Some(1.0));
+
+        // without the checker, the fault corrupts the output
+        let mut unchecked = sys.clone();
+        unchecked.bad_states.clear();
+        let stimulus = vec![vec![(en, BitVecValue::from_u64(1, 1))]; 8];
+        let campaign = FaultCampaign::new(&ctx, &unchecked, InitKind::Zero, stimulus);
+        let report = campaign.run(&[fault(a, 2)]);
+        assert_eq!(report.results[0].1, FaultOutcome::Corrupted { cycle: 2 });
+        assert_eq!(report.diagnostic_coverage(), Some(0.0));
+
+        let random = campaign.random_faults(10, 7);
+        assert_eq!(random.len(), 10);
+        assert!(random.iter().all(|f| f.bit < 4 && f.cycle < 8));
+        assert_eq!(random, campaign.random_faults(10, 7));
+    }
+}
@@ -0,0 +1,259 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Golden Vectors
+//! Expected values of signals at specific steps. Every non-empty line of a golden vector file
+//! contains a signal name, a step and a value, separated by commas or whitespace. Lines that
+//! start with `#` are comments. Values use the same format as [`Stimulus`](super::Stimulus)
+//! files. Signals are looked up by name among outputs, states and inputs.
+//!
+//! A [`GoldenChecker`] compares the expectations against a running simulation and collects
+//! all mismatches instead of stopping at the first one.
+
+use super::stimulus::parse_value;
+use super::Simulator;
+use crate::expr::{Context, ExprRef, TypeCheck};
+use crate::system::TransitionSystem;
+use baa::{BitVecOps, BitVecValue, Value};
+use rustc_hash::FxHashMap;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+#[derive(Debug, thiserror::Error)]
+pub enum GoldenError {
+    #[error("failed to read golden vectors")]
+    Io(#[from] std::io::Error),
+    #[error("line {line}: expected `signal, step, value`")]
+    Syntax { line: usize },
+    #[error("`{0}` is not a bit-vector signal of the system")]
+    UnknownSignal(String),
+    #[error("line {line}: `{value}` is not a valid value for `{signal}`")]
+    InvalidValue {
+        line: usize,
+        signal: String,
+        value: String,
+    },
+}
+
+type Result<T> = std::result::Result<T, GoldenError>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expectation {
+    /// line in the source file, used for error messages
+    pub line: usize,
+    pub signal: String,
+    pub step: usize,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GoldenVectors {
+    pub expectations: Vec<Expectation>,
+}
+
+impl GoldenVectors {
+    pub fn parse(src: &str) -> Result<Self> {
+        let mut expectations = vec![];
+        for (ii, line) in src.lines().enumerate() {
+            let line_no = ii + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|f| !f.is_empty())
+                .collect();
+            let [signal, step, value] = fields.as_slice() else {
+                return Err(GoldenError::Syntax { line: line_no });
+            };
+            let step = step
+                .parse()
+                .map_err(|_| GoldenError::Syntax { line: line_no })?;
+            expectations.push(Expectation {
+                line: line_no,
+                signal: signal.to_string(),
+                step,
+                value: value.to_string(),
+            });
+        }
+        Ok(Self { expectations })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+}
+
+/// A signal did not have the expected value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GoldenMismatch {
+    pub signal: String,
+    pub step: usize,
+    pub expected: BitVecValue,
+    pub actual: BitVecValue,
+}
+
+impl Display for GoldenMismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "step {}: {} = 0x{}, expected 0x{}",
+            self.step,
+            self.signal,
+            self.actual.to_hex_str(),
+            self.expected.to_hex_str()
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GoldenReport {
+    /// number of expectations that were compared
+    pub checked: usize,
+    pub mismatches: Vec<GoldenMismatch>,
+    /// expectations for steps that were never checked
+    pub unchecked: Vec<Expectation>,
+}
+
+impl GoldenReport {
+    pub fn passed(&self) -> bool {
+        self.mismatches.is_empty() && self.unchecked.is_empty()
+    }
+}
+
+struct Resolved {
+    source: Expectation,
+    expr: ExprRef,
+    value: BitVecValue,
+}
+
+/// Compares expectations against the state of a simulator, one step at a time.
+pub struct GoldenChecker {
+    by_step: FxHashMap<usize, Vec<Resolved>>,
+    report: GoldenReport,
+}
+
+impl GoldenChecker {
+    pub fn new(ctx: &Context, sys: &TransitionSystem, vectors: &GoldenVectors) -> Result<Self> {
+        let names = sys.get_name_map(ctx);
+        let mut by_step: FxHashMap<usize, Vec<Resolved>> = FxHashMap::default();
+        for e in vectors.expectations.iter() {
+            let (expr, width) = names
+                .get(&e.signal)
+                .and_then(|&expr| Some((expr, expr.get_bv_type(ctx)?)))
+                .ok_or_else(|| GoldenError::UnknownSignal(e.signal.clone()))?;
+            let value = parse_value(&e.value, width).ok_or_else(|| GoldenError::InvalidValue {
+                line: e.line,
+                signal: e.signal.clone(),
+                value: e.value.clone(),
+            })?;
+            by_step.entry(e.step).or_default().push(Resolved {
+                source: e.clone(),
+                expr,
+                value,
+            });
+        }
+        Ok(Self {
+            by_step,
+            report: GoldenReport::default(),
+        })
+    }
+
+    /// Compares all expectations for `step` with the current values in `sim`. Call this
+    /// after applying the inputs and before stepping the simulator, e.g., from the observer of
+    /// [`Stimulus::play`](super::Stimulus::play).
+    pub fn check(&mut self, step: usize, sim: &impl Simulator) {
+        let Some(expected) = self.by_step.remove(&step) else {
+            return;
+        };
+        for e in expected {
+            self.report.checked += 1;
+            let Value::BitVec(actual) = sim.get(e.expr) else {
+                unreachable!("golden vectors only refer to bit-vectors");
+            };
+            if !actual.is_equal(&e.value) {
+                self.report.mismatches.push(GoldenMismatch {
+                    signal: e.source.signal,
+                    step,
+                    expected: e.value,
+                    actual,
+                });
+            }
+        }
+    }
+
+    /// Returns all mismatches in the order they were found together with expectations for
+    /// steps that were not simulated.
+    pub fn finish(mut self) -> GoldenReport {
+        let mut unchecked: Vec<Expectation> = self
+            .by_step
+            .into_values()
+            .flatten()
+            .map(|e| e.source)
+            .collect();
+        unchecked.sort_by_key(|e| e.line);
+        self.report.unchecked = unchecked;
+        self.report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::{InitKind, Interpreter, Stimulus};
+    use crate::system::State;
+
+    #[test]
+    fn test_golden_vectors() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("acc".to_string());
+        let inc = ctx.bv_symbol("inc", 8);
+        sys.add_input(&ctx, inc);
+        let acc = ctx.bv_symbol("acc", 8);
+        let next = ctx.add(acc, inc);
+        let init = ctx.zero(8);
+        sys.add_state(
+            &ctx,
+            State {
+                symbol: acc,
+                init: Some(init),
+                next: Some(next),
+            },
+        );
+        sys.add_output(&mut ctx, "out".into(), next);
+
+        let golden =
+            "# signal step value\nout, 0, 1\nout 1 3\nacc, 2, 0x3\nout, 2, 0b110\nout, 9, 0\n";
+        let vectors = GoldenVectors::parse(golden).unwrap();
+        assert_eq!(vectors.expectations.len(), 5);
+        let mut checker = GoldenChecker::new(&ctx, &sys, &vectors).unwrap();
+        let stimulus = Stimulus::from_csv("inc\n1\n2\n4\n").unwrap();
+        let mut sim = Interpreter::new(&ctx, &sys);
+        sim.init(InitKind::Zero);
+        stimulus
+            .play(&ctx, &sys, &mut sim, |step, sim| checker.check(step, sim))
+            .unwrap();
+        let report = checker.finish();
+        assert_eq!(report.checked, 4);
+        // 3 + 4 = 7
+        assert_eq!(report.mismatches.len(), 1);
+        let mismatch = &report.mismatches[0];
+        assert_eq!((mismatch.signal.as_str(), mismatch.step), ("out", 2));
+        assert_eq!(mismatch.actual.to_u64(), Some(7));
+        assert_eq!(report.unchecked.len(), 1);
+        assert_eq!(report.unchecked[0].step, 9);
+        assert!(!report.passed());
+
+        assert!(matches!(
+            GoldenVectors::parse("out, 1"),
+            Err(GoldenError::Syntax { line: 1 })
+        ));
+        let unknown = GoldenVectors::parse("foo 0 1").unwrap();
+        assert!(matches!(
+            GoldenChecker::new(&ctx, &sys, &unknown),
+            Err(GoldenError::UnknownSignal(_))
+        ));
+    }
+}
@@ -0,0 +1,584 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # GPU Offload (Experimental)
+//! Lowers the step function of a system to a WGSL compute shader in which every invocation
+//! advances one independent run, the GPU counterpart of [`BatchSimulator`](super::BatchSimulator).
+//! Only a subset of systems is supported: all signals need to fit into a `u32` and division,
+//! remainder and array operations are not available. [`lower_to_wgsl`] returns an error for
+//! everything else.
+//!
+//! We do not depend on a GPU runtime. The shader is meant to be dispatched with any WebGPU
+//! implementation, e.g., `wgpu`, using the buffer layout described by [`GpuKernel`].
+//! Such an implementation is plugged into a [`GpuRunner`] as a [`GpuDevice`]. The runner
+//! transparently falls back to the CPU batch simulator if the system cannot be lowered, or if
+//! the device rejects the shader or fails to run it. Since the shader is only compiled by the
+//! device, this is also the only place where it gets validated.
+
+use super::interpreter::schedule;
+use super::{BatchError, BatchSimulator, InitKind, SimError};
+use crate::expr::{Context, Expr, ExprRef, TypeCheck, WidthInt};
+use crate::system::TransitionSystem;
+use baa::{BitVecOps, BitVecValue, BitVecValueRef};
+use rustc_hash::FxHashMap;
+use std::fmt::Write;
+
+/// Widest signal that can be stored in a single `u32`.
+const MAX_WIDTH: WidthInt = 32;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum GpuError {
+    #[error("{0:?} is wider than 32 bits")]
+    TooWide(ExprRef),
+    #[error("{0:?} uses an operation that is not supported on the GPU")]
+    Unsupported(ExprRef),
+    #[error("GPU device failed: {0}")]
+    Device(String),
+}
+
+/// A compute shader that advances every run by one step.
+///
+/// All buffers store one `u32` per signal and are indexed by `run * count + index`:
+/// - binding 0, `states`: read and written, one entry per state in [`GpuKernel::states`]
+/// - binding 1, `inputs`: read only, one entry per input in [`GpuKernel::inputs`]
+/// - binding 2, `bad`: written, the value of every bad state before the step
+/// - binding 3, `runs`: a uniform `u32` with the number of runs
+#[derive(Debug, Clone)]
+pub struct GpuKernel {
+    pub source: String,
+    pub states: Vec<ExprRef>,
+    pub inputs: Vec<ExprRef>,
+    pub bad_states: usize,
+    pub workgroup_size: u32,
+}
+
+impl GpuKernel {
+    /// Number of workgroups needed to simulate `runs` runs.
+    pub fn workgroups(&self, runs: u32) -> u32 {
+        runs.div_ceil(self.workgroup_size)
+    }
+}
+
+/// Runs the shader of a [`GpuKernel`] on a GPU, e.g., with `wgpu`.
+pub trait GpuDevice {
+    /// Compiles the shader, which is used by all following calls to [`GpuDevice::dispatch`].
+    fn load(&mut self, kernel: &GpuKernel) -> Result<(), GpuError>;
+    /// Advances `runs` runs by one step. All buffers use the layout described by [`GpuKernel`].
+    fn dispatch(
+        &mut self,
+        runs: u32,
+        states: &mut [u32],
+        inputs: &[u32],
+        bad: &mut [u32],
+    ) -> Result<(), GpuError>;
+}
+
+/// Simulates many runs at once on a [`GpuDevice`], with a [`BatchSimulator`] as fallback.
+/// The batch simulator also computes the initial states and answers all queries, the device
+/// only computes the next states, which are copied back after every step.
+pub struct GpuRunner<'a> {
+    batch: BatchSimulator<'a>,
+    gpu: Option<GpuRun<'a>>,
+    /// why the batch simulator is used instead of the device
+    fallback: Option<GpuError>,
+    step_count: u64,
+}
+
+/// A loaded kernel and the buffers of all runs.
+struct GpuRun<'a> {
+    device: Box<dyn GpuDevice + 'a>,
+    kernel: GpuKernel,
+    runs: u32,
+    /// width of every state in [`GpuKernel::states`]
+    widths: Vec<WidthInt>,
+    states: Vec<u32>,
+    inputs: Vec<u32>,
+    bad: Vec<u32>,
+}
+
+impl<'a> GpuRunner<'a> {
+    /// Uses `device` if there is one, `sys` can be lowered to WGSL and the device accepts the
+    /// shader. Only fails if `sys` is not supported by the [`BatchSimulator`] either.
+    pub fn new(
+        ctx: &'a Context,
+        sys: &'a TransitionSystem,
+        lanes: usize,
+        device: Option<Box<dyn GpuDevice + 'a>>,
+    ) -> Result<Self, BatchError> {
+        let batch = BatchSimulator::new(ctx, sys, lanes)?;
+        let (gpu, fallback) = match device.map(|d| GpuRun::load(ctx, sys, lanes, d)) {
+            Some(Ok(gpu)) => (Some(gpu), None),
+            Some(Err(e)) => (None, Some(e)),
+            None => (None, None),
+        };
+        Ok(Self {
+            batch,
+            gpu,
+            fallback,
+            step_count: 0,
+        })
+    }
+
+    /// Whether steps are computed by the GPU device.
+    pub fn is_offloaded(&self) -> bool {
+        self.gpu.is_some()
+    }
+
+    /// Why the runner fell back to the CPU, `None` if no device was provided.
+    pub fn fallback_reason(&self) -> Option<&GpuError> {
+        self.fallback.as_ref()
+    }
+
+    pub fn lanes(&self) -> usize {
+        self.batch.lanes()
+    }
+
+    /// Initializes states and inputs of all lanes, see [`BatchSimulator::init`].
+    pub fn init(&mut self, kind: InitKind) {
+        self.batch.init(kind);
+        if let Some(gpu) = &mut self.gpu {
+            gpu.upload(&self.batch);
+        }
+        self.step_count = 0;
+    }
+
+    /// Changes the value of an input or state in a single lane.
+    pub fn set<'b>(
+        &mut self,
+        lane: usize,
+        symbol: ExprRef,
+        value: impl Into<BitVecValueRef<'b>>,
+    ) -> Result<(), SimError> {
+        let value = value.into();
+        // signals on the GPU are never wider than 32 bits
+        let raw = value.to_u64();
+        self.batch.set(lane, symbol, value)?;
+        if let (Some(gpu), Some(raw)) = (&mut self.gpu, raw) {
+            gpu.set(lane, symbol, raw as u32);
+        }
+        Ok(())
+    }
+
+    /// Advances all lanes by one step. If the device fails, this and all following steps are
+    /// computed by the batch simulator.
+    pub fn step(&mut self) {
+        self.step_count += 1;
+        if let Some(gpu) = &mut self.gpu {
+            match gpu.step(&mut self.batch) {
+                Ok(()) => return,
+                Err(e) => {
+                    self.gpu = None;
+                    self.fallback = Some(e);
+                }
+            }
+        }
+        self.batch.step();
+    }
+
+    /// Value of a bit-vector expression in a single lane, see [`BatchSimulator::get`].
+    pub fn get(&mut self, lane: usize, expr: ExprRef) -> Option<BitVecValue> {
+        self.batch.update();
+        self.batch.get(lane, expr)
+    }
+
+    /// Lanes in which the bad state `index` is currently active.
+    pub fn failing_lanes(&mut self, index: usize) -> Vec<usize> {
+        self.batch.update();
+        self.batch.failing_lanes(index)
+    }
+
+    pub fn step_count(&self) -> u64 {
+        self.step_count
+    }
+}
+
+impl<'a> GpuRun<'a> {
+    fn load(
+        ctx: &Context,
+        sys: &TransitionSystem,
+        lanes: usize,
+        mut device: Box<dyn GpuDevice + 'a>,
+    ) -> Result<Self, GpuError> {
+        let runs = u32::try_from(lanes)
+            .map_err(|_| GpuError::Device(format!("{lanes} runs do not fit into a u32")))?;
+        let kernel = lower_to_wgsl(ctx, sys)?;
+        device.load(&kernel)?;
+        Ok(Self {
+            device,
+            runs,
+            widths: kernel
+                .states
+                .iter()
+                .map(|s| s.get_bv_type(ctx).unwrap())
+                .collect(),
+            states: vec![0; lanes * kernel.states.len()],
+            inputs: vec![0; lanes * kernel.inputs.len()],
+            bad: vec![0; lanes * kernel.bad_states],
+            kernel,
+        })
+    }
+
+    /// Copies all states and inputs from the batch simulator.
+    fn upload(&mut self, batch: &BatchSimulator<'_>) {
+        for lane in 0..self.runs as usize {
+            for (ii, &symbol) in self.kernel.states.iter().enumerate() {
+                let value = batch.get(lane, symbol).unwrap().to_u64().unwrap() as u32;
+                self.states[lane * self.kernel.states.len() + ii] = value;
+            }
+            for (ii, &symbol) in self.kernel.inputs.iter().enumerate() {
+                let value = batch.get(lane, symbol).unwrap().to_u64().unwrap() as u32;
+                self.inputs[lane * self.kernel.inputs.len() + ii] = value;
+            }
+        }
+    }
+
+    fn set(&mut self, lane: usize, symbol: ExprRef, value: u32) {
+        if let Some(ii) = self.kernel.states.iter().position(|&s| s == symbol) {
+            self.states[lane * self.kernel.states.len() + ii] = value;
+        } else if let Some(ii) = self.kernel.inputs.iter().position(|&i| i == symbol) {
+            self.inputs[lane * self.kernel.inputs.len() + ii] = value;
+        }
+    }
+
+    /// Runs the kernel and copies the next states back into the batch simulator.
+    fn step(&mut self, batch: &mut BatchSimulator<'_>) -> Result<(), GpuError> {
+        self.device
+            .dispatch(self.runs, &mut self.states, &self.inputs, &mut self.bad)?;
+        let count = self.kernel.states.len();
+        for (ii, (&symbol, &width)) in self
+            .kernel
+            .states
+            .iter()
+            .zip(self.widths.iter())
+            .enumerate()
+        {
+            for lane in 0..self.runs as usize {
+                let value = BitVecValue::from_u64(self.states[lane * count + ii] as u64, width);
+                batch.set(lane, symbol, &value).unwrap();
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn lower_to_wgsl(ctx: &Context, sys: &TransitionSystem) -> Result<GpuKernel, GpuError> {
+    let workgroup_size = 64;
+    let states: Vec<ExprRef> = sys.states.iter().map(|s| s.symbol).collect();
+    let mut names: FxHashMap<ExprRef, String> = FxHashMap::default();
+    let mut body = String::new();
+    for (ii, &symbol) in states.iter().enumerate() {
+        check_width(ctx, symbol)?;
+        let name = format!("s{ii}");
+        writeln!(
+            body,
+            "    let {name} = states[run * {}u + {ii}u];",
+            states.len()
+        )
+        .unwrap();
+        names.insert(symbol, name);
+    }
+    for (ii, &symbol) in sys.inputs.iter().enumerate() {
+        check_width(ctx, symbol)?;
+        let name = format!("i{ii}");
+        let count = sys.inputs.len();
+        writeln!(body, "    let {name} = inputs[run * {count}u + {ii}u];").unwrap();
+        names.insert(symbol, name);
+    }
+
+    for e in schedule(ctx, sys) {
+        let width = check_width(ctx, e)?;
+        let value = lower_expr(ctx, &names, e, width)?;
+        let name = format!("e{}", names.len());
+        writeln!(body, "    let {name} = {value};").unwrap();
+        names.insert(e, name);
+    }
+
+    for (ii, &bad) in sys.bad_states.iter().enumerate() {
+        let value = operand(ctx, &names, bad)?;
+        let count = sys.bad_states.len();
+        writeln!(body, "    bad[run * {count}u + {ii}u] = {value};").unwrap();
+    }
+    for (ii, state) in sys.states.iter().enumerate() {
+        if let Some(next) = state.next {
+            let value = operand(ctx, &names, next)?;
+            let count = states.len();
+            writeln!(body, "    states[run * {count}u + {ii}u] = {value};").unwrap();
+        }
+    }
+
+    let source = format!(
+        "@group(0) @binding(0) var<storage, read_write> states: array<u32>;\n\
+         @group(0) @binding(1) var<storage, read> inputs: array<u32>;\n\
+         @group(0) @binding(2) var<storage, read_write> bad: array<u32>;\n\
+         @group(0) @binding(3) var<uniform> runs: u32;\n\
+         \n\
+         @compute @workgroup_size({workgroup_size})\n\
+         fn step(@builtin(global_invocation_id) id: vec3<u32>) {{\n\
+         \x20   let run = id.x;\n\
+         \x20   if (run >= runs) {{\n\
+         \x20       return;\n\
+         \x20   }}\n\
+         {body}}}\n"
+    );
+    Ok(GpuKernel {
+        source,
+        states,
+        inputs: sys.inputs.clone(),
+        bad_states: sys.bad_states.len(),
+        workgroup_size,
+    })
+}
+
+fn check_width(ctx: &Context, e: ExprRef) -> Result<WidthInt, GpuError> {
+    match e.get_bv_type(ctx) {
+        Some(width) if width <= MAX_WIDTH => Ok(width),
+        Some(_) => Err(GpuError::TooWide(e)),
+        None => Err(GpuError::Unsupported(e)),
+    }
+}
+
+/// Name of a variable or a constant that holds the value of `e`.
+fn operand(
+    ctx: &Context,
+    names: &FxHashMap<ExprRef, String>,
+    e: ExprRef,
+) -> Result<String, GpuError> {
+    if let Some(name) = names.get(&e) {
+        return Ok(name.clone());
+    }
+    match &ctx[e] {
+        Expr::BVLiteral(value) => {
+            check_width(ctx, e)?;
+            Ok(format!("{}u", value.get(ctx).to_u64().unwrap()))
+        }
+        // the schedule guarantees that all other expressions were lowered before
+        _ => Err(GpuError::Unsupported(e)),
+    }
+}
+
+fn mask(width: WidthInt) -> String {
+    if width == MAX_WIDTH {
+        "0xffffffffu".to_string()
+    } else {
+        format!("{:#x}u", (1u32 << width) - 1)
+    }
+}
+
+/// Sign extends a `width`-bit value to 32 bits.
+fn signed(value: &str, width: WidthInt) -> String {
+    let shift = MAX_WIDTH - width;
+    format!("(bitcast<i32>({value} << {shift}u) >> {shift}u)")
+}
+
+/// WGSL expression that computes `e` from its operands. Results are always masked to the
+/// width of `e`, such that unused upper bits are zero.
+fn lower_expr(
+    ctx: &Context,
+    names: &FxHashMap<ExprRef, String>,
+    e: ExprRef,
+    width: WidthInt,
+) -> Result<String, GpuError> {
+    let op = |c: ExprRef| operand(ctx, names, c);
+    let m = mask(width);
+    let value = match ctx[e] {
+        Expr::BVZeroExt { e: a, .. } => op(a)?,
+        Expr::BVSignExt { e: a, .. } => {
+            let w = check_width(ctx, a)?;
+            format!("(bitcast<u32>({}) & {m})", signed(&op(a)?, w))
+        }
+        Expr::BVSlice { e: a, lo, .. } => format!("(({} >> {lo}u) & {m})", op(a)?),
+        Expr::BVNot(a, _) => format!("(~{} & {m})", op(a)?),
+        Expr::BVNegate(a, _) => format!("((0u - {}) & {m})", op(a)?),
+        Expr::BVEqual(a, b) => format!("select(0u, 1u, {} == {})", op(a)?, op(b)?),
+        Expr::BVImplies(a, b) => format!("((~{} | {}) & 1u)", op(a)?, op(b)?),
+        Expr::BVGreater(a, b) => format!("select(0u, 1u, {} > {})", op(a)?, op(b)?),
+        Expr::BVGreaterEqual(a, b) => format!("select(0u, 1u, {} >= {})", op(a)?, op(b)?),
+        Expr::BVGreaterSigned(a, b, w) => format!(
+            "select(0u, 1u, {} > {})",
+            signed(&op(a)?, w),
+            signed(&op(b)?, w)
+        ),
+        Expr::BVGreaterEqualSigned(a, b, w) => format!(
+            "select(0u, 1u, {} >= {})",
+            signed(&op(a)?, w),
+            signed(&op(b)?, w)
+        ),
+        Expr::BVConcat(a, b, _) => {
+            let lsb_width = check_width(ctx, b)?;
+            format!("(({} << {lsb_width}u) | {})", op(a)?, op(b)?)
+        }
+        Expr::BVAnd(a, b, _) => format!("({} & {})", op(a)?, op(b)?),
+        Expr::BVOr(a, b, _) => format!("({} | {})", op(a)?, op(b)?),
+        Expr::BVXor(a, b, _) => format!("({} ^ {})", op(a)?, op(b)?),
+        // WGSL only defines shifts by less than 32 bits
+        Expr::BVShiftLeft(a, b, w) => {
+            let by = op(b)?;
+            format!("select(0u, ({} << {by}) & {m}, {by} < {w}u)", op(a)?)
+        }
+        Expr::BVShiftRight(a, b, w) => {
+            let by = op(b)?;
+            format!("select(0u, {} >> {by}, {by} < {w}u)", op(a)?)
+        }
+        Expr::BVArithmeticShiftRight(a, b, w) => format!(
+            "(bitcast<u32>({} >> min({}, 31u)) & {m})",
+            signed(&op(a)?, w),
+            op(b)?
+        ),
+        Expr::BVAdd(a, b, _) => format!("(({} + {}) & {m})", op(a)?, op(b)?),
+        Expr::BVMul(a, b, _) => format!("(({} * {}) & {m})", op(a)?, op(b)?),
+        Expr::BVSub(a, b, _) => format!("(({} - {}) & {m})", op(a)?, op(b)?),
+        Expr::BVIte { cond, tru, fals } => {
+            format!("select({}, {}, {} != 0u)", op(fals)?, op(tru)?, op(cond)?)
+        }
+        _ => return Err(GpuError::Unsupported(e)),
+    };
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::State;
+
+    fn counter(ctx: &mut Context) -> TransitionSystem {
+        let mut sys = TransitionSystem::new("counter".to_string());
+        let en = ctx.bv_symbol("en", 1);
+        sys.add_input(ctx, en);
+        let count = ctx.bv_symbol("count", 4);
+        let next = ctx.build(|c| c.ite(en, c.add(count, c.one(4)), count));
+        sys.add_state(
+            ctx,
+            State {
+                symbol: count,
+                init: None,
+                next: Some(next),
+            },
+        );
+        let bad = ctx.build(|c| c.equal(count, c.bit_vec_val(15, 4)));
+        sys.bad_states.push(bad);
+        sys
+    }
+
+    /// Computes what the counter shader computes, fails after a number of steps.
+    struct CounterDevice {
+        fail_after: u32,
+    }
+
+    impl GpuDevice for CounterDevice {
+        fn load(&mut self, kernel: &GpuKernel) -> Result<(), GpuError> {
+            assert!(kernel.source.contains("fn step("));
+            Ok(())
+        }
+
+        fn dispatch(
+            &mut self,
+            runs: u32,
+            states: &mut [u32],
+            inputs: &[u32],
+            bad: &mut [u32],
+        ) -> Result<(), GpuError> {
+            if self.fail_after == 0 {
+                return Err(GpuError::Device("lost device".to_string()));
+            }
+            self.fail_after -= 1;
+            for run in 0..runs as usize {
+                bad[run] = (states[run] == 15) as u32;
+                states[run] = (states[run] + inputs[run]) & 0xf;
+            }
+            Ok(())
+        }
+    }
+
+    struct NoAdapter;
+
+    impl GpuDevice for NoAdapter {
+        fn load(&mut self, _kernel: &GpuKernel) -> Result<(), GpuError> {
+            Err(GpuError::Device("no adapter".to_string()))
+        }
+
+        fn dispatch(
+            &mut self,
+            _: u32,
+            _: &mut [u32],
+            _: &[u32],
+            _: &mut [u32],
+        ) -> Result<(), GpuError> {
+            unreachable!("the kernel was never loaded")
+        }
+    }
+
+    #[test]
+    fn test_lower_counter() {
+        let mut ctx = Context::default();
+        let mut sys = counter(&mut ctx);
+        let count = sys.states[0].symbol;
+        let en = sys.inputs[0];
+
+        let kernel = lower_to_wgsl(&ctx, &sys).unwrap();
+        assert_eq!(kernel.states, [count]);
+        assert_eq!(kernel.inputs, [en]);
+        assert_eq!(kernel.workgroups(100), 2);
+        let src = &kernel.source;
+        assert!(src.contains("fn step("));
+        assert!(src.contains("let s0 = states[run * 1u + 0u];"));
+        assert!(src.contains("((s0 + 1u) & 0xfu)"));
+        assert!(src.contains("select(0u, 1u, s0 == 15u)"));
+        assert!(src.contains("bad[run * 1u + 0u] = "));
+        assert!(src.contains("states[run * 1u + 0u] = "));
+
+        // wide signals need to stay on the CPU
+        let wide = ctx.bv_symbol("wide", 64);
+        sys.add_input(&ctx, wide);
+        assert_eq!(
+            lower_to_wgsl(&ctx, &sys).unwrap_err(),
+            GpuError::TooWide(wide)
+        );
+    }
+
+    #[test]
+    fn test_runner_fallback() {
+        let mut ctx = Context::default();
+        let sys = counter(&mut ctx);
+        let (count, en) = (sys.states[0].symbol, sys.inputs[0]);
+        let lanes = 3;
+
+        let cpu = GpuRunner::new(&ctx, &sys, lanes, None).unwrap();
+        assert!(!cpu.is_offloaded());
+        assert!(cpu.fallback_reason().is_none());
+        let rejected = GpuRunner::new(&ctx, &sys, lanes, Some(Box::new(NoAdapter))).unwrap();
+        assert!(!rejected.is_offloaded());
+        assert!(matches!(
+            rejected.fallback_reason(),
+            Some(GpuError::Device(_))
+        ));
+
+        // the device fails half way through, the batch simulator takes over
+        let device = Box::new(CounterDevice { fail_after: 5 });
+        let mut runner = GpuRunner::new(&ctx, &sys, lanes, Some(device)).unwrap();
+        assert!(runner.is_offloaded());
+        let mut reference = BatchSimulator::new(&ctx, &sys, lanes).unwrap();
+        runner.init(InitKind::Zero);
+        reference.init(InitKind::Zero);
+        for step in 0..10u64 {
+            for lane in 0..lanes {
+                let value = BitVecValue::from_u64((step + lane as u64) % 2, 1);
+                runner.set(lane, en, &value).unwrap();
+                reference.set(lane, en, &value).unwrap();
+            }
+            runner.step();
+            reference.step();
+            reference.update();
+            for lane in 0..lanes {
+                assert_eq!(runner.get(lane, count), reference.get(lane, count));
+            }
+            assert_eq!(runner.failing_lanes(0), reference.failing_lanes(0));
+        }
+        assert!(!runner.is_offloaded());
+        assert!(matches!(
+            runner.fallback_reason(),
+            Some(GpuError::Device(_))
+        ));
+        assert_eq!(runner.step_count(), 10);
+    }
+}
@@ -2,10 +2,10 @@
 // released under BSD 3-Clause License
 // author: Kevin Laeufer <laeufer@berkeley.edu>
 
-use crate::expr::{ArrayType, ExprRef, Type};
+use crate::expr::{ArrayType, ExprRef, Type, WidthInt};
+use crate::random::new_rng;
 use baa::{ArrayValue, BitVecValue, BitVecValueRef, Value};
 use rand::rngs::SmallRng;
-use rand::SeedableRng;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub enum InitKind {
@@ -13,6 +13,35 @@ pub enum InitKind {
     Random(u64),
 }
 
+impl InitKind {
+    /// Random initialization with the seed from [`crate::random::default_seed`].
+    pub fn random() -> Self {
+        InitKind::Random(crate::random::default_seed())
+    }
+
+    /// Returns the seed that needs to be reported in order to reproduce a run.
+    pub fn seed(&self) -> Option<u64> {
+        match self {
+            InitKind::Zero => None,
+            InitKind::Random(seed) => Some(*seed),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SimError {
+    #[error("{0:?} is not a bit-vector state or input of the simulated system")]
+    UnknownSymbol(ExprRef),
+    #[error("cannot assign a bv<{actual}> value to a bv<{expected}> signal")]
+    WidthMismatch {
+        expected: WidthInt,
+        actual: WidthInt,
+    },
+    /// snapshot ids of the [`crate::sim::Interpreter`]
+    #[error("no snapshot with id {0}")]
+    UnknownSnapshot(u32),
+}
+
 /// An implementation of a transition system simulator.
 pub trait Simulator {
     type SnapshotId;
@@ -24,17 +53,46 @@ pub trait Simulator {
     fn step(&mut self);
 
     /// Change the value or an expression in the simulator.
-    fn set<'a>(&mut self, expr: ExprRef, value: impl Into<BitVecValueRef<'a>>);
+    fn set<'a>(
+        &mut self,
+        expr: ExprRef,
+        value: impl Into<BitVecValueRef<'a>>,
+    ) -> Result<(), SimError>;
+
+    /// Recomputes combinational signals after inputs were changed with [`Simulator::set`].
+    /// Simulators that evaluate every expression on demand do not need to do anything.
+    fn update(&mut self) {}
 
     /// Inspect the value of any expression in the circuit
     fn get(&self, expr: ExprRef) -> Value;
 
+    /// Change the values of several expressions at once.
+    /// Stops at the first value that cannot be applied.
+    fn set_many<'a>(&mut self, values: &[(ExprRef, BitVecValueRef<'a>)]) -> Result<(), SimError> {
+        for &(expr, value) in values.iter() {
+            self.set(expr, value)?;
+        }
+        Ok(())
+    }
+
+    /// Inspect the values of several bit-vector expressions at once.
+    /// Returns `None` for any expression that does not evaluate to a bit-vector.
+    fn get_many(&self, exprs: &[ExprRef]) -> Vec<Option<BitVecValue>> {
+        exprs
+            .iter()
+            .map(|&e| match self.get(e) {
+                Value::BitVec(value) => Some(value),
+                Value::Array(_) => None,
+            })
+            .collect()
+    }
+
     fn step_count(&self) -> u64;
 
     /// Takes a snapshot of the state (excluding inputs) and saves it internally.
     fn take_snapshot(&mut self) -> Self::SnapshotId;
     /// Restores a snapshot that was previously taken with the same simulator.
-    fn restore_snapshot(&mut self, id: Self::SnapshotId);
+    fn restore_snapshot(&mut self, id: Self::SnapshotId) -> Result<(), SimError>;
 }
 
 pub struct InitValueGenerator {
@@ -46,7 +104,7 @@ impl InitValueGenerator {
         match kind {
             InitKind::Zero => Self { rng: None },
             InitKind::Random(seed) => Self {
-                rng: Some(SmallRng::seed_from_u64(seed)),
+                rng: Some(new_rng(seed)),
             },
         }
     }
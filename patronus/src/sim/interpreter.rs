@@ -3,10 +3,39 @@
 // released under BSD 3-Clause License
 // author: Kevin Laeufer <laeufer@cornell.edu>
 
-use super::{InitKind, InitValueGenerator, Simulator};
+use super::activity::{ActivityStats, ActivityTracker};
+use super::bytecode::{Program, UnsupportedExpr};
+use super::perf::PerfCounters;
+use super::program_cache::ProgramCache;
+use super::revisit::{state_hash, Revisit, RevisitTracker};
+use super::snapshot::{SnapshotSpill, SnapshotStore};
+use super::two_phase::{StaleRead, TwoPhaseCache};
+use super::{
+    InitKind, InitValueGenerator, PerfReport, SimError, Simulator, StateImage, StateImageError,
+};
 use crate::expr::*;
 use crate::system::*;
 use baa::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde::{Deserialize, Serialize};
+use std::cell::OnceCell;
+use std::time::Instant;
+
+/// Determines when the [`Interpreter`] evaluates the expressions of a system.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EvalOrder {
+    /// Every `get` evaluates the requested expression from scratch.
+    /// Best when only a few signals are inspected, e.g., during interactive debugging.
+    #[default]
+    Lazy,
+    /// All expressions of the system are evaluated once per step in topological order and
+    /// `get` returns the cached value. Best when most signals are read every step.
+    Eager,
+    /// Like `Eager`, but all expressions are compiled into a linear bytecode first, which is
+    /// faster to execute. Systems with arrays fall back to `Eager`.
+    Bytecode,
+}
 
 /// Interpreter based simulator for a transition system.
 pub struct Interpreter<'a> {
@@ -14,16 +43,59 @@ pub struct Interpreter<'a> {
     sys: &'a TransitionSystem,
     step_count: u64,
     data: SymbolValueStore,
-    snapshots: Vec<SymbolValueStore>,
+    snapshots: SnapshotStore,
     #[allow(dead_code)]
     do_trace: bool,
+    perf: Option<PerfCounters>,
+    two_phase: Option<TwoPhaseCache>,
+    revisits: Option<RevisitTracker>,
+    activity: Option<ActivityTracker>,
+    eval_order: EvalOrder,
+    /// all non-leaf expressions in topological order, only used for eager evaluation
+    schedule: Vec<ExprRef>,
+    /// values of the expressions in the schedule
+    cache: SymbolValueStore,
+    /// the schedule compiled to bytecode, replaces the cache
+    program: Option<Program>,
+    /// where compiled programs are stored across runs
+    program_cache: Option<ProgramCache>,
+    registers: Vec<BitVecValue>,
+    /// inputs changed since the cache was last computed
+    cache_stale: bool,
+    /// signals that keep their value until they are released
+    forced: FxHashMap<ExprRef, BitVecValue>,
+    /// built on the first lookup by name
+    name_index: OnceCell<NameIndex>,
+    /// `(state, next)` for every state that can change, states whose next function is the
+    /// state itself never need to be evaluated
+    next_functions: Vec<(ExprRef, ExprRef)>,
+    /// states with an init expression in the order in which they are initialized
+    init_order: Vec<usize>,
+    /// inputs that init expressions refer to
+    init_inputs: FxHashSet<ExprRef>,
+    /// while true, changing one of the `init_inputs` re-evaluates the init expressions
+    before_first_step: bool,
 }
 
 impl<'a> Interpreter<'a> {
+    /// Panics while simulating a system that cannot be evaluated, see [`Interpreter::try_new`].
     pub fn new(ctx: &'a Context, sys: &'a TransitionSystem) -> Self {
         Self::internal_new(ctx, sys, false)
     }
 
+    /// Fails if the system contains an uninterpreted function or another expression that
+    /// cannot be evaluated.
+    pub fn try_new(ctx: &'a Context, sys: &'a TransitionSystem) -> Result<Self, ExprError> {
+        check_evaluable(ctx, sys.get_all_exprs())?;
+        Ok(Self::new(ctx, sys))
+    }
+
+    pub fn with_eval_order(ctx: &'a Context, sys: &'a TransitionSystem, order: EvalOrder) -> Self {
+        let mut sim = Self::internal_new(ctx, sys, false);
+        sim.set_eval_order(order);
+        sim
+    }
+
     pub fn new_with_trace(ctx: &'a Context, sys: &'a TransitionSystem) -> Self {
         Self::internal_new(ctx, sys, true)
     }
@@ -34,10 +106,444 @@ impl<'a> Interpreter<'a> {
             sys,
             step_count: 0,
             data: Default::default(),
-            snapshots: vec![],
+            snapshots: SnapshotStore::default(),
             do_trace,
+            perf: None,
+            two_phase: None,
+            revisits: None,
+            activity: None,
+            eval_order: EvalOrder::Lazy,
+            schedule: vec![],
+            cache: Default::default(),
+            program: None,
+            program_cache: None,
+            registers: vec![],
+            cache_stale: true,
+            forced: FxHashMap::default(),
+            name_index: OnceCell::new(),
+            next_functions: sys
+                .states
+                .iter()
+                .filter(|s| !s.is_const())
+                .flat_map(|s| Some((s.symbol, s.next?)))
+                .collect(),
+            init_order: init_order(ctx, sys)
+                .into_iter()
+                .filter(|&ii| sys.states[ii].init.is_some())
+                .collect(),
+            init_inputs: init_inputs(ctx, sys),
+            before_first_step: false,
+        }
+    }
+
+    pub fn eval_order(&self) -> EvalOrder {
+        self.eval_order
+    }
+
+    /// Switches the evaluation strategy. Takes effect immediately.
+    pub fn set_eval_order(&mut self, order: EvalOrder) {
+        let _ = self.try_set_eval_order(order);
+    }
+
+    /// Like [`Interpreter::set_eval_order`], but returns the expression that could not be
+    /// compiled when [`EvalOrder::Bytecode`] falls back to [`EvalOrder::Eager`].
+    pub(crate) fn try_set_eval_order(&mut self, order: EvalOrder) -> Result<(), UnsupportedExpr> {
+        self.eval_order = order;
+        self.cache.clear();
+        self.cache_stale = true;
+        self.schedule = match order {
+            EvalOrder::Lazy => vec![],
+            EvalOrder::Eager | EvalOrder::Bytecode => schedule(self.ctx, self.sys),
+        };
+        self.program = None;
+        if order == EvalOrder::Bytecode {
+            let program = match &self.program_cache {
+                Some(cache) => cache.load_or_compile(self.ctx, &self.schedule),
+                None => Program::compile(self.ctx, &self.schedule),
+            };
+            match program {
+                Ok(program) => {
+                    self.registers = program.new_registers();
+                    self.program = Some(program);
+                }
+                Err(e) => {
+                    self.eval_order = EvalOrder::Eager;
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads and stores the programs compiled for [`EvalOrder::Bytecode`] in a directory,
+    /// such that later runs on the same design skip the compilation.
+    /// Only used the next time the evaluation order is set.
+    pub fn set_program_cache(&mut self, cache: Option<ProgramCache>) {
+        self.program_cache = cache;
+    }
+
+    /// Number of bytecode instructions executed per step, `None` unless the evaluation order
+    /// is [`EvalOrder::Bytecode`].
+    pub fn bytecode_len(&self) -> Option<usize> {
+        self.program.as_ref().map(|p| p.instruction_count())
+    }
+
+    /// Evaluates all expressions in the schedule, reusing the values of their children.
+    fn update_cache(&mut self) {
+        if let Some(program) = &self.program {
+            let values = Values {
+                forced: &self.forced,
+                data: &self.data,
+                cache: None,
+                compiled: None,
+            };
+            program.run(self.ctx, &values, &self.forced, &mut self.registers);
+            if let Some(perf) = &mut self.perf {
+                // every instruction computes exactly one expression
+                program.exprs().iter().for_each(|&e| perf.record_eval(e));
+            }
+            self.cache_stale = false;
+            return;
+        }
+        for &e in self.schedule.iter() {
+            let values = Values {
+                forced: &self.forced,
+                data: &self.data,
+                cache: Some(&self.cache),
+                compiled: None,
+            };
+            let value = match &mut self.perf {
+                None => eval_expr(self.ctx, &values, e),
+                Some(perf) => eval_expr_observed(self.ctx, &values, e, |n| perf.record_eval(n)),
+            };
+            if self.cache.is_defined(e) {
+                self.cache.update(e, value);
+            } else {
+                match value {
+                    Value::BitVec(value) => self.cache.define_bv(e, &value),
+                    Value::Array(value) => self.cache.define_array(e, value),
+                }
+            }
+        }
+        self.cache_stale = false;
+    }
+
+    /// All values that are available without evaluating expressions.
+    fn values(&self) -> Values<'_> {
+        Values {
+            forced: &self.forced,
+            data: &self.data,
+            cache: (!self.cache_stale).then_some(&self.cache),
+            compiled: self
+                .program
+                .as_ref()
+                .filter(|_| !self.cache_stale)
+                .map(|p| (p, self.registers.as_slice())),
+        }
+    }
+
+    /// Overrides the value of `expr` until it is released. A forced state keeps its value
+    /// across steps, no matter its next state function, and assignments to a forced input are
+    /// ignored. Any other bit-vector expression can be forced as well, in which case all
+    /// expressions that depend on it observe the forced value.
+    pub fn force<'b>(
+        &mut self,
+        expr: ExprRef,
+        value: impl Into<BitVecValueRef<'b>>,
+    ) -> Result<(), SimError> {
+        let value = value.into();
+        let expected = expr
+            .get_bv_type(self.ctx)
+            .ok_or(SimError::UnknownSymbol(expr))?;
+        if expected != value.width() {
+            return Err(SimError::WidthMismatch {
+                expected,
+                actual: value.width(),
+            });
+        }
+        self.forced.insert(expr, value.into());
+        self.mark_all_changed();
+        self.invalidate();
+        Ok(())
+    }
+
+    /// Removes the override of `expr`. Like in Verilog, released states and inputs keep the
+    /// forced value until they are updated by the next step or a `set`, while all other
+    /// expressions immediately return to their computed value.
+    pub fn release(&mut self, expr: ExprRef) {
+        if let Some(value) = self.forced.remove(&expr) {
+            if self.data.is_defined(expr) {
+                self.data.update_bv(expr, &value);
+            }
+            self.mark_all_changed();
+            self.invalidate();
         }
     }
+
+    /// Expressions that are currently forced.
+    pub fn forced(&self) -> impl Iterator<Item = ExprRef> + '_ {
+        self.forced.keys().copied()
+    }
+
+    /// Returns the value of a signal by its hierarchical name, e.g., `core.alu.result`.
+    /// Any unique suffix of the name works as well, see [`NameIndex::resolve`].
+    pub fn get_by_name(&self, name: &str) -> Result<Value, NameError> {
+        let index = self
+            .name_index
+            .get_or_init(|| NameIndex::new(self.ctx, self.sys));
+        Ok(self.get(index.resolve(name)?))
+    }
+
+    /// Copies the values of all states, e.g., in order to continue the simulation with a
+    /// different backend or in another process.
+    pub fn dump_state(&self) -> StateImage {
+        StateImage::capture(self.ctx, self.sys, self.step_count, |e| self.get(e))
+    }
+
+    /// Overwrites all states and the step count with the values of `image`. Inputs keep their
+    /// current values.
+    pub fn load_state(&mut self, image: &StateImage) -> Result<(), StateImageError> {
+        for (symbol, value) in image.resolve(self.ctx, self.sys)? {
+            self.data.update(symbol, value);
+        }
+        self.step_count = image.step;
+        self.before_first_step = false;
+        self.mark_all_changed();
+        self.invalidate();
+        self.update();
+        Ok(())
+    }
+
+    /// Needs to be called whenever the value of a symbol changes outside of a step.
+    fn invalidate(&mut self) {
+        self.cache_stale = true;
+        if let Some(cache) = &mut self.two_phase {
+            cache.invalidate();
+        }
+    }
+
+    /// Starts collecting performance counters. Counters are reset if they were already enabled.
+    pub fn enable_perf_counters(&mut self) {
+        self.perf = Some(PerfCounters::default());
+    }
+
+    /// Returns a summary of the performance counters including up to `max_hot_exprs` of the
+    /// next state expressions that took the longest to evaluate and of the expression nodes
+    /// that were computed most often.
+    /// Returns `None` if counters were never enabled.
+    pub fn perf_report(&self, max_hot_exprs: usize) -> Option<PerfReport> {
+        self.perf.as_ref().map(|p| p.report(max_hot_exprs))
+    }
+
+    /// Limits the memory used by snapshots. Once the budget is exceeded, the oldest snapshots
+    /// are moved to files in `spill.dir` and read back when they are restored.
+    /// `None` keeps all snapshots in memory.
+    pub fn set_snapshot_spill(&mut self, spill: Option<SnapshotSpill>) {
+        self.snapshots.set_spill(spill);
+    }
+
+    /// Switches to two-phase update semantics: outputs, constraints and bad states are only
+    /// recomputed by [`Simulator::update`] and [`Simulator::step`]. Until then, reads return
+    /// the old values and are recorded as stale reads. All other expressions are still
+    /// evaluated on demand.
+    pub fn enable_two_phase_update(&mut self) {
+        self.two_phase = Some(TwoPhaseCache::default());
+        self.update();
+    }
+
+    /// Reads of combinational signals that happened between a `set` and the next `update`.
+    pub fn stale_reads(&self) -> Vec<StaleRead> {
+        self.two_phase
+            .as_ref()
+            .map(|c| c.stale_reads())
+            .unwrap_or_default()
+    }
+
+    /// Starts hashing the state after every `init` and `step` in order to detect when a state
+    /// is reached again. States reached through `set`, snapshots or [`Interpreter::load_state`]
+    /// are not recorded.
+    pub fn enable_revisit_detection(&mut self) {
+        let mut tracker = RevisitTracker::default();
+        tracker.record(state_hash(self.ctx, self.sys, &self.data), self.step_count);
+        self.revisits = Some(tracker);
+    }
+
+    /// All steps at which an already visited state was reached, in the order they happened.
+    pub fn revisits(&self) -> &[Revisit] {
+        self.revisits
+            .as_ref()
+            .map(|t| t.revisits())
+            .unwrap_or_default()
+    }
+
+    /// Number of different states seen since revisit detection was enabled or the simulator
+    /// was last initialized. Returns `None` if revisit detection was never enabled.
+    pub fn distinct_states(&self) -> Option<usize> {
+        self.revisits.as_ref().map(|t| t.distinct_states())
+    }
+
+    /// Only re-evaluates next state functions if one of the inputs or states that they read
+    /// changed since the previous step. Pays off for designs where most of the state is idle
+    /// in most cycles.
+    pub fn enable_sparse_evaluation(&mut self) {
+        self.activity = Some(ActivityTracker::new(
+            self.ctx,
+            self.sys,
+            &self.next_functions,
+        ));
+    }
+
+    /// Returns `None` if sparse evaluation was never enabled.
+    pub fn activity_stats(&self) -> Option<ActivityStats> {
+        self.activity.as_ref().map(|a| a.stats())
+    }
+
+    fn mark_all_changed(&mut self) {
+        if let Some(activity) = &mut self.activity {
+            activity.mark_all();
+        }
+    }
+
+    /// Evaluates all init expressions in dependency order.
+    fn eval_init(&mut self) {
+        self.mark_all_changed();
+        for &ii in self.init_order.iter() {
+            let state = &self.sys.states[ii];
+            let value = eval_expr(self.ctx, &self.values(), state.init.unwrap());
+            self.data.update(state.symbol, value);
+        }
+    }
+
+    /// Arrays are never compared and always count as changed.
+    fn has_value(&self, symbol: ExprRef, value: &Value) -> bool {
+        match value {
+            Value::BitVec(value) => self.data.get_bv(self.ctx, symbol).as_ref() == Some(value),
+            Value::Array(_) => false,
+        }
+    }
+
+    fn record_visit(&mut self) {
+        if let Some(tracker) = &mut self.revisits {
+            tracker.record(state_hash(self.ctx, self.sys, &self.data), self.step_count);
+        }
+    }
+
+    /// Returns `None` for next state functions that were skipped by sparse evaluation.
+    fn eval_next_states(&mut self) -> Vec<Option<Value>> {
+        if self.eval_order != EvalOrder::Lazy && self.cache_stale {
+            self.update_cache();
+        }
+        let values = Values {
+            forced: &self.forced,
+            data: &self.data,
+            cache: (!self.cache_stale).then_some(&self.cache),
+            compiled: self
+                .program
+                .as_ref()
+                .filter(|_| !self.cache_stale)
+                .map(|p| (p, self.registers.as_slice())),
+        };
+        let mut out = Vec::with_capacity(self.next_functions.len());
+        for (ii, &(_, next)) in self.next_functions.iter().enumerate() {
+            if let Some(activity) = &mut self.activity {
+                if !activity.needs_eval(ii) {
+                    out.push(None);
+                    continue;
+                }
+            }
+            let value = match &mut self.perf {
+                None => eval_expr(self.ctx, &values, next),
+                Some(perf) => {
+                    let start = Instant::now();
+                    let value =
+                        eval_expr_observed(self.ctx, &values, next, |n| perf.record_eval(n));
+                    perf.record_next_state(next, start.elapsed());
+                    value
+                }
+            };
+            out.push(Some(value));
+        }
+        out
+    }
+}
+
+/// Looks up forced values first, then symbols and finally the values of the eager schedule.
+struct Values<'a> {
+    forced: &'a FxHashMap<ExprRef, BitVecValue>,
+    data: &'a SymbolValueStore,
+    cache: Option<&'a SymbolValueStore>,
+    /// registers after running the bytecode
+    compiled: Option<(&'a Program, &'a [BitVecValue])>,
+}
+
+impl GetExprValue for Values<'_> {
+    fn get_bv(&self, ctx: &Context, symbol: ExprRef) -> Option<BitVecValue> {
+        if let Some(value) = self.forced.get(&symbol) {
+            return Some(value.clone());
+        }
+        self.data
+            .get_bv(ctx, symbol)
+            .or_else(|| self.cache?.get_bv(ctx, symbol))
+            .or_else(|| {
+                let (program, registers) = self.compiled?;
+                program.get(registers, symbol)
+            })
+    }
+
+    fn get_array(&self, ctx: &Context, symbol: ExprRef) -> Option<ArrayValue> {
+        self.data
+            .get_array(ctx, symbol)
+            .or_else(|| self.cache?.get_array(ctx, symbol))
+    }
+}
+
+/// All expressions that feed into next states, outputs, defines, constraints or bad states,
+/// children first. Symbols and literals are left out since they are not worth caching.
+pub(super) fn schedule(ctx: &Context, sys: &TransitionSystem) -> Vec<ExprRef> {
+    let roots = sys
+        .states
+        .iter()
+        .flat_map(|s| s.next)
+        .chain(sys.outputs.iter().map(|o| o.expr))
+        .chain(sys.defines.iter().map(|d| d.expr))
+        .chain(sys.constraints.iter().copied())
+        .chain(sys.bad_states.iter().copied());
+    let mut visited = FxHashSet::default();
+    let mut order = vec![];
+    let mut todo = vec![];
+    for root in roots {
+        todo.push((root, false));
+        while let Some((e, children_done)) = todo.pop() {
+            if children_done {
+                order.push(e);
+                continue;
+            }
+            if ctx[e].num_children() == 0 || !visited.insert(e) {
+                continue;
+            }
+            todo.push((e, true));
+            ctx[e].for_each_child(|&c| todo.push((c, false)));
+        }
+    }
+    order
+}
+
+/// Inputs that appear in at least one init expression.
+fn init_inputs(ctx: &Context, sys: &TransitionSystem) -> FxHashSet<ExprRef> {
+    let inputs: FxHashSet<ExprRef> = sys.inputs.iter().copied().collect();
+    let mut visited = FxHashSet::default();
+    let mut todo: Vec<ExprRef> = sys.states.iter().flat_map(|s| s.init).collect();
+    let mut out = FxHashSet::default();
+    while let Some(e) = todo.pop() {
+        if !visited.insert(e) {
+            continue;
+        }
+        if inputs.contains(&e) {
+            out.insert(e);
+        }
+        ctx[e].for_each_child(|&c| todo.push(c));
+    }
+    out
 }
 
 fn init_signal(
@@ -60,10 +566,12 @@ fn init_signal(
 impl<'a> Simulator for Interpreter<'a> {
     type SnapshotId = u32;
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
     fn init(&mut self, kind: InitKind) {
         let mut gen = InitValueGenerator::from_kind(kind);
 
         self.data.clear();
+        self.cache_stale = true;
 
         // allocate space for inputs, and states
         for state in self.sys.states.iter() {
@@ -73,41 +581,106 @@ impl<'a> Simulator for Interpreter<'a> {
             init_signal(self.ctx, &mut self.data, symbol, &mut gen);
         }
 
-        // evaluate init expressions
-        for state in self.sys.states.iter() {
-            if let Some(init) = state.init {
-                let value = eval_expr(self.ctx, &self.data, init);
-                self.data.update(state.symbol, value);
-            }
+        // evaluate init expressions, inputs keep their value until the first step
+        self.eval_init();
+        self.before_first_step = true;
+        if let Some(tracker) = &mut self.revisits {
+            tracker.clear();
         }
+        self.record_visit();
+        self.update();
     }
 
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip_all, fields(step = self.step_count))
+    )]
     fn step(&mut self) {
+        let start = self.perf.as_ref().map(|_| Instant::now());
+
         // calculate all next states
-        let next_states = self
-            .sys
-            .states
-            .iter()
-            .map(|s| s.next.map(|n| eval_expr(self.ctx, &self.data, n)))
-            .collect::<Vec<_>>();
+        let next_states = self.eval_next_states();
 
         // assign next value to store
-        for (state, next_value) in self.sys.states.iter().zip(next_states.into_iter()) {
-            if let Some(value) = next_value {
-                self.data.update(state.symbol, value);
+        let mut changed = vec![];
+        for (&(symbol, _), value) in self.next_functions.iter().zip(next_states.into_iter()) {
+            if let Some(value) = value {
+                if self.activity.is_some() && !self.has_value(symbol, &value) {
+                    changed.push(symbol);
+                }
+                self.data.update(symbol, value);
             }
         }
+        if let Some(activity) = &mut self.activity {
+            activity.finish_step(changed.into_iter());
+        }
 
         // increment step cout
         self.step_count += 1;
+        self.before_first_step = false;
+        self.record_visit();
+        self.update();
+
+        if let (Some(perf), Some(start)) = (&mut self.perf, start) {
+            perf.record_step(start.elapsed());
+        }
     }
 
-    fn set<'b>(&mut self, expr: ExprRef, value: impl Into<BitVecValueRef<'b>>) {
+    fn set<'b>(
+        &mut self,
+        expr: ExprRef,
+        value: impl Into<BitVecValueRef<'b>>,
+    ) -> Result<(), SimError> {
+        let value = value.into();
+        let expected = match expr.get_bv_type(self.ctx) {
+            Some(width) if self.data.is_defined(expr) => width,
+            _ => return Err(SimError::UnknownSymbol(expr)),
+        };
+        if expected != value.width() {
+            return Err(SimError::WidthMismatch {
+                expected,
+                actual: value.width(),
+            });
+        }
         self.data.update_bv(expr, value);
+        if let Some(activity) = &mut self.activity {
+            activity.mark(expr);
+        }
+        if self.before_first_step && self.init_inputs.contains(&expr) {
+            self.eval_init();
+        }
+        self.invalidate();
+        Ok(())
+    }
+
+    fn update(&mut self) {
+        if self.eval_order != EvalOrder::Lazy {
+            self.update_cache();
+        }
+        if self.two_phase.is_some() {
+            let values = self.values();
+            let combinational: Vec<_> = self
+                .sys
+                .outputs
+                .iter()
+                .map(|o| o.expr)
+                .chain(self.sys.constraints.iter().copied())
+                .chain(self.sys.bad_states.iter().copied())
+                .filter(|&e| !self.ctx[e].is_symbol())
+                .map(|e| (e, eval_expr(self.ctx, &values, e)))
+                .collect();
+            self.two_phase
+                .as_mut()
+                .unwrap()
+                .store(combinational.into_iter());
+        }
     }
 
     fn get(&self, expr: ExprRef) -> Value {
-        eval_expr(self.ctx, &self.data, expr)
+        self.two_phase
+            .as_ref()
+            .and_then(|c| c.read(self.step_count, expr))
+            .unwrap_or_else(|| eval_expr(self.ctx, &self.values(), expr))
     }
 
     fn step_count(&self) -> u64 {
@@ -115,12 +688,17 @@ impl<'a> Simulator for Interpreter<'a> {
     }
 
     fn take_snapshot(&mut self) -> Self::SnapshotId {
-        let id = self.snapshots.len() as u32;
-        self.snapshots.push(self.data.clone());
-        id
+        self.snapshots.push(&self.data) as u32
     }
 
-    fn restore_snapshot(&mut self, id: Self::SnapshotId) {
-        self.data = self.snapshots[id as usize].clone();
+    fn restore_snapshot(&mut self, id: Self::SnapshotId) -> Result<(), SimError> {
+        self.data = self
+            .snapshots
+            .get(id as usize)
+            .ok_or(SimError::UnknownSnapshot(id))?;
+        self.before_first_step = false;
+        self.mark_all_changed();
+        self.update();
+        Ok(())
     }
 }
@@ -0,0 +1,302 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Lockstep Simulation
+//! Drives two simulators with the same inputs and compares signals after every input
+//! assignment, e.g., the interpreter against another backend, or a system against an optimized
+//! version of itself. Signals and inputs are matched by name.
+
+use super::stimulus::{lookup_bv_input, parse_value};
+use super::{SimError, Simulator, Stimulus, StimulusError};
+use crate::expr::{Context, ExprRef, TypeCheck, WidthInt};
+use crate::random::new_rng;
+use crate::system::TransitionSystem;
+use baa::{BitVecOps, BitVecValue, Value};
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, thiserror::Error)]
+pub enum LockstepError {
+    #[error("`{0}` is not a bit-vector signal of both systems")]
+    UnknownSignal(String),
+    #[error(transparent)]
+    Stimulus(#[from] StimulusError),
+    #[error(transparent)]
+    Sim(#[from] SimError),
+}
+
+type Result<T> = std::result::Result<T, LockstepError>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignalDiff {
+    pub signal: String,
+    pub a: BitVecValue,
+    pub b: BitVecValue,
+}
+
+/// All signals that differ in the first step with a mismatch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub step: usize,
+    /// input values applied in the step
+    pub inputs: Vec<(String, BitVecValue)>,
+    pub diffs: Vec<SignalDiff>,
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "mismatch in step {}", self.step)?;
+        for (name, value) in self.inputs.iter() {
+            writeln!(f, "  input  {name} = 0x{}", value.to_hex_str())?;
+        }
+        for diff in self.diffs.iter() {
+            writeln!(
+                f,
+                "  signal {}: 0x{} != 0x{}",
+                diff.signal,
+                diff.a.to_hex_str(),
+                diff.b.to_hex_str()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs two simulators in lockstep. Both need to be initialized with the same values.
+pub struct LockstepRunner<'a, A: Simulator, B: Simulator> {
+    ctx: &'a Context,
+    sys_a: &'a TransitionSystem,
+    sys_b: &'a TransitionSystem,
+    a: A,
+    b: B,
+    /// name, width and expression in each system
+    inputs: Vec<(String, WidthInt, ExprRef, Option<ExprRef>)>,
+    signals: Vec<(String, ExprRef, ExprRef)>,
+}
+
+impl<'a, A: Simulator, B: Simulator> LockstepRunner<'a, A, B> {
+    /// Compares all bit-vector outputs and states that exist in both systems. Inputs of `sys_a`
+    /// that were removed from `sys_b` are only applied to `a`.
+    pub fn new(
+        ctx: &'a Context,
+        sys_a: &'a TransitionSystem,
+        a: A,
+        sys_b: &'a TransitionSystem,
+        b: B,
+    ) -> Self {
+        let inputs = sys_a
+            .inputs
+            .iter()
+            .flat_map(|&i| {
+                let name = ctx.get_symbol_name(i).unwrap().to_string();
+                let width = i.get_bv_type(ctx)?;
+                let in_b = sys_b.lookup_input(ctx, &name);
+                Some((name, width, i, in_b))
+            })
+            .collect();
+        let names = sys_a.outputs.iter().map(|o| ctx[o.name].to_string()).chain(
+            sys_a
+                .states
+                .iter()
+                .map(|s| ctx.get_symbol_name(s.symbol).unwrap().to_string()),
+        );
+        let signals = names
+            .flat_map(|name| {
+                let (in_a, in_b) = lookup_pair(ctx, sys_a, sys_b, &name)?;
+                Some((name, in_a, in_b))
+            })
+            .collect();
+        Self {
+            ctx,
+            sys_a,
+            sys_b,
+            a,
+            b,
+            inputs,
+            signals,
+        }
+    }
+
+    /// Only compares the signals with the given names.
+    pub fn with_signals(mut self, names: &[&str]) -> Result<Self> {
+        self.signals = names
+            .iter()
+            .map(|&name| {
+                lookup_pair(self.ctx, self.sys_a, self.sys_b, name)
+                    .map(|(a, b)| (name.to_string(), a, b))
+                    .ok_or_else(|| LockstepError::UnknownSignal(name.to_string()))
+            })
+            .collect::<Result<_>>()?;
+        Ok(self)
+    }
+
+    /// Plays back `stimulus` and returns the first mismatch.
+    pub fn run(&mut self, stimulus: &Stimulus) -> Result<Option<Mismatch>> {
+        let widths = stimulus
+            .signals
+            .iter()
+            .map(|name| Ok(lookup_bv_input(self.ctx, self.sys_a, name)?.1))
+            .collect::<Result<Vec<_>>>()?;
+        for (step, values) in stimulus.steps.iter().enumerate() {
+            let mut inputs = vec![];
+            for ((name, &width), value) in stimulus
+                .signals
+                .iter()
+                .zip(widths.iter())
+                .zip(values.iter())
+            {
+                let Some(value) = value else {
+                    continue;
+                };
+                let parsed =
+                    parse_value(value, width).ok_or_else(|| StimulusError::InvalidValue {
+                        step,
+                        signal: name.clone(),
+                        value: value.clone(),
+                    })?;
+                inputs.push((name.clone(), parsed));
+            }
+            if let Some(mismatch) = self.step(step, inputs)? {
+                return Ok(Some(mismatch));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Applies `steps` random input assignments and returns the first mismatch.
+    pub fn run_random(&mut self, steps: usize, seed: u64) -> Result<Option<Mismatch>> {
+        let mut rng = new_rng(seed);
+        for step in 0..steps {
+            let inputs = self
+                .inputs
+                .iter()
+                .map(|(name, width, _, _)| (name.clone(), BitVecValue::random(&mut rng, *width)))
+                .collect();
+            if let Some(mismatch) = self.step(step, inputs)? {
+                return Ok(Some(mismatch));
+            }
+        }
+        Ok(None)
+    }
+
+    fn step(
+        &mut self,
+        step: usize,
+        inputs: Vec<(String, BitVecValue)>,
+    ) -> Result<Option<Mismatch>> {
+        for (name, value) in inputs.iter() {
+            let (_, _, in_a, in_b) = self.inputs.iter().find(|(n, ..)| n == name).unwrap();
+            self.a.set(*in_a, value)?;
+            if let Some(in_b) = in_b {
+                self.b.set(*in_b, value)?;
+            }
+        }
+        let diffs: Vec<_> = self
+            .signals
+            .iter()
+            .flat_map(
+                |(name, in_a, in_b)| match (self.a.get(*in_a), self.b.get(*in_b)) {
+                    (Value::BitVec(a), Value::BitVec(b)) if a != b => Some(SignalDiff {
+                        signal: name.clone(),
+                        a,
+                        b,
+                    }),
+                    _ => None,
+                },
+            )
+            .collect();
+        if !diffs.is_empty() {
+            return Ok(Some(Mismatch {
+                step,
+                inputs,
+                diffs,
+            }));
+        }
+        self.a.step();
+        self.b.step();
+        Ok(None)
+    }
+
+    pub fn into_simulators(self) -> (A, B) {
+        (self.a, self.b)
+    }
+}
+
+fn lookup_signal(ctx: &Context, sys: &TransitionSystem, name: &str) -> Option<ExprRef> {
+    sys.lookup_output(ctx, name)
+        .or_else(|| sys.get_state_by_name(ctx, name).map(|s| s.symbol))
+        .or_else(|| sys.lookup_input(ctx, name))
+}
+
+fn lookup_pair(
+    ctx: &Context,
+    sys_a: &TransitionSystem,
+    sys_b: &TransitionSystem,
+    name: &str,
+) -> Option<(ExprRef, ExprRef)> {
+    let a = lookup_signal(ctx, sys_a, name)?;
+    let b = lookup_signal(ctx, sys_b, name)?;
+    (a.get_type(ctx).is_bit_vector() && b.get_type(ctx).is_bit_vector()).then_some((a, b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::examples::alu;
+    use crate::expr::simple_transform_expr;
+    use crate::sim::{InitKind, Interpreter};
+    use crate::system::transform::simplify_expressions;
+
+    fn zero_init<'a>(ctx: &'a Context, sys: &TransitionSystem) -> Interpreter<'a> {
+        let mut sim = Interpreter::new(ctx, sys);
+        sim.init(InitKind::Zero);
+        sim
+    }
+
+    #[test]
+    fn test_simplified_alu_matches() {
+        let (mut ctx, sys) = alu(8);
+        let mut simplified = sys.clone();
+        simplify_expressions(&mut ctx, &mut simplified);
+        let (a, b) = (zero_init(&ctx, &sys), zero_init(&ctx, &simplified));
+        let mut runner = LockstepRunner::new(&ctx, &sys, a, &simplified, b);
+        assert_eq!(runner.run_random(100, 0).unwrap(), None);
+    }
+
+    #[test]
+    fn test_mismatch() {
+        let (mut ctx, sys) = alu(8);
+        // swap the `a` and `b` inputs in the second system
+        let mut swapped = sys.clone();
+        let (a, b) = (sys.inputs[0], sys.inputs[1]);
+        swapped.outputs[0].expr =
+            simple_transform_expr(&mut ctx, sys.outputs[0].expr, |_, e, _| {
+                if e == a {
+                    Some(b)
+                } else if e == b {
+                    Some(a)
+                } else {
+                    None
+                }
+            });
+
+        let (sim_a, sim_b) = (zero_init(&ctx, &sys), zero_init(&ctx, &swapped));
+        assert!(matches!(
+            LockstepRunner::new(&ctx, &sys, sim_a, &swapped, sim_b).with_signals(&["missing"]),
+            Err(LockstepError::UnknownSignal(_))
+        ));
+
+        let (sim_a, sim_b) = (zero_init(&ctx, &sys), zero_init(&ctx, &swapped));
+        let mut runner = LockstepRunner::new(&ctx, &sys, sim_a, &swapped, sim_b)
+            .with_signals(&["out"])
+            .unwrap();
+        let stimulus = Stimulus::from_csv("a,b,op\n1,1,1\n3,1,0\n3,1,1\n").unwrap();
+        let mismatch = runner.run(&stimulus).unwrap().unwrap();
+        // addition is commutative, subtraction is not
+        assert_eq!(mismatch.step, 2);
+        assert_eq!(mismatch.diffs.len(), 1);
+        assert_eq!(mismatch.diffs[0].a.to_u64().unwrap(), 2);
+        assert_eq!(mismatch.diffs[0].b.to_u64().unwrap(), 0xfe);
+        assert!(mismatch.to_string().contains("signal out"));
+    }
+}
@@ -0,0 +1,312 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Memory Traces
+//! Records the contents of all array states of a simulation. Only the initial contents and the
+//! words that change from one step to the next are stored, which keeps traces of large memories
+//! small. The trace can be exported as JSON or queried for the value of `mem[addr]` at any
+//! recorded step.
+
+use super::Simulator;
+use crate::expr::{Context, ExprRef, TypeCheck, WidthInt};
+use crate::system::TransitionSystem;
+use baa::{ArrayOps, ArrayValue, BitVecOps, BitVecValue, SparseArrayValue, Value};
+use serde::Serialize;
+
+/// Contents of a single memory over time.
+#[derive(Debug, Clone)]
+pub struct MemoryWaves {
+    pub name: String,
+    pub index_width: WidthInt,
+    pub data_width: WidthInt,
+    /// contents at the first recorded step
+    pub initial: ArrayValue,
+    /// words written between step `t - 1` and step `t`, only for steps at which at least one
+    /// word changed, in ascending order
+    pub changes: Vec<(usize, Vec<(BitVecValue, BitVecValue)>)>,
+    /// number of recorded steps
+    pub len: usize,
+}
+
+impl MemoryWaves {
+    /// Value of `mem[addr]` at `step`. Returns `None` if the step was not recorded.
+    pub fn read(&self, addr: &BitVecValue, step: usize) -> Option<BitVecValue> {
+        if step >= self.len {
+            return None;
+        }
+        let until = self.changes.partition_point(|(s, _)| *s <= step);
+        let latest_write = self.changes[..until]
+            .iter()
+            .rev()
+            .flat_map(|(_, words)| words.iter().find(|(a, _)| a.is_equal(addr)))
+            .next();
+        match latest_write {
+            Some((_, data)) => Some(data.clone()),
+            None => Some(self.initial.select(addr)),
+        }
+    }
+
+    /// Words that changed between `step - 1` and `step`.
+    pub fn changes_at(&self, step: usize) -> &[(BitVecValue, BitVecValue)] {
+        match self.changes.binary_search_by_key(&step, |(s, _)| *s) {
+            Ok(ii) => self.changes[ii].1.as_slice(),
+            Err(_) => &[],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MemoryTrace {
+    pub memories: Vec<MemoryWaves>,
+}
+
+#[derive(Serialize)]
+struct RawMemory<'a> {
+    name: &'a str,
+    index_width: WidthInt,
+    data_width: WidthInt,
+    default: String,
+    initial: Vec<RawWord>,
+    changes: Vec<RawChange>,
+}
+
+#[derive(Serialize)]
+struct RawWord {
+    addr: String,
+    data: String,
+}
+
+#[derive(Serialize)]
+struct RawChange {
+    step: usize,
+    addr: String,
+    data: String,
+}
+
+fn hex(value: &BitVecValue) -> String {
+    format!("0x{}", value.to_hex_str())
+}
+
+impl MemoryTrace {
+    pub fn get(&self, name: &str) -> Option<&MemoryWaves> {
+        self.memories.iter().find(|m| m.name == name)
+    }
+
+    /// Value of `name[addr]` at `step`. Returns `None` if there is no memory called `name` or
+    /// the step was not recorded.
+    pub fn read(&self, name: &str, addr: &BitVecValue, step: usize) -> Option<BitVecValue> {
+        self.get(name)?.read(addr, step)
+    }
+
+    /// Number of recorded steps.
+    pub fn len(&self) -> usize {
+        self.memories.first().map_or(0, |m| m.len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn to_json(&self) -> String {
+        let raw: Vec<RawMemory> = self
+            .memories
+            .iter()
+            .map(|m| {
+                let initial: SparseArrayValue = (&m.initial).into();
+                RawMemory {
+                    name: &m.name,
+                    index_width: m.index_width,
+                    data_width: m.data_width,
+                    default: hex(&initial.default()),
+                    initial: initial
+                        .non_default_entries()
+                        .map(|(addr, data)| RawWord {
+                            addr: hex(&addr),
+                            data: hex(&data),
+                        })
+                        .collect(),
+                    changes: m
+                        .changes
+                        .iter()
+                        .flat_map(|(step, words)| {
+                            let step = *step;
+                            words.iter().map(move |(addr, data)| RawChange {
+                                step,
+                                addr: hex(addr),
+                                data: hex(data),
+                            })
+                        })
+                        .collect(),
+                }
+            })
+            .collect();
+        serde_json::to_string_pretty(&raw).expect("memory traces can always be serialized")
+    }
+}
+
+/// Records the contents of all array states of a system.
+pub struct MemoryRecorder {
+    states: Vec<ExprRef>,
+    /// contents at the previous step
+    prev: Vec<ArrayValue>,
+    trace: MemoryTrace,
+}
+
+impl MemoryRecorder {
+    pub fn new(ctx: &Context, sys: &TransitionSystem) -> Self {
+        let states: Vec<ExprRef> = sys
+            .states
+            .iter()
+            .map(|s| s.symbol)
+            .filter(|s| s.get_type(ctx).is_array())
+            .collect();
+        let memories = states
+            .iter()
+            .map(|&s| {
+                let tpe = s.get_array_type(ctx).unwrap();
+                MemoryWaves {
+                    name: ctx.get_symbol_name(s).unwrap().to_string(),
+                    index_width: tpe.index_width,
+                    data_width: tpe.data_width,
+                    initial: ArrayValue::new_sparse(
+                        tpe.index_width,
+                        &BitVecValue::zero(tpe.data_width),
+                    ),
+                    changes: vec![],
+                    len: 0,
+                }
+            })
+            .collect();
+        Self {
+            states,
+            prev: vec![],
+            trace: MemoryTrace { memories },
+        }
+    }
+
+    /// Records the current memory contents as the next step. Call this before stepping the
+    /// simulator.
+    pub fn record(&mut self, sim: &impl Simulator) {
+        let current: Vec<ArrayValue> = self
+            .states
+            .iter()
+            .map(|&s| match sim.get(s) {
+                Value::Array(value) => value,
+                Value::BitVec(_) => unreachable!("{s:?} is an array state"),
+            })
+            .collect();
+        if self.prev.is_empty() {
+            for (m, value) in self.trace.memories.iter_mut().zip(current.iter()) {
+                m.initial = value.clone();
+                m.len = 1;
+            }
+        } else {
+            for ((m, prev), value) in self
+                .trace
+                .memories
+                .iter_mut()
+                .zip(self.prev.iter())
+                .zip(current.iter())
+            {
+                let words = changed_words(prev, value);
+                if !words.is_empty() {
+                    m.changes.push((m.len, words));
+                }
+                m.len += 1;
+            }
+        }
+        self.prev = current;
+    }
+
+    pub fn finish(self) -> MemoryTrace {
+        self.trace
+    }
+}
+
+/// Words that differ between `prev` and `value`, sorted by address.
+fn changed_words(prev: &ArrayValue, value: &ArrayValue) -> Vec<(BitVecValue, BitVecValue)> {
+    let (sparse_prev, sparse_value): (SparseArrayValue, SparseArrayValue) =
+        (prev.into(), value.into());
+    let mut addrs: Vec<BitVecValue> = if sparse_prev.default().is_equal(&sparse_value.default()) {
+        sparse_prev
+            .non_default_entries()
+            .chain(sparse_value.non_default_entries())
+            .map(|(addr, _)| addr)
+            .collect()
+    } else {
+        // every word might have changed
+        (0..value.num_elements())
+            .map(|ii| BitVecValue::from_u64(ii as u64, value.index_width()))
+            .collect()
+    };
+    addrs.sort_by(|a, b| {
+        if a.is_equal(b) {
+            std::cmp::Ordering::Equal
+        } else if a.is_greater(b) {
+            std::cmp::Ordering::Greater
+        } else {
+            std::cmp::Ordering::Less
+        }
+    });
+    addrs.dedup_by(|a, b| a.is_equal(b));
+    addrs
+        .into_iter()
+        .flat_map(|addr| {
+            let data = value.select(&addr);
+            if data.is_equal(&prev.select(&addr)) {
+                None
+            } else {
+                Some((addr, data))
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::examples::fifo;
+    use crate::sim::{InitKind, Interpreter};
+
+    #[test]
+    fn test_record_fifo_memory() {
+        let (ctx, sys) = fifo(4, 8);
+        let push = sys.lookup_input(&ctx, "push").unwrap();
+        let data_in = sys.lookup_input(&ctx, "data_in").unwrap();
+        let mut sim = Interpreter::new(&ctx, &sys);
+        sim.init(InitKind::Zero);
+        let mut recorder = MemoryRecorder::new(&ctx, &sys);
+        sim.set(push, &BitVecValue::from_u64(1, 1)).unwrap();
+        for data in [10u64, 11, 12] {
+            sim.set(data_in, &BitVecValue::from_u64(data, 8)).unwrap();
+            recorder.record(&sim);
+            sim.step();
+        }
+        recorder.record(&sim);
+        let trace = recorder.finish();
+        assert_eq!(trace.len(), 4);
+
+        let addr = |a: u64| BitVecValue::from_u64(a, 2);
+        let read = |a: u64, step: usize| trace.read("mem", &addr(a), step).unwrap().to_u64();
+        assert_eq!(read(1, 1), Some(0));
+        assert_eq!(read(1, 2), Some(11));
+        assert_eq!(read(1, 3), Some(11));
+        assert_eq!(read(2, 3), Some(12));
+        assert_eq!(trace.read("mem", &addr(0), 4), None);
+        assert_eq!(trace.read("foo", &addr(0), 0), None);
+
+        let mem = trace.get("mem").unwrap();
+        assert!(mem.changes_at(0).is_empty());
+        assert_eq!(mem.changes_at(2).len(), 1);
+        assert!(mem.changes_at(2)[0].0.is_equal(&addr(1)));
+
+        let json: serde_json::Value = serde_json::from_str(&trace.to_json()).unwrap();
+        assert_eq!(json[0]["name"], "mem");
+        assert_eq!(json[0]["changes"].as_array().unwrap().len(), 3);
+        // steps without writes are not stored
+        assert_eq!(mem.changes.len(), 3);
+        assert_eq!(json[0]["changes"][1]["step"], 2);
+        assert_eq!(json[0]["changes"][1]["data"], "0x0b");
+    }
+}
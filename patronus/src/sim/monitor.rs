@@ -0,0 +1,214 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Monitors
+//! Keeps a history of watched signals across steps in order to detect edges and other events
+//! without having to manually store previous values.
+
+use super::{InitKind, SimError, Simulator};
+use crate::expr::ExprRef;
+use baa::{BitVecOps, BitVecValue, BitVecValueRef, Value};
+use std::collections::VecDeque;
+
+/// Wraps a simulator and records the value of all watched signals right before every step.
+/// Like in SVA, edges of multi-bit signals are detected on the least significant bit.
+pub struct Monitored<S: Simulator> {
+    sim: S,
+    watched: Vec<Watched>,
+}
+
+struct Watched {
+    expr: ExprRef,
+    depth: usize,
+    /// most recent value first
+    history: VecDeque<BitVecValue>,
+}
+
+impl<S: Simulator> Monitored<S> {
+    pub fn new(sim: S) -> Self {
+        Self {
+            sim,
+            watched: vec![],
+        }
+    }
+
+    /// Starts recording `expr`, keeping its values from the last `depth` steps.
+    /// Watching an already watched signal increases its depth if necessary.
+    pub fn watch(&mut self, expr: ExprRef, depth: usize) {
+        match self.watched.iter_mut().find(|w| w.expr == expr) {
+            Some(w) => w.depth = w.depth.max(depth),
+            None => self.watched.push(Watched {
+                expr,
+                depth,
+                history: VecDeque::with_capacity(depth),
+            }),
+        }
+    }
+
+    /// Value of `expr` `cycles` steps ago. Returns `None` if not enough steps were recorded.
+    pub fn past(&self, expr: ExprRef, cycles: usize) -> Option<&BitVecValue> {
+        if cycles == 0 {
+            return None;
+        }
+        let w = self.get_watched(expr);
+        assert!(
+            cycles <= w.depth,
+            "only the last {} values of {expr:?} are recorded",
+            w.depth
+        );
+        w.history.get(cycles - 1)
+    }
+
+    /// The least significant bit changed from zero to one.
+    pub fn rose(&self, expr: ExprRef) -> bool {
+        match self.past(expr, 1) {
+            Some(prev) => !prev.is_bit_set(0) && self.current(expr).is_bit_set(0),
+            None => false,
+        }
+    }
+
+    /// The least significant bit changed from one to zero.
+    pub fn fell(&self, expr: ExprRef) -> bool {
+        match self.past(expr, 1) {
+            Some(prev) => prev.is_bit_set(0) && !self.current(expr).is_bit_set(0),
+            None => false,
+        }
+    }
+
+    /// The value is different from the previous step.
+    pub fn changed(&self, expr: ExprRef) -> bool {
+        self.past(expr, 1)
+            .is_some_and(|prev| *prev != self.current(expr))
+    }
+
+    /// The value has not changed in the last `cycles` steps.
+    pub fn stable(&self, expr: ExprRef, cycles: usize) -> bool {
+        let current = self.current(expr);
+        (1..=cycles).all(|c| self.past(expr, c).is_some_and(|v| *v == current))
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.sim
+    }
+
+    pub fn into_inner(self) -> S {
+        self.sim
+    }
+
+    fn current(&self, expr: ExprRef) -> BitVecValue {
+        match self.sim.get(expr) {
+            Value::BitVec(value) => value,
+            Value::Array(_) => panic!("cannot monitor array {expr:?}"),
+        }
+    }
+
+    fn get_watched(&self, expr: ExprRef) -> &Watched {
+        self.watched
+            .iter()
+            .find(|w| w.expr == expr)
+            .unwrap_or_else(|| panic!("{expr:?} is not watched"))
+    }
+
+    fn clear_history(&mut self) {
+        for w in self.watched.iter_mut() {
+            w.history.clear();
+        }
+    }
+}
+
+impl<S: Simulator> Simulator for Monitored<S> {
+    type SnapshotId = S::SnapshotId;
+
+    fn init(&mut self, kind: InitKind) {
+        self.sim.init(kind);
+        self.clear_history();
+    }
+
+    fn step(&mut self) {
+        for ii in 0..self.watched.len() {
+            let value = self.current(self.watched[ii].expr);
+            let w = &mut self.watched[ii];
+            if w.history.len() == w.depth {
+                w.history.pop_back();
+            }
+            if w.depth > 0 {
+                w.history.push_front(value);
+            }
+        }
+        self.sim.step();
+    }
+
+    fn set<'a>(
+        &mut self,
+        expr: ExprRef,
+        value: impl Into<BitVecValueRef<'a>>,
+    ) -> Result<(), SimError> {
+        self.sim.set(expr, value)
+    }
+
+    fn update(&mut self) {
+        self.sim.update();
+    }
+
+    fn get(&self, expr: ExprRef) -> Value {
+        self.sim.get(expr)
+    }
+
+    fn step_count(&self) -> u64 {
+        self.sim.step_count()
+    }
+
+    fn take_snapshot(&mut self) -> Self::SnapshotId {
+        self.sim.take_snapshot()
+    }
+
+    /// The history is not part of the snapshot and is thus cleared.
+    fn restore_snapshot(&mut self, id: Self::SnapshotId) -> Result<(), SimError> {
+        self.sim.restore_snapshot(id)?;
+        self.clear_history();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::Context;
+    use crate::sim::Interpreter;
+    use crate::system::TransitionSystem;
+
+    #[test]
+    fn test_edges_and_stable() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let req = ctx.bv_symbol("req", 1);
+        let data = ctx.bv_symbol("data", 8);
+        sys.add_input(&ctx, req);
+        sys.add_input(&ctx, data);
+
+        let mut sim = Monitored::new(Interpreter::new(&ctx, &sys));
+        sim.init(InitKind::Zero);
+        sim.watch(req, 1);
+        sim.watch(data, 2);
+
+        let mut events = vec![];
+        for (r, d) in [(0u64, 1u64), (1, 1), (1, 1), (0, 2)] {
+            sim.set(req, &BitVecValue::from_u64(r, 1)).unwrap();
+            sim.set(data, &BitVecValue::from_u64(d, 8)).unwrap();
+            events.push((sim.rose(req), sim.fell(req), sim.stable(data, 2)));
+            sim.step();
+        }
+        assert_eq!(
+            events,
+            [
+                (false, false, false),
+                (true, false, false),
+                (false, false, true),
+                (false, true, false),
+            ]
+        );
+        assert!(!sim.changed(data));
+        assert_eq!(sim.past(data, 1).unwrap().to_u64().unwrap(), 2);
+    }
+}
@@ -0,0 +1,257 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Offline Property Checking
+//! Evaluates the bad states and constraints of a system on every step of recorded [`Waves`],
+//! e.g., traces from silicon, an emulator or another simulator. Recorded signals are bound to
+//! inputs and states by name. States that were not recorded, like the monitor states created
+//! by [`add_property`], are computed from their init and next state functions, as long as
+//! those only depend on values that are known. Checks that depend on signals which are neither
+//! recorded nor computable are reported as unchecked.
+
+use super::Waves;
+use crate::expr::traversal::{top_down, TraversalCmd};
+use crate::expr::{eval_expr, Context, ExprRef};
+use crate::system::{add_property, Property, PropertyError, State, TransitionSystem};
+use baa::{BitVecOps, BitVecValue, Value};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ViolationKind {
+    /// the bad state with this index fired
+    BadState(usize),
+    /// the constraint with this index was false, i.e., the trace left the assumed environment
+    Constraint(usize),
+    /// a temporal property added with [`OfflineChecker::add_property`] was violated
+    Property(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub step: u64,
+    /// timestamp of the step in the recorded waveform, if known
+    pub time: Option<u64>,
+    pub kind: ViolationKind,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OfflineReport {
+    pub steps: u64,
+    /// in the order in which they occurred
+    pub violations: Vec<Violation>,
+    /// checks that depend on signals that were neither recorded nor computable
+    pub unchecked: Vec<ViolationKind>,
+}
+
+impl OfflineReport {
+    pub fn is_clean(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    /// First step at which `kind` was violated.
+    pub fn first(&self, kind: &ViolationKind) -> Option<&Violation> {
+        self.violations.iter().find(|v| v.kind == *kind)
+    }
+}
+
+/// Checks a system, optionally extended with temporal properties, against recorded traces.
+#[derive(Debug, Clone)]
+pub struct OfflineChecker {
+    sys: TransitionSystem,
+    /// names of the temporal properties by the index of their bad state
+    properties: FxHashMap<usize, String>,
+}
+
+impl OfflineChecker {
+    pub fn new(sys: &TransitionSystem) -> Self {
+        Self {
+            sys: sys.clone(),
+            properties: FxHashMap::default(),
+        }
+    }
+
+    /// Compiles `property` into monitor states that are computed alongside the trace.
+    pub fn add_property(
+        &mut self,
+        ctx: &mut Context,
+        name: &str,
+        property: &Property,
+    ) -> Result<(), PropertyError> {
+        add_property(ctx, &mut self.sys, name, property)?;
+        self.properties
+            .insert(self.sys.bad_states.len() - 1, name.to_string());
+        Ok(())
+    }
+
+    /// Evaluates all checks on every recorded step. `times` optionally contains the timestamp
+    /// of every step.
+    pub fn check(&self, ctx: &Context, waves: &Waves, times: Option<&[u64]>) -> OfflineReport {
+        let symbols: FxHashMap<&str, ExprRef> = self
+            .sys
+            .inputs
+            .iter()
+            .copied()
+            .chain(self.sys.states.iter().map(|s| s.symbol))
+            .map(|e| (ctx.get_symbol_name(e).unwrap(), e))
+            .collect();
+        // index of the recorded signal and the symbol it is bound to
+        let recorded: Vec<(usize, ExprRef)> = waves
+            .signals
+            .iter()
+            .enumerate()
+            .flat_map(|(ii, s)| Some((ii, *symbols.get(s.name.as_str())?)))
+            .collect();
+        let recorded_symbols: FxHashSet<ExprRef> = recorded.iter().map(|(_, e)| *e).collect();
+        let computed = self.computable_states(ctx, &recorded_symbols);
+        let mut known = recorded_symbols;
+        known.extend(computed.iter().map(|s| s.symbol));
+
+        let mut report = OfflineReport {
+            steps: waves.len(),
+            ..Default::default()
+        };
+        let mut checks: Vec<(ExprRef, ViolationKind, bool)> = vec![];
+        for (ii, &bad) in self.sys.bad_states.iter().enumerate() {
+            let kind = match self.properties.get(&ii) {
+                Some(name) => ViolationKind::Property(name.clone()),
+                None => ViolationKind::BadState(ii),
+            };
+            checks.push((bad, kind, true));
+        }
+        for (ii, &constraint) in self.sys.constraints.iter().enumerate() {
+            checks.push((constraint, ViolationKind::Constraint(ii), false));
+        }
+        checks.retain(|(e, kind, _)| {
+            let checkable = depends_only_on(ctx, *e, &known);
+            if !checkable {
+                report.unchecked.push(kind.clone());
+            }
+            checkable
+        });
+
+        let mut computed_values: Vec<(ExprRef, BitVecValue)> = vec![];
+        for (step, values) in waves.iter_from(0).enumerate() {
+            let mut current: Vec<(ExprRef, BitVecValue)> = recorded
+                .iter()
+                .map(|&(ii, e)| (e, values[ii].clone()))
+                .collect();
+            if step == 0 {
+                computed_values = computed
+                    .iter()
+                    .map(|s| (s.symbol, eval_bv(ctx, &current, s.init.unwrap())))
+                    .collect();
+            }
+            current.append(&mut computed_values);
+            for (e, kind, fires_when_true) in checks.iter() {
+                if eval_bv(ctx, &current, *e).is_zero() != *fires_when_true {
+                    report.violations.push(Violation {
+                        step: step as u64,
+                        time: times.and_then(|t| t.get(step).copied()),
+                        kind: kind.clone(),
+                    });
+                }
+            }
+            computed_values = computed
+                .iter()
+                .map(|s| (s.symbol, eval_bv(ctx, &current, s.next.unwrap())))
+                .collect();
+        }
+        report
+    }
+
+    /// States that were not recorded, but whose init only depends on recorded signals and
+    /// whose next state only depends on recorded or computable signals.
+    fn computable_states(&self, ctx: &Context, recorded: &FxHashSet<ExprRef>) -> Vec<&State> {
+        let candidates: Vec<&State> = self
+            .sys
+            .states
+            .iter()
+            .filter(|s| !recorded.contains(&s.symbol))
+            .filter(|s| {
+                s.init
+                    .is_some_and(|init| depends_only_on(ctx, init, recorded))
+            })
+            .filter(|s| s.next.is_some())
+            .collect();
+        // remove states that depend on states which cannot be computed until nothing changes
+        let mut computable = candidates;
+        loop {
+            let mut known = recorded.clone();
+            known.extend(computable.iter().map(|s| s.symbol));
+            let before = computable.len();
+            computable.retain(|s| depends_only_on(ctx, s.next.unwrap(), &known));
+            if computable.len() == before {
+                return computable;
+            }
+        }
+    }
+}
+
+fn depends_only_on(ctx: &Context, e: ExprRef, known: &FxHashSet<ExprRef>) -> bool {
+    let mut only_known = true;
+    top_down(ctx, e, |ctx, e| {
+        if ctx[e].is_symbol() && !known.contains(&e) {
+            only_known = false;
+        }
+        TraversalCmd::Continue
+    });
+    only_known
+}
+
+fn eval_bv(ctx: &Context, values: &[(ExprRef, BitVecValue)], e: ExprRef) -> BitVecValue {
+    match eval_expr(ctx, values, e) {
+        Value::BitVec(value) => value,
+        Value::Array(_) => unreachable!("checks and computed states are bit-vectors"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::{InitKind, Interpreter, Simulator, WaveRecorder};
+
+    #[test]
+    fn test_check_recorded_handshake() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("handshake".to_string());
+        let req = ctx.bv_symbol("req", 1);
+        let ack = ctx.bv_symbol("ack", 1);
+        sys.add_input(&ctx, req);
+        sys.add_input(&ctx, ack);
+        // the environment never acknowledges without a request
+        let ack_needs_req = ctx.implies(ack, req);
+        sys.constraints.push(ack_needs_req);
+
+        // record a trace in which the second request is never acknowledged
+        let mut sim = Interpreter::new(&ctx, &sys);
+        sim.init(InitKind::Zero);
+        let mut recorder = WaveRecorder::for_system(&ctx, &sys);
+        let bit = |v| BitVecValue::from_u64(v, 1);
+        for (r, a) in [(1, 0), (0, 1), (0, 0), (1, 0), (0, 0), (0, 0), (0, 0)] {
+            sim.set(req, &bit(r)).unwrap();
+            sim.set(ack, &bit(a)).unwrap();
+            recorder.record(&sim);
+            sim.step();
+        }
+        let waves = recorder.finish();
+
+        let mut checker = OfflineChecker::new(&sys);
+        // every request is acknowledged within two steps
+        let req_ack = Property::globally(Property::implies(
+            Property::atom(req),
+            Property::next(Property::eventually(1, Property::atom(ack))),
+        ));
+        checker.add_property(&mut ctx, "req_ack", &req_ack).unwrap();
+        let times: Vec<u64> = (0..waves.len()).map(|s| s * 10).collect();
+        let report = checker.check(&ctx, &waves, Some(times.as_slice()));
+        let kind = ViolationKind::Property("req_ack".to_string());
+        let first = report.first(&kind).unwrap();
+        assert_eq!(first.time, Some(first.step * 10));
+        assert!(first.step > 3);
+        // the ack in step 1 comes without a request in the same step
+        assert_eq!(report.first(&ViolationKind::Constraint(0)).unwrap().step, 1);
+        assert!(report.unchecked.is_empty());
+        assert_eq!(report.steps, 7);
+    }
+}
@@ -0,0 +1,227 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Overflow Checks
+//! Bit-vector arithmetic wraps around silently. The [`OverflowChecker`] re-computes additions,
+//! subtractions and multiplications at twice their width while simulating and reports every
+//! operation whose result would have been different without wrapping.
+
+use super::Simulator;
+use crate::expr::{Context, Expr, ExprRef, ForEachChild, SerializableIrNode};
+use crate::system::TransitionSystem;
+use baa::{BitVecOps, BitVecValue, Value};
+use rustc_hash::FxHashSet;
+use std::fmt::{Display, Formatter};
+
+/// How operands and results are interpreted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Signedness {
+    #[default]
+    Unsigned,
+    Signed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ArithOp {
+    Add,
+    Sub,
+    Mul,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OverflowOptions {
+    pub signedness: Signedness,
+    /// operations that are checked
+    pub ops: Vec<ArithOp>,
+}
+
+impl Default for OverflowOptions {
+    fn default() -> Self {
+        Self {
+            signedness: Signedness::default(),
+            ops: vec![ArithOp::Add, ArithOp::Sub, ArithOp::Mul],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OverflowEvent {
+    pub step: u64,
+    pub expr: ExprRef,
+    pub op: ArithOp,
+    pub a: BitVecValue,
+    pub b: BitVecValue,
+    /// the wrapped result that the design actually computes
+    pub result: BitVecValue,
+}
+
+impl OverflowEvent {
+    /// Human readable description that includes the offending expression.
+    pub fn describe(&self, ctx: &Context) -> String {
+        format!("{self}: {}", self.expr.serialize_to_str(ctx))
+    }
+}
+
+impl Display for OverflowEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "step {}: {:?} of 0x{} and 0x{} wrapped to 0x{}",
+            self.step,
+            self.op,
+            self.a.to_hex_str(),
+            self.b.to_hex_str(),
+            self.result.to_hex_str()
+        )
+    }
+}
+
+/// Watches all arithmetic operations that feed into outputs, next states, constraints and
+/// bad states. Operations in `ite` branches that are not selected are checked as well.
+pub struct OverflowChecker {
+    signedness: Signedness,
+    watched: Vec<(ExprRef, ArithOp, ExprRef, ExprRef)>,
+    events: Vec<OverflowEvent>,
+}
+
+impl OverflowChecker {
+    pub fn new(ctx: &Context, sys: &TransitionSystem, opts: &OverflowOptions) -> Self {
+        let roots = sys
+            .outputs
+            .iter()
+            .map(|o| o.expr)
+            .chain(sys.states.iter().flat_map(|s| s.next))
+            .chain(sys.constraints.iter().copied())
+            .chain(sys.bad_states.iter().copied());
+        let mut visited = FxHashSet::default();
+        let mut todo: Vec<ExprRef> = roots.collect();
+        let mut watched = vec![];
+        while let Some(e) = todo.pop() {
+            if !visited.insert(e) {
+                continue;
+            }
+            let expr = &ctx[e];
+            let op = match *expr {
+                Expr::BVAdd(a, b, _) => Some((ArithOp::Add, a, b)),
+                Expr::BVSub(a, b, _) => Some((ArithOp::Sub, a, b)),
+                Expr::BVMul(a, b, _) => Some((ArithOp::Mul, a, b)),
+                _ => None,
+            };
+            if let Some((op, a, b)) = op {
+                if opts.ops.contains(&op) {
+                    watched.push((e, op, a, b));
+                }
+            }
+            expr.for_each_child(|&c| todo.push(c));
+        }
+        // deterministic report order
+        watched.sort_by_key(|(e, ..)| *e);
+        Self {
+            signedness: opts.signedness,
+            watched,
+            events: vec![],
+        }
+    }
+
+    /// Number of operations that are checked.
+    pub fn watched(&self) -> usize {
+        self.watched.len()
+    }
+
+    /// Checks all operations with the current values of `sim`. Call this after the inputs for
+    /// the current step have been applied. Returns the number of new overflows.
+    pub fn check(&mut self, sim: &impl Simulator) -> usize {
+        let before = self.events.len();
+        for &(expr, op, a, b) in self.watched.iter() {
+            let (Value::BitVec(a), Value::BitVec(b)) = (sim.get(a), sim.get(b)) else {
+                continue;
+            };
+            let result = match op {
+                ArithOp::Add => a.add(&b),
+                ArithOp::Sub => a.sub(&b),
+                ArithOp::Mul => a.mul(&b),
+            };
+            let by = a.width();
+            let extend = |v: &BitVecValue| match self.signedness {
+                Signedness::Unsigned => v.zero_extend(by),
+                Signedness::Signed => v.sign_extend(by),
+            };
+            let (wide_a, wide_b) = (extend(&a), extend(&b));
+            let wide = match op {
+                ArithOp::Add => wide_a.add(&wide_b),
+                ArithOp::Sub => wide_a.sub(&wide_b),
+                ArithOp::Mul => wide_a.mul(&wide_b),
+            };
+            if wide != extend(&result) {
+                let event = OverflowEvent {
+                    step: sim.step_count(),
+                    expr,
+                    op,
+                    a,
+                    b,
+                    result,
+                };
+                #[cfg(feature = "tracing")]
+                tracing::warn!(step = event.step, expr = ?expr, "{event}");
+                self.events.push(event);
+            }
+        }
+        self.events.len() - before
+    }
+
+    pub fn events(&self) -> &[OverflowEvent] {
+        &self.events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::examples::{alu, input, ALU_ADD, ALU_SUB};
+    use crate::sim::{InitKind, Interpreter};
+
+    fn run(signedness: Signedness, a: u64, b: u64, op: u64) -> Vec<OverflowEvent> {
+        let (ctx, sys) = alu(8);
+        let opts = OverflowOptions {
+            signedness,
+            ..Default::default()
+        };
+        let mut checker = OverflowChecker::new(&ctx, &sys, &opts);
+        // the ALU contains one adder and one subtractor
+        assert_eq!(checker.watched(), 2);
+        let mut sim = Interpreter::new(&ctx, &sys);
+        sim.init(InitKind::Zero);
+        sim.set(input(&ctx, &sys, "a"), &BitVecValue::from_u64(a, 8))
+            .unwrap();
+        sim.set(input(&ctx, &sys, "b"), &BitVecValue::from_u64(b, 8))
+            .unwrap();
+        sim.set(input(&ctx, &sys, "op"), &BitVecValue::from_u64(op, 3))
+            .unwrap();
+        checker.check(&sim);
+        checker.events().to_vec()
+    }
+
+    #[test]
+    fn test_unsigned() {
+        assert!(run(Signedness::Unsigned, 100, 100, ALU_ADD).is_empty());
+        // both operations are checked, independent of the op code
+        let events = run(Signedness::Unsigned, 200, 100, ALU_ADD);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].op, ArithOp::Add);
+        assert_eq!(events[0].result.to_u64().unwrap(), 44);
+        let events = run(Signedness::Unsigned, 1, 2, ALU_SUB);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].op, ArithOp::Sub);
+    }
+
+    #[test]
+    fn test_signed() {
+        // 100 + 100 does not fit into an i8
+        let events = run(Signedness::Signed, 100, 100, ALU_ADD);
+        assert_eq!(events.len(), 1);
+        assert!(events[0].to_string().starts_with("step 0: Add"));
+        // 1 - 2 = -1 is fine
+        assert!(run(Signedness::Signed, 1, 2, ALU_SUB).is_empty());
+    }
+}
@@ -0,0 +1,83 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+use crate::expr::ExprRef;
+use rustc_hash::FxHashMap;
+use std::time::Duration;
+
+/// Collects timing information while the simulator is stepping.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PerfCounters {
+    steps: u64,
+    step_time: Duration,
+    evaluations: FxHashMap<ExprRef, u64>,
+    expr_time: FxHashMap<ExprRef, Duration>,
+}
+
+impl PerfCounters {
+    pub(crate) fn record_step(&mut self, time: Duration) {
+        self.steps += 1;
+        self.step_time += time;
+    }
+
+    /// Time it took to compute the next state expression `expr`.
+    pub(crate) fn record_next_state(&mut self, expr: ExprRef, time: Duration) {
+        *self.expr_time.entry(expr).or_default() += time;
+    }
+
+    /// Called once for every expression node that is computed.
+    pub(crate) fn record_eval(&mut self, expr: ExprRef) {
+        *self.evaluations.entry(expr).or_default() += 1;
+    }
+
+    pub(crate) fn report(&self, max_hot_exprs: usize) -> PerfReport {
+        let mut hottest: Vec<_> = self.expr_time.iter().map(|(e, t)| (*e, *t)).collect();
+        // longest first, ties are broken by expression to make the report deterministic
+        hottest.sort_by(|(a_e, a_t), (b_e, b_t)| b_t.cmp(a_t).then(a_e.cmp(b_e)));
+        hottest.truncate(max_hot_exprs);
+        let mut most_evaluated: Vec<_> = self.evaluations.iter().map(|(e, n)| (*e, *n)).collect();
+        most_evaluated.sort_by(|(a_e, a_n), (b_e, b_n)| b_n.cmp(a_n).then(a_e.cmp(b_e)));
+        most_evaluated.truncate(max_hot_exprs);
+        PerfReport {
+            steps: self.steps,
+            step_time: self.step_time,
+            evaluations: self.evaluations.values().sum(),
+            hottest,
+            most_evaluated,
+        }
+    }
+}
+
+/// Summary of the performance counters of a simulator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PerfReport {
+    /// number of steps executed while the counters were enabled
+    pub steps: u64,
+    /// total time spent in `step`
+    pub step_time: Duration,
+    /// number of expression nodes computed while the counters were enabled
+    pub evaluations: u64,
+    /// next state expressions that took the longest to evaluate, in descending order
+    pub hottest: Vec<(ExprRef, Duration)>,
+    /// expression nodes that were computed most often, in descending order
+    pub most_evaluated: Vec<(ExprRef, u64)>,
+}
+
+impl PerfReport {
+    pub fn time_per_step(&self) -> Duration {
+        if self.steps == 0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(self.step_time.as_secs_f64() / self.steps as f64)
+        }
+    }
+
+    pub fn evaluations_per_step(&self) -> f64 {
+        if self.steps == 0 {
+            0.0
+        } else {
+            self.evaluations as f64 / self.steps as f64
+        }
+    }
+}
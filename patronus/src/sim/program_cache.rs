@@ -0,0 +1,143 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Program Cache
+//! Regression flows simulate the same design over and over again. A [`ProgramCache`] stores
+//! the bytecode compiled for a design in a directory, keyed by a hash of its expressions,
+//! such that only the first run pays for the compilation.
+//! Programs refer to expressions by their id. Thus, a cached program is only found again if
+//! the design is loaded into a context in the same way, e.g., by parsing the same btor2 file.
+
+use super::bytecode::{Program, UnsupportedExpr};
+use crate::expr::{Context, Expr, ExprRef, ForEachChild};
+use baa::BitVecOps;
+use rustc_hash::FxHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+/// Directory in which compiled bytecode programs are stored across runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProgramCache {
+    pub dir: PathBuf,
+}
+
+impl ProgramCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Loads the program for `schedule` or compiles it and stores it for the next run.
+    /// Failing to read or write the cache is not an error, the program is compiled instead.
+    pub(crate) fn load_or_compile(
+        &self,
+        ctx: &Context,
+        schedule: &[ExprRef],
+    ) -> Result<Program, UnsupportedExpr> {
+        let path = self.path(ctx, schedule);
+        if let Ok(bytes) = std::fs::read(&path) {
+            let mut input = bytes.as_slice();
+            if let Some(program) = Program::decode(&mut input).filter(|_| input.is_empty()) {
+                return Ok(program);
+            }
+        }
+        let program = Program::compile(ctx, schedule)?;
+        let mut bytes = vec![];
+        program.encode(&mut bytes);
+        let _ = self.store(&path, &bytes);
+        Ok(program)
+    }
+
+    fn path(&self, ctx: &Context, schedule: &[ExprRef]) -> PathBuf {
+        self.dir
+            .join(format!("{:016x}.bytecode", program_key(ctx, schedule)))
+    }
+
+    fn store(&self, path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        // concurrent runs must never read a partially written program
+        let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+        std::fs::write(&tmp, bytes)?;
+        std::fs::rename(&tmp, path)
+    }
+}
+
+/// Covers the id and structure of every expression in `schedule` and of their children,
+/// including the values of all literals.
+fn program_key(ctx: &Context, schedule: &[ExprRef]) -> u64 {
+    let mut hasher = FxHasher::default();
+    env!("CARGO_PKG_VERSION").hash(&mut hasher);
+    schedule.len().hash(&mut hasher);
+    let mut hash_expr = |e: ExprRef| {
+        e.hash(&mut hasher);
+        ctx[e].hash(&mut hasher);
+        if let Expr::BVLiteral(value) = &ctx[e] {
+            value.get(ctx).to_hex_str().hash(&mut hasher);
+        }
+    };
+    for &e in schedule.iter() {
+        hash_expr(e);
+        ctx[e].for_each_child(|&c| hash_expr(c));
+    }
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btor2;
+    use crate::sim::{EvalOrder, InitKind, Interpreter, Simulator};
+    use baa::BitVecValue;
+
+    const COUNTER: &str = r#"
+1 sort bitvec 4
+2 input 1 step
+3 state 1 count
+4 zero 1
+5 init 1 3 4
+6 add 1 3 2
+7 sort bitvec 1
+8 ugt 7 2 3
+9 ite 1 8 6 3
+10 next 1 3 9
+"#;
+
+    #[test]
+    fn test_reuse_cached_program() {
+        let dir =
+            std::env::temp_dir().join(format!("patronus_program_cache_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let run = || {
+            let mut ctx = Context::default();
+            let sys = btor2::parse_str(&mut ctx, COUNTER, Some("counter")).unwrap();
+            let mut sim = Interpreter::new(&ctx, &sys);
+            sim.set_program_cache(Some(ProgramCache::new(&dir)));
+            sim.set_eval_order(EvalOrder::Bytecode);
+            assert_eq!(sim.eval_order(), EvalOrder::Bytecode);
+            sim.init(InitKind::Zero);
+            let mut values = vec![];
+            for ii in 0..20u64 {
+                sim.set(sys.inputs[0], &BitVecValue::from_u64(ii % 16, 4))
+                    .unwrap();
+                sim.step();
+                values.push(sim.get(sys.states[0].symbol).try_into_u64().unwrap());
+            }
+            (sim.bytecode_len(), values)
+        };
+
+        let compiled = run();
+        let files: Vec<PathBuf> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|f| f.unwrap().path())
+            .collect();
+        assert_eq!(files.len(), 1);
+        let written = std::fs::metadata(&files[0]).unwrap().modified().unwrap();
+
+        // the second run loads the program instead of storing it again
+        let cached = run();
+        assert_eq!(compiled, cached);
+        let modified = std::fs::metadata(&files[0]).unwrap().modified().unwrap();
+        assert_eq!(written, modified);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,328 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Recorded Waveforms
+//! Loads VCD and FST files written by other simulators with the `wellen` library and binds
+//! their signals by name to the inputs and states of a [`TransitionSystem`]. A system signal
+//! matches a waveform signal with the same full name, or one whose hierarchical name ends in
+//! it, e.g., `count` matches `tb.dut.count`. The recorded inputs can be replayed on a patronus
+//! simulator through [`RecordedTrace::stimulus`], and properties can be checked directly on
+//! the recorded values with [`RecordedTrace::check`].
+//!
+//! Unknown bits (`x` and `z`) are read as zero.
+
+use super::{OfflineChecker, OfflineReport, SignalWaves, Stimulus, Waves};
+use crate::expr::{Context, ExprRef, TypeCheck, WidthInt};
+use crate::system::{TransitionSystem, HIERARCHY_SEPARATOR};
+use baa::{BitVecOps, BitVecValue};
+use std::path::Path;
+use wellen::{Signal, SignalRef, TimeTableIdx};
+
+#[derive(Debug, thiserror::Error)]
+pub enum WaveformError {
+    #[error("failed to read waveform")]
+    Wellen(#[from] wellen::WellenError),
+    #[error("clock `{0}` is not part of the waveform")]
+    UnknownClock(String),
+    #[error("`{name}` matches several waveform signals: {}", candidates.join(", "))]
+    Ambiguous {
+        name: String,
+        candidates: Vec<String>,
+    },
+    #[error("`{name}` has {actual} bits in the waveform, but {expected} bits in the system")]
+    WidthMismatch {
+        name: String,
+        expected: WidthInt,
+        actual: WidthInt,
+    },
+}
+
+type Result<T> = std::result::Result<T, WaveformError>;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WaveformOptions {
+    /// Steps are sampled right before every rising edge of this signal.
+    /// Without a clock, every time step of the waveform is a step.
+    pub clock: Option<String>,
+    /// Only binds signals directly inside this scope, e.g., `tb.dut`.
+    pub scope: Option<String>,
+}
+
+/// Values of the system inputs and states over the course of a recorded waveform.
+#[derive(Debug, Clone)]
+pub struct RecordedTrace {
+    /// values of all bound signals, named like the system signal they are bound to
+    pub waves: Waves,
+    /// system inputs and states together with the full name of their waveform signal
+    pub bound: Vec<(ExprRef, String)>,
+    /// bit-vector inputs and states without a matching waveform signal
+    pub unbound: Vec<ExprRef>,
+    /// waveform time at which every step was sampled
+    pub times: Vec<u64>,
+}
+
+impl RecordedTrace {
+    pub fn load(
+        filename: impl AsRef<Path>,
+        ctx: &Context,
+        sys: &TransitionSystem,
+        opts: &WaveformOptions,
+    ) -> Result<Self> {
+        let mut wave = wellen::simple::read(filename)?;
+        let hierarchy = wave.hierarchy();
+        let vars: Vec<(String, SignalRef, Option<u32>)> = hierarchy
+            .iter_vars()
+            .map(|v| (v.full_name(hierarchy), v.signal_ref(), v.length()))
+            .collect();
+
+        let symbols = sys
+            .inputs
+            .iter()
+            .copied()
+            .chain(sys.states.iter().map(|s| s.symbol))
+            .filter(|e| e.get_type(ctx).is_bit_vector());
+        let mut bound = vec![];
+        let mut signal_refs = vec![];
+        let mut unbound = vec![];
+        for symbol in symbols {
+            let name = ctx.get_symbol_name(symbol).unwrap();
+            let candidates: Vec<_> = vars
+                .iter()
+                .filter(|(full, _, _)| matches_name(full, name, opts.scope.as_deref()))
+                .collect();
+            match candidates.as_slice() {
+                [] => unbound.push(symbol),
+                [(full, signal_ref, length)] => {
+                    let expected = symbol.get_bv_type(ctx).unwrap();
+                    let actual = length.unwrap_or(0);
+                    if actual != expected {
+                        return Err(WaveformError::WidthMismatch {
+                            name: full.clone(),
+                            expected,
+                            actual,
+                        });
+                    }
+                    bound.push((symbol, full.clone()));
+                    signal_refs.push(*signal_ref);
+                }
+                _ => {
+                    return Err(WaveformError::Ambiguous {
+                        name: name.to_string(),
+                        candidates: candidates.iter().map(|(f, _, _)| f.clone()).collect(),
+                    })
+                }
+            }
+        }
+
+        let clock = match &opts.clock {
+            None => None,
+            Some(clock) => Some(
+                vars.iter()
+                    .find(|(full, _, _)| matches_name(full, clock, opts.scope.as_deref()))
+                    .map(|(_, signal_ref, _)| *signal_ref)
+                    .ok_or_else(|| WaveformError::UnknownClock(clock.clone()))?,
+            ),
+        };
+        let mut to_load = signal_refs.clone();
+        to_load.extend(clock);
+        wave.load_signals(&to_load);
+
+        // time table indices at which every step is sampled
+        let num_times = wave.time_table().len() as TimeTableIdx;
+        let samples: Vec<TimeTableIdx> = match clock {
+            None => (0..num_times).collect(),
+            Some(clock) => {
+                let clock = wave.get_signal(clock).unwrap();
+                let mut was_high = true;
+                (0..num_times)
+                    .filter(|&idx| {
+                        let high = !read_value(clock, idx, 1).is_zero();
+                        let rising = high && !was_high;
+                        was_high = high;
+                        rising
+                    })
+                    .map(|idx| idx - 1)
+                    .collect()
+            }
+        };
+
+        let signals = bound
+            .iter()
+            .zip(signal_refs)
+            .map(|((symbol, _), signal_ref)| {
+                let signal = wave.get_signal(signal_ref).unwrap();
+                let width = symbol.get_bv_type(ctx).unwrap();
+                let mut changes: Vec<(u64, BitVecValue)> = vec![];
+                for (step, &idx) in samples.iter().enumerate() {
+                    let value = read_value(signal, idx, width);
+                    if !matches!(changes.last(), Some((_, prev)) if prev.is_equal(&value)) {
+                        changes.push((step as u64, value));
+                    }
+                }
+                SignalWaves {
+                    name: ctx.get_symbol_name(*symbol).unwrap().to_string(),
+                    width,
+                    changes,
+                }
+            })
+            .collect();
+        let time_table = wave.time_table();
+        Ok(Self {
+            waves: Waves::new(signals, samples.len() as u64),
+            bound,
+            unbound,
+            times: samples
+                .iter()
+                .map(|&idx| time_table[idx as usize])
+                .collect(),
+        })
+    }
+
+    /// Recorded values of all bound inputs, in order to re-simulate the trace.
+    pub fn stimulus(&self, sys: &TransitionSystem) -> Stimulus {
+        let inputs: Vec<usize> = (0..self.bound.len())
+            .filter(|&ii| sys.inputs.contains(&self.bound[ii].0))
+            .collect();
+        let signals = inputs
+            .iter()
+            .map(|&ii| self.waves.signals[ii].name.clone())
+            .collect();
+        let steps = self
+            .waves
+            .iter_from(0)
+            .map(|values| {
+                inputs
+                    .iter()
+                    .map(|&ii| Some(format!("0x{}", values[ii].to_hex_str())))
+                    .collect()
+            })
+            .collect();
+        Stimulus { signals, steps }
+    }
+
+    /// Evaluates the bad states and constraints of `sys` on the recorded values of every step,
+    /// see [`OfflineChecker`] in order to also check temporal properties.
+    pub fn check_bad_states(&self, ctx: &Context, sys: &TransitionSystem) -> OfflineReport {
+        self.check(ctx, &OfflineChecker::new(sys))
+    }
+
+    /// Runs `checker` on the recorded values, violations are reported with their waveform time.
+    pub fn check(&self, ctx: &Context, checker: &OfflineChecker) -> OfflineReport {
+        checker.check(ctx, &self.waves, Some(self.times.as_slice()))
+    }
+}
+
+fn matches_name(full: &str, name: &str, scope: Option<&str>) -> bool {
+    match scope {
+        Some(scope) => full
+            .strip_prefix(scope)
+            .and_then(|rest| rest.strip_prefix(HIERARCHY_SEPARATOR))
+            .is_some_and(|rest| rest == name),
+        None => full
+            .strip_suffix(name)
+            .is_some_and(|prefix| prefix.is_empty() || prefix.ends_with(HIERARCHY_SEPARATOR)),
+    }
+}
+
+/// Value of `signal` at time table index `idx`, unknown bits are read as zero.
+fn read_value(signal: &Signal, idx: TimeTableIdx, width: WidthInt) -> BitVecValue {
+    let bits = signal
+        .get_offset(idx)
+        .and_then(|offset| signal.get_value_at(&offset, 0).to_bit_string());
+    match bits {
+        Some(bits) => {
+            let bits: String = bits
+                .chars()
+                .map(|c| if c == '1' { '1' } else { '0' })
+                .collect();
+            BitVecValue::from_bit_str(&bits).unwrap()
+        }
+        None => BitVecValue::zero(width),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::ViolationKind;
+    use crate::system::State;
+
+    const COUNTER_VCD: &str = r#"$timescale 1ns $end
+$scope module tb $end
+$scope module dut $end
+$var wire 1 ! clk $end
+$var wire 1 " en $end
+$var wire 4 # count $end
+$upscope $end
+$upscope $end
+$enddefinitions $end
+#0
+0!
+1"
+b0000 #
+#5
+1!
+#10
+0!
+b0001 #
+#15
+1!
+#20
+0!
+b0010 #
+#25
+1!
+#30
+0!
+b0011 #
+#35
+1!
+"#;
+
+    #[test]
+    fn test_load_recorded_counter() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("counter".to_string());
+        let en = ctx.bv_symbol("en", 1);
+        sys.add_input(&ctx, en);
+        let count = ctx.bv_symbol("count", 4);
+        let next = ctx.build(|c| c.ite(en, c.add(count, c.one(4)), count));
+        sys.add_state(
+            &ctx,
+            State {
+                symbol: count,
+                init: None,
+                next: Some(next),
+            },
+        );
+        let unknown = ctx.bv_symbol("unknown", 4);
+        sys.add_input(&ctx, unknown);
+        let three = ctx.build(|c| c.equal(count, c.bit_vec_val(3, 4)));
+        sys.bad_states.push(three);
+        let uses_unknown = ctx.build(|c| c.equal(unknown, count));
+        sys.bad_states.push(uses_unknown);
+
+        let filename = std::env::temp_dir().join("patronus_recorded_counter.vcd");
+        std::fs::write(&filename, COUNTER_VCD).unwrap();
+        let opts = WaveformOptions {
+            clock: Some("clk".to_string()),
+            scope: None,
+        };
+        let trace = RecordedTrace::load(&filename, &ctx, &sys, &opts).unwrap();
+        assert_eq!(trace.unbound, [unknown]);
+        assert_eq!(trace.bound[1], (count, "tb.dut.count".to_string()));
+        // sampled before each of the four rising clock edges
+        assert_eq!(trace.waves.len(), 4);
+        assert_eq!(trace.waves.value_at("count", 2).unwrap().to_u64(), Some(2));
+
+        let report = trace.check_bad_states(&ctx, &sys);
+        let violations: Vec<_> = report.violations.iter().map(|v| (v.step, v.time)).collect();
+        assert_eq!(violations, [(3, Some(30))]);
+        assert_eq!(report.unchecked, [ViolationKind::BadState(1)]);
+
+        let stimulus = trace.stimulus(&sys);
+        assert_eq!(stimulus.signals, ["en"]);
+        assert_eq!(stimulus.steps.len(), 4);
+    }
+}
@@ -0,0 +1,273 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Replay Logs
+//! Records every interaction of a harness with a simulator, i.e., `init`, `set`, `update`,
+//! `step` and snapshots, so that a session can be reproduced exactly without the harness that
+//! created it. Signals are recorded by name and the log is saved as JSON, which makes it
+//! possible to attach a log to a bug report and replay it on a freshly parsed design.
+
+use super::{parse_value, InitKind, SimError, Simulator};
+use crate::expr::{Context, ExprRef, TypeCheck};
+use crate::system::{NameError, NameIndex, TransitionSystem};
+use baa::{BitVecOps, BitVecValueRef, Value};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReplayError {
+    #[error("failed to parse replay log")]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Name(#[from] NameError),
+    #[error("invalid value `{value}` for `{name}`")]
+    InvalidValue { name: String, value: String },
+    #[error("event {event} restores snapshot {snapshot}, but only {taken} snapshots were taken")]
+    UnknownSnapshot {
+        event: usize,
+        snapshot: usize,
+        taken: usize,
+    },
+    #[error("event {event} failed")]
+    Sim {
+        event: usize,
+        #[source]
+        source: SimError,
+    },
+}
+
+/// A single interaction with the simulator.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum ReplayEvent {
+    /// `seed` is `None` for zero initialization
+    Init {
+        seed: Option<u64>,
+    },
+    /// `value` is hexadecimal with a `0x` prefix
+    Set {
+        name: String,
+        value: String,
+    },
+    Update,
+    Step,
+    Snapshot,
+    /// restores the `index`-th snapshot that was taken
+    Restore {
+        index: usize,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplayLog {
+    pub events: Vec<ReplayEvent>,
+}
+
+impl ReplayLog {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.events).expect("replay logs can always be serialized")
+    }
+
+    pub fn from_json(src: &str) -> Result<Self, ReplayError> {
+        Ok(Self {
+            events: serde_json::from_str(src)?,
+        })
+    }
+
+    /// Executes all events on `sim`, which needs to simulate the same system as the recorded
+    /// simulator.
+    pub fn replay<S: Simulator>(
+        &self,
+        ctx: &Context,
+        sys: &TransitionSystem,
+        sim: &mut S,
+    ) -> Result<(), ReplayError>
+    where
+        S::SnapshotId: Clone,
+    {
+        let names = NameIndex::new(ctx, sys);
+        let mut snapshots = vec![];
+        for (ii, event) in self.events.iter().enumerate() {
+            match event {
+                ReplayEvent::Init { seed } => sim.init(match seed {
+                    Some(seed) => InitKind::Random(*seed),
+                    None => InitKind::Zero,
+                }),
+                ReplayEvent::Set { name, value } => {
+                    let expr = names.resolve(name)?;
+                    let parsed = expr
+                        .get_bv_type(ctx)
+                        .and_then(|width| parse_value(value, width))
+                        .ok_or_else(|| ReplayError::InvalidValue {
+                            name: name.clone(),
+                            value: value.clone(),
+                        })?;
+                    sim.set(expr, &parsed)
+                        .map_err(|source| ReplayError::Sim { event: ii, source })?;
+                }
+                ReplayEvent::Update => sim.update(),
+                ReplayEvent::Step => sim.step(),
+                ReplayEvent::Snapshot => snapshots.push(sim.take_snapshot()),
+                ReplayEvent::Restore { index } => {
+                    let id = snapshots.get(*index).cloned().ok_or_else(|| {
+                        ReplayError::UnknownSnapshot {
+                            event: ii,
+                            snapshot: *index,
+                            taken: snapshots.len(),
+                        }
+                    })?;
+                    sim.restore_snapshot(id)
+                        .map_err(|source| ReplayError::Sim { event: ii, source })?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Wraps a simulator and records all interactions with it in a [`ReplayLog`].
+/// Calls that fail are not recorded, since they do not change the state of the simulator.
+pub struct ReplayRecorder<'a, S: Simulator> {
+    ctx: &'a Context,
+    sim: S,
+    log: ReplayLog,
+    /// ids of all snapshots in the order they were taken
+    snapshots: Vec<S::SnapshotId>,
+}
+
+impl<'a, S: Simulator> ReplayRecorder<'a, S> {
+    pub fn new(ctx: &'a Context, sim: S) -> Self {
+        Self {
+            ctx,
+            sim,
+            log: ReplayLog::default(),
+            snapshots: vec![],
+        }
+    }
+
+    pub fn log(&self) -> &ReplayLog {
+        &self.log
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.sim
+    }
+
+    pub fn into_inner(self) -> (S, ReplayLog) {
+        (self.sim, self.log)
+    }
+}
+
+impl<S: Simulator> Simulator for ReplayRecorder<'_, S>
+where
+    S::SnapshotId: PartialEq + Clone,
+{
+    type SnapshotId = S::SnapshotId;
+
+    fn init(&mut self, kind: InitKind) {
+        self.sim.init(kind);
+        self.log
+            .events
+            .push(ReplayEvent::Init { seed: kind.seed() });
+    }
+
+    fn step(&mut self) {
+        self.sim.step();
+        self.log.events.push(ReplayEvent::Step);
+    }
+
+    fn set<'b>(
+        &mut self,
+        expr: ExprRef,
+        value: impl Into<BitVecValueRef<'b>>,
+    ) -> Result<(), SimError> {
+        let value = value.into();
+        let name = self
+            .ctx
+            .get_symbol_name(expr)
+            .ok_or(SimError::UnknownSymbol(expr))?;
+        let hex = format!("0x{}", value.to_hex_str());
+        self.sim.set(expr, value)?;
+        self.log.events.push(ReplayEvent::Set {
+            name: name.to_string(),
+            value: hex,
+        });
+        Ok(())
+    }
+
+    fn update(&mut self) {
+        self.sim.update();
+        self.log.events.push(ReplayEvent::Update);
+    }
+
+    fn get(&self, expr: ExprRef) -> Value {
+        self.sim.get(expr)
+    }
+
+    fn step_count(&self) -> u64 {
+        self.sim.step_count()
+    }
+
+    fn take_snapshot(&mut self) -> Self::SnapshotId {
+        let id = self.sim.take_snapshot();
+        self.snapshots.push(id.clone());
+        self.log.events.push(ReplayEvent::Snapshot);
+        id
+    }
+
+    fn restore_snapshot(&mut self, id: Self::SnapshotId) -> Result<(), SimError> {
+        let index = self.snapshots.iter().position(|s| *s == id);
+        self.sim.restore_snapshot(id)?;
+        let index = index.expect("successfully restored snapshot was taken through the recorder");
+        self.log.events.push(ReplayEvent::Restore { index });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::Interpreter;
+    use crate::system::State;
+    use baa::BitVecValue;
+
+    #[test]
+    fn test_replay_session() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("acc".to_string());
+        let inc = ctx.bv_symbol("inc", 8);
+        sys.add_input(&ctx, inc);
+        let acc = ctx.bv_symbol("acc", 8);
+        let next = ctx.build(|c| c.add(acc, inc));
+        sys.add_state(
+            &ctx,
+            State {
+                symbol: acc,
+                init: None,
+                next: Some(next),
+            },
+        );
+
+        let mut sim = ReplayRecorder::new(&ctx, Interpreter::new(&ctx, &sys));
+        sim.init(InitKind::Random(7));
+        sim.set(inc, &BitVecValue::from_u64(3, 8)).unwrap();
+        sim.step();
+        let snapshot = sim.take_snapshot();
+        sim.set(inc, &BitVecValue::from_u64(100, 8)).unwrap();
+        sim.step();
+        sim.step();
+        sim.restore_snapshot(snapshot).unwrap();
+        sim.step();
+        // failed calls are not recorded
+        assert!(sim.set(next, &BitVecValue::from_u64(1, 8)).is_err());
+        let expected = sim.get(acc).try_into_u64().unwrap();
+        let (_, log) = sim.into_inner();
+        assert_eq!(log.events.len(), 9);
+
+        let log = ReplayLog::from_json(&log.to_json()).unwrap();
+        let mut replayed = Interpreter::new(&ctx, &sys);
+        log.replay(&ctx, &sys, &mut replayed).unwrap();
+        assert_eq!(replayed.get(acc).try_into_u64().unwrap(), expected);
+        assert_eq!(replayed.step_count(), 4);
+    }
+}
@@ -0,0 +1,112 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Revisit Detection
+//! Hashes the complete state after every step and remembers when each hash was last seen.
+//! Reaching a state a second time closes a loop. Without inputs, the simulation will go around
+//! this loop forever, which helps to find livelocks. Once no new states are found on a small
+//! design, the number of distinct states bounds the depth needed for a complete BMC run.
+//!
+//! Only hashes are stored, thus two different states can in rare cases be reported as a
+//! revisit.
+
+use crate::expr::{hash_array, Context, GetExprValue, SymbolValueStore};
+use crate::system::TransitionSystem;
+use baa::BitVecOps;
+use rustc_hash::{FxHashMap, FxHasher};
+use std::hash::{Hash, Hasher};
+
+/// The state reached at `step` was already seen at `previous`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Revisit {
+    pub step: u64,
+    pub previous: u64,
+}
+
+impl Revisit {
+    /// Number of steps it took to come back to the same state.
+    pub fn loop_length(&self) -> u64 {
+        self.step - self.previous
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RevisitTracker {
+    /// step at which every state hash was seen last
+    last_seen: FxHashMap<u64, u64>,
+    revisits: Vec<Revisit>,
+}
+
+impl RevisitTracker {
+    pub(crate) fn clear(&mut self) {
+        self.last_seen.clear();
+        self.revisits.clear();
+    }
+
+    pub(crate) fn record(&mut self, hash: u64, step: u64) {
+        if let Some(previous) = self.last_seen.insert(hash, step) {
+            self.revisits.push(Revisit { step, previous });
+        }
+    }
+
+    pub(crate) fn revisits(&self) -> &[Revisit] {
+        &self.revisits
+    }
+
+    pub(crate) fn distinct_states(&self) -> usize {
+        self.last_seen.len()
+    }
+}
+
+/// Hashes the values of all states of `sys`. Inputs are not part of the state.
+pub(crate) fn state_hash(ctx: &Context, sys: &TransitionSystem, data: &SymbolValueStore) -> u64 {
+    let mut hasher = FxHasher::default();
+    for state in sys.states.iter() {
+        if let Some(value) = data.get_bv(ctx, state.symbol) {
+            value.width().hash(&mut hasher);
+            value.words().hash(&mut hasher);
+        } else if let Some(value) = data.get_array(ctx, state.symbol) {
+            hash_array(&value).hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::expr::Context;
+    use crate::sim::{InitKind, Interpreter, Simulator};
+    use crate::system::{State, TransitionSystem};
+
+    #[test]
+    fn test_counter_loop() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("counter".to_string());
+        let count = ctx.bv_symbol("count", 3);
+        let next = ctx.build(|c| c.add(count, c.one(3)));
+        let init = ctx.zero(3);
+        sys.add_state(
+            &ctx,
+            State {
+                symbol: count,
+                init: Some(init),
+                next: Some(next),
+            },
+        );
+
+        let mut sim = Interpreter::new(&ctx, &sys);
+        sim.enable_revisit_detection();
+        sim.init(InitKind::Zero);
+        for _ in 0..7 {
+            sim.step();
+        }
+        assert!(sim.revisits().is_empty());
+        assert_eq!(sim.distinct_states(), Some(8));
+        sim.step();
+        let revisit = sim.revisits()[0];
+        assert_eq!(revisit.step, 8);
+        assert_eq!(revisit.previous, 0);
+        assert_eq!(revisit.loop_length(), 8);
+    }
+}
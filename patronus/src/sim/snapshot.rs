@@ -0,0 +1,309 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Snapshot Storage
+//! Most signals do not change between two snapshots, thus we only store a full copy of the
+//! simulator state for every [`CHECKPOINT_INTERVAL`]-th snapshot and the values that changed
+//! since the previous snapshot for all others. A snapshot is restored by applying the deltas
+//! since the closest checkpoint, which bounds the cost of restoring to
+//! [`CHECKPOINT_INTERVAL`] deltas.
+//!
+//! Long interactive sessions can take more snapshots than fit into memory. With a
+//! [`SnapshotSpill`] budget, the oldest segments, i.e., a checkpoint together with its
+//! deltas, are moved to disk once the snapshots in memory exceed the budget. Files are written
+//! on a background thread and read back transparently when a spilled snapshot is restored.
+//! The segment that is currently being extended always stays in memory.
+
+use crate::expr::{SymbolValueDelta, SymbolValueStore};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::JoinHandle;
+
+/// Number of snapshots after which another full copy of the state is stored.
+const CHECKPOINT_INTERVAL: usize = 64;
+
+/// Memory budget for snapshots and the directory that snapshots are spilled to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotSpill {
+    pub budget_bytes: usize,
+    pub dir: PathBuf,
+}
+
+enum Snapshot {
+    Full(SymbolValueStore),
+    /// changes since the previous snapshot
+    Delta(SymbolValueDelta),
+    /// part of the segment with the given index in `SnapshotStore::spilled`
+    Spilled(usize),
+}
+
+impl Snapshot {
+    fn size_in_bytes(&self) -> usize {
+        match self {
+            Snapshot::Full(data) => data.size_in_bytes(),
+            Snapshot::Delta(delta) => delta.size_in_bytes(),
+            Snapshot::Spilled(_) => 0,
+        }
+    }
+}
+
+struct SpilledSegment {
+    path: PathBuf,
+    /// index of the checkpoint that starts the segment
+    first: usize,
+    /// background write, joined before the file is read for the first time
+    write: Option<JoinHandle<std::io::Result<()>>>,
+}
+
+/// Distinguishes the files of different stores in the same process.
+static NEXT_STORE_ID: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Default)]
+pub(super) struct SnapshotStore {
+    snapshots: Vec<Snapshot>,
+    /// full copy of the most recent snapshot, used to compute the next delta
+    latest: Option<SymbolValueStore>,
+    spill: Option<SnapshotSpill>,
+    spilled: Vec<SpilledSegment>,
+    /// approximate size of all snapshots that are still in memory
+    bytes_in_memory: usize,
+    /// all snapshots before this index are spilled
+    first_in_memory: usize,
+    store_id: usize,
+}
+
+impl SnapshotStore {
+    pub(super) fn set_spill(&mut self, spill: Option<SnapshotSpill>) {
+        self.store_id = NEXT_STORE_ID.fetch_add(1, Ordering::Relaxed);
+        self.spill = spill;
+        self.spill_if_over_budget();
+    }
+
+    pub(super) fn push(&mut self, data: &SymbolValueStore) -> usize {
+        let id = self.snapshots.len();
+        let delta = match &self.latest {
+            Some(latest) if id % CHECKPOINT_INTERVAL != 0 => data.diff(latest),
+            _ => None,
+        };
+        let snapshot = match delta {
+            Some(delta) => Snapshot::Delta(delta),
+            // the layout of the store changed, or it is time for another checkpoint
+            None => Snapshot::Full(data.clone()),
+        };
+        self.bytes_in_memory += snapshot.size_in_bytes();
+        self.snapshots.push(snapshot);
+        self.latest = Some(data.clone());
+        self.spill_if_over_budget();
+        id
+    }
+
+    pub(super) fn get(&mut self, id: usize) -> Option<SymbolValueStore> {
+        if id >= self.snapshots.len() {
+            return None;
+        }
+        if let Snapshot::Spilled(segment) = self.snapshots[id] {
+            let snapshots = self.read_segment(segment);
+            let offset = id - self.spilled[segment].first;
+            return Some(restore(&snapshots[..=offset]));
+        }
+        let checkpoint = (0..=id)
+            .rev()
+            .find(|&ii| matches!(self.snapshots[ii], Snapshot::Full(_)))
+            .expect("the first snapshot in memory is always a full copy");
+        Some(restore(&self.snapshots[checkpoint..=id]))
+    }
+
+    /// Spills the oldest complete segments until the snapshots in memory fit the budget.
+    fn spill_if_over_budget(&mut self) {
+        let Some(spill) = self.spill.clone() else {
+            return;
+        };
+        while self.bytes_in_memory > spill.budget_bytes {
+            let first = self.first_in_memory;
+            // the segment ends with the next full copy, the last segment is never spilled
+            let Some(end) = (first + 1..self.snapshots.len())
+                .find(|&ii| matches!(self.snapshots[ii], Snapshot::Full(_)))
+            else {
+                return;
+            };
+            let segment = self.spilled.len();
+            let mut bytes = vec![];
+            for ii in first..end {
+                let snapshot =
+                    std::mem::replace(&mut self.snapshots[ii], Snapshot::Spilled(segment));
+                self.bytes_in_memory -= snapshot.size_in_bytes();
+                encode(&mut bytes, &snapshot);
+            }
+            let path = spill.dir.join(format!(
+                "patronus-snapshots-{}-{}-{segment}.bin",
+                std::process::id(),
+                self.store_id
+            ));
+            let file = path.clone();
+            let write = std::thread::spawn(move || std::fs::write(file, bytes));
+            self.spilled.push(SpilledSegment {
+                path,
+                first,
+                write: Some(write),
+            });
+            self.first_in_memory = end;
+        }
+    }
+
+    fn read_segment(&mut self, segment: usize) -> Vec<Snapshot> {
+        let spilled = &mut self.spilled[segment];
+        if let Some(write) = spilled.write.take() {
+            write
+                .join()
+                .expect("snapshot writer panicked")
+                .expect("failed to spill snapshots to disk");
+        }
+        let bytes = std::fs::read(&spilled.path).expect("failed to read spilled snapshots");
+        let mut input = bytes.as_slice();
+        let mut out = vec![];
+        while !input.is_empty() {
+            out.push(decode(&mut input).expect("corrupted snapshot file"));
+        }
+        out
+    }
+}
+
+impl Drop for SnapshotStore {
+    fn drop(&mut self) {
+        for segment in self.spilled.iter_mut() {
+            if let Some(write) = segment.write.take() {
+                let _ = write.join();
+            }
+            let _ = std::fs::remove_file(&segment.path);
+        }
+    }
+}
+
+/// Applies all deltas to the full copy at the start of `snapshots`.
+fn restore(snapshots: &[Snapshot]) -> SymbolValueStore {
+    let Snapshot::Full(base) = &snapshots[0] else {
+        unreachable!("a segment always starts with a full copy")
+    };
+    let mut data = base.clone();
+    for snapshot in snapshots[1..].iter() {
+        if let Snapshot::Delta(delta) = snapshot {
+            data.apply(delta);
+        }
+    }
+    data
+}
+
+fn encode(out: &mut Vec<u8>, snapshot: &Snapshot) {
+    match snapshot {
+        Snapshot::Full(data) => {
+            out.push(0);
+            data.encode(out);
+        }
+        Snapshot::Delta(delta) => {
+            out.push(1);
+            delta.encode(out);
+        }
+        Snapshot::Spilled(_) => unreachable!("snapshot was already spilled"),
+    }
+}
+
+fn decode(input: &mut &[u8]) -> Option<Snapshot> {
+    let (&tag, rest) = input.split_first()?;
+    *input = rest;
+    match tag {
+        0 => Some(Snapshot::Full(SymbolValueStore::decode(input)?)),
+        1 => Some(Snapshot::Delta(SymbolValueDelta::decode(input)?)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::{eval_expr, Context, GetExprValue};
+    use baa::{ArrayMutOps, ArrayOps, ArrayValue, BitVecOps, BitVecValue};
+
+    #[test]
+    fn test_restore_from_deltas() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("a", 8);
+        let b = ctx.bv_symbol("b", 100);
+        let mut data = SymbolValueStore::default();
+        data.define_bv(a, &BitVecValue::zero(8));
+        data.define_bv(b, &BitVecValue::zero(100));
+
+        let mut store = SnapshotStore::default();
+        for ii in 0..200u64 {
+            data.update_bv(a, &BitVecValue::from_u64(ii % 256, 8));
+            if ii % 10 == 0 {
+                data.update_bv(b, &BitVecValue::from_u64(ii, 100));
+            }
+            assert_eq!(store.push(&data), ii as usize);
+        }
+        let full_copies = store
+            .snapshots
+            .iter()
+            .filter(|s| matches!(s, Snapshot::Full(_)))
+            .count();
+        assert_eq!(full_copies, 200usize.div_ceil(CHECKPOINT_INTERVAL));
+
+        for ii in [0u64, 1, 63, 64, 65, 137, 199] {
+            let restored = store.get(ii as usize).unwrap();
+            let value = |e| eval_expr(&ctx, &restored, e).try_into_u64().unwrap();
+            assert_eq!(value(a), ii);
+            assert_eq!(value(b), ii - ii % 10);
+        }
+        assert!(store.get(200).is_none());
+    }
+
+    #[test]
+    fn test_spill_to_disk() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("a", 8);
+        let m = ctx.array_symbol("m", 4, 8);
+        let mut data = SymbolValueStore::default();
+        data.define_bv(a, &BitVecValue::zero(8));
+        data.define_array(m, ArrayValue::new_sparse(4, &BitVecValue::zero(8)));
+
+        let dir = std::env::temp_dir();
+        let mut store = SnapshotStore::default();
+        store.set_spill(Some(SnapshotSpill {
+            budget_bytes: 1024,
+            dir: dir.clone(),
+        }));
+        for ii in 0..300u64 {
+            data.update_bv(a, &BitVecValue::from_u64(ii % 256, 8));
+            let mut mem = ArrayValue::new_sparse(4, &BitVecValue::zero(8));
+            mem.store(
+                &BitVecValue::from_u64(ii % 16, 4),
+                &BitVecValue::from_u64(ii % 256, 8),
+            );
+            data.update_array(m, mem);
+            store.push(&data);
+        }
+        // the last segment is still being extended and thus stays in memory
+        assert_eq!(store.first_in_memory, 256);
+        assert!(store.snapshots[..256]
+            .iter()
+            .all(|s| matches!(s, Snapshot::Spilled(_))));
+        let files: Vec<PathBuf> = store.spilled.iter().map(|s| s.path.clone()).collect();
+        assert!(!files.is_empty());
+
+        for ii in [0u64, 1, 63, 64, 130, 299] {
+            let restored = store.get(ii as usize).unwrap();
+            assert_eq!(
+                eval_expr(&ctx, &restored, a).try_into_u64().unwrap(),
+                ii % 256
+            );
+            let mem = restored.get_array(&ctx, m).unwrap();
+            let word = mem.select(&BitVecValue::from_u64(ii % 16, 4));
+            assert_eq!(word.to_u64().unwrap(), ii % 256);
+        }
+        // restoring the same spilled snapshot twice works as well
+        assert!(store.get(0).is_some());
+
+        drop(store);
+        assert!(files.iter().all(|f| !f.exists()));
+    }
+}
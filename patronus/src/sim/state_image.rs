@@ -0,0 +1,334 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # State Images
+//! A [`StateImage`] contains the value of every state of a system, much like the contents of
+//! a scan chain. Images refer to states by name and not by [`ExprRef`], so they can be moved
+//! between simulator backends and between processes that parsed the same design.
+//!
+//! Images are exchanged as JSON:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "system": "counter",
+//!   "step": 5,
+//!   "states": [
+//!     { "name": "count", "width": 4, "value": "0x5" },
+//!     { "name": "mem", "index_width": 2, "data_width": 8, "default": "0x0",
+//!       "words": [ { "addr": "0x1", "data": "0x2a" } ] }
+//!   ]
+//! }
+//! ```
+//!
+//! Bit-vector states have a `width` and a `value`. Array states store a `default` value
+//! together with the `words` that differ from it. All values are hexadecimal with a `0x`
+//! prefix. The `version` is incremented with every incompatible change to the format.
+
+use super::parse_value;
+use crate::expr::{ArrayType, Context, ExprRef, Type, TypeCheck, WidthInt};
+use crate::system::TransitionSystem;
+use baa::{ArrayMutOps, ArrayOps, ArrayValue, BitVecOps, BitVecValue, SparseArrayValue, Value};
+use serde::{Deserialize, Serialize};
+
+/// Version of the JSON format written by [`StateImage::to_json`].
+pub const STATE_IMAGE_VERSION: u32 = 1;
+
+#[derive(Debug, thiserror::Error)]
+pub enum StateImageError {
+    #[error("failed to parse state image")]
+    Json(#[from] serde_json::Error),
+    #[error("state image version {0} is not supported, expected {STATE_IMAGE_VERSION}")]
+    UnsupportedVersion(u32),
+    #[error("state image contains `{0}`, which is not a state of the system")]
+    UnknownState(String),
+    #[error("state image does not contain a value for `{0}`")]
+    MissingState(String),
+    #[error("`{name}` is a {expected} in the system, but the image contains a {actual}")]
+    TypeMismatch {
+        name: String,
+        expected: Type,
+        actual: Type,
+    },
+    #[error("invalid value `{value}` for `{name}`")]
+    InvalidValue { name: String, value: String },
+}
+
+type Result<T> = std::result::Result<T, StateImageError>;
+
+/// Values of all states of a system at a particular step.
+#[derive(Debug, Clone)]
+pub struct StateImage {
+    /// name of the transition system the image was taken from
+    pub system: String,
+    /// step count of the simulator when the image was taken
+    pub step: u64,
+    /// states in the order in which they appear in the system
+    pub states: Vec<(String, Value)>,
+}
+
+impl StateImage {
+    /// Collects the values of all states of `sys` through `get`.
+    pub fn capture(
+        ctx: &Context,
+        sys: &TransitionSystem,
+        step: u64,
+        get: impl Fn(ExprRef) -> Value,
+    ) -> Self {
+        let states = sys
+            .states
+            .iter()
+            .map(|s| {
+                let name = ctx.get_symbol_name(s.symbol).unwrap().to_string();
+                (name, get(s.symbol))
+            })
+            .collect();
+        Self {
+            system: sys.name.clone(),
+            step,
+            states,
+        }
+    }
+
+    /// Matches the values of the image to the states of `sys`. Every state needs a value of
+    /// the correct type and the image may not contain values for anything else.
+    pub fn resolve(&self, ctx: &Context, sys: &TransitionSystem) -> Result<Vec<(ExprRef, Value)>> {
+        let is_state = |name: &str| {
+            sys.states
+                .iter()
+                .any(|s| ctx.get_symbol_name(s.symbol) == Some(name))
+        };
+        if let Some((name, _)) = self.states.iter().find(|(name, _)| !is_state(name)) {
+            return Err(StateImageError::UnknownState(name.clone()));
+        }
+        sys.states
+            .iter()
+            .map(|s| {
+                let name = ctx.get_symbol_name(s.symbol).unwrap();
+                let (_, value) = self
+                    .states
+                    .iter()
+                    .find(|(n, _)| n == name)
+                    .ok_or_else(|| StateImageError::MissingState(name.to_string()))?;
+                let expected = s.symbol.get_type(ctx);
+                let actual = value_type(value);
+                if expected != actual {
+                    return Err(StateImageError::TypeMismatch {
+                        name: name.to_string(),
+                        expected,
+                        actual,
+                    });
+                }
+                Ok((s.symbol, value.clone()))
+            })
+            .collect()
+    }
+
+    pub fn to_json(&self) -> String {
+        let raw = RawImage {
+            version: STATE_IMAGE_VERSION,
+            system: self.system.clone(),
+            step: self.step,
+            states: self
+                .states
+                .iter()
+                .map(|(name, value)| raw_state(name, value))
+                .collect(),
+        };
+        serde_json::to_string_pretty(&raw).expect("state images can always be serialized")
+    }
+
+    pub fn from_json(src: &str) -> Result<Self> {
+        let raw: RawImage = serde_json::from_str(src)?;
+        if raw.version != STATE_IMAGE_VERSION {
+            return Err(StateImageError::UnsupportedVersion(raw.version));
+        }
+        let states = raw
+            .states
+            .into_iter()
+            .map(|s| Ok((s.name().to_string(), s.to_value()?)))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            system: raw.system,
+            step: raw.step,
+            states,
+        })
+    }
+}
+
+fn value_type(value: &Value) -> Type {
+    match value {
+        Value::BitVec(v) => Type::BV(v.width()),
+        Value::Array(v) => Type::Array(ArrayType {
+            index_width: v.index_width(),
+            data_width: v.data_width(),
+        }),
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct RawImage {
+    version: u32,
+    system: String,
+    step: u64,
+    states: Vec<RawState>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum RawState {
+    BitVec {
+        name: String,
+        width: WidthInt,
+        value: String,
+    },
+    Array {
+        name: String,
+        index_width: WidthInt,
+        data_width: WidthInt,
+        default: String,
+        words: Vec<RawWord>,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+struct RawWord {
+    addr: String,
+    data: String,
+}
+
+fn hex(value: &BitVecValue) -> String {
+    format!("0x{}", value.to_hex_str())
+}
+
+fn raw_state(name: &str, value: &Value) -> RawState {
+    let name = name.to_string();
+    match value {
+        Value::BitVec(value) => RawState::BitVec {
+            name,
+            width: value.width(),
+            value: hex(value),
+        },
+        Value::Array(value) => {
+            let sparse: SparseArrayValue = value.into();
+            RawState::Array {
+                name,
+                index_width: value.index_width(),
+                data_width: value.data_width(),
+                default: hex(&sparse.default()),
+                words: sparse
+                    .non_default_entries()
+                    .map(|(addr, data)| RawWord {
+                        addr: hex(&addr),
+                        data: hex(&data),
+                    })
+                    .collect(),
+            }
+        }
+    }
+}
+
+impl RawState {
+    fn name(&self) -> &str {
+        match self {
+            RawState::BitVec { name, .. } | RawState::Array { name, .. } => name,
+        }
+    }
+
+    fn to_value(&self) -> Result<Value> {
+        let parse = |value: &str, width: WidthInt| {
+            parse_value(value, width).ok_or_else(|| StateImageError::InvalidValue {
+                name: self.name().to_string(),
+                value: value.to_string(),
+            })
+        };
+        match self {
+            RawState::BitVec { width, value, .. } => Ok(parse(value, *width)?.into()),
+            RawState::Array {
+                index_width,
+                data_width,
+                default,
+                words,
+                ..
+            } => {
+                let mut array = ArrayValue::new_sparse(*index_width, &parse(default, *data_width)?);
+                for word in words.iter() {
+                    let addr = parse(&word.addr, *index_width)?;
+                    array.store(&addr, &parse(&word.data, *data_width)?);
+                }
+                Ok(array.into())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::{InitKind, Interpreter, Simulator};
+    use crate::system::State;
+
+    #[test]
+    fn test_transplant_state() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("counter".to_string());
+        let count = ctx.bv_symbol("count", 4);
+        let next = ctx.build(|c| c.add(count, c.one(4)));
+        sys.add_state(
+            &ctx,
+            State {
+                symbol: count,
+                init: None,
+                next: Some(next),
+            },
+        );
+
+        let mut a = Interpreter::new(&ctx, &sys);
+        a.init(InitKind::Zero);
+        for _ in 0..5 {
+            a.step();
+        }
+        let json = a.dump_state().to_json();
+
+        let mut b = Interpreter::new(&ctx, &sys);
+        b.init(InitKind::Zero);
+        b.load_state(&StateImage::from_json(&json).unwrap())
+            .unwrap();
+        assert_eq!(b.step_count(), 5);
+        b.step();
+        assert_eq!(b.get(count).try_into_u64().unwrap(), 6);
+
+        let mut other = a.dump_state();
+        other.states[0].0 = "counter".to_string();
+        assert!(matches!(
+            b.load_state(&other),
+            Err(StateImageError::UnknownState(name)) if name == "counter"
+        ));
+    }
+
+    #[test]
+    fn test_state_image_json_round_trip() {
+        let mut mem = ArrayValue::new_sparse(2, &BitVecValue::zero(8));
+        mem.store(&BitVecValue::from_u64(1, 2), &BitVecValue::from_u64(42, 8));
+        let image = StateImage {
+            system: "counter".to_string(),
+            step: 5,
+            states: vec![
+                ("count".to_string(), BitVecValue::from_u64(5, 4).into()),
+                ("mem".to_string(), mem.into()),
+            ],
+        };
+        let json = image.to_json();
+        let parsed = StateImage::from_json(&json).unwrap();
+        assert_eq!(parsed.system, "counter");
+        assert_eq!(parsed.step, 5);
+        assert_eq!(parsed.to_json(), json);
+
+        let future = json.replace("\"version\": 1", "\"version\": 2");
+        assert!(matches!(
+            StateImage::from_json(&future),
+            Err(StateImageError::UnsupportedVersion(2))
+        ));
+    }
+}
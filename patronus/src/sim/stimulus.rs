@@ -0,0 +1,343 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Stimulus Files
+//! Input values for each step of a simulation, addressed by input name. Stimuli can be loaded
+//! from CSV or JSON files, played back on any [`Simulator`] and recorded from a running
+//! simulation, e.g., in order to replay a trace captured by another simulator.
+//!
+//! In CSV files, the header lists the input names and every following line contains the values
+//! for one step. In JSON files, every step is an object that maps input names to values.
+//! Values are decimal, or hexadecimal and binary with a `0x` or `0b` prefix. An `x` or a missing
+//! value leaves the input unchanged.
+
+use super::{SimError, Simulator};
+use crate::expr::{Context, ExprRef, TypeCheck, WidthInt};
+use crate::system::TransitionSystem;
+use baa::{BitVecOps, BitVecValue, Value};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[derive(Debug, thiserror::Error)]
+pub enum StimulusError {
+    #[error("failed to read or write stimulus")]
+    Io(#[from] std::io::Error),
+    #[error("invalid JSON stimulus: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("line {line}: {msg}")]
+    Csv { line: usize, msg: String },
+    #[error("`{0}` is not a bit-vector input of the system")]
+    UnknownInput(String),
+    #[error("step {step}: `{value}` is not a valid value for `{signal}`")]
+    InvalidValue {
+        step: usize,
+        signal: String,
+        value: String,
+    },
+    #[error(transparent)]
+    Sim(#[from] SimError),
+}
+
+type Result<T> = std::result::Result<T, StimulusError>;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stimulus {
+    /// names of all inputs that are assigned in at least one step
+    pub signals: Vec<String>,
+    /// one entry per step and signal, `None` leaves the input unchanged
+    pub steps: Vec<Vec<Option<String>>>,
+}
+
+impl Stimulus {
+    pub fn from_csv(src: &str) -> Result<Self> {
+        let mut lines = src
+            .lines()
+            .enumerate()
+            .filter(|(_, l)| !l.trim().is_empty());
+        let Some((_, header)) = lines.next() else {
+            return Ok(Self::default());
+        };
+        let signals: Vec<String> = header.split(',').map(|s| s.trim().to_string()).collect();
+        let mut steps = vec![];
+        for (ii, line) in lines {
+            let values: Vec<Option<String>> = line.split(',').map(parse_cell).collect();
+            if values.len() != signals.len() {
+                return Err(StimulusError::Csv {
+                    line: ii + 1,
+                    msg: format!("expected {} values, got {}", signals.len(), values.len()),
+                });
+            }
+            steps.push(values);
+        }
+        Ok(Self { signals, steps })
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut out = self.signals.join(",");
+        out.push('\n');
+        for step in self.steps.iter() {
+            let values: Vec<&str> = step.iter().map(|v| v.as_deref().unwrap_or("x")).collect();
+            out.push_str(&values.join(","));
+            out.push('\n');
+        }
+        out
+    }
+
+    pub fn from_json(src: &str) -> Result<Self> {
+        let raw: Vec<BTreeMap<String, serde_json::Value>> = serde_json::from_str(src)?;
+        let mut signals: Vec<String> = vec![];
+        for name in raw.iter().flat_map(|step| step.keys()) {
+            if !signals.contains(name) {
+                signals.push(name.clone());
+            }
+        }
+        let steps = raw
+            .iter()
+            .map(|step| {
+                signals
+                    .iter()
+                    .map(|name| match step.get(name) {
+                        Some(serde_json::Value::String(s)) => parse_cell(s),
+                        Some(serde_json::Value::Number(n)) => Some(n.to_string()),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .collect();
+        Ok(Self { signals, steps })
+    }
+
+    pub fn to_json(&self) -> String {
+        let raw: Vec<BTreeMap<&str, &str>> = self
+            .steps
+            .iter()
+            .map(|step| {
+                self.signals
+                    .iter()
+                    .zip(step.iter())
+                    .flat_map(|(name, value)| Some((name.as_str(), value.as_deref()?)))
+                    .collect()
+            })
+            .collect();
+        serde_json::to_string_pretty(&raw).expect("stimuli can always be serialized")
+    }
+
+    /// Loads a JSON file if the extension is `.json`, otherwise a CSV file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let src = std::fs::read_to_string(path.as_ref())?;
+        if is_json(path.as_ref()) {
+            Self::from_json(&src)
+        } else {
+            Self::from_csv(&src)
+        }
+    }
+
+    /// Saves as JSON if the extension is `.json`, otherwise as CSV.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let out = if is_json(path.as_ref()) {
+            self.to_json()
+        } else {
+            self.to_csv()
+        };
+        std::fs::write(path, out)?;
+        Ok(())
+    }
+
+    /// Applies the inputs of every step, calls `observe` and then advances the simulation.
+    pub fn play<S: Simulator>(
+        &self,
+        ctx: &Context,
+        sys: &TransitionSystem,
+        sim: &mut S,
+        mut observe: impl FnMut(usize, &S),
+    ) -> Result<()> {
+        let inputs = self
+            .signals
+            .iter()
+            .map(|name| lookup_bv_input(ctx, sys, name))
+            .collect::<Result<Vec<_>>>()?;
+        for (step, values) in self.steps.iter().enumerate() {
+            for ((signal, &(input, width)), value) in
+                self.signals.iter().zip(inputs.iter()).zip(values.iter())
+            {
+                let Some(value) = value else {
+                    continue;
+                };
+                let parsed =
+                    parse_value(value, width).ok_or_else(|| StimulusError::InvalidValue {
+                        step,
+                        signal: signal.clone(),
+                        value: value.clone(),
+                    })?;
+                sim.set(input, &parsed)?;
+            }
+            observe(step, sim);
+            sim.step();
+        }
+        Ok(())
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == "json")
+}
+
+fn parse_cell(cell: &str) -> Option<String> {
+    let cell = cell.trim();
+    if cell.is_empty() || cell.eq_ignore_ascii_case("x") {
+        None
+    } else {
+        Some(cell.to_string())
+    }
+}
+
+/// Parses a decimal value, or a hexadecimal or binary value with a `0x` or `0b` prefix.
+pub fn parse_value(value: &str, width: WidthInt) -> Option<BitVecValue> {
+    let (digits, radix) = if let Some(hex) = value.strip_prefix("0x") {
+        (hex, 16)
+    } else if let Some(bin) = value.strip_prefix("0b") {
+        (bin, 2)
+    } else {
+        (value, 10)
+    };
+    BitVecValue::from_str_radix(digits, radix, width).ok()
+}
+
+pub(super) fn lookup_bv_input(
+    ctx: &Context,
+    sys: &TransitionSystem,
+    name: &str,
+) -> Result<(ExprRef, WidthInt)> {
+    sys.lookup_input(ctx, name)
+        .and_then(|i| Some((i, i.get_bv_type(ctx)?)))
+        .ok_or_else(|| StimulusError::UnknownInput(name.to_string()))
+}
+
+/// Records the values of all bit-vector inputs of a system.
+pub struct StimulusRecorder {
+    inputs: Vec<ExprRef>,
+    stimulus: Stimulus,
+}
+
+impl StimulusRecorder {
+    pub fn new(ctx: &Context, sys: &TransitionSystem) -> Self {
+        let inputs: Vec<ExprRef> = sys
+            .inputs
+            .iter()
+            .copied()
+            .filter(|i| i.get_type(ctx).is_bit_vector())
+            .collect();
+        let signals = inputs
+            .iter()
+            .map(|&i| ctx.get_symbol_name(i).unwrap().to_string())
+            .collect();
+        Self {
+            inputs,
+            stimulus: Stimulus {
+                signals,
+                steps: vec![],
+            },
+        }
+    }
+
+    /// Records the current input values as the next step. Call this before stepping the simulator.
+    pub fn record(&mut self, sim: &impl Simulator) {
+        let values = self
+            .inputs
+            .iter()
+            .map(|&i| match sim.get(i) {
+                Value::BitVec(value) => Some(format!("0x{}", value.to_hex_str())),
+                Value::Array(_) => None,
+            })
+            .collect();
+        self.stimulus.steps.push(values);
+    }
+
+    pub fn finish(self) -> Stimulus {
+        self.stimulus
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::{InitKind, Interpreter};
+
+    fn counter(ctx: &mut Context) -> TransitionSystem {
+        let mut sys = TransitionSystem::new("counter".to_string());
+        let en = ctx.bv_symbol("en", 1);
+        let inc = ctx.bv_symbol("inc", 8);
+        sys.add_input(ctx, en);
+        sys.add_input(ctx, inc);
+        let count = ctx.bv_symbol("count", 8);
+        let next = ctx.build(|c| c.ite(en, c.add(count, inc), count));
+        let init = ctx.zero(8);
+        sys.add_state(
+            ctx,
+            crate::system::State {
+                symbol: count,
+                init: Some(init),
+                next: Some(next),
+            },
+        );
+        sys.add_output(ctx, "out".into(), count);
+        sys
+    }
+
+    #[test]
+    fn test_csv_and_json() {
+        let csv = "en, inc\n1, 3\n0, x\n1, 0x10\n";
+        let stimulus = Stimulus::from_csv(csv).unwrap();
+        assert_eq!(stimulus.signals, ["en", "inc"]);
+        assert_eq!(stimulus.steps[1], [Some("0".to_string()), None]);
+        assert_eq!(Stimulus::from_csv(&stimulus.to_csv()).unwrap(), stimulus);
+        assert_eq!(Stimulus::from_json(&stimulus.to_json()).unwrap(), stimulus);
+        let json = r#"[{"en": 1, "inc": "3"}, {"en": 0}]"#;
+        let from_json = Stimulus::from_json(json).unwrap();
+        assert_eq!(from_json.steps, stimulus.steps[..2]);
+        assert!(matches!(
+            Stimulus::from_csv("en,inc\n1\n"),
+            Err(StimulusError::Csv { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_play_and_record() {
+        let mut ctx = Context::default();
+        let sys = counter(&mut ctx);
+        let out = sys.lookup_output(&ctx, "out").unwrap();
+        let stimulus = Stimulus::from_csv("en,inc\n1,3\n0,x\n1,0x10\n0,0\n").unwrap();
+
+        let mut sim = Interpreter::new(&ctx, &sys);
+        sim.init(InitKind::Zero);
+        let mut recorder = StimulusRecorder::new(&ctx, &sys);
+        let mut outputs = vec![];
+        stimulus
+            .play(&ctx, &sys, &mut sim, |_, sim| {
+                recorder.record(sim);
+                outputs.push(sim.get(out).try_into_u64().unwrap());
+            })
+            .unwrap();
+        assert_eq!(outputs, [0, 3, 3, 19]);
+
+        // replaying the recording produces the same outputs
+        let recorded = recorder.finish();
+        assert_eq!(recorded.steps.len(), 4);
+        let mut sim = Interpreter::new(&ctx, &sys);
+        sim.init(InitKind::Zero);
+        let mut replayed = vec![];
+        recorded
+            .play(&ctx, &sys, &mut sim, |_, sim| {
+                replayed.push(sim.get(out).try_into_u64().unwrap())
+            })
+            .unwrap();
+        assert_eq!(replayed, outputs);
+
+        let unknown = Stimulus::from_csv("foo\n1\n").unwrap();
+        assert!(matches!(
+            unknown.play(&ctx, &sys, &mut sim, |_, _| {}),
+            Err(StimulusError::UnknownInput(ref n)) if n == "foo"
+        ));
+    }
+}
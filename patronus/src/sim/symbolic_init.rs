@@ -0,0 +1,172 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Constrained Initial States
+//! Some designs have no reset state in the transition system, but starting from all zeros is
+//! not legal either. Here, an SMT solver picks an initial assignment that satisfies a set of
+//! constraints over the state symbols, which is then installed in the simulator.
+
+use super::{InitKind, SimError, Simulator};
+use crate::equiv::collect_symbols;
+use crate::expr::{Context, ExprRef, TypeCheck};
+use crate::mc::get_smt_value;
+use crate::random::new_rng;
+use crate::smt::{CheckSatResponse, Logic, SmtLibSolver, Solver, SolverContext, SolverMetaData};
+use crate::system::TransitionSystem;
+use baa::{BitVecValue, Value};
+
+#[derive(Debug, thiserror::Error)]
+pub enum InitError {
+    #[error("the initial state constraints are unsatisfiable")]
+    Unsat,
+    #[error(transparent)]
+    Smt(#[from] crate::smt::Error),
+}
+
+pub type InitResult<T> = std::result::Result<T, InitError>;
+
+/// Finds values for all bit-vector states that satisfy `constraints` as well as the init
+/// expressions of `sys`. Without a `seed`, the solver picks whatever value it finds first.
+/// With a `seed`, each uninitialized state is pulled towards a random value whenever the
+/// constraints allow it, so that different seeds lead to different initial states.
+pub fn solve_init(
+    ctx: &mut Context,
+    sys: &TransitionSystem,
+    constraints: &[ExprRef],
+    solver: &SmtLibSolver,
+    seed: Option<u64>,
+) -> InitResult<Vec<(ExprRef, BitVecValue)>> {
+    let mut smt_ctx = solver.start(None::<std::fs::File>)?;
+    smt_ctx.set_logic(if solver.supports_uf() {
+        Logic::QfAufbv
+    } else {
+        Logic::QfAbv
+    })?;
+    let init_constraints: Vec<ExprRef> = sys
+        .states
+        .iter()
+        .flat_map(|s| s.init.map(|init| ctx.equal(s.symbol, init)))
+        .chain(constraints.iter().copied())
+        .collect();
+    let states = sys.states.iter().map(|s| s.symbol);
+    for symbol in collect_symbols(ctx, states.chain(init_constraints.iter().copied())) {
+        smt_ctx.declare_const(ctx, symbol)?;
+    }
+    for &constraint in init_constraints.iter() {
+        smt_ctx.assert(ctx, constraint)?;
+    }
+    if smt_ctx.check_sat()? != CheckSatResponse::Sat {
+        return Err(InitError::Unsat);
+    }
+
+    if let Some(seed) = seed {
+        let mut rng = new_rng(seed);
+        for state in sys.states.iter().filter(|s| s.init.is_none()) {
+            let Some(width) = state.symbol.get_bv_type(ctx) else {
+                continue;
+            };
+            let value = BitVecValue::random(&mut rng, width);
+            let hint = ctx.build(|c| c.equal(state.symbol, c.bv_lit(&value)));
+            smt_ctx.push()?;
+            smt_ctx.assert(ctx, hint)?;
+            let sat = smt_ctx.check_sat()? == CheckSatResponse::Sat;
+            smt_ctx.pop()?;
+            if sat {
+                smt_ctx.assert(ctx, hint)?;
+            }
+        }
+        // the model needs to be recomputed after the last pop
+        smt_ctx.check_sat()?;
+    }
+
+    let mut values = vec![];
+    for state in sys.states.iter() {
+        if state.symbol.get_type(ctx).is_bit_vector() {
+            if let Value::BitVec(value) = get_smt_value(ctx, &mut smt_ctx, state.symbol)? {
+                values.push((state.symbol, value));
+            }
+        }
+    }
+    Ok(values)
+}
+
+/// Initializes `sim` with zeros and then overrides states with the values found by
+/// [`solve_init`]. States that do not appear in `values`, like arrays, remain zero.
+pub fn install_init(
+    sim: &mut impl Simulator,
+    values: &[(ExprRef, BitVecValue)],
+) -> Result<(), SimError> {
+    sim.init(InitKind::Zero);
+    for (symbol, value) in values.iter() {
+        sim.set(*symbol, value)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::Interpreter;
+    use crate::smt::BITWUZLA;
+    use crate::system::State;
+    use baa::BitVecOps;
+
+    /// A one-hot state machine without a reset state.
+    fn one_hot(ctx: &mut Context) -> (TransitionSystem, ExprRef) {
+        let mut sys = TransitionSystem::new("one_hot".into());
+        let state = ctx.bv_symbol("state", 4);
+        let next = ctx.build(|c| c.concat(c.slice(state, 2, 0), c.slice(state, 3, 3)));
+        sys.add_state(
+            ctx,
+            State {
+                symbol: state,
+                init: None,
+                next: Some(next),
+            },
+        );
+        let is_one_hot = ctx.build(|c| {
+            let minus_one = c.sub(state, c.one(4));
+            c.and(
+                c.not(c.equal(state, c.zero(4))),
+                c.equal(c.and(state, minus_one), c.zero(4)),
+            )
+        });
+        (sys, is_one_hot)
+    }
+
+    #[test]
+    fn test_one_hot_init() {
+        let mut ctx = Context::default();
+        let (sys, is_one_hot) = one_hot(&mut ctx);
+        let state = sys.states[0].symbol;
+        for seed in [None, Some(0), Some(1)] {
+            let values = solve_init(&mut ctx, &sys, &[is_one_hot], &BITWUZLA, seed).unwrap();
+            assert_eq!(values.len(), 1);
+            assert_eq!(values[0].1.to_u64().unwrap().count_ones(), 1);
+        }
+
+        let values = solve_init(&mut ctx, &sys, &[is_one_hot], &BITWUZLA, None).unwrap();
+        let mut sim = Interpreter::new(&ctx, &sys);
+        install_init(&mut sim, &values).unwrap();
+        for _ in 0..8 {
+            sim.step();
+            let Value::BitVec(value) = sim.get(state) else {
+                unreachable!()
+            };
+            assert_eq!(value.to_u64().unwrap().count_ones(), 1);
+        }
+    }
+
+    #[test]
+    fn test_unsat() {
+        let mut ctx = Context::default();
+        let (sys, is_one_hot) = one_hot(&mut ctx);
+        let state = sys.states[0].symbol;
+        let zero = ctx.build(|c| c.equal(state, c.zero(4)));
+        assert!(matches!(
+            solve_init(&mut ctx, &sys, &[is_one_hot, zero], &BITWUZLA, None),
+            Err(InitError::Unsat)
+        ));
+    }
+}
@@ -0,0 +1,51 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+use crate::expr::ExprRef;
+use baa::Value;
+use rustc_hash::FxHashMap;
+use std::cell::RefCell;
+
+/// Combinational values that were computed by the last `update`. Mirrors an RTL simulator
+/// where outputs only change once the design has settled.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TwoPhaseCache {
+    values: FxHashMap<ExprRef, Value>,
+    /// an input was changed since the last update
+    dirty: bool,
+    stale_reads: RefCell<Vec<StaleRead>>,
+}
+
+impl TwoPhaseCache {
+    pub(crate) fn invalidate(&mut self) {
+        self.dirty = true;
+    }
+
+    pub(crate) fn store(&mut self, values: impl Iterator<Item = (ExprRef, Value)>) {
+        self.values.clear();
+        self.values.extend(values);
+        self.dirty = false;
+    }
+
+    /// Returns the cached value and records the read if the cache is out of date.
+    /// Returns `None` for expressions that are not cached.
+    pub(crate) fn read(&self, step: u64, expr: ExprRef) -> Option<Value> {
+        let value = self.values.get(&expr)?;
+        if self.dirty {
+            self.stale_reads.borrow_mut().push(StaleRead { step, expr });
+        }
+        Some(value.clone())
+    }
+
+    pub(crate) fn stale_reads(&self) -> Vec<StaleRead> {
+        self.stale_reads.borrow().clone()
+    }
+}
+
+/// A combinational signal was read after an input changed, but before `update` was called.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StaleRead {
+    pub step: u64,
+    pub expr: ExprRef,
+}
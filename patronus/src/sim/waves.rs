@@ -0,0 +1,230 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Waves
+//! Records the values of bit-vector signals over the course of a simulation. A value is only
+//! stored when it differs from the previous step, which bounds the memory used by long runs
+//! to the number of changes rather than the number of steps. Values at any step are
+//! reconstructed with a binary search, or step by step through [`Waves::iter_from`].
+//! Array states are recorded by the [`MemoryRecorder`](super::MemoryRecorder).
+
+use super::Simulator;
+use crate::expr::{Context, ExprRef, TypeCheck, WidthInt};
+use crate::system::TransitionSystem;
+use baa::{BitVecOps, BitVecValue, Value};
+
+/// Value changes of a single signal.
+#[derive(Debug, Clone)]
+pub struct SignalWaves {
+    pub name: String,
+    pub width: WidthInt,
+    /// steps at which the value changed, in ascending order, the first entry is for step 0
+    pub changes: Vec<(u64, BitVecValue)>,
+}
+
+impl SignalWaves {
+    /// Value at `step`. Returns `None` if nothing was recorded yet.
+    pub fn value_at(&self, step: u64) -> Option<&BitVecValue> {
+        let after = self.changes.partition_point(|(s, _)| *s <= step);
+        after.checked_sub(1).map(|ii| &self.changes[ii].1)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Waves {
+    pub signals: Vec<SignalWaves>,
+    /// number of recorded steps
+    len: u64,
+}
+
+impl Waves {
+    /// `signals` need to start with a value at step 0 and may not change after `len` steps.
+    pub(super) fn new(signals: Vec<SignalWaves>, len: u64) -> Self {
+        Self { signals, len }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&SignalWaves> {
+        self.signals.iter().find(|s| s.name == name)
+    }
+
+    /// Value of the signal called `name` at `step`. Returns `None` if there is no such signal
+    /// or the step was not recorded.
+    pub fn value_at(&self, name: &str, step: u64) -> Option<&BitVecValue> {
+        if step >= self.len {
+            return None;
+        }
+        self.get(name)?.value_at(step)
+    }
+
+    /// Number of recorded steps.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Total number of stored values.
+    pub fn num_changes(&self) -> usize {
+        self.signals.iter().map(|s| s.changes.len()).sum()
+    }
+
+    /// Iterates over the values of all signals, starting at `step`. Every item contains one value
+    /// per signal, in the same order as [`Waves::signals`].
+    pub fn iter_from(&self, step: u64) -> WavesIter<'_> {
+        let cursors = self
+            .signals
+            .iter()
+            .map(|s| s.changes.partition_point(|(s, _)| *s <= step))
+            .collect();
+        WavesIter {
+            waves: self,
+            step,
+            cursors,
+        }
+    }
+}
+
+/// Reconstructs the values of all signals step by step, see [`Waves::iter_from`].
+pub struct WavesIter<'a> {
+    waves: &'a Waves,
+    step: u64,
+    /// index of the first change that lies after the current step
+    cursors: Vec<usize>,
+}
+
+impl<'a> Iterator for WavesIter<'a> {
+    type Item = Vec<&'a BitVecValue>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.step >= self.waves.len {
+            return None;
+        }
+        let (waves, step) = (self.waves, self.step);
+        let values = waves
+            .signals
+            .iter()
+            .zip(self.cursors.iter_mut())
+            .map(|(signal, cursor)| {
+                while signal.changes.get(*cursor).is_some_and(|(s, _)| *s <= step) {
+                    *cursor += 1;
+                }
+                &signal.changes[*cursor - 1].1
+            })
+            .collect();
+        self.step += 1;
+        Some(values)
+    }
+}
+
+/// Records the values of bit-vector signals, only keeping changes.
+pub struct WaveRecorder {
+    exprs: Vec<ExprRef>,
+    waves: Waves,
+}
+
+impl WaveRecorder {
+    /// Records the given signals, names are used to look them up later.
+    pub fn new(ctx: &Context, signals: impl IntoIterator<Item = (String, ExprRef)>) -> Self {
+        let (exprs, signals) = signals
+            .into_iter()
+            .map(|(name, e)| {
+                let width = e
+                    .get_bv_type(ctx)
+                    .unwrap_or_else(|| panic!("{name} is not a bit-vector"));
+                let signal = SignalWaves {
+                    name,
+                    width,
+                    changes: vec![],
+                };
+                (e, signal)
+            })
+            .unzip();
+        Self {
+            exprs,
+            waves: Waves { signals, len: 0 },
+        }
+    }
+
+    /// Records all bit-vector inputs, states and outputs of a system.
+    pub fn for_system(ctx: &Context, sys: &TransitionSystem) -> Self {
+        let symbols = sys
+            .inputs
+            .iter()
+            .copied()
+            .chain(sys.states.iter().map(|s| s.symbol))
+            .filter(|e| e.get_type(ctx).is_bit_vector())
+            .map(|e| (ctx.get_symbol_name(e).unwrap().to_string(), e));
+        let outputs = sys
+            .outputs
+            .iter()
+            .filter(|o| o.expr.get_type(ctx).is_bit_vector())
+            .map(|o| (ctx[o.name].to_string(), o.expr));
+        Self::new(ctx, symbols.chain(outputs).collect::<Vec<_>>())
+    }
+
+    /// Records the current values as the next step. Call this before stepping the simulator.
+    pub fn record(&mut self, sim: &impl Simulator) {
+        let step = self.waves.len;
+        for (&e, signal) in self.exprs.iter().zip(self.waves.signals.iter_mut()) {
+            let Value::BitVec(value) = sim.get(e) else {
+                unreachable!("{} is a bit-vector", signal.name)
+            };
+            let unchanged =
+                matches!(signal.changes.last(), Some((_, prev)) if prev.is_equal(&value));
+            if !unchanged {
+                signal.changes.push((step, value));
+            }
+        }
+        self.waves.len += 1;
+    }
+
+    pub fn finish(self) -> Waves {
+        self.waves
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::examples::fifo;
+    use crate::sim::{InitKind, Interpreter};
+
+    #[test]
+    fn test_record_fifo_waves() {
+        let (ctx, sys) = fifo(4, 8);
+        let push = sys.lookup_input(&ctx, "push").unwrap();
+        let data_in = sys.lookup_input(&ctx, "data_in").unwrap();
+        let mut sim = Interpreter::new(&ctx, &sys);
+        sim.init(InitKind::Zero);
+        let mut recorder = WaveRecorder::for_system(&ctx, &sys);
+        sim.set(push, &BitVecValue::from_u64(1, 1)).unwrap();
+        sim.set(data_in, &BitVecValue::from_u64(7, 8)).unwrap();
+        for _ in 0..100 {
+            recorder.record(&sim);
+            sim.step();
+        }
+        let waves = recorder.finish();
+        assert_eq!(waves.len(), 100);
+
+        // constant inputs are only stored once
+        assert_eq!(waves.get("data_in").unwrap().changes.len(), 1);
+        assert_eq!(waves.get("push").unwrap().changes.len(), 1);
+        assert!(waves.num_changes() < waves.signals.len() * 100);
+        assert_eq!(waves.value_at("data_in", 99).unwrap().to_u64(), Some(7));
+        assert_eq!(waves.value_at("data_in", 100), None);
+        assert_eq!(waves.value_at("foo", 0), None);
+
+        // the iterator agrees with random access
+        let iterated: Vec<_> = waves.iter_from(3).collect();
+        assert_eq!(iterated.len(), 97);
+        for (offset, values) in iterated.iter().enumerate() {
+            for (signal, value) in waves.signals.iter().zip(values.iter()) {
+                let expected = signal.value_at(3 + offset as u64).unwrap();
+                assert!(value.is_equal(expected), "{}", signal.name);
+            }
+        }
+    }
+}
@@ -0,0 +1,269 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Wide Bit-Vector Kernels
+//! Operations on values with more than [`WIDE_THRESHOLD`] bits, as found in crypto datapaths,
+//! spend most of their time in loops over the words of their operands. The kernels in this
+//! module work in place on the destination register, instead of allocating a new value for
+//! every result, and are written such that the compiler can vectorize them.
+//! They are compiled twice: once for the baseline target and once with AVX2 enabled. Which
+//! version is used is decided at runtime, by checking what the CPU supports.
+
+use crate::expr::WidthInt;
+use baa::{BitVecMutOps, BitVecOps, BitVecValue, Word};
+
+/// Operands with more bits than this are evaluated with the kernels in this module.
+pub(crate) const WIDE_THRESHOLD: WidthInt = 256;
+
+const WORD_BITS: usize = Word::BITS as usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum WideOp {
+    And,
+    Or,
+    Xor,
+    Add,
+    Sub,
+    Equal,
+    Greater,
+    ShiftLeft,
+    ShiftRight,
+}
+
+impl WideOp {
+    /// All operations, in the order in which they are declared.
+    pub(crate) const ALL: [WideOp; 9] = [
+        WideOp::And,
+        WideOp::Or,
+        WideOp::Xor,
+        WideOp::Add,
+        WideOp::Sub,
+        WideOp::Equal,
+        WideOp::Greater,
+        WideOp::ShiftLeft,
+        WideOp::ShiftRight,
+    ];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Kernels {
+    Scalar,
+    #[cfg(target_arch = "x86_64")]
+    Avx2,
+}
+
+impl Kernels {
+    /// Fastest kernels supported by the CPU we are running on.
+    pub(crate) fn detect() -> Self {
+        #[cfg(target_arch = "x86_64")]
+        if is_x86_feature_detected!("avx2") {
+            return Self::Avx2;
+        }
+        Self::Scalar
+    }
+}
+
+/// Computes `op` on `a` and `b` and stores the result in `dst`, which needs to have the width
+/// of the result.
+pub(crate) fn eval(
+    kernels: Kernels,
+    op: WideOp,
+    dst: &mut BitVecValue,
+    a: &BitVecValue,
+    b: &BitVecValue,
+) {
+    let width = dst.width();
+    match kernels {
+        Kernels::Scalar => eval_words(op, width, dst.words_mut(), a.words(), b.words()),
+        #[cfg(target_arch = "x86_64")]
+        // SAFETY: `Kernels::Avx2` is only returned by `detect` if the CPU supports AVX2
+        Kernels::Avx2 => unsafe { eval_avx2(op, width, dst.words_mut(), a.words(), b.words()) },
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn eval_avx2(op: WideOp, width: WidthInt, dst: &mut [Word], a: &[Word], b: &[Word]) {
+    eval_words(op, width, dst, a, b)
+}
+
+#[inline(always)]
+fn eval_words(op: WideOp, width: WidthInt, dst: &mut [Word], a: &[Word], b: &[Word]) {
+    match op {
+        WideOp::And => bitwise(dst, a, b, |x, y| x & y),
+        WideOp::Or => bitwise(dst, a, b, |x, y| x | y),
+        WideOp::Xor => bitwise(dst, a, b, |x, y| x ^ y),
+        WideOp::Add => {
+            add(dst, a, b);
+            mask_msb(dst, width);
+        }
+        WideOp::Sub => {
+            sub(dst, a, b);
+            mask_msb(dst, width);
+        }
+        WideOp::Equal => dst[0] = is_equal(a, b) as Word,
+        WideOp::Greater => dst[0] = is_greater(a, b) as Word,
+        WideOp::ShiftLeft => match shift_amount(b, width) {
+            Some(by) => {
+                shift_left(dst, a, by);
+                mask_msb(dst, width);
+            }
+            None => dst.fill(0),
+        },
+        WideOp::ShiftRight => match shift_amount(b, width) {
+            Some(by) => shift_right(dst, a, by),
+            None => dst.fill(0),
+        },
+    }
+}
+
+#[inline(always)]
+fn bitwise(dst: &mut [Word], a: &[Word], b: &[Word], f: impl Fn(Word, Word) -> Word) {
+    for ((d, &x), &y) in dst.iter_mut().zip(a.iter()).zip(b.iter()) {
+        *d = f(x, y);
+    }
+}
+
+#[inline(always)]
+fn add(dst: &mut [Word], a: &[Word], b: &[Word]) {
+    let mut carry = false;
+    for ((d, &x), &y) in dst.iter_mut().zip(a.iter()).zip(b.iter()) {
+        let (sum, c0) = x.overflowing_add(y);
+        let (sum, c1) = sum.overflowing_add(carry as Word);
+        *d = sum;
+        carry = c0 | c1;
+    }
+}
+
+#[inline(always)]
+fn sub(dst: &mut [Word], a: &[Word], b: &[Word]) {
+    let mut borrow = false;
+    for ((d, &x), &y) in dst.iter_mut().zip(a.iter()).zip(b.iter()) {
+        let (diff, b0) = x.overflowing_sub(y);
+        let (diff, b1) = diff.overflowing_sub(borrow as Word);
+        *d = diff;
+        borrow = b0 | b1;
+    }
+}
+
+/// Reduces over all words without an early exit, which allows the loop to be vectorized.
+#[inline(always)]
+fn is_equal(a: &[Word], b: &[Word]) -> bool {
+    a.iter()
+        .zip(b.iter())
+        .fold(0, |acc, (&x, &y)| acc | (x ^ y))
+        == 0
+}
+
+/// Unsigned comparison, the first word that differs, starting at the msb, decides.
+#[inline(always)]
+fn is_greater(a: &[Word], b: &[Word]) -> bool {
+    a.iter()
+        .zip(b.iter())
+        .rev()
+        .find(|(x, y)| x != y)
+        .is_some_and(|(x, y)| x > y)
+}
+
+/// `None` if every bit is shifted out.
+#[inline(always)]
+fn shift_amount(b: &[Word], width: WidthInt) -> Option<usize> {
+    if b[1..].iter().any(|&w| w != 0) || b[0] >= width as Word {
+        None
+    } else {
+        Some(b[0] as usize)
+    }
+}
+
+#[inline(always)]
+fn shift_left(dst: &mut [Word], a: &[Word], by: usize) {
+    let (words, bits) = (by / WORD_BITS, by % WORD_BITS);
+    for (ii, d) in dst.iter_mut().enumerate() {
+        *d = if ii < words {
+            0
+        } else if bits == 0 {
+            a[ii - words]
+        } else {
+            let lower = if ii > words {
+                a[ii - words - 1] >> (WORD_BITS - bits)
+            } else {
+                0
+            };
+            (a[ii - words] << bits) | lower
+        };
+    }
+}
+
+#[inline(always)]
+fn shift_right(dst: &mut [Word], a: &[Word], by: usize) {
+    let (words, bits) = (by / WORD_BITS, by % WORD_BITS);
+    let len = dst.len();
+    for (ii, d) in dst.iter_mut().enumerate() {
+        *d = if ii + words >= len {
+            0
+        } else if bits == 0 {
+            a[ii + words]
+        } else {
+            let upper = if ii + words + 1 < len {
+                a[ii + words + 1] << (WORD_BITS - bits)
+            } else {
+                0
+            };
+            (a[ii + words] >> bits) | upper
+        };
+    }
+}
+
+/// Clears the bits above `width` that a carry or a shift might have set.
+#[inline(always)]
+fn mask_msb(dst: &mut [Word], width: WidthInt) {
+    let bits = width as usize % WORD_BITS;
+    if bits != 0 {
+        if let Some(msb) = dst.last_mut() {
+            *msb &= Word::MAX >> (WORD_BITS - bits);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::new_rng;
+    use rand::Rng;
+
+    #[test]
+    fn test_wide_kernels_match_baa() {
+        let mut rng = new_rng(1);
+        let mut kernels = vec![Kernels::Scalar];
+        if Kernels::detect() != Kernels::Scalar {
+            kernels.push(Kernels::detect());
+        }
+        for width in [257, 320, 1000] {
+            for _ in 0..20 {
+                let a = BitVecValue::random(&mut rng, width);
+                let b = BitVecValue::random(&mut rng, width);
+                let by = BitVecValue::from_u64(rng.gen_range(0..width as u64 + 8), width);
+                let expected = [
+                    (WideOp::And, a.and(&b), &b),
+                    (WideOp::Or, a.or(&b), &b),
+                    (WideOp::Xor, a.xor(&b), &b),
+                    (WideOp::Add, a.add(&b), &b),
+                    (WideOp::Sub, a.sub(&b), &b),
+                    (WideOp::Equal, a.is_equal(&b).into(), &b),
+                    (WideOp::Equal, a.is_equal(&a).into(), &a),
+                    (WideOp::Greater, a.is_greater(&b).into(), &b),
+                    (WideOp::ShiftLeft, a.shift_left(&by), &by),
+                    (WideOp::ShiftRight, a.shift_right(&by), &by),
+                ];
+                for &k in kernels.iter() {
+                    for (op, value, rhs) in expected.iter() {
+                        let mut dst = BitVecValue::zero(value.width());
+                        eval(k, *op, &mut dst, &a, rhs);
+                        assert_eq!(&dst, value, "{op:?} on {width} bits with {k:?}");
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,410 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # X-Propagation
+//! Simulates a transition system with three valued logic: every bit is either `0`, `1` or
+//! unknown (`X`). States without an init value start out as `X`. Bitwise operations, slices,
+//! extensions and multiplexers track unknown bits precisely, all other operations are
+//! conservative: as soon as a single input bit is unknown, the whole result is unknown.
+//! Arrays are either completely known or completely unknown.
+//!
+//! [`check_reset_coverage`] uses the simulation to find registers that are not reset.
+
+use crate::expr::*;
+use crate::system::TransitionSystem;
+use baa::*;
+use rustc_hash::FxHashMap;
+use std::fmt::Write;
+
+/// Three valued bit-vector or array.
+#[derive(Debug, Clone)]
+pub enum XValue {
+    /// `known` contains a one for every bit of `value` that is not `X`
+    BitVec {
+        value: BitVecValue,
+        known: BitVecValue,
+    },
+    /// `None` if the contents of the array are unknown
+    Array(Option<ArrayValue>),
+}
+
+impl XValue {
+    pub fn known(value: Value) -> Self {
+        match value {
+            Value::BitVec(value) => {
+                let known = BitVecValue::ones(value.width());
+                XValue::BitVec { value, known }
+            }
+            Value::Array(value) => XValue::Array(Some(value)),
+        }
+    }
+
+    pub fn unknown(tpe: Type) -> Self {
+        match tpe {
+            Type::BV(width) => XValue::BitVec {
+                value: BitVecValue::zero(width),
+                known: BitVecValue::zero(width),
+            },
+            Type::Array(_) => XValue::Array(None),
+        }
+    }
+
+    /// Returns `true` iff no bit is `X`.
+    pub fn is_known(&self) -> bool {
+        match self {
+            XValue::BitVec { known, .. } => known.is_equal(&BitVecValue::ones(known.width())),
+            XValue::Array(value) => value.is_some(),
+        }
+    }
+
+    /// Returns the value if no bit is `X`.
+    pub fn to_value(&self) -> Option<Value> {
+        match self {
+            XValue::BitVec { value, .. } if self.is_known() => Some(value.clone().into()),
+            XValue::Array(Some(value)) => Some(value.clone().into()),
+            _ => None,
+        }
+    }
+
+    fn bits(&self) -> (&BitVecValue, &BitVecValue) {
+        match self {
+            XValue::BitVec { value, known } => (value, known),
+            XValue::Array(_) => unreachable!("expected a bit-vector"),
+        }
+    }
+}
+
+/// Simulator that tracks unknown bits.
+pub struct XSimulator<'a> {
+    ctx: &'a Context,
+    sys: &'a TransitionSystem,
+    /// values of inputs and states
+    symbols: FxHashMap<ExprRef, XValue>,
+    /// values of all expressions evaluated in the current step
+    values: FxHashMap<ExprRef, XValue>,
+    step_count: u64,
+}
+
+impl<'a> XSimulator<'a> {
+    /// All inputs start out as `X`. States are initialized with their init expression,
+    /// or `X` if they do not have one.
+    pub fn new(ctx: &'a Context, sys: &'a TransitionSystem) -> Self {
+        let mut sim = Self {
+            ctx,
+            sys,
+            symbols: FxHashMap::default(),
+            values: FxHashMap::default(),
+            step_count: 0,
+        };
+        let init: Vec<_> = sys
+            .states
+            .iter()
+            .map(|s| match s.init {
+                Some(init) => (s.symbol, sim.eval(init)),
+                None => (s.symbol, XValue::unknown(s.symbol.get_type(ctx))),
+            })
+            .collect();
+        sim.symbols.extend(init);
+        sim.values.clear();
+        sim
+    }
+
+    pub fn set(&mut self, input: ExprRef, value: Value) {
+        self.symbols.insert(input, XValue::known(value));
+        self.values.clear();
+    }
+
+    /// Makes all bits of the input unknown.
+    pub fn set_x(&mut self, input: ExprRef) {
+        let value = XValue::unknown(input.get_type(self.ctx));
+        self.symbols.insert(input, value);
+        self.values.clear();
+    }
+
+    pub fn step(&mut self) {
+        let next: Vec<_> = self
+            .sys
+            .states
+            .iter()
+            .map(|s| match s.next {
+                Some(next) => (s.symbol, self.eval(next)),
+                // states without a next function are treated like inputs
+                None => (s.symbol, XValue::unknown(s.symbol.get_type(self.ctx))),
+            })
+            .collect();
+        self.symbols.extend(next);
+        self.values.clear();
+        self.step_count += 1;
+    }
+
+    pub fn get(&mut self, expr: ExprRef) -> XValue {
+        self.eval(expr)
+    }
+
+    pub fn step_count(&self) -> u64 {
+        self.step_count
+    }
+
+    fn eval(&mut self, expr: ExprRef) -> XValue {
+        let mut todo = vec![(expr, false)];
+        while let Some((e, children_done)) = todo.pop() {
+            if self.values.contains_key(&e) {
+                continue;
+            }
+            if children_done {
+                let value = self.eval_node(e);
+                self.values.insert(e, value);
+            } else {
+                todo.push((e, true));
+                self.ctx[e].for_each_child(|c| {
+                    if !self.values.contains_key(c) {
+                        todo.push((*c, false));
+                    }
+                });
+            }
+        }
+        self.values[&expr].clone()
+    }
+
+    /// Evaluates a single expression whose children have already been evaluated.
+    fn eval_node(&self, e: ExprRef) -> XValue {
+        let v = |e: &ExprRef| &self.values[e];
+        let bits = |value: BitVecValue, known: BitVecValue| XValue::BitVec { value, known };
+        match &self.ctx[e] {
+            Expr::BVSymbol { .. } | Expr::ArraySymbol { .. } => self
+                .symbols
+                .get(&e)
+                .cloned()
+                .unwrap_or_else(|| XValue::unknown(e.get_type(self.ctx))),
+            Expr::BVNot(a, _) => {
+                let (a, ka) = v(a).bits();
+                bits(a.not(), ka.clone())
+            }
+            Expr::BVAnd(a, b, _) => {
+                let ((a, ka), (b, kb)) = (v(a).bits(), v(b).bits());
+                // a known zero on either side determines the result
+                let known = ka.and(kb).or(&ka.and(&a.not())).or(&kb.and(&b.not()));
+                bits(a.and(b), known)
+            }
+            Expr::BVOr(a, b, _) => {
+                let ((a, ka), (b, kb)) = (v(a).bits(), v(b).bits());
+                // a known one on either side determines the result
+                let known = ka.and(kb).or(&ka.and(a)).or(&kb.and(b));
+                bits(a.or(b), known)
+            }
+            Expr::BVXor(a, b, _) => {
+                let ((a, ka), (b, kb)) = (v(a).bits(), v(b).bits());
+                bits(a.xor(b), ka.and(kb))
+            }
+            Expr::BVConcat(a, b, _) => {
+                let ((a, ka), (b, kb)) = (v(a).bits(), v(b).bits());
+                bits(a.concat(b), ka.concat(kb))
+            }
+            Expr::BVSlice { e, hi, lo } => {
+                let (a, ka) = v(e).bits();
+                bits(a.slice(*hi, *lo), ka.slice(*hi, *lo))
+            }
+            Expr::BVZeroExt { e, by, .. } => {
+                let (a, ka) = v(e).bits();
+                // the added zeros are always known
+                bits(a.zero_extend(*by), BitVecValue::ones(*by).concat(ka))
+            }
+            Expr::BVSignExt { e, by, .. } => {
+                let (a, ka) = v(e).bits();
+                bits(a.sign_extend(*by), ka.sign_extend(*by))
+            }
+            Expr::BVIte { cond, tru, fals } => match v(cond).to_value() {
+                Some(Value::BitVec(c)) if c.is_zero() => v(fals).clone(),
+                Some(_) => v(tru).clone(),
+                None => {
+                    // bits on which both sides agree are known
+                    let ((t, kt), (f, kf)) = (v(tru).bits(), v(fals).bits());
+                    bits(t.clone(), kt.and(kf).and(&t.xor(f).not()))
+                }
+            },
+            Expr::ArrayIte { cond, tru, fals } => match v(cond).to_value() {
+                Some(Value::BitVec(c)) if c.is_zero() => v(fals).clone(),
+                Some(_) => v(tru).clone(),
+                None => XValue::Array(None),
+            },
+            other => {
+                let mut all_known = true;
+                other.for_each_child(|c| all_known &= v(c).is_known());
+                if all_known {
+                    XValue::known(eval_expr(self.ctx, &KnownValues(&self.values), e))
+                } else {
+                    XValue::unknown(e.get_type(self.ctx))
+                }
+            }
+        }
+    }
+}
+
+/// Provides the values of already evaluated children to the concrete evaluator.
+struct KnownValues<'a>(&'a FxHashMap<ExprRef, XValue>);
+
+impl GetExprValue for KnownValues<'_> {
+    fn get_bv(&self, _ctx: &Context, symbol: ExprRef) -> Option<BitVecValue> {
+        match self.0.get(&symbol)?.to_value()? {
+            Value::BitVec(value) => Some(value),
+            Value::Array(_) => None,
+        }
+    }
+
+    fn get_array(&self, _ctx: &Context, symbol: ExprRef) -> Option<ArrayValue> {
+        match self.0.get(&symbol)? {
+            XValue::Array(value) => value.clone(),
+            XValue::BitVec { .. } => None,
+        }
+    }
+}
+
+/// A state that still contains unknown bits after reset.
+#[derive(Debug, Clone)]
+pub struct UnresetState {
+    pub symbol: ExprRef,
+    pub name: String,
+    /// ones mark the bits that are `X`, `None` for arrays
+    pub unknown_bits: Option<BitVecValue>,
+    /// the expression that defines the next value of the state
+    pub next: Option<ExprRef>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ResetCoverage {
+    pub unreset: Vec<UnresetState>,
+}
+
+impl ResetCoverage {
+    pub fn is_complete(&self) -> bool {
+        self.unreset.is_empty()
+    }
+
+    /// Lists every unreset state together with its next state function.
+    pub fn report(&self, ctx: &Context) -> String {
+        let mut out = String::new();
+        for state in self.unreset.iter() {
+            let next = match state.next {
+                Some(next) => next.serialize_to_str(ctx),
+                None => "(no next state)".to_string(),
+            };
+            match &state.unknown_bits {
+                Some(bits) => writeln!(out, "{} (X: 0b{}): {next}", state.name, bits.to_bit_str()),
+                None => writeln!(out, "{} (X): {next}", state.name),
+            }
+            .unwrap();
+        }
+        out
+    }
+}
+
+/// Applies the reset sequence, one entry of input assignments per step, and reports all states
+/// that are not completely known afterwards. Inputs that are not assigned in a step are `X`.
+pub fn check_reset_coverage(
+    ctx: &Context,
+    sys: &TransitionSystem,
+    reset: &[Vec<(ExprRef, BitVecValue)>],
+) -> ResetCoverage {
+    let mut sim = XSimulator::new(ctx, sys);
+    for inputs in reset.iter() {
+        for &input in sys.inputs.iter() {
+            sim.set_x(input);
+        }
+        for (input, value) in inputs.iter() {
+            sim.set(*input, value.clone().into());
+        }
+        sim.step();
+    }
+    let unreset = sys
+        .states
+        .iter()
+        .filter_map(|s| {
+            let unknown_bits = match sim.get(s.symbol) {
+                XValue::BitVec { known, .. } => {
+                    let unknown = known.not();
+                    if unknown.is_zero() {
+                        return None;
+                    }
+                    Some(unknown)
+                }
+                XValue::Array(Some(_)) => return None,
+                XValue::Array(None) => None,
+            };
+            Some(UnresetState {
+                symbol: s.symbol,
+                name: ctx.get_symbol_name(s.symbol).unwrap().to_string(),
+                unknown_bits,
+                next: s.next,
+            })
+        })
+        .collect();
+    ResetCoverage { unreset }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::State;
+
+    #[test]
+    fn test_reset_coverage() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("regs".to_string());
+        let reset = ctx.bv_symbol("reset", 1);
+        let data = ctx.bv_symbol("data", 8);
+        sys.add_input(&ctx, reset);
+        sys.add_input(&ctx, data);
+        let (count, low, shadow) = (
+            ctx.bv_symbol("count", 8),
+            ctx.bv_symbol("low", 8),
+            ctx.bv_symbol("shadow", 8),
+        );
+        // fully reset counter
+        let count_next = ctx.build(|c| c.ite(reset, c.zero(8), c.add(count, c.one(8))));
+        // only the lower nibble is reset
+        let low_next = ctx.build(|c| {
+            c.ite(
+                reset,
+                c.concat(c.slice(data, 7, 4), c.zero(4)),
+                c.and(low, data),
+            )
+        });
+        // never reset
+        let shadow_next = ctx.build(|c| c.add(shadow, count));
+        for (symbol, next) in [(count, count_next), (low, low_next), (shadow, shadow_next)] {
+            let next = Some(next);
+            sys.add_state(
+                &ctx,
+                State {
+                    symbol,
+                    init: None,
+                    next,
+                },
+            );
+        }
+
+        let sequence = vec![vec![(reset, BitVecValue::from_u64(1, 1))]; 2];
+        let coverage = check_reset_coverage(&ctx, &sys, &sequence);
+        assert!(!coverage.is_complete());
+        let names: Vec<_> = coverage.unreset.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["low", "shadow"]);
+        let low_x = coverage.unreset[0].unknown_bits.as_ref().unwrap();
+        assert_eq!(low_x.to_u64(), Some(0xf0));
+        assert_eq!(coverage.unreset[1].next, Some(shadow_next));
+        assert!(coverage.report(&ctx).contains("shadow"));
+
+        // without reset, the counter is unknown as well
+        let coverage = check_reset_coverage(&ctx, &sys, &[vec![]]);
+        assert_eq!(coverage.unreset.len(), 3);
+
+        // a known zero masks unknown bits of the other operand
+        let mut sim = XSimulator::new(&ctx, &sys);
+        sim.set(data, BitVecValue::from_u64(0x0f, 8).into());
+        let masked = ctx.build(|c| c.and(shadow, data));
+        let XValue::BitVec { known, .. } = sim.get(masked) else {
+            unreachable!()
+        };
+        assert_eq!(known.to_u64(), Some(0xf0));
+    }
+}
@@ -3,10 +3,16 @@
 // released under BSD 3-Clause License
 // author: Kevin Laeufer <laeufer@cornell.edu>
 
+mod mangle;
+mod model;
 mod parser;
+mod query_log;
 mod serialize;
 mod solver;
 
+pub use mangle::{demangle_smt_identifier, mangle_smt_identifier};
+pub use model::ArrayModel;
 pub use parser::{parse_command, parse_expr};
+pub use query_log::{read_query_log, replay_query, LoggedQuery, QueryReplay};
 pub use serialize::serialize_cmd;
 pub use solver::*;
@@ -0,0 +1,156 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Name Mangling
+//! Frontends produce symbol names that contain spaces, unicode or even `|` and `\`, which
+//! cannot appear in an SMT-LIB symbol, not even in a quoted one. Every name is turned into a
+//! legal symbol in one of three ways:
+//! 1. simple symbols that do not contain `%` are used as they are
+//! 2. other printable ASCII names without `|`, `\` and `%` are quoted: `|a b|`
+//! 3. everything else is percent encoded, e.g., `ä` becomes `%C3%A4`
+//!
+//! The mapping only depends on the name and is injective: symbols from the first two cases
+//! never contain a `%`, while the third case always does. Thus [`demangle_smt_identifier`] can
+//! recover the original name of every symbol in a solver response, without any extra state.
+
+use std::borrow::Cow;
+
+/// Characters other than letters and digits that may appear in a simple symbol.
+/// See <simple_symbol> definition in the Concrete Syntax Appendix of the SMTLib Spec
+fn is_other_allowed_char(c: u8) -> bool {
+    matches!(
+        c,
+        b'+' | b'-'
+            | b'/'
+            | b'*'
+            | b'='
+            | b'%'
+            | b'?'
+            | b'!'
+            | b'.'
+            | b'$'
+            | b'_'
+            | b'~'
+            | b'&'
+            | b'^'
+            | b'<'
+            | b'>'
+            | b'@'
+    )
+}
+
+/// Whether `c` can be used unchanged at position `pos` of a mangled name.
+fn is_kept(c: char, pos: usize) -> bool {
+    c.is_ascii_alphabetic()
+        || (c.is_ascii_digit() && pos > 0)
+        || (c != '%' && c.is_ascii() && is_other_allowed_char(c as u8))
+}
+
+fn is_simple_smt_identifier(id: &str) -> bool {
+    !id.is_empty()
+        && id.chars().enumerate().all(|(pos, c)| {
+            c.is_ascii_alphabetic()
+                || (c.is_ascii_digit() && pos > 0)
+                || (c.is_ascii() && is_other_allowed_char(c as u8))
+        })
+}
+
+/// Returns a legal SMT-LIB symbol for `name`.
+pub fn mangle_smt_identifier(name: &str) -> Cow<'_, str> {
+    let has_percent = name.contains('%');
+    if is_simple_smt_identifier(name) && !has_percent {
+        Cow::Borrowed(name)
+    } else if !has_percent
+        && name
+            .bytes()
+            .all(|b| (b' '..=b'~').contains(&b) && b != b'|' && b != b'\\')
+    {
+        Cow::Owned(format!("|{name}|"))
+    } else {
+        let mut out = String::with_capacity(name.len() * 3);
+        for (pos, c) in name.chars().enumerate() {
+            if is_kept(c, pos) {
+                out.push(c);
+            } else {
+                let mut buf = [0u8; 4];
+                for b in c.encode_utf8(&mut buf).bytes() {
+                    out.push_str(&format!("%{b:02X}"));
+                }
+            }
+        }
+        Cow::Owned(out)
+    }
+}
+
+/// Recovers the original name from a symbol created by [`mangle_smt_identifier`], after the
+/// surrounding `|` were removed. Names that are not valid percent encodings are returned as
+/// they are, since they were not created by us.
+pub fn demangle_smt_identifier(symbol: &str) -> Cow<'_, str> {
+    if !symbol.contains('%') {
+        return Cow::Borrowed(symbol);
+    }
+    let bytes = symbol.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut ii = 0;
+    while ii < bytes.len() {
+        if bytes[ii] == b'%' {
+            let byte = symbol
+                .get(ii + 1..ii + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            match byte {
+                Some(byte) => out.push(byte),
+                None => return Cow::Borrowed(symbol),
+            }
+            ii += 3;
+        } else {
+            out.push(bytes[ii]);
+            ii += 1;
+        }
+    }
+    match String::from_utf8(out) {
+        Ok(name) => Cow::Owned(name),
+        Err(_) => Cow::Borrowed(symbol),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mangle_round_trip() {
+        let names = [
+            "a",
+            "a b",
+            "",
+            "$auto$async2sync.cc:262:execute$65@20",
+            "100%",
+            "a|b",
+            "back\\slash",
+            "zähler",
+            "1ä",
+            "%41",
+            "tab\tnew\nline",
+        ];
+        let mut mangled = vec![];
+        for name in names {
+            let symbol = mangle_smt_identifier(name).into_owned();
+            // what the lexer returns after reading the symbol
+            let unquoted = symbol
+                .strip_prefix('|')
+                .map_or(symbol.as_str(), |s| s.strip_suffix('|').unwrap());
+            assert_eq!(demangle_smt_identifier(unquoted), name, "{symbol}");
+            if !symbol.starts_with('|') {
+                assert!(is_simple_smt_identifier(&symbol), "{symbol}");
+            }
+            mangled.push(symbol);
+        }
+        assert_eq!(mangled[0], "a");
+        assert_eq!(mangled[1], "|a b|");
+        assert_eq!(mangled[4], "%3100%25");
+        assert_eq!(mangled[7], "z%C3%A4hler");
+        assert_eq!(mangled[8], "%31%C3%A4");
+        assert_eq!(mangled[9], "%2541");
+    }
+}
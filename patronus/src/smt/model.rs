@@ -0,0 +1,162 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Array Models
+//! SMT solvers describe the value of an array as a chain of `store`s on top of a constant
+//! array, i.e., `(store ((as const (Array ...)) default) index data)`. Evaluating such an
+//! expression is cheap, but enumerating all of its entries, e.g., in order to print a witness,
+//! is not an option for large memories. An [`ArrayModel`] keeps the default value and the
+//! exceptions separate and only materializes an [`ArrayValue`] on demand.
+
+use crate::expr::{Context, Expr, ExprRef, WidthInt};
+use baa::{ArrayMutOps, ArrayValue, BitVecOps, BitVecValue};
+use std::collections::BTreeMap;
+
+/// Value of an array in a model, represented as a default value and a set of exceptions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArrayModel {
+    index_width: WidthInt,
+    default: BitVecValue,
+    exceptions: BTreeMap<BitVecValue, BitVecValue>,
+}
+
+impl ArrayModel {
+    /// Reconstructs the model from a store chain over a constant array. Returns `None` if the
+    /// expression contains anything other than stores of literals and a constant array.
+    pub fn from_expr(ctx: &Context, expr: ExprRef) -> Option<Self> {
+        let literal = |e: ExprRef| match ctx[e] {
+            Expr::BVLiteral(value) => Some(BitVecValue::from(value.get(ctx))),
+            _ => None,
+        };
+        let mut stores = vec![];
+        let mut current = expr;
+        let (index_width, default) = loop {
+            match ctx[current] {
+                Expr::ArrayStore { array, index, data } => {
+                    stores.push((index, data));
+                    current = array;
+                }
+                Expr::ArrayConstant { e, index_width, .. } => break (index_width, literal(e)?),
+                _ => return None,
+            }
+        };
+        let mut model = Self::new(index_width, default);
+        // the innermost store is applied first
+        for (index, data) in stores.into_iter().rev() {
+            model.store(literal(index)?, literal(data)?);
+        }
+        Some(model)
+    }
+
+    pub fn new(index_width: WidthInt, default: BitVecValue) -> Self {
+        Self {
+            index_width,
+            default,
+            exceptions: BTreeMap::new(),
+        }
+    }
+
+    pub fn index_width(&self) -> WidthInt {
+        self.index_width
+    }
+
+    pub fn data_width(&self) -> WidthInt {
+        self.default.width()
+    }
+
+    pub fn default_value(&self) -> &BitVecValue {
+        &self.default
+    }
+
+    pub fn store(&mut self, index: BitVecValue, data: BitVecValue) {
+        debug_assert_eq!(index.width(), self.index_width);
+        debug_assert_eq!(data.width(), self.data_width());
+        if data.is_equal(&self.default) {
+            self.exceptions.remove(&index);
+        } else {
+            self.exceptions.insert(index, data);
+        }
+    }
+
+    pub fn select(&self, index: &BitVecValue) -> &BitVecValue {
+        self.exceptions.get(index).unwrap_or(&self.default)
+    }
+
+    /// All entries that differ from the default value.
+    pub fn exceptions(&self) -> impl Iterator<Item = (&BitVecValue, &BitVecValue)> {
+        self.exceptions.iter()
+    }
+
+    /// The indices that need to be listed when all other entries are assumed to be zero,
+    /// as is the case for btor2 witnesses. Only enumerates the whole array if the default
+    /// value is not zero.
+    pub fn relevant_indices(&self) -> Vec<BitVecValue> {
+        if self.default.is_zero() {
+            self.exceptions.keys().cloned().collect()
+        } else {
+            let num_elements = 1u64 << self.index_width;
+            (0..num_elements)
+                .map(|ii| BitVecValue::from_u64(ii, self.index_width))
+                .collect()
+        }
+    }
+
+    /// Materializes the model as a sparse array value.
+    pub fn to_array_value(&self) -> ArrayValue {
+        let mut value = ArrayValue::new_sparse(self.index_width, &self.default);
+        for (index, data) in self.exceptions.iter() {
+            value.store(index, data);
+        }
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::smt::parse_expr;
+    use baa::ArrayOps;
+    use rustc_hash::FxHashMap;
+
+    #[test]
+    fn test_array_model_from_store_chain() {
+        let mut ctx = Context::default();
+        let symbols = FxHashMap::default();
+        let base = "((as const (Array (_ BitVec 32) (_ BitVec 8))) #x00)";
+        let src = format!(
+            "(store (store (store {base} #x00000010 #x07) #x00000020 #x03) #x00000010 #x05)"
+        );
+        let expr = parse_expr(&mut ctx, &symbols, src.as_bytes()).unwrap();
+        let model = ArrayModel::from_expr(&ctx, expr).unwrap();
+        assert_eq!(model.index_width(), 32);
+        assert_eq!(model.data_width(), 8);
+
+        // later stores overwrite earlier ones
+        let index = |value: u64| BitVecValue::from_u64(value, 32);
+        assert_eq!(model.select(&index(0x10)).to_u64(), Some(5));
+        assert_eq!(model.select(&index(0x20)).to_u64(), Some(3));
+        assert_eq!(model.select(&index(0x30)).to_u64(), Some(0));
+        assert_eq!(model.exceptions().count(), 2);
+        // only the exceptions need to be listed since the default is zero
+        assert_eq!(model.relevant_indices(), [index(0x10), index(0x20)]);
+
+        let value = model.to_array_value();
+        assert_eq!(value.select(&index(0x10)).to_u64(), Some(5));
+        assert_eq!(value.select(&index(0xffff)).to_u64(), Some(0));
+
+        // storing the default removes the exception
+        let src = format!("(store {base} #x00000010 #x00)");
+        let expr = parse_expr(&mut ctx, &symbols, src.as_bytes()).unwrap();
+        let model = ArrayModel::from_expr(&ctx, expr).unwrap();
+        assert_eq!(model.exceptions().count(), 0);
+
+        // a symbolic index cannot be part of a model
+        let a = ctx.bv_symbol("a", 32);
+        let mut symbols = FxHashMap::default();
+        symbols.insert("a".to_string(), a);
+        let src = format!("(store {base} a #x01)");
+        let expr = parse_expr(&mut ctx, &symbols, src.as_bytes()).unwrap();
+        assert_eq!(ArrayModel::from_expr(&ctx, expr), None);
+    }
+}
@@ -3,6 +3,7 @@
 // author: Kevin Laeufer <laeufer@cornell.edu>
 
 use crate::expr::{ArrayType, Context, ExprRef, SerializableIrNode, Type, TypeCheck, WidthInt};
+use crate::smt::mangle::demangle_smt_identifier;
 use crate::smt::{Logic, SmtCommand};
 use regex::bytes::RegexSet;
 use rustc_hash::FxHashMap;
@@ -27,12 +28,16 @@ pub enum SmtParserError {
     UnknownLogic(String),
     #[error("[smt] only part of the input string was parsed into an expr. Next token: `{0}`")]
     ExprSuffix(String),
+    #[error("[smt] the input ended before the expression was complete: {0}")]
+    IncompleteExpr(String),
     #[error("[smt] expected an opening parenthesis, encountered this instead: {0}")]
     MissingOpen(String),
     #[error("[smt] expected a closing parenthesis, encountered this instead: {0}")]
     MissingClose(String),
     #[error("[smt] get-value response: {0}")]
     GetValueResponse(String),
+    #[error("[smt] get-model response: {0}")]
+    GetModelResponse(String),
     #[error("[smt] expected an identifier token but got: {0}")]
     ExpectedIdentifer(String),
     #[error("[smt] expected an expression but got: {0}")]
@@ -148,7 +153,9 @@ fn parse_expr_or_type(
             }
             Token::StringLit(value) => {
                 let value = string_lit_to_string(value);
-                todo!("unexpected string literal in expression: {value}")
+                return Err(SmtParserError::Unsupported(format!(
+                    "string literal `{value}` in expression"
+                )));
             }
             Token::Comment(_) => {} // ignore comments
         }
@@ -160,7 +167,7 @@ fn parse_expr_or_type(
             _ => {} // cotinue parsing
         }
     }
-    todo!("error message!: {stack:?}")
+    Err(SmtParserError::IncompleteExpr(format!("{stack:?}")))
 }
 
 /// Extracts the value expression from SMT solver responses of the form ((... value))
@@ -181,6 +188,77 @@ pub fn parse_get_value_response(ctx: &mut Context, input: &[u8]) -> Result<ExprR
     Ok(expr)
 }
 
+/// Extracts symbol and value expression pairs from an SMT solver response to `(get-model)`.
+/// Symbols are re-created from the name and type of each `define-fun`, which results in the same
+/// expression that was declared, as long as the same context is used.
+/// Definitions of functions with arguments are skipped.
+pub fn parse_get_model_response(
+    ctx: &mut Context,
+    input: &[u8],
+) -> Result<Vec<(ExprRef, ExprRef)>> {
+    let mut lexer = Lexer::new(input);
+    skip_open_parens(&mut lexer)?;
+    let st = FxHashMap::default();
+    let mut model = vec![];
+    loop {
+        match lexer.next_no_comment() {
+            Some(Token::Close) => break,
+            // older solvers wrap the definitions in `(model ...)`
+            Some(Token::Value(b"model")) => {}
+            Some(Token::Open) => {
+                let cmd = value_token(&mut lexer)?;
+                if cmd != b"define-fun" {
+                    return Err(SmtParserError::GetModelResponse(format!(
+                        "expected define-fun, got {}",
+                        String::from_utf8_lossy(cmd)
+                    )));
+                }
+                let name = String::from_utf8_lossy(value_token(&mut lexer)?);
+                skip_open_parens(&mut lexer)?;
+                match lexer.next_no_comment() {
+                    Some(Token::Close) => {}
+                    // function with arguments
+                    Some(Token::Open) => {
+                        skip_scopes(&mut lexer, 3)?;
+                        continue;
+                    }
+                    other => return Err(SmtParserError::MissingClose(format!("{other:?}"))),
+                }
+                let tpe = parse_type(ctx, &st, &mut lexer)?;
+                let value = parse_expr_internal(ctx, &st, &mut lexer)?;
+                if value.get_type(ctx) != tpe {
+                    return Err(SmtParserError::GetModelResponse(format!(
+                        "value of {name} is a {:?}, not a {tpe:?}",
+                        value.get_type(ctx)
+                    )));
+                }
+                skip_close_parens(&mut lexer)?;
+                let name_ref = ctx.string(demangle_smt_identifier(&name));
+                model.push((ctx.symbol(name_ref, tpe), value));
+            }
+            other => return Err(SmtParserError::MissingOpen(format!("{other:?}"))),
+        }
+    }
+    Ok(model)
+}
+
+/// Consumes tokens until `depth` open parenthesis have been closed.
+fn skip_scopes(lexer: &mut Lexer, mut depth: u64) -> Result<()> {
+    for token in lexer.by_ref() {
+        match token {
+            Token::Open => depth += 1,
+            Token::Close => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(());
+                }
+            }
+            _ => {}
+        }
+    }
+    Err(SmtParserError::MissingClose("end of input".to_string()))
+}
+
 fn skip_open_parens(lexer: &mut Lexer) -> Result<()> {
     let token = lexer.next_no_comment();
     if token == Some(Token::Open) {
@@ -239,7 +317,7 @@ pub fn parse_command(ctx: &mut Context, st: &SymbolTable, input: &[u8]) -> Resul
             b"declare-const" => {
                 let name = String::from_utf8_lossy(value_token(&mut lexer)?);
                 let tpe = parse_type(ctx, st, &mut lexer)?;
-                let name_ref = ctx.string(name);
+                let name_ref = ctx.string(demangle_smt_identifier(&name));
                 let sym = ctx.symbol(name_ref, tpe);
                 SmtCommand::DeclareConst(sym)
             }
@@ -249,7 +327,7 @@ pub fn parse_command(ctx: &mut Context, st: &SymbolTable, input: &[u8]) -> Resul
                 skip_open_parens(&mut lexer)?;
                 skip_close_parens(&mut lexer)?;
                 let tpe = parse_type(ctx, st, &mut lexer)?;
-                let name_ref = ctx.string(name);
+                let name_ref = ctx.string(demangle_smt_identifier(&name));
                 let sym = ctx.symbol(name_ref, tpe);
                 SmtCommand::DeclareConst(sym)
             }
@@ -259,7 +337,7 @@ pub fn parse_command(ctx: &mut Context, st: &SymbolTable, input: &[u8]) -> Resul
                 let value = parse_expr_internal(ctx, st, &mut lexer)?;
                 // TODO: turn this into a proper error
                 debug_assert_eq!(ctx[value].get_type(ctx), tpe);
-                let name_ref = ctx.string(name);
+                let name_ref = ctx.string(demangle_smt_identifier(&name));
                 let sym = ctx.symbol(name_ref, tpe);
                 SmtCommand::DefineConst(sym, value)
             }
@@ -272,7 +350,7 @@ pub fn parse_command(ctx: &mut Context, st: &SymbolTable, input: &[u8]) -> Resul
                 let value = parse_expr_internal(ctx, st, &mut lexer)?;
                 // TODO: turn this into a proper error
                 debug_assert_eq!(ctx[value].get_type(ctx), tpe);
-                let name_ref = ctx.string(name);
+                let name_ref = ctx.string(demangle_smt_identifier(&name));
                 let sym = ctx.symbol(name_ref, tpe);
                 SmtCommand::DefineConst(sym, value)
             }
@@ -294,6 +372,7 @@ pub fn parse_command(ctx: &mut Context, st: &SymbolTable, input: &[u8]) -> Resul
                 let expr = parse_expr_internal(ctx, st, &mut lexer)?;
                 SmtCommand::GetValue(expr)
             }
+            b"get-model" => SmtCommand::GetModel,
             _ => {
                 return Err(SmtParserError::UnknownCommand(format!(
                     "{}",
@@ -314,6 +393,7 @@ fn parse_logic(lexer: &mut Lexer) -> Result<Logic> {
         b"QF_ABV" => Ok(Logic::QfAbv),
         b"QF_AUFBV" => Ok(Logic::QfAufbv),
         b"ALL" => Ok(Logic::All),
+        b"BV" => Ok(Logic::Bv),
         other => Err(SmtParserError::UnknownLogic(
             String::from_utf8_lossy(other).into(),
         )),
@@ -367,7 +447,7 @@ fn skip_expr(lexer: &mut Lexer) -> Result<()> {
 
 fn lookup_sym(st: &SymbolTable, name: &[u8]) -> Result<ExprRef> {
     let name = std::str::from_utf8(name)?;
-    match st.get(name) {
+    match st.get(demangle_smt_identifier(name).as_ref()) {
         Some(s) => Ok(*s),
         None => Err(SmtParserError::UnknownSymbol(name.to_string())),
     }
@@ -849,6 +929,14 @@ mod tests {
         let symbols = FxHashMap::from_iter([("a".to_string(), a)]);
         let expr = parse_expr(&mut ctx, &symbols, "(bvand a #b00)".as_bytes()).unwrap();
         assert_eq!(expr, ctx.build(|c| c.and(a, c.bit_vec_val(0, 2))));
+        assert!(matches!(
+            parse_expr(&mut ctx, &symbols, "(bvand a #b00".as_bytes()),
+            Err(SmtParserError::IncompleteExpr(_))
+        ));
+        assert!(matches!(
+            parse_expr(&mut ctx, &symbols, "(bvand a \"00\")".as_bytes()),
+            Err(SmtParserError::Unsupported(_))
+        ));
     }
 
     #[test]
@@ -863,6 +951,36 @@ mod tests {
         assert_eq!(expr, ctx.bit_vec_val(1, 4));
     }
 
+    #[test]
+    fn test_get_model_parser() {
+        let mut ctx = Context::default();
+        let response = r#"(
+  (define-fun a () (_ BitVec 3) #b011)
+  (define-fun |b c| () Bool true)
+  (define-fun f ((x (_ BitVec 3))) (_ BitVec 3) (bvadd x #b001))
+  (define-fun m () (Array (_ BitVec 2) (_ BitVec 4))
+    (store ((as const (Array (_ BitVec 2) (_ BitVec 4))) #x0) #b01 #x7))
+)"#;
+        let model = parse_get_model_response(&mut ctx, response.as_bytes()).unwrap();
+        assert_eq!(model.len(), 3);
+        assert_eq!(model[0], (ctx.bv_symbol("a", 3), ctx.bit_vec_val(3, 3)));
+        assert_eq!(model[1], (ctx.bv_symbol("b c", 1), ctx.bit_vec_val(1, 1)));
+        assert_eq!(ctx.get_symbol_name(model[2].0), Some("m"));
+        assert_eq!(
+            model[2].1.serialize_to_str(&ctx),
+            "([4'b0000] x 2^2)[2'b01 := 4'b0111]"
+        );
+
+        // z3 style
+        let response = "(model (define-fun a () (_ BitVec 3) #b011))";
+        let model = parse_get_model_response(&mut ctx, response.as_bytes()).unwrap();
+        assert_eq!(model, [(ctx.bv_symbol("a", 3), ctx.bit_vec_val(3, 3))]);
+
+        // type mismatch
+        let response = "((define-fun a () (_ BitVec 3) #b0011))";
+        assert!(parse_get_model_response(&mut ctx, response.as_bytes()).is_err());
+    }
+
     #[test]
     fn test_parse_smt_array_const_and_store() {
         let mut ctx = Context::default();
@@ -0,0 +1,250 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Query Logs
+//! Saves every `check-sat` query sent to a solver as a self-contained SMT-LIB file, i.e.,
+//! together with all declarations and assertions that are active at the time of the query.
+//! The file starts with comments that record the solver, its answer and how long it took:
+//!
+//! ```smt2
+//! ; solver: bitwuzla
+//! ; result: unsat
+//! ; time-us: 1530
+//! (set-logic QF_AUFBV)
+//! (declare-const a (_ BitVec 3))
+//! ...
+//! (check-sat)
+//! ```
+//!
+//! Logged queries can be replayed against any other solver with [`replay_query`] in order to
+//! track down discrepancies between solvers or performance regressions.
+
+use crate::smt::{CheckSatResponse, Result, SmtCommand, SmtLibSolver, Solver};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Commands that were sent to the solver, one entry per assertion stack level.
+#[derive(Debug, Clone)]
+pub(super) struct AssertionStack {
+    pub(super) frames: Vec<Vec<u8>>,
+}
+
+impl Default for AssertionStack {
+    fn default() -> Self {
+        Self {
+            frames: vec![vec![]],
+        }
+    }
+}
+
+impl AssertionStack {
+    /// Keeps track of a command that was already serialized.
+    pub(super) fn record(&mut self, cmd: &SmtCommand, serialized: &[u8]) {
+        match cmd {
+            SmtCommand::Push(_) => self.frames.push(serialized.to_vec()),
+            SmtCommand::Pop(n) => {
+                for _ in 0..*n {
+                    self.frames.pop();
+                }
+            }
+            SmtCommand::SetLogic(_)
+            | SmtCommand::SetInfo(_, _)
+            | SmtCommand::Assert(_)
+            | SmtCommand::AssertForAll(_, _)
+            | SmtCommand::DeclareConst(_)
+            | SmtCommand::DeclareFun(_, _)
+            | SmtCommand::DefineConst(_, _) => self
+                .frames
+                .last_mut()
+                .unwrap()
+                .extend_from_slice(serialized),
+            // options are set by the solver, everything else does not modify the solver state
+            _ => {}
+        }
+    }
+}
+
+/// Writes every query into a separate file inside a directory.
+pub(super) struct QueryLog {
+    dir: PathBuf,
+    count: usize,
+    pub(super) stack: AssertionStack,
+}
+
+impl QueryLog {
+    pub(super) fn create(dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            count: 0,
+            stack: AssertionStack::default(),
+        })
+    }
+
+    /// Saves the current assertion stack followed by the serialized `check` command.
+    pub(super) fn write_query(
+        &mut self,
+        solver: &str,
+        check: &[u8],
+        result: &Result<CheckSatResponse>,
+        time: Duration,
+    ) -> Result<()> {
+        let filename = self.dir.join(format!("query_{:05}.smt2", self.count));
+        self.count += 1;
+        let mut out = std::io::BufWriter::new(std::fs::File::create(filename)?);
+        writeln!(out, "; solver: {solver}")?;
+        writeln!(out, "; result: {}", result_str(result))?;
+        writeln!(out, "; time-us: {}", time.as_micros())?;
+        for frame in self.stack.frames.iter() {
+            out.write_all(frame)?;
+        }
+        out.write_all(check)?;
+        out.flush()?;
+        Ok(())
+    }
+}
+
+fn result_str(result: &Result<CheckSatResponse>) -> &'static str {
+    match result {
+        Ok(CheckSatResponse::Sat) => "sat",
+        Ok(CheckSatResponse::Unsat) => "unsat",
+        Ok(CheckSatResponse::Unknown) => "unknown",
+        Err(_) => "error",
+    }
+}
+
+/// A query that was read back from a query log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoggedQuery {
+    pub path: PathBuf,
+    pub solver: String,
+    /// `None` if the solver did not answer
+    pub result: Option<CheckSatResponse>,
+    pub time: Duration,
+    /// SMT-LIB commands without the header
+    pub script: String,
+}
+
+impl LoggedQuery {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)?;
+        let mut solver = String::new();
+        let mut result = None;
+        let mut time = Duration::ZERO;
+        let mut script = String::new();
+        for line in content.lines() {
+            let header = line
+                .strip_prefix("; ")
+                .and_then(|comment| comment.split_once(": "));
+            match header {
+                Some(("solver", name)) => solver = name.to_string(),
+                Some(("result", "sat")) => result = Some(CheckSatResponse::Sat),
+                Some(("result", "unsat")) => result = Some(CheckSatResponse::Unsat),
+                Some(("result", "unknown")) => result = Some(CheckSatResponse::Unknown),
+                Some(("result", _)) => result = None,
+                Some(("time-us", us)) => {
+                    time = Duration::from_micros(us.parse().unwrap_or_default())
+                }
+                _ => {
+                    script.push_str(line);
+                    script.push('\n');
+                }
+            }
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            solver,
+            result,
+            time,
+            script,
+        })
+    }
+}
+
+/// Loads all queries of a log directory in the order in which they were issued.
+pub fn read_query_log(dir: impl AsRef<Path>) -> Result<Vec<LoggedQuery>> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<_>>()?;
+    paths.retain(|p| p.extension().is_some_and(|ext| ext == "smt2"));
+    paths.sort();
+    paths.into_iter().map(LoggedQuery::load).collect()
+}
+
+/// Outcome of running a logged query on a different solver.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryReplay {
+    pub result: CheckSatResponse,
+    pub time: Duration,
+}
+
+impl QueryReplay {
+    /// The solver disagrees with the logged answer. Unknown answers never disagree.
+    pub fn disagrees_with(&self, query: &LoggedQuery) -> bool {
+        match (&self.result, &query.result) {
+            (CheckSatResponse::Unknown, _) | (_, Some(CheckSatResponse::Unknown)) | (_, None) => {
+                false
+            }
+            (new, Some(old)) => new != old,
+        }
+    }
+}
+
+/// Runs `query` on a fresh instance of `solver`.
+pub fn replay_query(solver: &SmtLibSolver, query: &LoggedQuery) -> Result<QueryReplay> {
+    let mut smt_ctx = solver.start(None::<std::fs::File>)?;
+    let start = Instant::now();
+    let result = smt_ctx.run_script(query.script.as_bytes())?;
+    Ok(QueryReplay {
+        result,
+        time: start.elapsed(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::smt::Error;
+
+    #[test]
+    fn test_load_logged_query() {
+        let dir = std::env::temp_dir().join("patronus_query_log_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut log = QueryLog::create(&dir).unwrap();
+        log.stack.record(&SmtCommand::Push(1), b"(push 1)\n");
+        log.write_query(
+            "bitwuzla",
+            b"(check-sat)\n",
+            &Ok(CheckSatResponse::Unsat),
+            Duration::from_micros(42),
+        )
+        .unwrap();
+        log.stack.record(&SmtCommand::Pop(1), b"(pop 1)\n");
+        log.write_query(
+            "bitwuzla",
+            b"(check-sat)\n",
+            &Err(Error::StackUnderflow),
+            Duration::ZERO,
+        )
+        .unwrap();
+
+        let queries = read_query_log(&dir).unwrap();
+        assert_eq!(queries.len(), 2);
+        assert_eq!(queries[0].solver, "bitwuzla");
+        assert_eq!(queries[0].result, Some(CheckSatResponse::Unsat));
+        assert_eq!(queries[0].time, Duration::from_micros(42));
+        assert_eq!(queries[0].script, "(push 1)\n(check-sat)\n");
+        assert_eq!(queries[1].result, None);
+        assert_eq!(queries[1].script, "(check-sat)\n");
+
+        let replay = QueryReplay {
+            result: CheckSatResponse::Sat,
+            time: Duration::ZERO,
+        };
+        assert!(replay.disagrees_with(&queries[0]));
+        assert!(!replay.disagrees_with(&queries[1]));
+    }
+}
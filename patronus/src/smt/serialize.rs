@@ -4,8 +4,9 @@
 // author: Kevin Laeufer <laeufer@cornell.edu>
 
 use crate::expr::{Context, Expr, ExprRef, ForEachChild, Type, TypeCheck};
+use crate::smt::mangle::mangle_smt_identifier;
 use crate::smt::solver::SmtCommand;
-use baa::BitVecOps;
+use baa::{BitVecOps, BitVecValue, SparseArrayValue};
 use std::io::Write;
 
 pub type Result<T> = std::io::Result<T>;
@@ -167,6 +168,20 @@ pub fn serialize_expr(out: &mut impl Write, ctx: &Context, expr: ExprRef) -> Res
                 Expr::ArraySymbol { name, .. } => {
                     write!(out, "{}", escape_smt_identifier(&ctx[*name]))?;
                 }
+                Expr::ArrayLiteral { value, .. } => {
+                    // only words that differ from the default need to be stored
+                    let sparse: SparseArrayValue = value.get(ctx).into();
+                    let entries: Vec<_> = sparse.non_default_entries().collect();
+                    for _ in entries.iter() {
+                        write!(out, "(store ")?;
+                    }
+                    write!(out, "((as const ")?;
+                    serialize_type(out, expr.get_type(ctx))?;
+                    write!(out, ") {})", smt_value(&sparse.default()))?;
+                    for (index, data) in entries.iter() {
+                        write!(out, " {} {})", smt_value(index), smt_value(data))?;
+                    }
+                }
                 Expr::ArrayConstant { .. } => {
                     write!(out, "((as const ")?;
                     let tpe = expr.get_type(ctx);
@@ -182,12 +197,30 @@ pub fn serialize_expr(out: &mut impl Write, ctx: &Context, expr: ExprRef) -> Res
                 Expr::ArrayIte { .. } => {
                     write!(out, "(ite ")?;
                 }
+                Expr::BVFunction { name, .. } => {
+                    write!(out, "{}", escape_smt_identifier(&ctx[*name]))?;
+                }
+                Expr::BVApply { .. } => {
+                    let (func, _) = ctx
+                        .get_application(e)
+                        .expect("only uninterpreted functions can be applied");
+                    let name = ctx.get_symbol_name(func).unwrap();
+                    write!(out, "({}", escape_smt_identifier(name))?;
+                }
             }
         }
 
         let child_must_be_bit_vec = always_consumes_bit_vec(expr);
 
-        if let Some(next_child) = find_next_child(pc, expr) {
+        // applications are curried in our IR, but flat in SMTLib
+        let next_child = if let Expr::BVApply { .. } = expr {
+            let (_, args) = ctx.get_application(e).unwrap();
+            args.get(pc as usize).copied()
+        } else {
+            find_next_child(pc, expr)
+        };
+
+        if let Some(next_child) = next_child {
             write!(out, " ")?;
             todo.push((e, pc + 1, must_be_bit_vec));
             todo.push((next_child, 0, child_must_be_bit_vec));
@@ -217,6 +250,17 @@ pub fn serialize_expr(out: &mut impl Write, ctx: &Context, expr: ExprRef) -> Res
     Ok(())
 }
 
+/// 1-bit values are booleans in SMTLib.
+fn smt_value(value: &BitVecValue) -> String {
+    if value.width() > 1 {
+        format!("#b{}", value.to_bit_str())
+    } else if value.is_true() {
+        "true".to_string()
+    } else {
+        "false".to_string()
+    }
+}
+
 /// Returns whether the expressions always consumes bit vectors, even with 1-bit arguments
 fn always_consumes_bit_vec(e: &Expr) -> bool {
     match e {
@@ -299,6 +343,25 @@ pub fn serialize_cmd(out: &mut impl Write, ctx: Option<&Context>, cmd: &SmtComma
             serialize_expr(out, ctx.unwrap(), *e)?;
             writeln!(out, ")")
         }
+        SmtCommand::AssertForAll(bound, e) => {
+            let ctx = ctx.unwrap();
+            write!(out, "(assert (forall (")?;
+            for (ii, &symbol) in bound.iter().enumerate() {
+                if ii > 0 {
+                    write!(out, " ")?;
+                }
+                write!(
+                    out,
+                    "({} ",
+                    escape_smt_identifier(ctx.get_symbol_name(symbol).unwrap())
+                )?;
+                serialize_type(out, symbol.get_type(ctx))?;
+                write!(out, ")")?;
+            }
+            write!(out, ") ")?;
+            serialize_expr(out, ctx, *e)?;
+            writeln!(out, "))")
+        }
         SmtCommand::DeclareConst(symbol) => {
             let ctx = ctx.unwrap();
             write!(
@@ -309,6 +372,23 @@ pub fn serialize_cmd(out: &mut impl Write, ctx: Option<&Context>, cmd: &SmtComma
             serialize_type(out, symbol.get_type(ctx))?;
             writeln!(out, ")")
         }
+        SmtCommand::DeclareFun(func, args) => {
+            let ctx = ctx.unwrap();
+            write!(
+                out,
+                "(declare-fun {} (",
+                escape_smt_identifier(ctx.get_symbol_name(*func).unwrap())
+            )?;
+            for (ii, &tpe) in args.iter().enumerate() {
+                if ii > 0 {
+                    write!(out, " ")?;
+                }
+                serialize_type(out, tpe)?;
+            }
+            write!(out, ") ")?;
+            serialize_type(out, func.get_type(ctx))?;
+            writeln!(out, ")")
+        }
         SmtCommand::DefineConst(symbol, value) => {
             let ctx = ctx.unwrap();
             // name, then empty arguments
@@ -339,6 +419,7 @@ pub fn serialize_cmd(out: &mut impl Write, ctx: Option<&Context>, cmd: &SmtComma
             serialize_expr(out, ctx, *e)?;
             writeln!(out, "))")
         }
+        SmtCommand::GetModel => writeln!(out, "(get-model)"),
     }
 }
 
@@ -355,56 +436,8 @@ pub fn serialize_type(out: &mut impl Write, tpe: Type) -> Result<()> {
     }
 }
 
-/// See <simple_symbol> definition in the Concrete Syntax Appendix of the SMTLib Spec
-fn is_simple_smt_identifier(id: &str) -> bool {
-    if id.is_empty() {
-        return false; // needs to be non-empty
-    }
-    let mut is_first = true;
-    for cc in id.chars() {
-        if !cc.is_ascii() {
-            return false; // all allowed characters are ASCII characters
-        }
-        let ac = cc as u8;
-        let is_alpha = ac.is_ascii_uppercase() || ac.is_ascii_lowercase();
-        let is_num = ac.is_ascii_digit();
-        let is_other_allowed_char = matches!(
-            ac,
-            b'+' | b'-'
-                | b'/'
-                | b'*'
-                | b'='
-                | b'%'
-                | b'?'
-                | b'!'
-                | b'.'
-                | b'$'
-                | b'_'
-                | b'~'
-                | b'&'
-                | b'^'
-                | b'<'
-                | b'>'
-                | b'@'
-        );
-        if !(is_alpha | is_num | is_other_allowed_char) {
-            return false;
-        }
-        if is_num && is_first {
-            return false; // the first character is not allowed ot be a digit
-        }
-        is_first = false;
-    }
-    true // passed all checks
-}
-
 fn escape_smt_identifier(id: &str) -> std::borrow::Cow<'_, str> {
-    if is_simple_smt_identifier(id) {
-        std::borrow::Cow::Borrowed(id)
-    } else {
-        let escaped = format!("|{}|", id);
-        std::borrow::Cow::Owned(escaped)
-    }
+    mangle_smt_identifier(id)
 }
 
 #[cfg(test)]
@@ -437,6 +470,18 @@ mod tests {
         assert_eq!(s_expr(&ctx, sym), "|$auto$async2sync.cc:262:execute$65@20|");
     }
 
+    #[test]
+    fn test_serialize_forall() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("a", 4);
+        let b = ctx.bv_symbol("b", 1);
+        let e = ctx.build(|c| c.implies(b, c.equal(a, a)));
+        let cmd = SmtCommand::AssertForAll(vec![a, b], e);
+        let serialized = s_cmd(&ctx, &cmd);
+        assert!(serialized.starts_with("(assert (forall ((a (_ BitVec 4)) (b Bool)) (=> "));
+        assert!(serialized.ends_with(")))\n"));
+    }
+
     #[test]
     fn test_serialize_declare_const() {
         let mut ctx = Context::default();
@@ -449,6 +494,48 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_serialize_uninterpreted_function() {
+        let mut ctx = Context::default();
+        let f = ctx.function("f", 2, 8);
+        let a = ctx.bv_symbol("a", 8);
+        let b = ctx.bv_symbol("b", 1);
+        let app = ctx.apply(f, &[a, b]);
+        let cmd = SmtCommand::DeclareFun(f, vec![Type::BV(8), Type::BV(1)]);
+        assert_eq!(
+            s_cmd(&ctx, &cmd),
+            "(declare-fun f ((_ BitVec 8) Bool) (_ BitVec 8))\n"
+        );
+        assert_eq!(s_expr(&ctx, app), "(f a b)");
+        let sum = ctx.add(app, a);
+        assert_eq!(s_expr(&ctx, sum), "(bvadd (f a b) a)");
+    }
+
+    #[test]
+    fn test_serialize_array_lit() {
+        let mut ctx = Context::default();
+        let tpe = ArrayType {
+            index_width: 2,
+            data_width: 4,
+        };
+        let rom = ctx.array_lit_from_bytes(tpe, &[0, 3, 0, 0]);
+        assert_eq!(
+            s_expr(&ctx, rom),
+            "(store ((as const (Array (_ BitVec 2) (_ BitVec 4))) #b0000) #b01 #b0011)"
+        );
+        let flags = ctx.array_lit_from_bytes(
+            ArrayType {
+                index_width: 2,
+                data_width: 1,
+            },
+            &[1],
+        );
+        assert_eq!(
+            s_expr(&ctx, flags),
+            "(store ((as const (Array (_ BitVec 2) Bool)) false) #b00 true)"
+        );
+    }
+
     fn s_type(t: Type) -> String {
         let mut out = Vec::new();
         serialize_type(&mut out, t).unwrap();
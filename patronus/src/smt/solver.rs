@@ -2,12 +2,15 @@
 // released under BSD 3-Clause License
 // author: Kevin Laeufer <laeufer@cornell.edu>
 
-use crate::expr::{Context, ExprRef};
-use crate::smt::parser::{parse_get_value_response, SmtParserError};
+use crate::expr::{Context, ExprRef, Type};
+use crate::smt::parser::{parse_get_model_response, parse_get_value_response, SmtParserError};
+use crate::smt::query_log::{AssertionStack, QueryLog};
 use crate::smt::serialize::serialize_cmd;
 use std::io::{BufRead, BufReader, BufWriter};
 use std::io::{Read, Write};
+use std::path::Path;
 use std::process::{Command, Stdio};
+use std::time::Instant;
 use thiserror::Error;
 
 /// A SMT Solver Error.
@@ -25,6 +28,10 @@ pub enum Error {
     UnexpectedResponse(String, String),
     #[error("[smt] failed to parse a response")]
     Parser(#[from] SmtParserError),
+    #[error("[smt] {0} crashed and could not be recovered after {1} restart(s)")]
+    RecoveryFailed(String, u32),
+    #[error("[smt] {0} does not support uninterpreted functions")]
+    UninterpretedFunctionsUnsupported(String),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -35,6 +42,8 @@ pub enum Logic {
     All,
     QfAufbv,
     QfAbv,
+    /// bit-vectors with quantifiers
+    Bv,
 }
 
 impl Logic {
@@ -43,6 +52,7 @@ impl Logic {
             Logic::All => "ALL",
             Logic::QfAufbv => "QF_AUFBV",
             Logic::QfAbv => "QF_ABV",
+            Logic::Bv => "BV",
         }
     }
 }
@@ -56,12 +66,18 @@ pub enum SmtCommand {
     SetOption(String, String),
     SetInfo(String, String),
     Assert(ExprRef),
+    /// Asserts that the expression holds for all values of the bound symbols.
+    /// The bound symbols must not be declared.
+    AssertForAll(Vec<ExprRef>, ExprRef),
     DeclareConst(ExprRef),
+    /// Declares an uninterpreted function with the given argument types.
+    DeclareFun(ExprRef, Vec<Type>),
     DefineConst(ExprRef, ExprRef),
     CheckSatAssuming(Vec<ExprRef>),
     Push(u64),
     Pop(u64),
     GetValue(ExprRef),
+    GetModel,
 }
 
 /// The result of a `(check-sat)` command.
@@ -95,7 +111,11 @@ pub trait SolverContext: SolverMetaData {
     fn restart(&mut self) -> Result<()>;
     fn set_logic(&mut self, option: Logic) -> Result<()>;
     fn assert(&mut self, ctx: &Context, e: ExprRef) -> Result<()>;
+    /// Asserts that `e` holds for every possible value of the `bound` symbols.
+    fn assert_forall(&mut self, ctx: &Context, bound: &[ExprRef], e: ExprRef) -> Result<()>;
     fn declare_const(&mut self, ctx: &Context, symbol: ExprRef) -> Result<()>;
+    /// Declares an uninterpreted function. Requires [`SolverMetaData::supports_uf`].
+    fn declare_fun(&mut self, ctx: &Context, func: ExprRef, args: &[Type]) -> Result<()>;
     fn define_const(&mut self, ctx: &Context, symbol: ExprRef, expr: ExprRef) -> Result<()>;
     fn check_sat_assuming(
         &mut self,
@@ -106,6 +126,12 @@ pub trait SolverContext: SolverMetaData {
     fn push(&mut self) -> Result<()>;
     fn pop(&mut self) -> Result<()>;
     fn get_value(&mut self, ctx: &mut Context, e: ExprRef) -> Result<ExprRef>;
+    /// Returns a value expression for every constant in the current model.
+    /// Constants are identified by their symbol, functions with arguments are skipped.
+    fn get_model(&mut self, ctx: &mut Context) -> Result<Vec<(ExprRef, ExprRef)>>;
+    /// Saves every following query into `dir`, see [`crate::smt::read_query_log`].
+    /// Needs to be called before any other command is sent to the solver.
+    fn log_queries(&mut self, dir: &Path) -> Result<()>;
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -163,6 +189,8 @@ impl<R: Write + Send> Solver<R> for SmtLibSolver {
             supports_uf: self.supports_uf,
             supports_check_assuming: self.supports_check_assuming,
             supports_const_array: self.supports_const_array,
+            recovery: None,
+            query_log: None,
         };
         for option in self.options.iter() {
             solver.write_cmd(
@@ -194,9 +222,117 @@ pub struct SmtLibSolverCtx<R: Write + Send> {
     supports_uf: bool,
     supports_check_assuming: bool,
     supports_const_array: bool,
+    /// only set when automatic recovery from solver crashes is enabled
+    recovery: Option<Recovery>,
+    query_log: Option<QueryLog>,
+}
+
+/// Everything needed to bring a restarted solver back into the state it was in before it crashed.
+struct Recovery {
+    max_restarts: u32,
+    restarts: u32,
+    stack: AssertionStack,
 }
 
 impl<R: Write + Send> SmtLibSolverCtx<R> {
+    /// Automatically restart the solver if it crashes and replay the assertion stack.
+    /// Gives up with [`Error::RecoveryFailed`] after `max_restarts` restarts.
+    pub fn with_recovery(mut self, max_restarts: u32) -> Self {
+        self.recovery = Some(Recovery {
+            max_restarts,
+            restarts: 0,
+            stack: AssertionStack::default(),
+        });
+        self
+    }
+
+    /// Number of times the solver was restarted because of a crash.
+    pub fn crash_restarts(&self) -> u32 {
+        self.recovery.as_ref().map(|r| r.restarts).unwrap_or(0)
+    }
+
+    /// Remembers commands that change the solver state, so that they can be replayed after a
+    /// crash and included in logged queries.
+    fn record(&mut self, ctx: Option<&Context>, cmd: &SmtCommand) -> Result<()> {
+        if self.recovery.is_none() && self.query_log.is_none() {
+            return Ok(());
+        }
+        let mut serialized = vec![];
+        serialize_cmd(&mut serialized, ctx, cmd)?;
+        if let Some(recovery) = self.recovery.as_mut() {
+            recovery.stack.record(cmd, &serialized);
+        }
+        if let Some(log) = self.query_log.as_mut() {
+            log.stack.record(cmd, &serialized);
+        }
+        Ok(())
+    }
+
+    /// Sends a `check-sat` or `check-sat-assuming` command and logs the query if enabled.
+    fn check(&mut self, ctx: Option<&Context>, cmd: &SmtCommand) -> Result<CheckSatResponse> {
+        let start = Instant::now();
+        let result = self.with_retry(|s| {
+            s.write_cmd(ctx, cmd)?;
+            s.read_sat_response()
+        });
+        if let Some(log) = self.query_log.as_mut() {
+            let mut check = vec![];
+            serialize_cmd(&mut check, ctx, cmd)?;
+            log.write_query(&self.name, &check, &result, start.elapsed())?;
+        }
+        result
+    }
+
+    /// Sends raw SMT-LIB commands which end in a `check-sat` and returns the answer.
+    pub(crate) fn run_script(&mut self, script: &[u8]) -> Result<CheckSatResponse> {
+        self.stdin.write_all(script)?;
+        self.read_sat_response()
+    }
+
+    fn is_crash(&mut self, error: &Error) -> bool {
+        match error {
+            Error::SolverDead(_) | Error::Io(_) => true,
+            Error::FromSolver(_, _) => matches!(self.proc.try_wait(), Ok(Some(_))),
+            _ => false,
+        }
+    }
+
+    /// Executes `op` and, if recovery is enabled, retries it after restarting a crashed solver.
+    fn with_retry<T>(&mut self, mut op: impl FnMut(&mut Self) -> Result<T>) -> Result<T> {
+        loop {
+            match op(self) {
+                Err(e) if self.recovery.is_some() && self.is_crash(&e) => self.recover()?,
+                other => return other,
+            }
+        }
+    }
+
+    fn recover(&mut self) -> Result<()> {
+        let recovery = self.recovery.as_mut().unwrap();
+        if recovery.restarts >= recovery.max_restarts {
+            return Err(Error::RecoveryFailed(self.name.clone(), recovery.restarts));
+        }
+        recovery.restarts += 1;
+        self.has_error = false;
+        self.restart()?;
+        // replay the assertion stack
+        let stack = std::mem::take(&mut self.recovery.as_mut().unwrap().stack);
+        let replayed = stack
+            .frames
+            .iter()
+            .try_for_each(|frame| self.stdin.write_all(frame))
+            .and_then(|_| self.stdin.flush());
+        self.recovery.as_mut().unwrap().stack = stack;
+        replayed?;
+        Ok(())
+    }
+
+    /// Sends a command that modifies the solver state.
+    fn write_state_cmd(&mut self, ctx: Option<&Context>, cmd: &SmtCommand) -> Result<()> {
+        self.with_retry(|s| s.write_cmd(ctx, cmd))?;
+        self.record(ctx, cmd)
+    }
+
     #[inline]
     fn write_cmd(&mut self, ctx: Option<&Context>, cmd: &SmtCommand) -> Result<()> {
         if let Some(rf) = self.replay_file.as_mut() {
@@ -341,19 +477,32 @@ impl<R: Write + Send> SolverContext for SmtLibSolverCtx<R> {
     }
 
     fn set_logic(&mut self, logic: Logic) -> Result<()> {
-        self.write_cmd(None, &SmtCommand::SetLogic(logic))
+        self.write_state_cmd(None, &SmtCommand::SetLogic(logic))
     }
 
     fn assert(&mut self, ctx: &Context, e: ExprRef) -> Result<()> {
-        self.write_cmd(Some(ctx), &SmtCommand::Assert(e))
+        self.write_state_cmd(Some(ctx), &SmtCommand::Assert(e))
+    }
+
+    fn assert_forall(&mut self, ctx: &Context, bound: &[ExprRef], e: ExprRef) -> Result<()> {
+        self.write_state_cmd(Some(ctx), &SmtCommand::AssertForAll(bound.to_vec(), e))
     }
 
     fn declare_const(&mut self, ctx: &Context, symbol: ExprRef) -> Result<()> {
-        self.write_cmd(Some(ctx), &SmtCommand::DeclareConst(symbol))
+        self.write_state_cmd(Some(ctx), &SmtCommand::DeclareConst(symbol))
+    }
+
+    fn declare_fun(&mut self, ctx: &Context, func: ExprRef, args: &[Type]) -> Result<()> {
+        debug_assert!(
+            self.supports_uf,
+            "{} does not support uninterpreted functions",
+            self.name
+        );
+        self.write_state_cmd(Some(ctx), &SmtCommand::DeclareFun(func, args.to_vec()))
     }
 
     fn define_const(&mut self, ctx: &Context, symbol: ExprRef, expr: ExprRef) -> Result<()> {
-        self.write_cmd(Some(ctx), &SmtCommand::DefineConst(symbol, expr))
+        self.write_state_cmd(Some(ctx), &SmtCommand::DefineConst(symbol, expr))
     }
 
     fn check_sat_assuming(
@@ -361,25 +510,23 @@ impl<R: Write + Send> SolverContext for SmtLibSolverCtx<R> {
         ctx: &Context,
         props: impl IntoIterator<Item = ExprRef>,
     ) -> Result<CheckSatResponse> {
-        let props: Vec<ExprRef> = props.into_iter().collect();
-        self.write_cmd(Some(ctx), &SmtCommand::CheckSatAssuming(props))?;
-        self.read_sat_response()
+        let cmd = SmtCommand::CheckSatAssuming(props.into_iter().collect());
+        self.check(Some(ctx), &cmd)
     }
 
     fn check_sat(&mut self) -> Result<CheckSatResponse> {
-        self.write_cmd(None, &SmtCommand::CheckSat)?;
-        self.read_sat_response()
+        self.check(None, &SmtCommand::CheckSat)
     }
 
     fn push(&mut self) -> Result<()> {
-        self.write_cmd(None, &SmtCommand::Push(1))?;
+        self.write_state_cmd(None, &SmtCommand::Push(1))?;
         self.stack_depth += 1;
         Ok(())
     }
 
     fn pop(&mut self) -> Result<()> {
         if self.stack_depth > 0 {
-            self.write_cmd(None, &SmtCommand::Pop(1))?;
+            self.write_state_cmd(None, &SmtCommand::Pop(1))?;
             self.stack_depth -= 1;
             Ok(())
         } else {
@@ -395,13 +542,27 @@ impl<R: Write + Send> SolverContext for SmtLibSolverCtx<R> {
         let expr = parse_get_value_response(ctx, response.as_bytes())?;
         Ok(expr)
     }
+
+    fn get_model(&mut self, ctx: &mut Context) -> Result<Vec<(ExprRef, ExprRef)>> {
+        self.write_cmd(Some(ctx), &SmtCommand::GetModel)?;
+        self.stdin.flush()?; // make sure that the commands reached the solver
+        self.read_response()?;
+        let response = self.response.trim();
+        let model = parse_get_model_response(ctx, response.as_bytes())?;
+        Ok(model)
+    }
+
+    fn log_queries(&mut self, dir: &Path) -> Result<()> {
+        self.query_log = Some(QueryLog::create(dir)?);
+        Ok(())
+    }
 }
 
 pub const BITWUZLA: SmtLibSolver = SmtLibSolver {
     name: "bitwuzla",
     args: &[],
     options: &["incremental", "produce-models"],
-    supports_uf: false,
+    supports_uf: true,
     supports_check_assuming: true,
     supports_const_array: true,
 };
@@ -450,6 +611,25 @@ mod tests {
         assert_eq!(value_of_a, ctx.bit_vec_val(3, 3));
     }
 
+    #[test]
+    fn test_bitwuzla_get_model() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("a", 3);
+        let b = ctx.bv_symbol("b", 3);
+        let e = ctx.build(|c| c.and(c.equal(a, c.bit_vec_val(3, 3)), c.equal(b, c.add(a, a))));
+        let mut solver = BITWUZLA.start(None::<std::fs::File>).unwrap();
+        solver.declare_const(&ctx, a).unwrap();
+        solver.declare_const(&ctx, b).unwrap();
+        solver.assert(&ctx, e).unwrap();
+        assert_eq!(solver.check_sat().unwrap(), CheckSatResponse::Sat);
+        let mut model = solver.get_model(&mut ctx).unwrap();
+        model.sort();
+        assert_eq!(
+            model,
+            [(a, ctx.bit_vec_val(3, 3)), (b, ctx.bit_vec_val(6, 3))]
+        );
+    }
+
     #[test]
     fn test_bitwuzla_restart() {
         let mut ctx = Context::default();
@@ -470,4 +650,78 @@ mod tests {
         let value_of_a = solver.get_value(&mut ctx, a).unwrap();
         assert_eq!(value_of_a, four);
     }
+
+    #[test]
+    fn test_bitwuzla_forall() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("a", 3);
+        let x = ctx.bv_symbol("x", 3);
+        // a is the neutral element of addition
+        let e = ctx.build(|c| c.equal(c.add(x, a), x));
+        let mut solver = BITWUZLA.start(None::<std::fs::File>).unwrap();
+        solver.set_logic(Logic::Bv).unwrap();
+        solver.declare_const(&ctx, a).unwrap();
+        solver.assert_forall(&ctx, &[x], e).unwrap();
+        assert_eq!(solver.check_sat().unwrap(), CheckSatResponse::Sat);
+        let value_of_a = solver.get_value(&mut ctx, a).unwrap();
+        assert_eq!(value_of_a, ctx.bit_vec_val(0, 3));
+    }
+
+    #[test]
+    fn test_bitwuzla_uninterpreted_function() {
+        let mut ctx = Context::default();
+        let f = ctx.function("f", 1, 4);
+        let a = ctx.bv_symbol("a", 4);
+        let b = ctx.bv_symbol("b", 4);
+        let f_a = ctx.apply(f, &[a]);
+        let f_b = ctx.apply(f, &[b]);
+        let mut solver = BITWUZLA.start(None::<std::fs::File>).unwrap();
+        solver.set_logic(Logic::QfAufbv).unwrap();
+        solver.declare_fun(&ctx, f, &[Type::BV(4)]).unwrap();
+        solver.declare_const(&ctx, a).unwrap();
+        solver.declare_const(&ctx, b).unwrap();
+        // different results are possible for different arguments
+        let results_differ = ctx.build(|c| c.not(c.equal(f_a, f_b)));
+        solver.assert(&ctx, results_differ).unwrap();
+        assert_eq!(solver.check_sat().unwrap(), CheckSatResponse::Sat);
+        // but functions are consistent
+        let args_equal = ctx.equal(a, b);
+        solver.assert(&ctx, args_equal).unwrap();
+        assert_eq!(solver.check_sat().unwrap(), CheckSatResponse::Unsat);
+    }
+
+    #[test]
+    fn test_bitwuzla_recovers_from_crash() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("a", 3);
+        let e = ctx.build(|c| c.equal(a, c.bit_vec_val(3, 3)));
+        let not_e = ctx.not(e);
+        let mut solver = BITWUZLA
+            .start(None::<std::fs::File>)
+            .unwrap()
+            .with_recovery(1);
+        solver.declare_const(&ctx, a).unwrap();
+        solver.push().unwrap();
+        solver.assert(&ctx, e).unwrap();
+
+        // simulate a crash
+        solver.proc.kill().unwrap();
+        solver.proc.wait().unwrap();
+
+        // the assertion stack is replayed, thus `a == 3 && a != 3` is still unsat
+        let res = solver.check_sat_assuming(&ctx, [not_e]).unwrap();
+        assert_eq!(res, CheckSatResponse::Unsat);
+        assert_eq!(solver.crash_restarts(), 1);
+        solver.pop().unwrap();
+        let res = solver.check_sat_assuming(&ctx, [not_e]).unwrap();
+        assert_eq!(res, CheckSatResponse::Sat);
+
+        // no restarts left
+        solver.proc.kill().unwrap();
+        solver.proc.wait().unwrap();
+        assert!(matches!(
+            solver.check_sat(),
+            Err(Error::RecoveryFailed(_, 1))
+        ));
+    }
 }
@@ -0,0 +1,307 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Sketch Based Synthesis
+//! A sketch is an expression with holes, i.e., constants or operator choices that are left open.
+//! The synthesizer fills all holes such that the sketch becomes equivalent to a specification,
+//! which is useful to discover small peephole rewrites.
+//!
+//! We use counterexample guided inductive synthesis (CEGIS): an SMT solver proposes hole values
+//! that work for a growing set of example inputs and every proposal is checked for full
+//! equivalence. Counterexamples become new examples until a proposal is correct or no fill is
+//! left.
+
+use crate::equiv::{collect_symbols, prove_equiv, EquivBackend, EquivOptions, EquivResult};
+use crate::expr::{simple_transform_expr, Context, ExprRef, TypeCheck, WidthInt};
+use crate::mc::get_smt_value;
+use crate::random::{default_seed, new_rng};
+use crate::smt::{
+    CheckSatResponse, Logic, SmtLibSolver, Solver, SolverContext, SolverMetaData, BITWUZLA,
+};
+use baa::{BitVecOps, BitVecValue, Value};
+
+#[derive(Debug, thiserror::Error)]
+pub enum SynthError {
+    #[error("`{0}` is not a bit-vector, only bit-vector inputs are supported")]
+    NotBitVec(String),
+    #[error("no solution found after {0} iterations")]
+    IterationLimit(u32),
+    #[error(transparent)]
+    Equiv(#[from] crate::equiv::EquivError),
+    #[error(transparent)]
+    Smt(#[from] crate::smt::Error),
+}
+
+pub type Result<T> = std::result::Result<T, SynthError>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HoleKind {
+    /// An unknown constant.
+    Constant,
+    /// Picks one of the options. The hole symbol selects the option by index and any index out
+    /// of range selects the last option.
+    Choice {
+        options: Vec<ExprRef>,
+        /// root of the ite chain that implements the choice
+        expr: ExprRef,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hole {
+    pub symbol: ExprRef,
+    pub kind: HoleKind,
+}
+
+/// Keeps track of the holes used in sketch expressions.
+#[derive(Debug, Clone, Default)]
+pub struct Sketch {
+    holes: Vec<Hole>,
+}
+
+/// Values for all holes of a sketch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Solution {
+    pub values: Vec<(ExprRef, BitVecValue)>,
+}
+
+impl Solution {
+    pub fn get(&self, hole: ExprRef) -> Option<&BitVecValue> {
+        self.values.iter().find(|(h, _)| *h == hole).map(|(_, v)| v)
+    }
+}
+
+impl Sketch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn holes(&self) -> &[Hole] {
+        &self.holes
+    }
+
+    /// A `width` bit constant that is picked by the synthesizer.
+    pub fn constant(&mut self, ctx: &mut Context, width: WidthInt) -> ExprRef {
+        let symbol = self.hole_symbol(ctx, width);
+        self.holes.push(Hole {
+            symbol,
+            kind: HoleKind::Constant,
+        });
+        symbol
+    }
+
+    /// One of the `options`, which all need to have the same type.
+    pub fn choice(&mut self, ctx: &mut Context, options: &[ExprRef]) -> ExprRef {
+        assert!(!options.is_empty(), "a choice needs at least one option");
+        debug_assert!(options
+            .iter()
+            .all(|o| o.get_type(ctx) == options[0].get_type(ctx)));
+        let width = usize::BITS - (options.len() - 1).leading_zeros();
+        let selector = self.hole_symbol(ctx, width.max(1));
+        let (last, rest) = options.split_last().unwrap();
+        let expr = rest
+            .iter()
+            .enumerate()
+            .rev()
+            .fold(*last, |fals, (ii, &tru)| {
+                ctx.build(|c| {
+                    let index = c.bit_vec_val(ii as u64, width.max(1));
+                    c.ite(c.equal(selector, index), tru, fals)
+                })
+            });
+        self.holes.push(Hole {
+            symbol: selector,
+            kind: HoleKind::Choice {
+                options: options.to_vec(),
+                expr,
+            },
+        });
+        expr
+    }
+
+    fn hole_symbol(&self, ctx: &mut Context, width: WidthInt) -> ExprRef {
+        let name = format!("__hole_{}", self.holes.len());
+        ctx.bv_symbol(&name, width)
+    }
+
+    fn is_hole(&self, e: ExprRef) -> bool {
+        self.holes.iter().any(|h| h.symbol == e)
+    }
+
+    /// Replaces all holes in `e` with the values from `solution`. Choices are replaced by the
+    /// selected option.
+    pub fn fill(&self, ctx: &mut Context, e: ExprRef, solution: &Solution) -> ExprRef {
+        simple_transform_expr(ctx, e, |ctx, e, _| {
+            let hole = self.holes.iter().find(|h| match &h.kind {
+                HoleKind::Constant => h.symbol == e,
+                HoleKind::Choice { expr, .. } => *expr == e,
+            })?;
+            let value = solution.get(hole.symbol)?;
+            match &hole.kind {
+                HoleKind::Constant => Some(ctx.bv_lit(value)),
+                HoleKind::Choice { options, .. } => {
+                    let index = value.to_u64().map_or(usize::MAX, |i| i as usize);
+                    let option = options[index.min(options.len() - 1)];
+                    Some(self.fill(ctx, option, solution))
+                }
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SynthOptions {
+    pub solver: SmtLibSolver,
+    /// number of random examples to start with
+    pub initial_examples: usize,
+    pub max_iterations: u32,
+    pub seed: u64,
+}
+
+impl Default for SynthOptions {
+    fn default() -> Self {
+        Self {
+            solver: BITWUZLA,
+            initial_examples: 4,
+            max_iterations: 64,
+            seed: default_seed(),
+        }
+    }
+}
+
+/// Fills the holes in `sketch_expr` such that it is equivalent to `spec`.
+/// Returns `None` if no such fill exists.
+pub fn synthesize(
+    ctx: &mut Context,
+    sketch: &Sketch,
+    sketch_expr: ExprRef,
+    spec: ExprRef,
+    opts: &SynthOptions,
+) -> Result<Option<Solution>> {
+    let inputs: Vec<(ExprRef, WidthInt)> = collect_symbols(ctx, [sketch_expr, spec])
+        .into_iter()
+        .filter(|&s| !sketch.is_hole(s))
+        .map(|s| {
+            s.get_bv_type(ctx)
+                .map(|w| (s, w))
+                .ok_or_else(|| SynthError::NotBitVec(ctx.get_symbol_name(s).unwrap().into()))
+        })
+        .collect::<Result<_>>()?;
+
+    let mut smt_ctx = opts.solver.start(None::<std::fs::File>)?;
+    smt_ctx.set_logic(if opts.solver.supports_uf() {
+        Logic::QfAufbv
+    } else {
+        Logic::QfAbv
+    })?;
+    for hole in sketch.holes.iter() {
+        smt_ctx.declare_const(ctx, hole.symbol)?;
+    }
+    let correct = ctx.equal(sketch_expr, spec);
+
+    let mut rng = new_rng(opts.seed);
+    let mut examples: Vec<Vec<(ExprRef, BitVecValue)>> = (0..opts.initial_examples)
+        .map(|_| {
+            inputs
+                .iter()
+                .map(|&(s, w)| (s, BitVecValue::random(&mut rng, w)))
+                .collect()
+        })
+        .collect();
+    let equiv_opts = EquivOptions {
+        backend: EquivBackend::Smt(opts.solver.clone()),
+        ..Default::default()
+    };
+
+    for _ in 0..opts.max_iterations {
+        // only new examples need to be asserted
+        for example in examples.drain(..) {
+            let constraint = simple_transform_expr(ctx, correct, |ctx, e, _| {
+                let (_, value) = example.iter().find(|(s, _)| *s == e)?;
+                Some(ctx.bv_lit(value))
+            });
+            smt_ctx.assert(ctx, constraint)?;
+        }
+        if smt_ctx.check_sat()? != CheckSatResponse::Sat {
+            return Ok(None);
+        }
+        let values = sketch
+            .holes
+            .iter()
+            .map(|h| match get_smt_value(ctx, &mut smt_ctx, h.symbol)? {
+                Value::BitVec(v) => Ok((h.symbol, v)),
+                Value::Array(_) => unreachable!("holes are bit-vectors"),
+            })
+            .collect::<Result<_>>()?;
+        let solution = Solution { values };
+        let candidate = sketch.fill(ctx, sketch_expr, &solution);
+        match prove_equiv(ctx, candidate, spec, &equiv_opts)? {
+            EquivResult::Equivalent => return Ok(Some(solution)),
+            EquivResult::NotEquivalent(assignment) => {
+                let example = assignment
+                    .into_iter()
+                    .flat_map(|(s, v)| match v {
+                        Value::BitVec(v) => Some((s, v)),
+                        Value::Array(_) => None,
+                    })
+                    .collect();
+                examples.push(example);
+            }
+        }
+    }
+    Err(SynthError::IterationLimit(opts.max_iterations))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::SerializableIrNode;
+
+    #[test]
+    fn test_constant_hole() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("a", 8);
+        let mut sketch = Sketch::new();
+        let c = sketch.constant(&mut ctx, 8);
+        let sketch_expr = ctx.mul(a, c);
+        let spec = ctx.build(|c| c.shift_left(a, c.bit_vec_val(3, 8)));
+        let solution = synthesize(&mut ctx, &sketch, sketch_expr, spec, &Default::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(solution.get(c).unwrap().to_u64().unwrap(), 8);
+        let filled = sketch.fill(&mut ctx, sketch_expr, &solution);
+        assert_eq!(filled, ctx.build(|c| c.mul(a, c.bit_vec_val(8, 8))));
+    }
+
+    #[test]
+    fn test_operator_choice() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("a", 4);
+        let b = ctx.bv_symbol("b", 4);
+        let mut sketch = Sketch::new();
+        let options = [ctx.add(a, b), ctx.sub(a, b), ctx.and(a, b), ctx.or(a, b)];
+        let sketch_expr = sketch.choice(&mut ctx, &options);
+        // a - b == a + ~b + 1
+        let spec = ctx.build(|c| c.add(c.add(a, c.not(b)), c.one(4)));
+        let solution = synthesize(&mut ctx, &sketch, sketch_expr, spec, &Default::default())
+            .unwrap()
+            .unwrap();
+        let filled = sketch.fill(&mut ctx, sketch_expr, &solution);
+        assert_eq!(filled.serialize_to_str(&ctx), "sub(a, b)");
+    }
+
+    #[test]
+    fn test_infeasible() {
+        let mut ctx = Context::default();
+        let a = ctx.bv_symbol("a", 4);
+        let mut sketch = Sketch::new();
+        let c = sketch.constant(&mut ctx, 4);
+        let sketch_expr = ctx.and(a, c);
+        let spec = ctx.build(|c| c.add(a, c.one(4)));
+        assert_eq!(
+            synthesize(&mut ctx, &sketch, sketch_expr, spec, &Default::default()).unwrap(),
+            None
+        );
+    }
+}
@@ -3,9 +3,68 @@
 // released under BSD 3-Clause License
 // author: Kevin Laeufer <laeufer@cornell.edu>
 
+mod abstraction;
 pub mod analysis;
+mod clock_reset;
+mod criticality;
+mod diff;
+mod fsm;
+mod hierarchy;
+mod idioms;
+mod invariants;
+mod isomorphism;
+mod memory_image;
+mod mutation;
+mod names;
+mod passes;
+mod pipeline;
+mod precision;
+mod provenance;
 mod serialize;
+mod slice;
+mod stats;
+mod templates;
+mod temporal;
 pub mod transform;
 mod transition_system;
+mod trivial_constraints;
+mod validate;
 
+pub use abstraction::{
+    abstract_datapath, AbstractFunction, Abstraction, DatapathAbstraction, OperatorClass,
+};
+pub use clock_reset::{
+    infer_clock_reset, ClockCandidate, ClockEnable, ClockResetReport, ResetCandidate,
+    ATTR_CLOCK_ENABLE,
+};
+pub use criticality::{rank_criticality, Criticality, CriticalityReport};
+pub use diff::{diff, expr_differences, Change, ElementKind, SystemDiff};
+pub use fsm::{find_fsms, Fsm, Transition, MAX_FSM_WIDTH};
+pub use hierarchy::{NameError, NameIndex, HIERARCHY_SEPARATOR};
+pub use idioms::{find_idioms, Annotations, Counter, Idiom};
+pub use invariants::{simplify_with_invariants, InvariantReport, PrunedBranch};
+pub use isomorphism::{
+    find_isomorphic_subgraphs, IsomorphicSubgraphs, SubgraphInstance, MAX_SUBGRAPH_NODES,
+};
+pub use memory_image::{MemoryImage, MemoryImageError, MemoryImageFormat, MemoryImageResult};
+pub use mutation::{find_mutants, run_mutation_tests, Mutant, MutationKind, MutationReport};
+pub use names::{
+    prefix_all, rename_signals, rename_with, NamePolicy, RenameMap, RenameReport, SymbolRenames,
+};
+pub use passes::{
+    Canonicalize, FnPass, Pass, PassConfig, PassManager, PassRun, PassStatistics,
+    PropagateConstantStates, ReplaceAnonymousInputs, Simplify,
+};
+pub use pipeline::{insert_pipeline_registers, pipeline_inputs};
+pub use precision::{analyze_ranges, reduce_precision, PrecisionReport, Ranges, ReducedOp};
+pub use provenance::{Origin, Provenance};
+pub use slice::{extract_cone, extract_cone_with_cut};
+pub use stats::SystemStats;
+pub use templates::{add_safety_property, SafetyTemplate, TemplateError};
+pub use temporal::{add_property, Property, PropertyError};
 pub use transition_system::*;
+pub use trivial_constraints::{find_trivial_constraints, TrivialConstraint, Triviality};
+pub use validate::{
+    break_cycles, detect_cycles, init_order, validate_states, CycleBreaking, CycleError, InitCycle,
+    StateKind, StateValidation,
+};
@@ -0,0 +1,308 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Datapath Abstraction
+//! Replaces expensive operators like multipliers and dividers with uninterpreted functions.
+//! All operators of the same kind and width share a function, which means that two datapaths
+//! computing the same product can still be proven equivalent.
+//! The abstraction over-approximates the behavior of the system: safety proofs carry over
+//! to the concrete system, counterexamples might be spurious and require refinement.
+
+use super::transform::do_transform;
+use super::{NamePolicy, Pass, TransitionSystem};
+use crate::expr::*;
+use rustc_hash::FxHashSet;
+use serde::{Deserialize, Serialize};
+
+/// Operators that can be abstracted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OperatorClass {
+    /// multiplication
+    Mul,
+    /// signed and unsigned division
+    Div,
+    /// signed and unsigned remainder as well as signed modulo
+    Rem,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Operator {
+    Mul,
+    UnsignedDiv,
+    SignedDiv,
+    UnsignedRem,
+    SignedRem,
+    SignedMod,
+}
+
+impl Operator {
+    fn from_expr(expr: &Expr) -> Option<(Self, WidthInt)> {
+        match *expr {
+            Expr::BVMul(_, _, w) => Some((Operator::Mul, w)),
+            Expr::BVUnsignedDiv(_, _, w) => Some((Operator::UnsignedDiv, w)),
+            Expr::BVSignedDiv(_, _, w) => Some((Operator::SignedDiv, w)),
+            Expr::BVUnsignedRem(_, _, w) => Some((Operator::UnsignedRem, w)),
+            Expr::BVSignedRem(_, _, w) => Some((Operator::SignedRem, w)),
+            Expr::BVSignedMod(_, _, w) => Some((Operator::SignedMod, w)),
+            _ => None,
+        }
+    }
+
+    fn class(&self) -> OperatorClass {
+        match self {
+            Operator::Mul => OperatorClass::Mul,
+            Operator::UnsignedDiv | Operator::SignedDiv => OperatorClass::Div,
+            Operator::UnsignedRem | Operator::SignedRem | Operator::SignedMod => OperatorClass::Rem,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Operator::Mul => "mul",
+            Operator::UnsignedDiv => "udiv",
+            Operator::SignedDiv => "sdiv",
+            Operator::UnsignedRem => "urem",
+            Operator::SignedRem => "srem",
+            Operator::SignedMod => "smod",
+        }
+    }
+
+    fn build(&self, ctx: &mut Context, a: ExprRef, b: ExprRef) -> ExprRef {
+        match self {
+            Operator::Mul => ctx.mul(a, b),
+            Operator::UnsignedDiv => ctx.div(a, b),
+            Operator::SignedDiv => ctx.signed_div(a, b),
+            Operator::UnsignedRem => ctx.remainder(a, b),
+            Operator::SignedRem => ctx.signed_remainder(a, b),
+            Operator::SignedMod => ctx.signed_mod(a, b),
+        }
+    }
+}
+
+/// An uninterpreted function that stands in for all operators of the same kind and width.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AbstractFunction {
+    pub func: ExprRef,
+    pub class: OperatorClass,
+    pub width: WidthInt,
+    /// number of distinct operator expressions that were replaced
+    pub uses: usize,
+    op: Operator,
+}
+
+/// Keeps track of all functions that are currently abstracted in a system.
+#[derive(Debug, Clone, Default)]
+pub struct Abstraction {
+    functions: Vec<AbstractFunction>,
+}
+
+impl Abstraction {
+    pub fn functions(&self) -> &[AbstractFunction] {
+        &self.functions
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.functions.is_empty()
+    }
+
+    /// Replaces all applications of `func` with the concrete operator.
+    /// Returns `false` if `func` is not part of the abstraction.
+    pub fn refine(&mut self, ctx: &mut Context, sys: &mut TransitionSystem, func: ExprRef) -> bool {
+        let Some(pos) = self.functions.iter().position(|f| f.func == func) else {
+            return false;
+        };
+        let f = self.functions.remove(pos);
+        do_transform(
+            ctx,
+            sys,
+            ExprTransformMode::SingleStep,
+            |ctx, expr, children| {
+                if !matches!(ctx[expr], Expr::BVApply { .. })
+                    || ctx.is_partial_application(expr)
+                    || ctx.get_application(expr)?.0 != f.func
+                {
+                    return None;
+                }
+                // children might have been refined as well
+                let (_, args) = ctx.get_application_args(children[0], children[1])?;
+                Some(f.op.build(ctx, args[0], args[1]))
+            },
+        );
+        true
+    }
+
+    /// Reintroduces the concrete semantics of all abstracted operators.
+    pub fn refine_all(&mut self, ctx: &mut Context, sys: &mut TransitionSystem) {
+        let funcs = self.functions.iter().map(|f| f.func).collect::<Vec<_>>();
+        for func in funcs {
+            self.refine(ctx, sys, func);
+        }
+    }
+
+    /// Returns all abstracted functions that `roots` depend on, either directly or through
+    /// the next state and init functions of the states they depend on.
+    pub fn functions_in_cone(
+        &self,
+        ctx: &Context,
+        sys: &TransitionSystem,
+        roots: &[ExprRef],
+    ) -> Vec<ExprRef> {
+        let states = sys.state_map();
+        let mut used = FxHashSet::default();
+        let mut visited = FxHashSet::default();
+        let mut todo = roots.to_vec();
+        while let Some(e) = todo.pop() {
+            if !visited.insert(e) {
+                continue;
+            }
+            if let Some(state) = states.get(&e) {
+                todo.extend(state.next);
+                todo.extend(state.init);
+            }
+            if let Some((func, _)) = ctx.get_application(e) {
+                used.insert(func);
+            }
+            ctx[e].for_each_child(|&c| todo.push(c));
+        }
+        self.functions
+            .iter()
+            .map(|f| f.func)
+            .filter(|f| used.contains(f))
+            .collect()
+    }
+}
+
+/// Replaces all operators of the selected classes that are at least `min_width` bits wide with
+/// uninterpreted functions.
+pub fn abstract_datapath(
+    ctx: &mut Context,
+    sys: &mut TransitionSystem,
+    classes: &[OperatorClass],
+    min_width: WidthInt,
+) -> Abstraction {
+    let names = sys.get_name_map(ctx);
+    let policy = NamePolicy::Prefix("__abs_".to_string());
+    let mut functions: Vec<AbstractFunction> = vec![];
+    do_transform(
+        ctx,
+        sys,
+        ExprTransformMode::SingleStep,
+        |ctx, expr, children| {
+            let (op, width) = Operator::from_expr(&ctx[expr])?;
+            if !classes.contains(&op.class()) || width < min_width {
+                return None;
+            }
+            let index = match functions
+                .iter()
+                .position(|f| f.op == op && f.width == width)
+            {
+                Some(index) => index,
+                None => {
+                    let base = format!("{}{width}", op.name());
+                    let name = policy.generate(&base, |n| names.contains_key(n));
+                    let func = ctx.function(&name, 2, width);
+                    functions.push(AbstractFunction {
+                        func,
+                        class: op.class(),
+                        width,
+                        uses: 0,
+                        op,
+                    });
+                    functions.len() - 1
+                }
+            };
+            functions[index].uses += 1;
+            Some(ctx.apply(functions[index].func, &[children[0], children[1]]))
+        },
+    );
+    Abstraction { functions }
+}
+
+/// Pass that abstracts the datapath, see [`abstract_datapath`].
+/// The resulting abstraction is kept in order to allow for later refinement.
+#[derive(Debug, Clone)]
+pub struct DatapathAbstraction {
+    pub classes: Vec<OperatorClass>,
+    pub min_width: WidthInt,
+    abstraction: Abstraction,
+}
+
+impl DatapathAbstraction {
+    pub fn new(classes: Vec<OperatorClass>, min_width: WidthInt) -> Self {
+        Self {
+            classes,
+            min_width,
+            abstraction: Abstraction::default(),
+        }
+    }
+
+    pub fn abstraction(&self) -> &Abstraction {
+        &self.abstraction
+    }
+
+    pub fn into_abstraction(self) -> Abstraction {
+        self.abstraction
+    }
+}
+
+impl Pass for DatapathAbstraction {
+    fn name(&self) -> &'static str {
+        "datapath-abstraction"
+    }
+
+    fn run(&mut self, ctx: &mut Context, sys: &mut TransitionSystem) {
+        let abstraction = abstract_datapath(ctx, sys, &self.classes, self.min_width);
+        self.abstraction.functions.extend(abstraction.functions);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_abstract_and_refine() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let a = ctx.bv_symbol("a", 8);
+        let b = ctx.bv_symbol("b", 8);
+        sys.add_input(&ctx, a);
+        sys.add_input(&ctx, b);
+        let prod = ctx.mul(a, b);
+        let quot = ctx.div(a, b);
+        let narrow = ctx.build(|c| c.mul(c.slice(a, 3, 0), c.slice(b, 3, 0)));
+        let nested = ctx.mul(prod, b);
+        sys.add_output(&mut ctx, "prod".into(), prod);
+        sys.add_output(&mut ctx, "quot".into(), quot);
+        sys.add_output(&mut ctx, "narrow".into(), narrow);
+        sys.add_output(&mut ctx, "nested".into(), nested);
+        let original = sys.clone();
+
+        let mut abstraction = abstract_datapath(&mut ctx, &mut sys, &[OperatorClass::Mul], 8);
+        // both 8-bit multiplications share a single function
+        assert_eq!(abstraction.functions().len(), 1);
+        let f = abstraction.functions()[0].clone();
+        assert_eq!(f.uses, 2);
+        assert_eq!(ctx.get_symbol_name(f.func), Some("__abs_mul8"));
+        assert_eq!(
+            sys.outputs[3].expr.serialize_to_str(&ctx),
+            "__abs_mul8(__abs_mul8(a, b), b)"
+        );
+        // division and the narrow multiplication are untouched
+        assert_eq!(sys.outputs[1].expr, quot);
+        assert_eq!(sys.outputs[2].expr, narrow);
+        assert_eq!(
+            abstraction.functions_in_cone(&ctx, &sys, &[sys.outputs[3].expr]),
+            [f.func]
+        );
+
+        assert!(abstraction.refine(&mut ctx, &mut sys, f.func));
+        assert!(abstraction.is_empty());
+        assert!(!abstraction.refine(&mut ctx, &mut sys, f.func));
+        for (out, orig) in sys.outputs.iter().zip(original.outputs.iter()) {
+            assert_eq!(out.expr, orig.expr);
+        }
+    }
+}
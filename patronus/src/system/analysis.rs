@@ -191,6 +191,7 @@ pub enum SerializeSignalKind {
     BadState,
     Constraint,
     Output,
+    Define,
     Input,
     StateInit,
     StateNext,
@@ -288,6 +289,14 @@ pub fn analyze_for_serialization(
                 SerializeSignalKind::Output,
             )
         }));
+        todo.extend(sys.defines.iter().map(|d| {
+            RootInfo::new(
+                d.expr,
+                Some(d.name),
+                uses[d.expr],
+                SerializeSignalKind::Define,
+            )
+        }));
     }
     todo.extend(sys.constraints.iter().map(|&e| {
         RootInfo::new(
@@ -359,11 +368,14 @@ pub fn analyze_for_serialization(
         let is_output_like = matches!(
             info.kind,
             SerializeSignalKind::Output
+                | SerializeSignalKind::Define
                 | SerializeSignalKind::Constraint
                 | SerializeSignalKind::BadState
         );
         let is_input = info.kind == SerializeSignalKind::Input;
-        if num_children > 0 || is_output_like || is_input {
+        // partial function applications cannot be expressed as a separate signal
+        let is_partial_application = ctx.is_partial_application(info.expr);
+        if (num_children > 0 || is_output_like || is_input) && !is_partial_application {
             let used_multiple_times = info.uses.total() > 1;
             if is_output_like || used_multiple_times {
                 signal_order.push(info.clone());
@@ -375,6 +387,32 @@ pub fn analyze_for_serialization(
     SerializeMeta { signal_order }
 }
 
+/// Finds all uninterpreted functions used by the system, together with their argument types
+/// derived from the first complete application.
+pub fn find_uninterpreted_functions(
+    ctx: &Context,
+    sys: &TransitionSystem,
+) -> Vec<(ExprRef, Vec<Type>)> {
+    let mut out: Vec<(ExprRef, Vec<Type>)> = vec![];
+    let mut visited = DenseExprSet::default();
+    let mut todo = sys.get_assert_assume_output_exprs();
+    todo.extend(sys.get_init_exprs());
+    todo.extend(sys.get_next_exprs());
+    while let Some(e) = todo.pop() {
+        if visited.contains(&e) {
+            continue;
+        }
+        visited.insert(e);
+        if let Some((func, args)) = ctx.get_application(e) {
+            if !ctx.is_partial_application(e) && !out.iter().any(|(f, _)| *f == func) {
+                out.push((func, args.iter().map(|a| a.get_type(ctx)).collect()));
+            }
+        }
+        ctx[e].for_each_child(|&c| todo.push(c));
+    }
+    out
+}
+
 #[inline]
 fn find_name(ctx: &Context, sys: &TransitionSystem, e: ExprRef) -> Option<StringRef> {
     ctx[e].get_symbol_name_ref().or_else(|| sys.names[e])
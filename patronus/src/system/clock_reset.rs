@@ -0,0 +1,306 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Clock and Reset Inference
+//! Designs imported from btor2 carry no information about which inputs are clocks or resets.
+//! This pass recognizes them from the structure of the next state functions:
+//! - a synchronous reset is a 1-bit input that selects a constant in a mux at the root of the
+//!   next state function, e.g., `ite(rst, 8'b0, ...)`
+//! - a clock enable is a condition that selects between a new value and the state itself,
+//!   e.g., `ite(en, d, q)`
+//! - a clock is an input that is sampled by a state and compared with its previous value, as
+//!   generated by yosys' `clk2fflogic` pass
+//!
+//! The results are heuristics and can be turned into [`ATTR_CLOCK`], [`ATTR_RESET`] and
+//! [`ATTR_CLOCK_ENABLE`] attributes.
+
+use super::TransitionSystem;
+use crate::expr::*;
+use rustc_hash::FxHashMap;
+
+/// Attached to the condition of a clock enable.
+pub const ATTR_CLOCK_ENABLE: &str = "clock_enable";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResetCandidate {
+    pub input: ExprRef,
+    pub active_high: bool,
+    /// states that are reset by this input together with their reset value
+    pub states: Vec<(ExprRef, ExprRef)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClockEnable {
+    /// 1-bit expression, the states are updated iff it is true
+    pub enable: ExprRef,
+    pub states: Vec<ExprRef>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClockCandidate {
+    pub input: ExprRef,
+    /// state that holds the value of the clock in the previous step
+    pub past: ExprRef,
+    pub posedge: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClockResetReport {
+    pub clocks: Vec<ClockCandidate>,
+    pub resets: Vec<ResetCandidate>,
+    pub enables: Vec<ClockEnable>,
+}
+
+impl ClockResetReport {
+    /// Reset value of `state`, if it has a synchronous reset.
+    pub fn reset_value(&self, state: ExprRef) -> Option<ExprRef> {
+        self.resets
+            .iter()
+            .flat_map(|r| r.states.iter())
+            .find(|(s, _)| *s == state)
+            .map(|(_, value)| *value)
+    }
+
+    /// Attaches the inferred roles as attributes.
+    pub fn annotate(&self, ctx: &mut Context) {
+        for clock in self.clocks.iter() {
+            ctx.add_attribute(clock.input, ATTR_CLOCK);
+        }
+        for reset in self.resets.iter() {
+            ctx.add_attribute(reset.input, ATTR_RESET);
+        }
+        for enable in self.enables.iter() {
+            ctx.add_attribute(enable.enable, ATTR_CLOCK_ENABLE);
+        }
+    }
+}
+
+pub fn infer_clock_reset(ctx: &mut Context, sys: &TransitionSystem) -> ClockResetReport {
+    let is_input = |e: ExprRef| sys.inputs.contains(&e);
+    let mut resets: Vec<ResetCandidate> = vec![];
+    let mut enables: Vec<ClockEnable> = vec![];
+    let mut conflicting_polarity = vec![];
+
+    for state in sys.states.iter() {
+        let Some(mut next) = state.next else {
+            continue;
+        };
+        if state.symbol.get_bv_type(ctx).is_none() {
+            continue;
+        }
+        // a reset mux is always the outermost one
+        if let Some((input, active_high, value, rest)) = reset_mux(ctx, next, &is_input) {
+            match resets.iter_mut().find(|r| r.input == input) {
+                Some(r) if r.active_high == active_high => r.states.push((state.symbol, value)),
+                Some(_) => conflicting_polarity.push(input),
+                None => resets.push(ResetCandidate {
+                    input,
+                    active_high,
+                    states: vec![(state.symbol, value)],
+                }),
+            }
+            next = rest;
+        }
+        if let Some(enable) = enable_mux(ctx, state.symbol, next) {
+            match enables.iter_mut().find(|e| e.enable == enable) {
+                Some(e) => e.states.push(state.symbol),
+                None => enables.push(ClockEnable {
+                    enable,
+                    states: vec![state.symbol],
+                }),
+            }
+        }
+    }
+    resets.retain(|r| !conflicting_polarity.contains(&r.input));
+    let clocks = find_clocks(ctx, sys);
+    // the edge detector of a clock looks like an enable
+    let edges: Vec<ExprRef> = clocks.iter().map(|c| edge(ctx, c)).collect();
+    enables.retain(|e| !edges.contains(&e.enable));
+    ClockResetReport {
+        clocks,
+        resets,
+        enables,
+    }
+}
+
+/// Matches `ite(rst, const, rest)` and `ite(rst_n, rest, const)`.
+fn reset_mux(
+    ctx: &Context,
+    next: ExprRef,
+    is_input: &impl Fn(ExprRef) -> bool,
+) -> Option<(ExprRef, bool, ExprRef, ExprRef)> {
+    let Expr::BVIte { cond, tru, fals } = ctx[next] else {
+        return None;
+    };
+    let (input, inverted) = match ctx[cond] {
+        Expr::BVNot(e, 1) => (e, true),
+        _ => (cond, false),
+    };
+    if !is_input(input) {
+        return None;
+    }
+    if ctx[tru].is_bv_lit() {
+        Some((input, !inverted, tru, fals))
+    } else if ctx[fals].is_bv_lit() {
+        Some((input, inverted, fals, tru))
+    } else {
+        None
+    }
+}
+
+/// Matches `ite(en, d, state)` and `ite(en_n, state, d)` and returns the enable condition.
+fn enable_mux(ctx: &mut Context, state: ExprRef, next: ExprRef) -> Option<ExprRef> {
+    let Expr::BVIte { cond, tru, fals } = ctx[next] else {
+        return None;
+    };
+    if ctx[cond].is_bv_lit() {
+        None
+    } else if fals == state && tru != state {
+        Some(cond)
+    } else if tru == state && fals != state {
+        Some(ctx.not(cond))
+    } else {
+        None
+    }
+}
+
+/// Looks for `past' = clk` together with `!past & clk` (posedge) or `past & !clk` (negedge).
+fn find_clocks(ctx: &mut Context, sys: &TransitionSystem) -> Vec<ClockCandidate> {
+    let sampled: FxHashMap<ExprRef, ExprRef> = sys
+        .states
+        .iter()
+        .filter_map(|s| {
+            let next = s.next?;
+            (sys.inputs.contains(&next) && next.get_bv_type(ctx) == Some(1))
+                .then_some((next, s.symbol))
+        })
+        .collect();
+    let mut edges = FxHashMap::default();
+    for root in sys.get_all_exprs() {
+        traversal::top_down(ctx, root, |ctx, e| {
+            if let Expr::BVAnd(a, b, 1) = ctx[e] {
+                for (x, y) in [(a, b), (b, a)] {
+                    if let Expr::BVNot(negated, _) = ctx[x] {
+                        if sampled.get(&y) == Some(&negated) {
+                            edges.entry(y).or_insert((negated, true));
+                        } else if sampled.get(&negated) == Some(&y) {
+                            edges.entry(negated).or_insert((y, false));
+                        }
+                    }
+                }
+            }
+            traversal::TraversalCmd::Continue
+        });
+    }
+    let mut clocks: Vec<ClockCandidate> = edges
+        .into_iter()
+        .map(|(input, (past, posedge))| ClockCandidate {
+            input,
+            past,
+            posedge,
+        })
+        .collect();
+    clocks.sort_by_key(|c| c.input);
+    clocks
+}
+
+fn edge(ctx: &mut Context, clock: &ClockCandidate) -> ExprRef {
+    if clock.posedge {
+        ctx.build(|c| c.and(c.not(clock.past), clock.input))
+    } else {
+        ctx.build(|c| c.and(clock.past, c.not(clock.input)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::State;
+
+    #[test]
+    fn test_infer_reset_and_enable() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let rst = ctx.bv_symbol("rst", 1);
+        let rst_n = ctx.bv_symbol("rst_n", 1);
+        let en = ctx.bv_symbol("en", 1);
+        let d = ctx.bv_symbol("d", 8);
+        for input in [rst, rst_n, en, d] {
+            sys.add_input(&ctx, input);
+        }
+        let a = ctx.bv_symbol("a", 8);
+        let a_next = ctx.build(|c| c.ite(rst, c.zero(8), c.ite(en, d, a)));
+        let b = ctx.bv_symbol("b", 8);
+        let b_next = ctx.build(|c| c.ite(rst, c.bit_vec_val(3, 8), c.add(b, c.one(8))));
+        let c = ctx.bv_symbol("c", 8);
+        let c_next = ctx.build(|x| x.ite(rst_n, x.ite(en, d, c), x.zero(8)));
+        for (symbol, next) in [(a, a_next), (b, b_next), (c, c_next)] {
+            sys.add_state(
+                &ctx,
+                State {
+                    symbol,
+                    init: None,
+                    next: Some(next),
+                },
+            );
+        }
+
+        let report = infer_clock_reset(&mut ctx, &sys);
+        assert!(report.clocks.is_empty());
+        assert_eq!(report.resets.len(), 2);
+        assert_eq!(report.resets[0].input, rst);
+        assert!(report.resets[0].active_high);
+        assert_eq!(report.resets[0].states.len(), 2);
+        assert_eq!(report.resets[1].input, rst_n);
+        assert!(!report.resets[1].active_high);
+        assert_eq!(report.reset_value(b), Some(ctx.bit_vec_val(3, 8)));
+        assert_eq!(
+            report.enables,
+            [ClockEnable {
+                enable: en,
+                states: vec![a, c]
+            }]
+        );
+
+        report.annotate(&mut ctx);
+        assert!(ctx.has_attribute(rst, ATTR_RESET));
+        assert!(ctx.has_attribute(en, ATTR_CLOCK_ENABLE));
+        assert!(!ctx.has_attribute(d, ATTR_RESET));
+    }
+
+    #[test]
+    fn test_infer_clock() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let clk = ctx.bv_symbol("clk", 1);
+        let d = ctx.bv_symbol("d", 8);
+        sys.add_input(&ctx, clk);
+        sys.add_input(&ctx, d);
+        // clk2fflogic style flip-flop
+        let past_clk = ctx.bv_symbol("past_clk", 1);
+        let q = ctx.bv_symbol("q", 8);
+        let q_next = ctx.build(|c| c.ite(c.and(c.not(past_clk), clk), d, q));
+        for (symbol, next) in [(past_clk, clk), (q, q_next)] {
+            sys.add_state(
+                &ctx,
+                State {
+                    symbol,
+                    init: None,
+                    next: Some(next),
+                },
+            );
+        }
+        let report = infer_clock_reset(&mut ctx, &sys);
+        assert_eq!(
+            report.clocks,
+            [ClockCandidate {
+                input: clk,
+                past: past_clk,
+                posedge: true
+            }]
+        );
+        assert!(report.enables.is_empty());
+        assert!(report.resets.is_empty());
+    }
+}
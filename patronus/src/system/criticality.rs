@@ -0,0 +1,212 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Criticality
+//! Ranks the expressions of a system by how likely they are to matter for a bug, purely
+//! based on structure. An expression is critical if many other expressions depend on it
+//! (fan-out) and if it is close to a bad state. The distance counts operators between the
+//! expression and the closest bad state, where going from a state to its next state function
+//! counts as one more step. Highly ranked internal signals are good candidates for additional
+//! assertions or for waveform tracing.
+
+use super::TransitionSystem;
+use crate::expr::{Context, Expr, ExprRef, ForEachChild, SerializableIrNode};
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Criticality {
+    pub expr: ExprRef,
+    /// number of expressions and properties that use this expression
+    pub fanout: usize,
+    /// operators between this expression and the closest bad state, `None` if no bad state
+    /// depends on the expression
+    pub distance: Option<u32>,
+    /// `fanout / (1 + distance)`, expressions that no bad state depends on count as one step
+    /// further away than the furthest expression that a bad state depends on
+    pub score: f64,
+    /// name of the signal or the expression itself
+    pub name: String,
+}
+
+/// Expressions sorted by descending score.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CriticalityReport {
+    pub entries: Vec<Criticality>,
+}
+
+impl CriticalityReport {
+    pub fn top(&self, n: usize) -> &[Criticality] {
+        &self.entries[..n.min(self.entries.len())]
+    }
+}
+
+impl Display for CriticalityReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{:>8} {:>7} {:>9}  signal",
+            "score", "fanout", "distance"
+        )?;
+        for entry in self.entries.iter() {
+            let distance = entry
+                .distance
+                .map(|d| d.to_string())
+                .unwrap_or_else(|| "-".to_string());
+            writeln!(
+                f,
+                "{:>8.3} {:>7} {:>9}  {}",
+                entry.score, entry.fanout, distance, entry.name
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Ranks every expression of `sys`, except for literals.
+pub fn rank_criticality(ctx: &Context, sys: &TransitionSystem) -> CriticalityReport {
+    let fanout = count_fanout(ctx, sys);
+    let distance = distance_to_bad_states(ctx, sys);
+    let unreachable = distance.values().max().map_or(0, |&d| d + 1);
+
+    let mut entries: Vec<Criticality> = fanout
+        .into_iter()
+        .filter(|(e, _)| !ctx[*e].is_bv_lit() && !matches!(ctx[*e], Expr::ArrayLiteral { .. }))
+        .map(|(expr, fanout)| {
+            let distance = distance.get(&expr).copied();
+            let score = fanout as f64 / (1 + distance.unwrap_or(unreachable)) as f64;
+            Criticality {
+                expr,
+                fanout,
+                distance,
+                score,
+                name: name(ctx, sys, expr),
+            }
+        })
+        .collect();
+    // expressions are ordered by creation to break ties deterministically
+    entries.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.expr.cmp(&b.expr)));
+    CriticalityReport { entries }
+}
+
+fn name(ctx: &Context, sys: &TransitionSystem, e: ExprRef) -> String {
+    if let Some(name) = ctx.get_symbol_name(e) {
+        name.to_string()
+    } else if let Some(name) = sys.names[e] {
+        ctx[name].clone()
+    } else {
+        e.serialize_to_str(ctx)
+    }
+}
+
+/// Number of uses of every expression reachable from the roots of `sys`. Being a next state
+/// function, an output or a property counts as a use.
+fn count_fanout(ctx: &Context, sys: &TransitionSystem) -> FxHashMap<ExprRef, usize> {
+    let mut fanout: FxHashMap<ExprRef, usize> = FxHashMap::default();
+    let mut todo = vec![];
+    let roots = sys
+        .states
+        .iter()
+        .flat_map(|s| s.next.into_iter().chain(s.init))
+        .chain(sys.get_assert_assume_output_exprs());
+    for root in roots {
+        *fanout.entry(root).or_default() += 1;
+        todo.push(root);
+    }
+    for state in sys.states.iter() {
+        fanout.entry(state.symbol).or_default();
+        todo.push(state.symbol);
+    }
+    let mut visited = FxHashSet::default();
+    while let Some(e) = todo.pop() {
+        if !visited.insert(e) {
+            continue;
+        }
+        ctx[e].for_each_child(|&c| {
+            *fanout.entry(c).or_default() += 1;
+            todo.push(c);
+        });
+    }
+    fanout
+}
+
+/// Breadth first search from all bad states towards the leaves, continuing through the next
+/// state function of every state that is reached.
+fn distance_to_bad_states(ctx: &Context, sys: &TransitionSystem) -> FxHashMap<ExprRef, u32> {
+    let next: FxHashMap<ExprRef, ExprRef> = sys
+        .states
+        .iter()
+        .flat_map(|s| Some((s.symbol, s.next?)))
+        .collect();
+    let mut distance: FxHashMap<ExprRef, u32> = FxHashMap::default();
+    let mut todo = VecDeque::new();
+    for &bad in sys.bad_states.iter() {
+        if distance.insert(bad, 0).is_none() {
+            todo.push_back(bad);
+        }
+    }
+    while let Some(e) = todo.pop_front() {
+        let d = distance[&e] + 1;
+        let mut visit = |c: ExprRef| {
+            if let std::collections::hash_map::Entry::Vacant(entry) = distance.entry(c) {
+                entry.insert(d);
+                todo.push_back(c);
+            }
+        };
+        ctx[e].for_each_child(|&c| visit(c));
+        if let Some(&n) = next.get(&e) {
+            visit(n);
+        }
+    }
+    distance
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::State;
+
+    #[test]
+    fn test_rank_criticality() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let a = ctx.bv_symbol("a", 8);
+        let b = ctx.bv_symbol("b", 8);
+        sys.add_input(&ctx, a);
+        sys.add_input(&ctx, b);
+        let count = ctx.bv_symbol("count", 8);
+        let sum = ctx.build(|c| c.add(a, b));
+        let next = ctx.build(|c| c.add(count, sum));
+        sys.add_state(
+            &ctx,
+            State {
+                symbol: count,
+                init: None,
+                next: Some(next),
+            },
+        );
+        let bad = ctx.build(|c| c.equal(count, c.bit_vec_val(100, 8)));
+        sys.bad_states.push(bad);
+        // used a lot, but no bad state depends on it
+        let unrelated = ctx.build(|c| c.xor(a, b));
+        for ii in 0..3 {
+            let out = ctx.build(|c| c.add(unrelated, c.bit_vec_val(ii, 8)));
+            sys.add_output(&mut ctx, format!("out{ii}").into(), out);
+        }
+
+        let report = rank_criticality(&ctx, &sys);
+        let get = |e: ExprRef| report.entries.iter().find(|c| c.expr == e).unwrap();
+        assert_eq!(get(bad).distance, Some(0));
+        assert_eq!(get(count).distance, Some(1));
+        assert_eq!(get(next).distance, Some(2));
+        assert_eq!(get(sum).distance, Some(3));
+        assert_eq!(get(unrelated).distance, None);
+        assert_eq!(get(unrelated).fanout, 3);
+        let top: Vec<ExprRef> = report.top(2).iter().map(|c| c.expr).collect();
+        assert!(top.contains(&bad) && top.contains(&count));
+        assert!(get(count).score > get(unrelated).score);
+        assert!(report.to_string().contains("count"));
+    }
+}
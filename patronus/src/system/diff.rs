@@ -0,0 +1,409 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # System Diff
+//! Compares two transition systems which may live in different contexts. Inputs, outputs and
+//! states are matched by name, bad states and constraints by name if they have one and by
+//! position otherwise. For every matched pair of expressions, both are traversed in parallel
+//! to find the smallest sub-expressions that differ. Symbols are compared by name and type.
+
+use super::TransitionSystem;
+use crate::expr::*;
+use baa::{ArrayOps, BitVecOps};
+use rustc_hash::FxHashSet;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElementKind {
+    Input,
+    Output,
+    State,
+    Init,
+    Next,
+    BadState,
+    Constraint,
+}
+
+impl ElementKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            ElementKind::Input => "input",
+            ElementKind::Output => "output",
+            ElementKind::State => "state",
+            ElementKind::Init => "init",
+            ElementKind::Next => "next",
+            ElementKind::BadState => "bad",
+            ElementKind::Constraint => "constraint",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// only exists in the second system
+    Added { kind: ElementKind, name: String },
+    /// only exists in the first system
+    Removed { kind: ElementKind, name: String },
+    TypeChanged {
+        kind: ElementKind,
+        name: String,
+        a: Type,
+        b: Type,
+    },
+    ExprChanged {
+        kind: ElementKind,
+        name: String,
+        a: ExprRef,
+        b: ExprRef,
+        /// smallest pairs of sub-expressions that differ
+        differences: Vec<(ExprRef, ExprRef)>,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SystemDiff {
+    pub changes: Vec<Change>,
+}
+
+impl SystemDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Human readable summary with one line per change and one indented line per differing
+    /// sub-expression.
+    pub fn serialize_to_str(&self, ctx_a: &Context, ctx_b: &Context) -> String {
+        let mut out = String::new();
+        for change in self.changes.iter() {
+            match change {
+                Change::Added { kind, name } => {
+                    writeln!(out, "+ {} {name}", kind.as_str()).unwrap();
+                }
+                Change::Removed { kind, name } => {
+                    writeln!(out, "- {} {name}", kind.as_str()).unwrap();
+                }
+                Change::TypeChanged { kind, name, a, b } => {
+                    writeln!(out, "~ {} {name} : {a} -> {b}", kind.as_str()).unwrap();
+                }
+                Change::ExprChanged {
+                    kind,
+                    name,
+                    differences,
+                    ..
+                } => {
+                    writeln!(out, "~ {} {name}", kind.as_str()).unwrap();
+                    for (a, b) in differences.iter() {
+                        let a = a.serialize_to_str(ctx_a);
+                        let b = b.serialize_to_str(ctx_b);
+                        writeln!(out, "    {a} -> {b}").unwrap();
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+pub fn diff(
+    ctx_a: &Context,
+    a: &TransitionSystem,
+    ctx_b: &Context,
+    b: &TransitionSystem,
+) -> SystemDiff {
+    let mut d = Differ {
+        ctx_a,
+        ctx_b,
+        changes: vec![],
+    };
+    d.diff_symbols(
+        ElementKind::Input,
+        &symbol_names(ctx_a, a.inputs.iter().copied()),
+        &symbol_names(ctx_b, b.inputs.iter().copied()),
+    );
+    let states_a = symbol_names(ctx_a, a.states.iter().map(|s| s.symbol));
+    let states_b = symbol_names(ctx_b, b.states.iter().map(|s| s.symbol));
+    d.diff_symbols(ElementKind::State, &states_a, &states_b);
+    for (name, sa) in states_a.iter() {
+        let Some(state_b) = b.get_state_by_name(ctx_b, name) else {
+            continue;
+        };
+        let state_a = a.states.iter().find(|s| s.symbol == *sa).unwrap();
+        d.diff_optional(ElementKind::Init, name, state_a.init, state_b.init);
+        d.diff_optional(ElementKind::Next, name, state_a.next, state_b.next);
+    }
+    let outputs = |ctx: &Context, sys: &TransitionSystem| {
+        sys.outputs
+            .iter()
+            .map(|o| (ctx[o.name].clone(), o.expr))
+            .collect::<Vec<_>>()
+    };
+    d.diff_named(ElementKind::Output, &outputs(ctx_a, a), &outputs(ctx_b, b));
+    d.diff_named(
+        ElementKind::BadState,
+        &named(ctx_a, a, &a.bad_states, "bad"),
+        &named(ctx_b, b, &b.bad_states, "bad"),
+    );
+    d.diff_named(
+        ElementKind::Constraint,
+        &named(ctx_a, a, &a.constraints, "constraint"),
+        &named(ctx_b, b, &b.constraints, "constraint"),
+    );
+    SystemDiff { changes: d.changes }
+}
+
+fn symbol_names(ctx: &Context, symbols: impl Iterator<Item = ExprRef>) -> Vec<(String, ExprRef)> {
+    symbols
+        .map(|s| (ctx.get_symbol_name(s).unwrap().to_string(), s))
+        .collect()
+}
+
+/// Uses the signal name if there is one and the position otherwise.
+fn named(
+    ctx: &Context,
+    sys: &TransitionSystem,
+    exprs: &[ExprRef],
+    prefix: &str,
+) -> Vec<(String, ExprRef)> {
+    exprs
+        .iter()
+        .enumerate()
+        .map(|(ii, &e)| {
+            let name = sys.names[e]
+                .map(|n| ctx[n].clone())
+                .unwrap_or_else(|| format!("{prefix}{ii}"));
+            (name, e)
+        })
+        .collect()
+}
+
+struct Differ<'a> {
+    ctx_a: &'a Context,
+    ctx_b: &'a Context,
+    changes: Vec<Change>,
+}
+
+impl<'a> Differ<'a> {
+    fn added_and_removed(
+        &mut self,
+        kind: ElementKind,
+        a: &[(String, ExprRef)],
+        b: &[(String, ExprRef)],
+    ) {
+        for (name, _) in a.iter().filter(|(n, _)| !b.iter().any(|(m, _)| m == n)) {
+            self.changes.push(Change::Removed {
+                kind,
+                name: name.clone(),
+            });
+        }
+        for (name, _) in b.iter().filter(|(n, _)| !a.iter().any(|(m, _)| m == n)) {
+            self.changes.push(Change::Added {
+                kind,
+                name: name.clone(),
+            });
+        }
+    }
+
+    fn diff_symbols(
+        &mut self,
+        kind: ElementKind,
+        a: &[(String, ExprRef)],
+        b: &[(String, ExprRef)],
+    ) {
+        self.added_and_removed(kind, a, b);
+        for (name, sa) in a.iter() {
+            let Some((_, sb)) = b.iter().find(|(n, _)| n == name) else {
+                continue;
+            };
+            let (ta, tb) = (sa.get_type(self.ctx_a), sb.get_type(self.ctx_b));
+            if ta != tb {
+                self.changes.push(Change::TypeChanged {
+                    kind,
+                    name: name.clone(),
+                    a: ta,
+                    b: tb,
+                });
+            }
+        }
+    }
+
+    fn diff_named(&mut self, kind: ElementKind, a: &[(String, ExprRef)], b: &[(String, ExprRef)]) {
+        self.added_and_removed(kind, a, b);
+        for (name, ea) in a.iter() {
+            if let Some((_, eb)) = b.iter().find(|(n, _)| n == name) {
+                self.diff_exprs(kind, name, *ea, *eb);
+            }
+        }
+    }
+
+    fn diff_optional(
+        &mut self,
+        kind: ElementKind,
+        name: &str,
+        a: Option<ExprRef>,
+        b: Option<ExprRef>,
+    ) {
+        let name = name.to_string();
+        match (a, b) {
+            (Some(a), Some(b)) => self.diff_exprs(kind, &name, a, b),
+            (Some(_), None) => self.changes.push(Change::Removed { kind, name }),
+            (None, Some(_)) => self.changes.push(Change::Added { kind, name }),
+            (None, None) => {}
+        }
+    }
+
+    fn diff_exprs(&mut self, kind: ElementKind, name: &str, a: ExprRef, b: ExprRef) {
+        let differences = expr_differences(self.ctx_a, a, self.ctx_b, b);
+        if !differences.is_empty() {
+            self.changes.push(Change::ExprChanged {
+                kind,
+                name: name.to_string(),
+                a,
+                b,
+                differences,
+            });
+        }
+    }
+}
+
+/// Traverses both expressions in parallel and returns the outermost pairs of sub-expressions
+/// that differ.
+pub fn expr_differences(
+    ctx_a: &Context,
+    a: ExprRef,
+    ctx_b: &Context,
+    b: ExprRef,
+) -> Vec<(ExprRef, ExprRef)> {
+    let mut out = vec![];
+    let mut visited = FxHashSet::default();
+    let mut todo = vec![(a, b)];
+    while let Some((a, b)) = todo.pop() {
+        if !visited.insert((a, b)) {
+            continue;
+        }
+        match same_node(ctx_a, a, ctx_b, b) {
+            Some(children) => {
+                // visit children from left to right
+                todo.extend(children.into_iter().rev());
+            }
+            None => out.push((a, b)),
+        }
+    }
+    out
+}
+
+/// Returns the pairs of children if both nodes are the same operation or equal leaves.
+fn same_node(
+    ctx_a: &Context,
+    a: ExprRef,
+    ctx_b: &Context,
+    b: ExprRef,
+) -> Option<Vec<(ExprRef, ExprRef)>> {
+    let (na, nb) = (&ctx_a[a], &ctx_b[b]);
+    let equal = match (na, nb) {
+        (Expr::BVSymbol { name: x, .. }, Expr::BVSymbol { name: y, .. })
+        | (Expr::ArraySymbol { name: x, .. }, Expr::ArraySymbol { name: y, .. })
+        | (Expr::BVFunction { name: x, .. }, Expr::BVFunction { name: y, .. }) => {
+            ctx_a[*x] == ctx_b[*y] && a.get_type(ctx_a) == b.get_type(ctx_b)
+        }
+        (Expr::BVLiteral(x), Expr::BVLiteral(y)) => {
+            x.width() == y.width() && x.get(ctx_a).is_equal(&y.get(ctx_b))
+        }
+        (Expr::ArrayLiteral { value: x, .. }, Expr::ArrayLiteral { value: y, .. }) => {
+            x.get(ctx_a).is_equal(y.get(ctx_b)) == Some(true)
+        }
+        _ if na.num_children() == 0 || na.num_children() != nb.num_children() => false,
+        _ if std::mem::discriminant(na) != std::mem::discriminant(nb) => false,
+        _ => {
+            // compare everything but the children
+            let mut children = Vec::with_capacity(3);
+            na.for_each_child(|c| children.push(*c));
+            expr_with_children(nb, &children) == *na
+        }
+    };
+    if !equal {
+        return None;
+    }
+    let mut children_a = vec![];
+    na.for_each_child(|c| children_a.push(*c));
+    let mut children_b = vec![];
+    nb.for_each_child(|c| children_b.push(*c));
+    Some(children_a.into_iter().zip(children_b).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::State;
+
+    fn counter(ctx: &mut Context, step: u64, with_output: bool) -> TransitionSystem {
+        let mut sys = TransitionSystem::new("counter".to_string());
+        let en = ctx.bv_symbol("en", 1);
+        sys.add_input(ctx, en);
+        let count = ctx.bv_symbol("count", 8);
+        let next = ctx.build(|c| c.ite(en, c.add(count, c.bit_vec_val(step, 8)), count));
+        let init = ctx.zero(8);
+        sys.add_state(
+            ctx,
+            State {
+                symbol: count,
+                init: Some(init),
+                next: Some(next),
+            },
+        );
+        if with_output {
+            let done = ctx.build(|c| c.equal(count, c.bit_vec_val(100, 8)));
+            sys.add_output(ctx, "done".into(), done);
+        }
+        sys
+    }
+
+    #[test]
+    fn test_diff_identical_systems() {
+        let mut ctx_a = Context::default();
+        let a = counter(&mut ctx_a, 1, true);
+        let mut ctx_b = Context::default();
+        // create some unrelated expressions to make sure that references differ
+        ctx_b.bv_symbol("unrelated", 3);
+        let b = counter(&mut ctx_b, 1, true);
+        assert!(diff(&ctx_a, &a, &ctx_b, &b).is_empty());
+    }
+
+    #[test]
+    fn test_diff_changed_systems() {
+        let mut ctx_a = Context::default();
+        let a = counter(&mut ctx_a, 1, true);
+        let mut ctx_b = Context::default();
+        let mut b = counter(&mut ctx_b, 2, false);
+        let valid = ctx_b.bv_symbol("valid", 1);
+        b.add_input(&ctx_b, valid);
+
+        let d = diff(&ctx_a, &a, &ctx_b, &b);
+        assert_eq!(d.changes.len(), 3, "{:?}", d.changes);
+        assert!(d.changes.contains(&Change::Added {
+            kind: ElementKind::Input,
+            name: "valid".to_string()
+        }));
+        assert!(d.changes.contains(&Change::Removed {
+            kind: ElementKind::Output,
+            name: "done".to_string()
+        }));
+        let Some(Change::ExprChanged { differences, .. }) = d.changes.iter().find(|c| {
+            matches!(
+                c,
+                Change::ExprChanged {
+                    kind: ElementKind::Next,
+                    ..
+                }
+            )
+        }) else {
+            panic!("next state change is missing");
+        };
+        assert_eq!(differences.len(), 1);
+        let text = d.serialize_to_str(&ctx_a, &ctx_b);
+        assert!(
+            text.contains("~ next count\n    8'b00000001 -> 8'b00000010\n"),
+            "{text}"
+        );
+    }
+}
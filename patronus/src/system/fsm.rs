@@ -0,0 +1,254 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Finite State Machine Extraction
+//! Finds narrow state registers whose next state function is a mux tree over constants that is
+//! controlled by the current state, and extracts their state transition graph.
+
+use super::TransitionSystem;
+use crate::expr::*;
+use baa::{BitVecOps, BitVecValue};
+use rustc_hash::FxHashSet;
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+/// Wider registers are not considered to be FSM state registers.
+pub const MAX_FSM_WIDTH: WidthInt = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transition {
+    pub from: u64,
+    pub to: u64,
+    /// condition under which the transition is taken, may refer to inputs and other states
+    pub guard: ExprRef,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fsm {
+    pub state: ExprRef,
+    pub init: u64,
+    /// all encodings reachable from `init`, in the order in which they were discovered
+    pub states: Vec<u64>,
+    pub transitions: Vec<Transition>,
+}
+
+impl Fsm {
+    /// Name of the variant if the state register has an enum type, the encoding otherwise.
+    pub fn state_name(&self, ctx: &Context, encoding: u64) -> String {
+        let width = self.state.get_bv_type(ctx).unwrap();
+        let value = BitVecValue::from_u64(encoding, width);
+        ctx.enum_type(self.state)
+            .and_then(|t| t.variant_name(&value))
+            .map_or_else(|| encoding.to_string(), |n| n.to_string())
+    }
+
+    /// Renders the state transition graph in the graphviz dot format.
+    pub fn to_dot(&self, ctx: &Context) -> String {
+        let name = ctx.get_symbol_name(self.state).unwrap();
+        let mut out = String::new();
+        writeln!(out, "digraph \"{name}\" {{").unwrap();
+        writeln!(out, "  init [shape=point];").unwrap();
+        for &s in self.states.iter() {
+            let label = self.state_name(ctx, s);
+            writeln!(out, "  s{s} [label=\"{label}\", shape=circle];").unwrap();
+        }
+        writeln!(out, "  init -> s{};", self.init).unwrap();
+        for t in self.transitions.iter() {
+            let guard = t.guard.serialize_to_str(ctx).replace('"', "\\\"");
+            writeln!(out, "  s{} -> s{} [label=\"{guard}\"];", t.from, t.to).unwrap();
+        }
+        writeln!(out, "}}").unwrap();
+        out
+    }
+}
+
+/// Extracts all state machines from `sys`.
+pub fn find_fsms(ctx: &mut Context, sys: &TransitionSystem) -> Vec<Fsm> {
+    sys.states
+        .iter()
+        .flat_map(|s| extract_fsm(ctx, s.symbol, s.init?, s.next?))
+        .collect()
+}
+
+fn extract_fsm(ctx: &mut Context, state: ExprRef, init: ExprRef, next: ExprRef) -> Option<Fsm> {
+    let width = state.get_bv_type(ctx)?;
+    if width > MAX_FSM_WIDTH || !is_fsm_next(ctx, state, next) {
+        return None;
+    }
+    let init = get_u64(ctx, init)?;
+
+    let mut states = vec![init];
+    let mut transitions = vec![];
+    let mut todo = VecDeque::from([init]);
+    while let Some(from) = todo.pop_front() {
+        let value = ctx.bit_vec_val(from, width);
+        let next = simple_transform_expr(ctx, next, |_, e, _| (e == state).then_some(value));
+        let next = simplify_single_expression(ctx, next);
+        for (to, guard) in guarded_leaves(ctx, next)? {
+            let to = get_u64(ctx, to)?;
+            if !states.contains(&to) {
+                states.push(to);
+                todo.push_back(to);
+            }
+            transitions.push(Transition { from, to, guard });
+        }
+    }
+    Some(Fsm {
+        state,
+        init,
+        states,
+        transitions,
+    })
+}
+
+/// The next state function needs to be a mux tree over constants and the state itself where at
+/// least one condition depends on the state.
+fn is_fsm_next(ctx: &Context, state: ExprRef, next: ExprRef) -> bool {
+    let mut depends_on_state = false;
+    let mut todo = vec![next];
+    while let Some(e) = todo.pop() {
+        match ctx[e] {
+            Expr::BVIte { cond, tru, fals } => {
+                depends_on_state |= depends_on(ctx, cond, state);
+                todo.push(tru);
+                todo.push(fals);
+            }
+            Expr::BVLiteral(_) => {}
+            _ if e == state => {}
+            _ => return false,
+        }
+    }
+    depends_on_state
+}
+
+fn depends_on(ctx: &Context, e: ExprRef, symbol: ExprRef) -> bool {
+    let mut visited = FxHashSet::default();
+    let mut todo = vec![e];
+    while let Some(e) = todo.pop() {
+        if e == symbol {
+            return true;
+        }
+        if visited.insert(e) {
+            ctx[e].for_each_child(|&c| todo.push(c));
+        }
+    }
+    false
+}
+
+/// Returns all leaves of a mux tree together with the (simplified) condition to reach them.
+/// Conditions that lead to the same leaf are merged.
+fn guarded_leaves(ctx: &mut Context, e: ExprRef) -> Option<Vec<(ExprRef, ExprRef)>> {
+    let mut out: Vec<(ExprRef, ExprRef)> = vec![];
+    let tru = ctx.get_true();
+    let mut todo = vec![(e, tru)];
+    while let Some((e, guard)) = todo.pop() {
+        match ctx[e].clone() {
+            Expr::BVIte { cond, tru, fals } => {
+                let not_cond = ctx.not(cond);
+                let fals_guard = ctx.and(guard, not_cond);
+                let tru_guard = ctx.and(guard, cond);
+                todo.push((fals, simplify_single_expression(ctx, fals_guard)));
+                todo.push((tru, simplify_single_expression(ctx, tru_guard)));
+            }
+            Expr::BVLiteral(_) => match out.iter_mut().find(|(leaf, _)| *leaf == e) {
+                Some((_, existing)) => {
+                    let merged = ctx.or(*existing, guard);
+                    *existing = simplify_single_expression(ctx, merged);
+                }
+                None => out.push((e, guard)),
+            },
+            _ => return None,
+        }
+    }
+    Some(out)
+}
+
+fn get_u64(ctx: &Context, e: ExprRef) -> Option<u64> {
+    match ctx[e] {
+        Expr::BVLiteral(value) => value.get(ctx).to_u64(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::State;
+
+    #[test]
+    fn test_extract_fsm() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let start = ctx.bv_symbol("start", 1);
+        let done = ctx.bv_symbol("done", 1);
+        sys.add_input(&ctx, start);
+        sys.add_input(&ctx, done);
+        // idle (0) -> busy (1) -> finished (2) -> idle (0)
+        let fsm = ctx.bv_symbol("fsm", 2);
+        let next = ctx.build(|c| {
+            c.ite(
+                c.equal(fsm, c.bit_vec_val(0, 2)),
+                c.ite(start, c.bit_vec_val(1, 2), fsm),
+                c.ite(
+                    c.equal(fsm, c.bit_vec_val(1, 2)),
+                    c.ite(done, c.bit_vec_val(2, 2), fsm),
+                    c.bit_vec_val(0, 2),
+                ),
+            )
+        });
+        let init = ctx.bit_vec_val(0, 2);
+        sys.add_state(
+            &ctx,
+            State {
+                symbol: fsm,
+                init: Some(init),
+                next: Some(next),
+            },
+        );
+        // a counter is not an fsm
+        let count = ctx.bv_symbol("count", 2);
+        let count_next = ctx.build(|c| c.add(count, c.one(2)));
+        sys.add_state(
+            &ctx,
+            State {
+                symbol: count,
+                init: Some(init),
+                next: Some(count_next),
+            },
+        );
+
+        let fsms = find_fsms(&mut ctx, &sys);
+        assert_eq!(fsms.len(), 1);
+        let fsm = &fsms[0];
+        assert_eq!(fsm.states, [0, 1, 2]);
+        let edges = fsm
+            .transitions
+            .iter()
+            .map(|t| (t.from, t.to, t.guard.serialize_to_str(&ctx)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            edges,
+            [
+                (0, 1, "start".to_string()),
+                (0, 0, "not(start)".to_string()),
+                (1, 2, "done".to_string()),
+                (1, 1, "not(done)".to_string()),
+                (2, 0, "1'b1".to_string()),
+            ]
+        );
+        let dot = fsm.to_dot(&ctx);
+        assert!(dot.contains("init -> s0;"));
+        assert!(dot.contains("s1 -> s2 [label=\"done\"];"));
+
+        let states = EnumType::binary("State", &["Idle", "Busy", "Finished"]);
+        ctx.set_enum_type(fsm.state, states);
+        assert_eq!(fsm.state_name(&ctx, 2), "Finished");
+        assert!(fsm
+            .to_dot(&ctx)
+            .contains("s1 [label=\"Busy\", shape=circle];"));
+        let serialized = sys.serialize_to_str(&ctx);
+        assert!(serialized.contains("state fsm : bv<2> ; enum State\n"));
+        assert!(serialized.contains("; Idle\n"), "{serialized}");
+    }
+}
@@ -0,0 +1,174 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Hierarchical Names
+//! Flattened designs encode the instance hierarchy in their signal names, e.g.,
+//! `core.alu.result`. [`NameIndex`] finds signals by their full dotted name or by any suffix
+//! that starts at a hierarchy boundary, like `alu.result`, as long as the suffix is unique.
+
+use crate::expr::{Context, ExprRef};
+use crate::system::TransitionSystem;
+use rustc_hash::FxHashMap;
+
+pub const HIERARCHY_SEPARATOR: char = '.';
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum NameError {
+    #[error("no signal named `{0}`")]
+    NotFound(String),
+    #[error("`{name}` is ambiguous, it could refer to: {}", candidates.join(", "))]
+    Ambiguous {
+        name: String,
+        candidates: Vec<String>,
+    },
+}
+
+/// Maps the names of inputs, states, outputs, defines and named expressions to expressions.
+#[derive(Debug, Clone, Default)]
+pub struct NameIndex {
+    /// full names in sorted order
+    names: Vec<(String, ExprRef)>,
+    by_name: FxHashMap<String, Vec<ExprRef>>,
+}
+
+impl NameIndex {
+    pub fn new(ctx: &Context, sys: &TransitionSystem) -> Self {
+        let mut by_name: FxHashMap<String, Vec<ExprRef>> = FxHashMap::default();
+        let mut add = |name: &str, e: ExprRef| {
+            let exprs = by_name.entry(name.to_string()).or_default();
+            if !exprs.contains(&e) {
+                exprs.push(e);
+            }
+        };
+        for &input in sys.inputs.iter() {
+            add(ctx.get_symbol_name(input).unwrap(), input);
+        }
+        for state in sys.states.iter() {
+            add(ctx.get_symbol_name(state.symbol).unwrap(), state.symbol);
+        }
+        for output in sys.outputs.iter() {
+            add(&ctx[output.name], output.expr);
+        }
+        for define in sys.defines.iter() {
+            add(&ctx[define.name], define.expr);
+        }
+        for e in sys.names.non_default_value_keys() {
+            if let Some(name) = sys.names[e] {
+                add(&ctx[name], e);
+            }
+        }
+        let mut names: Vec<(String, ExprRef)> = by_name
+            .iter()
+            .flat_map(|(n, exprs)| exprs.iter().map(|&e| (n.clone(), e)))
+            .collect();
+        names.sort();
+        Self { names, by_name }
+    }
+
+    /// Resolves a full name or a unique suffix of one. A full name always takes precedence
+    /// over suffix matches.
+    pub fn resolve(&self, name: &str) -> Result<ExprRef, NameError> {
+        let matches: Vec<&(String, ExprRef)> = match self.by_name.get(name) {
+            Some(exprs) if exprs.len() == 1 => return Ok(exprs[0]),
+            Some(_) => self.names.iter().filter(|(n, _)| n == name).collect(),
+            None => self
+                .names
+                .iter()
+                .filter(|(n, _)| is_hierarchical_suffix(n, name))
+                .collect(),
+        };
+        match matches.as_slice() {
+            [] => Err(NameError::NotFound(name.to_string())),
+            [(_, e)] => Ok(*e),
+            _ if matches.iter().all(|(_, e)| *e == matches[0].1) => Ok(matches[0].1),
+            _ => Err(NameError::Ambiguous {
+                name: name.to_string(),
+                candidates: matches.iter().map(|(n, _)| n.clone()).collect(),
+            }),
+        }
+    }
+
+    /// Names of the signals and sub-instances directly inside `scope`.
+    /// The empty scope refers to the top level.
+    pub fn children(&self, scope: &str) -> Vec<&str> {
+        let mut out: Vec<&str> = self
+            .names
+            .iter()
+            .flat_map(|(n, _)| {
+                let rest = if scope.is_empty() {
+                    n.as_str()
+                } else {
+                    n.strip_prefix(scope)?.strip_prefix(HIERARCHY_SEPARATOR)?
+                };
+                rest.split(HIERARCHY_SEPARATOR).next()
+            })
+            .collect();
+        // names are sorted, thus duplicates are always next to each other
+        out.dedup();
+        out
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+fn is_hierarchical_suffix(full: &str, suffix: &str) -> bool {
+    full.strip_suffix(suffix)
+        .is_some_and(|prefix| prefix.ends_with(HIERARCHY_SEPARATOR))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::State;
+
+    #[test]
+    fn test_resolve_dotted_names() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("top".to_string());
+        let valid = ctx.bv_symbol("core.valid", 1);
+        sys.add_input(&ctx, valid);
+        let add_state = |ctx: &mut Context, sys: &mut TransitionSystem, name: &str| {
+            let symbol = ctx.bv_symbol(name, 8);
+            sys.add_state(
+                ctx,
+                State {
+                    symbol,
+                    init: None,
+                    next: None,
+                },
+            );
+            symbol
+        };
+        let alu = add_state(&mut ctx, &mut sys, "core.alu.result");
+        let fpu = add_state(&mut ctx, &mut sys, "core.fpu.result");
+        let sum = ctx.build(|c| c.add(alu, fpu));
+        sys.add_output(&mut ctx, "core.sum".into(), sum);
+
+        let index = NameIndex::new(&ctx, &sys);
+        assert_eq!(index.resolve("core.alu.result"), Ok(alu));
+        assert_eq!(index.resolve("alu.result"), Ok(alu));
+        assert_eq!(index.resolve("sum"), Ok(sum));
+        assert_eq!(index.resolve("valid"), Ok(valid));
+        // `lu.result` does not start at a hierarchy boundary
+        assert_eq!(
+            index.resolve("lu.result"),
+            Err(NameError::NotFound("lu.result".to_string()))
+        );
+        assert_eq!(
+            index.resolve("result"),
+            Err(NameError::Ambiguous {
+                name: "result".to_string(),
+                candidates: vec!["core.alu.result".to_string(), "core.fpu.result".to_string()]
+            })
+        );
+        assert_eq!(index.children(""), ["core"]);
+        assert_eq!(index.children("core"), ["alu", "fpu", "sum", "valid"]);
+    }
+}
@@ -0,0 +1,318 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Idiom Detection
+//! Structural analysis that recognizes common hardware idioms like counters, one-hot
+//! registers and FIFO pointers. The results are meant as hints for downstream engines,
+//! e.g., the one-hot invariant can be used to strengthen k-induction.
+
+use super::TransitionSystem;
+use crate::expr::*;
+use baa::BitVecOps;
+use rustc_hash::FxHashSet;
+
+/// A state that is incremented and/or decremented by a constant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Counter {
+    pub state: ExprRef,
+    /// literal that is added to the counter
+    pub up: Option<ExprRef>,
+    /// literal that is subtracted from the counter
+    pub down: Option<ExprRef>,
+}
+
+impl Counter {
+    pub fn is_up_down(&self) -> bool {
+        self.up.is_some() && self.down.is_some()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Idiom {
+    Counter(Counter),
+    /// A state that always holds a one-hot value, e.g., the state register of a one-hot FSM.
+    OneHot {
+        state: ExprRef,
+    },
+    /// Two counters that are compared with each other, like the read and write pointer of a
+    /// FIFO. The analysis cannot tell which pointer is which.
+    FifoPointers {
+        a: ExprRef,
+        b: ExprRef,
+    },
+}
+
+impl Idiom {
+    /// Returns an invariant that holds in all reachable states, if the idiom implies one.
+    pub fn invariant(&self, ctx: &mut Context) -> Option<ExprRef> {
+        match *self {
+            Idiom::OneHot { state } => {
+                let width = state.get_bv_type(ctx).unwrap();
+                // s != 0 && (s & (s - 1)) == 0
+                Some(ctx.build(|c| {
+                    c.and(
+                        c.not(c.equal(state, c.zero(width))),
+                        c.equal(c.and(state, c.sub(state, c.one(width))), c.zero(width)),
+                    )
+                }))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Idioms found in a transition system.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Annotations {
+    pub idioms: Vec<Idiom>,
+}
+
+impl Annotations {
+    pub fn counters(&self) -> impl Iterator<Item = &Counter> + '_ {
+        self.idioms.iter().flat_map(|i| match i {
+            Idiom::Counter(c) => Some(c),
+            _ => None,
+        })
+    }
+
+    pub fn is_one_hot(&self, state: ExprRef) -> bool {
+        self.idioms
+            .iter()
+            .any(|i| matches!(i, Idiom::OneHot { state: s } if *s == state))
+    }
+
+    /// Invariants implied by all idioms, see [`Idiom::invariant`].
+    pub fn invariants(&self, ctx: &mut Context) -> Vec<ExprRef> {
+        self.idioms.iter().flat_map(|i| i.invariant(ctx)).collect()
+    }
+}
+
+/// Detects counters, one-hot registers and FIFO pointers.
+pub fn find_idioms(ctx: &Context, sys: &TransitionSystem) -> Annotations {
+    let mut idioms = vec![];
+    let mut counters = vec![];
+    for state in sys.states.iter() {
+        let (Some(next), Some(width)) = (state.next, state.symbol.get_bv_type(ctx)) else {
+            continue;
+        };
+        let leaves = ite_leaves(ctx, next);
+        if let Some(counter) = as_counter(ctx, state.symbol, &leaves) {
+            counters.push(counter);
+            idioms.push(Idiom::Counter(counter));
+        } else if width > 1 && is_one_hot(ctx, state.symbol, state.init, width, &leaves) {
+            idioms.push(Idiom::OneHot {
+                state: state.symbol,
+            });
+        }
+    }
+
+    // two increment-by-one counters of the same width that are compared with each other
+    let pointers: FxHashSet<ExprRef> = counters
+        .iter()
+        .filter(|c| c.down.is_none() && c.up.is_some_and(|u| is_one(ctx, u)))
+        .map(|c| c.state)
+        .collect();
+    let mut pairs = vec![];
+    let mut visited = FxHashSet::default();
+    let mut todo = sys.get_all_exprs();
+    while let Some(e) = todo.pop() {
+        if !visited.insert(e) {
+            continue;
+        }
+        if let Expr::BVEqual(a, b) | Expr::BVSub(a, b, _) = ctx[e] {
+            if a != b && pointers.contains(&a) && pointers.contains(&b) {
+                let pair = if a < b { (a, b) } else { (b, a) };
+                if !pairs.contains(&pair) {
+                    pairs.push(pair);
+                }
+            }
+        }
+        ctx[e].for_each_child(|&c| todo.push(c));
+    }
+    pairs.sort();
+    idioms.extend(pairs.into_iter().map(|(a, b)| Idiom::FifoPointers { a, b }));
+
+    Annotations { idioms }
+}
+
+/// Returns all values that the expression might evaluate to, by looking through muxes.
+fn ite_leaves(ctx: &Context, e: ExprRef) -> Vec<ExprRef> {
+    let mut out = vec![];
+    let mut todo = vec![e];
+    while let Some(e) = todo.pop() {
+        match ctx[e] {
+            Expr::BVIte { tru, fals, .. } => {
+                todo.push(fals);
+                todo.push(tru);
+            }
+            _ => {
+                if !out.contains(&e) {
+                    out.push(e);
+                }
+            }
+        }
+    }
+    out
+}
+
+fn as_counter(ctx: &Context, state: ExprRef, leaves: &[ExprRef]) -> Option<Counter> {
+    let mut counter = Counter {
+        state,
+        up: None,
+        down: None,
+    };
+    for &leaf in leaves {
+        // holding the value or resetting to a constant are allowed
+        if leaf == state || ctx[leaf].is_bv_lit() {
+            continue;
+        }
+        let (step, up) = match ctx[leaf] {
+            Expr::BVAdd(a, b, _) if a == state && ctx[b].is_bv_lit() => (b, true),
+            Expr::BVAdd(a, b, _) if b == state && ctx[a].is_bv_lit() => (a, true),
+            Expr::BVSub(a, b, _) if a == state && ctx[b].is_bv_lit() => (b, false),
+            _ => return None,
+        };
+        let slot = if up {
+            &mut counter.up
+        } else {
+            &mut counter.down
+        };
+        // we only support a single step size in each direction
+        if slot.is_some_and(|s| s != step) {
+            return None;
+        }
+        *slot = Some(step);
+    }
+    if counter.up.is_some() || counter.down.is_some() {
+        Some(counter)
+    } else {
+        None
+    }
+}
+
+fn is_one_hot(
+    ctx: &Context,
+    state: ExprRef,
+    init: Option<ExprRef>,
+    width: WidthInt,
+    leaves: &[ExprRef],
+) -> bool {
+    let one_hot_lit = |e: ExprRef| match ctx[e] {
+        Expr::BVLiteral(value) => value.get(ctx).is_pow_2().is_some(),
+        _ => false,
+    };
+    init.is_some_and(one_hot_lit)
+        && leaves
+            .iter()
+            .all(|&l| l == state || one_hot_lit(l) || is_rotation(ctx, l, state, width))
+}
+
+/// Rotation by one bit in either direction.
+fn is_rotation(ctx: &Context, e: ExprRef, state: ExprRef, width: WidthInt) -> bool {
+    let slice = |e: ExprRef| match ctx[e] {
+        Expr::BVSlice { e, hi, lo } if e == state => Some((hi, lo)),
+        _ => None,
+    };
+    let Expr::BVConcat(a, b, _) = ctx[e] else {
+        return false;
+    };
+    match (slice(a), slice(b)) {
+        // rotate left: s[w-2:0] ++ s[w-1]
+        (Some((hi_a, 0)), Some((hi_b, lo_b))) if hi_a == width - 2 && lo_b == width - 1 => {
+            hi_b == width - 1
+        }
+        // rotate right: s[0] ++ s[w-1:1]
+        (Some((0, 0)), Some((hi_b, 1))) => hi_b == width - 1,
+        _ => false,
+    }
+}
+
+fn is_one(ctx: &Context, e: ExprRef) -> bool {
+    match ctx[e] {
+        Expr::BVLiteral(value) => value.get(ctx).is_one(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::State;
+
+    fn add_state(ctx: &mut Context, sys: &mut TransitionSystem, name: &str, init: u64) -> ExprRef {
+        let symbol = ctx.bv_symbol(name, 4);
+        let init = ctx.bit_vec_val(init, 4);
+        sys.add_state(
+            ctx,
+            State {
+                symbol,
+                init: Some(init),
+                next: None,
+            },
+        );
+        symbol
+    }
+
+    fn set_next(sys: &mut TransitionSystem, state: ExprRef, next: ExprRef) {
+        sys.states
+            .iter_mut()
+            .find(|s| s.symbol == state)
+            .unwrap()
+            .next = Some(next);
+    }
+
+    #[test]
+    fn test_find_idioms() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let push = ctx.bv_symbol("push", 1);
+        let pop = ctx.bv_symbol("pop", 1);
+        sys.add_input(&ctx, push);
+        sys.add_input(&ctx, pop);
+        let wr = add_state(&mut ctx, &mut sys, "wr", 0);
+        let rd = add_state(&mut ctx, &mut sys, "rd", 0);
+        let count = add_state(&mut ctx, &mut sys, "count", 0);
+        let fsm = add_state(&mut ctx, &mut sys, "fsm", 1);
+        let other = add_state(&mut ctx, &mut sys, "other", 0);
+
+        let wr_next = ctx.build(|c| c.ite(push, c.add(wr, c.one(4)), wr));
+        let rd_next = ctx.build(|c| c.ite(pop, c.add(c.one(4), rd), rd));
+        let count_next = ctx.build(|c| {
+            c.ite(
+                push,
+                c.add(count, c.one(4)),
+                c.ite(pop, c.sub(count, c.one(4)), count),
+            )
+        });
+        let fsm_next = ctx.build(|c| {
+            c.ite(
+                push,
+                c.concat(c.slice(fsm, 2, 0), c.slice(fsm, 3, 3)),
+                c.ite(pop, c.bit_vec_val(8, 4), fsm),
+            )
+        });
+        let other_next = ctx.build(|c| c.add(other, rd));
+        set_next(&mut sys, wr, wr_next);
+        set_next(&mut sys, rd, rd_next);
+        set_next(&mut sys, count, count_next);
+        set_next(&mut sys, fsm, fsm_next);
+        set_next(&mut sys, other, other_next);
+        let empty = ctx.equal(rd, wr);
+        sys.add_output(&mut ctx, "empty".into(), empty);
+
+        let annotations = find_idioms(&ctx, &sys);
+        let counters = annotations.counters().map(|c| c.state).collect::<Vec<_>>();
+        assert_eq!(counters, [wr, rd, count]);
+        assert!(annotations
+            .counters()
+            .find(|c| c.state == count)
+            .unwrap()
+            .is_up_down());
+        assert!(annotations.is_one_hot(fsm));
+        assert!(!annotations.is_one_hot(other));
+        let (a, b) = if wr < rd { (wr, rd) } else { (rd, wr) };
+        assert!(annotations.idioms.contains(&Idiom::FifoPointers { a, b }));
+        assert_eq!(annotations.invariants(&mut ctx).len(), 1);
+    }
+}
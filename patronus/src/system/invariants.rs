@@ -0,0 +1,212 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Invariant Based Simplification
+//! An invariant is a 1-bit expression over the states of a system that is true in every
+//! reachable step, e.g., a proven [`Candidate`](crate::mc::Candidate) or a property that the user
+//! knows to hold. Under the invariants, some mux conditions can never be true or never be false
+//! and the unreachable branch can be removed. A branch is only removed after a solver proved
+//! that the invariants imply the value of its condition. The invariants themselves are not
+//! checked: the result is only equivalent to the original system if they actually hold.
+
+use super::transform::do_transform;
+use super::TransitionSystem;
+use crate::equiv::{prove_equiv, EquivError, EquivOptions, EquivResult};
+use crate::expr::traversal::{top_down, TraversalCmd};
+use crate::expr::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+/// A mux whose condition always has the same value under the invariants.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrunedBranch {
+    /// the original mux
+    pub ite: ExprRef,
+    /// value of the condition in all reachable steps
+    pub cond: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InvariantReport {
+    pub pruned: Vec<PrunedBranch>,
+    /// number of conditions that were sent to the solver
+    pub queries: usize,
+}
+
+/// Removes mux branches that are unreachable if all `invariants` hold.
+pub fn simplify_with_invariants(
+    ctx: &mut Context,
+    sys: &mut TransitionSystem,
+    invariants: &[ExprRef],
+    opts: &EquivOptions,
+) -> Result<InvariantReport, EquivError> {
+    let mut report = InvariantReport::default();
+    if invariants.is_empty() {
+        return Ok(report);
+    }
+    for &inv in invariants.iter() {
+        debug_assert_eq!(inv.get_bv_type(ctx), Some(1), "invariants need to be 1-bit");
+    }
+    let assumption = invariants[1..]
+        .iter()
+        .fold(invariants[0], |acc, &inv| ctx.and(acc, inv));
+    let inv_symbols = symbols(ctx, &[assumption]);
+
+    // conditions can be shared between many muxes
+    let mut known: FxHashMap<ExprRef, Option<bool>> = FxHashMap::default();
+    let mut error = None;
+    do_transform(
+        ctx,
+        sys,
+        ExprTransformMode::SingleStep,
+        |ctx, expr, children| {
+            if error.is_some() || !matches!(ctx[expr], Expr::BVIte { .. }) {
+                return None;
+            }
+            let (cond, tru, fals) = (children[0], children[1], children[2]);
+            if ctx[cond].is_bv_lit() {
+                return None;
+            }
+            let value = match known.get(&cond) {
+                Some(value) => *value,
+                None => {
+                    // conditions that do not share a symbol with the invariants cannot be decided
+                    let value = if symbols(ctx, &[cond]).is_disjoint(&inv_symbols) {
+                        None
+                    } else {
+                        report.queries += 1;
+                        match implied_value(ctx, assumption, cond, opts) {
+                            Ok(value) => value,
+                            Err(e) => {
+                                error = Some(e);
+                                return None;
+                            }
+                        }
+                    };
+                    known.insert(cond, value);
+                    value
+                }
+            };
+            let value = value?;
+            report.pruned.push(PrunedBranch {
+                ite: expr,
+                cond: value,
+            });
+            Some(if value { tru } else { fals })
+        },
+    );
+    match error {
+        Some(e) => Err(e),
+        None => Ok(report),
+    }
+}
+
+/// Returns the value of `cond` if it is the same in every assignment that satisfies `assumption`.
+fn implied_value(
+    ctx: &mut Context,
+    assumption: ExprRef,
+    cond: ExprRef,
+    opts: &EquivOptions,
+) -> Result<Option<bool>, EquivError> {
+    let tru = ctx.one(1);
+    for value in [true, false] {
+        let goal = if value { cond } else { ctx.not(cond) };
+        let implication = ctx.implies(assumption, goal);
+        if prove_equiv(ctx, implication, tru, opts)? == EquivResult::Equivalent {
+            return Ok(Some(value));
+        }
+    }
+    Ok(None)
+}
+
+fn symbols(ctx: &Context, roots: &[ExprRef]) -> FxHashSet<ExprRef> {
+    let mut out = FxHashSet::default();
+    for &root in roots.iter() {
+        top_down(ctx, root, |ctx, e| {
+            if ctx[e].is_symbol() {
+                out.insert(e);
+            }
+            TraversalCmd::Continue
+        });
+    }
+    out
+}
+
+#[cfg(all(test, feature = "sat"))]
+mod tests {
+    use super::*;
+    use crate::equiv::EquivBackend;
+    use crate::system::State;
+
+    fn sat() -> EquivOptions {
+        EquivOptions {
+            backend: EquivBackend::Sat,
+            bdd: None,
+        }
+    }
+
+    #[test]
+    fn test_prune_unreachable_branch() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let en = ctx.bv_symbol("en", 1);
+        let data = ctx.bv_symbol("data", 8);
+        sys.add_input(&ctx, en);
+        sys.add_input(&ctx, data);
+        // counts up to 9 and wraps around
+        let count = ctx.bv_symbol("count", 4);
+        let count_next = ctx.build(|c| {
+            c.ite(
+                c.equal(count, c.bit_vec_val(9, 4)),
+                c.zero(4),
+                c.add(count, c.one(4)),
+            )
+        });
+        let init = ctx.zero(4);
+        sys.add_state(
+            &ctx,
+            State {
+                symbol: count,
+                init: Some(init),
+                next: Some(count_next),
+            },
+        );
+        let overflow = ctx.build(|c| c.greater(count, c.bit_vec_val(9, 4)));
+        let out = ctx.build(|c| c.ite(overflow, c.zero(8), data));
+        let gated = ctx.build(|c| c.ite(en, data, c.zero(8)));
+        sys.add_output(&mut ctx, "out".into(), out);
+        sys.add_output(&mut ctx, "gated".into(), gated);
+
+        let invariant = ctx.build(|c| c.not(c.greater(count, c.bit_vec_val(9, 4))));
+        let report = simplify_with_invariants(&mut ctx, &mut sys, &[invariant], &sat()).unwrap();
+        assert_eq!(
+            report.pruned,
+            [PrunedBranch {
+                ite: out,
+                cond: false
+            }]
+        );
+        assert_eq!(sys.outputs[0].expr, data);
+        // `en` does not appear in the invariant
+        assert_eq!(sys.outputs[1].expr, gated);
+        assert_eq!(sys.states[0].next, Some(count_next));
+    }
+
+    #[test]
+    fn test_keep_undecided_condition() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let a = ctx.bv_symbol("a", 8);
+        let b = ctx.bv_symbol("b", 8);
+        sys.add_input(&ctx, a);
+        sys.add_input(&ctx, b);
+        let out = ctx.build(|c| c.ite(c.equal(a, b), a, b));
+        sys.add_output(&mut ctx, "out".into(), out);
+        // `a` is at most 16 which does not imply anything about `a == b`
+        let invariant = ctx.build(|c| c.greater_or_equal(c.bit_vec_val(16, 8), a));
+        let report = simplify_with_invariants(&mut ctx, &mut sys, &[invariant], &sat()).unwrap();
+        assert!(report.pruned.is_empty());
+        assert_eq!(report.queries, 1);
+        assert_eq!(sys.outputs[0].expr, out);
+    }
+}
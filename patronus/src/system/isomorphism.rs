@@ -0,0 +1,212 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Structural Isomorphism
+//! Finds subgraphs that appear several times with the same structure, but different symbols,
+//! e.g., the datapath of every lane of a SIMD unit. Expressions are already hash-consed, thus
+//! only the symbols can differ between two instances. A property that was verified for one
+//! instance can be replicated for all others with the node mapping between them.
+//!
+//! Only maximal subgraphs are reported: a group is left out if all of its instances are
+//! part of instances of a larger group that was already reported.
+
+use super::TransitionSystem;
+use crate::expr::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+/// Subgraphs with more nodes are not considered, which bounds the cost of the analysis.
+pub const MAX_SUBGRAPH_NODES: usize = 256;
+
+/// A node of a subgraph without any reference to the symbols it uses.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Shape {
+    Symbol(Type),
+    /// children refer to the position of the node in the shape
+    Node(Expr),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubgraphInstance {
+    pub root: ExprRef,
+    /// All nodes of the subgraph in post order. Corresponding nodes of instances in the same
+    /// group are at the same position.
+    pub nodes: Vec<ExprRef>,
+}
+
+impl SubgraphInstance {
+    /// The symbols that the subgraph depends on, in the same order for all instances.
+    pub fn symbols<'a>(&'a self, ctx: &'a Context) -> impl Iterator<Item = ExprRef> + 'a {
+        self.nodes.iter().copied().filter(|&n| ctx[n].is_symbol())
+    }
+}
+
+/// Subgraphs that are isomorphic, i.e., identical up to a renaming of their symbols.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IsomorphicSubgraphs {
+    /// number of operations in every instance, symbols and literals are not counted
+    pub ops: usize,
+    pub instances: Vec<SubgraphInstance>,
+}
+
+impl IsomorphicSubgraphs {
+    /// Maps every node of instance `from` to the corresponding node of instance `to`.
+    pub fn mapping(&self, from: usize, to: usize) -> FxHashMap<ExprRef, ExprRef> {
+        let from = &self.instances[from].nodes;
+        let to = &self.instances[to].nodes;
+        from.iter().copied().zip(to.iter().copied()).collect()
+    }
+}
+
+/// Finds all maximal groups of at least two isomorphic subgraphs with at least `min_ops`
+/// operations. Larger subgraphs come first.
+pub fn find_isomorphic_subgraphs(
+    ctx: &Context,
+    sys: &TransitionSystem,
+    min_ops: usize,
+) -> Vec<IsomorphicSubgraphs> {
+    let mut candidates = vec![];
+    let mut visited = FxHashSet::default();
+    let mut todo = sys.get_all_exprs();
+    while let Some(e) = todo.pop() {
+        if !visited.insert(e) {
+            continue;
+        }
+        if ctx[e].num_children() > 0 {
+            candidates.push(e);
+        }
+        ctx[e].for_each_child(|&c| todo.push(c));
+    }
+    candidates.sort();
+
+    let mut groups: FxHashMap<Vec<Shape>, Vec<SubgraphInstance>> = FxHashMap::default();
+    for root in candidates {
+        if let Some((shape, nodes)) = shape_of(ctx, root) {
+            groups
+                .entry(shape)
+                .or_default()
+                .push(SubgraphInstance { root, nodes });
+        }
+    }
+    let mut groups: Vec<IsomorphicSubgraphs> = groups
+        .into_iter()
+        .filter(|(_, instances)| instances.len() > 1)
+        .map(|(shape, instances)| IsomorphicSubgraphs {
+            ops: shape
+                .iter()
+                .filter(|s| matches!(s, Shape::Node(e) if e.num_children() > 0))
+                .count(),
+            instances,
+        })
+        .filter(|g| g.ops >= min_ops)
+        .collect();
+    groups.sort_by_key(|g| (std::cmp::Reverse(g.ops), g.instances[0].root));
+
+    let mut covered = FxHashSet::default();
+    let mut out = vec![];
+    for group in groups {
+        if group.instances.iter().all(|i| covered.contains(&i.root)) {
+            continue;
+        }
+        for instance in group.instances.iter() {
+            covered.extend(instance.nodes.iter().copied());
+        }
+        out.push(group);
+    }
+    out
+}
+
+/// Returns the shape of the subgraph rooted at `root` and its nodes in post order, or `None`
+/// if it has more than [`MAX_SUBGRAPH_NODES`] nodes.
+fn shape_of(ctx: &Context, root: ExprRef) -> Option<(Vec<Shape>, Vec<ExprRef>)> {
+    let mut ids: FxHashMap<ExprRef, usize> = FxHashMap::default();
+    let mut shape = vec![];
+    let mut nodes = vec![];
+    let mut children = vec![];
+    let mut todo = vec![(root, false)];
+    while let Some((e, expanded)) = todo.pop() {
+        if ids.contains_key(&e) {
+            continue;
+        }
+        let expr = &ctx[e];
+        if !expanded && expr.num_children() > 0 {
+            todo.push((e, true));
+            expr.for_each_child(|&c| {
+                if !ids.contains_key(&c) {
+                    todo.push((c, false));
+                }
+            });
+            continue;
+        }
+        let node = if expr.is_symbol() {
+            Shape::Symbol(e.get_type(ctx))
+        } else if expr.num_children() == 0 {
+            Shape::Node(expr.clone())
+        } else {
+            children.clear();
+            expr.for_each_child(|c| children.push(ExprRef::from_index(ids[c])));
+            Shape::Node(expr_with_children(expr, &children))
+        };
+        ids.insert(e, nodes.len());
+        shape.push(node);
+        nodes.push(e);
+        if nodes.len() > MAX_SUBGRAPH_NODES {
+            return None;
+        }
+    }
+    Some((shape, nodes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::State;
+
+    #[test]
+    fn test_find_isomorphic_subgraphs() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("lanes".to_string());
+        let lane = |ctx: &mut Context, sys: &mut TransitionSystem, name: &str, square: bool| {
+            let a = ctx.bv_symbol(&format!("{name}_a"), 8);
+            let b = ctx.bv_symbol(&format!("{name}_b"), 8);
+            let acc = ctx.bv_symbol(&format!("{name}_acc"), 8);
+            sys.add_input(ctx, a);
+            sys.add_input(ctx, b);
+            // squaring shares the operand and thus has a different structure
+            let b = if square { a } else { b };
+            let next = ctx.build(|c| c.add(acc, c.mul(a, b)));
+            sys.add_state(
+                ctx,
+                State {
+                    symbol: acc,
+                    init: None,
+                    next: Some(next),
+                },
+            );
+            (next, a, acc)
+        };
+        let (next0, a0, acc0) = lane(&mut ctx, &mut sys, "lane0", false);
+        let (next1, a1, acc1) = lane(&mut ctx, &mut sys, "lane1", false);
+        let (next2, _, _) = lane(&mut ctx, &mut sys, "lane2", true);
+
+        let groups = find_isomorphic_subgraphs(&ctx, &sys, 2);
+        assert_eq!(groups.len(), 1);
+        let group = &groups[0];
+        assert_eq!(group.ops, 2);
+        let roots: Vec<_> = group.instances.iter().map(|i| i.root).collect();
+        assert_eq!(roots.len(), 2);
+        assert!(roots.contains(&next0) && roots.contains(&next1));
+        assert!(!roots.contains(&next2));
+        assert_eq!(group.instances[0].symbols(&ctx).count(), 3);
+
+        let (from, to) = if roots[0] == next0 { (0, 1) } else { (1, 0) };
+        let mapping = group.mapping(from, to);
+        assert_eq!(mapping[&next0], next1);
+        assert_eq!(mapping[&a0], a1);
+        assert_eq!(mapping[&acc0], acc1);
+
+        // the multiplications are part of the reported lanes and thus not reported on their own
+        let groups = find_isomorphic_subgraphs(&ctx, &sys, 1);
+        assert_eq!(groups.len(), 1);
+    }
+}
@@ -0,0 +1,296 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Memory Images
+//! Reads memory contents in the format of Verilog's `$readmemh` and `$readmemb`: whitespace
+//! separated words in hex or binary, `@addr` directives (always in hex) that move the write
+//! pointer and `//` as well as `/* */` comments. Words that are not part of the image are zero.
+//!
+//! An image can be turned into an [`ArrayValue`] or into an init expression for an array state.
+//! The init expression is a single array literal, no matter how large the image is.
+
+use super::TransitionSystem;
+use crate::expr::{ArrayType, Context, ExprRef, TypeCheck, WidthInt};
+use baa::{ArrayMutOps, ArrayValue, BitVecOps, BitVecValue};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryImageFormat {
+    /// `$readmemh`
+    Hex,
+    /// `$readmemb`
+    Bin,
+}
+
+impl MemoryImageFormat {
+    fn radix(self) -> u32 {
+        match self {
+            MemoryImageFormat::Hex => 16,
+            MemoryImageFormat::Bin => 2,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MemoryImageError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("line {line}: `{word}` is not a valid {data_width}-bit word")]
+    InvalidWord {
+        line: usize,
+        word: String,
+        data_width: WidthInt,
+    },
+    #[error("line {line}: `{addr}` is not a valid address")]
+    InvalidAddress { line: usize, addr: String },
+    #[error("line {line}: address {addr:#x} does not fit into {index_width} bits")]
+    AddressOutOfRange {
+        line: usize,
+        addr: u64,
+        index_width: WidthInt,
+    },
+    #[error("unterminated block comment")]
+    UnterminatedComment,
+    #[error("there is no array state called `{0}`")]
+    UnknownState(String),
+}
+
+pub type MemoryImageResult<T> = std::result::Result<T, MemoryImageError>;
+
+/// Contents of a memory, all words that are not listed are zero.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryImage {
+    pub tpe: ArrayType,
+    /// in the order in which they appear in the image, later words overwrite earlier ones
+    pub words: Vec<(u64, BitVecValue)>,
+}
+
+impl MemoryImage {
+    pub fn parse(text: &str, format: MemoryImageFormat, tpe: ArrayType) -> MemoryImageResult<Self> {
+        let mut words = vec![];
+        let mut addr = 0u64;
+        let mut in_comment = false;
+        for (ii, line) in text.lines().enumerate() {
+            let line_no = ii + 1;
+            let mut rest = line;
+            while !rest.is_empty() {
+                if in_comment {
+                    match rest.find("*/") {
+                        Some(end) => {
+                            rest = &rest[end + 2..];
+                            in_comment = false;
+                        }
+                        None => rest = "",
+                    }
+                    continue;
+                }
+                rest = rest.trim_start();
+                if rest.starts_with("//") {
+                    break;
+                }
+                if let Some(tail) = rest.strip_prefix("/*") {
+                    rest = tail;
+                    in_comment = true;
+                    continue;
+                }
+                let end = rest
+                    .find(|c: char| c.is_whitespace() || c == '/')
+                    .unwrap_or(rest.len());
+                let (token, tail) = rest.split_at(end);
+                rest = tail;
+                if token.is_empty() {
+                    // a single `/` that does not start a comment
+                    rest = &rest[1..];
+                    continue;
+                }
+                if let Some(a) = token.strip_prefix('@') {
+                    addr = u64::from_str_radix(&a.replace('_', ""), 16).map_err(|_| {
+                        MemoryImageError::InvalidAddress {
+                            line: line_no,
+                            addr: a.to_string(),
+                        }
+                    })?;
+                    continue;
+                }
+                if tpe.index_width < u64::BITS as WidthInt && addr >> tpe.index_width != 0 {
+                    return Err(MemoryImageError::AddressOutOfRange {
+                        line: line_no,
+                        addr,
+                        index_width: tpe.index_width,
+                    });
+                }
+                let data = parse_word(token, format, tpe).ok_or_else(|| {
+                    MemoryImageError::InvalidWord {
+                        line: line_no,
+                        word: token.to_string(),
+                        data_width: tpe.data_width,
+                    }
+                })?;
+                words.push((addr, data));
+                addr += 1;
+            }
+        }
+        if in_comment {
+            return Err(MemoryImageError::UnterminatedComment);
+        }
+        Ok(Self { tpe, words })
+    }
+
+    pub fn load(
+        path: impl AsRef<Path>,
+        format: MemoryImageFormat,
+        tpe: ArrayType,
+    ) -> MemoryImageResult<Self> {
+        let text = std::fs::read_to_string(path)?;
+        Self::parse(&text, format, tpe)
+    }
+
+    pub fn to_array_value(&self) -> ArrayValue {
+        let mut value = ArrayValue::new_sparse(
+            self.tpe.index_width,
+            &BitVecValue::zero(self.tpe.data_width),
+        );
+        for (addr, data) in self.words.iter() {
+            value.store(&BitVecValue::from_u64(*addr, self.tpe.index_width), data);
+        }
+        value
+    }
+
+    pub fn to_expr(&self, ctx: &mut Context) -> ExprRef {
+        ctx.array_lit(&self.to_array_value())
+    }
+}
+
+fn parse_word(token: &str, format: MemoryImageFormat, tpe: ArrayType) -> Option<BitVecValue> {
+    let digits = token.replace('_', "");
+    let bits_per_digit = match format {
+        MemoryImageFormat::Hex => 4,
+        MemoryImageFormat::Bin => 1,
+    };
+    // leading zeros may make the word wider than the memory
+    let width = (digits.len() as WidthInt * bits_per_digit).max(tpe.data_width);
+    let value = BitVecValue::from_str_radix(&digits, format.radix(), width).ok()?;
+    let significant = value.bit_set_intervals().iter().map(|i| i.end).max();
+    if significant.unwrap_or(0) > tpe.data_width {
+        return None;
+    }
+    Some(value.slice(tpe.data_width - 1, 0))
+}
+
+impl TransitionSystem {
+    /// Uses the memory image in `path` as the init expression of the array state `name`.
+    pub fn load_memory_image(
+        &mut self,
+        ctx: &mut Context,
+        name: &str,
+        path: impl AsRef<Path>,
+        format: MemoryImageFormat,
+    ) -> MemoryImageResult<MemoryImage> {
+        let symbol = self
+            .get_state_by_name(ctx, name)
+            .map(|s| s.symbol)
+            .filter(|s| s.get_type(ctx).is_array())
+            .ok_or_else(|| MemoryImageError::UnknownState(name.to_string()))?;
+        let image = MemoryImage::load(path, format, symbol.get_array_type(ctx).unwrap())?;
+        let init = image.to_expr(ctx);
+        let state = self.states.iter_mut().find(|s| s.symbol == symbol).unwrap();
+        state.init = Some(init);
+        Ok(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::{InitKind, Interpreter, Simulator};
+    use crate::system::State;
+    use baa::{ArrayOps, Value};
+
+    const ROM: ArrayType = ArrayType {
+        index_width: 4,
+        data_width: 8,
+    };
+
+    #[test]
+    fn test_parse_hex() {
+        let text = "// boot rom\nde ad\n@8 /* jump */ be_ef\n@a\n00ff\n";
+        let image = MemoryImage::parse(text, MemoryImageFormat::Hex, ROM).unwrap();
+        let words: Vec<(u64, u64)> = image
+            .words
+            .iter()
+            .map(|(a, d)| (*a, d.to_u64().unwrap()))
+            .collect();
+        assert_eq!(
+            words,
+            [(0, 0xde), (1, 0xad), (8, 0xbe), (9, 0xef), (10, 0xff)]
+        );
+        let value = image.to_array_value();
+        let read = |a: u64| value.select(&BitVecValue::from_u64(a, 4)).to_u64();
+        assert_eq!(read(1), Some(0xad));
+        assert_eq!(read(2), Some(0));
+        assert_eq!(read(10), Some(0xff));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let parse = |text: &str| MemoryImage::parse(text, MemoryImageFormat::Bin, ROM);
+        assert_eq!(parse("0101 1_1").unwrap().words.len(), 2);
+        assert!(matches!(
+            parse("0101\n2"),
+            Err(MemoryImageError::InvalidWord { line: 2, .. })
+        ));
+        assert!(matches!(
+            parse("111111111"),
+            Err(MemoryImageError::InvalidWord { .. })
+        ));
+        assert!(matches!(
+            parse("@10 1"),
+            Err(MemoryImageError::AddressOutOfRange { addr: 16, .. })
+        ));
+        assert!(matches!(
+            parse("1 /* 0"),
+            Err(MemoryImageError::UnterminatedComment)
+        ));
+    }
+
+    #[test]
+    fn test_init_rom_in_simulator() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("rom".to_string());
+        let addr = ctx.bv_symbol("addr", 4);
+        sys.add_input(&ctx, addr);
+        let rom = ctx.array_symbol("rom", 4, 8);
+        sys.add_state(
+            &ctx,
+            State {
+                symbol: rom,
+                init: None,
+                next: Some(rom),
+            },
+        );
+        let data = ctx.array_read(rom, addr);
+        sys.add_output(&mut ctx, "data".into(), data);
+
+        let path = std::env::temp_dir().join("patronus_test_init_rom.hex");
+        std::fs::write(&path, "@3 2a 2b\n").unwrap();
+        let image = sys
+            .load_memory_image(&mut ctx, "rom", &path, MemoryImageFormat::Hex)
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(image.words.len(), 2);
+        assert!(matches!(
+            sys.load_memory_image(&mut ctx, "addr", "missing.hex", MemoryImageFormat::Hex),
+            Err(MemoryImageError::UnknownState(_))
+        ));
+
+        let mut sim = Interpreter::new(&ctx, &sys);
+        sim.init(InitKind::Zero);
+        sim.set(addr, &BitVecValue::from_u64(4, 4)).unwrap();
+        sim.step();
+        match sim.get(data) {
+            Value::BitVec(v) => assert_eq!(v.to_u64(), Some(0x2b)),
+            Value::Array(_) => unreachable!(),
+        }
+    }
+}
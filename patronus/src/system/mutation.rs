@@ -0,0 +1,231 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Mutation Testing
+//! Applies small, systematic changes to the expressions of a transition system and checks
+//! whether a user provided test or property suite notices them. Mutants that survive point to
+//! behavior that is not covered by the suite.
+
+use super::transform::do_transform;
+use super::TransitionSystem;
+use crate::expr::*;
+use baa::{BitVecOps, BitVecValue};
+use rustc_hash::FxHashSet;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MutationKind {
+    /// replaces an operator with a similar one, e.g., `add` with `sub`
+    OperatorSwap,
+    /// adds or subtracts one from a literal
+    ConstantPerturbation,
+    /// turns a strict comparison into a non-strict one (and vice versa) or moves a slice by one bit
+    OffByOne,
+}
+
+/// A single change to an expression. The change applies to all uses of `original`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mutant {
+    pub kind: MutationKind,
+    pub original: ExprRef,
+    pub replacement: ExprRef,
+}
+
+impl Mutant {
+    pub fn describe(&self, ctx: &Context) -> String {
+        format!(
+            "{} -> {}",
+            self.original.serialize_to_str(ctx),
+            self.replacement.serialize_to_str(ctx)
+        )
+    }
+
+    /// Returns a copy of `sys` with the mutation applied.
+    pub fn apply(&self, ctx: &mut Context, sys: &TransitionSystem) -> TransitionSystem {
+        let mut mutated = sys.clone();
+        do_transform(
+            ctx,
+            &mut mutated,
+            ExprTransformMode::SingleStep,
+            |_, e, _| (e == self.original).then_some(self.replacement),
+        );
+        mutated
+    }
+}
+
+/// Enumerates all mutants of the expressions reachable from `sys`.
+pub fn find_mutants(ctx: &mut Context, sys: &TransitionSystem) -> Vec<Mutant> {
+    let mut out = vec![];
+    let mut visited = FxHashSet::default();
+    let mut todo = sys.get_all_exprs();
+    while let Some(e) = todo.pop() {
+        if !visited.insert(e) {
+            continue;
+        }
+        ctx[e].for_each_child(|&c| todo.push(c));
+        for (kind, replacement) in mutate(ctx, e) {
+            if replacement != e {
+                out.push(Mutant {
+                    kind,
+                    original: e,
+                    replacement,
+                });
+            }
+        }
+    }
+    out
+}
+
+fn mutate(ctx: &mut Context, e: ExprRef) -> Vec<(MutationKind, ExprRef)> {
+    use MutationKind::*;
+    match ctx[e].clone() {
+        Expr::BVLiteral(value) => {
+            let value = value.get(ctx);
+            let one = BitVecValue::from_u64(1, value.width());
+            let inc = value.add(&one);
+            let dec = value.sub(&one);
+            let mut out = vec![(ConstantPerturbation, ctx.bv_lit(&inc))];
+            if dec != inc {
+                out.push((ConstantPerturbation, ctx.bv_lit(&dec)));
+            }
+            out
+        }
+        Expr::BVAdd(a, b, _) => vec![(OperatorSwap, ctx.sub(a, b))],
+        Expr::BVSub(a, b, _) => vec![(OperatorSwap, ctx.add(a, b))],
+        Expr::BVMul(a, b, _) => vec![(OperatorSwap, ctx.add(a, b))],
+        Expr::BVAnd(a, b, _) => vec![(OperatorSwap, ctx.or(a, b))],
+        Expr::BVOr(a, b, _) => vec![(OperatorSwap, ctx.and(a, b)), (OperatorSwap, ctx.xor(a, b))],
+        Expr::BVXor(a, b, _) => vec![(OperatorSwap, ctx.or(a, b))],
+        Expr::BVShiftLeft(a, b, _) => vec![(OperatorSwap, ctx.shift_right(a, b))],
+        Expr::BVShiftRight(a, b, _) => vec![
+            (OperatorSwap, ctx.shift_left(a, b)),
+            (OperatorSwap, ctx.arithmetic_shift_right(a, b)),
+        ],
+        Expr::BVArithmeticShiftRight(a, b, _) => vec![(OperatorSwap, ctx.shift_right(a, b))],
+        Expr::BVEqual(a, b) => vec![(OperatorSwap, ctx.build(|c| c.not(c.equal(a, b))))],
+        Expr::BVZeroExt { e, by, .. } => vec![(OperatorSwap, ctx.sign_extend(e, by))],
+        Expr::BVSignExt { e, by, .. } => vec![(OperatorSwap, ctx.zero_extend(e, by))],
+        Expr::BVGreater(a, b) => vec![
+            (OffByOne, ctx.greater_or_equal(a, b)),
+            (OperatorSwap, ctx.greater_signed(a, b)),
+        ],
+        Expr::BVGreaterEqual(a, b) => vec![
+            (OffByOne, ctx.greater(a, b)),
+            (OperatorSwap, ctx.greater_or_equal_signed(a, b)),
+        ],
+        Expr::BVGreaterSigned(a, b, _) => vec![
+            (OffByOne, ctx.greater_or_equal_signed(a, b)),
+            (OperatorSwap, ctx.greater(a, b)),
+        ],
+        Expr::BVGreaterEqualSigned(a, b, _) => vec![
+            (OffByOne, ctx.greater_signed(a, b)),
+            (OperatorSwap, ctx.greater_or_equal(a, b)),
+        ],
+        Expr::BVSlice { e, hi, lo } => {
+            let width = e.get_bv_type(ctx).unwrap();
+            let mut out = vec![];
+            if hi + 1 < width {
+                out.push((OffByOne, ctx.slice(e, hi + 1, lo + 1)));
+            }
+            if lo > 0 {
+                out.push((OffByOne, ctx.slice(e, hi - 1, lo - 1)));
+            }
+            out
+        }
+        _ => vec![],
+    }
+}
+
+/// Outcome of running a suite against all mutants.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MutationReport {
+    /// mutants for which the suite failed
+    pub killed: Vec<Mutant>,
+    /// mutants that passed the suite
+    pub survivors: Vec<Mutant>,
+}
+
+impl MutationReport {
+    /// Fraction of mutants that were killed. A suite without mutants gets a perfect score.
+    pub fn score(&self) -> f64 {
+        let total = self.killed.len() + self.survivors.len();
+        if total == 0 {
+            1.0
+        } else {
+            self.killed.len() as f64 / total as f64
+        }
+    }
+}
+
+/// Runs `suite` on every mutant of `sys`. The suite returns `true` if all of its tests pass.
+pub fn run_mutation_tests(
+    ctx: &mut Context,
+    sys: &TransitionSystem,
+    mut suite: impl FnMut(&mut Context, &TransitionSystem) -> bool,
+) -> MutationReport {
+    let mut report = MutationReport::default();
+    for mutant in find_mutants(ctx, sys) {
+        let mutated = mutant.apply(ctx, sys);
+        if suite(ctx, &mutated) {
+            report.survivors.push(mutant);
+        } else {
+            report.killed.push(mutant);
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::{InitKind, Interpreter, Simulator};
+    use crate::system::State;
+
+    #[test]
+    fn test_mutation_testing() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let count = ctx.bv_symbol("count", 4);
+        let next = ctx.build(|c| c.add(count, c.one(4)));
+        let init = ctx.zero(4);
+        sys.add_state(
+            &ctx,
+            State {
+                symbol: count,
+                init: Some(init),
+                next: Some(next),
+            },
+        );
+        sys.add_output(&mut ctx, "out".into(), count);
+
+        let mutants = find_mutants(&mut ctx, &sys);
+        let kinds: Vec<_> = mutants.iter().map(|m| m.kind).collect();
+        assert!(kinds.contains(&MutationKind::OperatorSwap));
+        assert!(kinds.contains(&MutationKind::ConstantPerturbation));
+
+        // a suite that only checks the init value misses all changes to the next state
+        let weak = run_mutation_tests(&mut ctx, &sys, |ctx, sys| {
+            let out = sys.lookup_output(ctx, "out").unwrap();
+            let mut sim = Interpreter::new(ctx, sys);
+            sim.init(InitKind::Zero);
+            sim.get(out).try_into_u64().unwrap() == 0
+        });
+        // the suite checks the init value and thus kills the mutants of the init literal
+        assert!(!weak.killed.is_empty());
+        assert!(!weak.survivors.is_empty());
+
+        // checking a few steps kills all mutants
+        let strong = run_mutation_tests(&mut ctx, &sys, |ctx, sys| {
+            let out = sys.lookup_output(ctx, "out").unwrap();
+            let mut sim = Interpreter::new(ctx, sys);
+            sim.init(InitKind::Zero);
+            (0..3).all(|ii| {
+                let ok = sim.get(out).try_into_u64().unwrap() == ii;
+                sim.step();
+                ok
+            })
+        });
+        assert!(strong.survivors.is_empty());
+        assert_eq!(strong.score(), 1.0);
+    }
+}
@@ -0,0 +1,284 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Names across Transformations
+//! Passes like simplification or input removal may replace state and input symbols.
+//! In order to still be able to map a counterexample back to the original design,
+//! every replacement is recorded in [`SymbolRenames`].
+//! Signals can also be renamed in bulk, e.g., to avoid collisions when composing systems.
+
+use crate::expr::{Context, ExprRef, ExprTransformMode, TypeCheck};
+use crate::system::transform::do_transform;
+use crate::system::TransitionSystem;
+use regex::Regex;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+/// Maps symbols of the original system to the expressions that replaced them.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolRenames {
+    map: FxHashMap<ExprRef, ExprRef>,
+}
+
+impl SymbolRenames {
+    pub fn record(&mut self, old: ExprRef, new: ExprRef) {
+        if old != new {
+            self.map.insert(old, new);
+        }
+    }
+
+    /// Returns the expression that `old` was replaced with, following renames across
+    /// several passes. Returns `old` if it was never replaced.
+    pub fn resolve(&self, old: ExprRef) -> ExprRef {
+        let mut e = old;
+        // the number of steps is bounded in case a later pass maps back to an older symbol
+        for _ in 0..=self.map.len() {
+            match self.map.get(&e) {
+                Some(&next) => e = next,
+                None => break,
+            }
+        }
+        e
+    }
+
+    pub fn was_replaced(&self, e: ExprRef) -> bool {
+        self.map.contains_key(&e)
+    }
+
+    /// Finds a replaced symbol by its original name and returns what it resolves to now.
+    pub fn lookup_by_name(&self, ctx: &Context, name: &str) -> Option<ExprRef> {
+        self.map
+            .keys()
+            .find(|&&old| ctx.get_symbol_name(old) == Some(name))
+            .map(|&old| self.resolve(old))
+    }
+
+    /// Iterates over all direct `(old, new)` replacements.
+    pub fn iter(&self) -> impl Iterator<Item = (ExprRef, ExprRef)> + '_ {
+        self.map.iter().map(|(&old, &new)| (old, new))
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
+
+/// Determines how a pass names the signals it creates.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum NamePolicy {
+    /// Reuse the original name, adding a numeric suffix if it is already taken.
+    #[default]
+    Suffix,
+    /// Prepend a fixed prefix (e.g. the name of the pass), adding a numeric suffix if needed.
+    Prefix(String),
+}
+
+impl NamePolicy {
+    /// Derives a fresh name from `base`. `is_taken` reports names that are already in use.
+    pub fn generate(&self, base: &str, is_taken: impl Fn(&str) -> bool) -> String {
+        let base = match self {
+            NamePolicy::Suffix => base.to_string(),
+            NamePolicy::Prefix(prefix) => format!("{prefix}{base}"),
+        };
+        if !is_taken(&base) {
+            return base;
+        }
+        (0..)
+            .map(|ii| format!("{base}_{ii}"))
+            .find(|name| !is_taken(name))
+            .unwrap()
+    }
+}
+
+/// Regex based rename rules. The first rule whose pattern matches a name is applied.
+#[derive(Debug, Clone, Default)]
+pub struct RenameMap {
+    rules: Vec<(Regex, String)>,
+}
+
+impl RenameMap {
+    /// Adds a rule that replaces all matches of `pattern` with `replacement`, which may refer
+    /// to capture groups like `$1`.
+    pub fn add(&mut self, pattern: &str, replacement: &str) -> Result<(), regex::Error> {
+        self.rules
+            .push((Regex::new(pattern)?, replacement.to_string()));
+        Ok(())
+    }
+
+    pub fn rename(&self, name: &str) -> Option<String> {
+        self.rules
+            .iter()
+            .find(|(r, _)| r.is_match(name))
+            .map(|(r, replacement)| r.replace_all(name, replacement.as_str()).into_owned())
+    }
+}
+
+/// All `(old, new)` names of the signals that were renamed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RenameReport {
+    pub renamed: Vec<(String, String)>,
+}
+
+impl RenameReport {
+    pub fn get(&self, old: &str) -> Option<&str> {
+        self.renamed
+            .iter()
+            .find(|(o, _)| o == old)
+            .map(|(_, n)| n.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.renamed.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.renamed.is_empty()
+    }
+}
+
+/// Adds `prefix` to the names of all inputs, states and outputs.
+pub fn prefix_all(ctx: &mut Context, sys: &mut TransitionSystem, prefix: &str) -> RenameReport {
+    rename_signals(ctx, sys, |name| Some(format!("{prefix}{name}")))
+}
+
+/// Renames inputs, states and outputs according to `map`.
+pub fn rename_with(ctx: &mut Context, sys: &mut TransitionSystem, map: &RenameMap) -> RenameReport {
+    rename_signals(ctx, sys, |name| map.rename(name))
+}
+
+/// Renames all inputs, states and outputs for which `rename` returns a new name.
+/// Input and state symbols are replaced with new symbols, which is recorded in
+/// [`TransitionSystem::renames`]. If a new name is already taken, a numeric suffix is added.
+pub fn rename_signals(
+    ctx: &mut Context,
+    sys: &mut TransitionSystem,
+    mut rename: impl FnMut(&str) -> Option<String>,
+) -> RenameReport {
+    let symbols: Vec<ExprRef> = sys
+        .inputs
+        .iter()
+        .copied()
+        .chain(sys.states.iter().map(|s| s.symbol))
+        .collect();
+    let old_names: Vec<String> = symbols
+        .iter()
+        .map(|&s| ctx.get_symbol_name(s).unwrap().to_string())
+        .chain(sys.outputs.iter().map(|o| ctx[o.name].clone()))
+        .collect();
+    let new_names: Vec<Option<String>> = old_names
+        .iter()
+        .map(|n| rename(n).filter(|new| new != n))
+        .collect();
+
+    // names that stay the same cannot be taken by renamed signals
+    let mut taken: FxHashSet<String> = old_names
+        .iter()
+        .zip(new_names.iter())
+        .filter(|(_, new)| new.is_none())
+        .map(|(old, _)| old.clone())
+        .collect();
+    let mut report = RenameReport::default();
+    let new_names: Vec<Option<String>> = old_names
+        .iter()
+        .zip(new_names)
+        .map(|(old, new)| {
+            let new = NamePolicy::Suffix.generate(&new?, |n| taken.contains(n));
+            taken.insert(new.clone());
+            report.renamed.push((old.clone(), new.clone()));
+            Some(new)
+        })
+        .collect();
+
+    let mut replace = FxHashMap::default();
+    for (&old, new_name) in symbols.iter().zip(new_names.iter()) {
+        if let Some(new_name) = new_name {
+            let name = ctx.string(new_name.as_str().into());
+            let new = ctx.symbol(name, old.get_type(ctx));
+            replace.insert(old, new);
+        }
+    }
+    for (output, new_name) in sys
+        .outputs
+        .iter_mut()
+        .zip(new_names[symbols.len()..].iter())
+    {
+        if let Some(new_name) = new_name {
+            output.name = ctx.string(new_name.as_str().into());
+        }
+    }
+    if !replace.is_empty() {
+        do_transform(ctx, sys, ExprTransformMode::SingleStep, |_, e, _| {
+            replace.get(&e).copied()
+        });
+        for &new in replace.values() {
+            sys.names[new] = ctx[new].get_symbol_name_ref();
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::transform::replace_anonymous_inputs_with_zero;
+    use crate::system::TransitionSystem;
+
+    #[test]
+    fn test_renames_are_recorded() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let a = ctx.bv_symbol("a", 8);
+        let anon = ctx.bv_symbol("_input_0", 8);
+        sys.add_input(&ctx, a);
+        sys.add_input(&ctx, anon);
+        let sum = ctx.add(a, anon);
+        sys.add_output(&mut ctx, "out".into(), sum);
+
+        replace_anonymous_inputs_with_zero(&mut ctx, &mut sys);
+        let zero = ctx.zero(8);
+        assert!(sys.renames.was_replaced(anon));
+        assert!(!sys.renames.was_replaced(a));
+        assert_eq!(sys.renames.resolve(anon), zero);
+        assert_eq!(sys.renames.resolve(a), a);
+        assert_eq!(sys.renames.lookup_by_name(&ctx, "_input_0"), Some(zero));
+    }
+
+    #[test]
+    fn test_name_policy() {
+        let taken = ["a", "a_0", "p_b"];
+        let is_taken = |n: &str| taken.contains(&n);
+        assert_eq!(NamePolicy::Suffix.generate("a", is_taken), "a_1");
+        assert_eq!(NamePolicy::Suffix.generate("b", is_taken), "b");
+        let prefix = NamePolicy::Prefix("p_".to_string());
+        assert_eq!(prefix.generate("a", is_taken), "p_a");
+        assert_eq!(prefix.generate("b", is_taken), "p_b_0");
+    }
+
+    #[test]
+    fn test_prefix_and_rename_map() {
+        let (mut ctx, mut sys) = crate::examples::fifo(2, 8);
+        let report = prefix_all(&mut ctx, &mut sys, "left.");
+        assert_eq!(report.get("push"), Some("left.push"));
+        assert_eq!(report.get("wr"), Some("left.wr"));
+        assert_eq!(report.get("full"), Some("left.full"));
+        assert!(sys.lookup_input(&ctx, "push").is_none());
+        let push = sys.lookup_input(&ctx, "left.push").unwrap();
+        assert_eq!(sys.renames.lookup_by_name(&ctx, "push"), Some(push));
+        assert!(sys.get_state_by_name(&ctx, "left.rd").is_some());
+
+        // the new name of `left.pop` collides with `left.push`
+        let mut map = RenameMap::default();
+        map.add(r"^left\.pop$", "left.push").unwrap();
+        map.add(r"^left\.(.*)_in$", "in_$1").unwrap();
+        let report = rename_with(&mut ctx, &mut sys, &map);
+        assert_eq!(report.len(), 2);
+        assert_eq!(report.get("left.pop"), Some("left.push_0"));
+        assert_eq!(report.get("left.data_in"), Some("in_data"));
+        assert!(sys.lookup_input(&ctx, "left.push").is_some());
+        assert!(sys.lookup_input(&ctx, "in_data").is_some());
+    }
+}
@@ -0,0 +1,291 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Pass Manager
+//! Runs a sequence of transformation passes over a transition system and collects statistics.
+
+use super::transform::{
+    canonicalize_expressions, propagate_constant_states, replace_anonymous_inputs_with_zero,
+    simplify_expressions, ConstantState,
+};
+use super::TransitionSystem;
+use crate::expr::Context;
+use crate::plugin::PluginRegistry;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// A transformation of a transition system.
+pub trait Pass {
+    /// Unique name used to enable or disable the pass and to label its statistics.
+    fn name(&self) -> &'static str;
+    fn run(&mut self, ctx: &mut Context, sys: &mut TransitionSystem);
+}
+
+/// Replaces all anonymous inputs and states with zero, see [`replace_anonymous_inputs_with_zero`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ReplaceAnonymousInputs;
+
+impl Pass for ReplaceAnonymousInputs {
+    fn name(&self) -> &'static str {
+        "replace-anonymous-inputs"
+    }
+
+    fn run(&mut self, ctx: &mut Context, sys: &mut TransitionSystem) {
+        replace_anonymous_inputs_with_zero(ctx, sys);
+    }
+}
+
+/// Simplifies all expressions, see [`simplify_expressions`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Simplify;
+
+impl Pass for Simplify {
+    fn name(&self) -> &'static str {
+        "simplify"
+    }
+
+    fn run(&mut self, ctx: &mut Context, sys: &mut TransitionSystem) {
+        simplify_expressions(ctx, sys);
+    }
+}
+
+/// Canonicalizes commutative and associative operators, see [`canonicalize_expressions`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Canonicalize;
+
+impl Pass for Canonicalize {
+    fn name(&self) -> &'static str {
+        "canonicalize"
+    }
+
+    fn run(&mut self, ctx: &mut Context, sys: &mut TransitionSystem) {
+        canonicalize_expressions(ctx, sys);
+    }
+}
+
+/// Replaces states that never change with their init value, see [`propagate_constant_states`].
+/// The replaced states are kept in order to report them later.
+#[derive(Debug, Default, Clone)]
+pub struct PropagateConstantStates {
+    constants: Vec<ConstantState>,
+}
+
+impl PropagateConstantStates {
+    pub fn constants(&self) -> &[ConstantState] {
+        &self.constants
+    }
+}
+
+impl Pass for PropagateConstantStates {
+    fn name(&self) -> &'static str {
+        "propagate-constant-states"
+    }
+
+    fn run(&mut self, ctx: &mut Context, sys: &mut TransitionSystem) {
+        let constants = propagate_constant_states(ctx, sys);
+        self.constants.extend(constants);
+    }
+}
+
+/// Wraps a function so that it can be used as a pass.
+pub struct FnPass<F> {
+    name: &'static str,
+    run: F,
+}
+
+impl<F: FnMut(&mut Context, &mut TransitionSystem)> FnPass<F> {
+    pub fn new(name: &'static str, run: F) -> Self {
+        Self { name, run }
+    }
+}
+
+impl<F: FnMut(&mut Context, &mut TransitionSystem)> Pass for FnPass<F> {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn run(&mut self, ctx: &mut Context, sys: &mut TransitionSystem) {
+        (self.run)(ctx, sys)
+    }
+}
+
+/// Selects the built-in passes run by [`PassManager::from_config`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PassConfig {
+    pub replace_anonymous_inputs: bool,
+    pub simplify: bool,
+    pub canonicalize: bool,
+}
+
+impl Default for PassConfig {
+    fn default() -> Self {
+        Self {
+            replace_anonymous_inputs: false,
+            simplify: true,
+            canonicalize: false,
+        }
+    }
+}
+
+/// Statistics collected while running a single pass.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PassRun {
+    pub name: &'static str,
+    pub time: Duration,
+    pub states_before: usize,
+    pub states_after: usize,
+    pub inputs_before: usize,
+    pub inputs_after: usize,
+    /// number of state or input symbols that the pass replaced
+    pub replaced_symbols: usize,
+}
+
+/// Statistics shared by all passes of a [`PassManager`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PassStatistics {
+    pub runs: Vec<PassRun>,
+}
+
+impl PassStatistics {
+    pub fn total_time(&self) -> Duration {
+        self.runs.iter().map(|r| r.time).sum()
+    }
+}
+
+struct Entry {
+    pass: Box<dyn Pass>,
+    enabled: bool,
+}
+
+/// Runs registered passes in the order in which they were added.
+#[derive(Default)]
+pub struct PassManager {
+    passes: Vec<Entry>,
+    stats: PassStatistics,
+}
+
+impl PassManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a pass manager with the built-in passes that are enabled in `config`.
+    pub fn from_config(config: &PassConfig) -> Self {
+        Self::new()
+            .with_enabled(ReplaceAnonymousInputs, config.replace_anonymous_inputs)
+            .with_enabled(Simplify, config.simplify)
+            .with_enabled(Canonicalize, config.canonicalize)
+    }
+
+    pub fn with(self, pass: impl Pass + 'static) -> Self {
+        self.with_enabled(pass, true)
+    }
+
+    pub fn with_enabled(mut self, pass: impl Pass + 'static, enabled: bool) -> Self {
+        self.add(pass, enabled);
+        self
+    }
+
+    pub fn add(&mut self, pass: impl Pass + 'static, enabled: bool) {
+        self.add_boxed(Box::new(pass), enabled);
+    }
+
+    /// Adds a fresh instance of every pass in `registry`. All of them start out disabled and
+    /// need to be enabled by name with [`PassManager::set_enabled`].
+    pub fn add_plugin_passes(&mut self, registry: &PluginRegistry) {
+        for name in registry.pass_names() {
+            let pass = registry
+                .create_pass(name)
+                .expect("name was just returned by the registry");
+            self.add_boxed(pass, false);
+        }
+    }
+
+    fn add_boxed(&mut self, pass: Box<dyn Pass>, enabled: bool) {
+        assert!(
+            !self.passes.iter().any(|e| e.pass.name() == pass.name()),
+            "pass {} was already registered",
+            pass.name()
+        );
+        self.passes.push(Entry { pass, enabled });
+    }
+
+    /// Enables or disables a registered pass. Returns `false` if no pass has that name.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        match self.passes.iter_mut().find(|e| e.pass.name() == name) {
+            Some(entry) => {
+                entry.enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Names of all passes that will run, in order.
+    pub fn enabled_passes(&self) -> Vec<&'static str> {
+        self.passes
+            .iter()
+            .filter(|e| e.enabled)
+            .map(|e| e.pass.name())
+            .collect()
+    }
+
+    pub fn run(&mut self, ctx: &mut Context, sys: &mut TransitionSystem) {
+        for entry in self.passes.iter_mut().filter(|e| e.enabled) {
+            let states_before = sys.states.len();
+            let inputs_before = sys.inputs.len();
+            let renames_before = sys.renames.len();
+            if let Some(provenance) = sys.provenance.as_mut() {
+                provenance.set_pass(Some(entry.pass.name()));
+            }
+            let start = Instant::now();
+            entry.pass.run(ctx, sys);
+            if let Some(provenance) = sys.provenance.as_mut() {
+                provenance.set_pass(None);
+            }
+            self.stats.runs.push(PassRun {
+                name: entry.pass.name(),
+                time: start.elapsed(),
+                states_before,
+                states_after: sys.states.len(),
+                inputs_before,
+                inputs_after: sys.inputs.len(),
+                replaced_symbols: sys.renames.len().saturating_sub(renames_before),
+            });
+        }
+    }
+
+    pub fn statistics(&self) -> &PassStatistics {
+        &self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btor2;
+
+    #[test]
+    fn test_pass_manager() {
+        let (mut ctx, mut sys) = btor2::parse_file("../inputs/unittest/delay.btor").unwrap();
+        let mut pm = PassManager::from_config(&PassConfig::default()).with(FnPass::new(
+            "noop",
+            |_: &mut Context, _: &mut TransitionSystem| {},
+        ));
+        assert_eq!(pm.enabled_passes(), ["simplify", "noop"]);
+        assert!(pm.set_enabled("replace-anonymous-inputs", true));
+        assert!(pm.set_enabled("noop", false));
+        assert!(!pm.set_enabled("does-not-exist", true));
+        assert_eq!(
+            pm.enabled_passes(),
+            ["replace-anonymous-inputs", "simplify"]
+        );
+        pm.run(&mut ctx, &mut sys);
+        let stats = pm.statistics();
+        assert_eq!(stats.runs.len(), 2);
+        assert_eq!(stats.runs[1].name, "simplify");
+        assert_eq!(stats.runs[1].states_before, stats.runs[1].states_after);
+    }
+}
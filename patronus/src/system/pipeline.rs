@@ -0,0 +1,103 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+use crate::expr::*;
+use crate::system::{State, TransitionSystem};
+use rustc_hash::FxHashMap;
+
+/// Delays every expression in `cut` by `stages` registers. All outputs, next states,
+/// constraints and bad states that used a cut expression now use its delayed version. Init
+/// expressions are not changed. If every path from the inputs to the outputs crosses the cut
+/// exactly once, the latency of all outputs grows by `stages`.
+/// Registers are named `{name}_pipe_{stage}` and start out as zero.
+/// Returns the registers of the last stage, in the order of `cut`.
+pub fn insert_pipeline_registers(
+    ctx: &mut Context,
+    sys: &mut TransitionSystem,
+    cut: &[ExprRef],
+    stages: u32,
+) -> Vec<ExprRef> {
+    if stages == 0 {
+        return cut.to_vec();
+    }
+    let mut delayed = FxHashMap::default();
+    let mut registers = vec![];
+    for (ii, &e) in cut.iter().enumerate() {
+        let tpe = e.get_type(ctx);
+        let base = ctx
+            .get_symbol_name(e)
+            .map(|n| n.to_string())
+            .unwrap_or_else(|| format!("cut_{ii}"));
+        let mut prev = e;
+        for stage in 0..stages {
+            let name = ctx.string(format!("{base}_pipe_{stage}").into());
+            let symbol = ctx.symbol(name, tpe);
+            let init = match tpe {
+                Type::BV(width) => ctx.zero(width),
+                Type::Array(tpe) => ctx.zero_array(tpe),
+            };
+            registers.push(State {
+                symbol,
+                init: Some(init),
+                next: Some(prev),
+            });
+            prev = symbol;
+        }
+        delayed.insert(e, prev);
+    }
+
+    let substitute = |ctx: &mut Context, e: ExprRef| {
+        simple_transform_expr(ctx, e, |_, e, _| delayed.get(&e).copied())
+    };
+    for output in sys.outputs.iter_mut() {
+        output.expr = substitute(ctx, output.expr);
+    }
+    for state in sys.states.iter_mut() {
+        state.next = state.next.map(|n| substitute(ctx, n));
+    }
+    for e in sys.constraints.iter_mut().chain(sys.bad_states.iter_mut()) {
+        *e = substitute(ctx, *e);
+    }
+
+    let last = cut.iter().map(|e| delayed[e]).collect();
+    for state in registers {
+        sys.add_state(ctx, state);
+    }
+    last
+}
+
+/// Registers all inputs `stages` times. For a combinational system this increases the latency
+/// of every output by `stages`.
+pub fn pipeline_inputs(ctx: &mut Context, sys: &mut TransitionSystem, stages: u32) {
+    let inputs = sys.inputs.clone();
+    insert_pipeline_registers(ctx, sys, &inputs, stages);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::examples::{alu, input};
+    use crate::sim::{InitKind, Interpreter, Simulator};
+    use baa::{BitVecOps, BitVecValue};
+
+    #[test]
+    fn test_pipelined_alu() {
+        let (mut ctx, mut sys) = alu(8);
+        pipeline_inputs(&mut ctx, &mut sys, 2);
+        assert_eq!(sys.states.len(), 3 * 2);
+        assert!(sys.get_state_by_name(&ctx, "op_pipe_1").is_some());
+        let out = sys.lookup_output(&ctx, "out").unwrap();
+        let mut sim = Interpreter::new(&ctx, &sys);
+        sim.init(InitKind::Zero);
+        sim.set(input(&ctx, &sys, "a"), &BitVecValue::from_u64(3, 8))
+            .unwrap();
+        sim.set(input(&ctx, &sys, "b"), &BitVecValue::from_u64(4, 8))
+            .unwrap();
+        // the result shows up two steps later
+        for expected in [0, 0, 7] {
+            assert_eq!(sim.get(out).try_into_u64().unwrap(), expected);
+            sim.step();
+        }
+    }
+}
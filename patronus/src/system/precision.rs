@@ -0,0 +1,314 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Precision Reduction
+//! A range analysis determines for every bit-vector expression how many of its least
+//! significant bits can be non-zero. Operators whose result provably fits into fewer bits than
+//! their width are then computed with the smaller width on truncated operands and zero extended
+//! afterwards. Since the low `n` bits of additions, subtractions, multiplications and bitwise
+//! operations only depend on the low `n` bits of their operands, this does not change the result.
+//!
+//! State ranges are the fixed point over their init and next state expressions. States without
+//! a next state take an arbitrary value after the first step and are thus never narrowed.
+
+use super::transform::do_transform;
+use super::TransitionSystem;
+use crate::expr::traversal::{top_down, TraversalCmd};
+use crate::expr::*;
+use baa::BitVecOps;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+/// Result of the range analysis: every bit-vector value `v` of an expression with
+/// `significant_bits` of `n` satisfies `v < 2^n`.
+#[derive(Debug, Clone, Default)]
+pub struct Ranges {
+    bits: FxHashMap<ExprRef, WidthInt>,
+}
+
+impl Ranges {
+    pub fn significant_bits(&self, e: ExprRef) -> Option<WidthInt> {
+        self.bits.get(&e).copied()
+    }
+}
+
+pub fn analyze_ranges(ctx: &Context, sys: &TransitionSystem) -> Ranges {
+    let mut nodes = FxHashSet::default();
+    for root in sys.get_all_exprs() {
+        top_down(ctx, root, |_, e| {
+            if nodes.insert(e) {
+                TraversalCmd::Continue
+            } else {
+                TraversalCmd::Stop
+            }
+        });
+    }
+    // children are always created before their parents
+    let mut nodes: Vec<ExprRef> = nodes.into_iter().collect();
+    nodes.sort();
+
+    // states start out as zero and grow with every value they can be assigned,
+    // states without a next state are unconstrained after the first step
+    let mut states: FxHashMap<ExprRef, WidthInt> = sys
+        .states
+        .iter()
+        .flat_map(|s| {
+            let width = s.symbol.get_bv_type(ctx)?;
+            let constrained = s.init.is_some() && s.next.is_some();
+            Some((s.symbol, if constrained { 0 } else { width }))
+        })
+        .collect();
+    loop {
+        let ranges = compute_ranges(ctx, &nodes, &states);
+        let mut changed = false;
+        for state in sys.states.iter() {
+            let Some(bits) = states.get_mut(&state.symbol) else {
+                continue;
+            };
+            for e in state.init.iter().chain(state.next.iter()) {
+                if ranges.bits[e] > *bits {
+                    *bits = ranges.bits[e];
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            return ranges;
+        }
+    }
+}
+
+fn compute_ranges(
+    ctx: &Context,
+    nodes: &[ExprRef],
+    states: &FxHashMap<ExprRef, WidthInt>,
+) -> Ranges {
+    let mut bits: FxHashMap<ExprRef, WidthInt> = FxHashMap::default();
+    for &e in nodes.iter() {
+        let Some(width) = e.get_bv_type(ctx) else {
+            continue;
+        };
+        let r = |e: &ExprRef| bits[e];
+        let literal = |e: &ExprRef| match &ctx[*e] {
+            Expr::BVLiteral(value) => value.get(ctx).to_u64(),
+            _ => None,
+        };
+        let value = match &ctx[e] {
+            Expr::BVSymbol { .. } => states.get(&e).copied().unwrap_or(width),
+            Expr::BVLiteral(value) => value
+                .get(ctx)
+                .bit_set_intervals()
+                .iter()
+                .map(|i| i.end)
+                .max()
+                .unwrap_or(0),
+            Expr::BVZeroExt { e, .. } => r(e),
+            Expr::BVSignExt { e, .. } if r(e) < e.get_bv_type(ctx).unwrap() => r(e),
+            Expr::BVSlice { e, hi, lo } => (hi - lo + 1).min(r(e).saturating_sub(*lo)),
+            Expr::BVEqual(..)
+            | Expr::BVImplies(..)
+            | Expr::BVGreater(..)
+            | Expr::BVGreaterSigned(..)
+            | Expr::BVGreaterEqual(..)
+            | Expr::BVGreaterEqualSigned(..) => 1,
+            Expr::BVConcat(a, b, _) if r(a) == 0 => r(b),
+            Expr::BVConcat(a, b, _) => r(a) + b.get_bv_type(ctx).unwrap(),
+            Expr::BVAnd(a, b, _) => r(a).min(r(b)),
+            Expr::BVOr(a, b, _) | Expr::BVXor(a, b, _) => r(a).max(r(b)),
+            Expr::BVAdd(a, b, _) if r(a) == 0 || r(b) == 0 => r(a).max(r(b)),
+            Expr::BVAdd(a, b, _) => r(a).max(r(b)) + 1,
+            Expr::BVMul(a, b, _) if r(a) == 0 || r(b) == 0 => 0,
+            Expr::BVMul(a, b, _) => r(a) + r(b),
+            Expr::BVShiftLeft(a, _, _) if r(a) == 0 => 0,
+            Expr::BVShiftLeft(a, b, _) => match literal(b) {
+                Some(by) if by < width as u64 => r(a) + by as WidthInt,
+                _ => width,
+            },
+            Expr::BVShiftRight(a, b, _) => match literal(b) {
+                Some(by) => r(a).saturating_sub(by.min(width as u64) as WidthInt),
+                None => r(a),
+            },
+            // without the sign bit, this is the same as a logical shift
+            Expr::BVArithmeticShiftRight(a, _, _) if r(a) < width => r(a),
+            // division by zero results in all ones
+            Expr::BVUnsignedDiv(a, b, _) if literal(b).is_some_and(|d| d != 0) => r(a),
+            // the remainder of a division by zero is the dividend
+            Expr::BVUnsignedRem(a, _, _) => r(a),
+            Expr::BVIte { tru, fals, .. } => r(tru).max(r(fals)),
+            _ => width,
+        };
+        bits.insert(e, value.min(width));
+    }
+    Ranges { bits }
+}
+
+/// An operator that is now computed with fewer bits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReducedOp {
+    /// the original expression
+    pub expr: ExprRef,
+    pub from: WidthInt,
+    pub to: WidthInt,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PrecisionReport {
+    pub reduced: Vec<ReducedOp>,
+}
+
+impl PrecisionReport {
+    /// Total number of bits removed from all operators.
+    pub fn saved_bits(&self) -> u64 {
+        self.reduced.iter().map(|r| (r.from - r.to) as u64).sum()
+    }
+}
+
+/// Computes additions, subtractions, multiplications and bitwise operations with the number of
+/// bits that `ranges` guarantees for their result.
+pub fn reduce_precision(
+    ctx: &mut Context,
+    sys: &mut TransitionSystem,
+    ranges: &Ranges,
+) -> PrecisionReport {
+    let mut report = PrecisionReport::default();
+    do_transform(
+        ctx,
+        sys,
+        ExprTransformMode::SingleStep,
+        |ctx, expr, children| {
+            let op: fn(&mut Context, ExprRef, ExprRef) -> ExprRef = match ctx[expr] {
+                Expr::BVAdd(..) => Context::add,
+                Expr::BVSub(..) => Context::sub,
+                Expr::BVMul(..) => Context::mul,
+                Expr::BVAnd(..) => Context::and,
+                Expr::BVOr(..) => Context::or,
+                Expr::BVXor(..) => Context::xor,
+                _ => return None,
+            };
+            let width = expr.get_bv_type(ctx).unwrap();
+            let bits = ranges.significant_bits(expr)?;
+            if bits >= width {
+                return None;
+            }
+            report.reduced.push(ReducedOp {
+                expr,
+                from: width,
+                to: bits,
+            });
+            if bits == 0 {
+                return Some(ctx.zero(width));
+            }
+            let a = ctx.slice(children[0], bits - 1, 0);
+            let b = ctx.slice(children[1], bits - 1, 0);
+            let res = op(ctx, a, b);
+            Some(ctx.zero_extend(res, width - bits))
+        },
+    );
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "sat")]
+    use crate::equiv::{prove_equiv, EquivBackend, EquivOptions, EquivResult};
+    #[cfg(feature = "sat")]
+    use crate::mc::{check_with_sat, ModelCheckResult};
+
+    #[test]
+    #[cfg(feature = "sat")]
+    fn test_reduce_precision() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let a = ctx.bv_symbol("a", 4);
+        let b = ctx.bv_symbol("b", 4);
+        let c = ctx.bv_symbol("c", 16);
+        for input in [a, b, c] {
+            sys.add_input(&ctx, input);
+        }
+        let sum = ctx.build(|x| x.add(x.zero_extend(a, 12), x.zero_extend(b, 12)));
+        let masked = ctx.build(|x| x.and(c, x.zero_extend(a, 12)));
+        let full = ctx.add(c, c);
+        sys.add_output(&mut ctx, "sum".into(), sum);
+        sys.add_output(&mut ctx, "masked".into(), masked);
+        sys.add_output(&mut ctx, "full".into(), full);
+
+        let ranges = analyze_ranges(&ctx, &sys);
+        assert_eq!(ranges.significant_bits(sum), Some(5));
+        assert_eq!(ranges.significant_bits(masked), Some(4));
+        assert_eq!(ranges.significant_bits(full), Some(16));
+
+        let original = sys.clone();
+        let report = reduce_precision(&mut ctx, &mut sys, &ranges);
+        assert_eq!(report.reduced.len(), 2);
+        assert_eq!(report.saved_bits(), 11 + 12);
+        let opts = EquivOptions {
+            backend: EquivBackend::Sat,
+            bdd: None,
+        };
+        for (before, after) in original.outputs.iter().zip(sys.outputs.iter()) {
+            assert_eq!(
+                prove_equiv(&mut ctx, before.expr, after.expr, &opts).unwrap(),
+                EquivResult::Equivalent
+            );
+        }
+        assert_eq!(sys.outputs[2].expr, full);
+    }
+
+    #[test]
+    fn test_state_ranges() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let flag = ctx.bv_symbol("flag", 1);
+        sys.add_input(&ctx, flag);
+        // toggles between 0 and 3
+        let s = ctx.bv_symbol("s", 8);
+        let next = ctx.build(|x| x.ite(flag, x.bit_vec_val(3, 8), x.zero(8)));
+        let init = ctx.zero(8);
+        sys.add_state(
+            &ctx,
+            crate::system::State {
+                symbol: s,
+                init: Some(init),
+                next: Some(next),
+            },
+        );
+        let doubled = ctx.add(s, s);
+        sys.add_output(&mut ctx, "doubled".into(), doubled);
+        let ranges = analyze_ranges(&ctx, &sys);
+        assert_eq!(ranges.significant_bits(s), Some(2));
+        assert_eq!(ranges.significant_bits(doubled), Some(3));
+    }
+
+    #[test]
+    #[cfg(feature = "sat")]
+    fn test_init_only_state() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        // zero in the first step, arbitrary afterwards
+        let s = ctx.bv_symbol("s", 8);
+        let init = ctx.zero(8);
+        sys.add_state(
+            &ctx,
+            crate::system::State {
+                symbol: s,
+                init: Some(init),
+                next: None,
+            },
+        );
+        let doubled = ctx.add(s, s);
+        let bad = ctx.build(|x| x.equal(doubled, x.bit_vec_val(2, 8)));
+        sys.bad_states.push(bad);
+
+        let ranges = analyze_ranges(&ctx, &sys);
+        assert_eq!(ranges.significant_bits(s), Some(8));
+        assert!(reduce_precision(&mut ctx, &mut sys, &ranges)
+            .reduced
+            .is_empty());
+        // narrowing `s + s` to zero bits would make the bad state unreachable
+        let ModelCheckResult::Fail(wit) = check_with_sat(&ctx, &sys, 1).unwrap() else {
+            panic!("the bad state is reachable in the second step");
+        };
+        assert_eq!(wit.inputs.len(), 2);
+    }
+}
@@ -0,0 +1,176 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Expression Provenance
+//! When enabled with [`TransitionSystem::enable_provenance`], every replacement of an
+//! expression by [`TransitionSystem::update_expressions`] or
+//! [`do_transform`](crate::system::transform::do_transform) is recorded together with the
+//! name of the pass that was running in the [`PassManager`](crate::system::PassManager).
+//! For [`do_transform`](crate::system::transform::do_transform) this includes all
+//! sub-expressions that were rewritten, not only the ones that the system refers to directly.
+//! Any expression of the transformed system can then be traced back to the expressions of the
+//! original system that it was derived from.
+
+use super::TransitionSystem;
+use crate::expr::ExprRef;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+/// An expression was derived from `expr` by the pass `pass`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Origin {
+    /// `None` if the transformation did not run as part of a pass manager
+    pub pass: Option<&'static str>,
+    pub expr: ExprRef,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Provenance {
+    pass: Option<&'static str>,
+    origins: FxHashMap<ExprRef, Vec<Origin>>,
+}
+
+impl Provenance {
+    /// All following replacements are attributed to `pass`.
+    pub fn set_pass(&mut self, pass: Option<&'static str>) {
+        self.pass = pass;
+    }
+
+    pub fn record(&mut self, old: ExprRef, new: ExprRef) {
+        if old == new {
+            return;
+        }
+        let origin = Origin {
+            pass: self.pass,
+            expr: old,
+        };
+        let origins = self.origins.entry(new).or_default();
+        if !origins.contains(&origin) {
+            origins.push(origin);
+        }
+    }
+
+    /// Expressions that were directly replaced with `e`.
+    pub fn origins(&self, e: ExprRef) -> &[Origin] {
+        self.origins.get(&e).map(|o| o.as_slice()).unwrap_or(&[])
+    }
+
+    /// Expressions that `e` was derived from and which were not themselves derived from
+    /// anything, i.e., expressions of the original system. Returns `e` if it was never
+    /// the result of a replacement.
+    pub fn trace_back(&self, e: ExprRef) -> Vec<ExprRef> {
+        let mut out = vec![];
+        let mut visited = FxHashSet::default();
+        let mut todo = vec![e];
+        while let Some(e) = todo.pop() {
+            if !visited.insert(e) {
+                continue;
+            }
+            match self.origins.get(&e) {
+                Some(origins) => todo.extend(origins.iter().map(|o| o.expr)),
+                None => out.push(e),
+            }
+        }
+        out.sort();
+        out
+    }
+
+    /// All passes that were involved in deriving `e`, starting with the earliest.
+    pub fn passes(&self, e: ExprRef) -> Vec<Option<&'static str>> {
+        let mut out = vec![];
+        let mut visited = FxHashSet::default();
+        let mut todo = vec![e];
+        while let Some(e) = todo.pop() {
+            if !visited.insert(e) {
+                continue;
+            }
+            for origin in self.origins(e).iter() {
+                if !out.contains(&origin.pass) {
+                    out.push(origin.pass);
+                }
+                todo.push(origin.expr);
+            }
+        }
+        out.reverse();
+        out
+    }
+
+    pub fn len(&self) -> usize {
+        self.origins.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.origins.is_empty()
+    }
+}
+
+impl TransitionSystem {
+    /// Starts recording the provenance of expressions. Expressions that are part of the
+    /// system right now are considered to be the originals.
+    pub fn enable_provenance(&mut self) {
+        if self.provenance.is_none() {
+            self.provenance = Some(Provenance::default());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::{Context, ExprTransformMode};
+    use crate::system::transform::do_transform;
+    use crate::system::{FnPass, PassManager, Simplify, State};
+
+    #[test]
+    fn test_trace_back_through_passes() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let a = ctx.bv_symbol("a", 8);
+        let b = ctx.bv_symbol("b", 8);
+        sys.add_input(&ctx, a);
+        sys.add_input(&ctx, b);
+        let sum = ctx.build(|c| c.add(c.and(a, c.zero(8)), b));
+        let r = ctx.bv_symbol("r", 8);
+        let r_next = ctx.build(|c| c.add(r, sum));
+        sys.add_state(
+            &ctx,
+            State {
+                symbol: r,
+                init: None,
+                next: Some(r_next),
+            },
+        );
+        sys.add_output(&mut ctx, "sum".into(), sum);
+        sys.enable_provenance();
+
+        // replace `b` with `a` in a pass that we can name
+        let swap = FnPass::new(
+            "swap",
+            move |ctx: &mut Context, sys: &mut TransitionSystem| {
+                do_transform(ctx, sys, ExprTransformMode::SingleStep, |_, e, _| {
+                    (e == b).then_some(a)
+                });
+            },
+        );
+        let mut pm = PassManager::new().with(Simplify).with(swap);
+        pm.run(&mut ctx, &mut sys);
+
+        let out = sys.outputs[0].expr;
+        assert_eq!(out, a);
+        let p = sys.provenance.as_ref().unwrap();
+        assert_eq!(p.trace_back(out), [sum]);
+        assert_eq!(p.passes(out), [Some("simplify"), Some("swap")]);
+        // a rewritten sub-expression of the next state function
+        let new_next = sys.states[0].next.unwrap();
+        assert_eq!(new_next, ctx.build(|c| c.add(r, a)));
+        assert_eq!(p.trace_back(new_next), [r_next]);
+        assert_eq!(
+            p.origins(a),
+            [Origin {
+                pass: Some("swap"),
+                expr: b
+            }]
+        );
+        assert_eq!(p.trace_back(r), [r]);
+    }
+}
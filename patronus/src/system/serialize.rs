@@ -6,7 +6,7 @@
 use super::analysis::{analyze_for_serialization, SerializeSignalKind};
 use super::TransitionSystem;
 use crate::expr::{
-    serialize_expr, serialize_expr_ref, Context, ExprRef, SerializableIrNode, SparseExprMap,
+    serialize_expr, serialize_expr_ref, Context, Expr, ExprRef, SerializableIrNode, SparseExprMap,
     TypeCheck,
 };
 use std::io::Write;
@@ -60,13 +60,12 @@ fn serialize_transition_system<W: Write>(
         let tpe = expr.get_type(ctx);
         write!(writer, " : {tpe}",)?;
 
-        if expr.is_symbol() && expr.get_symbol_name(ctx).unwrap() == name {
-            writeln!(writer)?;
-        } else {
+        if !(expr.is_symbol() && expr.get_symbol_name(ctx).unwrap() == name) {
             write!(writer, " = ")?;
             serialize_expr(expr, ctx, writer, &serialize_child)?;
-            writeln!(writer)?;
         }
+        serialize_attributes(ctx, root.expr, writer)?;
+        writeln!(writer)?;
     }
 
     // states
@@ -75,16 +74,20 @@ fn serialize_transition_system<W: Write>(
             .get_symbol_name(state.symbol)
             .expect("all states are required to have a name!");
         let tpe = state.symbol.get_type(ctx);
-        writeln!(writer, "state {name} : {tpe}")?;
+        write!(writer, "state {name} : {tpe}")?;
+        serialize_attributes(ctx, state.symbol, writer)?;
+        writeln!(writer)?;
 
         if let Some(expr) = &state.init {
             write!(writer, "  [init] ")?;
             serialize_expr_ref(expr, ctx, writer, &serialize_child)?;
+            serialize_variant_name(ctx, state.symbol, *expr, writer)?;
             writeln!(writer)?;
         }
         if let Some(expr) = &state.next {
             write!(writer, "  [next] ")?;
             serialize_expr_ref(expr, ctx, writer, &serialize_child)?;
+            serialize_variant_name(ctx, state.symbol, *expr, writer)?;
             writeln!(writer)?;
         }
     }
@@ -92,11 +95,36 @@ fn serialize_transition_system<W: Write>(
     Ok(())
 }
 
+/// Attributes are printed as a comment at the end of the line.
+fn serialize_attributes(ctx: &Context, e: ExprRef, writer: &mut impl Write) -> std::io::Result<()> {
+    if let Some(attrs) = ctx.attributes(e) {
+        write!(writer, " ; ")?;
+        attrs.serialize(ctx, writer)?;
+    }
+    Ok(())
+}
+
+/// Constant values of states with an enum type are annotated with the name of their variant.
+fn serialize_variant_name(
+    ctx: &Context,
+    symbol: ExprRef,
+    e: ExprRef,
+    writer: &mut impl Write,
+) -> std::io::Result<()> {
+    if let (Some(tpe), Expr::BVLiteral(value)) = (ctx.enum_type(symbol), &ctx[e]) {
+        if let Some(name) = tpe.variant_name(&value.get(ctx)) {
+            write!(writer, " ; {name}")?;
+        }
+    }
+    Ok(())
+}
+
 fn kind_to_string(kind: SerializeSignalKind) -> &'static str {
     match kind {
         SerializeSignalKind::BadState => "bad",
         SerializeSignalKind::Constraint => "constraint",
         SerializeSignalKind::Output => "output",
+        SerializeSignalKind::Define => "define",
         SerializeSignalKind::Input => "input",
         SerializeSignalKind::StateInit => "init",
         SerializeSignalKind::StateNext => "next",
@@ -0,0 +1,143 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+use crate::expr::*;
+use crate::system::{State, TransitionSystem};
+use rustc_hash::FxHashSet;
+
+/// Creates a self-contained copy of the logic that feeds `root`, following states through
+/// their init and next expressions. `root` becomes an output of the new system and a bad
+/// state if it was one in `sys`.
+pub fn extract_cone(
+    ctx: &Context,
+    sys: &TransitionSystem,
+    root: ExprRef,
+) -> (Context, TransitionSystem) {
+    extract_cone_with_cut(ctx, sys, root, &[])
+}
+
+/// Like [`extract_cone`], but every expression in `cut` is replaced with a fresh input and
+/// the logic behind it is dropped.
+pub fn extract_cone_with_cut(
+    ctx: &Context,
+    sys: &TransitionSystem,
+    root: ExprRef,
+    cut: &[ExprRef],
+) -> (Context, TransitionSystem) {
+    let mut dst = Context::default();
+    let mut out = TransitionSystem::new(format!("{}_cone", sys.name));
+    let mut copied: SparseExprMap<Option<ExprRef>> = SparseExprMap::default();
+
+    // cut points turn into inputs that carry the name of the signal they replace
+    for (ii, &e) in cut.iter().enumerate() {
+        let name = signal_name(ctx, sys, e).unwrap_or_else(|| format!("cut_{ii}"));
+        let name = dst.string(name.into());
+        let input = dst.symbol(name, e.get_type(ctx));
+        out.add_input(&dst, input);
+        copied[e] = Some(input);
+    }
+
+    let support = sequential_support(ctx, sys, root, cut);
+    for &input in sys.inputs.iter().filter(|i| support.contains(i)) {
+        let new_input = copy_expr(ctx, &mut dst, &mut copied, input);
+        out.add_input(&dst, new_input);
+    }
+    for state in sys.states.iter().filter(|s| support.contains(&s.symbol)) {
+        if cut.contains(&state.symbol) {
+            continue;
+        }
+        let symbol = copy_expr(ctx, &mut dst, &mut copied, state.symbol);
+        let init = state.init.map(|e| copy_expr(ctx, &mut dst, &mut copied, e));
+        let next = state.next.map(|e| copy_expr(ctx, &mut dst, &mut copied, e));
+        out.add_state(&dst, State { symbol, init, next });
+    }
+
+    // constraints remain meaningful as long as they only talk about signals we kept
+    for &constraint in sys.constraints.iter() {
+        if sequential_support(ctx, sys, constraint, cut).is_subset(&support) {
+            let new_constraint = copy_expr(ctx, &mut dst, &mut copied, constraint);
+            out.constraints.push(new_constraint);
+        }
+    }
+
+    let new_root = copy_expr(ctx, &mut dst, &mut copied, root);
+    let root_name = signal_name(ctx, sys, root).unwrap_or_else(|| "root".to_string());
+    out.add_output(&mut dst, root_name.into(), new_root);
+    if sys.bad_states.contains(&root) {
+        out.bad_states.push(new_root);
+    }
+    (dst, out)
+}
+
+fn signal_name(ctx: &Context, sys: &TransitionSystem, e: ExprRef) -> Option<String> {
+    sys.outputs
+        .iter()
+        .find(|o| o.expr == e)
+        .map(|o| o.name)
+        .or(sys.names[e])
+        .map(|n| ctx[n].clone())
+        .or_else(|| ctx.get_symbol_name(e).map(|n| n.to_string()))
+}
+
+/// All inputs and states that `root` depends on, without looking behind the `cut`.
+fn sequential_support(
+    ctx: &Context,
+    sys: &TransitionSystem,
+    root: ExprRef,
+    cut: &[ExprRef],
+) -> FxHashSet<ExprRef> {
+    let states = sys.state_map();
+    let mut visited = FxHashSet::default();
+    let mut todo = vec![root];
+    while let Some(e) = todo.pop() {
+        if cut.contains(&e) || !visited.insert(e) {
+            continue;
+        }
+        ctx[e].for_each_child(|c| todo.push(*c));
+        if let Some(state) = states.get(&e) {
+            todo.extend(state.init);
+            todo.extend(state.next);
+        }
+    }
+    visited.retain(|e| ctx[*e].is_symbol());
+    visited
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::examples::fifo;
+
+    #[test]
+    fn test_fifo_full_cone() {
+        let (ctx, sys) = fifo(4, 8);
+        let full = sys.lookup_output(&ctx, "full").unwrap();
+        let (cone_ctx, cone) = extract_cone(&ctx, &sys, full);
+        let names = |symbols: Vec<ExprRef>| -> Vec<String> {
+            symbols
+                .into_iter()
+                .map(|s| cone_ctx.get_symbol_name(s).unwrap().to_string())
+                .collect()
+        };
+        // the memory and data input do not influence the full flag
+        assert_eq!(names(cone.inputs.clone()), ["push", "pop"]);
+        assert_eq!(
+            names(cone.states.iter().map(|s| s.symbol).collect()),
+            ["wr", "rd"]
+        );
+        assert!(cone.lookup_output(&cone_ctx, "full").is_some());
+    }
+
+    #[test]
+    fn test_cut_state() {
+        let (ctx, sys) = fifo(4, 8);
+        let full = sys.lookup_output(&ctx, "full").unwrap();
+        let rd = sys.get_state_by_name(&ctx, "rd").unwrap().symbol;
+        let (cone_ctx, cone) = extract_cone_with_cut(&ctx, &sys, full, &[rd]);
+        assert_eq!(cone.states.len(), 1);
+        assert!(cone.lookup_input(&cone_ctx, "rd").is_some());
+        // pop only influences the read pointer
+        assert!(cone.lookup_input(&cone_ctx, "pop").is_none());
+    }
+}
@@ -0,0 +1,126 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+use crate::expr::traversal::{top_down, TraversalCmd};
+use crate::expr::{Context, ExprRef, ForEachChild, TypeCheck, WidthInt};
+use crate::system::TransitionSystem;
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+/// Size and shape of a transition system, e.g., to judge which engine is a good fit.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SystemStats {
+    pub name: String,
+    pub inputs: usize,
+    pub outputs: usize,
+    pub states: usize,
+    pub array_states: usize,
+    pub constraints: usize,
+    pub bad_states: usize,
+    /// number of distinct expressions reachable from any output, state or property
+    pub expr_nodes: usize,
+    /// longest path from a root expression to a leaf
+    pub max_depth: usize,
+    /// number of distinct bit-vector expressions of each width
+    pub widths: BTreeMap<WidthInt, usize>,
+}
+
+impl TransitionSystem {
+    pub fn stats(&self, ctx: &Context) -> SystemStats {
+        let mut nodes = FxHashSet::default();
+        for root in self.get_all_exprs() {
+            top_down(ctx, root, |_, e| {
+                if nodes.insert(e) {
+                    TraversalCmd::Continue
+                } else {
+                    TraversalCmd::Stop
+                }
+            });
+        }
+        // children are always created before their parents
+        let mut sorted: Vec<ExprRef> = nodes.into_iter().collect();
+        sorted.sort();
+        let mut depth: FxHashMap<ExprRef, usize> = FxHashMap::default();
+        let mut widths = BTreeMap::new();
+        for &e in sorted.iter() {
+            let mut d = 0;
+            ctx[e].for_each_child(|c| d = d.max(depth[c]));
+            depth.insert(e, d + 1);
+            if let Some(w) = e.get_bv_type(ctx) {
+                *widths.entry(w).or_default() += 1;
+            }
+        }
+        SystemStats {
+            name: self.name.clone(),
+            inputs: self.inputs.len(),
+            outputs: self.outputs.len(),
+            states: self.states.len(),
+            array_states: self
+                .states
+                .iter()
+                .filter(|s| s.symbol.get_type(ctx).is_array())
+                .count(),
+            constraints: self.constraints.len(),
+            bad_states: self.bad_states.len(),
+            expr_nodes: sorted.len(),
+            max_depth: depth.values().copied().max().unwrap_or(0),
+            widths,
+        }
+    }
+}
+
+impl SystemStats {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("statistics can always be serialized")
+    }
+}
+
+impl Display for SystemStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{:<14}{}", "system", self.name)?;
+        let rows = [
+            ("inputs", self.inputs),
+            ("outputs", self.outputs),
+            ("states", self.states),
+            ("array states", self.array_states),
+            ("constraints", self.constraints),
+            ("bad states", self.bad_states),
+            ("expr nodes", self.expr_nodes),
+            ("max depth", self.max_depth),
+        ];
+        for (name, value) in rows {
+            writeln!(f, "{name:<14}{value:>8}")?;
+        }
+        writeln!(f, "widths")?;
+        for (width, count) in self.widths.iter() {
+            writeln!(f, "  {:<12}{count:>8}", format!("{width} bit"))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::examples::fifo;
+
+    #[test]
+    fn test_fifo_stats() {
+        let (ctx, sys) = fifo(4, 8);
+        let stats = sys.stats(&ctx);
+        assert_eq!(stats.inputs, 3);
+        assert_eq!(stats.states, 3);
+        assert_eq!(stats.array_states, 1);
+        assert_eq!(stats.bad_states, 1);
+        assert!(stats.max_depth > 1);
+        assert!(stats.widths[&1] > 0);
+        assert!(stats.widths[&8] > 0);
+        assert!(stats.expr_nodes >= stats.widths.values().sum());
+        assert!(stats.to_string().contains("array states"));
+        let json: serde_json::Value = serde_json::from_str(&stats.to_json()).unwrap();
+        assert_eq!(json["array_states"], 1);
+    }
+}
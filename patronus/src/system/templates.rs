@@ -0,0 +1,270 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Safety Property Templates
+//! Generates bad states for properties that show up in almost every design from a one line
+//! spec. Signals are looked up by name among inputs, outputs and states.
+//!
+//! | spec                     | property                                  |
+//! |--------------------------|-------------------------------------------|
+//! | `one_hot state`          | exactly one bit of `state` is set         |
+//! | `one_hot0 state`         | at most one bit of `state` is set         |
+//! | `mutex gnt0 gnt1 gnt2`   | at most one of the 1-bit signals is true  |
+//! | `range ptr 2 7`          | `2 <= ptr <= 7`                           |
+//! | `ptr < 8`                | also `<=`, `>` and `>=`, always unsigned  |
+//!
+//! Numbers are decimal or hex with a `0x` prefix.
+
+use super::TransitionSystem;
+use crate::expr::{Context, ExprRef, TypeCheck, WidthInt};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SafetyTemplate {
+    /// unsigned bounds, both inclusive
+    Range {
+        signal: ExprRef,
+        min: Option<u64>,
+        max: Option<u64>,
+    },
+    OneHot {
+        signal: ExprRef,
+        /// `one_hot0`, all bits may be zero
+        allow_zero: bool,
+    },
+    /// at most one of the 1-bit signals is true
+    MutuallyExclusive(Vec<ExprRef>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum TemplateError {
+    #[error("cannot parse property spec `{0}`")]
+    InvalidSpec(String),
+    #[error("unknown signal `{0}`")]
+    UnknownSignal(String),
+    #[error("`{0}` needs to be a bit-vector")]
+    NotBitVector(String),
+    #[error("`{0}` needs to be a 1-bit signal")]
+    NotBoolean(String),
+    #[error("{value} does not fit into the {width} bits of `{signal}`")]
+    ValueOutOfRange {
+        signal: String,
+        value: u64,
+        width: WidthInt,
+    },
+}
+
+impl SafetyTemplate {
+    pub fn parse(ctx: &Context, sys: &TransitionSystem, spec: &str) -> Result<Self, TemplateError> {
+        let invalid = || TemplateError::InvalidSpec(spec.to_string());
+        let names = sys.get_name_map(ctx);
+        let signal = |name: &str| -> Result<(ExprRef, WidthInt), TemplateError> {
+            let e = *names
+                .get(name)
+                .ok_or_else(|| TemplateError::UnknownSignal(name.to_string()))?;
+            let width = e
+                .get_bv_type(ctx)
+                .ok_or_else(|| TemplateError::NotBitVector(name.to_string()))?;
+            Ok((e, width))
+        };
+        let value = |name: &str, width: WidthInt, token: &str| -> Result<u64, TemplateError> {
+            let value = match token.strip_prefix("0x") {
+                Some(hex) => u64::from_str_radix(hex, 16),
+                None => token.parse(),
+            }
+            .map_err(|_| invalid())?;
+            if width < u64::BITS as WidthInt && value >> width != 0 {
+                Err(TemplateError::ValueOutOfRange {
+                    signal: name.to_string(),
+                    value,
+                    width,
+                })
+            } else {
+                Ok(value)
+            }
+        };
+
+        let tokens: Vec<&str> = spec.split_whitespace().collect();
+        match tokens.as_slice() {
+            [kind @ ("one_hot" | "one_hot0"), name] => Ok(SafetyTemplate::OneHot {
+                signal: signal(name)?.0,
+                allow_zero: *kind == "one_hot0",
+            }),
+            ["mutex", names @ ..] if names.len() >= 2 => {
+                let signals = names
+                    .iter()
+                    .map(|name| match signal(name)? {
+                        (e, 1) => Ok(e),
+                        _ => Err(TemplateError::NotBoolean(name.to_string())),
+                    })
+                    .collect::<Result<_, _>>()?;
+                Ok(SafetyTemplate::MutuallyExclusive(signals))
+            }
+            ["range", name, min, max] => {
+                let (signal, width) = signal(name)?;
+                Ok(SafetyTemplate::Range {
+                    signal,
+                    min: Some(value(name, width, min)?),
+                    max: Some(value(name, width, max)?),
+                })
+            }
+            [name, op @ ("<" | "<=" | ">" | ">="), bound] => {
+                let (signal, width) = signal(name)?;
+                let bound = value(name, width, bound)?;
+                let (min, max) = match *op {
+                    "<" => (None, Some(bound.checked_sub(1).ok_or_else(invalid)?)),
+                    "<=" => (None, Some(bound)),
+                    ">" => (Some(bound.checked_add(1).ok_or_else(invalid)?), None),
+                    _ => (Some(bound), None),
+                };
+                Ok(SafetyTemplate::Range { signal, min, max })
+            }
+            _ => Err(invalid()),
+        }
+    }
+
+    /// Returns a 1-bit expression that is true iff the property is violated.
+    pub fn to_bad_state(&self, ctx: &mut Context) -> ExprRef {
+        match self {
+            SafetyTemplate::Range { signal, min, max } => {
+                let width = signal.get_bv_type(ctx).unwrap();
+                let below =
+                    min.map(|min| ctx.build(|c| c.greater(c.bit_vec_val(min, width), *signal)));
+                let above =
+                    max.map(|max| ctx.build(|c| c.greater(*signal, c.bit_vec_val(max, width))));
+                match (below, above) {
+                    (Some(b), Some(a)) => ctx.or(b, a),
+                    (Some(v), None) | (None, Some(v)) => v,
+                    (None, None) => ctx.zero(1),
+                }
+            }
+            SafetyTemplate::OneHot { signal, allow_zero } => {
+                let width = signal.get_bv_type(ctx).unwrap();
+                // `x & (x - 1)` clears the lowest bit that is set
+                let more_than_one = ctx.build(|c| {
+                    let rest = c.and(*signal, c.sub(*signal, c.one(width)));
+                    c.not(c.equal(rest, c.zero(width)))
+                });
+                if *allow_zero {
+                    more_than_one
+                } else {
+                    let none = ctx.build(|c| c.equal(*signal, c.zero(width)));
+                    ctx.or(none, more_than_one)
+                }
+            }
+            SafetyTemplate::MutuallyExclusive(signals) => {
+                let mut bad = ctx.zero(1);
+                for (ii, &a) in signals.iter().enumerate() {
+                    for &b in signals[ii + 1..].iter() {
+                        let both = ctx.and(a, b);
+                        bad = ctx.or(bad, both);
+                    }
+                }
+                bad
+            }
+        }
+    }
+}
+
+/// Parses `spec` and adds the resulting bad state to `sys` under `name`.
+pub fn add_safety_property(
+    ctx: &mut Context,
+    sys: &mut TransitionSystem,
+    name: &str,
+    spec: &str,
+) -> Result<ExprRef, TemplateError> {
+    let template = SafetyTemplate::parse(ctx, sys, spec)?;
+    let bad = template.to_bad_state(ctx);
+    sys.bad_states.push(bad);
+    sys.names[bad] = Some(ctx.string(name.into()));
+    Ok(bad)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mc::{check_exhaustive, ExhaustiveOptions, ModelCheckResult};
+    use crate::system::State;
+
+    /// A one-hot state that rotates, a pointer that counts from 0 to 5 and two grant outputs.
+    fn arbiter() -> (Context, TransitionSystem) {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("arbiter".to_string());
+        let req = ctx.bv_symbol("req", 1);
+        sys.add_input(&ctx, req);
+        let state = ctx.bv_symbol("state", 3);
+        let state_next = ctx.build(|c| c.concat(c.slice(state, 1, 0), c.slice(state, 2, 2)));
+        let ptr = ctx.bv_symbol("ptr", 3);
+        let ptr_next = ctx.build(|c| {
+            c.ite(
+                c.equal(ptr, c.bit_vec_val(5, 3)),
+                c.zero(3),
+                c.add(ptr, c.one(3)),
+            )
+        });
+        for (symbol, init, next) in [(state, 1, state_next), (ptr, 0, ptr_next)] {
+            let init = ctx.bit_vec_val(init, 3);
+            sys.add_state(
+                &ctx,
+                State {
+                    symbol,
+                    init: Some(init),
+                    next: Some(next),
+                },
+            );
+        }
+        let gnt0 = ctx.build(|c| c.and(req, c.slice(state, 0, 0)));
+        let gnt1 = ctx.build(|c| c.and(req, c.slice(state, 1, 1)));
+        sys.add_output(&mut ctx, "gnt0".into(), gnt0);
+        sys.add_output(&mut ctx, "gnt1".into(), gnt1);
+        (ctx, sys)
+    }
+
+    fn holds(spec: &str) -> bool {
+        let (mut ctx, mut sys) = arbiter();
+        add_safety_property(&mut ctx, &mut sys, "prop", spec).unwrap();
+        let res = check_exhaustive(&ctx, &sys, 8, ExhaustiveOptions::default()).unwrap();
+        matches!(res, ModelCheckResult::Success)
+    }
+
+    #[test]
+    fn test_templates() {
+        assert!(holds("one_hot state"));
+        assert!(holds("one_hot0 state"));
+        assert!(!holds("one_hot ptr"));
+        assert!(holds("mutex gnt0 gnt1"));
+        assert!(!holds("mutex gnt0 req"));
+        assert!(holds("ptr < 6"));
+        assert!(!holds("ptr < 5"));
+        assert!(holds("range ptr 0 0x5"));
+        assert!(!holds("ptr >= 1"));
+    }
+
+    #[test]
+    fn test_template_errors() {
+        let (mut ctx, mut sys) = arbiter();
+        let mut add = |spec: &str| add_safety_property(&mut ctx, &mut sys, "prop", spec);
+        assert_eq!(
+            add("one_hot foo"),
+            Err(TemplateError::UnknownSignal("foo".to_string()))
+        );
+        assert_eq!(
+            add("mutex gnt0 state"),
+            Err(TemplateError::NotBoolean("state".to_string()))
+        );
+        assert_eq!(
+            add("ptr < 8"),
+            Err(TemplateError::ValueOutOfRange {
+                signal: "ptr".to_string(),
+                value: 8,
+                width: 3
+            })
+        );
+        assert!(matches!(add("ptr < 0"), Err(TemplateError::InvalidSpec(_))));
+        assert!(matches!(
+            add("mutex gnt0"),
+            Err(TemplateError::InvalidSpec(_))
+        ));
+        assert!(sys.bad_states.is_empty());
+    }
+}
@@ -0,0 +1,377 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Temporal Properties
+//! A small LTL / SVA inspired property language. Properties are compiled into monitor states
+//! and a single bad state, so that every model checker can verify them. Only the safety
+//! fragment is supported: eventually and until need a bound, which makes them bounded
+//! liveness properties.
+//!
+//! The compiler works with obligations: a 1-bit trigger signals that a sub-property has to
+//! hold starting in the current step. Temporal operators delay or extend the trigger with
+//! additional states and report a violation as soon as an obligation cannot be met anymore.
+
+use crate::expr::{Context, ExprRef, TypeCheck};
+use crate::system::{State, TransitionSystem};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Property {
+    /// 1-bit expression that has to be true
+    Atom(ExprRef),
+    Not(Box<Property>),
+    And(Box<Property>, Box<Property>),
+    Or(Box<Property>, Box<Property>),
+    /// SVA's overlapping implication `a |-> b`: whenever `a` holds, `b` needs to hold
+    /// starting in the same step.
+    Implies(Box<Property>, Box<Property>),
+    /// holds in the next step
+    Next(Box<Property>),
+    /// holds in every step
+    Globally(Box<Property>),
+    /// holds in one of the next `within + 1` steps, starting with the current one
+    Eventually {
+        within: u32,
+        body: Box<Property>,
+    },
+    /// `lhs` holds in every step until `rhs` holds, which needs to happen within `within`
+    /// steps
+    Until {
+        lhs: Box<Property>,
+        rhs: Box<Property>,
+        within: u32,
+    },
+}
+
+impl Property {
+    pub fn atom(e: ExprRef) -> Self {
+        Property::Atom(e)
+    }
+    #[allow(clippy::should_implement_trait)]
+    pub fn not(p: Property) -> Self {
+        Property::Not(Box::new(p))
+    }
+    pub fn and(a: Property, b: Property) -> Self {
+        Property::And(Box::new(a), Box::new(b))
+    }
+    pub fn or(a: Property, b: Property) -> Self {
+        Property::Or(Box::new(a), Box::new(b))
+    }
+    pub fn implies(a: Property, b: Property) -> Self {
+        Property::Implies(Box::new(a), Box::new(b))
+    }
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(p: Property) -> Self {
+        Property::Next(Box::new(p))
+    }
+    pub fn globally(p: Property) -> Self {
+        Property::Globally(Box::new(p))
+    }
+    pub fn eventually(within: u32, p: Property) -> Self {
+        Property::Eventually {
+            within,
+            body: Box::new(p),
+        }
+    }
+    pub fn until(lhs: Property, rhs: Property, within: u32) -> Self {
+        Property::Until {
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+            within,
+        }
+    }
+
+    /// Properties without temporal operators can be expressed as a single expression.
+    fn is_propositional(&self) -> bool {
+        match self {
+            Property::Atom(_) => true,
+            Property::Not(p) => p.is_propositional(),
+            Property::And(a, b) | Property::Or(a, b) | Property::Implies(a, b) => {
+                a.is_propositional() && b.is_propositional()
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum PropertyError {
+    #[error("{0:?} is not a 1-bit expression")]
+    NotBoolean(ExprRef),
+    #[error("{0} is only supported on properties without temporal operators")]
+    NotPropositional(&'static str),
+}
+
+/// Adds monitor states for `property` to `sys` together with a bad state that is reached iff
+/// the property is violated. The property needs to hold starting in the first step.
+/// Monitor states are named `{name}_0`, `{name}_1`, ... Returns the bad state expression.
+/// `sys` is left untouched if the property is not supported.
+pub fn add_property(
+    ctx: &mut Context,
+    sys: &mut TransitionSystem,
+    name: &str,
+    property: &Property,
+) -> Result<ExprRef, PropertyError> {
+    let mut monitored = sys.clone();
+    let mut compiler = Compiler {
+        ctx,
+        sys: &mut monitored,
+        name,
+        count: 0,
+    };
+    let one = compiler.ctx.one(1);
+    let zero = compiler.ctx.zero(1);
+    let first = compiler.register(Some(one), zero);
+    let bad = compiler.compile(property, first)?;
+    monitored.bad_states.push(bad);
+    *sys = monitored;
+    Ok(bad)
+}
+
+struct Compiler<'a> {
+    ctx: &'a mut Context,
+    sys: &'a mut TransitionSystem,
+    name: &'a str,
+    count: usize,
+}
+
+impl<'a> Compiler<'a> {
+    /// Creates a 1-bit monitor state. Registers start out as zero, unless `init` is given.
+    fn register(&mut self, init: Option<ExprRef>, next: ExprRef) -> ExprRef {
+        let symbol = self
+            .ctx
+            .bv_symbol(&format!("{}_{}", self.name, self.count), 1);
+        self.count += 1;
+        let init = Some(init.unwrap_or_else(|| self.ctx.zero(1)));
+        self.sys.add_state(
+            self.ctx,
+            State {
+                symbol,
+                init,
+                next: Some(next),
+            },
+        );
+        symbol
+    }
+
+    /// A register whose next state refers to itself is created in two steps.
+    fn feedback_register(
+        &mut self,
+        next: impl FnOnce(&mut Context, ExprRef) -> ExprRef,
+    ) -> ExprRef {
+        let placeholder = self.ctx.zero(1);
+        let symbol = self.register(None, placeholder);
+        let next = next(self.ctx, symbol);
+        self.sys
+            .states
+            .iter_mut()
+            .find(|s| s.symbol == symbol)
+            .unwrap()
+            .next = Some(next);
+        symbol
+    }
+
+    fn delay(&mut self, e: ExprRef) -> ExprRef {
+        self.register(None, e)
+    }
+
+    fn propositional(&mut self, p: &Property) -> Result<ExprRef, PropertyError> {
+        let e = match p {
+            Property::Atom(e) => {
+                if !e.is_bool(self.ctx) {
+                    return Err(PropertyError::NotBoolean(*e));
+                }
+                *e
+            }
+            Property::Not(p) => {
+                let p = self.propositional(p)?;
+                self.ctx.not(p)
+            }
+            Property::And(a, b) => {
+                let (a, b) = (self.propositional(a)?, self.propositional(b)?);
+                self.ctx.and(a, b)
+            }
+            Property::Or(a, b) => {
+                let (a, b) = (self.propositional(a)?, self.propositional(b)?);
+                self.ctx.or(a, b)
+            }
+            Property::Implies(a, b) => {
+                let (a, b) = (self.propositional(a)?, self.propositional(b)?);
+                self.ctx.implies(a, b)
+            }
+            _ => unreachable!("temporal operator"),
+        };
+        Ok(e)
+    }
+
+    fn require_propositional(
+        &mut self,
+        p: &Property,
+        op: &'static str,
+    ) -> Result<ExprRef, PropertyError> {
+        if p.is_propositional() {
+            self.propositional(p)
+        } else {
+            Err(PropertyError::NotPropositional(op))
+        }
+    }
+
+    /// Returns a 1-bit expression that is true in the step in which `p` is found to be
+    /// violated for an obligation started by `trigger`.
+    fn compile(&mut self, p: &Property, trigger: ExprRef) -> Result<ExprRef, PropertyError> {
+        if p.is_propositional() {
+            let e = self.propositional(p)?;
+            return Ok(self.ctx.build(|c| c.and(trigger, c.not(e))));
+        }
+        match p {
+            Property::Atom(_) => unreachable!("atoms are propositional"),
+            Property::Not(_) => Err(PropertyError::NotPropositional("negation")),
+            Property::And(a, b) => {
+                let (a, b) = (self.compile(a, trigger)?, self.compile(b, trigger)?);
+                Ok(self.ctx.or(a, b))
+            }
+            Property::Or(a, b) => {
+                // one side needs to be decided in the current step
+                let (cond, other) = if a.is_propositional() {
+                    (self.propositional(a)?, b)
+                } else if b.is_propositional() {
+                    (self.propositional(b)?, a)
+                } else {
+                    return Err(PropertyError::NotPropositional("one side of a disjunction"));
+                };
+                let trigger = self.ctx.build(|c| c.and(trigger, c.not(cond)));
+                self.compile(other, trigger)
+            }
+            Property::Implies(a, b) => {
+                let a = self.require_propositional(a, "the antecedent of an implication")?;
+                let trigger = self.ctx.and(trigger, a);
+                self.compile(b, trigger)
+            }
+            Property::Next(p) => {
+                let trigger = self.delay(trigger);
+                self.compile(p, trigger)
+            }
+            Property::Globally(p) => {
+                let active = self.feedback_register(|c, active| c.or(active, trigger));
+                let trigger = self.ctx.or(active, trigger);
+                self.compile(p, trigger)
+            }
+            Property::Eventually { within, body } => {
+                let body = self.require_propositional(body, "eventually")?;
+                let always = self.ctx.one(1);
+                Ok(self.bounded_until(trigger, always, body, *within))
+            }
+            Property::Until { lhs, rhs, within } => {
+                let lhs = self.require_propositional(lhs, "the left side of until")?;
+                let rhs = self.require_propositional(rhs, "the right side of until")?;
+                Ok(self.bounded_until(trigger, lhs, rhs, *within))
+            }
+        }
+    }
+
+    /// Keeps one register per step that an obligation has been pending for. Overlapping
+    /// obligations are handled, since each of them is in a different register.
+    fn bounded_until(
+        &mut self,
+        trigger: ExprRef,
+        lhs: ExprRef,
+        rhs: ExprRef,
+        within: u32,
+    ) -> ExprRef {
+        let mut pending = self.ctx.build(|c| c.and(trigger, c.not(rhs)));
+        let mut fail = self.ctx.build(|c| c.and(pending, c.not(lhs)));
+        for _ in 0..within {
+            let keep = self.ctx.build(|c| c.and(pending, lhs));
+            let delayed = self.delay(keep);
+            pending = self.ctx.build(|c| c.and(delayed, c.not(rhs)));
+            let violated = self.ctx.build(|c| c.and(pending, c.not(lhs)));
+            fail = self.ctx.or(fail, violated);
+        }
+        // the deadline has passed
+        self.ctx.or(fail, pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mc::{check_exhaustive, ExhaustiveOptions, ModelCheckResult};
+
+    /// `ack` follows `req` with one step of delay.
+    fn req_ack() -> (Context, TransitionSystem, ExprRef, ExprRef) {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("req_ack".into());
+        let req = ctx.bv_symbol("req", 1);
+        sys.add_input(&ctx, req);
+        let ack = ctx.bv_symbol("ack", 1);
+        let init = ctx.zero(1);
+        sys.add_state(
+            &ctx,
+            State {
+                symbol: ack,
+                init: Some(init),
+                next: Some(req),
+            },
+        );
+        (ctx, sys, req, ack)
+    }
+
+    fn holds(property: impl FnOnce(ExprRef, ExprRef) -> Property) -> bool {
+        let (mut ctx, mut sys, req, ack) = req_ack();
+        add_property(&mut ctx, &mut sys, "prop", &property(req, ack)).unwrap();
+        let res = check_exhaustive(&ctx, &sys, 5, ExhaustiveOptions::default()).unwrap();
+        matches!(res, ModelCheckResult::Success)
+    }
+
+    #[test]
+    fn test_next_and_eventually() {
+        use Property as P;
+        let req_then = |p: fn(Property) -> Property| {
+            move |req, ack| P::globally(P::implies(P::atom(req), p(P::atom(ack))))
+        };
+        assert!(holds(req_then(P::next)));
+        assert!(!holds(req_then(|p| p)));
+        assert!(holds(req_then(|p| P::eventually(1, p))));
+        assert!(!holds(req_then(|p| P::eventually(0, p))));
+    }
+
+    #[test]
+    fn test_globally_and_until() {
+        use Property as P;
+        // without G, a property only needs to hold in the first step
+        assert!(holds(|_, ack| P::not(P::atom(ack))));
+        assert!(!holds(|_, ack| P::globally(P::not(P::atom(ack)))));
+        let req_until_ack = |within| {
+            move |req, ack| {
+                P::globally(P::implies(
+                    P::atom(req),
+                    P::until(P::atom(req), P::atom(ack), within),
+                ))
+            }
+        };
+        assert!(holds(req_until_ack(1)));
+        assert!(!holds(req_until_ack(0)));
+    }
+
+    #[test]
+    fn test_errors() {
+        use Property as P;
+        let (mut ctx, mut sys, req, ack) = req_ack();
+        let eventually_next = P::eventually(2, P::next(P::atom(ack)));
+        assert_eq!(
+            add_property(&mut ctx, &mut sys, "a", &eventually_next),
+            Err(PropertyError::NotPropositional("eventually"))
+        );
+        let wide = ctx.bv_symbol("wide", 8);
+        assert_eq!(
+            add_property(
+                &mut ctx,
+                &mut sys,
+                "b",
+                &P::and(P::atom(req), P::atom(wide))
+            ),
+            Err(PropertyError::NotBoolean(wide))
+        );
+        assert!(sys.states.len() == 1 && sys.bad_states.is_empty());
+    }
+}
@@ -28,6 +28,10 @@ pub fn replace_anonymous_inputs_with_zero(ctx: &mut Context, sys: &mut Transitio
         }
     });
 
+    for (&old, &new) in replace_map.iter() {
+        sys.renames.record(old, new);
+    }
+
     // replace any use of the input with zero
     do_transform(
         ctx,
@@ -42,6 +46,93 @@ pub fn simplify_expressions(ctx: &mut Context, sys: &mut TransitionSystem) {
     do_transform(ctx, sys, ExprTransformMode::FixedPoint, simplify);
 }
 
+/// Sorts the operands of commutative operators and re-associates operator chains,
+/// see [`Canonicalizer`].
+pub fn canonicalize_expressions(ctx: &mut Context, sys: &mut TransitionSystem) {
+    let mut canonicalizer = Canonicalizer::default();
+    sys.update_expressions(|old| Some(canonicalizer.canonicalize(ctx, old)));
+}
+
+/// A state that never changes its initial value and was replaced with a constant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConstantState {
+    pub name: String,
+    pub symbol: ExprRef,
+    pub value: ExprRef,
+}
+
+/// Replaces states that always keep their initial value with that value, e.g., configuration
+/// registers that are only ever written with their own value. States may depend on each other:
+/// we start out by assuming that all states with a literal init value are constant and then
+/// remove all states whose next state function does not simplify to the init value.
+pub fn propagate_constant_states(
+    ctx: &mut Context,
+    sys: &mut TransitionSystem,
+) -> Vec<ConstantState> {
+    let mut constants: FxHashMap<ExprRef, ExprRef> = sys
+        .states
+        .iter()
+        .filter(|s| s.next.is_some())
+        .filter_map(|s| {
+            s.init
+                .filter(|&i| ctx[i].is_bv_lit())
+                .map(|i| (s.symbol, i))
+        })
+        .collect();
+    loop {
+        let mut not_constant = vec![];
+        for state in sys.states.iter() {
+            let Some(&value) = constants.get(&state.symbol) else {
+                continue;
+            };
+            let next = simple_transform_expr(ctx, state.next.unwrap(), |_, e, _| {
+                constants.get(&e).copied()
+            });
+            if simplify_single_expression(ctx, next) != value {
+                not_constant.push(state.symbol);
+            }
+        }
+        if not_constant.is_empty() {
+            break;
+        }
+        for symbol in not_constant {
+            constants.remove(&symbol);
+        }
+    }
+    if constants.is_empty() {
+        return vec![];
+    }
+
+    let report = sys
+        .states
+        .iter()
+        .filter_map(|s| {
+            constants.get(&s.symbol).map(|&value| ConstantState {
+                name: ctx.get_symbol_name(s.symbol).unwrap().to_string(),
+                symbol: s.symbol,
+                value,
+            })
+        })
+        .collect::<Vec<_>>();
+    // the transform also replaces the state symbols, thus we need to remember them
+    let symbols = sys.states.iter().map(|s| s.symbol).collect::<Vec<_>>();
+    do_transform(
+        ctx,
+        sys,
+        ExprTransformMode::FixedPoint,
+        |ctx, expr, children| {
+            constants
+                .get(&expr)
+                .copied()
+                .or_else(|| simplify(ctx, expr, children))
+        },
+    );
+    let mut symbols = symbols.into_iter();
+    sys.states
+        .retain(|_| !constants.contains_key(&symbols.next().unwrap()));
+    report
+}
+
 pub fn do_transform(
     ctx: &mut Context,
     sys: &mut TransitionSystem,
@@ -53,6 +144,41 @@ pub fn do_transform(
     let mut transformed = DenseExprMetaData::default();
     do_transform_expr(ctx, mode, &mut transformed, todo, tran);
 
+    // remember which state and input symbols were replaced
+    let symbols = sys
+        .inputs
+        .iter()
+        .cloned()
+        .chain(sys.states.iter().map(|s| s.symbol))
+        .collect::<Vec<_>>();
+    for old in symbols {
+        let new = if mode == ExprTransformMode::FixedPoint {
+            get_fixed_point(&mut transformed, old)
+        } else {
+            transformed[old]
+        };
+        if let Some(new) = new {
+            sys.renames.record(old, new);
+        }
+    }
+
+    // rewritten sub-expressions are not visible to `update_expressions`
+    if let Some(provenance) = sys.provenance.as_mut() {
+        let changed = transformed
+            .iter()
+            .filter(|(_, new)| new.is_some())
+            .map(|(old, _)| old)
+            .collect::<Vec<_>>();
+        for old in changed {
+            let new = if mode == ExprTransformMode::FixedPoint {
+                get_fixed_point(&mut transformed, old)
+            } else {
+                transformed[old]
+            };
+            provenance.record(old, new.unwrap());
+        }
+    }
+
     // update transition system signals to point to updated expressions
     sys.update_expressions(|old_expr| {
         if mode == ExprTransformMode::FixedPoint {
@@ -62,3 +188,53 @@ pub fn do_transform(
         }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::State;
+
+    #[test]
+    fn test_propagate_constant_states() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let en = ctx.bv_symbol("en", 1);
+        let data = ctx.bv_symbol("data", 8);
+        sys.add_input(&ctx, en);
+        sys.add_input(&ctx, data);
+        // cfg keeps its value
+        let cfg = ctx.bv_symbol("cfg", 8);
+        let cfg_next = ctx.build(|c| c.ite(c.zero(1), data, cfg));
+        // mode is only ever overwritten with cfg, which is 3
+        let mode = ctx.bv_symbol("mode", 8);
+        let mode_next = ctx.build(|c| c.ite(en, cfg, mode));
+        // count changes
+        let count = ctx.bv_symbol("count", 8);
+        let count_next = ctx.build(|c| c.add(count, mode));
+        for (symbol, next) in [(cfg, cfg_next), (mode, mode_next), (count, count_next)] {
+            let init = ctx.bit_vec_val(3, 8);
+            sys.add_state(
+                &ctx,
+                State {
+                    symbol,
+                    init: Some(init),
+                    next: Some(next),
+                },
+            );
+        }
+        sys.add_output(&mut ctx, "out".into(), count);
+
+        let constants = propagate_constant_states(&mut ctx, &mut sys);
+        let names = constants
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["cfg", "mode"]);
+        assert_eq!(sys.states.len(), 1);
+        assert_eq!(
+            sys.states[0].next.unwrap().serialize_to_str(&ctx),
+            "add(count, 8'b00000011)"
+        );
+        assert_eq!(sys.renames.len(), 2);
+    }
+}
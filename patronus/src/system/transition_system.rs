@@ -2,6 +2,7 @@
 // released under BSD 3-Clause License
 // author: Kevin Laeufer <laeufer@berkeley.edu>
 
+use super::{Provenance, SymbolRenames};
 use crate::expr::{Context, ExprMap, ExprRef, SparseExprMap, StringRef};
 use rustc_hash::{FxHashMap, FxHashSet};
 
@@ -36,15 +37,28 @@ pub struct Output {
     pub expr: ExprRef,
 }
 
+/// A named combinational signal. Unlike names of intermediate expressions, defines are roots
+/// of the system which are kept by all transformations.
+#[derive(Debug, Clone, Copy)]
+pub struct Define {
+    pub name: StringRef,
+    pub expr: ExprRef,
+}
+
 #[derive(Debug, Clone)]
 pub struct TransitionSystem {
     pub name: String,
     pub states: Vec<State>,
     pub inputs: Vec<ExprRef>,
     pub outputs: Vec<Output>,
+    pub defines: Vec<Define>,
     pub bad_states: Vec<ExprRef>,
     pub constraints: Vec<ExprRef>,
     pub names: SparseExprMap<Option<StringRef>>,
+    /// symbols that were replaced by transformation passes
+    pub renames: SymbolRenames,
+    /// only recorded if enabled, see [`TransitionSystem::enable_provenance`]
+    pub provenance: Option<Provenance>,
 }
 
 impl TransitionSystem {
@@ -54,9 +68,12 @@ impl TransitionSystem {
             states: Vec::default(),
             inputs: Vec::default(),
             outputs: Vec::default(),
+            defines: Vec::default(),
             bad_states: Vec::default(),
             constraints: Vec::default(),
             names: SparseExprMap::default(),
+            renames: SymbolRenames::default(),
+            provenance: None,
         }
     }
 
@@ -87,6 +104,16 @@ impl TransitionSystem {
         self.outputs.push(Output { name, expr });
     }
 
+    /// Adds a named combinational signal. The name is also recorded for the expression,
+    /// unless it already has one.
+    pub fn add_define(&mut self, ctx: &mut Context, name: std::borrow::Cow<str>, expr: ExprRef) {
+        let name = ctx.string(name);
+        self.defines.push(Define { name, expr });
+        if self.names[expr].is_none() && !ctx[expr].is_symbol() {
+            self.names[expr] = Some(name);
+        }
+    }
+
     pub fn add_state(&mut self, ctx: &Context, state: impl Into<State>) -> StateRef {
         let state = state.into();
         assert!(ctx[state.symbol].is_symbol());
@@ -122,6 +149,14 @@ impl TransitionSystem {
     /// Update all output, input, assume, assert, state expressions.
     /// If `update` returns `None`, no update is performed.
     pub fn update_expressions(&mut self, mut update: impl FnMut(ExprRef) -> Option<ExprRef>) {
+        let provenance = &mut self.provenance;
+        let mut update = |old: ExprRef| {
+            let new = update(old);
+            if let (Some(p), Some(new)) = (provenance.as_mut(), new) {
+                p.record(old, new);
+            }
+            new
+        };
         for old in self.inputs.iter_mut() {
             *old = update(*old).unwrap_or(*old);
         }
@@ -134,6 +169,9 @@ impl TransitionSystem {
         for output in self.outputs.iter_mut() {
             output.expr = update(output.expr).unwrap_or(output.expr);
         }
+        for define in self.defines.iter_mut() {
+            define.expr = update(define.expr).unwrap_or(define.expr);
+        }
         for state in self.states.iter_mut() {
             state.symbol = update(state.symbol).unwrap_or(state.symbol);
             state.init = state.init.and_then(&mut update);
@@ -159,11 +197,16 @@ impl TransitionSystem {
         out
     }
 
-    /// Returns a list of all assume, assert and output expressions.
+    /// Returns a list of all assume, assert, output and define expressions.
     pub fn get_assert_assume_output_exprs(&self) -> Vec<ExprRef> {
-        let mut out =
-            Vec::with_capacity(self.outputs.len() + self.bad_states.len() + self.constraints.len());
+        let mut out = Vec::with_capacity(
+            self.outputs.len()
+                + self.defines.len()
+                + self.bad_states.len()
+                + self.constraints.len(),
+        );
         out.extend(self.outputs.iter().map(|o| o.expr));
+        out.extend(self.defines.iter().map(|d| d.expr));
         out.extend_from_slice(self.bad_states.as_slice());
         out.extend_from_slice(self.constraints.as_slice());
         out
@@ -185,6 +228,7 @@ impl TransitionSystem {
         let mut out = vec![];
         out.extend_from_slice(self.inputs.as_slice());
         out.extend(self.outputs.iter().map(|o| o.expr));
+        out.extend(self.defines.iter().map(|d| d.expr));
         out.extend_from_slice(self.bad_states.as_slice());
         out.extend_from_slice(self.constraints.as_slice());
 
@@ -208,6 +252,9 @@ impl TransitionSystem {
         for out in self.outputs.iter() {
             m.insert(ctx[out.name].to_string(), out.expr);
         }
+        for define in self.defines.iter() {
+            m.insert(ctx[define.name].to_string(), define.expr);
+        }
         for &e in self
             .bad_states
             .iter()
@@ -245,4 +292,12 @@ impl TransitionSystem {
             .find(|&&o| ctx[o.name] == name)
             .map(|o| o.expr)
     }
+
+    /// Returns define by name.
+    pub fn lookup_define(&self, ctx: &Context, name: &str) -> Option<ExprRef> {
+        self.defines
+            .iter()
+            .find(|&&d| ctx[d.name] == name)
+            .map(|d| d.expr)
+    }
 }
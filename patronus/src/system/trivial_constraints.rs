@@ -0,0 +1,144 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Trivial Constraints
+//! A constraint that is always false removes every trace from the system. Since no bad state
+//! can be reached anymore, every property passes without any indication that something is
+//! wrong. A constraint that is always true is harmless, but often points to a mistake in the
+//! frontend or in the testbench that was supposed to restrict the inputs.
+//!
+//! [`find_trivial_constraints`] first simplifies every constraint structurally, which is cheap
+//! and can be done on every import, see
+//! [`ParseOptions::check_constraints`](crate::btor2::ParseOptions). Constraints that do not
+//! simplify to a constant can optionally be checked with a solver. Every constraint is checked
+//! on its own, with states treated as unconstrained.
+
+use super::TransitionSystem;
+use crate::equiv::{prove_equiv, EquivError, EquivOptions, EquivResult};
+use crate::expr::{simplify_single_expression, Context, ExprRef};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Triviality {
+    /// always true, has no effect
+    Tautology,
+    /// always false, no trace satisfies the constraints
+    Contradiction,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrivialConstraint {
+    /// index into the constraints of the system
+    pub index: usize,
+    pub kind: Triviality,
+    /// `false` if a solver was needed to show that the constraint is trivial
+    pub structural: bool,
+}
+
+impl TrivialConstraint {
+    pub fn describe(&self, ctx: &Context, sys: &TransitionSystem) -> String {
+        let constraint = sys.constraints[self.index];
+        let name = sys.names[constraint]
+            .map(|n| ctx[n].clone())
+            .unwrap_or_else(|| format!("constraint{}", self.index));
+        match self.kind {
+            Triviality::Tautology => format!("constraint `{name}` is always true"),
+            Triviality::Contradiction => {
+                format!("constraint `{name}` is always false, thus all properties trivially hold")
+            }
+        }
+    }
+}
+
+/// Finds constraints that are always true or always false. The solver described by `solver`
+/// is only used if structural simplification is inconclusive and if it is not `None`.
+pub fn find_trivial_constraints(
+    ctx: &mut Context,
+    sys: &TransitionSystem,
+    solver: Option<&EquivOptions>,
+) -> Result<Vec<TrivialConstraint>, EquivError> {
+    let mut out = vec![];
+    for (index, &constraint) in sys.constraints.iter().enumerate() {
+        let simplified = simplify_single_expression(ctx, constraint);
+        let structural = if ctx[simplified].is_true() {
+            Some(Triviality::Tautology)
+        } else if ctx[simplified].is_false() {
+            Some(Triviality::Contradiction)
+        } else {
+            None
+        };
+        if let Some(kind) = structural {
+            out.push(TrivialConstraint {
+                index,
+                kind,
+                structural: true,
+            });
+        } else if let Some(opts) = solver {
+            if let Some(kind) = prove_trivial(ctx, simplified, opts)? {
+                out.push(TrivialConstraint {
+                    index,
+                    kind,
+                    structural: false,
+                });
+            }
+        }
+    }
+    Ok(out)
+}
+
+fn prove_trivial(
+    ctx: &mut Context,
+    constraint: ExprRef,
+    opts: &EquivOptions,
+) -> Result<Option<Triviality>, EquivError> {
+    let tru = ctx.one(1);
+    if prove_equiv(ctx, constraint, tru, opts)? == EquivResult::Equivalent {
+        return Ok(Some(Triviality::Tautology));
+    }
+    let fals = ctx.zero(1);
+    if prove_equiv(ctx, constraint, fals, opts)? == EquivResult::Equivalent {
+        return Ok(Some(Triviality::Contradiction));
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_trivial_constraints() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let a = ctx.bv_symbol("a", 4);
+        sys.add_input(&ctx, a);
+        let structural = ctx.build(|c| c.and(c.equal(a, a), c.one(1)));
+        // needs a solver: a < 3 and a > 5 can never be true at the same time
+        let contradiction = ctx.build(|c| {
+            c.and(
+                c.greater(c.bit_vec_val(3, 4), a),
+                c.greater(a, c.bit_vec_val(5, 4)),
+            )
+        });
+        let useful = ctx.build(|c| c.greater(a, c.bit_vec_val(5, 4)));
+        sys.constraints = vec![structural, contradiction, useful];
+
+        let found = find_trivial_constraints(&mut ctx, &sys, None).unwrap();
+        assert_eq!(
+            found,
+            [TrivialConstraint {
+                index: 0,
+                kind: Triviality::Tautology,
+                structural: true,
+            }]
+        );
+
+        let found =
+            find_trivial_constraints(&mut ctx, &sys, Some(&EquivOptions::default())).unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[1].index, 1);
+        assert_eq!(found[1].kind, Triviality::Contradiction);
+        assert!(!found[1].structural);
+        assert!(found[1].describe(&ctx, &sys).contains("always false"));
+    }
+}
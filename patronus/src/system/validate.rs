@@ -0,0 +1,474 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # State Validation
+//! Frontends declare states explicitly, thus the expression graph itself is always acyclic.
+//! However, the init expression of a state may refer to other states. If these references form
+//! a cycle, there is no well defined initial value. The simulator evaluates init expressions
+//! in the order in which states are declared, so a cycle, or a reference to a state that is
+//! declared later, silently produces a wrong value.
+//!
+//! [`validate_states`] re-derives how every state behaves and reports all such problems
+//! together with the expressions involved. It can be enabled while parsing btor2 files
+//! through [`ParseOptions::validate_states`](crate::btor2::ParseOptions).
+//! [`break_cycles`] repairs a system, such that init expressions can always be evaluated in
+//! declaration order.
+//!
+//! The simulator and the SMT encoding evaluate init expressions in [`init_order`]: an init
+//! expression sees the initial value of every state it refers to and the value that an input
+//! has in the first step. Only inside of a cycle the result depends on the declaration order.
+
+use super::{State, TransitionSystem};
+use crate::expr::{simple_transform_expr, Context, ExprRef, ForEachChild, TypeCheck};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StateKind {
+    /// next state depends on the current inputs or states
+    Register,
+    /// next state is always the current state
+    Constant,
+    /// no next state, i.e., the state is assigned an arbitrary value in every step
+    Undriven,
+}
+
+/// States whose init expressions depend on each other.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InitCycle {
+    /// state symbols in declaration order
+    pub states: Vec<ExprRef>,
+    /// the init expressions of `states`
+    pub exprs: Vec<ExprRef>,
+}
+
+impl InitCycle {
+    pub fn describe(&self, ctx: &Context) -> String {
+        let names: Vec<&str> = self
+            .states
+            .iter()
+            .map(|&s| ctx.get_symbol_name(s).unwrap())
+            .collect();
+        format!("cycle in init expressions of {}", names.join(", "))
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateValidation {
+    /// in the same order as the states of the system
+    pub kinds: Vec<StateKind>,
+    pub init_cycles: Vec<InitCycle>,
+    /// `(state, dependency)` pairs where the init expression of `state` refers to a state
+    /// that is initialized later
+    pub out_of_order: Vec<(ExprRef, ExprRef)>,
+}
+
+impl StateValidation {
+    /// True iff all init expressions can be evaluated in declaration order.
+    pub fn is_ok(&self) -> bool {
+        self.init_cycles.is_empty() && self.out_of_order.is_empty()
+    }
+}
+
+pub fn validate_states(ctx: &Context, sys: &TransitionSystem) -> StateValidation {
+    let kinds = sys
+        .states
+        .iter()
+        .map(|s| match s.next {
+            None => StateKind::Undriven,
+            Some(_) if s.is_const() => StateKind::Constant,
+            Some(_) => StateKind::Register,
+        })
+        .collect();
+
+    let deps = init_dependencies(ctx, sys);
+    let init_cycles = find_cycles(sys, &deps);
+    let in_cycle: FxHashSet<ExprRef> = init_cycles
+        .iter()
+        .flat_map(|c| c.states.iter().copied())
+        .collect();
+
+    let mut out_of_order = vec![];
+    for (ii, succ) in deps.iter().enumerate() {
+        if in_cycle.contains(&sys.states[ii].symbol) {
+            continue;
+        }
+        for &jj in succ.iter() {
+            if jj > ii {
+                out_of_order.push((sys.states[ii].symbol, sys.states[jj].symbol));
+            }
+        }
+    }
+
+    StateValidation {
+        kinds,
+        init_cycles,
+        out_of_order,
+    }
+}
+
+/// Finds all groups of states whose init expressions depend on each other.
+pub fn detect_cycles(ctx: &Context, sys: &TransitionSystem) -> Vec<InitCycle> {
+    find_cycles(sys, &init_dependencies(ctx, sys))
+}
+
+fn find_cycles(sys: &TransitionSystem, deps: &[Vec<usize>]) -> Vec<InitCycle> {
+    let mut out: Vec<InitCycle> = strongly_connected(deps)
+        .into_iter()
+        .filter(|component| component.len() > 1 || deps[component[0]].contains(&component[0]))
+        .map(|component| InitCycle {
+            states: component.iter().map(|&ii| sys.states[ii].symbol).collect(),
+            exprs: component
+                .iter()
+                .map(|&ii| sys.states[ii].init.unwrap())
+                .collect(),
+        })
+        .collect();
+    out.sort_by_key(|c| c.states[0]);
+    out
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CycleBreaking {
+    /// fail if there is a cycle
+    Error,
+    /// cut every cycle with a delta-delay state
+    DeltaDelay,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{message}")]
+pub struct CycleError {
+    pub cycles: Vec<InitCycle>,
+    message: String,
+}
+
+/// Reorders states, such that every init expression only refers to states that are initialized
+/// before it. With [`CycleBreaking::DeltaDelay`], a cycle is cut by replacing the first state
+/// of the cycle with a new state `{name}_delta` in the init expressions of the cycle. The
+/// delta-delay state has no init expression, thus it starts out with an arbitrary value, and
+/// afterwards holds the previous value of the state it replaces.
+/// Note that [`StateRef`](super::StateRef)s are invalidated when states get reordered.
+/// Returns the delta-delay states that were inserted.
+pub fn break_cycles(
+    ctx: &mut Context,
+    sys: &mut TransitionSystem,
+    mode: CycleBreaking,
+) -> Result<Vec<ExprRef>, CycleError> {
+    let mut delays = vec![];
+    loop {
+        let cycles = detect_cycles(ctx, sys);
+        if cycles.is_empty() {
+            break;
+        }
+        if mode == CycleBreaking::Error {
+            let message = cycles
+                .iter()
+                .map(|c| c.describe(ctx))
+                .collect::<Vec<_>>()
+                .join("; ");
+            return Err(CycleError { cycles, message });
+        }
+        // cutting one state of each cycle might leave a smaller cycle, which we find in
+        // the next iteration
+        for cycle in cycles {
+            let cut = cycle.states[0];
+            let tpe = cut.get_type(ctx);
+            let name = format!("{}_delta", ctx.get_symbol_name(cut).unwrap());
+            let name = ctx.string(name.into());
+            let delta = ctx.symbol(name, tpe);
+            for state in sys.states.iter_mut() {
+                if cycle.states.contains(&state.symbol) {
+                    state.init = state.init.map(|init| {
+                        simple_transform_expr(ctx, init, |_, e, _| (e == cut).then_some(delta))
+                    });
+                }
+            }
+            sys.add_state(
+                ctx,
+                State {
+                    symbol: delta,
+                    init: None,
+                    next: Some(cut),
+                },
+            );
+            delays.push(delta);
+        }
+    }
+
+    // without cycles, every component is a single state and dependencies come first
+    let order = strongly_connected(&init_dependencies(ctx, sys));
+    let mut states: Vec<Option<State>> = std::mem::take(&mut sys.states)
+        .into_iter()
+        .map(Some)
+        .collect();
+    sys.states = order
+        .into_iter()
+        .map(|component| states[component[0]].take().unwrap())
+        .collect();
+    Ok(delays)
+}
+
+/// Indices of all states in the order in which they need to be initialized: first all states
+/// without an init expression, then every state after the states that its init expression
+/// refers to. States in the same cycle are ordered by declaration, thus the init expression of
+/// the first state in a cycle sees an arbitrary value for the other states.
+pub fn init_order(ctx: &Context, sys: &TransitionSystem) -> Vec<usize> {
+    // components come with their dependencies first
+    let mut order: Vec<usize> = strongly_connected(&init_dependencies(ctx, sys))
+        .into_iter()
+        .flatten()
+        .collect();
+    // stable sort keeps the dependency order of states with an init expression
+    order.sort_by_key(|&ii| sys.states[ii].init.is_some());
+    order
+}
+
+/// For every state, the indices of all states with an init expression that its own init
+/// expression refers to.
+fn init_dependencies(ctx: &Context, sys: &TransitionSystem) -> Vec<Vec<usize>> {
+    let with_init: FxHashMap<ExprRef, usize> = sys
+        .states
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| s.init.is_some())
+        .map(|(ii, s)| (s.symbol, ii))
+        .collect();
+    sys.states
+        .iter()
+        .map(|state| {
+            let mut deps = vec![];
+            if let Some(init) = state.init {
+                let mut visited = FxHashSet::default();
+                let mut todo = vec![init];
+                while let Some(e) = todo.pop() {
+                    if !visited.insert(e) {
+                        continue;
+                    }
+                    if let Some(&ii) = with_init.get(&e) {
+                        deps.push(ii);
+                    }
+                    ctx[e].for_each_child(|&c| todo.push(c));
+                }
+                deps.sort_unstable();
+            }
+            deps
+        })
+        .collect()
+}
+
+/// Tarjan's algorithm without recursion, since systems can have many states.
+/// Components are returned in reverse topological order and every component is sorted.
+fn strongly_connected(succ: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    const UNVISITED: usize = usize::MAX;
+    let mut index = vec![UNVISITED; succ.len()];
+    let mut low = vec![0; succ.len()];
+    let mut on_stack = vec![false; succ.len()];
+    let mut stack = vec![];
+    let mut out = vec![];
+    let mut counter = 0;
+    for root in 0..succ.len() {
+        if index[root] != UNVISITED {
+            continue;
+        }
+        // node and the position of the next successor to visit
+        let mut call = vec![(root, 0)];
+        index[root] = counter;
+        low[root] = counter;
+        counter += 1;
+        stack.push(root);
+        on_stack[root] = true;
+        while let Some(&(v, ii)) = call.last() {
+            if let Some(&w) = succ[v].get(ii) {
+                call.last_mut().unwrap().1 += 1;
+                if index[w] == UNVISITED {
+                    index[w] = counter;
+                    low[w] = counter;
+                    counter += 1;
+                    stack.push(w);
+                    on_stack[w] = true;
+                    call.push((w, 0));
+                } else if on_stack[w] {
+                    low[v] = low[v].min(index[w]);
+                }
+            } else {
+                call.pop();
+                if let Some(&(parent, _)) = call.last() {
+                    low[parent] = low[parent].min(low[v]);
+                }
+                if low[v] == index[v] {
+                    let mut component = vec![];
+                    loop {
+                        let w = stack.pop().unwrap();
+                        on_stack[w] = false;
+                        component.push(w);
+                        if w == v {
+                            break;
+                        }
+                    }
+                    component.sort_unstable();
+                    out.push(component);
+                }
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::{InitKind, Interpreter, Simulator};
+
+    #[test]
+    fn test_validate_states() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let a = ctx.bv_symbol("a", 8);
+        let b = ctx.bv_symbol("b", 8);
+        let c = ctx.bv_symbol("c", 8);
+        let d = ctx.bv_symbol("d", 8);
+        let e = ctx.bv_symbol("e", 8);
+        let a_init = ctx.build(|c| c.add(b, c.one(8)));
+        let b_init = ctx.build(|c| c.not(a));
+        let d_init = ctx.zero(8);
+        let a_next = ctx.build(|c| c.add(a, d));
+        for state in [
+            State {
+                symbol: a,
+                init: Some(a_init),
+                next: Some(a_next),
+            },
+            State {
+                symbol: b,
+                init: Some(b_init),
+                next: Some(b),
+            },
+            // refers to `d` which is only initialized afterwards
+            State {
+                symbol: c,
+                init: Some(d),
+                next: None,
+            },
+            State {
+                symbol: d,
+                init: Some(d_init),
+                next: Some(e),
+            },
+        ] {
+            sys.add_state(&ctx, state);
+        }
+        sys.add_input(&ctx, e);
+
+        let r = validate_states(&ctx, &sys);
+        assert_eq!(
+            r.kinds,
+            [
+                StateKind::Register,
+                StateKind::Constant,
+                StateKind::Undriven,
+                StateKind::Register
+            ]
+        );
+        assert_eq!(
+            r.init_cycles,
+            [InitCycle {
+                states: vec![a, b],
+                exprs: vec![a_init, b_init]
+            }]
+        );
+        assert_eq!(
+            r.init_cycles[0].describe(&ctx),
+            "cycle in init expressions of a, b"
+        );
+        assert_eq!(r.out_of_order, [(c, d)]);
+        assert!(!r.is_ok());
+
+        // without the cycle and with `c` moved to the end, everything is fine
+        sys.states[1].init = None;
+        let c_state = sys.states.remove(2);
+        sys.states.push(c_state);
+        assert!(validate_states(&ctx, &sys).is_ok());
+    }
+
+    #[test]
+    fn test_break_cycles() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let a = ctx.bv_symbol("a", 8);
+        let b = ctx.bv_symbol("b", 8);
+        let a_init = ctx.build(|c| c.add(b, c.one(8)));
+        let b_init = ctx.build(|c| c.not(a));
+        sys.add_state(
+            &ctx,
+            State {
+                symbol: a,
+                init: Some(a_init),
+                next: Some(a),
+            },
+        );
+        sys.add_state(
+            &ctx,
+            State {
+                symbol: b,
+                init: Some(b_init),
+                next: Some(b),
+            },
+        );
+        assert_eq!(detect_cycles(&ctx, &sys).len(), 1);
+        let err = break_cycles(&mut ctx, &mut sys.clone(), CycleBreaking::Error).unwrap_err();
+        assert_eq!(err.to_string(), "cycle in init expressions of a, b");
+
+        let delays = break_cycles(&mut ctx, &mut sys, CycleBreaking::DeltaDelay).unwrap();
+        assert_eq!(delays.len(), 1);
+        let a_delta = delays[0];
+        assert_eq!(ctx.get_symbol_name(a_delta), Some("a_delta"));
+        assert!(detect_cycles(&ctx, &sys).is_empty());
+        assert!(validate_states(&ctx, &sys).is_ok());
+        // `b` now depends on the delta-delay state and needs to be initialized before `a`
+        let symbols: Vec<ExprRef> = sys.states.iter().map(|s| s.symbol).collect();
+        assert_eq!(symbols, [b, a, a_delta]);
+        assert_eq!(sys.states[0].init, Some(ctx.build(|c| c.not(a_delta))));
+
+        let mut sim = Interpreter::new(&ctx, &sys);
+        sim.init(InitKind::Zero);
+        assert_eq!(sim.get(b).try_into_u64().unwrap(), 0xff);
+        assert_eq!(sim.get(a).try_into_u64().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_init_order() {
+        let mut ctx = Context::default();
+        let mut sys = TransitionSystem::new("test".to_string());
+        let a = ctx.bv_symbol("a", 8);
+        let b = ctx.bv_symbol("b", 8);
+        let c = ctx.bv_symbol("c", 8);
+        let i = ctx.bv_symbol("i", 8);
+        sys.add_input(&ctx, i);
+        let b_init = ctx.build(|c| c.add(i, c.one(8)));
+        // `a` refers to `b` which is declared later, `c` has no init expression
+        for (symbol, init) in [(a, Some(b)), (b, Some(b_init)), (c, None)] {
+            sys.add_state(
+                &ctx,
+                State {
+                    symbol,
+                    init,
+                    next: Some(symbol),
+                },
+            );
+        }
+        assert_eq!(init_order(&ctx, &sys), [2, 1, 0]);
+
+        // the init expressions see the value of the input in the first step
+        let mut sim = Interpreter::new(&ctx, &sys);
+        sim.init(InitKind::Zero);
+        assert_eq!(sim.get(a).try_into_u64().unwrap(), 1);
+        sim.set(i, &baa::BitVecValue::from_u64(5, 8)).unwrap();
+        assert_eq!(sim.get(b).try_into_u64().unwrap(), 6);
+        assert_eq!(sim.get(a).try_into_u64().unwrap(), 6);
+        // after the first step, inputs no longer influence the initial state
+        sim.step();
+        sim.set(i, &baa::BitVecValue::from_u64(7, 8)).unwrap();
+        assert_eq!(sim.get(a).try_into_u64().unwrap(), 6);
+    }
+}
@@ -93,6 +93,32 @@ fn parse_sdram_and_simplify_expressions() {
     insta::assert_snapshot!(sys.serialize_to_str(&ctx));
 }
 
+#[test]
+fn parse_sdram_without_signal_names() {
+    let filename = "../inputs/repair/sdram_controller.original.btor";
+    let (ctx, sys) = btor2::parse_file(filename).unwrap();
+    let options = btor2::ParseOptions {
+        skip_signal_names: true,
+        line_count_hint: None,
+        validate_states: false,
+        check_constraints: false,
+    };
+    let mut lean_ctx = Context::default();
+    let lean_sys = btor2::parse_file_with_options(filename, &mut lean_ctx, options).unwrap();
+    // states and inputs keep their names
+    assert_eq!(sys.states.len(), lean_sys.states.len());
+    assert_eq!(sys.inputs.len(), lean_sys.inputs.len());
+    for (a, b) in sys.states.iter().zip(lean_sys.states.iter()) {
+        assert_eq!(
+            ctx.get_symbol_name(a.symbol),
+            lean_ctx.get_symbol_name(b.symbol)
+        );
+    }
+    let name_count =
+        |s: &patronus::system::TransitionSystem| s.names.non_default_value_keys().count();
+    assert!(name_count(&lean_sys) < name_count(&sys));
+}
+
 /// Regression test for a bug that would prevent "_input_0" from being listed as a system input.
 /// The reason was that we did not handle inputs that are also outputs well.
 #[test]
@@ -2,6 +2,7 @@
 // released under BSD 3-Clause License
 // author: Kevin Laeufer <laeufer@berkeley.edu>
 
+use baa::*;
 use patronus::btor2;
 
 #[test]
@@ -40,6 +41,15 @@ fn test_multiple_witnesses() {
     let _wits = btor2::parse_witnesses(&mut MULTIPLE.as_bytes(), 30).unwrap();
 }
 
+#[test]
+fn test_array_input_witness() {
+    let mut wit = btor2::parse_witness(&mut NO_STATE.as_bytes()).unwrap();
+    let mem = ArrayValue::new_sparse(2, &BitVecValue::zero(8));
+    wit.inputs[0][1] = Some(Value::Array(mem));
+    let err = btor2::print_witness(&mut Vec::new(), &wit).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+}
+
 const NO_STATE: &str = r#"
 sat
 b0
@@ -2,10 +2,12 @@
 // released under BSD 3-Clause License
 // author: Kevin Laeufer <laeufer@berkeley.edu>
 
+use baa::BitVecValue;
 use patronus::btor2;
-use patronus::expr::Context;
+use patronus::expr::{Context, ExprError};
 use patronus::sim::Simulator;
-use patronus::sim::{InitKind, Interpreter};
+use patronus::sim::{Backend, EvalOrder, InitKind, Interpreter, SimError};
+use patronus::system::TransitionSystem;
 
 const COUNT_2: &str = r#"
 1 sort bitvec 3
@@ -49,13 +51,13 @@ fn interpret_count_2() {
     let at_three = sim.take_snapshot();
 
     // restore state
-    sim.restore_snapshot(at_one);
+    sim.restore_snapshot(at_one).unwrap();
     assert_eq!(sim.get(counter_state).try_into_u64().unwrap(), 1);
     sim.step();
     assert_eq!(sim.get(counter_state).try_into_u64().unwrap(), 2);
 
     // restore again
-    sim.restore_snapshot(at_three);
+    sim.restore_snapshot(at_three).unwrap();
     assert_eq!(sim.get(counter_state).try_into_u64().unwrap(), 3);
 
     // make bad condition fail
@@ -112,3 +114,300 @@ fn interpret_swap() {
     assert_eq!(sim.get(a).try_into_u64().unwrap(), 0, "a@2");
     assert_eq!(sim.get(b).try_into_u64().unwrap(), 1, "b@2");
 }
+
+#[test]
+fn create_auto_selects_bytecode() {
+    let mut ctx = Context::default();
+    let sys = btor2::parse_str(&mut ctx, COUNT_2, Some("count2")).unwrap();
+    let (mut sim, choice) = patronus::sim::create(&ctx, &sys, Backend::Auto);
+    assert_eq!(choice.requested, Backend::Auto);
+    assert_eq!(choice.selected, Backend::Bytecode);
+    assert_eq!(sim.eval_order(), EvalOrder::Bytecode);
+    sim.init(InitKind::Zero);
+    sim.step();
+    assert_eq!(sim.get(sys.states[0].symbol).try_into_u64().unwrap(), 1);
+}
+
+/// register that samples a memory, array reads cannot be compiled to bytecode
+const READ_MEMORY: &str = r#"
+1 sort bitvec 2
+2 sort bitvec 4
+3 sort array 1 2
+4 state 3 mem
+5 input 1 addr
+6 read 2 4 5
+7 state 2 data
+8 next 2 7 6
+"#;
+
+#[test]
+fn create_auto_falls_back_to_interpreter() {
+    let mut ctx = Context::default();
+    let sys = btor2::parse_str(&mut ctx, READ_MEMORY, Some("read")).unwrap();
+    let (mut sim, choice) = patronus::sim::create(&ctx, &sys, Backend::Auto);
+    assert_eq!(choice.requested, Backend::Auto);
+    assert_eq!(choice.selected, Backend::Interpreter);
+    assert!(
+        choice.reason.contains("cannot be compiled"),
+        "{}",
+        choice.reason
+    );
+    assert_eq!(sim.eval_order(), EvalOrder::Eager);
+    sim.init(InitKind::Zero);
+    sim.set(sys.inputs[0], &BitVecValue::from_u64(1, 2))
+        .unwrap();
+    sim.step();
+    assert_eq!(sim.get(sys.states[1].symbol).try_into_u64().unwrap(), 0);
+}
+
+#[test]
+fn interpret_count_2_perf_counters() {
+    let mut ctx = Context::default();
+    let sys = btor2::parse_str(&mut ctx, COUNT_2, Some("count2")).unwrap();
+    let mut sim = Interpreter::new(&ctx, &sys);
+    assert!(sim.perf_report(4).is_none());
+    sim.enable_perf_counters();
+    sim.init(InitKind::Zero);
+    for _ in 0..5 {
+        sim.step();
+    }
+    let report = sim.perf_report(4).unwrap();
+    assert_eq!(report.steps, 5);
+    // the adder and its constant operand are computed once per step
+    assert_eq!(report.evaluations, 10);
+    assert_eq!(report.hottest.len(), 1);
+    let next = sys.states[0].next.unwrap();
+    assert_eq!(report.hottest[0].0, next);
+    assert_eq!(report.most_evaluated.len(), 2);
+    assert!(report.most_evaluated.contains(&(next, 5)));
+    assert!(report.most_evaluated.iter().all(|(_, n)| *n == 5));
+}
+
+/// counter next to a configuration register that never changes
+const COUNT_WITH_CONFIG: &str = r#"
+1 sort bitvec 3
+2 zero 1
+3 state 1 count
+4 init 1 3 2
+5 one 1
+6 add 1 3 5
+7 next 1 3 6
+8 state 1 config
+9 next 1 8 8
+"#;
+
+#[test]
+fn interpret_skips_constant_states() {
+    let mut ctx = Context::default();
+    let sys = btor2::parse_str(&mut ctx, COUNT_WITH_CONFIG, Some("config")).unwrap();
+    let config = sys.get_state_by_name(&ctx, "config").unwrap().symbol;
+    let mut sim = Interpreter::new(&ctx, &sys);
+    sim.enable_perf_counters();
+    sim.init(InitKind::Zero);
+    sim.set(config, &BitVecValue::from_u64(5, 3)).unwrap();
+    for _ in 0..5 {
+        sim.step();
+    }
+    // only the counter is evaluated, the configuration keeps its value
+    assert_eq!(sim.perf_report(4).unwrap().evaluations, 10);
+    assert_eq!(sim.get(config).try_into_u64().unwrap(), 5);
+}
+
+/// free running counter and a register that samples an input
+const COUNT_AND_HOLD: &str = r#"
+1 sort bitvec 3
+2 input 1 in
+3 state 1 count
+4 one 1
+5 add 1 3 4
+6 next 1 3 5
+7 state 1 hold
+8 next 1 7 2
+"#;
+
+#[test]
+fn interpret_sparse_evaluation() {
+    let mut ctx = Context::default();
+    let sys = btor2::parse_str(&mut ctx, COUNT_AND_HOLD, Some("hold")).unwrap();
+    let input = sys.inputs[0];
+    let count = sys.get_state_by_name(&ctx, "count").unwrap().symbol;
+    let hold = sys.get_state_by_name(&ctx, "hold").unwrap().symbol;
+    let mut sim = Interpreter::new(&ctx, &sys);
+    assert!(sim.activity_stats().is_none());
+    sim.enable_sparse_evaluation();
+    sim.init(InitKind::Zero);
+    sim.set(input, &BitVecValue::from_u64(3, 3)).unwrap();
+    // the register only needs to be updated when the input changes
+    for _ in 0..3 {
+        sim.step();
+    }
+    assert_eq!(sim.get(hold).try_into_u64().unwrap(), 3);
+    sim.set(input, &BitVecValue::from_u64(4, 3)).unwrap();
+    sim.step();
+    assert_eq!(sim.get(hold).try_into_u64().unwrap(), 4);
+    assert_eq!(sim.get(count).try_into_u64().unwrap(), 4);
+    let stats = sim.activity_stats().unwrap();
+    assert_eq!(stats.evaluated, 6);
+    assert_eq!(stats.skipped, 2);
+}
+
+#[test]
+fn interpret_delay_set_get_many() {
+    let (ctx, sys) = btor2::parse_file("../inputs/unittest/delay.btor").unwrap();
+    let reg0 = sys.get_state_by_name(&ctx, "reg0").unwrap().symbol;
+    let reg1 = sys.get_state_by_name(&ctx, "reg1").unwrap().symbol;
+    let mut sim = Interpreter::new(&ctx, &sys);
+    sim.init(InitKind::Zero);
+
+    let one = BitVecValue::from_u64(1, 8);
+    sim.set_many(&[(reg0, (&one).into()), (reg1, (&one).into())])
+        .unwrap();
+    let values = sim.get_many(&[reg0, reg1]);
+    assert_eq!(values, vec![Some(one.clone()), Some(one)]);
+}
+
+#[test]
+fn interpret_delay_errors() {
+    let (ctx, sys) = btor2::parse_file("../inputs/unittest/delay.btor").unwrap();
+    let reg0 = sys.get_state_by_name(&ctx, "reg0").unwrap().symbol;
+    let not_a_state = sys.states[0].next.unwrap();
+    let mut sim = Interpreter::new(&ctx, &sys);
+    sim.init(InitKind::Zero);
+
+    assert_eq!(
+        sim.set(reg0, &BitVecValue::from_u64(1, 4)),
+        Err(SimError::WidthMismatch {
+            expected: 8,
+            actual: 4
+        })
+    );
+    assert_eq!(
+        sim.set(not_a_state, &BitVecValue::from_u64(1, 8)),
+        Err(SimError::UnknownSymbol(not_a_state))
+    );
+    assert!(sim.restore_snapshot(5).is_err());
+}
+
+#[test]
+fn interpret_alu_two_phase_update() {
+    let (ctx, sys) = patronus::examples::alu(8);
+    let a = patronus::examples::input(&ctx, &sys, "a");
+    let out = sys.lookup_output(&ctx, "out").unwrap();
+    let mut sim = Interpreter::new(&ctx, &sys);
+    sim.init(InitKind::Zero);
+    sim.enable_two_phase_update();
+
+    sim.set(a, &BitVecValue::from_u64(3, 8)).unwrap();
+    // the output only changes after the update
+    assert_eq!(sim.get(a).try_into_u64().unwrap(), 3);
+    assert_eq!(sim.get(out).try_into_u64().unwrap(), 0);
+    sim.update();
+    assert_eq!(sim.get(out).try_into_u64().unwrap(), 3);
+
+    let stale = sim.stale_reads();
+    assert_eq!(stale.len(), 1);
+    assert_eq!(stale[0].expr, out);
+    assert_eq!(stale[0].step, 0);
+}
+
+#[test]
+fn interpret_count_2_eager_matches_lazy() {
+    let mut ctx = Context::default();
+    let sys = btor2::parse_str(&mut ctx, COUNT_2, Some("count2")).unwrap();
+    let counter_state = sys.states[0].symbol;
+    let bad = sys.bad_states[0];
+    let mut lazy = Interpreter::new(&ctx, &sys);
+    let mut eager = Interpreter::with_eval_order(&ctx, &sys, EvalOrder::Eager);
+    assert_eq!(eager.eval_order(), EvalOrder::Eager);
+    lazy.init(InitKind::Zero);
+    eager.init(InitKind::Zero);
+    for _ in 0..10 {
+        for e in [counter_state, bad] {
+            assert_eq!(
+                lazy.get(e).try_into_u64().unwrap(),
+                eager.get(e).try_into_u64().unwrap()
+            );
+        }
+        lazy.step();
+        eager.step();
+    }
+
+    // values are still correct directly after a `set`
+    eager
+        .set(counter_state, &BitVecValue::from_u64(7, 3))
+        .unwrap();
+    assert_eq!(eager.get(bad).try_into_u64().unwrap(), 1);
+    eager.step();
+    assert_eq!(eager.get(counter_state).try_into_u64().unwrap(), 0);
+
+    eager.set_eval_order(EvalOrder::Lazy);
+    assert_eq!(eager.eval_order(), EvalOrder::Lazy);
+    assert_eq!(eager.get(bad).try_into_u64().unwrap(), 0);
+}
+
+#[test]
+fn interpret_count_2_force_and_release() {
+    let mut ctx = Context::default();
+    let sys = btor2::parse_str(&mut ctx, COUNT_2, Some("count2")).unwrap();
+    let counter_state = sys.states[0].symbol;
+    let next = sys.states[0].next.unwrap();
+    let bad = sys.bad_states[0];
+    for order in [EvalOrder::Lazy, EvalOrder::Eager] {
+        let mut sim = Interpreter::with_eval_order(&ctx, &sys, order);
+        sim.init(InitKind::Zero);
+        sim.step();
+
+        // a forced state ignores its next state function
+        sim.force(counter_state, &BitVecValue::from_u64(7, 3))
+            .unwrap();
+        assert_eq!(sim.get(bad).try_into_u64().unwrap(), 1);
+        sim.step();
+        sim.step();
+        assert_eq!(sim.get(counter_state).try_into_u64().unwrap(), 7);
+        // assignments are ignored while forced
+        sim.set(counter_state, &BitVecValue::from_u64(2, 3))
+            .unwrap();
+        assert_eq!(sim.get(counter_state).try_into_u64().unwrap(), 7);
+
+        // the released state keeps its value until the next step
+        sim.release(counter_state);
+        assert_eq!(sim.forced().count(), 0);
+        assert_eq!(sim.get(counter_state).try_into_u64().unwrap(), 7);
+        sim.step();
+        assert_eq!(sim.get(counter_state).try_into_u64().unwrap(), 0);
+
+        // internal signals can be forced as well
+        sim.force(next, &BitVecValue::from_u64(3, 3)).unwrap();
+        sim.step();
+        sim.step();
+        assert_eq!(sim.get(counter_state).try_into_u64().unwrap(), 3);
+        sim.release(next);
+        sim.step();
+        assert_eq!(sim.get(counter_state).try_into_u64().unwrap(), 4);
+
+        assert_eq!(
+            sim.force(counter_state, &BitVecValue::from_u64(1, 4)),
+            Err(SimError::WidthMismatch {
+                expected: 3,
+                actual: 4
+            })
+        );
+    }
+}
+
+#[test]
+fn interpret_uninterpreted_function() {
+    let mut ctx = Context::default();
+    let a = ctx.bv_symbol("a", 8);
+    let f = ctx.function("f", 1, 8);
+    let f_a = ctx.apply(f, &[a]);
+    let mut sys = TransitionSystem::new("uf".to_string());
+    sys.add_input(&ctx, a);
+    sys.add_output(&mut ctx, "out".into(), f_a);
+
+    assert!(matches!(
+        Interpreter::try_new(&ctx, &sys),
+        Err(ExprError::UninterpretedFunction(name)) if name == "f"
+    ));
+    assert!(Interpreter::try_new(&ctx, &TransitionSystem::new("empty".to_string())).is_ok());
+}
@@ -17,16 +17,69 @@ struct Args {
     #[arg(
         long,
         value_enum,
-        default_value = "bitwuzla",
-        help = "the SMT solver to use"
+        help = "the SMT solver to use, overrides the config file [default: bitwuzla]"
     )]
-    solver: SolverChoice,
+    solver: Option<SolverChoice>,
+    #[arg(long, help = "TOML file with engine settings")]
+    config: Option<std::path::PathBuf>,
     #[arg(short, long)]
     verbose: bool,
     #[arg(short, long)]
     dump_smt: bool,
-    #[arg(value_name = "BTOR2", index = 1)]
-    filename: String,
+    #[arg(
+        long,
+        help = "save every solver query into the `queries` directory, see the smt-replay tool"
+    )]
+    log_queries: bool,
+    #[arg(long, help = "abort checking after the given number of seconds")]
+    timeout: Option<u64>,
+    #[arg(
+        long = "abstract",
+        help = "replace multipliers and dividers with uninterpreted functions and refine on spurious counterexamples"
+    )]
+    abstract_datapath: bool,
+    #[arg(
+        long,
+        help = "bit-blast the system and use the embedded SAT solver instead of an SMT solver"
+    )]
+    sat: bool,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "write the bit-blasted query for the maximum bound in DIMACS format, with a variable order hint, and exit"
+    )]
+    dimacs: Option<std::path::PathBuf>,
+    #[arg(
+        long,
+        help = "run a registered engine by name instead, e.g., one provided by a plugin"
+    )]
+    engine: Option<String>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "write a JSON result record with status, bounds and timings"
+    )]
+    json: Option<std::path::PathBuf>,
+    #[arg(
+        long,
+        value_name = "TASKS",
+        help = "run all tasks of a JSON file and print their result records instead"
+    )]
+    batch: Option<std::path::PathBuf>,
+    #[arg(
+        long,
+        requires = "batch",
+        help = "run every batch task in its own process, enforcing memory limits"
+    )]
+    isolate: bool,
+    #[arg(long, hide = true)]
+    batch_worker: bool,
+    #[arg(
+        value_name = "BTOR2",
+        index = 1,
+        required_unless_present_any = ["batch", "batch_worker"]
+    )]
+    filename: Option<String>,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
@@ -37,22 +90,52 @@ pub enum SolverChoice {
 
 fn main() {
     let args = Args::parse();
-    let (mut ctx, sys) = btor2::parse_file(&args.filename).expect("Failed to load btor2 file!");
+    if args.batch_worker {
+        mc::run_task_worker(&mut std::io::stdin(), &mut std::io::stdout())
+            .expect("Failed to run batch task!");
+        return;
+    }
+    if let Some(path) = &args.batch {
+        run_batch(path, args.isolate);
+        return;
+    }
+    let filename = args.filename.as_ref().expect("required by clap");
+    let config = args
+        .config
+        .as_ref()
+        .map(|c| config::Config::load(c).expect("Failed to load config file!"))
+        .unwrap_or_default();
+    let mut ctx = Context::default();
+    let parse_options = btor2::ParseOptions {
+        check_constraints: true,
+        ..Default::default()
+    };
+    let sys = btor2::parse_file_with_options(filename, &mut ctx, parse_options)
+        .expect("Failed to load btor2 file!");
     if args.verbose {
         println!("Loaded: {}", sys.name);
         println!("{}", sys.serialize_to_str(&ctx));
         println!();
         println!();
     }
-    let k_max = 25;
+    let k_max = config.bmc.max_bound;
+    if let Some(path) = &args.dimacs {
+        let cnf = mc::encode_bmc(&ctx, &sys, k_max).expect("Failed to bit-blast system!");
+        let mut out = std::fs::File::create(path).expect("Failed to create DIMACS file!");
+        let order = patronus::sat::variable_order(&cnf);
+        cnf.write_dimacs_with_order(&mut out, &order).unwrap();
+        return;
+    }
     let checker_opts = mc::SmtModelCheckerOptions {
         check_constraints: true,
         check_bad_states_individually: true,
         save_smt_replay: args.dump_smt,
+        log_queries: args.log_queries,
     };
     let solver = match args.solver {
-        SolverChoice::Bitwuzla => BITWUZLA,
-        SolverChoice::Yices2 => YICES2,
+        Some(SolverChoice::Bitwuzla) => BITWUZLA,
+        Some(SolverChoice::Yices2) => YICES2,
+        None => config.solver.solver(),
     };
     if args.verbose {
         println!(
@@ -61,7 +144,59 @@ fn main() {
         );
     }
     let checker = mc::SmtModelChecker::new(solver, checker_opts);
-    let res = checker.check(&mut ctx, &sys, k_max).unwrap();
+    let token = args
+        .timeout
+        .or(config.bmc.timeout_secs)
+        .map(|t| mc::CancellationToken::with_timeout(std::time::Duration::from_secs(t)))
+        .unwrap_or_default();
+    let mut progress = PrintProgress {
+        verbose: args.verbose,
+    };
+    let start = std::time::Instant::now();
+    let engine_name = match (&args.engine, args.sat) {
+        (Some(name), _) => name.as_str(),
+        (None, true) => "sat-bmc",
+        (None, false) if args.abstract_datapath => "smt-cegar",
+        (None, false) => "smt-bmc",
+    };
+    let res = if let Some(name) = &args.engine {
+        let engine = {
+            let registry = plugin::registry();
+            registry
+                .engine(name)
+                .unwrap_or_else(|e| panic!("{e}, available: {:?}", registry.engine_names()))
+        };
+        engine.check(&mut ctx, &sys, k_max, &token).unwrap()
+    } else if args.sat {
+        mc::check_with_sat(&ctx, &sys, k_max).expect("Failed to bit-blast system!")
+    } else if args.abstract_datapath {
+        let run = checker
+            .check_with_abstraction(&mut ctx, &sys, k_max, &mc::CegarOptions::default())
+            .unwrap();
+        if args.verbose {
+            println!(
+                "{} spurious counterexamples, refined: {:?}",
+                run.spurious, run.refined
+            );
+        }
+        run.result
+    } else {
+        checker
+            .check_with_progress(&mut ctx, &sys, k_max, &token, &mut progress)
+            .unwrap()
+    };
+    if let Some(path) = &args.json {
+        let record = mc::ResultRecord::from_model_check(
+            mc::CheckKind::Bmc,
+            engine_name,
+            &sys.name,
+            &res,
+            k_max,
+        )
+        .with_time("check", start.elapsed())
+        .with_config(&config.bmc);
+        std::fs::write(path, record.to_json()).expect("Failed to write result record!");
+    }
     match res {
         mc::ModelCheckResult::Success => {
             println!("unsat");
@@ -69,5 +204,34 @@ fn main() {
         mc::ModelCheckResult::Fail(wit) => {
             btor2::print_witness(&mut std::io::stdout(), &wit).unwrap();
         }
+        mc::ModelCheckResult::Cancelled(k) => {
+            if args.verbose {
+                println!("Timeout after checking {k} steps.");
+            }
+            println!("unknown");
+        }
+    }
+}
+
+fn run_batch(path: &std::path::Path, isolate: bool) {
+    let tasks = std::fs::read_to_string(path).expect("Failed to read batch file!");
+    let tasks = mc::BatchTask::parse_list(&tasks).expect("Invalid batch file!");
+    let isolation = isolate.then(|| {
+        let exe = std::env::current_exe().expect("Failed to locate the bmc executable!");
+        mc::Isolation::new(exe, &["--batch-worker"])
+    });
+    let records = mc::run_batch(&tasks, isolation.as_ref());
+    println!("{}", mc::ResultRecord::list_to_json(&records));
+}
+
+struct PrintProgress {
+    verbose: bool,
+}
+
+impl mc::ProgressObserver for PrintProgress {
+    fn bound_checked(&mut self, k: u64, solver_time: std::time::Duration) {
+        if self.verbose {
+            println!("Checked bound {k} in {solver_time:?}");
+        }
     }
 }
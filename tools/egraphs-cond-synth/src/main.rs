@@ -8,18 +8,19 @@
 mod features;
 mod samples;
 mod summarize;
+mod tighten;
 
 use crate::features::*;
 use crate::samples::*;
 use crate::summarize::bdd_summarize;
+use crate::tighten::tighten_condition;
 use baa::BitVecOps;
 use clap::Parser;
 use patronus::expr::*;
 use patronus::mc::get_smt_value;
 use patronus::smt::{CheckSatResponse, SolverContext};
 use patronus_egraphs::*;
-use rand::rngs::StdRng;
-use rand::{Rng, SeedableRng};
+use rand::Rng;
 use std::io::Write;
 use std::path::{Path, PathBuf};
 
@@ -43,6 +44,11 @@ struct Args {
         help = "display up to N false negatives when checking conditions"
     )]
     show_false_negatives: Option<usize>,
+    #[arg(
+        long,
+        help = "suggests how to strengthen the condition if it admits unequivalent rewrites"
+    )]
+    tighten: bool,
     #[arg(long, help = "disable multi-threading")]
     single_thread: bool,
     #[arg(long, help = "write the generated assignments to a JSON file")]
@@ -118,6 +124,10 @@ fn main() {
         check_conditions(rule, &samples, &rule_info, args.show_false_negatives);
     }
 
+    if args.tighten {
+        suggest_tightening(rule, &samples, &rule_info);
+    }
+
     if let Some(out_filename) = args.write_assignments {
         let mut file = std::fs::File::create(&out_filename).expect("failed to open output JSON");
         samples
@@ -245,6 +255,11 @@ fn check_conditions(
     samples: &Samples,
     info: &RuleInfo,
     show_false_negatives: Option<usize>,
+    #[arg(
+        long,
+        help = "suggests how to strengthen the condition if it admits unequivalent rewrites"
+    )]
+    tighten: bool,
 ) {
     // false positive => our current condition says it is equivalent, while it actually is not
     let mut false_positive = 0u64;
@@ -294,6 +309,33 @@ fn check_conditions(
     }
 }
 
+fn suggest_tightening(rule: &ArithRewrite, samples: &Samples, info: &RuleInfo) {
+    let report = tighten_condition(rule, info, samples);
+    let Some(minimal) = report.minimal else {
+        println!("The condition does not admit any unequivalent rewrite.");
+        return;
+    };
+    println!(
+        "The condition admits {} unequivalent rewrites, the smallest one is:",
+        report.unsound
+    );
+    show_assignments(rule, info, &[minimal], 1, CheckSatResponse::Sat);
+    let current = rule
+        .condition()
+        .map_or("true".to_string(), |c| c.to_string());
+    match report.strengthening {
+        Some(strengthening) => {
+            println!("Weakest strengthening consistent with all samples:");
+            println!("  ({current}) && ({strengthening})");
+            println!(
+                "  rules out {} assignments that are currently equivalent",
+                report.lost
+            );
+        }
+        None => println!("Failed to find a strengthening from the candidate constraints."),
+    }
+}
+
 fn show_assignments(
     rule: &ArithRewrite,
     info: &RuleInfo,
@@ -301,7 +343,9 @@ fn show_assignments(
     num_samples: usize,
     expected: CheckSatResponse,
 ) {
-    let mut rnd = StdRng::seed_from_u64(0);
+    let seed = patronus::random::default_seed();
+    println!("Sampling assignments with seed {seed}");
+    let mut rnd = patronus::random::new_rng(seed);
     let mut ctx = Context::default();
     let mut smt_ctx = start_solver(false);
     for _ in 0..num_samples {
@@ -189,7 +189,7 @@ impl From<SamplesSerde> for Samples {
 }
 
 impl Samples {
-    fn new(rule: &RuleInfo) -> Self {
+    pub fn new(rule: &RuleInfo) -> Self {
         let vars = rule.assignment_vars().collect();
         let assignments = vec![];
         let is_equivalent = vec![];
@@ -199,7 +199,7 @@ impl Samples {
             is_equivalent,
         }
     }
-    fn add(&mut self, a: Assignment, is_equivalent: bool) {
+    pub fn add(&mut self, a: Assignment, is_equivalent: bool) {
         debug_assert_eq!(a.len(), self.vars.len());
         for ((a_var, a_value), &our_var) in a.into_iter().zip(self.vars.iter()) {
             assert_eq!(a_var, our_var);
@@ -346,8 +346,7 @@ impl RuleInfo {
         }
     }
 
-    #[allow(dead_code)]
-    fn iter_assignments(&self, max_width: WidthInt) -> impl Iterator<Item = Assignment> + '_ {
+    pub fn iter_assignments(&self, max_width: WidthInt) -> impl Iterator<Item = Assignment> + '_ {
         AssignmentIter {
             rule: self,
             index: 0,
@@ -0,0 +1,196 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+use crate::samples::{RuleInfo, Samples};
+use egg::Var;
+use patronus::expr::WidthInt;
+use patronus_egraphs::*;
+
+/// Result of comparing the condition of a rule against checked samples.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TighteningReport {
+    /// number of assignments that satisfy the condition, but are not equivalent
+    pub unsound: usize,
+    /// the unsound assignment with the smallest widths
+    pub minimal: Option<Assignment>,
+    /// constraint to conjoin with the current condition, `None` if the condition is sound
+    /// or no combination of candidate constraints excludes all unsound assignments
+    pub strengthening: Option<WidthConstraint>,
+    /// number of equivalent assignments that the strengthened condition no longer covers
+    pub lost: usize,
+}
+
+/// Suggests the weakest strengthening of the rule condition that excludes every sample on
+/// which the current condition claims equivalence, while the SMT solver found a difference.
+/// Candidates are compared atoms over the width and sign variables of the rule. Atoms are
+/// picked greedily, preferring those that keep the most equivalent samples.
+pub fn tighten_condition(
+    rule: &ArithRewrite,
+    info: &RuleInfo,
+    samples: &Samples,
+) -> TighteningReport {
+    let mut unsound = vec![];
+    let mut sound = vec![];
+    for (a, is_eq) in samples.iter() {
+        if rule.eval_condition(&a) {
+            if is_eq {
+                sound.push(a);
+            } else {
+                unsound.push(a);
+            }
+        }
+    }
+    let minimal = unsound
+        .iter()
+        .min_by_key(|a| (a.iter().map(|(_, v)| *v).sum::<WidthInt>(), values(a)))
+        .cloned();
+    let num_unsound = unsound.len();
+    let num_sound = sound.len();
+
+    let max_width = samples
+        .iter()
+        .flat_map(|(a, _)| a.into_iter().map(|(_, v)| v))
+        .max()
+        .unwrap_or(1);
+    let candidates = candidate_atoms(info, max_width);
+    let mut atoms = vec![];
+    while !unsound.is_empty() {
+        let holds = |c: &WidthConstraint, a: &Assignment| c.eval(|v| lookup(a, v)).unwrap_or(false);
+        let scored = candidates.iter().map(|c| {
+            let excluded = unsound.iter().filter(|a| !holds(c, a)).count();
+            let kept = sound.iter().filter(|a| holds(c, a)).count();
+            (excluded == unsound.len(), excluded, kept, c)
+        });
+        // among atoms that exclude everything, the weakest wins, ties go to the first atom
+        let best = scored
+            .filter(|(_, excluded, _, _)| *excluded > 0)
+            .rev()
+            .max_by_key(|&(all, excluded, kept, _)| {
+                if all {
+                    (true, kept, excluded)
+                } else {
+                    (false, excluded, kept)
+                }
+            });
+        let Some((_, _, _, atom)) = best else {
+            break;
+        };
+        unsound.retain(|a| holds(atom, a));
+        sound.retain(|a| holds(atom, a));
+        atoms.push(atom.clone());
+    }
+
+    let strengthening = if unsound.is_empty() {
+        atoms.into_iter().reduce(|a, b| a.and(b))
+    } else {
+        None
+    };
+    let lost = if strengthening.is_some() {
+        num_sound - sound.len()
+    } else {
+        0
+    };
+    TighteningReport {
+        unsound: num_unsound,
+        minimal,
+        strengthening,
+        lost,
+    }
+}
+
+fn values(a: &Assignment) -> Vec<WidthInt> {
+    a.iter().map(|(_, v)| *v).collect()
+}
+
+fn lookup(a: &Assignment, var: Var) -> Option<WidthInt> {
+    a.iter().find(|(k, _)| *k == var).map(|(_, v)| *v)
+}
+
+/// Atoms similar to the features of the ROVER paper, but expressed as width constraints
+/// such that they can be added to a rule.
+fn candidate_atoms(info: &RuleInfo, max_width: WidthInt) -> Vec<WidthConstraint> {
+    use WidthExpr::{Add, Const, LeftShift, MaxPlus1};
+    let cmp = |a: WidthExpr, op, b: WidthExpr| WidthConstraint::Cmp(a, op, b);
+    let var = WidthExpr::Var;
+    let widths: Vec<Var> = info.widths().collect();
+    let mut out = vec![];
+    for sign in info.signs() {
+        out.push(cmp(var(sign), CmpOp::Eq, Const(0)));
+        out.push(cmp(var(sign), CmpOp::Eq, Const(1)));
+    }
+    for &a in widths.iter() {
+        for &b in widths.iter().filter(|&&b| b != a) {
+            if a < b {
+                out.push(cmp(var(a), CmpOp::Eq, var(b)));
+            }
+            out.push(cmp(var(a), CmpOp::Le, var(b)));
+            out.push(cmp(var(a), CmpOp::Lt, var(b)));
+            out.push(cmp(Add(var(a).into(), Const(1).into()), CmpOp::Lt, var(b)));
+        }
+    }
+    for &a in widths.iter() {
+        for &b in widths.iter().filter(|&&b| b != a) {
+            for &o in widths.iter().filter(|&&o| o != a && o != b) {
+                if a < b {
+                    out.push(cmp(Add(var(a).into(), var(b).into()), CmpOp::Le, var(o)));
+                    out.push(cmp(
+                        MaxPlus1(var(a).into(), var(b).into()),
+                        CmpOp::Le,
+                        var(o),
+                    ));
+                }
+                out.push(cmp(
+                    LeftShift(var(a).into(), var(b).into()),
+                    CmpOp::Le,
+                    var(o),
+                ));
+            }
+        }
+    }
+    for &a in widths.iter() {
+        for c in 2..=max_width {
+            out.push(cmp(var(a), CmpOp::Ge, Const(c)));
+        }
+        for c in 1..max_width {
+            out.push(cmp(var(a), CmpOp::Le, Const(c)));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::samples::get_rule_info;
+
+    #[test]
+    fn test_tighten_missing_condition() {
+        let rule = ArithRewrite::try_new(
+            "commute-add",
+            "(+ ?wo ?wa ?sa ?a ?wb ?sb ?b)",
+            "(+ ?wo ?wb ?sb ?b ?wa ?sa ?a)",
+            None,
+            &[],
+            &[],
+        )
+        .unwrap();
+        let info = get_rule_info(&rule);
+        let wa: Var = "?wa".parse().unwrap();
+        let wo: Var = "?wo".parse().unwrap();
+        // pretend that the rule is only correct if the result is at least as wide as `a`
+        let mut samples = Samples::new(&info);
+        for a in info.iter_assignments(4) {
+            let is_eq = lookup(&a, wa) <= lookup(&a, wo);
+            samples.add(a, is_eq);
+        }
+
+        let report = tighten_condition(&rule, &info, &samples);
+        assert!(report.unsound > 0);
+        let minimal = report.minimal.unwrap();
+        assert_eq!(lookup(&minimal, wa), Some(2));
+        assert_eq!(lookup(&minimal, wo), Some(1));
+        assert_eq!(report.strengthening.unwrap().to_string(), "?wa <= ?wo");
+        assert_eq!(report.lost, 0);
+    }
+}
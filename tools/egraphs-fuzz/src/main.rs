@@ -0,0 +1,105 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+use baa::BitVecOps;
+use clap::Parser;
+use patronus::expr::WidthInt;
+use patronus::random::default_seed;
+use patronus_egraphs::*;
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+#[command(name = "patronus-egraphs-fuzz")]
+#[command(author = "Kevin Laeufer <laeufer@cornell.edu>")]
+#[command(version)]
+#[command(about = "Checks the semantics of the e-graph rewrite rules with random widths and values.", long_about = None)]
+struct Args {
+    #[arg(long, default_value = "8", value_parser = clap::value_parser!(WidthInt).range(1..))]
+    max_width: WidthInt,
+    #[arg(
+        long,
+        default_value = "256",
+        help = "width assignments per rule and round"
+    )]
+    assignments: usize,
+    #[arg(long, default_value = "8", help = "operand values per assignment")]
+    values: usize,
+    #[arg(
+        long,
+        help = "seed of the first round, every round uses the next seed; replays a round when combined with --rounds 1"
+    )]
+    seed: Option<u64>,
+    #[arg(
+        long,
+        default_value = "0",
+        help = "number of rounds, 0 runs until interrupted or all rules failed"
+    )]
+    rounds: u64,
+    #[arg(long, help = "only check the named rule, may be repeated")]
+    rule: Vec<String>,
+    #[arg(
+        long,
+        default_value = "fuzz-failures",
+        help = "directory into which failing cases are written as Rust tests"
+    )]
+    out: PathBuf,
+}
+
+fn main() {
+    let args = Args::parse();
+    let all = create_rewrites();
+    let mut rules: Vec<ArithRewrite> = if args.rule.is_empty() {
+        all
+    } else {
+        args.rule
+            .iter()
+            .map(|name| match all.iter().find(|r| r.name() == name) {
+                Some(r) => r.clone(),
+                None => {
+                    let available = all.iter().map(|r| r.name()).collect::<Vec<_>>();
+                    eprintln!("Unknown rule `{name}`. Available rules are: {available:?}");
+                    std::process::exit(1);
+                }
+            })
+            .collect()
+    };
+
+    let first_seed = args.seed.unwrap_or_else(default_seed);
+    let mut failures = 0;
+    let mut round = 0;
+    while !rules.is_empty() && (args.rounds == 0 || round < args.rounds) {
+        let seed = first_seed.wrapping_add(round);
+        let opts = FuzzOptions {
+            max_width: args.max_width,
+            assignments: args.assignments,
+            values: args.values,
+            seed,
+        };
+        let report = fuzz_rewrites(&rules, &opts);
+        let checked: usize = report.checked.iter().map(|(_, n)| n).sum();
+        println!("round {round} (seed {seed}): {checked} instances checked");
+        for mismatch in report.mismatches.iter() {
+            failures += 1;
+            let test_name = format!("{}_seed_{seed}", mismatch.rule.replace('-', "_"));
+            std::fs::create_dir_all(&args.out).expect("failed to create output directory");
+            let filename = args.out.join(format!("{test_name}.rs"));
+            std::fs::write(&filename, mismatch.to_rust_test(&test_name))
+                .expect("failed to write test case");
+            println!(
+                "{}: lhs = {}, rhs = {} with {:?}, written to {}",
+                mismatch.rule,
+                mismatch.lhs.to_bit_str(),
+                mismatch.rhs.to_bit_str(),
+                mismatch.assignment,
+                filename.display()
+            );
+            // every rule is only reported once
+            rules.retain(|r| r.name() != mismatch.rule);
+        }
+        round += 1;
+    }
+    if failures > 0 {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,407 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+//! # Simulation Server
+//! Drives the patronus interpreter through JSON-RPC 2.0 requests, one JSON object per line,
+//! either on a TCP socket, a local Unix socket or on stdin and stdout. Clients are served one
+//! after the other and every client starts with a fresh simulator.
+//!
+//! When serving a socket, the server acts as a daemon for edit-compile-check loops: parsed
+//! designs are kept in memory across clients and a design is only parsed again once its file
+//! changed, see [`DesignCache`].
+//!
+//! | method     | params                          | result                                 |
+//! |------------|---------------------------------|----------------------------------------|
+//! | `load`     | `path` of a btor2 file          | name, inputs, outputs and states       |
+//! | `info`     |                                 | same as `load`                         |
+//! | `init`     | optional `seed` or `random`     | seed used for random initialization    |
+//! | `set`      | `name`, `value`                 | `null`                                 |
+//! | `get`      | `name`                          | `value` as hex string and `width`      |
+//! | `step`     | optional `count`, defaults to 1 | `step_count`                           |
+//! | `snapshot` |                                 | `id`                                   |
+//! | `restore`  | `id`                            | `null`                                 |
+//! | `names`    | optional `scope`                | signals and instances inside `scope`   |
+//! | `shutdown` |                                 | `null`, then the server exits          |
+//!
+//! Names are resolved hierarchically, see [`NameIndex`]. Values can be numbers or strings in
+//! the syntax of stimulus files, i.e., decimal or hexadecimal and binary with a `0x` or `0b`
+//! prefix.
+
+use baa::{BitVecOps, Value};
+use clap::Parser;
+use patronus::btor2::DesignCache;
+use patronus::expr::{Context, ExprError, ExprRef, TypeCheck};
+use patronus::sim::{parse_value, InitKind, Interpreter, Simulator};
+use patronus::system::{NameIndex, TransitionSystem};
+use serde_json::{json, Value as Json};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+#[derive(Parser, Debug)]
+#[command(name = "sim-server")]
+#[command(author = "Kevin Laeufer <laeufer@cornell.edu>")]
+#[command(version)]
+#[command(about = "Exposes the patronus simulator over a JSON-RPC socket protocol.", long_about = None)]
+struct Args {
+    #[arg(long, default_value = "127.0.0.1:7878", help = "address to listen on")]
+    listen: String,
+    #[cfg(unix)]
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with = "stdio",
+        help = "listen on a local Unix socket instead of a TCP address"
+    )]
+    socket: Option<std::path::PathBuf>,
+    #[arg(
+        long,
+        help = "serve a single client on stdin and stdout instead of a socket"
+    )]
+    stdio: bool,
+    #[arg(
+        value_name = "BTOR2",
+        index = 1,
+        help = "design that every client starts out with"
+    )]
+    filename: Option<String>,
+}
+
+fn main() {
+    let args = Args::parse();
+    let mut cache = DesignCache::new();
+    if args.stdio {
+        let stdin = std::io::stdin().lock();
+        serve(stdin, std::io::stdout(), args.filename, &mut cache).expect("failed to communicate");
+        return;
+    }
+    #[cfg(unix)]
+    if let Some(path) = &args.socket {
+        use std::os::unix::fs::FileTypeExt;
+        use std::os::unix::net::{UnixListener, UnixStream};
+        // a socket that is left over from a previous daemon prevents binding
+        if std::fs::metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+            std::fs::remove_file(path).expect("failed to remove stale socket");
+        }
+        let listener = UnixListener::bind(path).expect("failed to bind to socket");
+        eprintln!("Listening on {}", path.display());
+        let incoming = listener.incoming();
+        serve_clients(incoming, UnixStream::try_clone, &args.filename, &mut cache);
+        let _ = std::fs::remove_file(path);
+        return;
+    }
+    let listener = TcpListener::bind(&args.listen).expect("failed to bind to address");
+    eprintln!("Listening on {}", args.listen);
+    let incoming = listener.incoming();
+    serve_clients(incoming, TcpStream::try_clone, &args.filename, &mut cache);
+}
+
+/// Serves one client after the other until one of them requests a shutdown.
+fn serve_clients<S: Read + Write>(
+    incoming: impl Iterator<Item = std::io::Result<S>>,
+    try_clone: impl Fn(&S) -> std::io::Result<S>,
+    design: &Option<String>,
+    cache: &mut DesignCache,
+) {
+    for stream in incoming {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("Failed to accept connection: {e}");
+                continue;
+            }
+        };
+        let reader = BufReader::new(try_clone(&stream).expect("failed to clone socket"));
+        match serve(reader, stream, design.clone(), cache) {
+            Ok(Shutdown::Yes) => break,
+            Ok(Shutdown::No) => {}
+            Err(e) => eprintln!("Connection failed: {e}"),
+        }
+    }
+}
+
+enum Shutdown {
+    Yes,
+    No,
+}
+
+/// What the client asked for after it stopped sending requests for the current design.
+enum Next {
+    Load { id: Json, path: String },
+    Disconnect,
+    Shutdown,
+}
+
+/// Serves one client until it disconnects or requests a shutdown.
+fn serve(
+    mut input: impl BufRead,
+    mut output: impl Write,
+    design: Option<String>,
+    cache: &mut DesignCache,
+) -> std::io::Result<Shutdown> {
+    // the design that is loaded on start up is not requested by the client and thus has no reply
+    let mut pending = design.map(|path| (None, path));
+    loop {
+        let next = match pending.take() {
+            None => serve_requests(&mut input, &mut output, None)?,
+            Some((id, path)) => {
+                let design = cache.load(&path);
+                let session = match design.as_deref() {
+                    Some((ctx, sys)) => {
+                        Session::new(ctx, sys).map_err(|e| format!("cannot simulate `{path}`: {e}"))
+                    }
+                    None => Err(format!("failed to load `{path}`")),
+                };
+                match session {
+                    Ok(mut session) => {
+                        if let Some(id) = id {
+                            respond(&mut output, id, Ok(session.info()))?;
+                        }
+                        serve_requests(&mut input, &mut output, Some(&mut session))?
+                    }
+                    Err(msg) => {
+                        match id {
+                            Some(id) => respond(&mut output, id, Err(RpcError::server(msg)))?,
+                            None => eprintln!("{msg}"),
+                        }
+                        serve_requests(&mut input, &mut output, None)?
+                    }
+                }
+            }
+        };
+        match next {
+            Next::Load { id, path } => pending = Some((Some(id), path)),
+            Next::Disconnect => return Ok(Shutdown::No),
+            Next::Shutdown => return Ok(Shutdown::Yes),
+        }
+    }
+}
+
+/// Answers requests until a new design is loaded or the client is done.
+fn serve_requests(
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    mut session: Option<&mut Session>,
+) -> std::io::Result<Next> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(Next::Disconnect);
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request: Json = match serde_json::from_str(&line) {
+            Ok(request) => request,
+            Err(e) => {
+                respond(output, Json::Null, Err(RpcError::parse(e.to_string())))?;
+                continue;
+            }
+        };
+        let id = request.get("id").cloned().unwrap_or(Json::Null);
+        let params = request.get("params").cloned().unwrap_or(Json::Null);
+        let Some(method) = request.get("method").and_then(Json::as_str) else {
+            respond(output, id, Err(RpcError::invalid_request()))?;
+            continue;
+        };
+        let result = match method {
+            "load" => match str_param(&params, "path") {
+                Ok(path) => {
+                    let path = path.to_string();
+                    return Ok(Next::Load { id, path });
+                }
+                Err(e) => Err(e),
+            },
+            "shutdown" => {
+                respond(output, id, Ok(Json::Null))?;
+                return Ok(Next::Shutdown);
+            }
+            _ => match session.as_deref_mut() {
+                Some(session) => session.handle(method, &params),
+                None => Err(RpcError::server("no design loaded".to_string())),
+            },
+        };
+        respond(output, id, result)?;
+    }
+}
+
+fn respond(
+    output: &mut impl Write,
+    id: Json,
+    result: Result<Json, RpcError>,
+) -> std::io::Result<()> {
+    let response = match result {
+        Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+        Err(e) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": {"code": e.code, "message": e.message}
+        }),
+    };
+    writeln!(output, "{response}")?;
+    output.flush()
+}
+
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn parse(message: String) -> Self {
+        Self {
+            code: -32700,
+            message,
+        }
+    }
+
+    fn invalid_request() -> Self {
+        Self {
+            code: -32600,
+            message: "request needs a `method`".to_string(),
+        }
+    }
+
+    fn unknown_method(method: &str) -> Self {
+        Self {
+            code: -32601,
+            message: format!("unknown method `{method}`"),
+        }
+    }
+
+    fn invalid_params(message: String) -> Self {
+        Self {
+            code: -32602,
+            message,
+        }
+    }
+
+    fn server(message: String) -> Self {
+        Self {
+            code: -32000,
+            message,
+        }
+    }
+}
+
+fn str_param<'a>(params: &'a Json, name: &str) -> Result<&'a str, RpcError> {
+    params
+        .get(name)
+        .and_then(Json::as_str)
+        .ok_or_else(|| RpcError::invalid_params(format!("missing string parameter `{name}`")))
+}
+
+fn u64_param(params: &Json, name: &str) -> Result<Option<u64>, RpcError> {
+    match params.get(name) {
+        None | Some(Json::Null) => Ok(None),
+        Some(value) => value.as_u64().map(Some).ok_or_else(|| {
+            RpcError::invalid_params(format!("`{name}` needs to be a non-negative integer"))
+        }),
+    }
+}
+
+/// A loaded design and its simulator.
+struct Session<'a> {
+    ctx: &'a Context,
+    sys: &'a TransitionSystem,
+    sim: Interpreter<'a>,
+    names: NameIndex,
+}
+
+impl<'a> Session<'a> {
+    fn new(ctx: &'a Context, sys: &'a TransitionSystem) -> Result<Self, ExprError> {
+        let mut sim = Interpreter::try_new(ctx, sys)?;
+        sim.init(InitKind::Zero);
+        Ok(Self {
+            ctx,
+            sys,
+            sim,
+            names: NameIndex::new(ctx, sys),
+        })
+    }
+
+    fn handle(&mut self, method: &str, params: &Json) -> Result<Json, RpcError> {
+        match method {
+            "info" => Ok(self.info()),
+            "init" => {
+                let kind = match u64_param(params, "seed")? {
+                    Some(seed) => InitKind::Random(seed),
+                    None if params.get("random").and_then(Json::as_bool) == Some(true) => {
+                        InitKind::random()
+                    }
+                    None => InitKind::Zero,
+                };
+                self.sim.init(kind);
+                Ok(json!({"seed": kind.seed()}))
+            }
+            "set" => {
+                let name = str_param(params, "name")?;
+                let expr = self.resolve(name)?;
+                let width = expr.get_bv_type(self.ctx).ok_or_else(|| {
+                    RpcError::invalid_params(format!("`{name}` is not a bit-vector"))
+                })?;
+                let value = match params.get("value") {
+                    Some(Json::String(s)) => parse_value(s, width),
+                    Some(Json::Number(n)) => parse_value(&n.to_string(), width),
+                    _ => None,
+                }
+                .ok_or_else(|| {
+                    RpcError::invalid_params(format!("invalid value for bv<{width}> `{name}`"))
+                })?;
+                self.sim
+                    .set(expr, &value)
+                    .map_err(|e| RpcError::server(e.to_string()))?;
+                Ok(Json::Null)
+            }
+            "get" => {
+                let name = str_param(params, "name")?;
+                match self.sim.get(self.resolve(name)?) {
+                    Value::BitVec(value) => Ok(json!({
+                        "value": format!("0x{}", value.to_hex_str()),
+                        "width": value.width(),
+                    })),
+                    Value::Array(_) => Err(RpcError::invalid_params(format!(
+                        "`{name}` is an array, only bit-vectors can be read"
+                    ))),
+                }
+            }
+            "step" => {
+                for _ in 0..u64_param(params, "count")?.unwrap_or(1) {
+                    self.sim.step();
+                }
+                Ok(json!({"step_count": self.sim.step_count()}))
+            }
+            "snapshot" => Ok(json!({"id": self.sim.take_snapshot()})),
+            "restore" => {
+                let id = u64_param(params, "id")?
+                    .ok_or_else(|| RpcError::invalid_params("missing `id`".to_string()))?;
+                self.sim
+                    .restore_snapshot(id as u32)
+                    .map_err(|e| RpcError::server(e.to_string()))?;
+                Ok(Json::Null)
+            }
+            "names" => {
+                let scope = params.get("scope").and_then(Json::as_str).unwrap_or("");
+                Ok(json!(self.names.children(scope)))
+            }
+            _ => Err(RpcError::unknown_method(method)),
+        }
+    }
+
+    fn resolve(&self, name: &str) -> Result<ExprRef, RpcError> {
+        self.names
+            .resolve(name)
+            .map_err(|e| RpcError::invalid_params(e.to_string()))
+    }
+
+    fn info(&self) -> Json {
+        let name = |e| self.ctx.get_symbol_name(e).unwrap();
+        json!({
+            "name": self.sys.name,
+            "inputs": self.sys.inputs.iter().map(|&i| name(i)).collect::<Vec<_>>(),
+            "outputs": self.sys.outputs.iter().map(|o| &self.ctx[o.name]).collect::<Vec<_>>(),
+            "states": self.sys.states.iter().map(|s| name(s.symbol)).collect::<Vec<_>>(),
+        })
+    }
+}
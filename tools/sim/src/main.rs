@@ -33,8 +33,19 @@ struct Args {
         help = "initialization strategy"
     )]
     init: Init,
+    #[arg(
+        long,
+        help = "seed for random initialization, defaults to the PATRONUS_SEED environment variable"
+    )]
+    seed: Option<u64>,
+    #[arg(long, help = "TOML file with simulator settings")]
+    config: Option<std::path::PathBuf>,
     #[arg(long, help = "Filename of a testbench.")]
     testbench: Option<String>,
+    #[arg(long, help = "CSV or JSON file with input values to play back")]
+    stimulus: Option<std::path::PathBuf>,
+    #[arg(long, help = "records all applied inputs to a CSV or JSON file")]
+    record: Option<std::path::PathBuf>,
     #[arg(value_name = "BTOR2", index = 1)]
     filename: String,
 }
@@ -47,6 +58,11 @@ pub enum Init {
 
 fn main() {
     let args = Args::parse();
+    let config = args
+        .config
+        .as_ref()
+        .map(|c| config::Config::load(c).expect("Failed to load config file!"))
+        .unwrap_or_default();
     let (ctx, sys) = btor2::parse_file(&args.filename).expect("Failed to load btor2 file!");
     if args.verbose {
         println!("Loaded: {}", sys.name);
@@ -55,30 +71,76 @@ fn main() {
         println!();
     }
 
+    if let Err(e) = check_evaluable(&ctx, sys.get_all_exprs()) {
+        eprintln!("Cannot simulate {}: {e}", sys.name);
+        std::process::exit(1);
+    }
+
     // start execution
     let start_load = std::time::Instant::now();
     let mut sim = if args.trace_instructions {
         Interpreter::new_with_trace(&ctx, &sys)
     } else {
-        Interpreter::new(&ctx, &sys)
+        let (sim, choice) = sim::create(&ctx, &sys, config.sim.backend);
+        if args.verbose {
+            println!("Using {:?} backend: {}", choice.selected, choice.reason);
+        }
+        sim
+    };
+    // only an explicitly requested interpreter uses the configured evaluation order
+    let eval_order = if args.trace_instructions || config.sim.backend == Backend::Interpreter {
+        config.sim.eval_order
+    } else {
+        sim.eval_order()
     };
+    if let Some(dir) = &config.sim.program_cache {
+        sim.set_program_cache(Some(ProgramCache::new(dir)));
+        // compile again, this time through the cache
+        sim.set_eval_order(eval_order);
+    } else if eval_order != sim.eval_order() {
+        sim.set_eval_order(eval_order);
+    }
 
     if args.show_programs {
-        println!("The interpreter no longer compiles to an internal program representation");
-        //sim.print_programs();
+        match sim.bytecode_len() {
+            Some(len) => println!("Executing {len} bytecode instructions per step"),
+            None => println!("Set `eval_order = \"bytecode\"` to compile the design"),
+        }
     }
 
-    match args.init {
-        Init::Zero => {
-            sim.init(InitKind::Zero);
-        }
-        Init::Random => {
-            sim.init(InitKind::Random(123));
-        }
+    let init = match args.init {
+        Init::Zero => InitKind::Zero,
+        Init::Random => InitKind::Random(
+            args.seed
+                .or(config.sim.seed)
+                .unwrap_or_else(random::default_seed),
+        ),
+    };
+    if let Some(seed) = init.seed() {
+        println!("Randomly initializing with seed {seed}");
     }
+    sim.init(init);
     let delta_load = std::time::Instant::now() - start_load;
     println!("Loaded the design into the interpreter in {:?}", delta_load);
 
+    if let Some(stimulus) = &args.stimulus {
+        let stimulus = Stimulus::load(stimulus).expect("Failed to load stimulus file");
+        let mut recorder = StimulusRecorder::new(&ctx, &sys);
+        let start_exec = std::time::Instant::now();
+        stimulus
+            .play(&ctx, &sys, &mut sim, |_, sim| recorder.record(sim))
+            .expect("Failed to play back stimulus");
+        let delta_exec = std::time::Instant::now() - start_exec;
+        println!("Executed {} steps in {:?}", sim.step_count(), delta_exec);
+        if let Some(record) = &args.record {
+            recorder
+                .finish()
+                .save(record)
+                .expect("Failed to save recording");
+        }
+        return;
+    }
+
     let testbench_file = match args.testbench {
         None => {
             println!("No testbench provided. Exiting...");
@@ -111,6 +173,7 @@ fn main() {
         signals_to_print.sort_by_key(|(name, _)| name.clone());
     }
 
+    let mut recorder = StimulusRecorder::new(&ctx, &sys);
     let start_exec = std::time::Instant::now();
     for (step_id, line) in tb.lines().flatten().enumerate() {
         do_step(
@@ -120,10 +183,18 @@ fn main() {
             &inputs,
             &outputs,
             &signals_to_print,
+            init.seed(),
+            &mut recorder,
         );
     }
     let delta_exec = std::time::Instant::now() - start_exec;
     println!("Executed {} steps in {:?}", sim.step_count(), delta_exec);
+    if let Some(record) = &args.record {
+        recorder
+            .finish()
+            .save(record)
+            .expect("Failed to save recording");
+    }
 }
 
 type IOInfo = Vec<(usize, ExprRef, String, WidthInt)>;
@@ -154,6 +225,7 @@ fn read_header(
 }
 
 /// Reads one line in the CSV, applies inputs, checks outputs and finally steps the system.
+#[allow(clippy::too_many_arguments)]
 fn do_step(
     step_id: usize,
     sim: &mut impl Simulator,
@@ -161,6 +233,8 @@ fn do_step(
     inputs: &[(usize, ExprRef, String, WidthInt)],
     outputs: &[(usize, ExprRef, String, WidthInt)],
     signal_to_print: &[(String, ExprRef)],
+    seed: Option<u64>,
+    recorder: &mut StimulusRecorder,
 ) {
     // apply inputs
     let mut input_iter = inputs.iter();
@@ -172,7 +246,8 @@ fn do_step(
                 if trimmed.to_ascii_lowercase() != "x" {
                     let value = u64::from_str_radix(trimmed, 10).unwrap();
                     let width = input.3;
-                    sim.set(input.1, &BitVecValue::from_u64(value, width));
+                    sim.set(input.1, &BitVecValue::from_u64(value, width))
+                        .expect("failed to apply input");
                 }
 
                 // get next input
@@ -185,6 +260,8 @@ fn do_step(
         }
     }
 
+    recorder.record(sim);
+
     // print values if the option is enabled
     if !signal_to_print.is_empty() {
         println!();
@@ -207,7 +284,7 @@ fn do_step(
                     let width = output.3;
                     let expected = BitVecValue::from_str_radix(trimmed, 10, width).unwrap();
                     let actual: BitVecValue = sim.get(output.1).try_into().unwrap();
-                    assert_eq!(expected, actual, "{}@{step_id}", output.2);
+                    assert_eq!(expected, actual, "{}@{step_id} (seed: {seed:?})", output.2);
                 }
 
                 // get next output
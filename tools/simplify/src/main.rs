@@ -58,6 +58,7 @@ fn simplify<T: ExprMap<Option<ExprRef>>>(
 ) -> SmtCommand {
     match cmd {
         SmtCommand::Assert(e) => SmtCommand::Assert(s.simplify(ctx, e)),
+        SmtCommand::AssertForAll(bound, e) => SmtCommand::AssertForAll(bound, s.simplify(ctx, e)),
         SmtCommand::DefineConst(sym, value) => SmtCommand::DefineConst(sym, s.simplify(ctx, value)),
         SmtCommand::CheckSatAssuming(e) => {
             SmtCommand::CheckSatAssuming(e.into_iter().map(|e| s.simplify(ctx, e)).collect())
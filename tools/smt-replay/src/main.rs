@@ -0,0 +1,82 @@
+// Copyright 2024 Cornell University
+// released under BSD 3-Clause License
+// author: Kevin Laeufer <laeufer@cornell.edu>
+
+use clap::{Parser, ValueEnum};
+use patronus::smt::*;
+
+#[derive(Parser, Debug)]
+#[command(name = "smt-replay")]
+#[command(author = "Kevin Laeufer <laeufer@cornell.edu>")]
+#[command(version)]
+#[command(about = "Replays a log of SMT queries against another solver and compares the results.", long_about = None)]
+struct Args {
+    #[arg(
+        long,
+        value_enum,
+        default_value = "bitwuzla",
+        help = "the SMT solver to replay with"
+    )]
+    solver: SolverChoice,
+    #[arg(
+        long,
+        help = "only report queries that are this many times slower than in the log"
+    )]
+    slowdown: Option<f64>,
+    #[arg(
+        value_name = "DIR",
+        index = 1,
+        help = "directory created with `--log-queries`"
+    )]
+    dir: std::path::PathBuf,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum SolverChoice {
+    Bitwuzla,
+    Yices2,
+}
+
+fn main() {
+    let args = Args::parse();
+    let solver = match args.solver {
+        SolverChoice::Bitwuzla => BITWUZLA,
+        SolverChoice::Yices2 => YICES2,
+    };
+    let queries = read_query_log(&args.dir).expect("Failed to read query log!");
+    let mut disagreements = 0;
+    for query in queries.iter() {
+        let name = query.path.file_name().unwrap().to_string_lossy();
+        let replay = match replay_query(&solver, query) {
+            Ok(replay) => replay,
+            Err(e) => {
+                println!("{name}: {} failed: {e}", solver.name());
+                disagreements += 1;
+                continue;
+            }
+        };
+        let disagrees = replay.disagrees_with(query);
+        disagreements += disagrees as usize;
+        let slowdown = replay.time.as_secs_f64() / query.time.as_secs_f64().max(1e-6);
+        let is_slow = args.slowdown.is_some_and(|limit| slowdown >= limit);
+        if disagrees || is_slow || args.slowdown.is_none() {
+            println!(
+                "{name}: {} said {:?} in {:?}, {} said {:?} in {:?}{}",
+                query.solver,
+                query.result,
+                query.time,
+                solver.name(),
+                replay.result,
+                replay.time,
+                if disagrees { " MISMATCH" } else { "" }
+            );
+        }
+    }
+    println!(
+        "Replayed {} queries, {disagreements} disagreement(s).",
+        queries.len()
+    );
+    if disagreements > 0 {
+        std::process::exit(1);
+    }
+}
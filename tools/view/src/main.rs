@@ -4,7 +4,7 @@
 
 use clap::Parser;
 use patronus::expr::SerializableIrNode;
-use patronus::system::transform::{replace_anonymous_inputs_with_zero, simplify_expressions};
+use patronus::system::{PassConfig, PassManager};
 
 #[derive(Parser, Debug)]
 #[command(name = "view")]
@@ -16,6 +16,8 @@ struct Args {
     simplify: bool,
     #[arg(long)]
     remove_anonymous_inputs: bool,
+    #[arg(long)]
+    canonicalize: bool,
     #[arg(value_name = "INPUT", index = 1)]
     input_file: std::path::PathBuf,
 }
@@ -27,13 +29,12 @@ fn main() {
     let (mut ctx, mut sys) =
         patronus::btor2::parse_file(args.input_file).expect("failed to open input file");
 
-    if args.remove_anonymous_inputs {
-        replace_anonymous_inputs_with_zero(&mut ctx, &mut sys);
-    }
-
-    if args.simplify {
-        simplify_expressions(&mut ctx, &mut sys);
-    }
+    let config = PassConfig {
+        replace_anonymous_inputs: args.remove_anonymous_inputs,
+        simplify: args.simplify,
+        canonicalize: args.canonicalize,
+    };
+    PassManager::from_config(&config).run(&mut ctx, &mut sys);
 
     println!("{}", sys.serialize_to_str(&ctx));
 }